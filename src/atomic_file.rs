@@ -0,0 +1,107 @@
+/*
+    Copyright (C) 2022  Biagio Festa
+
+    This program is free software: you can redistribute it and/or modify
+    it under the terms of the GNU General Public License as published by
+    the Free Software Foundation, either version 3 of the License, or
+    (at your option) any later version.
+
+    This program is distributed in the hope that it will be useful,
+    but WITHOUT ANY WARRANTY; without even the implied warranty of
+    MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+    GNU General Public License for more details.
+
+    You should have received a copy of the GNU General Public License
+    along with this program.  If not, see <https://www.gnu.org/licenses/>.
+*/
+
+use anyhow::Context;
+use anyhow::Result;
+use std::ffi::OsString;
+use std::fs::File;
+use std::io::Write;
+use std::path::Path;
+use std::path::PathBuf;
+
+/// A file written atomically.
+///
+/// The content is written on a temporary file (`<path>.tmp`) which replaces `path` only
+/// on `commit`. Hence, a crash (or a full disk) mid-write never corrupts the previous version.
+/// If not committed, the temporary file is removed on drop.
+pub struct AtomicFile {
+    path: PathBuf,
+    tmp_path: PathBuf,
+    file: File,
+    keep_backup: bool,
+    committed: bool,
+}
+
+impl AtomicFile {
+    /// Start writing `path`.
+    ///
+    /// With `keep_backup` the previous version of the file (if any) is kept as `<path>.bak`.
+    pub fn create(path: &Path, keep_backup: bool) -> Result<Self> {
+        let tmp_path = path_with_suffix(path, ".tmp");
+        let file = File::create(&tmp_path)
+            .with_context(|| format!("Cannot create the file '{}'", tmp_path.display()))?;
+
+        Ok(Self {
+            path: path.to_path_buf(),
+            tmp_path,
+            file,
+            keep_backup,
+            committed: false,
+        })
+    }
+
+    /// Flush the content on disk and replace the destination file.
+    pub fn commit(mut self) -> Result<()> {
+        self.file
+            .sync_all()
+            .with_context(|| format!("Cannot sync the file '{}'", self.tmp_path.display()))?;
+
+        if self.keep_backup && self.path.exists() {
+            let backup_path = backup_path(&self.path);
+
+            std::fs::rename(&self.path, &backup_path).with_context(|| {
+                format!("Cannot create the backup file '{}'", backup_path.display())
+            })?;
+        }
+
+        std::fs::rename(&self.tmp_path, &self.path)
+            .with_context(|| format!("Cannot replace the file '{}'", self.path.display()))?;
+
+        self.committed = true;
+        Ok(())
+    }
+}
+
+impl Write for AtomicFile {
+    fn write(&mut self, buf: &[u8]) -> std::io::Result<usize> {
+        self.file.write(buf)
+    }
+
+    fn flush(&mut self) -> std::io::Result<()> {
+        self.file.flush()
+    }
+}
+
+impl Drop for AtomicFile {
+    fn drop(&mut self) {
+        if !self.committed {
+            let _ = std::fs::remove_file(&self.tmp_path);
+        }
+    }
+}
+
+/// The path of the backup kept by `AtomicFile` for `path`.
+pub fn backup_path(path: &Path) -> PathBuf {
+    path_with_suffix(path, ".bak")
+}
+
+/// Append `suffix` to the file name of `path`.
+fn path_with_suffix(path: &Path, suffix: &str) -> PathBuf {
+    let mut path = OsString::from(path);
+    path.push(suffix);
+    PathBuf::from(path)
+}
@@ -15,22 +15,144 @@
     along with this program.  If not, see <https://www.gnu.org/licenses/>.
 */
 
+mod backoffs;
+mod ctl;
+mod gaps;
+pub mod outputs;
+pub mod placement;
+pub mod restores;
+mod splits;
+mod suspensions;
+
+use crate::birth::BirthWorkspaces;
 use crate::command_executor::CommandExecutor;
 use crate::command_executor::I3Node;
+use crate::control::Action;
+use crate::control::Announcement;
+use crate::control::ControlRequest;
 use crate::event_listener::EventListener;
-use crate::utilities::find_node_parent;
+use crate::gaps::DynamicGaps;
+use crate::history::LastLayouts;
+use crate::history::LayoutHistory;
+use crate::notify::Notifier;
+use crate::notify::NotifyLevel;
+use crate::signals;
+use crate::split_memory::SplitMemory;
+use crate::split_policy::SplitPolicy;
+use crate::state;
+use crate::stats::EventOutcome;
+use crate::stats::EventStats;
+use crate::unmanaged::UnmanagedWorkspaces;
 use crate::utilities::find_workspace_of_node;
-use crate::utilities::is_floating_container;
-use crate::utilities::ratio_of_node;
-use crate::utilities::set_node_split;
-use crate::utilities::RectRatio;
-use crate::utilities::Split;
-use anyhow::anyhow;
+use crate::utilities::find_workspaces;
+use crate::utilities::is_scratchpad_workspace;
+use crate::utilities::runtime_dir;
 use anyhow::Context;
 use anyhow::Result;
+use backoffs::Backoffs;
+use gaps::AppliedGaps;
 use i3_ipc::event::Event;
 use i3_ipc::event::WindowChange;
-use i3_ipc::reply::NodeLayout;
+use i3_ipc::event::WorkspaceChange;
+use outputs::OutputChangeAction;
+use outputs::WorkspaceHistory;
+use placement::EscapeDirection;
+use placement::Escapes;
+use placement::PendingResizes;
+use placement::PlacementRule;
+use placement::SettlingWindows;
+use restores::AutoRestoreRule;
+use restores::PendingRestores;
+use splits::AppliedSplits;
+use std::time::Duration;
+use std::time::Instant;
+use suspensions::Suspensions;
+
+/// Options of the AutoLayout service.
+pub struct AutoLayoutOptions {
+    /// The reaction when the set of active outputs changes.
+    pub on_output_change: Option<OutputChangeAction>,
+
+    /// The group providing the workspace-to-output mapping for the output change reaction.
+    pub output_group: Option<String>,
+
+    /// How the split of the focused window is decided.
+    pub split_policy: SplitPolicy,
+
+    /// Where new windows are placed (the first matching rule applies).
+    pub placements: Vec<PlacementRule>,
+
+    /// Broadcast each action as a tick event.
+    pub announce: bool,
+
+    /// The consecutive failures after which a window is left alone.
+    pub failure_threshold: u32,
+
+    /// How long a window is left alone after too many failures.
+    pub failure_cooldown: Duration,
+
+    /// The focus of a window is ignored if its split has been decided more recently
+    /// than this (and the window has not moved since). Zero disables the check.
+    pub min_reapply: Duration,
+
+    /// When set, a new window opened inside a stacked container is moved out of it in
+    /// this direction (and split as usual).
+    pub escape_stacked: Option<EscapeDirection>,
+
+    /// When set (with a history), the workspace layouts are snapshotted at most this often,
+    /// and the last layout of a workspace once emptied (see `LastLayouts`).
+    pub snapshot_interval: Option<Duration>,
+
+    /// When set, the outer gap of the focused workspace follows its number of windows.
+    pub dynamic_gaps: Option<DynamicGaps>,
+
+    /// The layouts restored on workspaces when they are created.
+    pub auto_restores: Vec<AutoRestoreRule>,
+
+    /// Which events are notified on the desktop.
+    pub notify: NotifyLevel,
+
+    /// When set, a new window is handled only if it still exists (tiled) after this delay,
+    /// so that short-lived windows (e.g., pickers, popups) do not trigger any split.
+    pub settle_new: Option<Duration>,
+
+    /// When set, the time spent on events is printed once the replayed events are over
+    /// (see `EventListener::replay`).
+    pub print_stats: bool,
+}
+
+impl AutoLayoutOptions {
+    /// The default number of consecutive failures after which a window is left alone.
+    pub const DEFAULT_FAILURE_THRESHOLD: u32 = 3;
+
+    /// How long a window is left alone after too many failures, by default.
+    pub const DEFAULT_FAILURE_COOLDOWN: Duration = Duration::from_secs(60);
+
+    /// The default interval within which the focus of a window is not evaluated again.
+    pub const DEFAULT_MIN_REAPPLY: Duration = Duration::from_millis(500);
+}
+
+impl Default for AutoLayoutOptions {
+    fn default() -> Self {
+        Self {
+            on_output_change: None,
+            output_group: None,
+            split_policy: SplitPolicy::default(),
+            placements: vec![],
+            announce: false,
+            failure_threshold: Self::DEFAULT_FAILURE_THRESHOLD,
+            failure_cooldown: Self::DEFAULT_FAILURE_COOLDOWN,
+            min_reapply: Self::DEFAULT_MIN_REAPPLY,
+            escape_stacked: None,
+            snapshot_interval: None,
+            dynamic_gaps: None,
+            auto_restores: vec![],
+            notify: NotifyLevel::None,
+            settle_new: None,
+            print_stats: false,
+        }
+    }
+}
 
 /// AutoLayout service.
 ///
@@ -41,74 +163,535 @@ pub struct AutoLayout {
 
     /// Command executor.
     command_executor: CommandExecutor,
+
+    /// Service options.
+    options: AutoLayoutOptions,
+
+    /// The names of the active outputs (sorted).
+    active_outputs: Vec<String>,
+
+    /// When the pending output change reaction has to be applied.
+    output_change_deadline: Option<Instant>,
+
+    /// Whether the next output change reaction has to be skipped.
+    skip_output_change: bool,
+
+    /// The resizes for windows opened after a rule split.
+    pending_resizes: PendingResizes,
+
+    /// The workspaces displayed on each output.
+    workspace_history: WorkspaceHistory,
+
+    /// The splits applied to windows.
+    applied_splits: AppliedSplits,
+
+    /// The workspaces where the actions are paused.
+    suspensions: Suspensions,
+
+    /// The splits remembered per application (if enabled).
+    split_memory: Option<SplitMemory>,
+
+    /// The windows whose split keeps failing.
+    backoffs: Backoffs,
+
+    /// The windows moved out of a stacked container.
+    escapes: Escapes,
+
+    /// The periodic snapshots of the workspace layouts (if enabled).
+    history: Option<LayoutHistory>,
+
+    /// When the last snapshots have been taken.
+    last_snapshot: Option<Instant>,
+
+    /// The last known layouts, snapshotted once their workspace is emptied.
+    last_layouts: LastLayouts,
+
+    /// The outer gaps set on workspaces.
+    applied_gaps: AppliedGaps,
+
+    /// The restores waiting for the windows of new workspaces.
+    pending_restores: PendingRestores,
+
+    /// The new windows waiting to settle (see `settle_new`).
+    settling_windows: SettlingWindows,
+
+    /// The workspaces handed back to manual control (see `ctl manage`).
+    unmanaged: UnmanagedWorkspaces,
+
+    /// The workspace each window first appeared on (see `send-home`).
+    birth_workspaces: BirthWorkspaces,
+
+    /// The time spent on events (see `ctl stats`).
+    event_stats: EventStats,
+
+    /// The desktop notifications.
+    notifier: Notifier,
+
+    /// When the service has been created (see `ctl status`).
+    started: Instant,
 }
 
 impl AutoLayout {
+    /// How long to wait for the burst of output events to settle.
+    const OUTPUT_CHANGE_DEBOUNCE: Duration = Duration::from_millis(1000);
+
+    /// The minimum interval between re-evaluations of a window after title changes.
+    const TITLE_CHANGE_INTERVAL: Duration = Duration::from_secs(1);
+
+    /// How long a suspension lasts if not resumed.
+    const SUSPENSION_TIMEOUT: Duration = Duration::from_secs(10);
+
+    /// How long a rule split waits for the new window.
+    const PENDING_RESIZE_TIMEOUT: Duration = Duration::from_secs(5);
+
+    /// How long an auto-restore waits for the windows of the layout.
+    const AUTO_RESTORE_TIMEOUT: Duration = Duration::from_secs(30);
+
+    /// How often a pending dump of the status (`SIGUSR2`) is checked while idle.
+    const SIGNAL_POLL_INTERVAL: Duration = Duration::from_secs(1);
+
+    /// The file (in the runtime directory) left when the connection with i3 is lost.
+    const CONNECTION_LOST_MARKER: &'static str = "connection-lost";
+
     /// Initialize and create the service.
-    pub fn new(event_listener: EventListener, command_executor: CommandExecutor) -> Self {
+    pub fn new(
+        event_listener: EventListener,
+        command_executor: CommandExecutor,
+        options: AutoLayoutOptions,
+    ) -> Self {
         Self {
             event_listener,
             command_executor,
+            notifier: Notifier::new(options.notify),
+            options,
+            active_outputs: vec![],
+            output_change_deadline: None,
+            skip_output_change: false,
+            pending_resizes: PendingResizes::default(),
+            workspace_history: WorkspaceHistory::default(),
+            applied_splits: AppliedSplits::default(),
+            suspensions: Suspensions::default(),
+            split_memory: None,
+            backoffs: Backoffs::default(),
+            escapes: Escapes::default(),
+            history: None,
+            last_snapshot: None,
+            last_layouts: LastLayouts::default(),
+            applied_gaps: AppliedGaps::default(),
+            pending_restores: PendingRestores::default(),
+            settling_windows: SettlingWindows::default(),
+            unmanaged: UnmanagedWorkspaces::default(),
+            birth_workspaces: BirthWorkspaces::default(),
+            event_stats: EventStats::default(),
+            started: Instant::now(),
         }
     }
 
+    /// Remember the splits decided for each application in `split_memory`.
+    pub fn with_split_memory(mut self, split_memory: SplitMemory) -> Self {
+        self.split_memory = Some(split_memory);
+        self
+    }
+
+    /// Snapshot the workspace layouts in `history` (see `snapshot_interval`).
+    pub fn with_history(mut self, history: LayoutHistory) -> Self {
+        self.history = Some(history);
+        self
+    }
+
     /// Run the service.
     ///
     /// Start the service itself within this *blocking* function.
     /// It only returns when the service stops for some critical error.
     pub fn serve(mut self) -> Result<()> {
+        self.active_outputs = self.query_active_outputs()?;
+
+        let root_node = self.command_executor.query_root_node()?;
+
+        self.unmanaged = UnmanagedWorkspaces::load()?;
+        for workspace in find_workspaces(&root_node) {
+            self.unmanaged.track(workspace);
+        }
+
+        self.birth_workspaces = BirthWorkspaces::load()?;
+        self.birth_workspaces.track(&root_node)?;
+
+        if self.options.dynamic_gaps.is_some()
+            && !DynamicGaps::is_supported(&mut self.command_executor)
+        {
+            eprintln!("[WARN]: This i3 build does not support gaps; dynamic gaps are disabled");
+            self.options.dynamic_gaps = None;
+        }
+
+        self.notify_recovery();
+
+        if let Err(error) = signals::watch_dump_signal() {
+            eprintln!("[WARN]: The status cannot be dumped on signal: {:?}", error);
+        }
+
         loop {
-            let event = self.event_listener.receive_event()?;
+            if signals::take_dump_request() {
+                eprintln!("{}", self.status(true).describe());
+            }
+
+            self.on_deadlines();
+
+            let event = match self.next_event() {
+                Ok(Some(event)) => event,
+                Ok(None) => continue,
+                Err(_) if self.event_listener.is_replay() => {
+                    if self.options.print_stats {
+                        println!("{}", self.event_stats.describe());
+                    }
+                    return Ok(());
+                }
+                Err(error) => {
+                    self.notify_connection_lost(&error);
+                    return Err(error);
+                }
+            };
 
             debug_assert!(
-                matches!(event, Event::Window(_)),
+                matches!(
+                    event,
+                    Event::Window(_) | Event::Output(_) | Event::Tick(_) | Event::Workspace(_)
+                ),
                 "Received an unexpected event"
             );
 
-            if let Event::Window(window_data) = event {
-                if let WindowChange::Focus = window_data.change {
-                    let node = window_data.container;
-                    let result = self.on_window_focus(&node).with_context(|| {
-                        format!(
-                            "AutoLayout failure for window [{}; '{:?}'; '{:?}'; {}]",
-                            node.id, node.name, node.floating, node.focused,
-                        )
-                    });
+            // The time spent waiting for the event (e.g., on deadlines) is not accounted.
+            self.command_executor.take_timings();
+            let started = Instant::now();
+
+            state::gc(
+                &event,
+                &mut [
+                    &mut self.pending_resizes,
+                    &mut self.workspace_history,
+                    &mut self.applied_splits,
+                    &mut self.suspensions,
+                    &mut self.backoffs,
+                    &mut self.escapes,
+                    &mut self.applied_gaps,
+                    &mut self.pending_restores,
+                    &mut self.settling_windows,
+                    &mut self.unmanaged,
+                    &mut self.birth_workspaces,
+                ],
+            );
+
+            self.take_snapshots(&event);
+
+            let outcome = match event {
+                Event::Window(window_data) => match window_data.change {
+                    WindowChange::Focus
+                        if !self
+                            .settling_windows
+                            .0
+                            .contains_key(&window_data.container.id) =>
+                    {
+                        let node = window_data.container;
+                        let result = self.on_window_focus(&node).with_context(|| {
+                            format!(
+                                "AutoLayout failure for window [{}; '{:?}'; '{:?}'; {}]",
+                                node.id, node.name, node.floating, node.focused,
+                            )
+                        });
+
+                        if let Err(error) = result {
+                            eprintln!(
+                                "[WARN]: Failure to set split mode for focused window: {:?}",
+                                error
+                            );
+                            EventOutcome::Failed
+                        } else {
+                            EventOutcome::Handled
+                        }
+                    }
+
+                    WindowChange::Title if window_data.container.focused => {
+                        if let Err(error) = self.on_window_title(&window_data.container) {
+                            eprintln!(
+                                "[WARN]: Failure to re-evaluate split mode after title change: {:?}",
+                                error
+                            );
+                            EventOutcome::Failed
+                        } else {
+                            EventOutcome::Handled
+                        }
+                    }
+
+                    WindowChange::New => {
+                        if let Err(error) = self.record_birth(&window_data.container) {
+                            eprintln!(
+                                "[WARN]: Failure to record the workspace of new window: {:?}",
+                                error
+                            );
+                        }
+
+                        match self.options.settle_new {
+                            Some(settle_new) => {
+                                self.settling_windows
+                                    .0
+                                    .insert(window_data.container.id, Instant::now() + settle_new);
+                            }
+                            None => self.handle_new_window(&window_data.container),
+                        }
+
+                        self.update_gaps();
+
+                        if !self.pending_restores.0.is_empty() {
+                            self.check_restores();
+                        }
+
+                        EventOutcome::Handled
+                    }
+
+                    WindowChange::Close | WindowChange::Move | WindowChange::Floating => {
+                        self.update_gaps();
+                        EventOutcome::Handled
+                    }
+
+                    _ => EventOutcome::Skipped,
+                },
+
+                Event::Workspace(workspace_data)
+                    if workspace_data.change == WorkspaceChange::Focus =>
+                {
+                    let result = self.on_workspace_focus();
+                    self.update_gaps();
 
                     if let Err(error) = result {
-                        println!(
-                            "[WARN]: Failure to set split mode for focused window: {:?}",
-                            error
-                        );
+                        eprintln!("[WARN]: Failure to track workspace focus: {:?}", error);
+                        EventOutcome::Failed
+                    } else {
+                        EventOutcome::Handled
+                    }
+                }
+
+                Event::Workspace(workspace_data)
+                    if workspace_data.change == WorkspaceChange::Init =>
+                {
+                    if let Some(workspace) = &workspace_data.current {
+                        self.unmanaged.track(workspace);
+                        self.on_workspace_init(workspace);
+                    }
+
+                    EventOutcome::Handled
+                }
+
+                Event::Workspace(workspace_data)
+                    if workspace_data.change == WorkspaceChange::Rename =>
+                {
+                    if let Some(workspace) = &workspace_data.current {
+                        if let Err(error) = self.unmanaged.on_renamed(workspace) {
+                            eprintln!(
+                                "[WARN]: Failure to follow the rename of an unmanaged workspace: {:?}",
+                                error
+                            );
+                            EventOutcome::Failed
+                        } else {
+                            EventOutcome::Handled
+                        }
+                    } else {
+                        EventOutcome::Skipped
+                    }
+                }
+
+                Event::Output(_) => {
+                    if let Err(error) = self.on_output_event() {
+                        eprintln!("[WARN]: Failure to handle output event: {:?}", error);
+                        EventOutcome::Failed
+                    } else {
+                        EventOutcome::Handled
+                    }
+                }
+
+                Event::Tick(tick_data) => {
+                    match ControlRequest::from_tick_payload(&tick_data.payload) {
+                        Some(request) => {
+                            self.on_control_command(request);
+                            EventOutcome::Handled
+                        }
+                        None => EventOutcome::Skipped,
                     }
                 }
+
+                _ => EventOutcome::Skipped,
+            };
+
+            self.event_stats.record(
+                outcome,
+                started.elapsed(),
+                self.command_executor.take_timings(),
+            );
+        }
+    }
+
+    /// Wait for the next event, at most until the earliest deadline.
+    ///
+    /// It returns `None` if the deadline passes first. The wait never exceeds
+    /// `SIGNAL_POLL_INTERVAL`, so that a dump request is served while idle.
+    fn next_event(&mut self) -> Result<Option<Event>> {
+        let deadline = [
+            self.output_change_deadline,
+            self.pending_restores.next_deadline(),
+            self.settling_windows.next_deadline(),
+            Some(Instant::now() + Self::SIGNAL_POLL_INTERVAL),
+        ]
+        .into_iter()
+        .flatten()
+        .min()
+        .expect("The signal poll is always due");
+
+        let timeout = deadline.saturating_duration_since(Instant::now());
+        self.event_listener.receive_event_timeout(timeout)
+    }
+
+    /// Notify that the service lost its connection with i3.
+    ///
+    /// A marker is left in the runtime directory, so that the next service notifies the
+    /// recovery (see `notify_recovery`).
+    fn notify_connection_lost(&mut self, error: &anyhow::Error) {
+        self.notifier
+            .error("i3-autolayout stopped", &format!("{:#}", error));
+
+        if let Ok(dir) = runtime_dir() {
+            let _ = std::fs::write(dir.join(Self::CONNECTION_LOST_MARKER), "");
+        }
+    }
+
+    /// Notify that the service is running again after a connection loss.
+    fn notify_recovery(&mut self) {
+        let Ok(marker) = runtime_dir().map(|dir| dir.join(Self::CONNECTION_LOST_MARKER)) else {
+            return;
+        };
+
+        if std::fs::remove_file(marker).is_ok() {
+            self.notifier.error(
+                "i3-autolayout running again",
+                "The connection with i3 is back",
+            );
+        }
+    }
+
+    /// Trigger the actions whose deadline has passed.
+    fn on_deadlines(&mut self) {
+        let now = Instant::now();
+
+        if self
+            .output_change_deadline
+            .is_some_and(|deadline| deadline <= now)
+        {
+            self.output_change_deadline = None;
+            self.on_output_change_settled();
+        }
+
+        let expired = self
+            .pending_restores
+            .0
+            .iter()
+            .filter(|(_, pending)| pending.deadline <= now)
+            .map(|(workspace, _)| workspace.clone())
+            .collect::<Vec<_>>();
+
+        for workspace in expired {
+            if let Some(pending) = self.pending_restores.0.remove(&workspace) {
+                println!(
+                    "Auto-restore of workspace '{}' timed out ({}/{} windows)",
+                    workspace,
+                    pending.matched,
+                    pending.saved_layout.window_count()
+                );
+
+                self.auto_restore(&workspace, pending.saved_layout);
             }
         }
+
+        if self
+            .settling_windows
+            .next_deadline()
+            .is_some_and(|deadline| deadline <= now)
+        {
+            self.on_windows_settled(now);
+        }
     }
 
-    /// Logic to trigger when receiving a Window/Focus event.
-    fn on_window_focus(&mut self, node: &I3Node) -> Result<()> {
-        if is_floating_container(node) {
+    /// Record the workspace a new window appears on (see `BirthWorkspaces`).
+    ///
+    /// Windows appearing on the scratchpad are not recorded.
+    fn record_birth(&mut self, node: &I3Node) -> Result<()> {
+        let root_node = self.command_executor.query_root_node()?;
+
+        let Some(workspace) = find_workspace_of_node(node.id, &root_node)
+            .filter(|workspace| !is_scratchpad_workspace(workspace))
+            .and_then(|workspace| workspace.name.as_deref())
+        else {
             return Ok(());
+        };
+
+        self.birth_workspaces.record(node, workspace)
+    }
+
+    /// Snapshot the workspace layouts, if the interval has elapsed, and the last known
+    /// layout of the workspaces emptied by a window closed or moved away (see `LastLayouts`).
+    ///
+    /// Layouts only change along with events, so the interval is checked on each event.
+    fn take_snapshots(&mut self, event: &Event) {
+        let (Some(history), Some(interval)) = (&self.history, self.options.snapshot_interval)
+        else {
+            return;
+        };
+
+        let window = match event {
+            Event::Window(window_data) => Some((window_data.change, window_data.container.id)),
+            _ => None,
+        };
+        let due = self
+            .last_snapshot
+            .is_none_or(|last_snapshot| last_snapshot.elapsed() >= interval);
+
+        if window.is_none() && !due {
+            return;
         }
 
-        let root_node = self.command_executor.query_root_node()?;
-        let parent_node = find_node_parent(node.id, &root_node)
-            .ok_or_else(|| anyhow!("Cannot find parent of focused window"))?;
-
-        match parent_node.layout {
-            NodeLayout::SplitH | NodeLayout::SplitV => {
-                let split = match find_workspace_of_node(node.id, &root_node) {
-                    Some(workspace) if ratio_of_node(workspace).is_vertical() => Split::Vertical,
-                    _ => match ratio_of_node(node) {
-                        RectRatio::Horizontal => Split::Horizontal,
-                        RectRatio::Vertical => Split::Vertical,
-                    },
-                };
-
-                set_node_split(node.id, split, &mut self.command_executor)
-            }
-            _ => Ok(()),
+        let result = self
+            .command_executor
+            .query_root_node()
+            .and_then(|root_node| {
+                if let Some((WindowChange::Close | WindowChange::Move, _)) = window {
+                    for workspace in self.last_layouts.take_emptied(&root_node) {
+                        history.record(&workspace)?;
+                    }
+                }
+
+                if let Some((change, node_id)) = window {
+                    if change != WindowChange::Close {
+                        self.last_layouts.remember(&root_node, node_id);
+                    }
+                }
+
+                if due {
+                    self.last_snapshot = Some(Instant::now());
+                    history.record_all(&root_node)?;
+                }
+
+                Ok(())
+            });
+
+        if let Err(error) = result {
+            eprintln!("[WARN]: Failure to snapshot the layouts: {:?}", error);
+        }
+    }
+
+    /// Broadcast an action, if announcements are enabled.
+    fn announce(&mut self, action: Action) {
+        if !self.options.announce {
+            return;
+        }
+
+        if let Err(error) = Announcement::send(action, &mut self.command_executor) {
+            eprintln!("[WARN]: Failure to announce an action: {:?}", error);
         }
     }
 }
@@ -0,0 +1,174 @@
+/*
+    Copyright (C) 2022  Biagio Festa
+
+    This program is free software: you can redistribute it and/or modify
+    it under the terms of the GNU General Public License as published by
+    the Free Software Foundation, either version 3 of the License, or
+    (at your option) any later version.
+
+    This program is distributed in the hope that it will be useful,
+    but WITHOUT ANY WARRANTY; without even the implied warranty of
+    MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+    GNU General Public License for more details.
+
+    You should have received a copy of the GNU General Public License
+    along with this program.  If not, see <https://www.gnu.org/licenses/>.
+*/
+
+use crate::state::StateComponent;
+use std::collections::HashMap;
+use std::time::Duration;
+use std::time::Instant;
+
+/// The consecutive failures on a window.
+pub(super) struct Backoff {
+    /// The number of consecutive failures.
+    failures: u32,
+
+    /// Until when the window is left alone (after too many failures).
+    cooldown: Option<Instant>,
+}
+
+/// The windows whose split keeps failing, keyed by window.
+#[derive(Default)]
+pub(super) struct Backoffs(pub(super) HashMap<usize, Backoff>);
+
+impl Backoffs {
+    /// Whether the window is left alone after too many failures.
+    ///
+    /// When the cooldown expires the failures are forgotten.
+    pub(super) fn is_cooling_down(&mut self, node_id: usize) -> bool {
+        let Some(cooldown) = self.0.get(&node_id).and_then(|backoff| backoff.cooldown) else {
+            return false;
+        };
+
+        if cooldown > Instant::now() {
+            return true;
+        }
+
+        self.0.remove(&node_id);
+        false
+    }
+
+    /// Count a failure on the window.
+    ///
+    /// It returns whether the window has reached `threshold` consecutive failures:
+    /// in that case it is left alone for `cooldown`.
+    pub(super) fn record_failure(
+        &mut self,
+        node_id: usize,
+        threshold: u32,
+        cooldown: Duration,
+    ) -> bool {
+        let backoff = self.0.entry(node_id).or_insert(Backoff {
+            failures: 0,
+            cooldown: None,
+        });
+
+        backoff.failures += 1;
+
+        if backoff.failures >= threshold {
+            backoff.cooldown = Some(Instant::now() + cooldown);
+            true
+        } else {
+            false
+        }
+    }
+}
+
+impl StateComponent for Backoffs {
+    fn name(&self) -> &'static str {
+        "backoffs"
+    }
+
+    fn entries(&self) -> usize {
+        self.0.len()
+    }
+
+    fn describe(&self) -> String {
+        let now = Instant::now();
+        let mut backoffs = self
+            .0
+            .iter()
+            .map(|(node_id, backoff)| match backoff.cooldown {
+                Some(cooldown) => format!(
+                    "{} ({} failures, {}s left)",
+                    node_id,
+                    backoff.failures,
+                    cooldown.saturating_duration_since(now).as_secs()
+                ),
+                None => format!("{} ({} failures)", node_id, backoff.failures),
+            })
+            .collect::<Vec<_>>();
+
+        backoffs.sort();
+
+        if backoffs.is_empty() {
+            "0".to_string()
+        } else {
+            format!("{} [{}]", backoffs.len(), backoffs.join(", "))
+        }
+    }
+
+    fn on_window_closed(&mut self, node_id: usize) {
+        self.0.remove(&node_id);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const COOLDOWN: Duration = Duration::from_secs(60);
+
+    #[test]
+    fn cooldown_after_threshold() {
+        let mut backoffs = Backoffs::default();
+
+        assert!(!backoffs.record_failure(101, 3, COOLDOWN));
+        assert!(!backoffs.record_failure(101, 3, COOLDOWN));
+        assert!(!backoffs.is_cooling_down(101));
+
+        assert!(backoffs.record_failure(101, 3, COOLDOWN));
+        assert!(backoffs.is_cooling_down(101));
+
+        // Failures are counted per window.
+        assert!(!backoffs.is_cooling_down(102));
+        assert!(!backoffs.record_failure(102, 3, COOLDOWN));
+        let description = backoffs.describe();
+        assert!(
+            description.starts_with("2 [101 (3 failures, "),
+            "{}",
+            description
+        );
+        assert!(
+            description.ends_with("s left), 102 (1 failures)]"),
+            "{}",
+            description
+        );
+    }
+
+    #[test]
+    fn expired_cooldowns_forget_failures() {
+        let mut backoffs = Backoffs::default();
+
+        assert!(backoffs.record_failure(101, 1, Duration::ZERO));
+        assert!(!backoffs.is_cooling_down(101));
+        assert_eq!(backoffs.entries(), 0);
+
+        // The count starts again.
+        assert!(!backoffs.record_failure(101, 2, COOLDOWN));
+    }
+
+    #[test]
+    fn closed_windows_are_forgotten() {
+        let mut backoffs = Backoffs::default();
+
+        backoffs.record_failure(101, 1, COOLDOWN);
+        backoffs.record_failure(102, 3, COOLDOWN);
+        backoffs.on_window_closed(101);
+
+        assert!(!backoffs.is_cooling_down(101));
+        assert_eq!(backoffs.describe(), "1 [102 (1 failures)]");
+    }
+}
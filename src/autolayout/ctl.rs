@@ -0,0 +1,258 @@
+/*
+    Copyright (C) 2022  Biagio Festa
+
+    This program is free software: you can redistribute it and/or modify
+    it under the terms of the GNU General Public License as published by
+    the Free Software Foundation, either version 3 of the License, or
+    (at your option) any later version.
+
+    This program is distributed in the hope that it will be useful,
+    but WITHOUT ANY WARRANTY; without even the implied warranty of
+    MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+    GNU General Public License for more details.
+
+    You should have received a copy of the GNU General Public License
+    along with this program.  If not, see <https://www.gnu.org/licenses/>.
+*/
+
+use super::suspensions::Suspension;
+use super::AutoLayout;
+use crate::command_executor::I3Node;
+use crate::control::ControlCommand;
+use crate::control::ControlRequest;
+use crate::notify::NotifyLevel;
+use crate::state;
+use crate::state::StateComponent;
+use crate::status::ServiceStatus;
+use crate::tabmode::TabMode;
+use crate::unmanaged::ManageState;
+use crate::utilities::find_workspaces;
+use crate::workspace::WorkspaceRef;
+use anyhow::Result;
+use std::time::Instant;
+
+impl AutoLayout {
+    /// Logic to trigger when receiving a command through a tick event.
+    pub(super) fn on_control_command(&mut self, request: ControlRequest) {
+        println!("Received command: {:?}", request.command);
+
+        match request.command {
+            ControlCommand::SkipOutputChange => self.skip_output_change = true,
+
+            ControlCommand::BackAndForth => {
+                if let Err(error) = self.back_and_forth() {
+                    eprintln!("[WARN]: Failure to switch workspace back: {:?}", error);
+                }
+            }
+
+            ControlCommand::DumpState => {
+                let state = state::dump(&self.state_components());
+
+                if let Err(error) = request.reply(&mut self.command_executor, &state) {
+                    eprintln!("[WARN]: Failure to reply with the state: {:?}", error);
+                }
+            }
+
+            ControlCommand::Stats => {
+                if let Err(error) =
+                    request.reply(&mut self.command_executor, &self.event_stats.describe())
+                {
+                    eprintln!("[WARN]: Failure to reply with the stats: {:?}", error);
+                }
+            }
+
+            ControlCommand::Status { verbose } => {
+                let status = self.status(verbose).describe();
+
+                if let Err(error) = request.reply(&mut self.command_executor, &status) {
+                    eprintln!("[WARN]: Failure to reply with the status: {:?}", error);
+                }
+            }
+
+            ControlCommand::Suspend { token, workspace } => {
+                let tabmode = self.query_tabmode(|node| node.name.as_deref() == Some(&workspace));
+
+                self.suspensions.0.insert(
+                    token,
+                    Suspension {
+                        workspace,
+                        tabmode,
+                        deadline: Instant::now() + Self::SUSPENSION_TIMEOUT,
+                    },
+                );
+            }
+
+            ControlCommand::Resume { token } => match self.suspensions.0.remove(&token) {
+                Some(suspension) => {
+                    if let Some((workspace_id, before)) = suspension.tabmode {
+                        match self.query_tabmode(|node| node.id == workspace_id) {
+                            Some((_, after)) if after != before => self.notifier.info(
+                                "i3-autolayout tabmode",
+                                &format!(
+                                    "Tabmode {} on workspace '{}'",
+                                    if after { "enabled" } else { "disabled" },
+                                    suspension.workspace
+                                ),
+                            ),
+                            _ => {}
+                        }
+                    }
+                }
+                None => eprintln!("[WARN]: Unknown suspension '{}'", token),
+            },
+
+            ControlCommand::Forget => match &mut self.split_memory {
+                Some(split_memory) => {
+                    if let Err(error) = split_memory.forget() {
+                        eprintln!("[WARN]: Failure to forget the splits: {:?}", error);
+                    }
+                }
+                None => eprintln!("[WARN]: The splits are not remembered (see '--remember')"),
+            },
+
+            ControlCommand::Claim => {
+                let result =
+                    request.reply(&mut self.command_executor, &std::process::id().to_string());
+
+                if let Err(error) = result {
+                    eprintln!("[WARN]: Failure to reply to the claim: {:?}", error);
+                }
+            }
+
+            ControlCommand::Manage { workspace, state } => {
+                if let Err(error) = self.manage(&workspace, state) {
+                    eprintln!("[WARN]: Failure to change the managed flag: {:?}", error);
+                }
+            }
+
+            ControlCommand::Profiles => {
+                let result = self
+                    .describe_profiles()
+                    .and_then(|profiles| request.reply(&mut self.command_executor, &profiles));
+
+                if let Err(error) = result {
+                    eprintln!("[WARN]: Failure to reply with the profiles: {:?}", error);
+                }
+            }
+        }
+    }
+
+    /// Change the managed flag of a workspace (see `ctl manage`).
+    ///
+    /// Existing workspaces are resolved to their name (e.g., `3` to `3: mail`); a name not
+    /// existing yet is taken as is.
+    fn manage(&mut self, workspace: &str, state: ManageState) -> Result<()> {
+        let reference = workspace.parse::<WorkspaceRef>()?;
+        let root_node = self.command_executor.query_root_node()?;
+
+        let node = match reference.resolve(&root_node, &mut self.command_executor) {
+            Ok(node) => Some(node),
+            Err(_) if matches!(reference, WorkspaceRef::Name(_)) => None,
+            Err(error) => return Err(error),
+        };
+        let name = node
+            .and_then(|node| node.name.clone())
+            .unwrap_or_else(|| workspace.to_string());
+
+        let managed = self.unmanaged.set(&name, state)?;
+
+        if let Some(node) = node {
+            self.unmanaged.track(node);
+        }
+
+        println!(
+            "Workspace '{}' {}",
+            name,
+            if managed {
+                "managed"
+            } else {
+                "handed back to manual control"
+            }
+        );
+
+        Ok(())
+    }
+
+    /// The id of the first workspace matching `predicate`, and whether it is in tabmode.
+    ///
+    /// It is only queried when all the events are notified (see `NotifyLevel::All`).
+    fn query_tabmode<P>(&mut self, predicate: P) -> Option<(usize, bool)>
+    where
+        P: Fn(&I3Node) -> bool,
+    {
+        if self.options.notify != NotifyLevel::All {
+            return None;
+        }
+
+        let root_node = self.command_executor.query_root_node().ok()?;
+
+        find_workspaces(&root_node)
+            .into_iter()
+            .find(|workspace| predicate(workspace))
+            .map(|workspace| (workspace.id, TabMode::is_tabmode(workspace)))
+    }
+
+    /// Describe the mode of each workspace (one per line).
+    fn describe_profiles(&mut self) -> Result<String> {
+        let lines = self
+            .command_executor
+            .query_workspaces()?
+            .into_iter()
+            .map(
+                |workspace| match self.options.split_policy.mode_of(&workspace.name) {
+                    _ if self.unmanaged.contains(&workspace.name) => {
+                        format!("{}: unmanaged (ctl manage)", workspace.name)
+                    }
+                    (mode, Some(profile)) => {
+                        format!(
+                            "{}: {} (profile '{}')",
+                            workspace.name,
+                            mode,
+                            profile.pattern()
+                        )
+                    }
+                    (mode, None) => format!("{}: {} (global)", workspace.name, mode),
+                },
+            )
+            .collect::<Vec<_>>();
+
+        Ok(lines.join("\n"))
+    }
+
+    /// The status of the service (see `ctl status`); `verbose` adds the time spent on
+    /// events and the state.
+    pub(super) fn status(&self, verbose: bool) -> ServiceStatus {
+        ServiceStatus {
+            pid: std::process::id(),
+            uptime: self.started.elapsed(),
+            events: self.event_stats.counters(),
+            backoffs: self.backoffs.describe(),
+            unmanaged: self.unmanaged.names(),
+            stats: verbose.then(|| self.event_stats.describe()),
+            state: verbose.then(|| state::dump(&self.state_components())),
+        }
+    }
+
+    /// The components of the state held by the service (see `ctl dump-state`).
+    fn state_components(&self) -> Vec<&dyn StateComponent> {
+        let mut components: Vec<&dyn StateComponent> = vec![
+            &self.pending_resizes,
+            &self.workspace_history,
+            &self.applied_splits,
+            &self.suspensions,
+            &self.backoffs,
+            &self.escapes,
+            &self.applied_gaps,
+            &self.pending_restores,
+            &self.settling_windows,
+            &self.unmanaged,
+            &self.birth_workspaces,
+        ];
+
+        if let Some(split_memory) = &self.split_memory {
+            components.push(split_memory);
+        }
+
+        components
+    }
+}
@@ -0,0 +1,78 @@
+/*
+    Copyright (C) 2022  Biagio Festa
+
+    This program is free software: you can redistribute it and/or modify
+    it under the terms of the GNU General Public License as published by
+    the Free Software Foundation, either version 3 of the License, or
+    (at your option) any later version.
+
+    This program is distributed in the hope that it will be useful,
+    but WITHOUT ANY WARRANTY; without even the implied warranty of
+    MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+    GNU General Public License for more details.
+
+    You should have received a copy of the GNU General Public License
+    along with this program.  If not, see <https://www.gnu.org/licenses/>.
+*/
+
+use super::AutoLayout;
+use crate::gaps::DynamicGaps;
+use crate::state::StateComponent;
+use crate::utilities::query_workspace_focused;
+use std::collections::HashMap;
+
+/// The outer gaps set by the service, keyed by workspace name.
+#[derive(Default)]
+pub(super) struct AppliedGaps(HashMap<String, u32>);
+
+impl StateComponent for AppliedGaps {
+    fn name(&self) -> &'static str {
+        "applied-gaps"
+    }
+
+    fn entries(&self) -> usize {
+        self.0.len()
+    }
+
+    fn on_workspace_emptied(&mut self, workspace: &str) {
+        self.0.remove(workspace);
+    }
+}
+
+impl AutoLayout {
+    /// Set the outer gap of the focused workspace according to its number of windows
+    /// (see `dynamic_gaps`).
+    ///
+    /// i3 sets gaps on the focused workspace only: the other workspaces are updated once
+    /// focused.
+    pub(super) fn update_gaps(&mut self) {
+        let Some(dynamic_gaps) = &self.options.dynamic_gaps else {
+            return;
+        };
+
+        let result = self
+            .command_executor
+            .query_root_node()
+            .and_then(|root_node| {
+                let workspace = query_workspace_focused(&root_node, &mut self.command_executor)?;
+                let name = workspace.name.clone().unwrap_or_default();
+                let gap = dynamic_gaps.gap_of(workspace);
+
+                if self.applied_gaps.0.get(&name) == Some(&gap)
+                    || self.suspensions.is_suspended(&name)
+                    || self.unmanaged.contains(&name)
+                {
+                    return Ok(());
+                }
+
+                DynamicGaps::apply(gap, &mut self.command_executor)?;
+                self.applied_gaps.0.insert(name, gap);
+
+                Ok(())
+            });
+
+        if let Err(error) = result {
+            eprintln!("[WARN]: Failure to update the gaps: {:?}", error);
+        }
+    }
+}
@@ -0,0 +1,228 @@
+/*
+    Copyright (C) 2022  Biagio Festa
+
+    This program is free software: you can redistribute it and/or modify
+    it under the terms of the GNU General Public License as published by
+    the Free Software Foundation, either version 3 of the License, or
+    (at your option) any later version.
+
+    This program is distributed in the hope that it will be useful,
+    but WITHOUT ANY WARRANTY; without even the implied warranty of
+    MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+    GNU General Public License for more details.
+
+    You should have received a copy of the GNU General Public License
+    along with this program.  If not, see <https://www.gnu.org/licenses/>.
+*/
+
+use super::AutoLayout;
+use crate::control::Action;
+use crate::focus_guard::FocusGuard;
+use crate::group::Groups;
+use crate::state::StateComponent;
+use crate::utilities::balance_subtree;
+use crate::utilities::find_workspaces;
+use crate::utilities::quote_ws_name;
+use crate::utilities::ShareRatio;
+use crate::utilities::DEFAULT_SHARE_TOLERANCE;
+use anyhow::anyhow;
+use anyhow::Context;
+use anyhow::Result;
+use std::collections::HashMap;
+use std::time::Instant;
+
+/// The reaction of the service when the set of active outputs changes.
+#[derive(Clone, Copy, clap::ValueEnum, serde::Deserialize, serde::Serialize)]
+#[serde(rename_all = "kebab-case")]
+pub enum OutputChangeAction {
+    /// Move the workspaces of a group on their outputs and balance them.
+    Restore,
+}
+
+/// The last two workspaces displayed on each output (the last one is the current).
+#[derive(Default)]
+pub(super) struct WorkspaceHistory(HashMap<String, Vec<String>>);
+
+impl StateComponent for WorkspaceHistory {
+    fn name(&self) -> &'static str {
+        "workspace-history"
+    }
+
+    fn entries(&self) -> usize {
+        self.0.values().map(Vec::len).sum()
+    }
+
+    fn on_workspace_emptied(&mut self, workspace: &str) {
+        for history in self.0.values_mut() {
+            history.retain(|name| name != workspace);
+        }
+    }
+}
+
+impl AutoLayout {
+    /// Logic to trigger when receiving a Workspace/Focus event.
+    ///
+    /// It records the workspace displayed on each output.
+    pub(super) fn on_workspace_focus(&mut self) -> Result<()> {
+        for workspace in self.command_executor.query_workspaces()? {
+            if !workspace.visible {
+                continue;
+            }
+
+            let history = self
+                .workspace_history
+                .0
+                .entry(workspace.output)
+                .or_default();
+
+            if history.last() != Some(&workspace.name) {
+                history.push(workspace.name);
+
+                if history.len() > 2 {
+                    history.remove(0);
+                }
+            }
+        }
+
+        Ok(())
+    }
+
+    /// Switch the focused output to the workspace it displayed before.
+    pub(super) fn back_and_forth(&mut self) -> Result<()> {
+        let workspaces = self.command_executor.query_workspaces()?;
+
+        let focused_output = workspaces
+            .iter()
+            .find(|workspace| workspace.focused)
+            .map(|workspace| workspace.output.clone())
+            .ok_or_else(|| anyhow!("Cannot detect the current focused workspace"))?;
+
+        let previous = match self.workspace_history.0.get(&focused_output) {
+            Some(history) if history.len() == 2 => history[0].clone(),
+            _ => {
+                println!("No previous workspace on output '{}'", focused_output);
+                return Ok(());
+            }
+        };
+
+        if !workspaces
+            .iter()
+            .any(|workspace| workspace.name == previous)
+        {
+            eprintln!(
+                "[WARN]: The previous workspace '{}' no longer exists",
+                previous
+            );
+            return Ok(());
+        }
+
+        self.command_executor
+            .run(format!(
+                "workspace --no-auto-back-and-forth {}",
+                quote_ws_name(&previous)?
+            ))
+            .context("Cannot switch workspace")?;
+
+        self.announce(Action::BackAndForth {
+            workspace: previous,
+        });
+
+        Ok(())
+    }
+
+    /// Logic to trigger when receiving an Output event.
+    ///
+    /// A reaction is scheduled only if the set of active outputs has changed.
+    /// Further events postpone the reaction, so that a burst of events is handled once.
+    pub(super) fn on_output_event(&mut self) -> Result<()> {
+        let active_outputs = self.query_active_outputs()?;
+
+        self.workspace_history
+            .0
+            .retain(|output, _| active_outputs.contains(output));
+
+        if self.options.on_output_change.is_none() {
+            return Ok(());
+        }
+
+        if active_outputs != self.active_outputs || self.output_change_deadline.is_some() {
+            self.active_outputs = active_outputs;
+            self.output_change_deadline = Some(Instant::now() + Self::OUTPUT_CHANGE_DEBOUNCE);
+        }
+
+        Ok(())
+    }
+
+    /// Logic to trigger when the output events have settled.
+    pub(super) fn on_output_change_settled(&mut self) {
+        if std::mem::take(&mut self.skip_output_change) {
+            println!("Output change reaction skipped");
+            return;
+        }
+
+        let result = match self.options.on_output_change {
+            Some(OutputChangeAction::Restore) => self.restore_output_group(),
+            None => Ok(()),
+        };
+
+        if let Err(error) = result {
+            eprintln!("[WARN]: Failure to react to output change: {:?}", error);
+            self.notifier
+                .error("i3-autolayout restore failed", &format!("{:#}", error));
+        }
+    }
+
+    /// Move the workspaces of the output group on their outputs and balance them.
+    fn restore_output_group(&mut self) -> Result<()> {
+        let group_name = self
+            .options
+            .output_group
+            .clone()
+            .ok_or_else(|| anyhow!("No output group configured"))?;
+
+        let groups = Groups::load()?;
+        let _focus_guard = FocusGuard::capture_window(&mut self.command_executor)?;
+
+        groups
+            .activate(&group_name, &mut self.command_executor)
+            .context("Cannot activate the output group")?;
+
+        let workspace_names = groups.workspaces(&group_name)?;
+        let root_node = self.command_executor.query_root_node()?;
+
+        for workspace in find_workspaces(&root_node) {
+            if workspace
+                .name
+                .as_ref()
+                .is_some_and(|name| workspace_names.contains(name))
+            {
+                balance_subtree(
+                    workspace,
+                    &ShareRatio::Equal,
+                    DEFAULT_SHARE_TOLERANCE,
+                    &mut self.command_executor,
+                )
+                .context("Cannot balance the workspace")?;
+            }
+        }
+
+        self.announce(Action::RestoreOutputGroup { group: group_name });
+
+        Ok(())
+    }
+
+    /// Query the names of the active outputs (sorted).
+    pub(super) fn query_active_outputs(&mut self) -> Result<Vec<String>> {
+        let mut active_outputs = self
+            .command_executor
+            .query_outputs()?
+            .into_iter()
+            .filter(|output| output.active)
+            .map(|output| output.name)
+            .collect::<Vec<_>>();
+
+        active_outputs.sort();
+
+        Ok(active_outputs)
+    }
+}
@@ -0,0 +1,668 @@
+/*
+    Copyright (C) 2022  Biagio Festa
+
+    This program is free software: you can redistribute it and/or modify
+    it under the terms of the GNU General Public License as published by
+    the Free Software Foundation, either version 3 of the License, or
+    (at your option) any later version.
+
+    This program is distributed in the hope that it will be useful,
+    but WITHOUT ANY WARRANTY; without even the implied warranty of
+    MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+    GNU General Public License for more details.
+
+    You should have received a copy of the GNU General Public License
+    along with this program.  If not, see <https://www.gnu.org/licenses/>.
+*/
+
+use super::AutoLayout;
+use crate::command_executor::I3Node;
+use crate::control::Action;
+use crate::focus_guard::FocusGuard;
+use crate::marks::COLUMN_MARK;
+use crate::selector::Criteria;
+use crate::split_policy::LayoutMode;
+use crate::split_policy::SplitPolicy;
+use crate::state::StateComponent;
+use crate::tabmode::TabMode;
+use crate::utilities::find_node_ancestors;
+use crate::utilities::find_node_by_id;
+use crate::utilities::find_node_by_mark;
+use crate::utilities::find_node_parent;
+use crate::utilities::find_workspace_of_node;
+use crate::utilities::holds_size_lock;
+use crate::utilities::is_floating_container;
+use crate::utilities::is_size_locked;
+use crate::utilities::is_window;
+use crate::utilities::quote;
+use crate::utilities::set_node_layout;
+use crate::utilities::set_node_split;
+use crate::utilities::Layout;
+use crate::utilities::Split;
+use crate::workspace::WorkspaceRef;
+use anyhow::anyhow;
+use anyhow::Context;
+use anyhow::Result;
+use i3_ipc::reply::NodeLayout;
+use std::collections::HashMap;
+use std::collections::HashSet;
+use std::fmt::Display;
+use std::str::FromStr;
+use std::time::Instant;
+
+/// The direction a new window is moved to, out of a stacked container.
+#[derive(Clone, Copy, clap::ValueEnum, serde::Deserialize, serde::Serialize)]
+#[serde(rename_all = "kebab-case")]
+pub enum EscapeDirection {
+    /// Next to the stack, on its left.
+    Left,
+
+    /// Next to the stack, on its right.
+    Right,
+
+    /// Next to the stack, above it.
+    Up,
+
+    /// Next to the stack, below it.
+    Down,
+}
+
+impl EscapeDirection {
+    /// The layout of the container where a window moved in this direction lands.
+    fn layout(self) -> NodeLayout {
+        match self {
+            Self::Left | Self::Right => NodeLayout::SplitH,
+            Self::Up | Self::Down => NodeLayout::SplitV,
+        }
+    }
+}
+
+impl Display for EscapeDirection {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Self::Left => write!(f, "left"),
+            Self::Right => write!(f, "right"),
+            Self::Up => write!(f, "up"),
+            Self::Down => write!(f, "down"),
+        }
+    }
+}
+
+/// A rule placing new windows on a workspace, possibly inside a marked container.
+///
+/// It is parsed from `<criteria>:workspace=<workspace>[:container=mark:<mark>[:layout=<layout>]]`
+/// (e.g., `class=Spotify:workspace=music:container=mark:media:layout=tabbed`).
+/// The container is created (with `layout`) around the first window placed into it.
+#[derive(Clone)]
+pub struct PlacementRule {
+    /// The windows the rule applies to.
+    criteria: Criteria,
+
+    /// The workspace of the windows.
+    workspace: WorkspaceRef,
+
+    /// The mark of the container of the windows.
+    container: Option<String>,
+
+    /// The layout of the container, when created.
+    layout: Option<Layout>,
+}
+
+impl FromStr for PlacementRule {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> Result<Self> {
+        let invalid = || {
+            anyhow!(
+                "Invalid placement '{}' (expected CRITERIA:workspace=WORKSPACE[:container=mark:MARK[:layout=LAYOUT]])",
+                s
+            )
+        };
+
+        let (criteria, rest) = s.split_once(":workspace=").ok_or_else(invalid)?;
+
+        let (workspace, container) = match rest.split_once(":container=") {
+            Some((workspace, container)) => {
+                let container = container.strip_prefix("mark:").ok_or_else(|| {
+                    anyhow!(
+                        "Invalid container '{}' in placement (expected 'mark:MARK')",
+                        container
+                    )
+                })?;
+
+                (workspace, Some(container))
+            }
+            None => (rest, None),
+        };
+
+        let (container, layout) = match container.and_then(|c| c.rsplit_once(":layout=")) {
+            Some((container, layout)) => (Some(container), Some(layout.parse::<Layout>()?)),
+            None => (container, None),
+        };
+
+        let workspace = match workspace.parse()? {
+            WorkspaceRef::Focused => return Err(invalid()),
+            workspace => workspace,
+        };
+
+        if container.is_some_and(str::is_empty) {
+            return Err(invalid());
+        }
+
+        let criteria: Criteria = criteria.parse()?;
+        if criteria.is_empty() {
+            return Err(invalid());
+        }
+
+        Ok(Self {
+            criteria,
+            workspace,
+            container: container.map(str::to_string),
+            layout,
+        })
+    }
+}
+
+/// A resize waiting for the window opened after a split.
+pub(super) struct PendingResize {
+    /// The dimension of the resize.
+    pub(super) split: Split,
+
+    /// The size (percentage points) of the new window.
+    pub(super) ppt: u32,
+
+    /// When the resize is discarded if no window has been opened.
+    pub(super) deadline: Instant,
+}
+
+/// The resizes for windows opened after a rule split, keyed by the split container.
+#[derive(Default)]
+pub(super) struct PendingResizes(pub(super) HashMap<usize, PendingResize>);
+
+impl StateComponent for PendingResizes {
+    fn name(&self) -> &'static str {
+        "pending-resizes"
+    }
+
+    fn entries(&self) -> usize {
+        self.0.len()
+    }
+
+    fn on_window_closed(&mut self, node_id: usize) {
+        let now = Instant::now();
+        self.0.retain(|container_id, pending_resize| {
+            *container_id != node_id && pending_resize.deadline > now
+        });
+    }
+}
+
+/// The windows already moved out of a stacked container, so that they are never moved again.
+#[derive(Default)]
+pub(super) struct Escapes(HashSet<usize>);
+
+impl StateComponent for Escapes {
+    fn name(&self) -> &'static str {
+        "escaped-stacks"
+    }
+
+    fn entries(&self) -> usize {
+        self.0.len()
+    }
+
+    fn on_window_closed(&mut self, node_id: usize) {
+        self.0.remove(&node_id);
+    }
+}
+
+/// The new windows waiting to settle before being handled, with their deadline.
+#[derive(Default)]
+pub(super) struct SettlingWindows(pub(super) HashMap<usize, Instant>);
+
+impl SettlingWindows {
+    /// The earliest deadline of the settling windows.
+    pub(super) fn next_deadline(&self) -> Option<Instant> {
+        self.0.values().min().copied()
+    }
+
+    /// Remove the windows whose deadline has passed at `now`, returning them by id.
+    pub(super) fn take_settled(&mut self, now: Instant) -> Vec<usize> {
+        let mut settled = self
+            .0
+            .iter()
+            .filter(|(_, deadline)| **deadline <= now)
+            .map(|(node_id, _)| *node_id)
+            .collect::<Vec<_>>();
+
+        settled.sort_unstable();
+
+        for node_id in &settled {
+            self.0.remove(node_id);
+        }
+
+        settled
+    }
+}
+
+impl StateComponent for SettlingWindows {
+    fn name(&self) -> &'static str {
+        "settling-windows"
+    }
+
+    fn entries(&self) -> usize {
+        self.0.len()
+    }
+
+    fn on_window_closed(&mut self, node_id: usize) {
+        self.0.remove(&node_id);
+    }
+}
+
+impl AutoLayout {
+    /// Handle the new windows whose settle delay has passed.
+    ///
+    /// Windows closed in the meantime have already been dropped (see `SettlingWindows`);
+    /// windows gone floating or moved to the scratchpad are dropped here.
+    pub(super) fn on_windows_settled(&mut self, now: Instant) {
+        let settled = self.settling_windows.take_settled(now);
+
+        let root_node = match self.command_executor.query_root_node() {
+            Ok(root_node) => root_node,
+            Err(error) => {
+                eprintln!("[WARN]: Failure to handle the settled windows: {:?}", error);
+                return;
+            }
+        };
+
+        for node_id in settled {
+            let Some(node) = find_node_by_id(node_id, &root_node) else {
+                continue;
+            };
+
+            if is_floating_container(node) {
+                continue;
+            }
+
+            self.handle_new_window(node);
+
+            if node.focused {
+                if let Err(error) = self.on_window_focus(node) {
+                    eprintln!(
+                        "[WARN]: Failure to set split mode for settled window: {:?}",
+                        error
+                    );
+                }
+            }
+        }
+    }
+
+    /// Logic to trigger when a new window is opened (after it settled, see `settle_new`).
+    pub(super) fn handle_new_window(&mut self, node: &I3Node) {
+        if let Err(error) = self.place_window(node) {
+            eprintln!("[WARN]: Failure to place new window: {:?}", error);
+        }
+
+        if let Err(error) = self.escape_stacked(node) {
+            eprintln!(
+                "[WARN]: Failure to move new window out of its stack: {:?}",
+                error
+            );
+        }
+
+        if let Err(error) = self.arrange_columns(node) {
+            eprintln!(
+                "[WARN]: Failure to place new window in its column: {:?}",
+                error
+            );
+        }
+
+        if !self.pending_resizes.0.is_empty() {
+            if let Err(error) = self.on_window_new(node) {
+                eprintln!("[WARN]: Failure to resize new window: {:?}", error);
+            }
+        }
+    }
+
+    /// Move a new window where the first matching placement rule wants it.
+    ///
+    /// Moves already done (e.g., by an i3 `assign`) are skipped. A missing container is
+    /// created around the window, then moved on the workspace.
+    fn place_window(&mut self, node: &I3Node) -> Result<()> {
+        if self.options.placements.is_empty() || is_floating_container(node) {
+            return Ok(());
+        }
+
+        let root_node = self.command_executor.query_root_node()?;
+        let Some(workspace) = find_workspace_of_node(node.id, &root_node) else {
+            return Ok(());
+        };
+
+        let Some(placement) = self
+            .options
+            .placements
+            .iter()
+            .find(|placement| placement.criteria.matches(node, workspace))
+        else {
+            return Ok(());
+        };
+
+        let workspace_name = workspace.name.as_deref().unwrap_or_default();
+        if self.suspensions.is_suspended(workspace_name) || self.unmanaged.contains(workspace_name)
+        {
+            return Ok(());
+        }
+
+        let on_workspace = placement
+            .workspace
+            .resolve(&root_node, &mut self.command_executor)
+            .is_ok_and(|target| target.id == workspace.id);
+        let move_to_workspace = format!(
+            "move container to {}",
+            placement.workspace.to_i3_selector()?
+        );
+
+        let Some(mark) = placement.container.as_deref() else {
+            if !on_workspace {
+                self.command_executor
+                    .run_on_node_id(node.id, &move_to_workspace)
+                    .context("Cannot move the window on its workspace")?;
+            }
+
+            return Ok(());
+        };
+
+        if let Some(container) = find_node_by_mark(mark, &root_node) {
+            let inside = find_node_ancestors(node.id, &root_node)
+                .iter()
+                .any(|ancestor| ancestor.id == container.id);
+
+            if !inside {
+                self.command_executor
+                    .run_on_node_id(node.id, format!("move container to mark {}", quote(mark)))
+                    .context("Cannot move the window into its container")?;
+            }
+
+            return Ok(());
+        }
+
+        // The focus is restored before the container leaves the workspace, so that
+        // it does not follow the container.
+        {
+            let _focus_guard = FocusGuard::capture(&mut self.command_executor)?;
+
+            let split = match placement.layout {
+                Some(Layout::SplitV | Layout::Stacked) => Split::Vertical,
+                _ => Split::Horizontal,
+            };
+
+            set_node_split(node.id, split, &mut self.command_executor)?;
+
+            if let Some(layout) = placement.layout {
+                set_node_layout(node.id, layout, &mut self.command_executor)?;
+            }
+
+            self.command_executor
+                .run_on_node_id(node.id, "focus")
+                .context("Cannot focus the window")?;
+            self.command_executor
+                .run(format!("focus parent; mark {}", quote(mark)))
+                .context("Cannot mark the new container")?;
+        }
+
+        if !on_workspace {
+            let root_node = self.command_executor.query_root_node()?;
+            let container = find_node_parent(node.id, &root_node)
+                .ok_or_else(|| anyhow!("Cannot find the new container"))?;
+
+            self.command_executor
+                .run_on_node_id(container.id, &move_to_workspace)
+                .context("Cannot move the container on its workspace")?;
+        }
+
+        Ok(())
+    }
+
+    /// Move a new window out of its stacked container (see `escape_stacked`).
+    ///
+    /// The window is moved only when the container around the stack is laid out in the
+    /// direction of the move, so that it lands next to the stack (never on another
+    /// workspace). Workspaces in tabmode are left alone, and each window is moved at most
+    /// once.
+    fn escape_stacked(&mut self, node: &I3Node) -> Result<()> {
+        let Some(direction) = self.options.escape_stacked else {
+            return Ok(());
+        };
+
+        if is_floating_container(node) || !self.escapes.0.insert(node.id) {
+            return Ok(());
+        }
+
+        let root_node = self.command_executor.query_root_node()?;
+        let ancestors = find_node_ancestors(node.id, &root_node);
+
+        let [stack, container, ..] = ancestors.as_slice() else {
+            return Ok(());
+        };
+
+        if stack.layout != NodeLayout::Stacked || container.layout != direction.layout() {
+            return Ok(());
+        }
+
+        let Some(workspace) = find_workspace_of_node(node.id, &root_node) else {
+            return Ok(());
+        };
+
+        let workspace_name = workspace.name.as_deref().unwrap_or_default();
+        if TabMode::is_tabmode(workspace)
+            || self.suspensions.is_suspended(workspace_name)
+            || self.unmanaged.contains(workspace_name)
+        {
+            return Ok(());
+        }
+
+        self.command_executor
+            .run_on_node_id(node.id, format!("move {}", direction))
+            .context("Cannot move the window out of the stack")?;
+
+        let root_node = self.command_executor.query_root_node()?;
+        let node = find_node_by_id(node.id, &root_node)
+            .ok_or_else(|| anyhow!("Cannot find the moved window"))?
+            .clone();
+
+        self.evaluate_window(&node, false)
+    }
+
+    /// Place a new window in the columns of its workspace (mode `columns`).
+    ///
+    /// The columns are the children of the workspace (made `splith`), re-derived from the
+    /// tree each time, so that windows moved by hand are accounted for. While there are
+    /// fewer columns than `SplitPolicy::columns`, the window becomes a new column.
+    /// Otherwise it stacks in the column of the window focused before: i3 already opens
+    /// it there, unless that column is a single window (which is then split vertically).
+    /// Columns are never balanced.
+    fn arrange_columns(&mut self, node: &I3Node) -> Result<()> {
+        if is_floating_container(node) {
+            return Ok(());
+        }
+
+        let root_node = self.command_executor.query_root_node()?;
+        let Some(workspace) = find_workspace_of_node(node.id, &root_node) else {
+            return Ok(());
+        };
+
+        let workspace_name = workspace.name.as_deref().unwrap_or_default();
+        let (mode, _) = self.options.split_policy.mode_of(workspace_name);
+
+        if mode != LayoutMode::Columns
+            || TabMode::is_tabmode(workspace)
+            || self.suspensions.is_suspended(workspace_name)
+            || self.unmanaged.contains(workspace_name)
+            || matches!(workspace.layout, NodeLayout::Tabbed | NodeLayout::Stacked)
+        {
+            return Ok(());
+        }
+
+        // The layout of a container is set through one of its children.
+        if let (NodeLayout::SplitV, Some(child)) = (&workspace.layout, workspace.nodes.first()) {
+            self.command_executor
+                .run_on_node_id(child.id, "layout splith")
+                .context("Cannot lay out the workspace in columns")?;
+        }
+
+        let columns = self
+            .options
+            .split_policy
+            .columns
+            .unwrap_or(SplitPolicy::DEFAULT_COLUMNS);
+        let is_column = workspace.nodes.iter().any(|column| column.id == node.id);
+        let other_columns = workspace
+            .nodes
+            .iter()
+            .filter(|column| column.id != node.id)
+            .collect::<Vec<_>>();
+
+        if other_columns.len() < columns {
+            if !is_column {
+                self.move_to_column(node.id, workspace.id)?;
+            }
+
+            return Ok(());
+        }
+
+        if !is_column {
+            return Ok(());
+        }
+
+        let Some(previous) = workspace
+            .focus
+            .iter()
+            .find_map(|&focused_id| other_columns.iter().find(|column| column.id == focused_id))
+        else {
+            return Ok(());
+        };
+
+        let column_id = if is_window(previous) {
+            set_node_split(previous.id, Split::Vertical, &mut self.command_executor)?;
+
+            let root_node = self.command_executor.query_root_node()?;
+            find_node_parent(previous.id, &root_node)
+                .ok_or_else(|| anyhow!("Cannot find the new column"))?
+                .id
+        } else {
+            previous.id
+        };
+
+        self.move_to_column(node.id, column_id)
+    }
+
+    /// Move a window into a container (a column, or the workspace for a new column).
+    fn move_to_column(&mut self, node_id: usize, container_id: usize) -> Result<()> {
+        let commands = [
+            format!(
+                "[con_id={}] mark --add {}",
+                container_id,
+                quote(COLUMN_MARK)
+            ),
+            format!(
+                "[con_id={}] move container to mark {}",
+                node_id,
+                quote(COLUMN_MARK)
+            ),
+            format!("unmark {}", quote(COLUMN_MARK)),
+        ];
+
+        for result in self.command_executor.run_batch(&commands)? {
+            result.context("Cannot move the window into its column")?;
+        }
+
+        Ok(())
+    }
+
+    /// Logic to trigger when receiving a Window/New event.
+    ///
+    /// If the window has been opened in a container split by a rule, it is resized accordingly.
+    fn on_window_new(&mut self, node: &I3Node) -> Result<()> {
+        let now = Instant::now();
+        self.pending_resizes
+            .0
+            .retain(|_, pending_resize| pending_resize.deadline > now);
+
+        let root_node = self.command_executor.query_root_node()?;
+        let Some(parent_node) = find_node_parent(node.id, &root_node) else {
+            return Ok(());
+        };
+
+        if let Some(pending_resize) = self.pending_resizes.0.remove(&parent_node.id) {
+            // The new window takes its space from its siblings.
+            if is_size_locked(parent_node.id, &root_node)
+                || parent_node.nodes.iter().any(holds_size_lock)
+            {
+                println!("Resize of new window '{}' skipped (size locked)", node.id);
+                return Ok(());
+            }
+
+            let dimension = match pending_resize.split {
+                Split::Horizontal => "width",
+                Split::Vertical => "height",
+                // Rules never toggle.
+                Split::Toggle => return Ok(()),
+            };
+
+            self.command_executor
+                .run_on_node_id(
+                    node.id,
+                    format!("resize set {} {} ppt", dimension, pending_resize.ppt),
+                )
+                .context("Cannot resize the new window")?;
+
+            self.announce(Action::Resize {
+                con_id: node.id,
+                ppt: pending_resize.ppt,
+            });
+        }
+
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::time::Duration;
+
+    #[test]
+    fn settled_windows_are_taken_once() {
+        let now = Instant::now();
+        let mut settling = SettlingWindows::default();
+        assert_eq!(settling.next_deadline(), None);
+
+        settling.0.insert(103, now + Duration::from_millis(10));
+        settling.0.insert(101, now);
+        settling.0.insert(102, now + Duration::from_millis(20));
+        assert_eq!(settling.next_deadline(), Some(now));
+
+        assert_eq!(settling.take_settled(now), [101]);
+        assert!(settling.take_settled(now).is_empty());
+        assert_eq!(
+            settling.next_deadline(),
+            Some(now + Duration::from_millis(10))
+        );
+
+        assert_eq!(
+            settling.take_settled(now + Duration::from_millis(30)),
+            [102, 103]
+        );
+        assert_eq!(settling.entries(), 0);
+    }
+
+    #[test]
+    fn closed_windows_never_settle() {
+        let now = Instant::now();
+        let mut settling = SettlingWindows::default();
+
+        settling.0.insert(101, now);
+        settling.0.insert(102, now);
+        settling.on_window_closed(101);
+
+        assert_eq!(settling.take_settled(now), [102]);
+    }
+}
@@ -0,0 +1,378 @@
+/*
+    Copyright (C) 2022  Biagio Festa
+
+    This program is free software: you can redistribute it and/or modify
+    it under the terms of the GNU General Public License as published by
+    the Free Software Foundation, either version 3 of the License, or
+    (at your option) any later version.
+
+    This program is distributed in the hope that it will be useful,
+    but WITHOUT ANY WARRANTY; without even the implied warranty of
+    MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+    GNU General Public License for more details.
+
+    You should have received a copy of the GNU General Public License
+    along with this program.  If not, see <https://www.gnu.org/licenses/>.
+*/
+
+use super::AutoLayout;
+use crate::command_executor::I3Node;
+use crate::control::Action;
+use crate::restore_layout::resolve_windows;
+use crate::restore_layout::saved_workspace;
+use crate::restore_layout::RestoreLayout;
+use crate::save_layout::SavedLayout;
+use crate::state::StateComponent;
+use crate::utilities::expand_home;
+use crate::workspace::WorkspaceRef;
+use anyhow::anyhow;
+use anyhow::Result;
+use std::collections::HashMap;
+use std::str::FromStr;
+use std::time::Instant;
+
+/// A rule restoring a saved layout on a workspace when it is created.
+///
+/// It is parsed from `<workspace>=<path>` (e.g., `mail=~/.config/i3-autolayout/mail.layout`).
+/// Layouts ending with `.json` are read as JSON.
+#[derive(Clone)]
+pub struct AutoRestoreRule {
+    /// The workspace of the rule.
+    workspace: WorkspaceRef,
+
+    /// The path of the saved layout (possibly starting with `~/`).
+    path: String,
+}
+
+impl AutoRestoreRule {
+    /// Load the saved layout of the rule.
+    fn load(&self) -> Result<SavedLayout> {
+        let path = expand_home(&self.path);
+        let json_input = path
+            .extension()
+            .is_some_and(|extension| extension == "json");
+
+        SavedLayout::load(&path, json_input)
+    }
+}
+
+impl FromStr for AutoRestoreRule {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> Result<Self> {
+        let invalid = || anyhow!("Invalid auto-restore '{}' (expected WORKSPACE=PATH)", s);
+
+        let (workspace, path) = s.split_once('=').ok_or_else(invalid)?;
+
+        let workspace = match workspace.parse()? {
+            WorkspaceRef::Focused => return Err(invalid()),
+            workspace => workspace,
+        };
+
+        if path.is_empty() {
+            return Err(invalid());
+        }
+
+        Ok(Self {
+            workspace,
+            path: path.to_string(),
+        })
+    }
+}
+
+impl<'de> serde::Deserialize<'de> for AutoRestoreRule {
+    fn deserialize<D>(deserializer: D) -> std::result::Result<Self, D::Error>
+    where
+        D: serde::Deserializer<'de>,
+    {
+        let rule = String::deserialize(deserializer)?;
+        rule.parse().map_err(serde::de::Error::custom)
+    }
+}
+
+impl serde::Serialize for AutoRestoreRule {
+    fn serialize<S>(&self, serializer: S) -> std::result::Result<S::Ok, S::Error>
+    where
+        S: serde::Serializer,
+    {
+        serializer.serialize_str(&format!("{}={}", self.workspace, self.path))
+    }
+}
+
+/// A restore waiting for the windows of a newly created workspace.
+pub(super) struct PendingRestore {
+    /// The layout to restore.
+    pub(super) saved_layout: SavedLayout,
+
+    /// The saved windows already matched by live windows.
+    pub(super) matched: usize,
+
+    /// When the layout is restored anyway.
+    pub(super) deadline: Instant,
+}
+
+/// The pending auto-restores, keyed by workspace name.
+#[derive(Default)]
+pub(super) struct PendingRestores(pub(super) HashMap<String, PendingRestore>);
+
+impl PendingRestores {
+    /// The earliest deadline of the pending restores.
+    pub(super) fn next_deadline(&self) -> Option<Instant> {
+        self.0.values().map(|pending| pending.deadline).min()
+    }
+}
+
+impl StateComponent for PendingRestores {
+    fn name(&self) -> &'static str {
+        "pending-restores"
+    }
+
+    fn entries(&self) -> usize {
+        self.0.len()
+    }
+
+    fn describe(&self) -> String {
+        let mut pending = self
+            .0
+            .iter()
+            .map(|(workspace, pending)| {
+                format!(
+                    "{} ({}/{} windows)",
+                    workspace,
+                    pending.matched,
+                    pending.saved_layout.window_count()
+                )
+            })
+            .collect::<Vec<_>>();
+        pending.sort();
+
+        format!("{} [{}]", self.entries(), pending.join(", "))
+    }
+
+    fn on_workspace_emptied(&mut self, workspace: &str) {
+        self.0.remove(workspace);
+    }
+}
+
+impl AutoLayout {
+    /// Logic to trigger when a workspace is created.
+    ///
+    /// If an auto-restore rule applies, the layout is restored once its windows exist
+    /// (see `check_restores`), or when the timeout expires.
+    pub(super) fn on_workspace_init(&mut self, workspace: &I3Node) {
+        let Some(rule) = self
+            .options
+            .auto_restores
+            .iter()
+            .find(|rule| rule.workspace.designates(workspace))
+        else {
+            return;
+        };
+
+        let saved_layout = match rule.load() {
+            Ok(saved_layout) => saved_layout,
+            Err(error) => {
+                eprintln!(
+                    "[WARN]: Failure to load the layout to auto-restore: {:?}",
+                    error
+                );
+                self.notifier
+                    .error("i3-autolayout restore skipped", &format!("{:#}", error));
+                return;
+            }
+        };
+
+        if !saved_workspace(&saved_layout).is_ok_and(|saved| saved.designates(workspace)) {
+            eprintln!(
+                "[WARN]: The layout '{}' was not saved from workspace '{}'; auto-restore skipped",
+                rule.path, rule.workspace
+            );
+            return;
+        }
+
+        self.pending_restores.0.insert(
+            workspace.name.clone().unwrap_or_default(),
+            PendingRestore {
+                saved_layout,
+                matched: 0,
+                deadline: Instant::now() + Self::AUTO_RESTORE_TIMEOUT,
+            },
+        );
+
+        self.check_restores();
+    }
+
+    /// Restore the pending layouts whose windows all exist.
+    pub(super) fn check_restores(&mut self) {
+        let root_node = match self.command_executor.query_root_node() {
+            Ok(root_node) => root_node,
+            Err(error) => {
+                eprintln!(
+                    "[WARN]: Failure to check the pending auto-restores: {:?}",
+                    error
+                );
+                return;
+            }
+        };
+
+        let mut ready = vec![];
+
+        for (workspace, pending) in &mut self.pending_restores.0 {
+            match resolve_windows(&pending.saved_layout, &root_node) {
+                Ok(windows) => pending.matched = windows.len(),
+                Err(error) => eprintln!("[WARN]: Failure to match the windows: {:?}", error),
+            }
+
+            if pending.matched >= pending.saved_layout.window_count() {
+                ready.push(workspace.clone());
+            }
+        }
+
+        for workspace in ready {
+            if let Some(pending) = self.pending_restores.0.remove(&workspace) {
+                self.auto_restore(&workspace, pending.saved_layout);
+            }
+        }
+    }
+
+    /// Restore the layout of a new workspace.
+    pub(super) fn auto_restore(&mut self, workspace: &str, saved_layout: SavedLayout) {
+        let result =
+            RestoreLayout::new(self.command_executor.share()).execute_layout(saved_layout, false);
+
+        match result {
+            Ok(()) => {
+                println!("Layout of workspace '{}' restored", workspace);
+                self.notifier.info(
+                    "i3-autolayout restored a layout",
+                    &format!("Workspace '{}'", workspace),
+                );
+                self.announce(Action::AutoRestore {
+                    workspace: workspace.to_string(),
+                });
+            }
+            Err(error) => {
+                eprintln!("[WARN]: Failure to auto-restore the layout: {:?}", error);
+                self.notifier
+                    .error("i3-autolayout restore failed", &format!("{:#}", error));
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::fixtures;
+    use crate::fixtures::TempDir;
+    use crate::save_layout::SaveLayout;
+    use std::time::Duration;
+
+    fn saved(workspace_id: usize) -> SavedLayout {
+        let root_node = fixtures::load("workspaces.json");
+        SaveLayout::save_subtree(fixtures::node(&root_node, workspace_id), false).unwrap()
+    }
+
+    #[test]
+    fn parse_rules() {
+        for (text, workspace, path) in [
+            (
+                "mail=~/mail.layout",
+                WorkspaceRef::Name("mail".to_string()),
+                "~/mail.layout",
+            ),
+            ("2=/tmp/2.json", WorkspaceRef::Num(2), "/tmp/2.json"),
+            (
+                "3: web=a=b",
+                WorkspaceRef::Name("3: web".to_string()),
+                "a=b",
+            ),
+        ] {
+            let rule = text.parse::<AutoRestoreRule>().unwrap();
+            assert_eq!(rule.workspace, workspace);
+            assert_eq!(rule.path, path);
+
+            assert_eq!(
+                serde_json::to_string(&rule).unwrap(),
+                serde_json::to_string(text).unwrap()
+            );
+        }
+
+        for invalid in ["mail", "mail=", "focused=x.layout"] {
+            assert_eq!(
+                invalid
+                    .parse::<AutoRestoreRule>()
+                    .err()
+                    .unwrap()
+                    .to_string(),
+                format!(
+                    "Invalid auto-restore '{}' (expected WORKSPACE=PATH)",
+                    invalid
+                )
+            );
+        }
+
+        assert_eq!(
+            "=x.layout"
+                .parse::<AutoRestoreRule>()
+                .err()
+                .unwrap()
+                .to_string(),
+            "Empty workspace reference"
+        );
+        assert!(serde_json::from_str::<AutoRestoreRule>(r#""web""#).is_err());
+    }
+
+    #[test]
+    fn rules_load_json_and_binary_layouts() {
+        let dir = TempDir::new("auto-restore");
+        let saved_layout = saved(200);
+
+        for (file_name, json) in [("2.json", true), ("2.layout", false)] {
+            let path = dir.path().join(file_name);
+            let mut bytes = vec![];
+            saved_layout.to_writer(&mut bytes, json).unwrap();
+            std::fs::write(&path, bytes).unwrap();
+
+            let rule = format!("2={}", path.display())
+                .parse::<AutoRestoreRule>()
+                .unwrap();
+            assert_eq!(rule.load().unwrap().window_count(), 4);
+        }
+    }
+
+    #[test]
+    fn pending_restores() {
+        let now = Instant::now();
+        let mut pending_restores = PendingRestores::default();
+        assert_eq!(pending_restores.next_deadline(), None);
+
+        for (workspace, workspace_id, matched, delay) in [("2", 200, 1, 30), ("1", 100, 3, 10)] {
+            pending_restores.0.insert(
+                workspace.to_string(),
+                PendingRestore {
+                    saved_layout: saved(workspace_id),
+                    matched,
+                    deadline: now + Duration::from_secs(delay),
+                },
+            );
+        }
+
+        assert_eq!(
+            pending_restores.next_deadline(),
+            Some(now + Duration::from_secs(10))
+        );
+        assert_eq!(
+            pending_restores.describe(),
+            "2 [1 (3/3 windows), 2 (1/4 windows)]"
+        );
+
+        // A workspace destroyed before its windows appeared.
+        pending_restores.on_workspace_emptied("1");
+        assert_eq!(pending_restores.describe(), "1 [2 (1/4 windows)]");
+        assert_eq!(
+            pending_restores.next_deadline(),
+            Some(now + Duration::from_secs(30))
+        );
+    }
+}
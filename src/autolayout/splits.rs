@@ -0,0 +1,361 @@
+/*
+    Copyright (C) 2022  Biagio Festa
+
+    This program is free software: you can redistribute it and/or modify
+    it under the terms of the GNU General Public License as published by
+    the Free Software Foundation, either version 3 of the License, or
+    (at your option) any later version.
+
+    This program is distributed in the hope that it will be useful,
+    but WITHOUT ANY WARRANTY; without even the implied warranty of
+    MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+    GNU General Public License for more details.
+
+    You should have received a copy of the GNU General Public License
+    along with this program.  If not, see <https://www.gnu.org/licenses/>.
+*/
+
+use super::placement::PendingResize;
+use super::AutoLayout;
+use crate::command_executor::I3Node;
+use crate::control::Action;
+use crate::state::StateComponent;
+use crate::utilities::find_node_parent;
+use crate::utilities::set_node_split;
+use crate::utilities::Split;
+use anyhow::anyhow;
+use anyhow::Result;
+use i3_ipc::reply::Rect;
+use std::collections::HashMap;
+use std::time::Duration;
+use std::time::Instant;
+
+/// The split applied to a window.
+pub(super) struct AppliedSplit {
+    /// The split.
+    split: Split,
+
+    /// When the split has been re-evaluated after a title change.
+    title_evaluated: Option<Instant>,
+
+    /// When the split has been decided.
+    decided: Instant,
+
+    /// The rect of the window when the split has been decided.
+    rect: Rect,
+
+    /// The parent of the window when the split has been decided.
+    parent: Option<usize>,
+}
+
+impl AppliedSplit {
+    /// Whether the split has been decided less than `interval` ago, with the window
+    /// still at the same place.
+    ///
+    /// Such a focus is spurious (e.g., the pointer hovering with `focus_follows_mouse`).
+    fn is_fresh(&self, interval: Duration, rect: &Rect, parent: Option<usize>) -> bool {
+        self.decided.elapsed() < interval && self.rect == *rect && self.parent == parent
+    }
+}
+
+/// The splits applied to windows, keyed by window.
+#[derive(Default)]
+pub(super) struct AppliedSplits(HashMap<usize, AppliedSplit>);
+
+impl AppliedSplits {
+    /// Whether a focus of the window is spurious: its split has been decided less than
+    /// `min_reapply` ago, with the window still at the same place (see `is_fresh`).
+    fn is_spurious(&self, node: &I3Node, parent: Option<usize>, min_reapply: Duration) -> bool {
+        self.0
+            .get(&node.id)
+            .is_some_and(|applied| applied.is_fresh(min_reapply, &node.rect, parent))
+    }
+
+    /// Record the split decided for the window.
+    ///
+    /// It returns whether the split has to be applied: after a title change, only if it
+    /// differs from the one previously applied.
+    fn record(
+        &mut self,
+        node: &I3Node,
+        parent: Option<usize>,
+        split: Split,
+        title_changed: bool,
+    ) -> bool {
+        let previous = self.0.insert(
+            node.id,
+            AppliedSplit {
+                split,
+                title_evaluated: title_changed.then(Instant::now),
+                decided: Instant::now(),
+                rect: node.rect.clone(),
+                parent,
+            },
+        );
+
+        !(title_changed && previous.is_some_and(|previous| previous.split == split))
+    }
+}
+
+impl StateComponent for AppliedSplits {
+    fn name(&self) -> &'static str {
+        "applied-splits"
+    }
+
+    fn entries(&self) -> usize {
+        self.0.len()
+    }
+
+    fn on_window_closed(&mut self, node_id: usize) {
+        self.0.remove(&node_id);
+    }
+}
+
+impl AutoLayout {
+    /// Logic to trigger when receiving a Window/Focus event.
+    pub(super) fn on_window_focus(&mut self, node: &I3Node) -> Result<()> {
+        self.evaluate_window(node, false)
+    }
+
+    /// Logic to trigger when receiving a Window/Title event for the focused window.
+    ///
+    /// Rules matching on the title are evaluated again, since many applications set their
+    /// title after the window has been mapped. It is rate-limited per window.
+    pub(super) fn on_window_title(&mut self, node: &I3Node) -> Result<()> {
+        let recently_evaluated = self
+            .applied_splits
+            .0
+            .get(&node.id)
+            .and_then(|applied_split| applied_split.title_evaluated)
+            .is_some_and(|evaluated| evaluated.elapsed() < Self::TITLE_CHANGE_INTERVAL);
+
+        if recently_evaluated {
+            return Ok(());
+        }
+
+        self.evaluate_window(node, true)
+    }
+
+    /// Decide and apply the split of a window.
+    ///
+    /// After `failure_threshold` consecutive failures, the window is left alone for
+    /// `failure_cooldown` (with a single warning), so that a persistent failure does not
+    /// flood i3 with commands on every event.
+    pub(super) fn evaluate_window(&mut self, node: &I3Node, title_changed: bool) -> Result<()> {
+        if self.backoffs.is_cooling_down(node.id) {
+            return Ok(());
+        }
+
+        let result = self.apply_window_split(node, title_changed);
+
+        if result.is_ok() {
+            self.backoffs.0.remove(&node.id);
+        } else if self.backoffs.record_failure(
+            node.id,
+            self.options.failure_threshold,
+            self.options.failure_cooldown,
+        ) {
+            let message = format!(
+                "Window '{}' failed {} times in a row; leaving it alone for {}s",
+                node.id,
+                self.options.failure_threshold,
+                self.options.failure_cooldown.as_secs()
+            );
+
+            eprintln!("[WARN]: {}", message);
+            self.notifier
+                .error("i3-autolayout paused a window", &message);
+        }
+
+        result
+    }
+
+    /// Decide and apply the split of a window.
+    ///
+    /// With `title_changed`, the split is applied only if it differs from the one
+    /// previously applied.
+    fn apply_window_split(&mut self, node: &I3Node, title_changed: bool) -> Result<()> {
+        let root_node = self.command_executor.query_root_node()?;
+        let parent = find_node_parent(node.id, &root_node).map(|parent| parent.id);
+
+        if !title_changed
+            && self
+                .applied_splits
+                .is_spurious(node, parent, self.options.min_reapply)
+        {
+            return Ok(());
+        }
+
+        let decision =
+            self.options
+                .split_policy
+                .decide(node, &root_node, self.split_memory.as_ref())?;
+
+        if self.suspensions.is_suspended(&decision.workspace)
+            || self.unmanaged.contains(&decision.workspace)
+        {
+            return Ok(());
+        }
+
+        let Some(split) = decision.split else {
+            return Ok(());
+        };
+        let ppt = decision.ppt;
+        let workspace = decision.workspace;
+
+        if let (Some(split_memory), Some(key)) = (&mut self.split_memory, decision.key) {
+            if let Err(error) = split_memory.remember(key, split) {
+                eprintln!("[WARN]: Failure to remember the split: {:?}", error);
+            }
+        }
+
+        if !self
+            .applied_splits
+            .record(node, parent, split, title_changed)
+        {
+            return Ok(());
+        }
+
+        set_node_split(node.id, split, &mut self.command_executor)?;
+
+        self.announce(Action::Split {
+            dir: split,
+            con_id: node.id,
+            workspace,
+        });
+
+        if let Some(ppt) = ppt {
+            let root_node = self.command_executor.query_root_node()?;
+            let container = find_node_parent(node.id, &root_node)
+                .ok_or_else(|| anyhow!("Cannot find parent of focused window"))?;
+
+            self.pending_resizes.0.insert(
+                container.id,
+                PendingResize {
+                    split,
+                    ppt,
+                    deadline: Instant::now() + Self::PENDING_RESIZE_TIMEOUT,
+                },
+            );
+        }
+
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::command_executor::RootNode;
+    use crate::fixtures;
+
+    const INTERVAL: Duration = Duration::from_millis(500);
+
+    fn applied(node: &I3Node, parent: Option<usize>, decided: Instant) -> AppliedSplit {
+        AppliedSplit {
+            split: Split::Vertical,
+            title_evaluated: None,
+            decided,
+            rect: node.rect.clone(),
+            parent,
+        }
+    }
+
+    #[test]
+    fn focus_right_after_the_split_is_spurious() {
+        let root_node = fixtures::load("workspaces.json");
+        let node = fixtures::node(&root_node, 102);
+        let applied = applied(node, Some(100), Instant::now());
+
+        assert!(applied.is_fresh(INTERVAL, &node.rect, Some(100)));
+
+        // A zero interval evaluates every focus.
+        assert!(!applied.is_fresh(Duration::ZERO, &node.rect, Some(100)));
+    }
+
+    #[test]
+    fn moved_or_resized_windows_are_evaluated() {
+        let root_node = fixtures::load("workspaces.json");
+        let node = fixtures::node(&root_node, 102);
+        let applied = applied(node, Some(100), Instant::now());
+
+        // Moved to another container.
+        assert!(!applied.is_fresh(INTERVAL, &node.rect, Some(210)));
+
+        // Resized.
+        let mut rect = node.rect.clone();
+        rect.width += 10;
+        assert!(!applied.is_fresh(INTERVAL, &rect, Some(100)));
+    }
+
+    #[test]
+    fn focus_after_the_interval_is_evaluated() {
+        let root_node = fixtures::load("workspaces.json");
+        let node = fixtures::node(&root_node, 102);
+
+        let Some(decided) = Instant::now().checked_sub(INTERVAL) else {
+            return;
+        };
+        let applied = applied(node, Some(100), decided);
+
+        assert!(!applied.is_fresh(INTERVAL, &node.rect, Some(100)));
+    }
+
+    /// The split commands issued for a sequence of focus events, as `apply_window_split`
+    /// does (the same split decided for every window).
+    fn split_commands(
+        applied_splits: &mut AppliedSplits,
+        root_node: &RootNode,
+        focused: &[usize],
+        min_reapply: Duration,
+    ) -> Vec<usize> {
+        let mut commands = vec![];
+
+        for &node_id in focused {
+            let node = fixtures::node(root_node, node_id);
+            let parent = find_node_parent(node_id, root_node).map(|parent| parent.id);
+
+            if applied_splits.is_spurious(node, parent, min_reapply) {
+                continue;
+            }
+
+            if applied_splits.record(node, parent, Split::Vertical, false) {
+                commands.push(node_id);
+            }
+        }
+
+        commands
+    }
+
+    #[test]
+    fn hover_storm_splits_each_window_once() {
+        let root_node = fixtures::load("workspaces.json");
+
+        // The pointer crosses the three windows of the workspace back and forth.
+        let storm = [101, 102, 103, 102, 101, 102, 103, 103, 102, 101, 102, 103];
+
+        let mut applied_splits = AppliedSplits::default();
+        let commands = split_commands(&mut applied_splits, &root_node, &storm, INTERVAL);
+        assert_eq!(commands, [101, 102, 103]);
+
+        // The same storm again, still within the interval: nothing is issued.
+        let commands = split_commands(&mut applied_splits, &root_node, &storm, INTERVAL);
+        assert!(commands.is_empty());
+
+        // Without the debounce, every focus issues a split.
+        let mut applied_splits = AppliedSplits::default();
+        let commands = split_commands(&mut applied_splits, &root_node, &storm, Duration::ZERO);
+        assert_eq!(commands.len(), storm.len());
+    }
+
+    #[test]
+    fn title_changes_split_again_only_on_change() {
+        let root_node = fixtures::load("workspaces.json");
+        let node = fixtures::node(&root_node, 101);
+        let mut applied_splits = AppliedSplits::default();
+
+        assert!(applied_splits.record(node, Some(100), Split::Vertical, false));
+        assert!(!applied_splits.record(node, Some(100), Split::Vertical, true));
+        assert!(applied_splits.record(node, Some(100), Split::Horizontal, true));
+    }
+}
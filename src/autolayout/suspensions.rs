@@ -0,0 +1,141 @@
+/*
+    Copyright (C) 2022  Biagio Festa
+
+    This program is free software: you can redistribute it and/or modify
+    it under the terms of the GNU General Public License as published by
+    the Free Software Foundation, either version 3 of the License, or
+    (at your option) any later version.
+
+    This program is distributed in the hope that it will be useful,
+    but WITHOUT ANY WARRANTY; without even the implied warranty of
+    MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+    GNU General Public License for more details.
+
+    You should have received a copy of the GNU General Public License
+    along with this program.  If not, see <https://www.gnu.org/licenses/>.
+*/
+
+use crate::state::StateComponent;
+use std::collections::HashMap;
+use std::time::Instant;
+
+/// A pause of the service actions on a workspace.
+pub(super) struct Suspension {
+    /// The workspace name.
+    pub(super) workspace: String,
+
+    /// The workspace id and whether it was in tabmode when suspended (only tracked to
+    /// notify tabmode toggles).
+    pub(super) tabmode: Option<(usize, bool)>,
+
+    /// When the suspension expires if not resumed.
+    pub(super) deadline: Instant,
+}
+
+/// The suspensions requested by other processes, keyed by token.
+#[derive(Default)]
+pub(super) struct Suspensions(pub(super) HashMap<String, Suspension>);
+
+impl Suspensions {
+    /// Whether the actions on the workspace are paused.
+    ///
+    /// Expired suspensions are dropped.
+    pub(super) fn is_suspended(&mut self, workspace: &str) -> bool {
+        let now = Instant::now();
+        self.0.retain(|token, suspension| {
+            let alive = suspension.deadline > now;
+            if !alive {
+                eprintln!(
+                    "[WARN]: Suspension '{}' expired without being resumed",
+                    token
+                );
+            }
+            alive
+        });
+
+        self.0
+            .values()
+            .any(|suspension| suspension.workspace == workspace)
+    }
+}
+
+impl StateComponent for Suspensions {
+    fn name(&self) -> &'static str {
+        "suspensions"
+    }
+
+    fn entries(&self) -> usize {
+        self.0.len()
+    }
+
+    fn on_workspace_emptied(&mut self, workspace: &str) {
+        self.0
+            .retain(|_, suspension| suspension.workspace != workspace);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::time::Duration;
+
+    fn suspension(workspace: &str, deadline: Instant) -> Suspension {
+        Suspension {
+            workspace: workspace.to_string(),
+            tabmode: None,
+            deadline,
+        }
+    }
+
+    #[test]
+    fn suspended_until_resumed() {
+        let mut suspensions = Suspensions::default();
+        let deadline = Instant::now() + Duration::from_secs(60);
+
+        suspensions
+            .0
+            .insert("1-0".to_string(), suspension("web", deadline));
+        suspensions
+            .0
+            .insert("1-1".to_string(), suspension("web", deadline));
+
+        assert!(suspensions.is_suspended("web"));
+        assert!(!suspensions.is_suspended("1"));
+
+        // Each token resumes its own suspension only.
+        suspensions.0.remove("1-0");
+        assert!(suspensions.is_suspended("web"));
+        suspensions.0.remove("1-1");
+        assert!(!suspensions.is_suspended("web"));
+    }
+
+    #[test]
+    fn expired_suspensions_are_dropped() {
+        let mut suspensions = Suspensions::default();
+
+        suspensions
+            .0
+            .insert("1-0".to_string(), suspension("web", Instant::now()));
+
+        assert!(!suspensions.is_suspended("web"));
+        assert_eq!(suspensions.entries(), 0);
+    }
+
+    #[test]
+    fn emptied_workspaces_drop_their_suspensions() {
+        let mut suspensions = Suspensions::default();
+        let deadline = Instant::now() + Duration::from_secs(60);
+
+        suspensions
+            .0
+            .insert("1-0".to_string(), suspension("web", deadline));
+        suspensions
+            .0
+            .insert("1-1".to_string(), suspension("2", deadline));
+
+        suspensions.on_workspace_emptied("web");
+
+        assert!(!suspensions.is_suspended("web"));
+        assert!(suspensions.is_suspended("2"));
+    }
+}
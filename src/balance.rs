@@ -0,0 +1,80 @@
+/*
+    Copyright (C) 2022  Biagio Festa
+
+    This program is free software: you can redistribute it and/or modify
+    it under the terms of the GNU General Public License as published by
+    the Free Software Foundation, either version 3 of the License, or
+    (at your option) any later version.
+
+    This program is distributed in the hope that it will be useful,
+    but WITHOUT ANY WARRANTY; without even the implied warranty of
+    MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+    GNU General Public License for more details.
+
+    You should have received a copy of the GNU General Public License
+    along with this program.  If not, see <https://www.gnu.org/licenses/>.
+*/
+
+use crate::command_executor::CommandExecutor;
+use crate::undo::record_undo;
+use crate::utilities::balance_subtree;
+use crate::utilities::ShareRatio;
+use crate::utilities::DEFAULT_SHARE_TOLERANCE;
+use crate::workspace::WorkspaceRef;
+use anyhow::Context;
+use anyhow::Result;
+
+/// Balance executor.
+///
+/// It represents a one-shot executor which divides the space of each container of a
+/// workspace among its children (see `balance_subtree`).
+pub struct Balance {
+    /// Command executor.
+    command_executor: CommandExecutor,
+
+    /// The tolerance (percentage points) of the shares after balancing.
+    tolerance: f64,
+
+    /// How the space of the containers is divided.
+    ratio: ShareRatio,
+}
+
+impl Balance {
+    /// A new balance executor.
+    pub fn new(command_executor: CommandExecutor) -> Self {
+        Self {
+            command_executor,
+            tolerance: DEFAULT_SHARE_TOLERANCE,
+            ratio: ShareRatio::Equal,
+        }
+    }
+
+    /// How the space of the containers is divided (see `ratio_shares`).
+    pub fn with_ratio(mut self, ratio: ShareRatio) -> Self {
+        self.ratio = ratio;
+        self
+    }
+
+    /// The tolerance (percentage points) of the shares after balancing (see `verify_shares`).
+    pub fn with_tolerance(mut self, tolerance: f64) -> Self {
+        self.tolerance = tolerance;
+        self
+    }
+
+    /// Execute the action on `workspace`.
+    ///
+    /// Windows holding a size lock keep their share.
+    pub fn execute(mut self, workspace: WorkspaceRef) -> Result<()> {
+        let root_node = self.command_executor.query_root_node()?;
+        let workspace = workspace.resolve(&root_node, &mut self.command_executor)?;
+        record_undo(&[workspace]);
+
+        balance_subtree(
+            workspace,
+            &self.ratio,
+            self.tolerance,
+            &mut self.command_executor,
+        )
+        .context("Cannot balance the workspace")
+    }
+}
@@ -0,0 +1,213 @@
+/*
+    Copyright (C) 2022  Biagio Festa
+
+    This program is free software: you can redistribute it and/or modify
+    it under the terms of the GNU General Public License as published by
+    the Free Software Foundation, either version 3 of the License, or
+    (at your option) any later version.
+
+    This program is distributed in the hope that it will be useful,
+    but WITHOUT ANY WARRANTY; without even the implied warranty of
+    MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+    GNU General Public License for more details.
+
+    You should have received a copy of the GNU General Public License
+    along with this program.  If not, see <https://www.gnu.org/licenses/>.
+*/
+
+use crate::utilities::quote;
+use anyhow::Context;
+use anyhow::Result;
+use std::io::BufRead;
+use std::io::Write;
+
+/// A click event of the i3bar protocol (only the fields of interest).
+#[derive(serde::Deserialize)]
+struct ClickEvent {
+    #[serde(default)]
+    name: Option<String>,
+
+    #[serde(default)]
+    instance: Option<String>,
+
+    button: u32,
+}
+
+/// The subcommand bound to a mouse button on the workspace blocks.
+#[derive(Clone, serde::Deserialize, serde::Serialize)]
+#[serde(deny_unknown_fields, rename_all = "kebab-case")]
+pub struct BarButton {
+    /// The mouse button (1 left, 2 middle, 3 right, ...).
+    pub button: u32,
+
+    /// The subcommand line (batch syntax), where `{workspace}` is replaced by the
+    /// clicked workspace (e.g., `tabmode -w {workspace}`).
+    pub action: String,
+}
+
+/// Bar proxy executor.
+///
+/// It sits between i3bar and the status command: click events on the workspace blocks
+/// (blocks named `block_name`, whose instance is the workspace name) bound to a button
+/// execute a subcommand, everything else is passed through.
+pub struct BarProxy {
+    /// The name of the workspace blocks.
+    block_name: String,
+
+    /// The bound buttons.
+    buttons: Vec<BarButton>,
+}
+
+impl BarProxy {
+    /// A new bar proxy executor.
+    pub fn new(block_name: String, buttons: Vec<BarButton>) -> Self {
+        Self {
+            block_name,
+            buttons,
+        }
+    }
+
+    /// Execute the proxy until the end of `input`.
+    ///
+    /// Each bound click is executed with `run` (failures are only reported). Lines which
+    /// are not click events (e.g., the protocol header or malformed JSON) are forwarded
+    /// unmodified; forwarded events only get their array separator adjusted, as the bound
+    /// clicks are consumed.
+    pub fn execute<R, W, F>(self, input: R, mut output: W, mut run: F) -> Result<()>
+    where
+        R: BufRead,
+        W: Write,
+        F: FnMut(&str) -> Result<()>,
+    {
+        let mut forwarded_event = false;
+
+        for line in input.lines() {
+            let line = line.context("Cannot read the click events")?;
+            let event = line.trim();
+            let event = event.strip_prefix(',').unwrap_or(event).trim_start();
+
+            match serde_json::from_str::<ClickEvent>(event) {
+                Ok(click_event) => {
+                    if let Some(action) = self.action_of(&click_event) {
+                        if let Err(error) = run(&action) {
+                            eprintln!("[WARN]: Cannot execute '{}': {:#}", action, error);
+                        }
+
+                        continue;
+                    }
+
+                    let separator = if forwarded_event { "," } else { "" };
+                    writeln!(output, "{}{}", separator, event)
+                        .context("Cannot forward the click events")?;
+                    forwarded_event = true;
+                }
+
+                Err(_) => {
+                    writeln!(output, "{}", line).context("Cannot forward the click events")?
+                }
+            }
+
+            output.flush().context("Cannot forward the click events")?;
+        }
+
+        Ok(())
+    }
+
+    /// The subcommand line bound to a click event (if any).
+    fn action_of(&self, click_event: &ClickEvent) -> Option<String> {
+        if click_event.name.as_deref() != Some(self.block_name.as_str()) {
+            return None;
+        }
+
+        let workspace = click_event.instance.as_deref()?;
+
+        self.buttons
+            .iter()
+            .find(|button| button.button == click_event.button)
+            .map(|button| button.action.replace("{workspace}", &quote(workspace)))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use anyhow::anyhow;
+
+    fn bar_proxy() -> BarProxy {
+        BarProxy::new(
+            "workspace".to_string(),
+            vec![
+                BarButton {
+                    button: 2,
+                    action: "tabmode -w {workspace}".to_string(),
+                },
+                BarButton {
+                    button: 3,
+                    action: "fail".to_string(),
+                },
+            ],
+        )
+    }
+
+    /// The output of the proxy and the executed actions.
+    fn proxy(input: &str) -> (String, Vec<String>) {
+        let mut output = vec![];
+        let mut actions = vec![];
+
+        bar_proxy()
+            .execute(input.as_bytes(), &mut output, |action| {
+                actions.push(action.to_string());
+                match action {
+                    "fail" => Err(anyhow!("Failure")),
+                    _ => Ok(()),
+                }
+            })
+            .unwrap();
+
+        (String::from_utf8(output).unwrap(), actions)
+    }
+
+    #[test]
+    fn bound_clicks_are_consumed() {
+        let (output, actions) = proxy(concat!(
+            "[\n",
+            "{\"name\":\"workspace\",\"instance\":\"3 []\",\"button\":2}\n",
+            ",{\"name\":\"workspace\",\"instance\":\"1\",\"button\":3}\n",
+        ));
+
+        assert_eq!(actions, ["tabmode -w \"3 []\"", "fail"]);
+        assert_eq!(output, "[\n");
+    }
+
+    #[test]
+    fn other_events_are_forwarded() {
+        let (output, actions) = proxy(concat!(
+            "[\n",
+            "{\"name\":\"workspace\",\"instance\":\"1\",\"button\":2}\n",
+            ",{\"name\":\"workspace\",\"instance\":\"1\",\"button\":1}\n",
+            ",{\"name\":\"clock\",\"button\":2}\n",
+            "not json\n",
+        ));
+
+        // The first forwarded event gets no separator, as the one before is consumed.
+        assert_eq!(actions, ["tabmode -w \"1\""]);
+        assert_eq!(
+            output,
+            concat!(
+                "[\n",
+                "{\"name\":\"workspace\",\"instance\":\"1\",\"button\":1}\n",
+                ",{\"name\":\"clock\",\"button\":2}\n",
+                "not json\n",
+            )
+        );
+    }
+
+    #[test]
+    fn blocks_without_workspace_are_not_bound() {
+        let click_event =
+            serde_json::from_str::<ClickEvent>("{\"name\":\"workspace\",\"button\":2,\"x\":10}")
+                .unwrap();
+
+        assert!(bar_proxy().action_of(&click_event).is_none());
+    }
+}
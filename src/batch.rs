@@ -0,0 +1,112 @@
+/*
+    Copyright (C) 2022  Biagio Festa
+
+    This program is free software: you can redistribute it and/or modify
+    it under the terms of the GNU General Public License as published by
+    the Free Software Foundation, either version 3 of the License, or
+    (at your option) any later version.
+
+    This program is distributed in the hope that it will be useful,
+    but WITHOUT ANY WARRANTY; without even the implied warranty of
+    MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+    GNU General Public License for more details.
+
+    You should have received a copy of the GNU General Public License
+    along with this program.  If not, see <https://www.gnu.org/licenses/>.
+*/
+
+use anyhow::anyhow;
+use anyhow::Result;
+
+/// Split a batch line into arguments, as a shell would.
+///
+/// Arguments are separated by whitespace. Single quotes preserve everything literally,
+/// while within double quotes (and outside quotes) a backslash escapes the next character.
+pub fn split_line(line: &str) -> Result<Vec<String>> {
+    let mut arguments = vec![];
+    let mut current: Option<String> = None;
+    let mut chars = line.chars();
+
+    while let Some(c) = chars.next() {
+        match c {
+            c if c.is_whitespace() => {
+                if let Some(argument) = current.take() {
+                    arguments.push(argument);
+                }
+            }
+
+            '\'' => {
+                let argument = current.get_or_insert_with(String::new);
+
+                loop {
+                    match chars.next() {
+                        Some('\'') => break,
+                        Some(c) => argument.push(c),
+                        None => return Err(anyhow!("Unterminated single quote in '{}'", line)),
+                    }
+                }
+            }
+
+            '"' => {
+                let argument = current.get_or_insert_with(String::new);
+
+                loop {
+                    match chars.next() {
+                        Some('"') => break,
+                        Some('\\') => match chars.next() {
+                            Some(c) => argument.push(c),
+                            None => return Err(anyhow!("Trailing backslash in '{}'", line)),
+                        },
+                        Some(c) => argument.push(c),
+                        None => return Err(anyhow!("Unterminated double quote in '{}'", line)),
+                    }
+                }
+            }
+
+            '\\' => match chars.next() {
+                Some(c) => current.get_or_insert_with(String::new).push(c),
+                None => return Err(anyhow!("Trailing backslash in '{}'", line)),
+            },
+
+            c => current.get_or_insert_with(String::new).push(c),
+        }
+    }
+
+    arguments.extend(current);
+
+    Ok(arguments)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn split_like_a_shell() {
+        for (line, expected) in [
+            ("", vec![]),
+            ("  tabmode   -w 3 ", vec!["tabmode", "-w", "3"]),
+            ("tabmode -w '3 []'", vec!["tabmode", "-w", "3 []"]),
+            (r#"tabmode -w "a \"b\"""#, vec!["tabmode", "-w", r#"a "b""#]),
+            (r"tabmode -w 3\ []", vec!["tabmode", "-w", "3 []"]),
+            (r"echo 'a\b'", vec!["echo", r"a\b"]),
+            (r#"echo a'b c'"d""#, vec!["echo", "ab cd"]),
+            ("echo ''", vec!["echo", ""]),
+        ] {
+            assert_eq!(split_line(line).unwrap(), expected, "Line '{}'", line);
+        }
+    }
+
+    #[test]
+    fn split_errors() {
+        for (line, expected) in [
+            ("echo 'a", "Unterminated single quote in 'echo 'a'"),
+            ("echo \"a", "Unterminated double quote in 'echo \"a'"),
+            ("echo \"a\\", "Trailing backslash in 'echo \"a\\'"),
+            ("echo a\\", "Trailing backslash in 'echo a\\'"),
+        ] {
+            let error = split_line(line).unwrap_err();
+            assert_eq!(error.to_string(), expected, "Line '{}'", line);
+        }
+    }
+}
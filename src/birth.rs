@@ -0,0 +1,293 @@
+/*
+    Copyright (C) 2022  Biagio Festa
+
+    This program is free software: you can redistribute it and/or modify
+    it under the terms of the GNU General Public License as published by
+    the Free Software Foundation, either version 3 of the License, or
+    (at your option) any later version.
+
+    This program is distributed in the hope that it will be useful,
+    but WITHOUT ANY WARRANTY; without even the implied warranty of
+    MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+    GNU General Public License for more details.
+
+    You should have received a copy of the GNU General Public License
+    along with this program.  If not, see <https://www.gnu.org/licenses/>.
+*/
+
+use crate::command_executor::I3Node;
+use crate::command_executor::RootNode;
+use crate::state::StateComponent;
+use crate::state_store::StateStore;
+use anyhow::Context;
+use anyhow::Result;
+use std::collections::HashMap;
+
+/// A window and the workspace it first appeared on.
+#[derive(serde::Deserialize, serde::Serialize)]
+struct BirthRecord {
+    /// The X11 window id.
+    window: usize,
+
+    /// The name of the workspace.
+    workspace: String,
+}
+
+/// The file content.
+#[derive(Default, serde::Deserialize, serde::Serialize)]
+struct BirthFile {
+    /// The records, the oldest first.
+    windows: Vec<BirthRecord>,
+}
+
+/// The workspace each window first appeared on (see `send-home`).
+///
+/// Windows are tracked by X11 window id, which survives i3 restarts, and persisted in the
+/// state directory (`birth-workspaces.json`), so that the service can be restarted
+/// meanwhile. At most `CAPACITY` windows are tracked: the oldest records go first.
+#[derive(Default)]
+pub struct BirthWorkspaces {
+    /// The store of the file (the state directory if not loaded yet).
+    store: Option<StateStore>,
+
+    file: BirthFile,
+
+    /// The X11 window ids of the live tracked windows by con id, to drop their records
+    /// when closed.
+    ids: HashMap<usize, usize>,
+}
+
+impl BirthWorkspaces {
+    const FILE_NAME: &'static str = "birth-workspaces.json";
+
+    /// The version of the file format.
+    const VERSION: u32 = 1;
+
+    /// The maximum number of windows tracked.
+    const CAPACITY: usize = 1024;
+
+    /// Load the birth workspaces.
+    ///
+    /// A missing file means none; an unreadable one is quarantined (see `StateStore::load`).
+    pub fn load() -> Result<Self> {
+        Ok(Self::load_from(StateStore::open()?))
+    }
+
+    /// Load the birth workspaces of a store.
+    fn load_from(store: StateStore) -> Self {
+        Self {
+            file: store.load(Self::FILE_NAME, Self::VERSION),
+            store: Some(store),
+            ids: HashMap::new(),
+        }
+    }
+
+    /// The workspace a window (by X11 window id) first appeared on.
+    pub fn workspace_of(&self, window: usize) -> Option<&str> {
+        self.file
+            .windows
+            .iter()
+            .find(|record| record.window == window)
+            .map(|record| record.workspace.as_str())
+    }
+
+    /// Follow the live windows, dropping the records of the windows closed meanwhile
+    /// (e.g., while the service was not running).
+    pub fn track(&mut self, root_node: &RootNode) -> Result<()> {
+        let mut live = HashMap::new();
+        let mut dfs = vec![root_node.node()];
+
+        while let Some(current) = dfs.pop() {
+            if let Some(window) = current.window {
+                live.insert(window, current.id);
+            }
+
+            dfs.extend(current.nodes.as_slice());
+            dfs.extend(current.floating_nodes.as_slice());
+        }
+
+        let before = self.file.windows.len();
+        self.file
+            .windows
+            .retain(|record| live.contains_key(&record.window));
+
+        self.ids = self
+            .file
+            .windows
+            .iter()
+            .map(|record| (live[&record.window], record.window))
+            .collect();
+
+        if self.file.windows.len() != before {
+            self.store()?;
+        }
+
+        Ok(())
+    }
+
+    /// Record the workspace a new window appeared on and persist it.
+    ///
+    /// A window already tracked keeps its record.
+    pub fn record(&mut self, node: &I3Node, workspace: &str) -> Result<()> {
+        let Some(window) = node.window else {
+            return Ok(());
+        };
+
+        if self.workspace_of(window).is_some() {
+            return Ok(());
+        }
+
+        if self.file.windows.len() >= Self::CAPACITY {
+            let excess = self.file.windows.len() + 1 - Self::CAPACITY;
+            let dropped = self.file.windows.drain(..excess).collect::<Vec<_>>();
+            self.ids
+                .retain(|_, window| !dropped.iter().any(|record| record.window == *window));
+        }
+
+        self.file.windows.push(BirthRecord {
+            window,
+            workspace: workspace.to_string(),
+        });
+        self.ids.insert(node.id, window);
+
+        self.store()
+    }
+
+    fn store(&mut self) -> Result<()> {
+        let store = match self.store.take() {
+            Some(store) => store,
+            None => StateStore::open()?,
+        };
+
+        let result = store
+            .store(Self::FILE_NAME, Self::VERSION, &self.file)
+            .context("Cannot write the birth workspaces");
+        self.store = Some(store);
+
+        result
+    }
+}
+
+impl StateComponent for BirthWorkspaces {
+    fn name(&self) -> &'static str {
+        "birth-workspaces"
+    }
+
+    fn entries(&self) -> usize {
+        self.file.windows.len()
+    }
+
+    fn on_window_closed(&mut self, node_id: usize) {
+        let Some(window) = self.ids.remove(&node_id) else {
+            return;
+        };
+
+        self.file.windows.retain(|record| record.window != window);
+
+        if let Err(error) = self.store() {
+            eprintln!("[WARN]: {:?}", error);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::fixtures;
+    use crate::fixtures::TempDir;
+
+    fn births_in(dir: &TempDir) -> BirthWorkspaces {
+        BirthWorkspaces::load_from(StateStore::in_dir(dir.path().to_path_buf()))
+    }
+
+    #[test]
+    fn records_survive_reload() {
+        let dir = TempDir::new("birth");
+        let root_node = fixtures::load("workspaces.json");
+
+        let mut births = births_in(&dir);
+        births.record(fixtures::node(&root_node, 101), "1").unwrap();
+        births
+            .record(fixtures::node(&root_node, 411), "web")
+            .unwrap();
+
+        // A window keeps its first workspace.
+        births.record(fixtures::node(&root_node, 101), "2").unwrap();
+
+        // Containers without a window are not recorded.
+        births.record(fixtures::node(&root_node, 501), "5").unwrap();
+
+        let births = births_in(&dir);
+        assert_eq!(births.entries(), 2);
+        assert_eq!(births.workspace_of(20971621), Some("1"));
+        assert_eq!(births.workspace_of(20971931), Some("web"));
+        assert_eq!(births.workspace_of(20971622), None);
+    }
+
+    #[test]
+    fn oldest_records_go_first() {
+        let dir = TempDir::new("birth");
+        let root_node = fixtures::load("workspaces.json");
+        let mut births = births_in(&dir);
+
+        births.file.windows = (0..BirthWorkspaces::CAPACITY)
+            .map(|window| BirthRecord {
+                window,
+                workspace: "1".to_string(),
+            })
+            .collect();
+        births.ids.insert(1, 0);
+
+        births.record(fixtures::node(&root_node, 102), "1").unwrap();
+
+        assert_eq!(births.entries(), BirthWorkspaces::CAPACITY);
+        assert_eq!(births.workspace_of(0), None);
+        assert_eq!(births.workspace_of(1), Some("1"));
+        assert_eq!(births.workspace_of(20971622), Some("1"));
+        assert!(!births.ids.contains_key(&1));
+    }
+
+    #[test]
+    fn closed_windows_are_dropped() {
+        let dir = TempDir::new("birth");
+        let root_node = fixtures::load("workspaces.json");
+
+        let mut births = births_in(&dir);
+        births.record(fixtures::node(&root_node, 101), "1").unwrap();
+        births.record(fixtures::node(&root_node, 102), "1").unwrap();
+
+        births.on_window_closed(101);
+        assert_eq!(births.workspace_of(20971621), None);
+        assert_eq!(births_in(&dir).workspace_of(20971621), None);
+
+        // Not a tracked window.
+        births.on_window_closed(999);
+        assert_eq!(births.entries(), 1);
+    }
+
+    #[test]
+    fn track_drops_the_windows_closed_meanwhile() {
+        let dir = TempDir::new("birth");
+        let root_node = fixtures::load("workspaces.json");
+
+        let mut births = births_in(&dir);
+        births.record(fixtures::node(&root_node, 101), "1").unwrap();
+        births.record(fixtures::node(&root_node, 211), "2").unwrap();
+
+        // After a restart, both windows are still open.
+        let mut births = births_in(&dir);
+        births.track(&fixtures::load("multi_output.json")).unwrap();
+        assert_eq!(births.workspace_of(20971621), Some("1"));
+        assert_eq!(births.workspace_of(20971731), Some("2"));
+
+        // Then the terminal of workspace 2 is closed while the service is down.
+        births.track(&fixtures::load("scratchpad.json")).unwrap();
+        assert_eq!(births.workspace_of(20971731), None);
+        assert_eq!(births.workspace_of(20971621), Some("1"));
+
+        // The live windows are followed by con id again.
+        births.on_window_closed(101);
+        assert_eq!(births.entries(), 0);
+        assert_eq!(births_in(&dir).entries(), 0);
+    }
+}
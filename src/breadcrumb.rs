@@ -0,0 +1,307 @@
+/*
+    Copyright (C) 2022  Biagio Festa
+
+    This program is free software: you can redistribute it and/or modify
+    it under the terms of the GNU General Public License as published by
+    the Free Software Foundation, either version 3 of the License, or
+    (at your option) any later version.
+
+    This program is distributed in the hope that it will be useful,
+    but WITHOUT ANY WARRANTY; without even the implied warranty of
+    MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+    GNU General Public License for more details.
+
+    You should have received a copy of the GNU General Public License
+    along with this program.  If not, see <https://www.gnu.org/licenses/>.
+*/
+use crate::command_executor::CommandExecutor;
+use crate::command_executor::I3Node;
+use crate::command_executor::RootNode;
+use crate::event_listener::EventListener;
+use crate::event_listener::EventSubscribe;
+use crate::print_tree::node_layout_name;
+use crate::utilities::find_focused_node;
+use crate::utilities::find_node_path;
+use anyhow::anyhow;
+use anyhow::Context;
+use anyhow::Result;
+use i3_ipc::event::Event;
+use i3_ipc::event::WindowChange;
+use i3_ipc::event::WorkspaceChange;
+use i3_ipc::reply::NodeType;
+
+/// A node on the path to the focused node.
+#[derive(serde::Serialize)]
+struct Hop {
+    /// The node type (`output`, `workspace`, `con`, `floating_con` or `window`).
+    #[serde(rename = "type")]
+    kind: &'static str,
+
+    /// The con id.
+    id: usize,
+
+    /// The node name (the title, for windows).
+    name: Option<String>,
+
+    /// The layout (containers and workspaces only).
+    layout: Option<&'static str>,
+
+    /// The position (from 1) among the siblings (containers and windows only).
+    index: Option<usize>,
+
+    /// The number of siblings, the node included (containers and windows only).
+    siblings: Option<usize>,
+
+    /// Whether the node is among the floating nodes of its parent.
+    floating: bool,
+}
+
+impl Hop {
+    /// A one-line description (e.g., `con [splitv] (2/3)`).
+    fn describe(&self) -> String {
+        let mut description = self.kind.to_string();
+
+        match self.kind {
+            "window" => {
+                description.push_str(&format!(" {:?}", self.name.as_deref().unwrap_or_default()))
+            }
+            "output" | "workspace" => {
+                description.push_str(&format!(" {}", self.name.as_deref().unwrap_or_default()))
+            }
+            _ => {}
+        }
+
+        if let Some(layout) = self.layout {
+            description.push_str(&format!(" [{}]", layout));
+        }
+
+        if let (Some(index), Some(siblings)) = (self.index, self.siblings) {
+            let floating = if self.floating { " floating" } else { "" };
+            description.push_str(&format!(" ({}/{}{})", index, siblings, floating));
+        }
+
+        description
+    }
+}
+
+/// Breadcrumb executor.
+///
+/// It represents an executor which prints the chain of nodes from the output down to
+/// the focused node, to debug the nesting of a workspace.
+pub struct Breadcrumb {
+    /// Command executor.
+    command_executor: CommandExecutor,
+
+    /// Print the chain as JSON.
+    json: bool,
+}
+
+impl Breadcrumb {
+    /// A new breadcrumb executor.
+    pub fn new(command_executor: CommandExecutor) -> Self {
+        Self {
+            command_executor,
+            json: false,
+        }
+    }
+
+    /// Print the chain as JSON (an array of nodes, one line per chain).
+    pub fn with_json(mut self, json: bool) -> Self {
+        self.json = json;
+        self
+    }
+
+    /// Print the chain of the focused node.
+    ///
+    /// With `watch`, it is printed again on every focus change (and never returns
+    /// unless the connection breaks).
+    pub fn execute(mut self, watch: bool) -> Result<()> {
+        let mut event_listener = if watch {
+            Some(EventListener::new(&[
+                EventSubscribe::Window,
+                EventSubscribe::Workspace,
+            ])?)
+        } else {
+            None
+        };
+
+        self.print()?;
+
+        let Some(event_listener) = &mut event_listener else {
+            return Ok(());
+        };
+
+        loop {
+            let refresh = match event_listener.receive_event()? {
+                Event::Window(window_data) => matches!(
+                    window_data.change,
+                    WindowChange::Focus | WindowChange::Move | WindowChange::Close
+                ),
+                Event::Workspace(workspace_data) => workspace_data.change == WorkspaceChange::Focus,
+                _ => false,
+            };
+
+            if refresh {
+                self.print()?;
+            }
+        }
+    }
+
+    /// Print the current chain of the focused node.
+    fn print(&mut self) -> Result<()> {
+        let root_node = self.command_executor.query_root_node()?;
+        let hops = Self::hops(&root_node)?;
+
+        if self.json {
+            println!(
+                "{}",
+                serde_json::to_string(&hops).context("Cannot serialize the chain")?
+            );
+        } else {
+            for hop in &hops {
+                println!("{}", hop.describe());
+            }
+
+            println!();
+        }
+
+        Ok(())
+    }
+
+    /// The chain of nodes from the output down to the focused node.
+    ///
+    /// The root and the content container of the output are skipped.
+    fn hops(root_node: &RootNode) -> Result<Vec<Hop>> {
+        let focused =
+            find_focused_node(root_node).ok_or_else(|| anyhow!("Cannot find the focused node"))?;
+        let path = find_node_path(focused.id, root_node);
+
+        let hops = path
+            .windows(2)
+            .filter_map(|pair| match pair {
+                [parent, node]
+                    if parent.node_type == NodeType::Output && node.node_type == NodeType::Con =>
+                {
+                    None
+                }
+                [parent, node] => Some(Self::hop(parent, node)),
+                _ => None,
+            })
+            .collect();
+
+        Ok(hops)
+    }
+
+    /// The hop of `node`, child of `parent`.
+    fn hop(parent: &I3Node, node: &I3Node) -> Hop {
+        let (kind, layout) = match node.node_type {
+            NodeType::Output => ("output", None),
+            NodeType::Workspace => ("workspace", Some(node_layout_name(&node.layout))),
+            NodeType::FloatingCon => ("floating_con", None),
+            _ if node.nodes.is_empty() && node.window.is_some() => ("window", None),
+            _ => ("con", Some(node_layout_name(&node.layout))),
+        };
+
+        let (position, siblings, floating) =
+            match parent.nodes.iter().position(|child| child.id == node.id) {
+                Some(position) => (Some(position), parent.nodes.len(), false),
+                None => (
+                    parent
+                        .floating_nodes
+                        .iter()
+                        .position(|child| child.id == node.id),
+                    parent.floating_nodes.len(),
+                    true,
+                ),
+            };
+
+        let indexed = !matches!(node.node_type, NodeType::Output | NodeType::Workspace);
+
+        Hop {
+            kind,
+            id: node.id,
+            name: node.name.clone(),
+            layout,
+            index: position.map(|position| position + 1).filter(|_| indexed),
+            siblings: Some(siblings).filter(|_| indexed),
+            floating,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::fixtures;
+
+    fn describe(root_node: &RootNode) -> Vec<String> {
+        Breadcrumb::hops(root_node)
+            .unwrap()
+            .iter()
+            .map(Hop::describe)
+            .collect()
+    }
+
+    #[test]
+    fn chain_of_nested_window() {
+        let root_node = fixtures::load("workspaces.json");
+        assert_eq!(
+            describe(&root_node),
+            ["output eDP-1", "workspace 1 [splith]", "window \"~\" (2/3)"]
+        );
+
+        let root_node = fixtures::edit(&root_node, 102, |window| window.focused = false);
+        let root_node = fixtures::edit(&root_node, 222, |window| window.focused = true);
+        assert_eq!(
+            describe(&root_node),
+            [
+                "output eDP-1",
+                "workspace 2 [splith]",
+                "con [splitv] (2/2)",
+                "con [stacked] (2/2)",
+                "window \"notes.pdf\" (2/2)",
+            ]
+        );
+    }
+
+    #[test]
+    fn chain_of_floating_window() {
+        let root_node = fixtures::load("floating.json");
+        let hops = Breadcrumb::hops(&root_node).unwrap();
+
+        assert_eq!(
+            hops.iter().map(Hop::describe).collect::<Vec<_>>(),
+            [
+                "output eDP-1",
+                "workspace 1 [splith]",
+                "floating_con (2/3 floating)",
+                "window \"Color picker\" (1/1)",
+            ]
+        );
+
+        let json = serde_json::to_value(&hops[2]).unwrap();
+        assert_eq!(
+            json,
+            serde_json::json!({
+                "type": "floating_con",
+                "id": 120,
+                "name": null,
+                "layout": null,
+                "index": 2,
+                "siblings": 3,
+                "floating": true,
+            })
+        );
+    }
+
+    #[test]
+    fn chain_requires_focused_node() {
+        let root_node = fixtures::load("workspaces.json");
+        let root_node = fixtures::edit(&root_node, 102, |window| window.focused = false);
+
+        let Err(error) = Breadcrumb::hops(&root_node) else {
+            panic!("A tree without focus has no chain");
+        };
+        assert_eq!(error.to_string(), "Cannot find the focused node");
+    }
+}
@@ -0,0 +1,103 @@
+/*
+    Copyright (C) 2022  Biagio Festa
+
+    This program is free software: you can redistribute it and/or modify
+    it under the terms of the GNU General Public License as published by
+    the Free Software Foundation, either version 3 of the License, or
+    (at your option) any later version.
+
+    This program is distributed in the hope that it will be useful,
+    but WITHOUT ANY WARRANTY; without even the implied warranty of
+    MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+    GNU General Public License for more details.
+
+    You should have received a copy of the GNU General Public License
+    along with this program.  If not, see <https://www.gnu.org/licenses/>.
+*/
+
+use crate::command_executor::CommandExecutor;
+use crate::control::ControlCommand;
+use anyhow::anyhow;
+use anyhow::Result;
+use std::path::Path;
+use std::time::Duration;
+
+/// The executables of other known auto-tiling daemons for i3.
+const KNOWN_AUTOTILERS: &[&str] = &[
+    "autotiling",
+    "i3-auto-layout",
+    "i3-layouts",
+    "alternating_layouts.py",
+    "i3-alternating-layout",
+];
+
+/// How long the startup waits for a running service to answer the claim.
+const CLAIM_TIMEOUT: Duration = Duration::from_millis(500);
+
+/// Make sure the service does not fight with another daemon over the layouts.
+///
+/// A running i3-autolayout service answers the claim (see `ControlCommand::Claim`): in
+/// that case the new service refuses to start. Other auto-tiling daemons are detected
+/// (best-effort) among the processes of the system: they are reported as warnings, and
+/// with `exclusive` the service refuses to start as well.
+pub fn check_cohabitation(command_executor: &mut CommandExecutor, exclusive: bool) -> Result<()> {
+    if let Some(pid) = ControlCommand::Claim.query(command_executor, CLAIM_TIMEOUT)? {
+        return Err(anyhow!(
+            "Another i3-autolayout service (pid {}) is already running",
+            pid
+        ));
+    }
+
+    let autotilers = find_autotilers();
+
+    for (pid, name) in &autotilers {
+        eprintln!(
+            "[WARN]: Another auto-tiling daemon is running ('{}', pid {}): both will split \
+             the windows",
+            name, pid
+        );
+    }
+
+    if exclusive && !autotilers.is_empty() {
+        return Err(anyhow!(
+            "Another auto-tiling daemon is running (see '--exclusive')"
+        ));
+    }
+
+    Ok(())
+}
+
+/// The processes running a known auto-tiling daemon (pid and name).
+///
+/// The daemon is looked for in the executable and, for scripts, in the first argument
+/// (e.g., `python3 /usr/bin/autotiling`). Without `/proc` nothing is found.
+fn find_autotilers() -> Vec<(u32, String)> {
+    let Ok(entries) = std::fs::read_dir("/proc") else {
+        return vec![];
+    };
+
+    let mut autotilers = entries
+        .filter_map(|entry| {
+            let entry = entry.ok()?;
+            let pid = entry.file_name().to_str()?.parse::<u32>().ok()?;
+
+            if pid == std::process::id() {
+                return None;
+            }
+
+            let cmdline = std::fs::read(entry.path().join("cmdline")).ok()?;
+            let name = cmdline
+                .split(|&byte| byte == 0)
+                .take(2)
+                .filter_map(|argument| Path::new(std::str::from_utf8(argument).ok()?).file_name())
+                .filter_map(|name| name.to_str())
+                .find(|name| KNOWN_AUTOTILERS.contains(name))?;
+
+            Some((pid, name.to_string()))
+        })
+        .collect::<Vec<_>>();
+
+    autotilers.sort();
+
+    autotilers
+}
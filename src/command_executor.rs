@@ -15,13 +15,25 @@
    along with this program.  If not, see <https://www.gnu.org/licenses/>.
 */
 
+use crate::errors::Failure;
 use anyhow::anyhow;
 use anyhow::Context;
 use anyhow::Result;
+use i3_ipc::msg::Msg;
+use i3_ipc::reply::Success;
 use i3_ipc::Connect;
 use i3_ipc::I3Stream;
+use i3_ipc::MsgResponse;
 use i3_ipc::I3;
+use std::cell::Cell;
+use std::cell::RefCell;
+use std::collections::BTreeMap;
 use std::fmt::Display;
+use std::io;
+use std::path::PathBuf;
+use std::rc::Rc;
+use std::time::Duration;
+use std::time::Instant;
 
 /// The I3 version data.
 pub type I3Version = i3_ipc::reply::Version;
@@ -29,23 +41,365 @@ pub type I3Version = i3_ipc::reply::Version;
 /// An I3 workspace.
 pub type I3Workspace = i3_ipc::reply::Workspace;
 
+/// An I3 output.
+pub type I3Output = i3_ipc::reply::Output;
+
 /// An I3 node.
 pub type I3Node = i3_ipc::reply::Node;
 
+/// The identity of the i3 instance a connection refers to.
+///
+/// It is taken from the connection itself (see `I3Instance::of_connection`), so that two
+/// connections to different instances never compare equal, whatever the environment says.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct I3Instance {
+    /// The IPC socket path the connection was opened on.
+    pub socket_path: PathBuf,
+
+    /// The inode of the IPC socket: each i3 process creates its own socket.
+    pub socket_inode: Option<u64>,
+
+    /// The version answered on the connection (`human_readable`).
+    pub version: String,
+
+    /// The configuration file answered on the connection.
+    pub config_file: PathBuf,
+}
+
+impl I3Instance {
+    /// The identity of the instance listening on `socket_path`, which answered `version`.
+    pub fn new(socket_path: PathBuf, socket_inode: Option<u64>, version: &I3Version) -> Self {
+        Self {
+            socket_path,
+            socket_inode,
+            version: version.human_readable.clone(),
+            config_file: PathBuf::from(&version.loaded_config_file_name),
+        }
+    }
+
+    /// Locate the socket as i3 does (`I3SOCK` or `i3 --get-socketpath`).
+    fn locate() -> Result<PathBuf> {
+        let socket_path =
+            PathBuf::from(i3_ipc::socket_path().context("Cannot detect the i3 socket path")?);
+
+        Ok(std::fs::canonicalize(&socket_path).unwrap_or(socket_path))
+    }
+
+    /// The instance at the other end of `i3_stream`, opened on `socket_path`.
+    ///
+    /// The instance is asked for its version on the connection (`GET_VERSION`).
+    fn of_connection(socket_path: PathBuf, i3_stream: &mut I3Stream) -> Result<Self> {
+        use std::os::unix::fs::MetadataExt;
+
+        let version = i3_stream
+            .get_version()
+            .context("Cannot query the version of the i3 instance")?;
+        let socket_inode = std::fs::metadata(&socket_path)
+            .ok()
+            .map(|metadata| metadata.ino());
+
+        Ok(Self::new(socket_path, socket_inode, &version))
+    }
+
+    /// Check that `other` refers to the same i3 instance.
+    pub fn ensure_same(&self, other: &I3Instance) -> Result<()> {
+        if self == other {
+            Ok(())
+        } else {
+            Err(anyhow!(
+                "Connections refer to different i3 instances ('{}' and '{}')",
+                self,
+                other
+            ))
+        }
+    }
+}
+
+/// Connect to i3, returning the connection with the identity of the instance it reached.
+///
+/// The socket is located before and after connecting (`I3::connect` locates it on its
+/// own), so that a socket moved in between (e.g., i3 restarted) is an error rather than a
+/// wrong identity.
+pub fn connect() -> Result<(I3Stream, I3Instance)> {
+    let socket_path = I3Instance::locate()?;
+    let mut i3_stream = I3::connect().map_err(diagnose_connection_failure)?;
+
+    if I3Instance::locate()? != socket_path {
+        return Err(anyhow!(
+            "The i3 socket moved while connecting to '{}'",
+            socket_path.display()
+        ));
+    }
+
+    let instance = I3Instance::of_connection(socket_path, &mut i3_stream)?;
+
+    Ok((i3_stream, instance))
+}
+
+impl Display for I3Instance {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self.socket_inode {
+            Some(inode) => write!(f, "{} (inode {})", self.socket_path.display(), inode),
+            None => write!(f, "{}", self.socket_path.display()),
+        }
+    }
+}
+
+/// The window manager at the other end of the IPC socket.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, serde::Serialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Flavor {
+    /// i3.
+    I3,
+
+    /// sway (which speaks the i3 IPC protocol).
+    Sway,
+}
+
+impl Flavor {
+    /// The window manager which answered GET_VERSION with `reply`.
+    ///
+    /// sway sets `variant` (since 1.5) and its `human_readable` starts with `sway version`
+    /// (before); i3 sets neither.
+    fn of_version(reply: &VersionReply) -> Self {
+        let is_sway = match &reply.variant {
+            Some(variant) => variant == "sway",
+            None => reply.human_readable.starts_with("sway version"),
+        };
+
+        if is_sway {
+            Self::Sway
+        } else {
+            Self::I3
+        }
+    }
+}
+
+/// The fields of the GET_VERSION reply telling the window managers apart (`I3Version` has
+/// no `variant`).
+#[derive(serde::Deserialize)]
+struct VersionReply {
+    human_readable: String,
+
+    #[serde(default)]
+    variant: Option<String>,
+}
+
+/// The capabilities of the connected window manager.
+#[derive(Debug, serde::Serialize)]
+pub struct Capabilities {
+    /// The window manager.
+    pub flavor: Flavor,
+
+    /// Whether each probed IPC request is supported (keyed by request name).
+    pub features: BTreeMap<&'static str, bool>,
+}
+
+/// The environment of the session, as relevant to the connection with i3.
+#[derive(Debug)]
+pub struct SessionEnv {
+    /// Whether there is a graphical session (`DISPLAY` or `WAYLAND_DISPLAY`).
+    pub display: bool,
+
+    /// The socket path located as i3 does (`I3SOCK` or `i3 --get-socketpath`).
+    pub socket_path: Option<PathBuf>,
+}
+
+impl SessionEnv {
+    /// The environment of the current process.
+    pub fn current() -> Self {
+        let display = ["DISPLAY", "WAYLAND_DISPLAY"]
+            .iter()
+            .any(|name| std::env::var_os(name).is_some_and(|value| !value.is_empty()));
+
+        Self {
+            display,
+            socket_path: i3_ipc::socket_path().ok().map(PathBuf::from),
+        }
+    }
+}
+
+/// The reason of a failed connection with i3.
+#[derive(Debug, PartialEq, Eq)]
+pub enum ConnectionFailure {
+    /// There is no graphical session.
+    NoDisplay,
+
+    /// The socket cannot be located.
+    NoSocket,
+
+    /// The socket file does not exist.
+    SocketMissing(PathBuf),
+
+    /// The socket exists but nobody listens on it.
+    StaleSocket(PathBuf),
+
+    /// The socket is not accessible.
+    PermissionDenied(PathBuf),
+
+    /// Any other failure.
+    Other(Option<PathBuf>),
+}
+
+impl ConnectionFailure {
+    /// Classify the failure of a connection given the environment it happened in.
+    pub fn classify(env: &SessionEnv, error_kind: std::io::ErrorKind) -> Self {
+        let Some(socket_path) = &env.socket_path else {
+            return if env.display {
+                Self::NoSocket
+            } else {
+                Self::NoDisplay
+            };
+        };
+
+        let socket_path = socket_path.clone();
+
+        match error_kind {
+            std::io::ErrorKind::NotFound => Self::SocketMissing(socket_path),
+            std::io::ErrorKind::ConnectionRefused => Self::StaleSocket(socket_path),
+            std::io::ErrorKind::PermissionDenied => Self::PermissionDenied(socket_path),
+            _ => Self::Other(Some(socket_path)),
+        }
+    }
+
+    /// A one-line suggestion to fix the failure.
+    pub fn hint(&self) -> &'static str {
+        match self {
+            Self::NoDisplay => "run it from within an i3 session (e.g., a binding or a terminal)",
+            Self::NoSocket => {
+                "start i3, or set I3SOCK to its socket path (on sway: I3SOCK=$SWAYSOCK)"
+            }
+            Self::SocketMissing(_) => {
+                "is i3 running? Check the socket path with 'i3 --get-socketpath'"
+            }
+            Self::StaleSocket(_) => {
+                "i3 has exited or restarted: check the socket path with 'i3 --get-socketpath'"
+            }
+            Self::PermissionDenied(_) => "run it as the user owning the i3 session",
+            Self::Other(_) => "check the socket path with 'i3 --get-socketpath'",
+        }
+    }
+}
+
+impl Display for ConnectionFailure {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Self::NoDisplay => write!(
+                f,
+                "Not in a graphical session (neither DISPLAY nor WAYLAND_DISPLAY is set)"
+            ),
+            Self::NoSocket => write!(
+                f,
+                "Cannot locate the i3 socket (I3SOCK is not set and 'i3 --get-socketpath' failed)"
+            ),
+            Self::SocketMissing(path) => {
+                write!(f, "The i3 socket '{}' does not exist", path.display())
+            }
+            Self::StaleSocket(path) => write!(
+                f,
+                "The i3 socket '{}' refuses connections (stale socket)",
+                path.display()
+            ),
+            Self::PermissionDenied(path) => {
+                write!(f, "Permission denied on the i3 socket '{}'", path.display())
+            }
+            Self::Other(Some(path)) => {
+                write!(f, "Cannot connect to the i3 socket '{}'", path.display())
+            }
+            Self::Other(None) => write!(f, "Cannot connect to i3"),
+        }?;
+
+        write!(f, "\nHint: {}", self.hint())
+    }
+}
+
+/// Explain why the connection with i3 failed with `error`.
+///
+/// The returned error tells apart the common causes (no graphical session, unknown or
+/// stale socket, ...) with a hint to fix each of them.
+pub fn diagnose_connection_failure(error: std::io::Error) -> anyhow::Error {
+    diagnose_connection_failure_in(&SessionEnv::current(), error)
+}
+
+/// Explain why the connection with i3 failed with `error`, in the environment `env`.
+fn diagnose_connection_failure_in(env: &SessionEnv, error: std::io::Error) -> anyhow::Error {
+    let failure = ConnectionFailure::classify(env, error.kind());
+
+    anyhow::Error::new(error)
+        .context(failure.to_string())
+        .context(Failure::Connection)
+}
+
 /// A connection with I3 IPC for command execution.
 pub struct CommandExecutor {
-    /// The connection with I3 for IPC.
-    i3_stream: I3Stream,
+    /// The connection with I3 for IPC (possibly shared with other executors).
+    i3_stream: Rc<RefCell<I3Stream>>,
+
+    /// The i3 instance of the connection.
+    instance: I3Instance,
+
+    /// The time spent talking with i3 (shared with the other executors of the connection).
+    timings: Rc<Cell<IpcTimings>>,
+}
+
+/// The time spent talking with i3 (see `CommandExecutor::take_timings`).
+#[derive(Clone, Copy, Debug, Default)]
+pub struct IpcTimings {
+    /// Spent on queries (e.g., the tree).
+    pub query: Duration,
+
+    /// Spent executing commands (ticks included).
+    pub command: Duration,
 }
 
 impl CommandExecutor {
+    const SYNC_POLL_INTERVAL: Duration = Duration::from_millis(10);
+    const SYNC_TIMEOUT: Duration = Duration::from_secs(1);
+
     /// Connect to I3.
     pub fn new() -> Result<Self> {
-        println!("Creating command executor...");
-        let i3_stream = I3::connect().context("Cannot create command executor")?;
-        println!("  Ok");
+        eprintln!("Creating command executor...");
+        let (i3_stream, instance) = connect().context("Cannot create command executor")?;
+        eprintln!("  Ok");
+
+        Ok(Self {
+            i3_stream: Rc::new(RefCell::new(i3_stream)),
+            instance,
+            timings: Rc::default(),
+        })
+    }
 
-        Ok(Self { i3_stream })
+    /// A new executor sharing the same connection.
+    pub fn share(&self) -> Self {
+        Self {
+            i3_stream: Rc::clone(&self.i3_stream),
+            instance: self.instance.clone(),
+            timings: Rc::clone(&self.timings),
+        }
+    }
+
+    /// The time spent talking with i3 since the last call, on this connection.
+    pub fn take_timings(&self) -> IpcTimings {
+        self.timings.take()
+    }
+
+    /// Account the time spent since `started` on a query (or on a command).
+    fn account(&self, started: Instant, query: bool) {
+        let mut timings = self.timings.get();
+        let elapsed = started.elapsed();
+
+        if query {
+            timings.query += elapsed;
+        } else {
+            timings.command += elapsed;
+        }
+
+        self.timings.set(timings);
+    }
+
+    /// The i3 instance of the connection.
+    pub fn instance(&self) -> &I3Instance {
+        &self.instance
     }
 
     /// Execute an I3 command.
@@ -53,10 +407,10 @@ impl CommandExecutor {
     where
         C: AsRef<str>,
     {
-        let response = self
-            .i3_stream
-            .run_command(command)
-            .context("Cannot execute the command")?;
+        let started = Instant::now();
+        let response = self.i3_stream.borrow_mut().run_command(command);
+        self.account(started, false);
+        let response = response.context("Cannot execute the command")?;
 
         for resp in response.into_iter() {
             if !resp.success {
@@ -70,6 +424,52 @@ impl CommandExecutor {
         Ok(())
     }
 
+    /// Execute several I3 commands in a single message.
+    ///
+    /// Unlike `run`, a failing command does not hide the others: it returns the outcome
+    /// of each command. Commands must not be chained (`,` or `;`).
+    pub fn run_batch<C>(&mut self, commands: &[C]) -> Result<Vec<Result<()>>>
+    where
+        C: AsRef<str>,
+    {
+        if commands.is_empty() {
+            return Ok(vec![]);
+        }
+
+        let command = commands
+            .iter()
+            .map(|command| command.as_ref())
+            .collect::<Vec<_>>()
+            .join("; ");
+
+        let started = Instant::now();
+        let response = self.i3_stream.borrow_mut().run_command(command);
+        self.account(started, false);
+        let response = response.context("Cannot execute the commands")?;
+
+        if response.len() != commands.len() {
+            return Err(anyhow!(
+                "Unexpected number of command results ({} instead of {})",
+                response.len(),
+                commands.len()
+            ));
+        }
+
+        Ok(response
+            .into_iter()
+            .map(|resp| {
+                if resp.success {
+                    Ok(())
+                } else {
+                    Err(anyhow!(
+                        "Command execution returned a failure response: '{}'",
+                        resp.error.unwrap_or_else(|| "N/A".to_string())
+                    ))
+                }
+            })
+            .collect())
+    }
+
     /// Execute an I3 command on a particular node.
     pub fn run_on_node_id<C>(&mut self, node_id: usize, command: C) -> Result<()>
     where
@@ -78,36 +478,296 @@ impl CommandExecutor {
         self.run(format!("[con_id={}] {}", node_id, command))
     }
 
+    /// Broadcast a tick event with `payload` to all subscribers.
+    pub fn send_tick<P>(&mut self, payload: P) -> Result<()>
+    where
+        P: AsRef<str>,
+    {
+        let started = Instant::now();
+        let response: io::Result<MsgResponse<Success>> =
+            self.i3_stream.borrow_mut().send_receive(Msg::Tick, payload);
+        self.account(started, false);
+        let response = response.context("Cannot send tick")?;
+
+        if response.body.success {
+            Ok(())
+        } else {
+            Err(anyhow!("Tick sending returned a failure response"))
+        }
+    }
+
     /// Return a list of all workspaces.
     pub fn query_workspaces(&mut self) -> Result<Vec<I3Workspace>> {
-        self.i3_stream
-            .get_workspaces()
-            .context("Cannot query i3 workspaces")
+        let started = Instant::now();
+        let workspaces = self.i3_stream.borrow_mut().get_workspaces();
+        self.account(started, true);
+
+        workspaces.context("Cannot query i3 workspaces")
+    }
+
+    /// Return a list of all outputs.
+    pub fn query_outputs(&mut self) -> Result<Vec<I3Output>> {
+        let started = Instant::now();
+        let outputs = self.i3_stream.borrow_mut().get_outputs();
+        self.account(started, true);
+
+        outputs.context("Cannot query i3 outputs")
     }
 
     /// Return the current snapshot of I3 state as root node.
     pub fn query_root_node(&mut self) -> Result<RootNode> {
-        Ok(RootNode(
-            self.i3_stream
-                .get_tree()
-                .context("Cannot query i3 root-node")?,
-        ))
+        let started = Instant::now();
+        let tree = self.i3_stream.borrow_mut().get_tree();
+        self.account(started, true);
+
+        Ok(RootNode(tree.context("Cannot query i3 root-node")?))
+    }
+
+    /// Poll the tree until `predicate` holds, so that the next commands do not race with
+    /// i3 still applying the previous ones. It returns the converged tree.
+    ///
+    /// It fails once `SYNC_TIMEOUT` expires.
+    pub fn wait_for_tree<P>(&mut self, predicate: P) -> Result<RootNode>
+    where
+        P: Fn(&RootNode) -> bool,
+    {
+        let deadline = Instant::now() + Self::SYNC_TIMEOUT;
+
+        loop {
+            let root_node = self.query_root_node()?;
+
+            if predicate(&root_node) {
+                return Ok(root_node);
+            }
+
+            if Instant::now() >= deadline {
+                return Err(anyhow!(
+                    "Cannot observe the expected tree (timeout after {:?})",
+                    Self::SYNC_TIMEOUT
+                ));
+            }
+
+            std::thread::sleep(Self::SYNC_POLL_INTERVAL);
+        }
     }
 
     /// Return I3 version.
     pub fn query_i3_version(&mut self) -> Result<I3Version> {
         self.i3_stream
+            .borrow_mut()
             .get_version()
             .context("Cannot query i3 version")
     }
+
+    /// Detect the window manager and the IPC requests it supports.
+    ///
+    /// The window manager is told by its GET_VERSION reply (i3 if it cannot be read). Each
+    /// feature is probed issuing a harmless query and recording whether it succeeds.
+    pub fn probe_capabilities(&mut self) -> Capabilities {
+        let reply: io::Result<MsgResponse<VersionReply>> =
+            self.i3_stream.borrow_mut().send_receive(Msg::Version, "");
+        let flavor = reply.map_or(Flavor::I3, |reply| Flavor::of_version(&reply.body));
+
+        let mut features = BTreeMap::new();
+        features.insert("get_marks", self.i3_stream.borrow_mut().get_marks().is_ok());
+        features.insert(
+            "get_bar_config",
+            self.i3_stream.borrow_mut().get_bar_ids().is_ok(),
+        );
+        features.insert(
+            "get_binding_modes",
+            self.i3_stream.borrow_mut().get_binding_modes().is_ok(),
+        );
+        features.insert(
+            "get_config",
+            self.i3_stream.borrow_mut().get_config().is_ok(),
+        );
+        features.insert(
+            "get_binding_state",
+            self.i3_stream.borrow_mut().get_binding_state().is_ok(),
+        );
+
+        Capabilities { flavor, features }
+    }
+}
+
+/// A connection established on first use and shared by the executors it hands out.
+#[derive(Default)]
+pub struct Connection(Option<CommandExecutor>);
+
+impl Connection {
+    /// An executor on the connection (which is established if needed).
+    pub fn executor(&mut self) -> Result<CommandExecutor> {
+        if let Some(command_executor) = &self.0 {
+            return Ok(command_executor.share());
+        }
+
+        let command_executor = CommandExecutor::new()?;
+        let shared = command_executor.share();
+        self.0 = Some(command_executor);
+
+        Ok(shared)
+    }
 }
 
 /// The root node of I3 containers.
 pub struct RootNode(I3Node);
 
 impl RootNode {
+    /// Wrap a tree not queried from i3 (e.g., a fixture).
+    pub fn new(node: I3Node) -> Self {
+        Self(node)
+    }
+
     /// As I3 node.
     pub fn node(&self) -> &I3Node {
         &self.0
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::io::ErrorKind;
+
+    fn env(display: bool, socket_path: Option<&str>) -> SessionEnv {
+        SessionEnv {
+            display,
+            socket_path: socket_path.map(PathBuf::from),
+        }
+    }
+
+    fn version(human_readable: &str) -> I3Version {
+        I3Version {
+            major: 4,
+            minor: 22,
+            patch: 0,
+            human_readable: human_readable.to_string(),
+            loaded_config_file_name: "/home/user/.config/i3/config".to_string(),
+        }
+    }
+
+    #[test]
+    fn flavor_of_version_reply() {
+        let flavor = |reply: &str| Flavor::of_version(&serde_json::from_str(reply).unwrap());
+
+        let i3 = r#"{"major": 4, "minor": 22, "patch": 0,
+            "human_readable": "4.22 (2023-01-02)",
+            "loaded_config_file_name": "/home/user/.config/i3/config"}"#;
+        assert_eq!(flavor(i3), Flavor::I3);
+
+        let sway = r#"{"major": 1, "minor": 8, "patch": 1,
+            "human_readable": "1.8.1", "variant": "sway",
+            "loaded_config_file_name": "/home/user/.config/sway/config"}"#;
+        assert_eq!(flavor(sway), Flavor::Sway);
+
+        // Before 1.5, sway had no variant.
+        let old_sway = r#"{"major": 1, "minor": 4, "patch": 0,
+            "human_readable": "sway version 1.4",
+            "loaded_config_file_name": "/etc/sway/config"}"#;
+        assert_eq!(flavor(old_sway), Flavor::Sway);
+    }
+
+    #[test]
+    fn instances_differ_by_connection() {
+        let socket = PathBuf::from("/run/user/1000/i3/ipc-socket.42");
+        let instance = I3Instance::new(socket.clone(), Some(7), &version("4.22"));
+
+        assert!(instance
+            .ensure_same(&I3Instance::new(socket.clone(), Some(7), &version("4.22")))
+            .is_ok());
+
+        // i3 restarted on the same path: a new socket.
+        let restarted = I3Instance::new(socket.clone(), Some(8), &version("4.22"));
+        let error = instance.ensure_same(&restarted).unwrap_err();
+        assert_eq!(
+            error.to_string(),
+            "Connections refer to different i3 instances \
+             ('/run/user/1000/i3/ipc-socket.42 (inode 7)' and \
+             '/run/user/1000/i3/ipc-socket.42 (inode 8)')"
+        );
+
+        // Another window manager answered on the connection.
+        let other = I3Instance::new(socket, Some(7), &version("4.23"));
+        assert!(instance.ensure_same(&other).is_err());
+
+        let nested = I3Instance::new(
+            PathBuf::from("/tmp/i3-nested/ipc-socket.99"),
+            Some(7),
+            &version("4.22"),
+        );
+        assert!(instance.ensure_same(&nested).is_err());
+    }
+
+    #[test]
+    fn classify_without_socket() {
+        assert_eq!(
+            ConnectionFailure::classify(&env(false, None), ErrorKind::NotFound),
+            ConnectionFailure::NoDisplay
+        );
+        assert_eq!(
+            ConnectionFailure::classify(&env(true, None), ErrorKind::NotFound),
+            ConnectionFailure::NoSocket
+        );
+    }
+
+    #[test]
+    fn classify_socket_errors() {
+        let socket = PathBuf::from("/run/user/1000/i3/ipc-socket.42");
+
+        // With a socket, the display does not matter (e.g., over ssh with I3SOCK set).
+        for display in [false, true] {
+            let env = env(display, socket.to_str());
+
+            for (error_kind, expected) in [
+                (
+                    ErrorKind::NotFound,
+                    ConnectionFailure::SocketMissing(socket.clone()),
+                ),
+                (
+                    ErrorKind::ConnectionRefused,
+                    ConnectionFailure::StaleSocket(socket.clone()),
+                ),
+                (
+                    ErrorKind::PermissionDenied,
+                    ConnectionFailure::PermissionDenied(socket.clone()),
+                ),
+                (
+                    ErrorKind::TimedOut,
+                    ConnectionFailure::Other(Some(socket.clone())),
+                ),
+            ] {
+                assert_eq!(ConnectionFailure::classify(&env, error_kind), expected);
+            }
+        }
+    }
+
+    #[test]
+    fn failure_message_ends_with_hint() {
+        let failure = ConnectionFailure::StaleSocket(PathBuf::from("/tmp/i3-ipc.sock"));
+
+        assert_eq!(
+            failure.to_string(),
+            "The i3 socket '/tmp/i3-ipc.sock' refuses connections (stale socket)\n\
+             Hint: i3 has exited or restarted: check the socket path with 'i3 --get-socketpath'"
+        );
+    }
+
+    #[test]
+    fn diagnose_keeps_cause_and_exit_code() {
+        let error = diagnose_connection_failure_in(
+            &env(true, Some("/tmp/i3-ipc.sock")),
+            std::io::Error::from(ErrorKind::ConnectionRefused),
+        );
+
+        assert_eq!(Failure::of(&error), Some(Failure::Connection));
+        assert!(format!("{:#}", error).contains("refuses connections (stale socket)"));
+        assert_eq!(
+            error
+                .root_cause()
+                .downcast_ref::<std::io::Error>()
+                .map(std::io::Error::kind),
+            Some(ErrorKind::ConnectionRefused)
+        );
+    }
+}
@@ -0,0 +1,47 @@
+/*
+    Copyright (C) 2022  Biagio Festa
+
+    This program is free software: you can redistribute it and/or modify
+    it under the terms of the GNU General Public License as published by
+    the Free Software Foundation, either version 3 of the License, or
+    (at your option) any later version.
+
+    This program is distributed in the hope that it will be useful,
+    but WITHOUT ANY WARRANTY; without even the implied warranty of
+    MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+    GNU General Public License for more details.
+
+    You should have received a copy of the GNU General Public License
+    along with this program.  If not, see <https://www.gnu.org/licenses/>.
+*/
+
+pub mod autolayout;
+pub mod inspect;
+pub mod layouts;
+pub mod scripting;
+pub mod setup;
+pub mod tabmode;
+pub mod windows;
+pub mod workspaces;
+
+use crate::command_executor::Connection;
+use crate::utilities::query_workspace_visible_on;
+use crate::workspace::WorkspaceRef;
+use anyhow::anyhow;
+use anyhow::Result;
+
+/// The reference to the workspace visible on an output.
+pub fn visible_workspace(output: &str, connection: &mut Connection) -> Result<WorkspaceRef> {
+    let workspace = query_workspace_visible_on(output, &mut connection.executor()?)?;
+
+    Ok(WorkspaceRef::Name(workspace.name))
+}
+
+/// Check the tolerance of the verification of shares.
+pub fn validate_tolerance(tolerance: f64) -> Result<f64> {
+    if !(0.0..100.0).contains(&tolerance) {
+        return Err(anyhow!("The tolerance must be in the range [0, 100)"));
+    }
+
+    Ok(tolerance)
+}
@@ -0,0 +1,655 @@
+/*
+    Copyright (C) 2022  Biagio Festa
+
+    This program is free software: you can redistribute it and/or modify
+    it under the terms of the GNU General Public License as published by
+    the Free Software Foundation, either version 3 of the License, or
+    (at your option) any later version.
+
+    This program is distributed in the hope that it will be useful,
+    but WITHOUT ANY WARRANTY; without even the implied warranty of
+    MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+    GNU General Public License for more details.
+
+    You should have received a copy of the GNU General Public License
+    along with this program.  If not, see <https://www.gnu.org/licenses/>.
+*/
+
+use crate::autolayout::outputs::OutputChangeAction;
+use crate::autolayout::placement::EscapeDirection;
+use crate::autolayout::placement::PlacementRule;
+use crate::autolayout::restores::AutoRestoreRule;
+use crate::autolayout::AutoLayout;
+use crate::autolayout::AutoLayoutOptions;
+use crate::claim::check_cohabitation;
+use crate::command_executor::Connection;
+use crate::config::AutolayoutSection;
+use crate::event_listener::EventListener;
+use crate::event_listener::EventSubscribe;
+use crate::gaps::DynamicGaps;
+use crate::history::LayoutHistory;
+use crate::history::Retention;
+use crate::notify::NotifyLevel;
+use crate::pin::Pin;
+use crate::split_memory::SplitMemory;
+use crate::split_policy::LayoutMode;
+use crate::split_policy::Profile;
+use crate::split_policy::SplitPolicy;
+use crate::split_policy::SplitRule;
+use crate::utilities::find_focused_node;
+use crate::utilities::OrientationPolicy;
+use crate::utilities::RectSource;
+use crate::utilities::Split;
+use anyhow::anyhow;
+use anyhow::Context;
+use anyhow::Result;
+use std::path::PathBuf;
+use std::time::Duration;
+
+/// Information about the autolayout command.
+#[derive(clap::Args)]
+pub struct AutolayoutCmd {
+    /// The reaction when the set of active outputs changes (e.g., monitor hotplug).
+    #[clap(long, value_enum)]
+    on_output_change: Option<OutputChangeAction>,
+
+    /// The workspace group whose workspace-to-output mapping is restored on output change.
+    #[clap(long)]
+    output_group: Option<String>,
+
+    /// Broadcast each action as an i3 tick event with a JSON payload
+    /// (e.g., '{"source":"i3-autolayout","action":"split","dir":"vertical",...}').
+    #[clap(long, action)]
+    announce: bool,
+
+    /// Leave a window alone after this many consecutive failures to split it [default: 3].
+    #[clap(long)]
+    failure_threshold: Option<u32>,
+
+    /// How long (seconds) a window is left alone after too many failures [default: 60].
+    #[clap(long)]
+    failure_cooldown: Option<u64>,
+
+    /// Ignore the focus of a window whose split has been decided less than this many
+    /// milliseconds ago, if the window has not moved since (e.g., hovering with
+    /// 'focus_follows_mouse'). 0 evaluates every focus [default: 500].
+    #[clap(long)]
+    min_reapply_ms: Option<u64>,
+
+    /// Handle a new window only if it still exists (tiled) after this many milliseconds
+    /// (e.g., 150), so that short-lived windows such as pickers and popups are not split.
+    /// 0 handles new windows immediately [default: 0].
+    #[clap(long)]
+    settle_new_ms: Option<u64>,
+
+    /// Poll the i3 tree every this many milliseconds instead of subscribing to events
+    /// (degraded mode: only new, close and focus of windows are handled). Polling is also
+    /// used, every 500 ms, when the event subscription fails.
+    #[clap(long)]
+    poll_interval_ms: Option<u64>,
+
+    /// Place new windows as 'CRITERIA:workspace=WORKSPACE[:container=mark:MARK[:layout=LAYOUT]]'
+    /// (e.g., 'class=Spotify:workspace=music:container=mark:media:layout=tabbed'). The marked
+    /// container is created with LAYOUT if missing. The first matching rule applies. Repeatable.
+    #[clap(long = "place")]
+    placements: Vec<PlacementRule>,
+
+    /// Move a new window opened inside a stacked container out of it, in this direction
+    /// (then split it as usual). Only when the stack sits in a container laid out that way;
+    /// workspaces in tabmode are left alone.
+    #[clap(long, value_enum)]
+    escape_stacked: Option<EscapeDirection>,
+
+    /// Snapshot the workspace layouts at most every this many minutes (and the last layout
+    /// of a workspace once its last window is closed or moved away), skipping unchanged
+    /// layouts. See the 'history' command.
+    #[clap(long)]
+    snapshot_interval: Option<u64>,
+
+    /// The snapshots kept for each workspace [default: 20].
+    #[clap(long)]
+    history_count: Option<usize>,
+
+    /// Drop the snapshots older than this many hours.
+    #[clap(long)]
+    history_max_age: Option<u64>,
+
+    /// Set the outer gap (i3-gaps or i3 4.22+) of the focused workspace according to its
+    /// number of windows, as 'COUNT:GAP,...,default:GAP' (e.g., '1:40,2:20,default:8').
+    /// Workspaces in tabmode count as one window.
+    #[clap(long)]
+    dynamic_gaps: Option<DynamicGaps>,
+
+    /// Send desktop notifications (through 'notify-send'): 'errors' for connection loss
+    /// and recovery, windows left alone and failed restores; 'all' also for tabmode
+    /// toggles and auto-restores. Rate-limited [default: none].
+    #[clap(long, value_enum)]
+    notify: Option<NotifyLevel>,
+
+    /// Refuse to start when another auto-tiling daemon (e.g., autotiling) is detected,
+    /// instead of only warning. A second i3-autolayout service always refuses to start.
+    #[clap(long, action)]
+    exclusive: bool,
+
+    /// Restore a saved layout on a workspace when it is created, as 'WORKSPACE=PATH'
+    /// (e.g., 'mail=~/.config/i3-autolayout/mail.layout'; '.json' files are read as JSON).
+    /// The restore waits for the windows of the layout to appear (at most 30 seconds).
+    /// Repeatable.
+    #[clap(long = "auto-restore")]
+    auto_restores: Vec<AutoRestoreRule>,
+
+    /// Record the received events in FILE (one JSON per line), to be replayed later with
+    /// '--replay'.
+    #[clap(long, value_name = "FILE")]
+    record_events: Option<PathBuf>,
+
+    /// Handle the events recorded in FILE (see '--record-events') instead of the live ones,
+    /// as fast as possible, then exit. The commands are executed on the running i3.
+    #[clap(long, value_name = "FILE", conflicts_with_all = &["poll-interval-ms", "record-events"])]
+    replay: Option<PathBuf>,
+
+    /// Print the time spent on events (see 'ctl stats') when the replay is over.
+    #[clap(long, action, requires = "replay")]
+    stats: bool,
+
+    #[clap(flatten)]
+    split_policy: SplitPolicyArgs,
+}
+
+/// How the split of a window is decided (shared by autolayout and next-split).
+#[derive(clap::Args)]
+pub struct SplitPolicyArgs {
+    /// A split rule as 'CRITERIA:vertical|horizontal[:PPT]' (e.g., 'class=Alacritty:vertical:30').
+    /// With PPT, the window opened after the split takes that percentage. Repeatable.
+    #[clap(long = "rule")]
+    rules: Vec<SplitRule>,
+
+    /// A window is split vertically if its height is greater than its width times this
+    /// threshold [default: 1.0].
+    #[clap(long)]
+    orientation_threshold: Option<f64>,
+
+    /// The rectangle considered for the orientation of a window [default: window].
+    #[clap(long, value_enum)]
+    rect_source: Option<RectSource>,
+
+    /// A window whose aspect ratio (height / width) is within SQUARE_BAND from 1.0 is split
+    /// toggling the direction of its parent ('split toggle') [default: 0.0, disabled].
+    #[clap(long)]
+    square_band: Option<f64>,
+
+    /// The mode of the workspaces not matching any profile [default: auto].
+    #[clap(long, value_enum)]
+    mode: Option<LayoutMode>,
+
+    /// The mode of the workspaces matching a name pattern as 'PATTERN:MODE' (e.g., 'music:off').
+    /// Patterns support '*' and '?'. The first matching profile applies. Repeatable.
+    #[clap(long = "profile")]
+    profiles: Vec<Profile>,
+
+    /// Manage only the windows inside a container marked with OPT_IN_MARK
+    /// (or 'OPT_IN_MARK:<suffix>', as set by the 'pin' subcommand).
+    #[clap(long)]
+    opt_in_mark: Option<String>,
+
+    /// Remember the split decided for each application (by class, parent layout and
+    /// workspace orientation) and reuse it, also across restarts. 'ctl forget' clears it.
+    #[clap(long, action)]
+    remember: bool,
+
+    /// On ultrawide workspaces, split horizontally the windows of the top-level container
+    /// until it has ULTRAWIDE_COLUMNS columns; then the usual policy applies [default: off].
+    #[clap(long)]
+    ultrawide_columns: Option<usize>,
+
+    /// The minimum width (pixels) of a workspace considered ultrawide [default: 3000].
+    #[clap(long)]
+    ultrawide_min_width: Option<isize>,
+
+    /// The number of columns of the workspaces in 'columns' mode: new windows become
+    /// columns until there are COLUMNS, then stack in the focused column [default: 2].
+    #[clap(long)]
+    columns: Option<usize>,
+
+    /// The target aspect ratio (width / height) of the windows in 'cells' mode: a window is
+    /// split in the direction whose resulting cells are closest to it, given the windows
+    /// already sharing its container [default: 1.2].
+    #[clap(long)]
+    cell_aspect: Option<f64>,
+}
+
+/// Information about the next-split command.
+#[derive(clap::Args)]
+pub struct NextSplitCmd {
+    /// Print the decision as JSON.
+    #[clap(long)]
+    json: bool,
+
+    #[clap(flatten)]
+    split_policy: SplitPolicyArgs,
+}
+
+/// Information about the pin command.
+#[derive(clap::Args)]
+pub struct PinCmd {
+    /// The opt-in mark [default: the 'opt-in-mark' of the configuration].
+    #[clap(long)]
+    mark: Option<String>,
+}
+
+/// Execute autolayout service.
+pub fn command_autolayout(
+    autolayout_cmd: AutolayoutCmd,
+    section: AutolayoutSection,
+    connection: &mut Connection,
+) -> Result<()> {
+    let split_policy = split_policy(autolayout_cmd.split_policy, &section)?;
+    let split_memory = if split_policy.remember {
+        Some(SplitMemory::load()?)
+    } else {
+        None
+    };
+
+    let options = AutoLayoutOptions {
+        on_output_change: autolayout_cmd.on_output_change.or(section.on_output_change),
+        output_group: autolayout_cmd.output_group.or(section.output_group),
+        split_policy,
+        placements: autolayout_cmd.placements,
+        announce: autolayout_cmd.announce || section.announce,
+        failure_threshold: autolayout_cmd
+            .failure_threshold
+            .or(section.failure_threshold)
+            .unwrap_or(AutoLayoutOptions::DEFAULT_FAILURE_THRESHOLD),
+        failure_cooldown: autolayout_cmd
+            .failure_cooldown
+            .or(section.failure_cooldown)
+            .map(Duration::from_secs)
+            .unwrap_or(AutoLayoutOptions::DEFAULT_FAILURE_COOLDOWN),
+        min_reapply: autolayout_cmd
+            .min_reapply_ms
+            .or(section.min_reapply_ms)
+            .map(Duration::from_millis)
+            .unwrap_or(AutoLayoutOptions::DEFAULT_MIN_REAPPLY),
+        settle_new: autolayout_cmd
+            .settle_new_ms
+            .or(section.settle_new_ms)
+            .filter(|ms| *ms > 0)
+            .map(Duration::from_millis),
+        escape_stacked: autolayout_cmd.escape_stacked.or(section.escape_stacked),
+        snapshot_interval: autolayout_cmd
+            .snapshot_interval
+            .or(section.snapshot_interval)
+            .map(|minutes| Duration::from_secs(minutes * 60)),
+        dynamic_gaps: autolayout_cmd.dynamic_gaps.or(section.dynamic_gaps),
+        notify: autolayout_cmd
+            .notify
+            .or(section.notify)
+            .unwrap_or(NotifyLevel::None),
+        auto_restores: if autolayout_cmd.auto_restores.is_empty() {
+            section.auto_restores
+        } else {
+            autolayout_cmd.auto_restores
+        },
+        print_stats: autolayout_cmd.stats,
+    };
+
+    if options.failure_threshold == 0 {
+        return Err(anyhow!("The failure threshold must be positive"));
+    }
+
+    let retention = Retention {
+        count: autolayout_cmd
+            .history_count
+            .or(section.history_count)
+            .unwrap_or(Retention::DEFAULT_COUNT),
+        max_age: autolayout_cmd
+            .history_max_age
+            .or(section.history_max_age)
+            .map(|hours| Duration::from_secs(hours * 3600)),
+    };
+
+    if retention.count == 0 {
+        return Err(anyhow!("The history count must be positive"));
+    }
+
+    let history = if options.snapshot_interval.is_some() {
+        Some(LayoutHistory::open(retention)?)
+    } else {
+        None
+    };
+
+    if options.on_output_change.is_some() && options.output_group.is_none() {
+        return Err(anyhow!(
+            "An output group is required to react to output changes"
+        ));
+    }
+
+    let mut command_executor = connection.executor()?;
+
+    check_cohabitation(
+        &mut command_executor,
+        autolayout_cmd.exclusive || section.exclusive,
+    )?;
+
+    let event_subscribe = [
+        EventSubscribe::Window,
+        EventSubscribe::Output,
+        EventSubscribe::Tick,
+        EventSubscribe::Workspace,
+    ];
+
+    let poll_interval_ms = autolayout_cmd.poll_interval_ms.or(section.poll_interval_ms);
+
+    let mut event_listener = match (&autolayout_cmd.replay, poll_interval_ms) {
+        (Some(path), _) => EventListener::replay(path, command_executor.instance().clone())?,
+        (None, Some(0)) => return Err(anyhow!("The poll interval must be positive")),
+        (None, Some(interval)) => EventListener::polling(Duration::from_millis(interval))?,
+        (None, None) => EventListener::new(&event_subscribe).or_else(|error| {
+            eprintln!(
+                "[WARN]: Cannot subscribe to i3 events ({:#}); falling back to polling",
+                error
+            );
+            EventListener::polling(EventListener::DEFAULT_POLL_INTERVAL)
+        })?,
+    };
+
+    if let Some(path) = &autolayout_cmd.record_events {
+        event_listener = event_listener.with_recording(path)?;
+    }
+
+    event_listener
+        .instance()
+        .ensure_same(command_executor.instance())
+        .context("Cannot start the autolayout service")?;
+
+    let mut autolayout = AutoLayout::new(event_listener, command_executor, options);
+
+    if let Some(split_memory) = split_memory {
+        autolayout = autolayout.with_split_memory(split_memory);
+    }
+
+    if let Some(history) = history {
+        autolayout = autolayout.with_history(history);
+    }
+
+    autolayout.serve()
+}
+
+/// Build the split policy from the command line and the configuration.
+pub fn split_policy(args: SplitPolicyArgs, section: &AutolayoutSection) -> Result<SplitPolicy> {
+    let default_policy = OrientationPolicy::default();
+
+    let split_policy = SplitPolicy {
+        rules: args.rules,
+        orientation_policy: OrientationPolicy {
+            threshold: args
+                .orientation_threshold
+                .or(section.orientation_threshold)
+                .unwrap_or(default_policy.threshold),
+            rect_source: args
+                .rect_source
+                .or(section.rect_source)
+                .unwrap_or(default_policy.rect_source),
+            square_band: args
+                .square_band
+                .or(section.square_band)
+                .unwrap_or(default_policy.square_band),
+        },
+        mode: args.mode.or(section.mode).unwrap_or_default(),
+        profiles: if args.profiles.is_empty() {
+            section.profiles.clone()
+        } else {
+            args.profiles
+        },
+        opt_in_mark: args.opt_in_mark.or_else(|| section.opt_in_mark.clone()),
+        remember: args.remember || section.remember,
+        ultrawide_columns: args.ultrawide_columns.or(section.ultrawide_columns),
+        ultrawide_min_width: args
+            .ultrawide_min_width
+            .or(section.ultrawide_min_width)
+            .unwrap_or(SplitPolicy::DEFAULT_ULTRAWIDE_MIN_WIDTH),
+        columns: args.columns.or(section.columns),
+        cell_aspect: args.cell_aspect.or(section.cell_aspect),
+    };
+
+    for (index, profile) in split_policy.profiles.iter().enumerate() {
+        if split_policy.profiles[..index]
+            .iter()
+            .any(|previous| previous.pattern() == profile.pattern())
+        {
+            return Err(anyhow!(
+                "The profile pattern '{}' is given more than once",
+                profile.pattern()
+            ));
+        }
+    }
+
+    if split_policy.orientation_policy.threshold <= 0.0 {
+        return Err(anyhow!("The orientation threshold must be positive"));
+    }
+
+    if !(0.0..1.0).contains(&split_policy.orientation_policy.square_band) {
+        return Err(anyhow!("The square band must be in the range [0, 1)"));
+    }
+
+    if split_policy.ultrawide_columns == Some(0) {
+        return Err(anyhow!("The ultrawide columns must be positive"));
+    }
+
+    if split_policy.columns == Some(0) {
+        return Err(anyhow!("The columns must be positive"));
+    }
+
+    if split_policy.cell_aspect.is_some_and(|aspect| aspect <= 0.0) {
+        return Err(anyhow!("The cell aspect must be positive"));
+    }
+
+    if split_policy.ultrawide_min_width <= 0 {
+        return Err(anyhow!("The ultrawide minimum width must be positive"));
+    }
+
+    Ok(split_policy)
+}
+
+/// Print the split the autolayout service would apply to the focused window.
+pub fn command_next_split(
+    next_split_cmd: NextSplitCmd,
+    section: AutolayoutSection,
+    connection: &mut Connection,
+) -> Result<()> {
+    let split_policy = split_policy(next_split_cmd.split_policy, &section)?;
+
+    let mut command_executor = connection.executor()?;
+    let root_node = command_executor.query_root_node()?;
+    let focused =
+        find_focused_node(&root_node).ok_or_else(|| anyhow!("Cannot find the focused window"))?;
+
+    let split_memory = if split_policy.remember {
+        Some(SplitMemory::load()?)
+    } else {
+        None
+    };
+
+    let decision = split_policy.decide(focused, &root_node, split_memory.as_ref())?;
+
+    let split = match decision.split {
+        Some(Split::Horizontal) => "horizontal",
+        Some(Split::Vertical) => "vertical",
+        Some(Split::Toggle) => "toggle",
+        None => "none",
+    };
+
+    if next_split_cmd.json {
+        let output = serde_json::json!({
+            "split": split,
+            "workspace": decision.workspace,
+            "mode": decision.mode,
+            "profile": decision.profile.map(|profile| profile.pattern()),
+            "remembered": decision.remembered,
+        });
+
+        println!(
+            "{}",
+            serde_json::to_string(&output).context("Cannot serialize the decision")?
+        );
+    } else {
+        println!("{}", split);
+    }
+
+    Ok(())
+}
+
+/// Toggle the opt-in mark of the focused container.
+pub fn command_pin(
+    pin_cmd: PinCmd,
+    section: AutolayoutSection,
+    connection: &mut Connection,
+) -> Result<()> {
+    let opt_in_mark = pin_cmd
+        .mark
+        .or(section.opt_in_mark)
+        .ok_or_else(|| anyhow!("No opt-in mark given (use --mark or the configuration)"))?;
+
+    let command_executor = connection.executor()?;
+    let pin = Pin::new(command_executor);
+
+    pin.execute(&opt_in_mark)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::config::Config;
+    use clap::Parser;
+
+    #[derive(Parser)]
+    struct Args {
+        #[clap(flatten)]
+        split_policy: SplitPolicyArgs,
+    }
+
+    #[derive(Parser)]
+    struct ServiceArgs {
+        #[clap(flatten)]
+        autolayout: AutolayoutCmd,
+    }
+
+    fn service(args: &[&str]) -> clap::Result<AutolayoutCmd> {
+        ServiceArgs::try_parse_from(std::iter::once("autolayout").chain(args.iter().copied()))
+            .map(|args| args.autolayout)
+    }
+
+    #[test]
+    fn replay_arguments() {
+        let cmd = service(&["--replay", "events.jsonl", "--stats"]).unwrap();
+        assert_eq!(cmd.replay, Some(PathBuf::from("events.jsonl")));
+        assert!(cmd.stats);
+
+        let cmd = service(&["--record-events", "events.jsonl"]).unwrap();
+        assert_eq!(cmd.record_events, Some(PathBuf::from("events.jsonl")));
+        assert!(cmd.replay.is_none());
+
+        assert!(service(&["--stats"]).is_err());
+        assert!(service(&["--replay", "a.jsonl", "--poll-interval-ms", "100"]).is_err());
+        assert!(service(&["--replay", "a.jsonl", "--record-events", "b.jsonl"]).is_err());
+    }
+
+    fn policy(args: &[&str], config: &str) -> Result<SplitPolicy> {
+        let args = Args::try_parse_from(std::iter::once("next-split").chain(args.iter().copied()))
+            .unwrap();
+        let config = Config::parse(config).unwrap();
+
+        split_policy(args.split_policy, &config.autolayout)
+    }
+
+    const CONFIG: &str = r#"
+        [autolayout]
+        orientation-threshold = 1.5
+        rect-source = "container"
+        mode = "columns"
+        profiles = ["music:off"]
+        columns = 3
+        remember = true
+    "#;
+
+    #[test]
+    fn defaults_without_flags_and_config() {
+        let split_policy = policy(&[], "").unwrap();
+        let default_policy = OrientationPolicy::default();
+
+        assert_eq!(
+            split_policy.orientation_policy.threshold,
+            default_policy.threshold
+        );
+        assert_eq!(split_policy.mode, LayoutMode::default());
+        assert!(split_policy.profiles.is_empty());
+        assert!(!split_policy.remember);
+        assert_eq!(
+            split_policy.ultrawide_min_width,
+            SplitPolicy::DEFAULT_ULTRAWIDE_MIN_WIDTH
+        );
+    }
+
+    #[test]
+    fn config_fills_missing_flags() {
+        let split_policy = policy(&[], CONFIG).unwrap();
+
+        assert_eq!(split_policy.orientation_policy.threshold, 1.5);
+        assert!(matches!(
+            split_policy.orientation_policy.rect_source,
+            RectSource::Container
+        ));
+        assert_eq!(split_policy.mode, LayoutMode::Columns);
+        assert_eq!(split_policy.profiles[0].pattern(), "music");
+        assert_eq!(split_policy.columns, Some(3));
+        assert!(split_policy.remember);
+    }
+
+    #[test]
+    fn flags_take_precedence() {
+        let split_policy = policy(
+            &[
+                "--orientation-threshold",
+                "2",
+                "--mode",
+                "cells",
+                "--profile",
+                "dev*:auto",
+                "--profile",
+                "chat:off",
+            ],
+            CONFIG,
+        )
+        .unwrap();
+
+        assert_eq!(split_policy.orientation_policy.threshold, 2.0);
+        assert_eq!(split_policy.mode, LayoutMode::Cells);
+
+        // Profiles on the command line replace those of the configuration.
+        let patterns = split_policy
+            .profiles
+            .iter()
+            .map(Profile::pattern)
+            .collect::<Vec<_>>();
+        assert_eq!(patterns, ["dev*", "chat"]);
+
+        // Options not given on the command line still come from the configuration.
+        assert!(matches!(
+            split_policy.orientation_policy.rect_source,
+            RectSource::Container
+        ));
+        assert_eq!(split_policy.columns, Some(3));
+    }
+
+    #[test]
+    fn invalid_values_are_rejected() {
+        for (args, config) in [
+            (&["--orientation-threshold", "0"][..], ""),
+            (&[], "[autolayout]\nsquare-band = 1.0\n"),
+            (&["--columns", "0"], ""),
+            (&["--profile", "music:off", "--profile", "music:auto"], ""),
+        ] {
+            assert!(policy(args, config).is_err(), "{:?} {:?}", args, config);
+        }
+    }
+}
@@ -0,0 +1,285 @@
+/*
+    Copyright (C) 2022  Biagio Festa
+
+    This program is free software: you can redistribute it and/or modify
+    it under the terms of the GNU General Public License as published by
+    the Free Software Foundation, either version 3 of the License, or
+    (at your option) any later version.
+
+    This program is distributed in the hope that it will be useful,
+    but WITHOUT ANY WARRANTY; without even the implied warranty of
+    MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+    GNU General Public License for more details.
+
+    You should have received a copy of the GNU General Public License
+    along with this program.  If not, see <https://www.gnu.org/licenses/>.
+*/
+
+use crate::breadcrumb::Breadcrumb;
+use crate::command_executor::Connection;
+use crate::commands::visible_workspace;
+use crate::config::TabModeSection;
+use crate::control::ControlCommand;
+use crate::doctor::Doctor;
+use crate::print_tree::print_tree;
+use crate::print_tree::tree_shape;
+use crate::state_store::StateStore;
+use crate::workspace::WorkspaceRef;
+use anyhow::Context;
+use anyhow::Result;
+
+/// Information about the i3version command.
+#[derive(clap::Args)]
+pub struct I3VersionCmd {
+    /// Print the version, the socket and the detected capabilities as JSON.
+    #[clap(long)]
+    json: bool,
+}
+
+/// Information about the print-tree command.
+#[derive(clap::Args)]
+pub struct PrintTreeCmd {
+    /// The workspace (number or name) to print of. If not specified prints all workspaces.
+    workspace: Option<WorkspaceRef>,
+
+    /// Print the workspace visible on this output (e.g., 'DP-2') instead.
+    #[clap(long, conflicts_with = "workspace")]
+    output: Option<String>,
+
+    /// Print the compact shape of the tree (e.g., 'splith(win, splitv(win, win))').
+    #[clap(short, long, action)]
+    shape: bool,
+
+    /// Annotate each child with its position and its rank in the focus order of its
+    /// parent (0 is the most recently focused).
+    #[clap(long, action, conflicts_with = "shape")]
+    show_order: bool,
+}
+
+/// Information about the where command.
+#[derive(clap::Args)]
+pub struct WhereCmd {
+    /// Print the chain as JSON.
+    #[clap(long, action)]
+    json: bool,
+
+    /// Print the chain again on every focus change.
+    #[clap(long, action)]
+    watch: bool,
+}
+
+/// A command of ctl: sent to the running service, or on its state directory.
+#[derive(clap::Subcommand)]
+pub enum CtlCmd {
+    /// Inspect and clean the state directory of the service (also when not running).
+    #[clap(name = "state")]
+    State(StateCmd),
+
+    #[clap(flatten)]
+    Service(ControlCommand),
+}
+
+/// Information about the state command.
+#[derive(clap::Args)]
+pub struct StateCmd {
+    /// The state action.
+    #[clap(subcommand)]
+    action: StateAction,
+}
+
+/// Action of the state command.
+#[derive(Debug, PartialEq, clap::Subcommand)]
+pub enum StateAction {
+    /// List the files of the state directory, with the schema version of state files
+    /// and the files quarantined because unreadable ('corrupt/').
+    Ls,
+
+    /// Remove the quarantined files and the leftovers of interrupted writes.
+    Prune,
+}
+
+/// Information about the doctor command.
+#[derive(clap::Args)]
+pub struct DoctorCmd {
+    /// Repair what is found (unmark, rename workspaces, remove state files).
+    #[clap(long, action)]
+    fix: bool,
+
+    /// The tab mode indicator suffix of workspace names (see 'tabmode --indicator-suffix').
+    #[clap(long)]
+    indicator_suffix: Option<String>,
+}
+
+/// Display i3 information.
+pub fn command_i3_version(i3_version_cmd: I3VersionCmd, connection: &mut Connection) -> Result<()> {
+    let mut command_executor = connection.executor()?;
+    let i3_version = command_executor.query_i3_version()?;
+
+    if i3_version_cmd.json {
+        let capabilities = command_executor.probe_capabilities();
+        let instance = command_executor.instance();
+
+        let output = serde_json::json!({
+            "major": i3_version.major,
+            "minor": i3_version.minor,
+            "patch": i3_version.patch,
+            "human_readable": i3_version.human_readable,
+            "loaded_config_file_name": i3_version.loaded_config_file_name,
+            "socket": instance.socket_path,
+            "socket_inode": instance.socket_inode,
+            "flavor": capabilities.flavor,
+            "features": capabilities.features,
+        });
+
+        println!(
+            "{}",
+            serde_json::to_string_pretty(&output).context("Cannot serialize the version")?
+        );
+
+        return Ok(());
+    }
+
+    println!(
+        "I3 version: '{}'\n\
+         Config File: '{}'\n\
+         Socket: '{}'",
+        i3_version.human_readable,
+        i3_version.loaded_config_file_name,
+        command_executor.instance()
+    );
+
+    Ok(())
+}
+
+/// Print the snapshot of I3 layout in the tree fashion.
+pub fn command_print_tree(print_tree_cmd: PrintTreeCmd, connection: &mut Connection) -> Result<()> {
+    let mut command_executor = connection.executor()?;
+    let root_node = command_executor.query_root_node()?;
+
+    let workspace = match print_tree_cmd.output {
+        Some(output) => Some(visible_workspace(&output, connection)?),
+        None => print_tree_cmd.workspace,
+    };
+
+    let node = match workspace {
+        Some(workspace) => workspace.resolve(&root_node, &mut command_executor)?,
+
+        None => root_node.node(),
+    };
+
+    if print_tree_cmd.shape {
+        println!("{}", tree_shape(node));
+        Ok(())
+    } else {
+        print_tree(node, print_tree_cmd.show_order)
+    }
+}
+
+/// Print the chain of nodes down to the focused one.
+pub fn command_where(where_cmd: WhereCmd, connection: &mut Connection) -> Result<()> {
+    let command_executor = connection.executor()?;
+    let breadcrumb = Breadcrumb::new(command_executor).with_json(where_cmd.json);
+
+    breadcrumb.execute(where_cmd.watch)
+}
+
+/// Inspect and clean the state directory.
+fn command_state(state_cmd: StateCmd) -> Result<()> {
+    let state_store = StateStore::open()?;
+
+    match state_cmd.action {
+        StateAction::Ls => {
+            for entry in state_store.entries()? {
+                println!("{}", entry.describe());
+            }
+        }
+
+        StateAction::Prune => {
+            let removed = state_store.prune()?;
+
+            for path in &removed {
+                println!("Removed '{}'", path.display());
+            }
+
+            println!("{} files removed", removed.len());
+        }
+    }
+
+    Ok(())
+}
+
+/// Find (and repair) the leftovers of interrupted actions.
+pub fn command_doctor(
+    doctor_cmd: DoctorCmd,
+    section: TabModeSection,
+    connection: &mut Connection,
+) -> Result<()> {
+    let command_executor = connection.executor()?;
+    let doctor = Doctor::new(command_executor)
+        .with_indicator_suffix(doctor_cmd.indicator_suffix.or(section.indicator_suffix));
+
+    doctor.execute(doctor_cmd.fix)
+}
+
+/// Run a ctl command.
+pub fn command_ctl(ctl_cmd: CtlCmd, connection: &mut Connection) -> Result<()> {
+    match ctl_cmd {
+        CtlCmd::State(state_cmd) => command_state(state_cmd),
+        CtlCmd::Service(control_command) => command_control(control_command, connection),
+    }
+}
+
+/// Send a command to the autolayout service.
+pub fn command_control(control_command: ControlCommand, connection: &mut Connection) -> Result<()> {
+    let mut command_executor = connection.executor()?;
+
+    if let Some(reply) = control_command.send(&mut command_executor)? {
+        println!("{}", reply);
+    }
+
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use clap::Parser;
+
+    #[derive(Parser)]
+    struct Args {
+        #[clap(subcommand)]
+        ctl: CtlCmd,
+    }
+
+    fn parse(args: &[&str]) -> clap::Result<CtlCmd> {
+        Args::try_parse_from(std::iter::once("ctl").chain(args.iter().copied()))
+            .map(|args| args.ctl)
+    }
+
+    #[test]
+    fn ctl_state_is_local() {
+        let CtlCmd::State(state_cmd) = parse(&["state", "ls"]).unwrap() else {
+            panic!("'ctl state ls' is not a state command");
+        };
+        assert_eq!(state_cmd.action, StateAction::Ls);
+
+        let CtlCmd::State(state_cmd) = parse(&["state", "prune"]).unwrap() else {
+            panic!("'ctl state prune' is not a state command");
+        };
+        assert_eq!(state_cmd.action, StateAction::Prune);
+
+        assert!(parse(&["state"]).is_err());
+    }
+
+    #[test]
+    fn ctl_service_commands() {
+        assert!(matches!(
+            parse(&["status", "--verbose"]).unwrap(),
+            CtlCmd::Service(ControlCommand::Status { verbose: true })
+        ));
+        assert!(matches!(
+            parse(&["resume", "token"]).unwrap(),
+            CtlCmd::Service(ControlCommand::Resume { token }) if token == "token"
+        ));
+    }
+}
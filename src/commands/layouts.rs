@@ -0,0 +1,458 @@
+/*
+    Copyright (C) 2022  Biagio Festa
+
+    This program is free software: you can redistribute it and/or modify
+    it under the terms of the GNU General Public License as published by
+    the Free Software Foundation, either version 3 of the License, or
+    (at your option) any later version.
+
+    This program is distributed in the hope that it will be useful,
+    but WITHOUT ANY WARRANTY; without even the implied warranty of
+    MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+    GNU General Public License for more details.
+
+    You should have received a copy of the GNU General Public License
+    along with this program.  If not, see <https://www.gnu.org/licenses/>.
+*/
+
+use crate::atomic_file::backup_path;
+use crate::atomic_file::AtomicFile;
+use crate::command_executor::Connection;
+use crate::commands::validate_tolerance;
+use crate::commands::visible_workspace;
+use crate::config::RestoreLayoutSection;
+use crate::config::SaveLayoutSection;
+use crate::errors::Failure;
+use crate::history::History;
+use crate::layout_store::project_of;
+use crate::layout_store::LayoutStore;
+use crate::picker::answers;
+use crate::picker::pick;
+use crate::picker::DEFAULT_PICKER;
+use crate::preview::render_box_diagram;
+use crate::preview::PreviewFormat;
+use crate::print_tree::print_saved_layout;
+use crate::restore_layout::Percentage;
+use crate::restore_layout::RestoreLayout;
+use crate::restore_layout::SizeSafeguards;
+use crate::save_layout::migrate_layouts;
+use crate::save_layout::SaveLayout;
+use crate::save_layout::SavedLayout;
+use crate::schema::layout_schema;
+use crate::schema::SchemaFormat;
+use crate::undo::Undo;
+use crate::utilities::DEFAULT_SHARE_TOLERANCE;
+use crate::workspace::WorkspaceFilter;
+use crate::workspace::WorkspaceRef;
+use anyhow::anyhow;
+use anyhow::Context;
+use anyhow::Result;
+use std::env::current_dir;
+use std::path::PathBuf;
+
+/// Information about the save-layout command.
+#[derive(clap::Args)]
+pub struct SaveLayoutCmd {
+    /// The workspace (number or name) to save.
+    #[clap(short, long, alias = "workspace-num", default_value = "focused")]
+    workspace: WorkspaceRef,
+
+    /// Save the workspace visible on this output (e.g., 'DP-2') instead.
+    #[clap(long, conflicts_with = "workspace")]
+    on_output: Option<String>,
+
+    /// The output filename where to save the layout. If not specified stdout will be used.
+    #[clap(short, long)]
+    output: Option<PathBuf>,
+
+    /// Format the output with JSON.
+    #[clap(short, long, action)]
+    json: bool,
+
+    /// Keep the previous version of the output file as '<OUTPUT>.bak'.
+    #[clap(long, action)]
+    keep_backup: bool,
+
+    /// Fail if some node cannot be saved (e.g., unexpected layouts), instead of skipping it.
+    #[clap(long, action)]
+    strict: bool,
+
+    /// Save a layout which can be restored on another machine: ids are omitted and
+    /// windows are restored by class, instance and title.
+    #[clap(long, action)]
+    portable: bool,
+
+    /// Save the layout in the layout store with this name (see 'restore-layout --name').
+    #[clap(long, conflicts_with_all = &["output", "json"])]
+    name: Option<String>,
+
+    /// Save the layout in the layout store for the project of the current directory: its
+    /// git repository, or the directory itself (see 'restore-layout --project').
+    #[clap(long, action, conflicts_with_all = &["output", "json", "name"])]
+    project: bool,
+}
+
+/// Information about the restore-layout command.
+#[derive(clap::Args)]
+pub struct RestoreLayoutCmd {
+    /// The input filename where layout has been stored. If not specified stdin will be used.
+    /// If the file is corrupted, its backup ('<INPUT>.bak') is used.
+    #[clap(short, long)]
+    input: Option<PathBuf>,
+
+    /// Whether the input is JSON format.
+    #[clap(short, long, action)]
+    json: bool,
+
+    /// Whether to attempt to restore sizes of windows.
+    #[clap(short, long, action)]
+    restore_sizes: bool,
+
+    /// Collapse the containers with a single child (e.g., the wrappers around each window
+    /// left by older versions) before restoring.
+    #[clap(long, action)]
+    simplify: bool,
+
+    /// Do not recreate the empty containers of the layout (e.g., opened with 'open').
+    #[clap(long, action)]
+    skip_empty: bool,
+
+    /// Restore the layout saved in the layout store with this name.
+    #[clap(long, conflicts_with_all = &["input", "json"])]
+    name: Option<String>,
+
+    /// Pick the layout of the store with an external menu.
+    #[clap(long, action, conflicts_with_all = &["input", "json", "name"])]
+    pick: bool,
+
+    /// Restore the layout saved for the project of the current directory: its git
+    /// repository, or the directory itself (see 'save-layout --project').
+    #[clap(long, action, conflicts_with_all = &["input", "json", "name", "pick"])]
+    project: bool,
+
+    /// The menu used by '--pick', reading entries from stdin and writing the selection on
+    /// stdout [default: dmenu].
+    #[clap(long, requires = "pick")]
+    picker: Option<String>,
+
+    /// The drift (percentage points) tolerated on the shares of windows after restoring
+    /// their sizes; windows beyond it are resized again [default: 1.0].
+    #[clap(long)]
+    tolerance: Option<f64>,
+
+    /// Print what the restore would do (windows moved, containers created, windows not
+    /// found) and ask for confirmation first. The answer is read from stdin, or from the
+    /// terminal if the layout is read from stdin.
+    #[clap(long, action)]
+    confirm: bool,
+
+    /// Do not ask for confirmation, even if 'confirm' is set in the configuration file.
+    #[clap(long, action)]
+    yes: bool,
+
+    /// Do not restore saved sizes (pixels) below this, e.g., a window saved while shrunk
+    /// to a sliver.
+    #[clap(long)]
+    min_restored_size: Option<isize>,
+
+    /// Skip the resizes changing a dimension by more than this percentage of its current
+    /// value (e.g., '70%').
+    #[clap(long)]
+    max_delta: Option<Percentage>,
+
+    /// Restore only if the workspace of the layout matches one of these patterns (names,
+    /// numbers or globs, comma-separated, e.g., 'mail,3').
+    #[clap(long, value_delimiter = ',')]
+    only: Vec<String>,
+
+    /// Do not restore if the workspace of the layout matches one of these patterns (names,
+    /// numbers or globs, comma-separated, e.g., 'scratch*').
+    #[clap(long, value_delimiter = ',')]
+    exclude: Vec<String>,
+}
+
+/// Information about the show-layout command.
+#[derive(clap::Args)]
+pub struct ShowLayoutCmd {
+    /// The input filename where layout has been stored. If not specified stdin will be used.
+    /// If the file is corrupted, its backup ('<INPUT>.bak') is used.
+    #[clap(short, long)]
+    input: Option<PathBuf>,
+
+    /// Whether the input is JSON format.
+    #[clap(short, long, action)]
+    json: bool,
+
+    /// Show the layout saved in the layout store with this name.
+    #[clap(long, conflicts_with_all = &["input", "json"])]
+    name: Option<String>,
+
+    /// How to render the layout.
+    #[clap(short, long, value_enum, default_value = "tree")]
+    format: PreviewFormat,
+
+    /// The width (characters) of the box diagram of 'ascii-art'.
+    #[clap(short, long, default_value = "80")]
+    width: usize,
+}
+
+/// Information about the schema command.
+#[derive(clap::Args)]
+pub struct SchemaCmd {
+    /// The format of the schema.
+    #[clap(short, long, value_enum, default_value = "json-schema")]
+    format: SchemaFormat,
+}
+
+/// Information about the migrate-layouts command.
+#[derive(clap::Args)]
+pub struct MigrateLayoutsCmd {
+    /// The directory of the layout files.
+    dir: PathBuf,
+
+    /// Whether the layout files are in JSON format.
+    #[clap(short, long, action)]
+    json: bool,
+}
+
+/// Information about the history command.
+#[derive(clap::Args)]
+pub struct HistoryCmd {
+    /// List only the snapshots of this workspace (number, name or 'focused').
+    #[clap(short, long)]
+    workspace: Option<WorkspaceRef>,
+
+    /// The history action [default: list the snapshots].
+    #[clap(subcommand)]
+    action: Option<HistoryAction>,
+}
+
+/// Action of the history command.
+#[derive(clap::Subcommand)]
+pub enum HistoryAction {
+    /// Restore a snapshot of a workspace.
+    Restore {
+        /// The snapshot: its timestamp, or its index in the list ('0' is the most recent).
+        #[clap(long)]
+        at: u64,
+
+        /// The workspace (number, name or 'focused').
+        #[clap(short, long, default_value = "focused")]
+        workspace: WorkspaceRef,
+    },
+}
+
+/// Save a layout for a workspace.
+pub fn command_save_layout(
+    save_layout_cmd: SaveLayoutCmd,
+    section: SaveLayoutSection,
+    connection: &mut Connection,
+) -> Result<()> {
+    let command_executor = connection.executor()?;
+    let save_layout = SaveLayout::new(command_executor)
+        .with_strict(save_layout_cmd.strict || section.strict)
+        .with_portable(save_layout_cmd.portable || section.portable);
+
+    let json = save_layout_cmd.json || section.json;
+    let keep_backup = save_layout_cmd.keep_backup || section.keep_backup;
+    let workspace = match save_layout_cmd.on_output {
+        Some(output) => visible_workspace(&output, connection)?,
+        None => save_layout_cmd.workspace,
+    };
+
+    if save_layout_cmd.project {
+        let project = project_of(&current_dir().context("Cannot read the current directory")?)?;
+
+        LayoutStore::open()?.write_project(&project, keep_backup, |output| {
+            save_layout.execute(workspace, output, LayoutStore::JSON)
+        })?;
+
+        eprintln!("Layout saved for project '{}'", project.display());
+        return Ok(());
+    }
+
+    let output_file = match (save_layout_cmd.name, save_layout_cmd.output) {
+        (Some(name), _) => {
+            return LayoutStore::open()?.write(&name, keep_backup, |output| {
+                save_layout.execute(workspace, output, LayoutStore::JSON)
+            })
+        }
+        (None, Some(output_file)) => output_file,
+        (None, None) => return save_layout.execute(workspace, std::io::stdout(), json),
+    };
+
+    let mut output = AtomicFile::create(&output_file, keep_backup)
+        .with_context(|| format!("Cannot create layout file '{}'", output_file.display()))?;
+
+    save_layout.execute(workspace, &mut output, json)?;
+    output.commit()
+}
+
+/// Restore a previously saved layout on a workspace.
+pub fn command_restore_layout(
+    restore_layout_cmd: RestoreLayoutCmd,
+    section: RestoreLayoutSection,
+    connection: &mut Connection,
+) -> Result<()> {
+    let json = restore_layout_cmd.json || section.json;
+    let restore_sizes = restore_layout_cmd.restore_sizes || section.restore_sizes;
+
+    let project_file = if restore_layout_cmd.project {
+        let project = project_of(&current_dir().context("Cannot read the current directory")?)?;
+        let layout_file = LayoutStore::open()?.project_path(&project);
+
+        if !layout_file.exists() && !backup_path(&layout_file).exists() {
+            return Err(
+                anyhow!("No layout saved for project '{}'", project.display())
+                    .context(Failure::NoProjectLayout),
+            );
+        }
+
+        Some(layout_file)
+    } else {
+        None
+    };
+
+    let name = if restore_layout_cmd.pick {
+        let layouts = LayoutStore::open()?.list()?;
+        let entries = layouts
+            .iter()
+            .map(|layout| layout.describe())
+            .collect::<Vec<_>>();
+
+        let picker = restore_layout_cmd
+            .picker
+            .or(section.picker)
+            .unwrap_or_else(|| DEFAULT_PICKER.to_string());
+
+        match pick(&picker, &entries)? {
+            Some(index) => Some(layouts[index].name.clone()),
+            None => {
+                eprintln!("No layout selected");
+                return Ok(());
+            }
+        }
+    } else {
+        restore_layout_cmd.name
+    };
+
+    let tolerance = validate_tolerance(
+        restore_layout_cmd
+            .tolerance
+            .or(section.tolerance)
+            .unwrap_or(DEFAULT_SHARE_TOLERANCE),
+    )?;
+
+    let confirm = (restore_layout_cmd.confirm || section.confirm) && !restore_layout_cmd.yes;
+    let layout_on_stdin =
+        project_file.is_none() && name.is_none() && restore_layout_cmd.input.is_none();
+    let confirm_answers = confirm.then(|| answers(layout_on_stdin)).transpose()?;
+
+    let command_executor = connection.executor()?;
+    let restore_layout = RestoreLayout::new(command_executor)
+        .with_confirm(confirm_answers)
+        .with_simplify(restore_layout_cmd.simplify || section.simplify)
+        .with_skip_empty(restore_layout_cmd.skip_empty || section.skip_empty)
+        .with_tolerance(tolerance)
+        .with_size_safeguards(SizeSafeguards {
+            min_size: restore_layout_cmd
+                .min_restored_size
+                .or(section.min_restored_size),
+            max_delta: restore_layout_cmd.max_delta.or(section.max_delta),
+        })
+        .with_filter(WorkspaceFilter::new(
+            &restore_layout_cmd.only,
+            &restore_layout_cmd.exclude,
+        ))
+        .with_fail_partial(true);
+
+    if let Some(layout_file) = project_file {
+        return restore_layout.execute_file(&layout_file, LayoutStore::JSON, restore_sizes);
+    }
+
+    match (name, restore_layout_cmd.input) {
+        (Some(name), _) => restore_layout.execute_file(
+            &LayoutStore::open()?.path(&name)?,
+            LayoutStore::JSON,
+            restore_sizes,
+        ),
+        (None, Some(input_file)) => restore_layout.execute_file(&input_file, json, restore_sizes),
+        (None, None) => restore_layout.execute(std::io::stdin(), json, restore_sizes),
+    }
+}
+
+/// Print a saved layout.
+pub fn command_show_layout(show_layout_cmd: ShowLayoutCmd) -> Result<()> {
+    let saved_layout = match (show_layout_cmd.name, show_layout_cmd.input) {
+        (Some(name), _) => {
+            SavedLayout::load(&LayoutStore::open()?.path(&name)?, LayoutStore::JSON)?
+        }
+        (None, Some(input_file)) => SavedLayout::load(&input_file, show_layout_cmd.json)?,
+        (None, None) => SavedLayout::from_reader(std::io::stdin(), show_layout_cmd.json)?,
+    };
+
+    print_saved_layout(&saved_layout)?;
+
+    if let PreviewFormat::AsciiArt = show_layout_cmd.format {
+        println!();
+        println!(
+            "{}",
+            render_box_diagram(&saved_layout, show_layout_cmd.width)
+        );
+    }
+
+    Ok(())
+}
+
+/// List the layouts of the layout store.
+pub fn command_list_layouts() -> Result<()> {
+    let layout_store = LayoutStore::open()?;
+
+    for layout in layout_store
+        .list()?
+        .into_iter()
+        .chain(layout_store.list_projects()?)
+    {
+        println!("{}", layout.describe());
+    }
+
+    Ok(())
+}
+
+/// Print the schema of layout files.
+pub fn command_schema(schema_cmd: SchemaCmd) -> Result<()> {
+    let schema = match schema_cmd.format {
+        SchemaFormat::JsonSchema => layout_schema(),
+    };
+
+    println!(
+        "{}",
+        serde_json::to_string_pretty(&schema).context("Cannot serialize the schema")?
+    );
+
+    Ok(())
+}
+
+/// Simplify the layout files of a directory.
+pub fn command_migrate_layouts(migrate_layouts_cmd: MigrateLayoutsCmd) -> Result<()> {
+    migrate_layouts(&migrate_layouts_cmd.dir, migrate_layouts_cmd.json)
+}
+
+/// Undo the last layout change.
+pub fn command_undo(connection: &mut Connection) -> Result<()> {
+    let command_executor = connection.executor()?;
+    let undo = Undo::new(command_executor);
+
+    undo.execute()
+}
+
+/// List or restore the layout snapshots.
+pub fn command_history(history_cmd: HistoryCmd, connection: &mut Connection) -> Result<()> {
+    let command_executor = connection.executor()?;
+    let history = History::new(command_executor);
+
+    match history_cmd.action {
+        None => history.execute_list(history_cmd.workspace.as_ref()),
+        Some(HistoryAction::Restore { at, workspace }) => history.execute_restore(&workspace, at),
+    }
+}
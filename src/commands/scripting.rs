@@ -0,0 +1,140 @@
+/*
+    Copyright (C) 2022  Biagio Festa
+
+    This program is free software: you can redistribute it and/or modify
+    it under the terms of the GNU General Public License as published by
+    the Free Software Foundation, either version 3 of the License, or
+    (at your option) any later version.
+
+    This program is distributed in the hope that it will be useful,
+    but WITHOUT ANY WARRANTY; without even the implied warranty of
+    MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+    GNU General Public License for more details.
+
+    You should have received a copy of the GNU General Public License
+    along with this program.  If not, see <https://www.gnu.org/licenses/>.
+*/
+
+use crate::bar_proxy::BarProxy;
+use crate::batch::split_line;
+use crate::command_executor::Connection;
+use crate::config::BarProxySection;
+use crate::config::Config;
+use crate::config::TabModeSection;
+use crate::run;
+use crate::serve_json::ServeJson;
+use crate::tabmode::TabModeOptions;
+use crate::CliArgs;
+use crate::Command;
+use anyhow::anyhow;
+use anyhow::Context;
+use anyhow::Result;
+use clap::Parser;
+use std::fs::File;
+use std::io::BufRead;
+use std::io::BufReader;
+use std::path::PathBuf;
+
+/// Information about the batch command.
+#[derive(clap::Args)]
+pub struct BatchCmd {
+    /// The file with one subcommand per line ('-' for stdin). Empty lines and lines
+    /// starting with '#' are skipped.
+    input: PathBuf,
+
+    /// Execute the remaining lines after a failure.
+    #[clap(long, action)]
+    keep_going: bool,
+}
+
+/// Execute the subcommands of a batch.
+///
+/// Each command's output is preceded by `[<line>] <command>`, and failures are
+/// reported as `[<line>] [ERROR]: ...`.
+pub fn command_batch(batch_cmd: BatchCmd, connection: &mut Connection) -> Result<()> {
+    let input: Box<dyn BufRead> = if batch_cmd.input.as_os_str() == "-" {
+        Box::new(std::io::stdin().lock())
+    } else {
+        let file = File::open(&batch_cmd.input).with_context(|| {
+            format!("Cannot open the batch file '{}'", batch_cmd.input.display())
+        })?;
+
+        Box::new(BufReader::new(file))
+    };
+
+    let mut failures = 0;
+
+    for (index, line) in input.lines().enumerate() {
+        let line = line.context("Cannot read the batch input")?;
+        let line = line.trim();
+        let line_num = index + 1;
+
+        if line.is_empty() || line.starts_with('#') {
+            continue;
+        }
+
+        println!("[{}] {}", line_num, line);
+
+        if let Err(error) = run_batch_line(line, connection) {
+            println!("[{}] [ERROR]: {:?}", line_num, error);
+            failures += 1;
+
+            if !batch_cmd.keep_going {
+                return Err(anyhow!("Batch stopped at line {}", line_num));
+            }
+        }
+    }
+
+    if failures > 0 {
+        Err(anyhow!("{} batch commands failed", failures))
+    } else {
+        Ok(())
+    }
+}
+
+/// Proxy the i3bar click events, executing the bound subcommands.
+pub fn command_bar_proxy(section: BarProxySection, connection: &mut Connection) -> Result<()> {
+    if section.buttons.is_empty() {
+        eprintln!("[WARN]: No buttons bound in the 'bar-proxy' config section");
+    }
+
+    let bar_proxy = BarProxy::new(section.block_name, section.buttons);
+
+    bar_proxy.execute(std::io::stdin().lock(), std::io::stdout(), |line| {
+        run_batch_line(line, connection)
+    })
+}
+
+/// Serve JSON requests from stdin, writing the responses on stdout.
+pub fn command_serve_json(section: TabModeSection, connection: &mut Connection) -> Result<()> {
+    let options = TabModeOptions {
+        file_layout: section.file_layout,
+        keep_backup: section.keep_backup,
+        restore_sizes: section.restore_sizes.unwrap_or(true),
+        title_format: section.title_format,
+        reset_title_format: section.reset_title_format,
+        border_tabbed: section.border_tabbed,
+        border_split: section.border_split,
+        indicator_suffix: section.indicator_suffix,
+        json: section.json,
+        direction: None,
+    };
+
+    let serve_json = ServeJson::new(connection.executor()?, options);
+
+    serve_json.execute(std::io::stdin().lock(), std::io::stdout())
+}
+
+/// Parse and execute a line of a batch.
+pub fn run_batch_line(line: &str, connection: &mut Connection) -> Result<()> {
+    let arguments = std::iter::once("i3-autolayout".to_string()).chain(split_line(line)?);
+    let cli_args = CliArgs::try_parse_from(arguments).map_err(|error| anyhow!("{}", error))?;
+
+    if let Command::Batch(_) | Command::BarProxy | Command::ServeJson = cli_args.command {
+        return Err(anyhow!("Nested batches are not supported"));
+    }
+
+    let config = Config::load(cli_args.config.as_deref())?;
+
+    run(cli_args.command, config, connection)
+}
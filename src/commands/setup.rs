@@ -0,0 +1,92 @@
+/*
+    Copyright (C) 2022  Biagio Festa
+
+    This program is free software: you can redistribute it and/or modify
+    it under the terms of the GNU General Public License as published by
+    the Free Software Foundation, either version 3 of the License, or
+    (at your option) any later version.
+
+    This program is distributed in the hope that it will be useful,
+    but WITHOUT ANY WARRANTY; without even the implied warranty of
+    MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+    GNU General Public License for more details.
+
+    You should have received a copy of the GNU General Public License
+    along with this program.  If not, see <https://www.gnu.org/licenses/>.
+*/
+
+use crate::command_executor::Connection;
+use crate::config::Config;
+use crate::config::GenerateConfigSection;
+use crate::generate_config::read_config;
+use crate::generate_config::ConfigTemplate;
+use anyhow::Context;
+use anyhow::Result;
+use std::path::PathBuf;
+
+/// Information about the generate-config command.
+#[derive(clap::Args)]
+pub struct GenerateConfigCmd {
+    /// The modifier key which replaces `$mod` in the prefix [default: Mod4].
+    #[clap(short, long)]
+    modifier: Option<String>,
+
+    /// The key combination which enters the i3-autolayout binding mode [default: $mod+t].
+    #[clap(short, long)]
+    prefix: Option<String>,
+
+    /// The directory where tabmode saves the layouts, one file per workspace
+    /// [default: ~/.cache/i3-autolayout].
+    #[clap(short, long)]
+    layout_dir: Option<String>,
+
+    /// Check the currently loaded i3 config for conflicting bindings instead of printing.
+    #[clap(short, long, action)]
+    check: bool,
+}
+
+/// Generate an i3 config snippet or check the loaded config for conflicts.
+pub fn command_generate_config(
+    generate_config_cmd: GenerateConfigCmd,
+    section: GenerateConfigSection,
+    connection: &mut Connection,
+) -> Result<()> {
+    let binary = std::env::current_exe().context("Cannot detect the i3-autolayout binary path")?;
+
+    let modifier = generate_config_cmd.modifier.unwrap_or(section.modifier);
+    let prefix = generate_config_cmd.prefix.unwrap_or(section.prefix);
+    let layout_dir = generate_config_cmd.layout_dir.unwrap_or(section.layout_dir);
+
+    let template = ConfigTemplate {
+        binary: &binary,
+        modifier: &modifier,
+        prefix: &prefix,
+        layout_dir: &layout_dir,
+    };
+
+    if generate_config_cmd.check {
+        let mut command_executor = connection.executor()?;
+        let config_path =
+            PathBuf::from(command_executor.query_i3_version()?.loaded_config_file_name);
+        let warnings = template.check(&read_config(&config_path)?);
+
+        if warnings.is_empty() {
+            println!("No conflicting bindings in '{}'", config_path.display());
+        }
+
+        for warning in warnings {
+            eprintln!("[WARN]: {}", warning);
+        }
+
+        Ok(())
+    } else {
+        print!("{}", template.render());
+        Ok(())
+    }
+}
+
+/// Print the effective configuration.
+pub fn command_check_config(config: Config) -> Result<()> {
+    print!("{}", config.to_toml()?);
+    Ok(())
+}
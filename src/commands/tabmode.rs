@@ -0,0 +1,209 @@
+/*
+    Copyright (C) 2022  Biagio Festa
+
+    This program is free software: you can redistribute it and/or modify
+    it under the terms of the GNU General Public License as published by
+    the Free Software Foundation, either version 3 of the License, or
+    (at your option) any later version.
+
+    This program is distributed in the hope that it will be useful,
+    but WITHOUT ANY WARRANTY; without even the implied warranty of
+    MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+    GNU General Public License for more details.
+
+    You should have received a copy of the GNU General Public License
+    along with this program.  If not, see <https://www.gnu.org/licenses/>.
+*/
+
+use crate::command_executor::Connection;
+use crate::commands::visible_workspace;
+use crate::config::TabModeSection;
+use crate::normalize::Normalize;
+use crate::normalize::NormalizeLayout;
+use crate::tabmode::TabMode;
+use crate::tabmode::TabModeDirection;
+use crate::tabmode::TabModeOptions;
+use crate::utilities::BorderStyle;
+use crate::workspace::WorkspaceRef;
+use anyhow::Result;
+use std::path::PathBuf;
+
+/// Information about the tabmode command.
+#[derive(clap::Args)]
+pub struct TabModeCmd {
+    /// The workspace (number or name) to apply tab mode.
+    #[clap(short, long, alias = "workspace-num", default_value = "focused")]
+    workspace: WorkspaceRef,
+
+    /// Apply tab mode to the workspace visible on this output (e.g., 'DP-2') instead.
+    #[clap(long, conflicts_with = "workspace")]
+    output: Option<String>,
+
+    /// The file where to save/load the layout. If it ends with '/' it is a directory
+    /// holding one layout file per workspace. '-' is stdout when saving and stdin when
+    /// restoring.
+    #[clap(short, long)]
+    file_layout: Option<PathBuf>,
+
+    /// Whether the layout file is in JSON format.
+    #[clap(short, long, action)]
+    json: bool,
+
+    /// Save (enter tab mode) or restore (leave tab mode) regardless of the current state,
+    /// e.g., when the layout is piped. Use 'restore' to leave tab mode entered by older
+    /// versions, which did not mark it and are not recognized.
+    #[clap(long, value_enum)]
+    direction: Option<TabModeDirection>,
+
+    /// Keep the previous version of the layout file as '<FILE_LAYOUT>.bak'.
+    #[clap(long, action)]
+    keep_backup: bool,
+
+    /// Restore sizes of windows when leaving tab mode (default).
+    #[clap(long, action)]
+    restore_sizes: bool,
+
+    /// Do not restore sizes of windows when leaving tab mode.
+    #[clap(long, action, conflicts_with = "restore-sizes")]
+    no_restore_sizes: bool,
+
+    /// The title format of the tabs (e.g., '%class: %title').
+    #[clap(long)]
+    title_format: Option<String>,
+
+    /// Do not reset the title format when leaving tab mode.
+    #[clap(long, action)]
+    keep_title_format: bool,
+
+    /// The border style of windows in tab mode ('normal [PX]', 'pixel [PX]' or 'none').
+    #[clap(long)]
+    border_tabbed: Option<BorderStyle>,
+
+    /// The border style of windows when leaving tab mode. With a layout file, each window
+    /// gets back the style it had instead.
+    #[clap(long)]
+    border_split: Option<BorderStyle>,
+
+    /// Append this suffix to the workspace name while in tab mode (e.g., '[]'), so that
+    /// i3bar shows it. The original name is restored when leaving tab mode.
+    #[clap(long)]
+    indicator_suffix: Option<String>,
+}
+
+/// Information about the normalize command.
+#[derive(clap::Args)]
+pub struct NormalizeCmd {
+    /// The workspace (number or name) to normalize.
+    #[clap(short, long, alias = "workspace-num", default_value = "focused")]
+    workspace: WorkspaceRef,
+
+    /// The layout of the normalized workspace.
+    #[clap(short, long, value_enum, default_value = "splith")]
+    layout: NormalizeLayout,
+}
+
+/// Whether sizes are restored when leaving tab mode.
+///
+/// The flags take precedence over the configuration; sizes are restored by default.
+fn restore_sizes(tabmode_cmd: &TabModeCmd, section: &TabModeSection) -> bool {
+    if tabmode_cmd.restore_sizes {
+        true
+    } else if tabmode_cmd.no_restore_sizes {
+        false
+    } else {
+        section.restore_sizes.unwrap_or(true)
+    }
+}
+
+/// Execute tabmode.
+pub fn command_tabmode(
+    tabmode_cmd: TabModeCmd,
+    section: TabModeSection,
+    connection: &mut Connection,
+) -> Result<()> {
+    let command_executor = connection.executor()?;
+    let tabmode = TabMode::new(command_executor);
+
+    let restore_sizes = restore_sizes(&tabmode_cmd, &section);
+
+    let options = TabModeOptions {
+        file_layout: tabmode_cmd.file_layout.or(section.file_layout),
+        keep_backup: tabmode_cmd.keep_backup || section.keep_backup,
+        restore_sizes,
+        title_format: tabmode_cmd.title_format.or(section.title_format),
+        reset_title_format: !tabmode_cmd.keep_title_format && section.reset_title_format,
+        border_tabbed: tabmode_cmd.border_tabbed.or(section.border_tabbed),
+        border_split: tabmode_cmd.border_split.or(section.border_split),
+        indicator_suffix: tabmode_cmd.indicator_suffix.or(section.indicator_suffix),
+        json: tabmode_cmd.json || section.json,
+        direction: tabmode_cmd.direction,
+    };
+
+    let workspace = match tabmode_cmd.output {
+        Some(output) => visible_workspace(&output, connection)?,
+        None => tabmode_cmd.workspace,
+    };
+
+    tabmode.execute(workspace, options)
+}
+
+/// Flatten a workspace into a single split.
+pub fn command_normalize(normalize_cmd: NormalizeCmd, connection: &mut Connection) -> Result<()> {
+    let command_executor = connection.executor()?;
+    let normalize = Normalize::new(command_executor);
+
+    normalize.execute(normalize_cmd.workspace, normalize_cmd.layout)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::config::Config;
+    use clap::Parser;
+
+    #[derive(Parser)]
+    struct Args {
+        #[clap(flatten)]
+        tabmode: TabModeCmd,
+    }
+
+    fn parse(args: &[&str]) -> clap::Result<TabModeCmd> {
+        Args::try_parse_from(std::iter::once("tabmode").chain(args.iter().copied()))
+            .map(|args| args.tabmode)
+    }
+
+    #[test]
+    fn restore_sizes_precedence() {
+        for (args, config, expected) in [
+            (&[][..], "", true),
+            (&[], "[tabmode]\nrestore-sizes = false\n", false),
+            (&[], "[tabmode]\nrestore-sizes = true\n", true),
+            (
+                &["--restore-sizes"],
+                "[tabmode]\nrestore-sizes = false\n",
+                true,
+            ),
+            (&["--no-restore-sizes"], "", false),
+            (
+                &["--no-restore-sizes"],
+                "[tabmode]\nrestore-sizes = true\n",
+                false,
+            ),
+        ] {
+            let section = Config::parse(config).unwrap().tabmode;
+
+            assert_eq!(
+                restore_sizes(&parse(args).unwrap(), &section),
+                expected,
+                "{:?} {:?}",
+                args,
+                config
+            );
+        }
+    }
+
+    #[test]
+    fn restore_sizes_flags_conflict() {
+        assert!(parse(&["--restore-sizes", "--no-restore-sizes"]).is_err());
+    }
+}
@@ -0,0 +1,303 @@
+/*
+    Copyright (C) 2022  Biagio Festa
+
+    This program is free software: you can redistribute it and/or modify
+    it under the terms of the GNU General Public License as published by
+    the Free Software Foundation, either version 3 of the License, or
+    (at your option) any later version.
+
+    This program is distributed in the hope that it will be useful,
+    but WITHOUT ANY WARRANTY; without even the implied warranty of
+    MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+    GNU General Public License for more details.
+
+    You should have received a copy of the GNU General Public License
+    along with this program.  If not, see <https://www.gnu.org/licenses/>.
+*/
+
+use crate::command_executor::Connection;
+use crate::commands::validate_tolerance;
+use crate::config::FloatSection;
+use crate::config::GatherSection;
+use crate::config::ResizeSection;
+use crate::errors::Failure;
+use crate::float::Float;
+use crate::float::FloatAction;
+use crate::gather::Gather;
+use crate::lock_size::LockSize;
+use crate::park::Park;
+use crate::resize::Resize;
+use crate::resize::ResizeAction;
+use crate::resize::ResizeStep;
+use crate::scratchpad::ScratchpadCycle;
+use crate::selector::select;
+use crate::selector::Criteria;
+use crate::send_home::SendHome;
+use crate::urgent::UrgentJump;
+use crate::urgent::UrgentJumpOutcome;
+use crate::utilities::ShareRatio;
+use crate::utilities::DEFAULT_SHARE_TOLERANCE;
+use crate::workspace::WorkspaceRef;
+use anyhow::anyhow;
+use anyhow::Result;
+
+/// Information about the float command.
+#[derive(clap::Args)]
+pub struct FloatCmd {
+    /// The action to apply.
+    #[clap(value_enum)]
+    action: FloatAction,
+
+    /// The workspace (number or name) for 'cascade'.
+    #[clap(short, long, alias = "workspace-num", default_value = "focused")]
+    workspace: WorkspaceRef,
+
+    /// The distance (pixels) kept from the output edges [default: 0].
+    #[clap(short, long)]
+    margin: Option<isize>,
+
+    /// Make the focused window floating if it is tiled.
+    #[clap(short, long, action)]
+    force_float: bool,
+}
+
+/// Information about the select command.
+#[derive(clap::Args)]
+pub struct SelectCmd {
+    /// The criteria (e.g., '[class="Firefox" title~="^Inbox"]').
+    criteria: Criteria,
+}
+
+/// Information about the gather command.
+#[derive(clap::Args)]
+pub struct GatherCmd {
+    /// Gather windows matching these criteria (e.g., '[class="Firefox" title~="^Inbox"]').
+    criteria: Option<Criteria>,
+
+    /// Gather windows with this class.
+    #[clap(short, long)]
+    class: Option<String>,
+
+    /// Gather windows with this mark.
+    #[clap(short, long)]
+    mark: Option<String>,
+
+    /// The target workspace (number, name or 'focused'), created if missing.
+    /// If not specified the focused workspace will be used.
+    #[clap(short, long)]
+    to: Option<WorkspaceRef>,
+
+    /// Only list the windows which would be moved.
+    #[clap(short, long, action)]
+    dry_run: bool,
+
+    /// Balance the target workspace afterwards.
+    #[clap(short, long, action)]
+    balance: bool,
+
+    /// How the balance divides the space of each container: 'equal', 'golden' (the first
+    /// child gets ~62% and the next ones divide the rest by the same rule) or weights
+    /// (e.g., '60,40') [default: equal].
+    #[clap(long, requires = "balance")]
+    ratio: Option<ShareRatio>,
+
+    /// The drift (percentage points) tolerated on the shares of windows after balancing;
+    /// windows beyond it are resized again [default: 1.0].
+    #[clap(long)]
+    tolerance: Option<f64>,
+}
+
+/// Information about the send-home command.
+#[derive(clap::Args)]
+pub struct SendHomeCmd {
+    /// Send the focused window home (default).
+    #[clap(long, action, conflicts_with = "all-on-workspace")]
+    focused: bool,
+
+    /// Send every window of the focused workspace (floating ones included) home.
+    #[clap(long, action)]
+    all_on_workspace: bool,
+}
+
+/// Information about the scratchpad-cycle command.
+#[derive(clap::Args)]
+pub struct ScratchpadCycleCmd {
+    /// Cycle among the scratchpad windows matching these criteria
+    /// (e.g., '[class="Firefox" title~="^Inbox"]').
+    criteria: Option<Criteria>,
+
+    /// Cycle among the scratchpad windows with this class.
+    #[clap(short, long)]
+    class: Option<String>,
+
+    /// Show the window on the focused workspace instead of the one it has been hidden from.
+    #[clap(long, action)]
+    here: bool,
+}
+
+/// Information about the unpark command.
+#[derive(clap::Args)]
+pub struct UnparkCmd {
+    /// The workspace (number or name) to unpark.
+    #[clap(short, long, alias = "workspace-num", default_value = "focused")]
+    workspace: WorkspaceRef,
+}
+
+/// Information about the resize command.
+#[derive(clap::Args)]
+pub struct ResizeCmd {
+    /// The resize operation.
+    #[clap(value_enum)]
+    action: ResizeAction,
+
+    /// The amount of the resize (e.g., '5ppt' or '10px') [default: 5ppt].
+    #[clap(short, long)]
+    step: Option<ResizeStep>,
+}
+
+/// Information about the urgent-jump command.
+#[derive(clap::Args)]
+pub struct UrgentJumpCmd {
+    /// Return to the window focused before the last jump.
+    #[clap(short, long, action)]
+    back: bool,
+}
+
+/// Arrange floating windows.
+pub fn command_float(
+    float_cmd: FloatCmd,
+    section: FloatSection,
+    connection: &mut Connection,
+) -> Result<()> {
+    let command_executor = connection.executor()?;
+    let float = Float::new(command_executor);
+
+    float.execute(
+        float_cmd.action,
+        float_cmd.workspace,
+        float_cmd.margin.unwrap_or(section.margin),
+        float_cmd.force_float,
+    )
+}
+
+/// Gather windows on a workspace.
+pub fn command_gather(
+    gather_cmd: GatherCmd,
+    section: GatherSection,
+    connection: &mut Connection,
+) -> Result<()> {
+    let tolerance = validate_tolerance(
+        gather_cmd
+            .tolerance
+            .or(section.tolerance)
+            .unwrap_or(DEFAULT_SHARE_TOLERANCE),
+    )?;
+
+    let command_executor = connection.executor()?;
+    let gather = Gather::new(command_executor)
+        .with_tolerance(tolerance)
+        .with_ratio(gather_cmd.ratio.or(section.ratio).unwrap_or_default());
+
+    let mut criteria = gather_cmd.criteria.unwrap_or_default();
+
+    if let Some(class) = gather_cmd.class {
+        criteria.class = Some(class);
+    }
+
+    if let Some(mark) = gather_cmd.mark {
+        criteria.mark = Some(mark);
+    }
+
+    gather.execute(
+        &criteria,
+        gather_cmd.to,
+        gather_cmd.dry_run,
+        gather_cmd.balance || section.balance,
+    )
+}
+
+/// Move windows back to their birth workspace.
+pub fn command_send_home(send_home_cmd: SendHomeCmd, connection: &mut Connection) -> Result<()> {
+    let command_executor = connection.executor()?;
+
+    SendHome::new(command_executor).execute(send_home_cmd.all_on_workspace)
+}
+
+/// Print the windows matching some criteria.
+pub fn command_select(select_cmd: SelectCmd, connection: &mut Connection) -> Result<()> {
+    let mut command_executor = connection.executor()?;
+    let root_node = command_executor.query_root_node()?;
+
+    for window in select(&select_cmd.criteria, &root_node) {
+        println!("{} {:?}", window.id, window.name);
+    }
+
+    Ok(())
+}
+
+/// Show the next scratchpad window.
+pub fn command_scratchpad_cycle(
+    scratchpad_cycle_cmd: ScratchpadCycleCmd,
+    connection: &mut Connection,
+) -> Result<()> {
+    let command_executor = connection.executor()?;
+    let scratchpad_cycle = ScratchpadCycle::new(command_executor);
+
+    let mut criteria = scratchpad_cycle_cmd.criteria.unwrap_or_default();
+
+    if let Some(class) = scratchpad_cycle_cmd.class {
+        criteria.class = Some(class);
+    }
+
+    scratchpad_cycle.execute(&criteria, scratchpad_cycle_cmd.here)
+}
+
+/// Park the focused workspace.
+pub fn command_park(connection: &mut Connection) -> Result<()> {
+    let command_executor = connection.executor()?;
+    let park = Park::new(command_executor);
+
+    park.park()
+}
+
+/// Unpark a workspace.
+pub fn command_unpark(unpark_cmd: UnparkCmd, connection: &mut Connection) -> Result<()> {
+    let command_executor = connection.executor()?;
+    let park = Park::new(command_executor);
+
+    park.unpark(unpark_cmd.workspace)
+}
+
+/// Resize the focused container.
+pub fn command_resize(
+    resize_cmd: ResizeCmd,
+    section: ResizeSection,
+    connection: &mut Connection,
+) -> Result<()> {
+    let command_executor = connection.executor()?;
+    let resize = Resize::new(command_executor);
+
+    resize.execute(resize_cmd.action, resize_cmd.step.unwrap_or(section.step))
+}
+
+/// Toggle the size lock of the focused container.
+pub fn command_lock_size(connection: &mut Connection) -> Result<()> {
+    let command_executor = connection.executor()?;
+    let lock_size = LockSize::new(command_executor);
+
+    lock_size.execute()
+}
+
+/// Jump to the next urgent window.
+pub fn command_urgent_jump(
+    urgent_jump_cmd: UrgentJumpCmd,
+    connection: &mut Connection,
+) -> Result<()> {
+    let command_executor = connection.executor()?;
+    let urgent_jump = UrgentJump::new(command_executor);
+
+    match urgent_jump.execute(urgent_jump_cmd.back)? {
+        UrgentJumpOutcome::Jumped | UrgentJumpOutcome::Returned => Ok(()),
+        UrgentJumpOutcome::NoUrgent => Err(anyhow!("No urgent windows").context(Failure::NoUrgent)),
+    }
+}
@@ -0,0 +1,277 @@
+/*
+    Copyright (C) 2022  Biagio Festa
+
+    This program is free software: you can redistribute it and/or modify
+    it under the terms of the GNU General Public License as published by
+    the Free Software Foundation, either version 3 of the License, or
+    (at your option) any later version.
+
+    This program is distributed in the hope that it will be useful,
+    but WITHOUT ANY WARRANTY; without even the implied warranty of
+    MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+    GNU General Public License for more details.
+
+    You should have received a copy of the GNU General Public License
+    along with this program.  If not, see <https://www.gnu.org/licenses/>.
+*/
+
+use crate::balance::Balance;
+use crate::command_executor::Connection;
+use crate::commands::validate_tolerance;
+use crate::commands::visible_workspace;
+use crate::config::MoveWorkspaceSection;
+use crate::group::GroupMember;
+use crate::group::Groups;
+use crate::move_workspace::MoveWorkspace;
+use crate::move_workspace::OutputTarget;
+use crate::picker::pick;
+use crate::picker::DEFAULT_PICKER;
+use crate::utilities::ShareRatio;
+use crate::utilities::DEFAULT_SHARE_TOLERANCE;
+use crate::workspace::WorkspaceRef;
+use anyhow::Result;
+
+/// Information about the move-workspace command.
+#[derive(clap::Args)]
+pub struct MoveWorkspaceCmd {
+    /// Move the workspace to this output (e.g., 'DP-2').
+    #[clap(long, required_unless_present_any = &["next", "prev"])]
+    to: Option<String>,
+
+    /// Move the workspace to the next active output.
+    #[clap(long, action, conflicts_with_all = &["to", "prev"])]
+    next: bool,
+
+    /// Move the workspace to the previous active output.
+    #[clap(long, action, conflicts_with_all = &["to", "next"])]
+    prev: bool,
+
+    /// The order of the outputs cycled by '--next' and '--prev' (comma-separated, e.g.,
+    /// 'DP-1,HDMI-A-0,eDP-1'); inactive outputs are skipped [default: left to right].
+    #[clap(long, value_delimiter = ',')]
+    order: Vec<String>,
+
+    /// Balance the workspace afterwards, so that its windows adapt to the new output.
+    #[clap(long, action)]
+    rebalance: bool,
+
+    /// The drift (percentage points) tolerated on the shares of windows after balancing;
+    /// windows beyond it are resized again [default: 1.0].
+    #[clap(long)]
+    tolerance: Option<f64>,
+}
+
+/// Information about the balance command.
+#[derive(clap::Args)]
+pub struct BalanceCmd {
+    /// The workspace (number or name) to balance.
+    #[clap(short, long, default_value = "focused")]
+    workspace: WorkspaceRef,
+
+    /// Balance the workspace visible on this output (e.g., 'DP-2') instead.
+    #[clap(long, conflicts_with = "workspace")]
+    output: Option<String>,
+
+    /// How the space of each container is divided: 'equal', 'golden' (the first child
+    /// gets ~62% and the next ones divide the rest by the same rule) or weights
+    /// (e.g., '60,40') [default: equal]. Windows with a locked size keep their share.
+    #[clap(long)]
+    ratio: Option<ShareRatio>,
+
+    /// The drift (percentage points) tolerated on the shares of windows after balancing;
+    /// windows beyond it are resized again [default: 1.0].
+    #[clap(long)]
+    tolerance: Option<f64>,
+}
+
+/// Information about the group command.
+#[derive(clap::Args)]
+pub struct GroupCmd {
+    /// The group action.
+    #[clap(subcommand)]
+    action: GroupAction,
+}
+
+/// Action of the group command.
+#[derive(clap::Subcommand)]
+pub enum GroupAction {
+    /// Define a group of workspaces.
+    Define {
+        /// The group name.
+        name: String,
+
+        /// The members of the group (WORKSPACE:OUTPUT).
+        #[clap(required = true)]
+        members: Vec<GroupMember>,
+    },
+
+    /// Display each workspace of a group on its output.
+    Activate {
+        /// The group name.
+        #[clap(required_unless_present = "pick")]
+        name: Option<String>,
+
+        /// Pick the group with an external menu.
+        #[clap(long, action, conflicts_with = "name")]
+        pick: bool,
+
+        /// The menu used by '--pick' [default: dmenu].
+        #[clap(long, requires = "pick")]
+        picker: Option<String>,
+    },
+
+    /// List the defined groups.
+    List,
+}
+
+/// Move the focused workspace to another output.
+pub fn command_move_workspace(
+    move_workspace_cmd: MoveWorkspaceCmd,
+    section: MoveWorkspaceSection,
+    connection: &mut Connection,
+) -> Result<()> {
+    let tolerance = validate_tolerance(
+        move_workspace_cmd
+            .tolerance
+            .or(section.tolerance)
+            .unwrap_or(DEFAULT_SHARE_TOLERANCE),
+    )?;
+
+    let order = if move_workspace_cmd.order.is_empty() {
+        section.order
+    } else {
+        move_workspace_cmd.order
+    };
+
+    let target = match move_workspace_cmd.to {
+        Some(output) => OutputTarget::Named(output),
+        None if move_workspace_cmd.prev => OutputTarget::Prev,
+        None => OutputTarget::Next,
+    };
+
+    let command_executor = connection.executor()?;
+    let move_workspace = MoveWorkspace::new(command_executor)
+        .with_order(order)
+        .with_tolerance(tolerance);
+
+    move_workspace.execute(target, move_workspace_cmd.rebalance || section.rebalance)
+}
+
+/// Divide the space of the containers of a workspace among their children.
+pub fn command_balance(balance_cmd: BalanceCmd, connection: &mut Connection) -> Result<()> {
+    let tolerance = validate_tolerance(balance_cmd.tolerance.unwrap_or(DEFAULT_SHARE_TOLERANCE))?;
+
+    let workspace = match balance_cmd.output {
+        Some(output) => visible_workspace(&output, connection)?,
+        None => balance_cmd.workspace,
+    };
+
+    let command_executor = connection.executor()?;
+    let balance = Balance::new(command_executor)
+        .with_ratio(balance_cmd.ratio.unwrap_or_default())
+        .with_tolerance(tolerance);
+
+    balance.execute(workspace)
+}
+
+/// Manage workspace groups.
+pub fn command_group(group_cmd: GroupCmd, connection: &mut Connection) -> Result<()> {
+    let groups = Groups::load()?;
+
+    match group_cmd.action {
+        GroupAction::Define { name, members } => groups.define(name, members),
+
+        GroupAction::Activate { name, picker, .. } => {
+            let name = match name {
+                Some(name) => name,
+
+                None => {
+                    // Without a name, '--pick' is given.
+                    let names = groups.names();
+                    let picker = picker.unwrap_or_else(|| DEFAULT_PICKER.to_string());
+
+                    match pick(&picker, &names)? {
+                        Some(index) => names[index].clone(),
+                        None => {
+                            eprintln!("No group selected");
+                            return Ok(());
+                        }
+                    }
+                }
+            };
+
+            let mut command_executor = connection.executor()?;
+            groups.activate(&name, &mut command_executor)
+        }
+
+        GroupAction::List => {
+            groups.list();
+            Ok(())
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::command_executor::I3Node;
+    use crate::fixtures;
+    use crate::marks::SIZE_LOCK_MARK_PREFIX;
+    use crate::utilities::plan_balance;
+    use clap::Parser;
+
+    #[derive(Parser)]
+    struct Args {
+        #[clap(flatten)]
+        balance: BalanceCmd,
+    }
+
+    fn parse(args: &[&str]) -> clap::Result<BalanceCmd> {
+        Args::try_parse_from(std::iter::once("balance").chain(args.iter().copied()))
+            .map(|args| args.balance)
+    }
+
+    #[test]
+    fn balance_arguments() {
+        let balance_cmd = parse(&[]).unwrap();
+        assert_eq!(balance_cmd.workspace, WorkspaceRef::Focused);
+        assert!(balance_cmd.ratio.is_none());
+
+        let balance_cmd = parse(&["-w", "3: mail", "--ratio", "60,40"]).unwrap();
+        assert_eq!(
+            balance_cmd.workspace,
+            WorkspaceRef::Name("3: mail".to_string())
+        );
+        assert_eq!(balance_cmd.ratio, Some(ShareRatio::Weights(vec![60, 40])));
+
+        assert!(parse(&["--output", "DP-2", "-w", "3"]).is_err());
+        assert!(parse(&["--ratio", "60,-40"]).is_err());
+    }
+
+    #[test]
+    fn balance_keeps_locked_windows() {
+        let root_node = fixtures::load("workspaces.json");
+        let workspace = fixtures::node(&root_node, 100);
+        let ratio = parse(&["--ratio", "golden"]).unwrap().ratio.unwrap();
+
+        // Three windows side by side: the last one takes the remaining space.
+        let plan = plan_balance(workspace, &ratio);
+        assert_eq!(
+            plan.resizes,
+            [
+                (101, "resize set width 62 ppt".to_string()),
+                (102, "resize set width 24 ppt".to_string()),
+            ]
+        );
+
+        // The locked window keeps its third; the others divide the rest.
+        let mut value = serde_json::to_value(workspace).unwrap();
+        value["nodes"][0]["marks"] = serde_json::json!([format!("{}101", SIZE_LOCK_MARK_PREFIX)]);
+        let workspace: I3Node = serde_json::from_value(value).unwrap();
+
+        let plan = plan_balance(&workspace, &ratio);
+        assert_eq!(plan.resizes, [(102, "resize set width 41 ppt".to_string())]);
+        assert_eq!(plan.targets.len(), 1);
+        assert_eq!(plan.targets[0].0, 100);
+    }
+}
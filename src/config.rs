@@ -0,0 +1,566 @@
+/*
+    Copyright (C) 2022  Biagio Festa
+
+    This program is free software: you can redistribute it and/or modify
+    it under the terms of the GNU General Public License as published by
+    the Free Software Foundation, either version 3 of the License, or
+    (at your option) any later version.
+
+    This program is distributed in the hope that it will be useful,
+    but WITHOUT ANY WARRANTY; without even the implied warranty of
+    MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+    GNU General Public License for more details.
+
+    You should have received a copy of the GNU General Public License
+    along with this program.  If not, see <https://www.gnu.org/licenses/>.
+*/
+
+use crate::autolayout::outputs::OutputChangeAction;
+use crate::autolayout::placement::EscapeDirection;
+use crate::autolayout::restores::AutoRestoreRule;
+use crate::bar_proxy::BarButton;
+use crate::gaps::DynamicGaps;
+use crate::notify::NotifyLevel;
+use crate::resize::ResizeStep;
+use crate::restore_layout::Percentage;
+use crate::split_policy::LayoutMode;
+use crate::split_policy::Profile;
+use crate::utilities::config_dir;
+use crate::utilities::BorderStyle;
+use crate::utilities::RectSource;
+use crate::utilities::ShareRatio;
+use anyhow::Context;
+use anyhow::Result;
+use std::path::Path;
+use std::path::PathBuf;
+
+/// The configuration file.
+///
+/// Each section holds the options of a subcommand. Values in the file are defaults:
+/// flags given on the command line take precedence.
+#[derive(Default, serde::Deserialize, serde::Serialize)]
+#[serde(default, deny_unknown_fields, rename_all = "kebab-case")]
+pub struct Config {
+    /// Options of the `autolayout` subcommand.
+    pub autolayout: AutolayoutSection,
+
+    /// Options of the `tabmode` subcommand.
+    pub tabmode: TabModeSection,
+
+    /// Options of the `save-layout` subcommand.
+    pub save_layout: SaveLayoutSection,
+
+    /// Options of the `restore-layout` subcommand.
+    pub restore_layout: RestoreLayoutSection,
+
+    /// Options of the `float` subcommand.
+    pub float: FloatSection,
+
+    /// Options of the `gather` subcommand.
+    pub gather: GatherSection,
+
+    /// Options of the `move-workspace` subcommand.
+    pub move_workspace: MoveWorkspaceSection,
+
+    /// Options of the `resize` subcommand.
+    pub resize: ResizeSection,
+
+    /// Options of the `generate-config` subcommand.
+    pub generate_config: GenerateConfigSection,
+
+    /// Options of the `bar-proxy` subcommand.
+    pub bar_proxy: BarProxySection,
+}
+
+/// Options of the `autolayout` subcommand.
+#[derive(Default, serde::Deserialize, serde::Serialize)]
+#[serde(default, deny_unknown_fields, rename_all = "kebab-case")]
+pub struct AutolayoutSection {
+    /// The reaction when the set of active outputs changes.
+    pub on_output_change: Option<OutputChangeAction>,
+
+    /// The workspace group restored on output change.
+    pub output_group: Option<String>,
+
+    /// The threshold of the orientation of windows.
+    pub orientation_threshold: Option<f64>,
+
+    /// The rectangle considered for the orientation of windows.
+    pub rect_source: Option<RectSource>,
+
+    /// The distance of the aspect ratio from 1.0 within which windows toggle the split.
+    pub square_band: Option<f64>,
+
+    /// The mode of the workspaces not matching any profile.
+    pub mode: Option<LayoutMode>,
+
+    /// The per-workspace modes.
+    pub profiles: Vec<Profile>,
+
+    /// Manage only the windows inside a container marked with it.
+    pub opt_in_mark: Option<String>,
+
+    /// Remember the splits decided for each application.
+    pub remember: bool,
+
+    /// The columns of ultrawide workspaces split horizontally regardless of the windows.
+    pub ultrawide_columns: Option<usize>,
+
+    /// The minimum width (pixels) of a workspace considered ultrawide.
+    pub ultrawide_min_width: Option<isize>,
+
+    /// The number of columns of the workspaces in `columns` mode.
+    pub columns: Option<usize>,
+
+    /// The target aspect ratio of the cells of the workspaces in `cells` mode.
+    pub cell_aspect: Option<f64>,
+
+    /// Broadcast each action as a tick event.
+    pub announce: bool,
+
+    /// The consecutive failures after which a window is left alone.
+    pub failure_threshold: Option<u32>,
+
+    /// How long (seconds) a window is left alone after too many failures.
+    pub failure_cooldown: Option<u64>,
+
+    /// The interval (milliseconds) within which the focus of a window is not evaluated again.
+    pub min_reapply_ms: Option<u64>,
+
+    /// The delay (milliseconds) a new window must survive before being handled.
+    pub settle_new_ms: Option<u64>,
+
+    /// The interval (milliseconds) of the tree polling replacing the event subscription.
+    pub poll_interval_ms: Option<u64>,
+
+    /// The direction new windows are moved out of a stacked container.
+    pub escape_stacked: Option<EscapeDirection>,
+
+    /// The interval (minutes) between layout snapshots.
+    pub snapshot_interval: Option<u64>,
+
+    /// The snapshots kept for each workspace.
+    pub history_count: Option<usize>,
+
+    /// The age (hours) after which snapshots are dropped.
+    pub history_max_age: Option<u64>,
+
+    /// The outer gap of workspaces depending on their number of windows.
+    pub dynamic_gaps: Option<DynamicGaps>,
+
+    /// Which events are notified on the desktop.
+    pub notify: Option<NotifyLevel>,
+
+    /// Refuse to start when another auto-tiling daemon is detected.
+    pub exclusive: bool,
+
+    /// The layouts restored on workspaces when they are created.
+    pub auto_restores: Vec<AutoRestoreRule>,
+}
+
+/// Options of the `tabmode` subcommand.
+#[derive(serde::Deserialize, serde::Serialize)]
+#[serde(default, deny_unknown_fields, rename_all = "kebab-case")]
+pub struct TabModeSection {
+    /// The file where to save/load the layout.
+    pub file_layout: Option<PathBuf>,
+
+    /// Keep the previous version of the layout file.
+    pub keep_backup: bool,
+
+    /// Restore sizes of windows when leaving tab mode (default: true).
+    pub restore_sizes: Option<bool>,
+
+    /// The title format of the tabs.
+    pub title_format: Option<String>,
+
+    /// Reset the title format when leaving tab mode.
+    pub reset_title_format: bool,
+
+    /// The border style of windows in tab mode.
+    pub border_tabbed: Option<BorderStyle>,
+
+    /// The border style of windows when leaving tab mode.
+    pub border_split: Option<BorderStyle>,
+
+    /// The suffix appended to the workspace name in tab mode.
+    pub indicator_suffix: Option<String>,
+
+    /// Whether the layout file is in JSON format.
+    pub json: bool,
+}
+
+impl Default for TabModeSection {
+    fn default() -> Self {
+        Self {
+            file_layout: None,
+            keep_backup: false,
+            restore_sizes: None,
+            title_format: None,
+            reset_title_format: true,
+            border_tabbed: None,
+            border_split: None,
+            indicator_suffix: None,
+            json: false,
+        }
+    }
+}
+
+/// Options of the `save-layout` subcommand.
+#[derive(Default, serde::Deserialize, serde::Serialize)]
+#[serde(default, deny_unknown_fields, rename_all = "kebab-case")]
+pub struct SaveLayoutSection {
+    /// Format the output with JSON.
+    pub json: bool,
+
+    /// Keep the previous version of the output file.
+    pub keep_backup: bool,
+
+    /// Fail if some node cannot be saved.
+    pub strict: bool,
+
+    /// Save layouts which can be restored on another machine.
+    pub portable: bool,
+}
+
+/// Options of the `restore-layout` subcommand.
+#[derive(Default, serde::Deserialize, serde::Serialize)]
+#[serde(default, deny_unknown_fields, rename_all = "kebab-case")]
+pub struct RestoreLayoutSection {
+    /// Whether the input is JSON format.
+    pub json: bool,
+
+    /// Whether to attempt to restore sizes of windows.
+    pub restore_sizes: bool,
+
+    /// Collapse the containers with a single child before restoring.
+    pub simplify: bool,
+
+    /// Do not recreate the empty containers of the layout.
+    pub skip_empty: bool,
+
+    /// The menu used to pick a layout of the store.
+    pub picker: Option<String>,
+
+    /// The tolerance (percentage points) of the shares after restoring sizes.
+    pub tolerance: Option<f64>,
+
+    /// Ask for confirmation before restoring.
+    pub confirm: bool,
+
+    /// Saved sizes (pixels) below this are not restored.
+    pub min_restored_size: Option<isize>,
+
+    /// The maximum change of a dimension by a resize, as a percentage of its current value.
+    pub max_delta: Option<Percentage>,
+}
+
+/// Options of the `float` subcommand.
+#[derive(Default, serde::Deserialize, serde::Serialize)]
+#[serde(default, deny_unknown_fields, rename_all = "kebab-case")]
+pub struct FloatSection {
+    /// The distance (pixels) kept from the output edges.
+    pub margin: isize,
+}
+
+/// Options of the `gather` subcommand.
+#[derive(Default, serde::Deserialize, serde::Serialize)]
+#[serde(default, deny_unknown_fields, rename_all = "kebab-case")]
+pub struct GatherSection {
+    /// Balance the target workspace afterwards.
+    pub balance: bool,
+
+    /// How the balance divides the space of each container.
+    pub ratio: Option<ShareRatio>,
+
+    /// The tolerance (percentage points) of the shares after balancing.
+    pub tolerance: Option<f64>,
+}
+
+/// Options of the `move-workspace` subcommand.
+#[derive(Default, serde::Deserialize, serde::Serialize)]
+#[serde(default, deny_unknown_fields, rename_all = "kebab-case")]
+pub struct MoveWorkspaceSection {
+    /// The order of the outputs cycled by `--next` and `--prev`.
+    pub order: Vec<String>,
+
+    /// Balance the workspace afterwards.
+    pub rebalance: bool,
+
+    /// The tolerance (percentage points) of the shares after balancing.
+    pub tolerance: Option<f64>,
+}
+
+/// Options of the `resize` subcommand.
+#[derive(serde::Deserialize, serde::Serialize)]
+#[serde(default, deny_unknown_fields, rename_all = "kebab-case")]
+pub struct ResizeSection {
+    /// The amount of the resize.
+    pub step: ResizeStep,
+}
+
+impl Default for ResizeSection {
+    fn default() -> Self {
+        Self {
+            step: ResizeStep::Ppt(5),
+        }
+    }
+}
+
+/// Options of the `generate-config` subcommand.
+#[derive(serde::Deserialize, serde::Serialize)]
+#[serde(default, deny_unknown_fields, rename_all = "kebab-case")]
+pub struct GenerateConfigSection {
+    /// The modifier key which replaces `$mod` in the prefix.
+    pub modifier: String,
+
+    /// The key combination which enters the i3-autolayout binding mode.
+    pub prefix: String,
+
+    /// The directory where tabmode saves the layouts (one file per workspace).
+    pub layout_dir: String,
+}
+
+impl Default for GenerateConfigSection {
+    fn default() -> Self {
+        Self {
+            modifier: "Mod4".to_string(),
+            prefix: "$mod+t".to_string(),
+            layout_dir: "~/.cache/i3-autolayout".to_string(),
+        }
+    }
+}
+
+/// Options of the `bar-proxy` subcommand.
+#[derive(serde::Deserialize, serde::Serialize)]
+#[serde(default, deny_unknown_fields, rename_all = "kebab-case")]
+pub struct BarProxySection {
+    /// The name of the blocks which represent workspaces.
+    pub block_name: String,
+
+    /// The subcommands bound to mouse buttons.
+    pub buttons: Vec<BarButton>,
+}
+
+impl Default for BarProxySection {
+    fn default() -> Self {
+        Self {
+            block_name: "workspace".to_string(),
+            buttons: vec![],
+        }
+    }
+}
+
+impl Config {
+    const FILE_NAME: &'static str = "config.toml";
+
+    /// Load the configuration.
+    ///
+    /// If `path` is `None` the default location (`$XDG_CONFIG_HOME/i3-autolayout/config.toml`)
+    /// is used, and a missing file means default configuration. An explicit `path` must exist.
+    pub fn load(path: Option<&Path>) -> Result<Self> {
+        let path = match path {
+            Some(path) => path.to_path_buf(),
+            None => {
+                let path = config_dir()?.join(Self::FILE_NAME);
+
+                if !path.exists() {
+                    return Ok(Self::default());
+                }
+
+                path
+            }
+        };
+
+        let content = std::fs::read_to_string(&path)
+            .with_context(|| format!("Cannot read config file '{}'", path.display()))?;
+
+        Self::parse(&content).with_context(|| format!("Invalid config file '{}'", path.display()))
+    }
+
+    /// Parse the configuration from its TOML content.
+    ///
+    /// Errors name the offending key and its line.
+    pub fn parse(content: &str) -> Result<Self> {
+        toml::from_str(content).map_err(|error| {
+            let message = error.to_string();
+
+            // Unknown keys and values parsed from strings are reported at the start of
+            // their table: point at the key itself.
+            let Some(line) = error_key(&message).and_then(|key| key_line(content, &key)) else {
+                return anyhow::anyhow!("{}", message);
+            };
+
+            let message = message
+                .rsplit_once(" at line ")
+                .map_or(message.as_str(), |(message, _)| message);
+
+            anyhow::anyhow!("{} at line {}", message, line)
+        })
+    }
+
+    /// Render the configuration as TOML.
+    pub fn to_toml(&self) -> Result<String> {
+        toml::to_string(self).context("Cannot serialize the configuration")
+    }
+}
+
+/// The path of the key a TOML error is about (e.g., `["autolayout", "mode"]`).
+fn error_key(message: &str) -> Option<Vec<&str>> {
+    let quoted = |prefix: &str| {
+        let start = message.find(prefix)? + prefix.len();
+        let end = message[start..].find('`')?;
+        Some(&message[start..start + end])
+    };
+
+    let mut key = quoted("for key `")
+        .map(|table| table.split('.').collect::<Vec<_>>())
+        .unwrap_or_default();
+    key.extend(quoted("unknown field `"));
+
+    (!key.is_empty()).then_some(key)
+}
+
+/// The line (from 1) where a key is defined, as a table header or as `key = value`.
+fn key_line(content: &str, key: &[&str]) -> Option<usize> {
+    let (name, table) = key.split_last()?;
+    let (full, table) = (key.join("."), table.join("."));
+    let mut current = String::new();
+
+    for (index, line) in content.lines().enumerate() {
+        let line = line.trim();
+
+        if let Some(header) = line.strip_prefix('[') {
+            let header = header.trim_start_matches('[');
+            let header = header.split(']').next().unwrap_or_default().trim();
+
+            if header == full {
+                return Some(index + 1);
+            }
+
+            current = header.to_string();
+            continue;
+        }
+
+        let Some((defined, _)) = line.split_once('=') else {
+            continue;
+        };
+
+        if current == table && defined.trim().trim_matches('"') == *name {
+            return Some(index + 1);
+        }
+    }
+
+    None
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::fixtures::TempDir;
+
+    #[test]
+    fn empty_file_is_default() {
+        let config = Config::parse("").unwrap();
+
+        assert!(config.autolayout.orientation_threshold.is_none());
+        assert!(config.tabmode.reset_title_format);
+        assert_eq!(
+            config.to_toml().unwrap(),
+            Config::default().to_toml().unwrap()
+        );
+    }
+
+    #[test]
+    fn parse_sections() {
+        let config = Config::parse(
+            r#"
+            [autolayout]
+            orientation-threshold = 1.5
+            mode = "columns"
+            profiles = ["music:off", "dev*:cells"]
+            remember = true
+
+            [tabmode]
+            keep-backup = true
+            restore-sizes = false
+
+            [restore-layout]
+            simplify = true
+            "#,
+        )
+        .unwrap();
+
+        assert_eq!(config.autolayout.orientation_threshold, Some(1.5));
+        assert_eq!(config.autolayout.mode, Some(LayoutMode::Columns));
+        assert_eq!(config.autolayout.profiles.len(), 2);
+        assert_eq!(config.autolayout.profiles[1].pattern(), "dev*");
+        assert!(config.autolayout.remember);
+        assert!(config.tabmode.keep_backup);
+        assert_eq!(config.tabmode.restore_sizes, Some(false));
+        assert!(config.restore_layout.simplify);
+
+        // What is rendered parses back the same.
+        let rendered = config.to_toml().unwrap();
+        assert_eq!(
+            Config::parse(&rendered).unwrap().to_toml().unwrap(),
+            rendered
+        );
+    }
+
+    #[test]
+    fn errors_name_key_and_line() {
+        let error = Config::parse("[autolayout]\nremember = true\norientation-treshold = 1.5\n")
+            .err()
+            .unwrap()
+            .to_string();
+        assert!(error.contains("orientation-treshold"), "{}", error);
+        assert!(error.contains("line 3"), "{}", error);
+
+        let error = Config::parse("[tabmode]\n\nkeep-backup = \"yes\"\n")
+            .err()
+            .unwrap()
+            .to_string();
+        assert!(error.contains("keep-backup"), "{}", error);
+        assert!(error.contains("line 3"), "{}", error);
+
+        let error = Config::parse("[autolayout]\nprofiles = [\"music:loud\"]\n")
+            .err()
+            .unwrap()
+            .to_string();
+        assert!(error.contains("'columns', 'cells'"), "{}", error);
+        assert!(error.contains("line 2"), "{}", error);
+
+        let error = Config::parse("[save-layout]\n\n[save-layouts]\n")
+            .err()
+            .unwrap()
+            .to_string();
+        assert!(error.contains("save-layouts"), "{}", error);
+        assert!(error.contains("line 3"), "{}", error);
+
+        // The same key in another table is not the offending one.
+        let error = Config::parse("[tabmode]\njson = true\n[autolayout]\njson = true\n")
+            .err()
+            .unwrap()
+            .to_string();
+        assert!(error.ends_with("at line 4"), "{}", error);
+    }
+
+    #[test]
+    fn load_explicit_path() {
+        let dir = TempDir::new("config");
+        let path = dir.path().join("config.toml");
+
+        // An explicit path must exist.
+        assert!(Config::load(Some(&path)).is_err());
+
+        std::fs::write(&path, "[autolayout]\nmode = \"off\"\n").unwrap();
+        let config = Config::load(Some(&path)).unwrap();
+        assert_eq!(config.autolayout.mode, Some(LayoutMode::Off));
+
+        std::fs::write(&path, "[autolayout]\nmode = 1\n").unwrap();
+        let error = format!("{:#}", Config::load(Some(&path)).err().unwrap());
+        assert!(error.starts_with("Invalid config file '"), "{}", error);
+        assert!(error.contains("mode"), "{}", error);
+    }
+}
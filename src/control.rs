@@ -0,0 +1,546 @@
+/*
+    Copyright (C) 2022  Biagio Festa
+
+    This program is free software: you can redistribute it and/or modify
+    it under the terms of the GNU General Public License as published by
+    the Free Software Foundation, either version 3 of the License, or
+    (at your option) any later version.
+
+    This program is distributed in the hope that it will be useful,
+    but WITHOUT ANY WARRANTY; without even the implied warranty of
+    MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+    GNU General Public License for more details.
+
+    You should have received a copy of the GNU General Public License
+    along with this program.  If not, see <https://www.gnu.org/licenses/>.
+*/
+
+use crate::command_executor::CommandExecutor;
+use crate::event_listener::EventListener;
+use crate::event_listener::EventSubscribe;
+use crate::unmanaged::ManageState;
+use crate::utilities::Split;
+use anyhow::anyhow;
+use anyhow::Context;
+use anyhow::Result;
+use i3_ipc::event::Event;
+use std::sync::atomic::AtomicUsize;
+use std::sync::atomic::Ordering;
+use std::time::Duration;
+use std::time::Instant;
+
+/// The prefix of tick payloads addressed to the autolayout service.
+const TICK_PREFIX: &str = "i3-autolayout:";
+
+/// The prefix of tick payloads replying to a query.
+const REPLY_PREFIX: &str = "i3-autolayout-reply:";
+
+/// How long a query waits for the reply of the service.
+const REPLY_TIMEOUT: Duration = Duration::from_secs(1);
+
+/// A command for the running autolayout service.
+///
+/// Commands are delivered as i3 tick events, so any i3 client (e.g., `i3-msg -t send_tick`)
+/// can control the service. When the service is not running, ticks are simply ignored.
+#[derive(Clone, Debug, clap::Subcommand)]
+pub enum ControlCommand {
+    /// Skip the next reaction to an output change.
+    #[clap(name = "skip-output-change")]
+    SkipOutputChange,
+
+    /// Switch the focused output to the workspace it displayed before.
+    #[clap(name = "back-and-forth")]
+    BackAndForth,
+
+    /// Print the size of the state held by the service (for debugging).
+    #[clap(name = "dump-state")]
+    DumpState,
+
+    /// Print the time spent by the service on events: counters and latency histograms of
+    /// the whole handling, of queries, of commands and of decision logic.
+    #[clap(name = "stats")]
+    Stats,
+
+    /// Print the status of the running service: the event counters, the windows left alone
+    /// after failed splits and the unmanaged workspaces (with '--verbose', also the time
+    /// spent on events and the state). Sending SIGUSR2 to the service prints the
+    /// verbose status on its standard error.
+    #[clap(name = "status")]
+    Status {
+        /// Include the time spent on events and the state.
+        #[clap(short, long, action)]
+        verbose: bool,
+    },
+
+    /// Print the layout mode of each workspace and the profile it comes from.
+    #[clap(name = "profiles")]
+    Profiles,
+
+    /// Pause the actions of the service on a workspace until resumed (or a timeout expires).
+    #[clap(name = "suspend")]
+    Suspend {
+        /// The token which resumes the service.
+        token: String,
+
+        /// The workspace name.
+        workspace: String,
+    },
+
+    /// Resume the actions paused with a token.
+    #[clap(name = "resume")]
+    Resume {
+        /// The token given when suspending.
+        token: String,
+    },
+
+    /// Forget the splits remembered per application (see 'autolayout --remember').
+    #[clap(name = "forget")]
+    Forget,
+
+    /// Print the process id of the running service (which claims the layouts).
+    #[clap(name = "claim")]
+    Claim,
+
+    /// Hand a workspace back to manual control (off) or to the service (on), until
+    /// changed again (also across restarts). 'ctl status' lists the unmanaged workspaces.
+    #[clap(name = "manage")]
+    Manage {
+        /// The workspace (number, name or 'focused').
+        workspace: String,
+
+        /// Whether the service manages the workspace.
+        #[clap(value_enum)]
+        state: ManageState,
+    },
+}
+
+/// A suspension of the autolayout service on a workspace.
+///
+/// While it is alive, the service (if running) does not act on the workspace, so that
+/// multi-step operations (e.g., tabmode) do not interleave with its commands.
+/// The service is resumed when dropped.
+pub struct SuspendGuard {
+    /// The connection of the suspended operation (shared), used to resume the service.
+    command_executor: CommandExecutor,
+
+    /// The token of the suspension.
+    token: String,
+}
+
+impl SuspendGuard {
+    /// Suspend the service on a workspace, through the connection of the operation
+    /// (shared).
+    pub fn acquire(command_executor: &CommandExecutor, workspace: &str) -> Result<Self> {
+        static COUNTER: AtomicUsize = AtomicUsize::new(0);
+
+        let mut command_executor = command_executor.share();
+        let token = format!(
+            "{}-{}",
+            std::process::id(),
+            COUNTER.fetch_add(1, Ordering::Relaxed)
+        );
+
+        ControlCommand::Suspend {
+            token: token.clone(),
+            workspace: workspace.to_string(),
+        }
+        .send(&mut command_executor)?;
+
+        Ok(Self {
+            command_executor,
+            token,
+        })
+    }
+}
+
+impl Drop for SuspendGuard {
+    fn drop(&mut self) {
+        let command = ControlCommand::Resume {
+            token: std::mem::take(&mut self.token),
+        };
+
+        if let Err(error) = command.send(&mut self.command_executor) {
+            eprintln!(
+                "[WARN]: Failure to resume the autolayout service: {:?}",
+                error
+            );
+        }
+    }
+}
+
+/// An action applied by the autolayout service.
+#[derive(serde::Serialize)]
+#[serde(tag = "action", rename_all = "kebab-case")]
+pub enum Action {
+    /// The split of a window has been set.
+    Split {
+        /// The split direction.
+        dir: Split,
+
+        /// The window.
+        con_id: usize,
+
+        /// The workspace name.
+        workspace: String,
+    },
+
+    /// A window opened after a rule split has been resized.
+    Resize {
+        /// The window.
+        con_id: usize,
+
+        /// The size (percentage points) of the window.
+        ppt: u32,
+    },
+
+    /// The focused output has been switched to the workspace displayed before.
+    BackAndForth {
+        /// The workspace name.
+        workspace: String,
+    },
+
+    /// The output group has been restored after an output change.
+    RestoreOutputGroup {
+        /// The group name.
+        group: String,
+    },
+
+    /// The saved layout of a new workspace has been restored.
+    AutoRestore {
+        /// The workspace name.
+        workspace: String,
+    },
+}
+
+/// The announcement of an action, broadcast as tick payload.
+///
+/// The payload is the JSON object of the action together with `"source": "i3-autolayout"`,
+/// e.g., `{"source":"i3-autolayout","action":"split","dir":"vertical","con_id":123,"workspace":"3"}`.
+#[derive(serde::Serialize)]
+pub struct Announcement {
+    /// Always `i3-autolayout`.
+    source: &'static str,
+
+    /// The action.
+    #[serde(flatten)]
+    action: Action,
+}
+
+impl Announcement {
+    /// Broadcast the action to all the i3 clients subscribed to tick events.
+    pub fn send(action: Action, command_executor: &mut CommandExecutor) -> Result<()> {
+        let announcement = Self {
+            source: "i3-autolayout",
+            action,
+        };
+
+        let payload =
+            serde_json::to_string(&announcement).context("Cannot serialize the announcement")?;
+
+        command_executor
+            .send_tick(payload)
+            .context("Cannot send the announcement")
+    }
+}
+
+/// A command received by the autolayout service.
+pub struct ControlRequest {
+    /// The command.
+    pub command: ControlCommand,
+
+    /// The identifier the reply is addressed to (only for queries).
+    reply_id: Option<String>,
+}
+
+impl ControlRequest {
+    /// Parse a tick payload.
+    ///
+    /// Queries are delivered as `i3-autolayout:<command>?<reply-id>`.
+    /// It returns `None` if the payload is not addressed to the service or it is unknown.
+    pub fn from_tick_payload(payload: &str) -> Option<Self> {
+        let payload = payload.strip_prefix(TICK_PREFIX)?;
+
+        if let Some(command) = ControlCommand::parse(payload) {
+            return Some(Self {
+                command,
+                reply_id: None,
+            });
+        }
+
+        let (command, reply_id) = payload.split_once('?')?;
+
+        Some(Self {
+            command: ControlCommand::parse(command).filter(ControlCommand::is_query)?,
+            reply_id: Some(reply_id.to_string()),
+        })
+    }
+
+    /// Reply to the query.
+    ///
+    /// It does nothing if the sender does not wait for a reply.
+    pub fn reply(&self, command_executor: &mut CommandExecutor, text: &str) -> Result<()> {
+        let Some(reply_id) = &self.reply_id else {
+            return Ok(());
+        };
+
+        command_executor
+            .send_tick(format!("{}{}:{}", REPLY_PREFIX, reply_id, text))
+            .context("Cannot send the reply")
+    }
+}
+
+impl ControlCommand {
+    /// Parse a command (its name followed by its `:`-separated arguments).
+    fn parse(command: &str) -> Option<Self> {
+        match command {
+            "skip-output-change" => Some(Self::SkipOutputChange),
+            "back-and-forth" => Some(Self::BackAndForth),
+            "dump-state" => Some(Self::DumpState),
+            "stats" => Some(Self::Stats),
+            "profiles" => Some(Self::Profiles),
+            "status" => Some(Self::Status { verbose: false }),
+            "status:verbose" => Some(Self::Status { verbose: true }),
+            "forget" => Some(Self::Forget),
+            "claim" => Some(Self::Claim),
+            _ => {
+                if let Some(arguments) = command.strip_prefix("suspend:") {
+                    let (token, workspace) = arguments.split_once(':')?;
+
+                    Some(Self::Suspend {
+                        token: token.to_string(),
+                        workspace: workspace.to_string(),
+                    })
+                } else if let Some(arguments) = command.strip_prefix("manage:") {
+                    let (state, workspace) = arguments.split_once(':')?;
+
+                    Some(Self::Manage {
+                        workspace: workspace.to_string(),
+                        state: ManageState::parse(state)?,
+                    })
+                } else {
+                    command.strip_prefix("resume:").map(|token| Self::Resume {
+                        token: token.to_string(),
+                    })
+                }
+            }
+        }
+    }
+
+    /// The command as delivered in the tick payload.
+    fn to_payload(&self) -> String {
+        match self {
+            Self::SkipOutputChange => "skip-output-change".to_string(),
+            Self::BackAndForth => "back-and-forth".to_string(),
+            Self::DumpState => "dump-state".to_string(),
+            Self::Stats => "stats".to_string(),
+            Self::Profiles => "profiles".to_string(),
+            Self::Status { verbose: false } => "status".to_string(),
+            Self::Status { verbose: true } => "status:verbose".to_string(),
+            Self::Forget => "forget".to_string(),
+            Self::Claim => "claim".to_string(),
+            Self::Suspend { token, workspace } => format!("suspend:{}:{}", token, workspace),
+            Self::Resume { token } => format!("resume:{}", token),
+            Self::Manage { workspace, state } => {
+                format!("manage:{}:{}", state.as_str(), workspace)
+            }
+        }
+    }
+
+    /// Whether the command expects a reply from the service.
+    fn is_query(&self) -> bool {
+        matches!(
+            self,
+            Self::DumpState | Self::Stats | Self::Status { .. } | Self::Profiles | Self::Claim
+        )
+    }
+
+    /// Send the command to the autolayout service.
+    ///
+    /// For queries, it waits for the reply of the service and returns it.
+    pub fn send(&self, command_executor: &mut CommandExecutor) -> Result<Option<String>> {
+        if !self.is_query() {
+            command_executor
+                .send_tick(format!("{}{}", TICK_PREFIX, self.to_payload()))
+                .context("Cannot send the command to the autolayout service")?;
+
+            return Ok(None);
+        }
+
+        self.query(command_executor, REPLY_TIMEOUT)?
+            .map(Some)
+            .ok_or_else(|| anyhow!("No reply from the autolayout service (is it running?)"))
+    }
+
+    /// Send the query to the autolayout service and wait at most `timeout` for the reply.
+    ///
+    /// It returns `None` if no service replies.
+    pub fn query(
+        &self,
+        command_executor: &mut CommandExecutor,
+        timeout: Duration,
+    ) -> Result<Option<String>> {
+        let mut event_listener = EventListener::new(&[EventSubscribe::Tick])?;
+        let reply_id = std::process::id().to_string();
+        let reply_prefix = format!("{}{}:", REPLY_PREFIX, reply_id);
+
+        command_executor
+            .send_tick(format!("{}{}?{}", TICK_PREFIX, self.to_payload(), reply_id))
+            .context("Cannot send the command to the autolayout service")?;
+
+        let deadline = Instant::now() + timeout;
+
+        loop {
+            let timeout = deadline.saturating_duration_since(Instant::now());
+
+            match event_listener.receive_event_timeout(timeout)? {
+                Some(Event::Tick(tick_data)) => {
+                    if let Some(reply) = tick_data.payload.strip_prefix(&reply_prefix) {
+                        return Ok(Some(reply.to_string()));
+                    }
+                }
+                Some(_) => {}
+                None => return Ok(None),
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn request(payload: &str) -> Option<(String, Option<String>)> {
+        ControlRequest::from_tick_payload(payload)
+            .map(|request| (request.command.to_payload(), request.reply_id))
+    }
+
+    #[test]
+    fn suspend_and_resume_payloads() {
+        let suspend = ControlCommand::Suspend {
+            token: "4242-0".to_string(),
+            workspace: "3: mail".to_string(),
+        };
+        assert_eq!(suspend.to_payload(), "suspend:4242-0:3: mail");
+
+        // The workspace name may hold colons: only the token is split off.
+        let Some(ControlCommand::Suspend { token, workspace }) =
+            ControlCommand::parse(&suspend.to_payload())
+        else {
+            panic!("Expected a suspension");
+        };
+        assert_eq!(token, "4242-0");
+        assert_eq!(workspace, "3: mail");
+
+        let Some(ControlCommand::Resume { token }) = ControlCommand::parse("resume:4242-0") else {
+            panic!("Expected a resume");
+        };
+        assert_eq!(token, "4242-0");
+
+        assert!(ControlCommand::parse("suspend:4242-0").is_none());
+    }
+
+    #[test]
+    fn payloads_round_trip() {
+        for payload in [
+            "skip-output-change",
+            "back-and-forth",
+            "dump-state",
+            "stats",
+            "status",
+            "status:verbose",
+            "profiles",
+            "forget",
+            "claim",
+            "suspend:1-2:web",
+            "resume:1-2",
+            "manage:off:9: chat",
+            "manage:toggle:focused",
+        ] {
+            let command = ControlCommand::parse(payload).unwrap();
+            assert_eq!(command.to_payload(), payload);
+        }
+
+        for unknown in [
+            "",
+            "suspend",
+            "manage:maybe:1",
+            "dump_state",
+            "resume",
+            "status:quiet",
+        ] {
+            assert!(ControlCommand::parse(unknown).is_none(), "{}", unknown);
+        }
+    }
+
+    #[test]
+    fn requests_from_tick_payloads() {
+        assert_eq!(
+            request("i3-autolayout:suspend:1-2:web"),
+            Some(("suspend:1-2:web".to_string(), None))
+        );
+        assert_eq!(
+            request("i3-autolayout:dump-state?777"),
+            Some(("dump-state".to_string(), Some("777".to_string())))
+        );
+
+        // Replies and other clients' ticks are not requests.
+        assert_eq!(request("i3-autolayout-reply:777:ok"), None);
+        assert_eq!(request("polybar:refresh"), None);
+
+        // Only queries carry a reply id.
+        assert_eq!(request("i3-autolayout:forget?777"), None);
+    }
+
+    fn announcement(action: Action) -> serde_json::Value {
+        serde_json::to_value(Announcement {
+            source: "i3-autolayout",
+            action,
+        })
+        .unwrap()
+    }
+
+    #[test]
+    fn announcement_payloads() {
+        assert_eq!(
+            serde_json::to_string(&Announcement {
+                source: "i3-autolayout",
+                action: Action::Split {
+                    dir: Split::Vertical,
+                    con_id: 123,
+                    workspace: "3".to_string(),
+                },
+            })
+            .unwrap(),
+            r#"{"source":"i3-autolayout","action":"split","dir":"vertical","con_id":123,"workspace":"3"}"#
+        );
+
+        for (action, mut expected) in [
+            (
+                Action::Resize { con_id: 7, ppt: 30 },
+                serde_json::json!({ "action": "resize", "con_id": 7, "ppt": 30 }),
+            ),
+            (
+                Action::BackAndForth {
+                    workspace: "9: chat".to_string(),
+                },
+                serde_json::json!({ "action": "back-and-forth", "workspace": "9: chat" }),
+            ),
+            (
+                Action::RestoreOutputGroup {
+                    group: "docked".to_string(),
+                },
+                serde_json::json!({ "action": "restore-output-group", "group": "docked" }),
+            ),
+            (
+                Action::AutoRestore {
+                    workspace: "web".to_string(),
+                },
+                serde_json::json!({ "action": "auto-restore", "workspace": "web" }),
+            ),
+        ] {
+            expected["source"] = "i3-autolayout".into();
+
+            assert_eq!(announcement(action), expected);
+        }
+    }
+}
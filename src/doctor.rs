@@ -0,0 +1,326 @@
+/*
+    Copyright (C) 2022  Biagio Festa
+
+    This program is free software: you can redistribute it and/or modify
+    it under the terms of the GNU General Public License as published by
+    the Free Software Foundation, either version 3 of the License, or
+    (at your option) any later version.
+
+    This program is distributed in the hope that it will be useful,
+    but WITHOUT ANY WARRANTY; without even the implied warranty of
+    MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+    GNU General Public License for more details.
+
+    You should have received a copy of the GNU General Public License
+    along with this program.  If not, see <https://www.gnu.org/licenses/>.
+*/
+
+use crate::command_executor::CommandExecutor;
+use crate::command_executor::RootNode;
+use crate::marks::owned_mark;
+use crate::park::Park;
+use crate::scratchpad::ScratchpadCycle;
+use crate::tabmode::strip_indicator;
+use crate::tabmode::TabMode;
+use crate::undo::UndoStack;
+use crate::urgent::UrgentJump;
+use crate::utilities::find_workspaces;
+use crate::utilities::node_marks;
+use crate::utilities::quote;
+use crate::utilities::quote_ws_name;
+use anyhow::Context;
+use anyhow::Result;
+use std::fmt::Display;
+use std::path::PathBuf;
+
+/// Something left behind by an interrupted action.
+struct Leftover {
+    /// What has been found, and why it is a leftover.
+    description: String,
+
+    /// How to repair it.
+    repair: Repair,
+}
+
+/// The repair of a leftover.
+enum Repair {
+    /// Remove a mark.
+    Unmark(String),
+
+    /// Rename a workspace.
+    RenameWorkspace { from: String, to: String },
+
+    /// Remove a state file (or directory).
+    Remove(PathBuf),
+}
+
+impl Display for Repair {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Self::Unmark(mark) => write!(f, "unmark '{}'", mark),
+            Self::RenameWorkspace { from, to } => write!(f, "rename '{}' to '{}'", from, to),
+            Self::Remove(path) => write!(f, "remove '{}'", path.display()),
+        }
+    }
+}
+
+/// Doctor executor.
+///
+/// It represents a one-shot executor which finds (and optionally repairs) what
+/// interrupted actions left behind: temporary marks, tab-mode indicators in workspace
+/// names and stale state files. Running it again after a repair finds nothing.
+pub struct Doctor {
+    /// Command executor.
+    command_executor: CommandExecutor,
+
+    /// The tab-mode indicator suffix of workspace names (see `TabModeOptions`).
+    indicator_suffix: Option<String>,
+}
+
+impl Doctor {
+    /// A new doctor executor.
+    pub fn new(command_executor: CommandExecutor) -> Self {
+        Self {
+            command_executor,
+            indicator_suffix: None,
+        }
+    }
+
+    /// Look for tab-mode indicators on workspaces which are not in tab mode.
+    pub fn with_indicator_suffix(mut self, indicator_suffix: Option<String>) -> Self {
+        self.indicator_suffix = indicator_suffix;
+        self
+    }
+
+    /// Execute the action.
+    ///
+    /// Each leftover is reported with its repair, which is applied only with `fix`.
+    pub fn execute(mut self, fix: bool) -> Result<()> {
+        let root_node = self.command_executor.query_root_node()?;
+
+        let mut leftovers = Self::leftover_marks(&root_node);
+        leftovers.extend(Self::leftover_indicators(
+            &root_node,
+            self.indicator_suffix.as_deref(),
+        ));
+        leftovers.extend(Self::leftover_files(&root_node)?);
+
+        if leftovers.is_empty() {
+            println!("Nothing to repair");
+            return Ok(());
+        }
+
+        for leftover in &leftovers {
+            println!("{}", leftover.description);
+
+            if !fix {
+                println!("  Repair: {}", leftover.repair);
+            } else if let Err(error) = self.repair(&leftover.repair) {
+                eprintln!("[WARN]: Cannot {}: {:#}", leftover.repair, error);
+            } else {
+                println!("  Repaired: {}", leftover.repair);
+            }
+        }
+
+        if !fix {
+            println!("Run with '--fix' to repair");
+        }
+
+        Ok(())
+    }
+
+    /// The i3-autolayout marks which should not outlive the action setting them.
+    fn leftover_marks(root_node: &RootNode) -> Vec<Leftover> {
+        let mut leftovers = vec![];
+        let mut dfs = vec![root_node.node()];
+
+        while let Some(current) = dfs.pop() {
+            for mark in node_marks(current) {
+                let Some(owned) = owned_mark(&mark) else {
+                    continue;
+                };
+
+                if !owned.persistent {
+                    leftovers.push(Leftover {
+                        description: format!(
+                            "Mark '{}' on node '{}': left by an interrupted {}",
+                            mark, current.id, owned.owner
+                        ),
+                        repair: Repair::Unmark(mark),
+                    });
+                }
+            }
+
+            dfs.extend(current.nodes.iter().rev());
+            dfs.extend(current.floating_nodes.iter().rev());
+        }
+
+        leftovers
+    }
+
+    /// The workspaces carrying the tab-mode indicator without being in tab mode.
+    fn leftover_indicators(root_node: &RootNode, suffix: Option<&str>) -> Vec<Leftover> {
+        let Some(suffix) = suffix else {
+            return vec![];
+        };
+
+        find_workspaces(root_node)
+            .into_iter()
+            .filter(|workspace| !TabMode::is_tabmode(workspace))
+            .filter_map(|workspace| {
+                let name = workspace.name.clone()?;
+                let stripped = strip_indicator(&name, suffix);
+
+                (stripped != name).then(|| Leftover {
+                    description: format!(
+                        "Workspace '{}': tab-mode indicator, but not in tab mode",
+                        name
+                    ),
+                    repair: Repair::RenameWorkspace {
+                        from: name,
+                        to: stripped,
+                    },
+                })
+            })
+            .collect()
+    }
+
+    /// The state files which refer to windows or workspaces which no longer exist.
+    fn leftover_files(root_node: &RootNode) -> Result<Vec<Leftover>> {
+        let sources = [
+            (
+                "Park state",
+                "the parked windows have been closed",
+                Park::leftovers(root_node)?,
+            ),
+            (
+                "Undo snapshot",
+                "incomplete, or its workspaces no longer exist",
+                UndoStack::open()?.leftovers(root_node)?,
+            ),
+            (
+                "Scratchpad state",
+                "the remembered windows have been closed",
+                ScratchpadCycle::leftovers(root_node)?,
+            ),
+            (
+                "Urgent-jump state",
+                "the remembered window has been closed",
+                UrgentJump::leftovers(root_node)?,
+            ),
+        ];
+
+        Ok(sources
+            .into_iter()
+            .flat_map(|(what, why, paths)| {
+                paths.into_iter().map(move |path| Leftover {
+                    description: format!("{} '{}': {}", what, path.display(), why),
+                    repair: Repair::Remove(path),
+                })
+            })
+            .collect())
+    }
+
+    /// Apply a repair.
+    ///
+    /// Repairs of leftovers already gone (e.g., repaired by a concurrent run) succeed.
+    fn repair(&mut self, repair: &Repair) -> Result<()> {
+        match repair {
+            Repair::Unmark(mark) => self.command_executor.run(format!("unmark {}", quote(mark))),
+
+            Repair::RenameWorkspace { from, to } => self.command_executor.run(format!(
+                "rename workspace {} to {}",
+                quote_ws_name(from)?,
+                quote_ws_name(to)?
+            )),
+
+            Repair::Remove(path) => {
+                let result = if path.is_dir() {
+                    std::fs::remove_dir_all(path)
+                } else {
+                    std::fs::remove_file(path)
+                };
+
+                match result {
+                    Err(error) if error.kind() != std::io::ErrorKind::NotFound => {
+                        Err(error).with_context(|| format!("Cannot remove '{}'", path.display()))
+                    }
+                    _ => Ok(()),
+                }
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::fixtures;
+    use crate::marks::restore_container_mark;
+    use crate::marks::size_lock_mark;
+    use crate::marks::NORMALIZE_MARK;
+
+    fn set_marks(root_node: &RootNode, node_id: usize, marks: &[String]) -> RootNode {
+        fixtures::edit(root_node, node_id, |node| {
+            node.marks = serde_json::from_value(serde_json::json!(marks)).unwrap();
+        })
+    }
+
+    fn repairs(leftovers: &[Leftover]) -> Vec<String> {
+        leftovers
+            .iter()
+            .map(|leftover| leftover.repair.to_string())
+            .collect()
+    }
+
+    #[test]
+    fn persistent_marks_are_not_leftovers() {
+        let root_node = fixtures::load("workspaces.json");
+        let root_node = set_marks(&root_node, 211, &[size_lock_mark(211)]);
+
+        // The tabmode mark of workspace '3 []' and the size lock stay.
+        assert!(Doctor::leftover_marks(&root_node).is_empty());
+    }
+
+    #[test]
+    fn temporary_marks_are_leftovers() {
+        let root_node = fixtures::load("workspaces.json");
+        let root_node = set_marks(&root_node, 100, &[NORMALIZE_MARK.to_string()]);
+        let root_node = set_marks(
+            &root_node,
+            222,
+            &["user mark".to_string(), restore_container_mark(7)],
+        );
+
+        let leftovers = Doctor::leftover_marks(&root_node);
+        assert_eq!(
+            repairs(&leftovers),
+            [
+                format!("unmark '{}'", NORMALIZE_MARK),
+                format!("unmark '{}'", restore_container_mark(7)),
+            ]
+        );
+        assert!(leftovers[1]
+            .description
+            .starts_with("Mark '__i3-autolayout__restore_7' on node '222'"));
+    }
+
+    #[test]
+    fn indicators_outside_tabmode_are_leftovers() {
+        let root_node = fixtures::load("workspaces.json");
+
+        // Workspace '3 []' is in tab mode.
+        assert!(Doctor::leftover_indicators(&root_node, Some("[]")).is_empty());
+
+        let root_node = fixtures::edit(&root_node, 400, |workspace| {
+            workspace.name = Some("web []".to_string());
+        });
+        assert_eq!(
+            repairs(&Doctor::leftover_indicators(&root_node, Some("[]"))),
+            ["rename 'web []' to 'web'"]
+        );
+
+        // Without an indicator suffix there is nothing to look for.
+        assert!(Doctor::leftover_indicators(&root_node, None).is_empty());
+    }
+}
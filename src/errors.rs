@@ -0,0 +1,159 @@
+/*
+    Copyright (C) 2022  Biagio Festa
+
+    This program is free software: you can redistribute it and/or modify
+    it under the terms of the GNU General Public License as published by
+    the Free Software Foundation, either version 3 of the License, or
+    (at your option) any later version.
+
+    This program is distributed in the hope that it will be useful,
+    but WITHOUT ANY WARRANTY; without even the implied warranty of
+    MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+    GNU General Public License for more details.
+
+    You should have received a copy of the GNU General Public License
+    along with this program.  If not, see <https://www.gnu.org/licenses/>.
+*/
+
+use std::fmt::Display;
+
+/// A failure which scripts can tell apart by the exit code.
+///
+/// Executors attach it as context of the error they return: the exit code is the
+/// one of the first failure found in the error chain (see `exit_code`).
+///
+/// The exit codes are 0 on success, `EXIT_GENERIC` (1) for any other error and 2 to 8 for
+/// the failures, in the order of the variants (they are listed in the `--porcelain` help too).
+/// Scripts rely on them: a code is never reused, a new failure takes the next free one.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Failure {
+    /// The workspace does not exist.
+    WorkspaceNotFound,
+
+    /// The layout file cannot be read or it is corrupt.
+    LayoutUnreadable,
+
+    /// Cannot connect to i3.
+    Connection,
+
+    /// Some command of a restore failed (the rest of the layout is restored).
+    PartialRestore,
+
+    /// Some window refused to move while normalizing (the others are normalized).
+    PartialNormalize,
+
+    /// No layout is saved for the project (see `restore-layout --project`).
+    NoProjectLayout,
+
+    /// There are no urgent windows to jump to, nor a window to return to (see `urgent-jump`).
+    NoUrgent,
+}
+
+impl Failure {
+    /// The exit code of any other error.
+    pub const EXIT_GENERIC: u8 = 1;
+
+    /// The exit code of the failure.
+    pub fn exit_code(self) -> u8 {
+        match self {
+            Self::WorkspaceNotFound => 2,
+            Self::LayoutUnreadable => 3,
+            Self::Connection => 4,
+            Self::PartialRestore => 5,
+            Self::PartialNormalize => 6,
+            Self::NoProjectLayout => 7,
+            Self::NoUrgent => 8,
+        }
+    }
+
+    /// A stable identifier of the failure (e.g., for `--porcelain`).
+    pub fn tag(self) -> &'static str {
+        match self {
+            Self::WorkspaceNotFound => "workspace-not-found",
+            Self::LayoutUnreadable => "layout-unreadable",
+            Self::Connection => "connection",
+            Self::PartialRestore => "partial-restore",
+            Self::PartialNormalize => "partial-normalize",
+            Self::NoProjectLayout => "no-project-layout",
+            Self::NoUrgent => "no-urgent",
+        }
+    }
+
+    /// The failure attached to an error, if any.
+    pub fn of(error: &anyhow::Error) -> Option<Self> {
+        error.downcast_ref::<Self>().copied()
+    }
+}
+
+impl Display for Failure {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Self::WorkspaceNotFound => write!(f, "Workspace not found"),
+            Self::LayoutUnreadable => write!(f, "Layout unreadable"),
+            Self::Connection => write!(f, "Cannot connect to i3"),
+            Self::PartialRestore => write!(f, "Layout partially restored"),
+            Self::PartialNormalize => write!(f, "Workspace partially normalized"),
+            Self::NoProjectLayout => write!(f, "No layout for the project"),
+            Self::NoUrgent => write!(f, "No urgent windows"),
+        }
+    }
+}
+
+impl std::error::Error for Failure {}
+
+/// The exit code of an error.
+pub fn exit_code(error: &anyhow::Error) -> u8 {
+    Failure::of(error)
+        .map(Failure::exit_code)
+        .unwrap_or(Failure::EXIT_GENERIC)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const FAILURES: [Failure; 7] = [
+        Failure::WorkspaceNotFound,
+        Failure::LayoutUnreadable,
+        Failure::Connection,
+        Failure::PartialRestore,
+        Failure::PartialNormalize,
+        Failure::NoProjectLayout,
+        Failure::NoUrgent,
+    ];
+
+    #[test]
+    fn exit_codes_are_distinct() {
+        let mut codes = FAILURES.map(Failure::exit_code).to_vec();
+        codes.push(Failure::EXIT_GENERIC);
+        codes.sort();
+        codes.dedup();
+
+        assert_eq!(codes.len(), FAILURES.len() + 1);
+        assert!(!codes.contains(&0));
+
+        // clap exits with 2 on usage errors: only scripts checking the workspace use it.
+        assert_eq!(Failure::WorkspaceNotFound.exit_code(), 2);
+        assert_eq!(Failure::NoUrgent.exit_code(), 8);
+    }
+
+    #[test]
+    fn tags_are_distinct() {
+        let mut tags = FAILURES.map(Failure::tag).to_vec();
+        tags.sort();
+        tags.dedup();
+
+        assert_eq!(tags.len(), FAILURES.len());
+    }
+
+    #[test]
+    fn exit_code_of_error_chain() {
+        let error = anyhow::anyhow!("No urgent windows")
+            .context(Failure::NoUrgent)
+            .context("Failure in command 'urgent-jump'");
+
+        assert_eq!(Failure::of(&error), Some(Failure::NoUrgent));
+        assert_eq!(exit_code(&error), 8);
+        assert_eq!(exit_code(&anyhow::anyhow!("Other")), Failure::EXIT_GENERIC);
+    }
+}
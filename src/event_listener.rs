@@ -15,10 +15,32 @@
     along with this program.  If not, see <https://www.gnu.org/licenses/>.
 */
 
+use crate::command_executor::connect;
+use crate::command_executor::I3Instance;
+use crate::command_executor::I3Node;
+use crate::utilities::is_window;
+use anyhow::anyhow;
 use anyhow::Context;
 use anyhow::Result;
+use i3_ipc::event::OutputData;
 use i3_ipc::event::Subscribe;
+use i3_ipc::event::TickData;
+use i3_ipc::event::WindowChange;
+use i3_ipc::event::WindowData;
+use i3_ipc::event::WorkspaceData;
 use i3_ipc::I3Stream;
+use serde::Deserialize;
+use serde::Serialize;
+use std::collections::BTreeMap;
+use std::fs::File;
+use std::io::BufRead;
+use std::io::BufReader;
+use std::io::LineWriter;
+use std::io::Write;
+use std::path::Path;
+use std::sync::mpsc::Receiver;
+use std::sync::mpsc::RecvTimeoutError;
+use std::time::Duration;
 
 /// An I3 Event.
 pub type I3Event = i3_ipc::event::Event;
@@ -31,28 +53,189 @@ pub type I3Event = i3_ipc::event::Event;
 pub enum EventSubscribe {
     /// Event of type Window.
     Window,
+
+    /// Event of type Output.
+    Output,
+
+    /// Event of type Tick.
+    Tick,
+
+    /// Event of type Workspace.
+    Workspace,
 }
 
 /// A connection with I3 IPC for event capturing.
+///
+/// Events are read by a background thread, so that the listener can wait for
+/// the next event with a timeout.
 pub struct EventListener {
-    /// The connection with I3 for IPC.
-    i3_stream: I3Stream,
+    /// The events received from the background thread.
+    events: Receiver<Result<I3Event>>,
+
+    /// The i3 instance of the connection.
+    instance: I3Instance,
+
+    /// Whether the events are replayed from a file (see `replay`).
+    replay: bool,
+
+    /// Where the received events are recorded, one JSON per line (see `with_recording`).
+    recording: Option<LineWriter<File>>,
+}
+
+/// An event as recorded in a file (see `EventListener::with_recording`).
+#[derive(Serialize, Deserialize)]
+#[serde(tag = "type", content = "data", rename_all = "snake_case")]
+enum RecordedEvent {
+    Window(Box<WindowData>),
+    Workspace(Box<WorkspaceData>),
+    Output(OutputData),
+    Tick(TickData),
+}
+
+impl RecordedEvent {
+    /// The recordable copy of `event` (`None` for the events the service does not handle).
+    fn of(event: &I3Event) -> Option<Self> {
+        match event {
+            I3Event::Window(data) => Some(Self::Window(data.clone())),
+            I3Event::Workspace(data) => Some(Self::Workspace(data.clone())),
+            I3Event::Output(data) => Some(Self::Output(data.clone())),
+            I3Event::Tick(data) => Some(Self::Tick(data.clone())),
+            _ => None,
+        }
+    }
+
+    /// The event to replay.
+    fn into_event(self) -> I3Event {
+        match self {
+            Self::Window(data) => I3Event::Window(data),
+            Self::Workspace(data) => I3Event::Workspace(data),
+            Self::Output(data) => I3Event::Output(data),
+            Self::Tick(data) => I3Event::Tick(data),
+        }
+    }
+}
+
+/// Parse the events recorded in `reader` (one JSON per line, empty lines skipped).
+fn read_recorded_events<R>(reader: R) -> Result<Vec<I3Event>>
+where
+    R: BufRead,
+{
+    let mut events = vec![];
+
+    for (index, line) in reader.lines().enumerate() {
+        let line = line.context("Cannot read the recorded events")?;
+
+        if line.trim().is_empty() {
+            continue;
+        }
+
+        let event = serde_json::from_str::<RecordedEvent>(&line)
+            .with_context(|| format!("Cannot parse the recorded event at line {}", index + 1))?;
+        events.push(event.into_event());
+    }
+
+    Ok(events)
 }
 
 impl EventListener {
+    /// The polling interval when the event subscription fails.
+    pub const DEFAULT_POLL_INTERVAL: Duration = Duration::from_millis(500);
+
     /// Connect to I3 and subscribe for particular event to catch.
     pub fn new(event_subscribe: &[EventSubscribe]) -> Result<Self> {
-        println!("Creating event listener...");
-        let i3_stream = I3Stream::conn_sub(
-            event_subscribe
-                .iter()
-                .map(|&e| e.into())
-                .collect::<Vec<_>>(),
-        )
-        .context("Cannot create event listener")?;
-        println!("  Ok");
+        eprintln!("Creating event listener...");
+        // The identity is queried before subscribing: replies and events share the socket.
+        let (mut i3_stream, instance) = connect().context("Cannot create event listener")?;
+        i3_stream
+            .subscribe(
+                event_subscribe
+                    .iter()
+                    .map(|&e| e.into())
+                    .collect::<Vec<_>>(),
+            )
+            .context("Cannot create event listener")?;
+        eprintln!("  Ok");
+
+        Ok(Self {
+            events: Self::spawn_reader(i3_stream),
+            instance,
+            replay: false,
+            recording: None,
+        })
+    }
+
+    /// Connect to I3 and poll its tree every `interval`, instead of subscribing to events.
+    ///
+    /// It is a degraded mode for setups where the event subscription fails: only the
+    /// window events `New`, `Close` and `Focus` are synthesized (see `diff_windows`).
+    pub fn polling(interval: Duration) -> Result<Self> {
+        eprintln!("Creating polling event listener...");
+        let (mut i3_stream, instance) =
+            connect().context("Cannot create polling event listener")?;
+        let tree = i3_stream
+            .get_tree()
+            .context("Cannot create polling event listener")?;
+        eprintln!("  Ok");
+
+        eprintln!(
+            "[WARN]: Polling mode active: the i3 tree is polled every {} ms and only window \
+             events (new, close, focus) are handled",
+            interval.as_millis()
+        );
 
-        Ok(Self { i3_stream })
+        Ok(Self {
+            events: Self::spawn_poller(i3_stream, tree, interval),
+            instance,
+            replay: false,
+            recording: None,
+        })
+    }
+
+    /// Replay the events recorded in `path` (see `with_recording`), as fast as they are
+    /// handled.
+    ///
+    /// The listener is disconnected once all the events have been received (see
+    /// `is_replay`). The commands are still executed on `instance`.
+    pub fn replay(path: &Path, instance: I3Instance) -> Result<Self> {
+        let file = File::open(path)
+            .with_context(|| format!("Cannot open the recorded events '{}'", path.display()))?;
+        let events = read_recorded_events(BufReader::new(file))?;
+
+        eprintln!(
+            "Replaying {} events from '{}'",
+            events.len(),
+            path.display()
+        );
+
+        let (sender, receiver) = std::sync::mpsc::channel();
+        for event in events {
+            sender.send(Ok(event)).expect("The receiver is alive");
+        }
+
+        Ok(Self {
+            events: receiver,
+            instance,
+            replay: true,
+            recording: None,
+        })
+    }
+
+    /// Record the received events in `path` (truncated), so that they can be replayed.
+    pub fn with_recording(mut self, path: &Path) -> Result<Self> {
+        let file = File::create(path)
+            .with_context(|| format!("Cannot record the events in '{}'", path.display()))?;
+        self.recording = Some(LineWriter::new(file));
+        Ok(self)
+    }
+
+    /// Whether the events are replayed from a file.
+    pub fn is_replay(&self) -> bool {
+        self.replay
+    }
+
+    /// The i3 instance of the connection.
+    pub fn instance(&self) -> &I3Instance {
+        &self.instance
     }
 
     /// Receive the next event.
@@ -60,16 +243,311 @@ impl EventListener {
     /// This is a blocking function. It waits until the next event is available
     /// or an error occour (e.g., I3 socket disconnection).
     pub fn receive_event(&mut self) -> Result<I3Event> {
-        self.i3_stream
-            .receive_event()
-            .context("Cannot receive event from i3 listener")
+        let event = self
+            .events
+            .recv()
+            .map_err(|_| anyhow!("The i3 listener has been disconnected"))??;
+        self.record(&event);
+        Ok(event)
     }
+
+    /// Receive the next event waiting at most `timeout`.
+    ///
+    /// It returns `None` if no event is available before the timeout expires.
+    pub fn receive_event_timeout(&mut self, timeout: Duration) -> Result<Option<I3Event>> {
+        match self.events.recv_timeout(timeout) {
+            Ok(event) => {
+                let event = event?;
+                self.record(&event);
+                Ok(Some(event))
+            }
+            Err(RecvTimeoutError::Timeout) => Ok(None),
+            Err(RecvTimeoutError::Disconnected) => {
+                Err(anyhow!("The i3 listener has been disconnected"))
+            }
+        }
+    }
+
+    /// Append `event` to the recording (if any); the recording stops on the first failure.
+    fn record(&mut self, event: &I3Event) {
+        let (Some(recording), Some(recorded)) = (&mut self.recording, RecordedEvent::of(event))
+        else {
+            return;
+        };
+
+        let written = serde_json::to_writer(&mut *recording, &recorded)
+            .map_err(anyhow::Error::from)
+            .and_then(|()| Ok(recording.write_all(b"\n")?));
+
+        if let Err(error) = written {
+            eprintln!("[WARN]: Cannot record the events: {:?}", error);
+            self.recording = None;
+        }
+    }
+
+    /// Spawn the thread reading events from the i3 connection.
+    ///
+    /// The thread stops after the first error (which is forwarded as well).
+    fn spawn_reader(mut i3_stream: I3Stream) -> Receiver<Result<I3Event>> {
+        let (sender, receiver) = std::sync::mpsc::channel();
+
+        std::thread::spawn(move || loop {
+            let event = i3_stream
+                .receive_event()
+                .context("Cannot receive event from i3 listener");
+            let failed = event.is_err();
+
+            if sender.send(event).is_err() || failed {
+                break;
+            }
+        });
+
+        receiver
+    }
+
+    /// Spawn the thread polling the tree and synthesizing the window events.
+    ///
+    /// The thread stops after the first error (which is forwarded as well).
+    fn spawn_poller(
+        mut i3_stream: I3Stream,
+        mut previous: I3Node,
+        interval: Duration,
+    ) -> Receiver<Result<I3Event>> {
+        let (sender, receiver) = std::sync::mpsc::channel();
+
+        std::thread::spawn(move || loop {
+            std::thread::sleep(interval);
+
+            let current = match i3_stream.get_tree() {
+                Ok(current) => current,
+                Err(error) => {
+                    let _ = sender.send(Err(error).context("Cannot poll the i3 tree"));
+                    break;
+                }
+            };
+
+            let events = diff_windows(&previous, &current)
+                .into_iter()
+                .map(|window_data| Ok(I3Event::Window(Box::new(window_data))));
+
+            for event in events {
+                if sender.send(event).is_err() {
+                    return;
+                }
+            }
+
+            previous = current;
+        });
+
+        receiver
+    }
+}
+
+/// The window events turning the tree `previous` into `current`.
+///
+/// Closed windows come first, then the new ones (by id), then the focus if the focused
+/// window changed. Floating windows are included.
+pub fn diff_windows(previous: &I3Node, current: &I3Node) -> Vec<WindowData> {
+    let previous_windows = collect_windows(previous);
+    let current_windows = collect_windows(current);
+
+    let closed = previous_windows
+        .iter()
+        .filter(|(id, _)| !current_windows.contains_key(id))
+        .map(|(_, node)| (WindowChange::Close, *node));
+
+    let new = current_windows
+        .iter()
+        .filter(|(id, _)| !previous_windows.contains_key(id))
+        .map(|(_, node)| (WindowChange::New, *node));
+
+    let previous_focused = previous_windows.values().find(|node| node.focused);
+    let focused = current_windows
+        .values()
+        .find(|node| node.focused)
+        .filter(|node| previous_focused.is_none_or(|previous| previous.id != node.id))
+        .map(|node| (WindowChange::Focus, *node));
+
+    closed
+        .chain(new)
+        .chain(focused)
+        .map(|(change, node)| WindowData {
+            change,
+            container: node.clone(),
+        })
+        .collect()
+}
+
+/// The windows of a tree (floating ones included), by id.
+fn collect_windows(tree: &I3Node) -> BTreeMap<usize, &I3Node> {
+    let mut windows = BTreeMap::new();
+    let mut dfs = vec![tree];
+
+    while let Some(current) = dfs.pop() {
+        if is_window(current) {
+            windows.insert(current.id, current);
+        }
+
+        dfs.extend(current.nodes.as_slice());
+        dfs.extend(current.floating_nodes.as_slice());
+    }
+
+    windows
 }
 
 impl From<EventSubscribe> for Subscribe {
     fn from(e: EventSubscribe) -> Self {
         match e {
             EventSubscribe::Window => Subscribe::Window,
+            EventSubscribe::Output => Subscribe::Output,
+            EventSubscribe::Tick => Subscribe::Tick,
+            EventSubscribe::Workspace => Subscribe::Workspace,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::fixtures;
+
+    fn tree(fixture: &str) -> I3Node {
+        fixtures::load(fixture).node().clone()
+    }
+
+    fn node_mut(tree: &mut I3Node, node_id: usize) -> &mut I3Node {
+        let mut dfs = vec![tree];
+
+        while let Some(current) = dfs.pop() {
+            if current.id == node_id {
+                return current;
+            }
+
+            dfs.extend(current.nodes.iter_mut());
+            dfs.extend(current.floating_nodes.iter_mut());
         }
+
+        panic!("No node '{}' in the tree", node_id);
+    }
+
+    fn changes(events: &[WindowData]) -> Vec<(WindowChange, usize)> {
+        events
+            .iter()
+            .map(|event| (event.change, event.container.id))
+            .collect()
+    }
+
+    #[test]
+    fn same_tree_has_no_events() {
+        let previous = tree("workspaces.json");
+        assert!(diff_windows(&previous, &previous.clone()).is_empty());
+    }
+
+    #[test]
+    fn closed_windows_come_first() {
+        let previous = tree("workspaces.json");
+        let mut current = previous.clone();
+
+        // 102 (focused) closes, 150 and 160 open, 101 gets the focus.
+        let workspace = node_mut(&mut current, 100);
+        let mut opened = workspace.nodes[0].clone();
+        workspace.nodes.remove(1);
+        for node_id in [160, 150] {
+            opened.id = node_id;
+            opened.focused = false;
+            workspace.nodes.push(opened.clone());
+        }
+        node_mut(&mut current, 101).focused = true;
+
+        assert_eq!(
+            changes(&diff_windows(&previous, &current)),
+            [
+                (WindowChange::Close, 102),
+                (WindowChange::New, 150),
+                (WindowChange::New, 160),
+                (WindowChange::Focus, 101),
+            ]
+        );
+    }
+
+    #[test]
+    fn focus_change_only() {
+        let previous = tree("workspaces.json");
+        let mut current = previous.clone();
+
+        node_mut(&mut current, 102).focused = false;
+        node_mut(&mut current, 211).focused = true;
+
+        assert_eq!(
+            changes(&diff_windows(&previous, &current)),
+            [(WindowChange::Focus, 211)]
+        );
+
+        // Focusing a container (not a window) is not a window event.
+        let mut current = previous.clone();
+        node_mut(&mut current, 102).focused = false;
+        node_mut(&mut current, 210).focused = true;
+
+        assert!(diff_windows(&previous, &current).is_empty());
+    }
+
+    #[test]
+    fn floating_windows_are_included() {
+        let previous = tree("floating.json");
+        let mut current = previous.clone();
+
+        node_mut(&mut current, 120).nodes.clear();
+
+        let events = diff_windows(&previous, &current);
+        assert_eq!(changes(&events), [(WindowChange::Close, 121)]);
+        assert!(is_window(&events[0].container));
+    }
+
+    #[test]
+    fn recorded_events_are_replayed_in_order() {
+        let mut tree = tree("workspaces.json");
+        let events = [
+            I3Event::Window(Box::new(WindowData {
+                change: WindowChange::New,
+                container: node_mut(&mut tree, 101).clone(),
+            })),
+            I3Event::Output(OutputData {
+                change: "unspecified".to_string(),
+            }),
+            I3Event::Tick(TickData {
+                first: false,
+                payload: "i3-autolayout:stats".to_string(),
+            }),
+        ];
+
+        let mut recorded = String::new();
+        for event in &events {
+            let recorded_event = RecordedEvent::of(event).unwrap();
+            recorded += &serde_json::to_string(&recorded_event).unwrap();
+            recorded += "\n\n";
+        }
+        assert!(recorded.starts_with(r#"{"type":"window","data":{"change":"new","#));
+
+        let replayed = read_recorded_events(recorded.as_bytes()).unwrap();
+        assert_eq!(replayed.len(), 3);
+        assert!(matches!(
+            &replayed[0],
+            I3Event::Window(data) if data.change == WindowChange::New && data.container.id == 101
+        ));
+        assert!(matches!(&replayed[1], I3Event::Output(data) if data.change == "unspecified"));
+        assert!(matches!(
+            &replayed[2],
+            I3Event::Tick(data) if data.payload == "i3-autolayout:stats"
+        ));
+    }
+
+    #[test]
+    fn malformed_recording_names_the_line() {
+        let recorded = "{\"type\":\"output\",\"data\":{\"change\":\"unspecified\"}}\n{}\n";
+        let error = read_recorded_events(recorded.as_bytes()).unwrap_err();
+        assert_eq!(
+            error.to_string(),
+            "Cannot parse the recorded event at line 2"
+        );
     }
 }
@@ -0,0 +1,340 @@
+/*
+    Copyright (C) 2022  Biagio Festa
+
+    This program is free software: you can redistribute it and/or modify
+    it under the terms of the GNU General Public License as published by
+    the Free Software Foundation, either version 3 of the License, or
+    (at your option) any later version.
+
+    This program is distributed in the hope that it will be useful,
+    but WITHOUT ANY WARRANTY; without even the implied warranty of
+    MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+    GNU General Public License for more details.
+
+    You should have received a copy of the GNU General Public License
+    along with this program.  If not, see <https://www.gnu.org/licenses/>.
+*/
+
+use crate::command_executor::I3Node;
+use crate::command_executor::RootNode;
+use crate::normalize::NormalizePlan;
+use crate::restore_layout::plan_restore;
+use crate::restore_layout::resolve_windows;
+use crate::restore_layout::Stage;
+use crate::save_layout::SaveLayout;
+use crate::split_policy::SplitPolicy;
+use crate::utilities::find_workspaces;
+use crate::utilities::is_window;
+use crate::utilities::layout_command;
+use crate::utilities::Layout;
+use crate::utilities::Split;
+use anyhow::Context;
+use anyhow::Result;
+use serde_json::json;
+use serde_json::Value;
+use std::fmt::Write;
+
+/// The `--help` examples of the subcommands.
+///
+/// The i3 commands of the examples are not written by hand: they are produced by the
+/// planners of the subcommands (`SplitPolicy::decide`, `plan_restore` and `NormalizePlan`)
+/// over a tiny embedded tree, so that they always match what the subcommands emit.
+pub struct HelpExamples {
+    /// The examples of `autolayout`.
+    pub autolayout: String,
+
+    /// The examples of `restore-layout`.
+    pub restore_layout: String,
+
+    /// The examples of `tabmode`.
+    pub tabmode: String,
+}
+
+impl HelpExamples {
+    /// Generate the examples.
+    ///
+    /// It runs the planners, so it is meant to be called only when the help is printed.
+    pub fn generate() -> Result<Self> {
+        Ok(Self {
+            autolayout: autolayout_examples()
+                .context("Cannot generate the examples of 'autolayout'")?,
+            restore_layout: restore_layout_examples()
+                .context("Cannot generate the examples of 'restore-layout'")?,
+            tabmode: tabmode_examples().context("Cannot generate the examples of 'tabmode'")?,
+        })
+    }
+
+    /// The examples by subcommand name.
+    pub fn all(&self) -> [(&'static str, &str); 3] {
+        [
+            ("autolayout", &self.autolayout),
+            ("restore-layout", &self.restore_layout),
+            ("tabmode", &self.tabmode),
+        ]
+    }
+}
+
+/// The examples of `autolayout`: the split set when each window of the fixture is focused.
+fn autolayout_examples() -> Result<String> {
+    let root_node = fixture(false)?;
+    let split_policy = SplitPolicy::default();
+
+    let mut text = String::from(
+        "EXAMPLES:\n    i3-autolayout autolayout\n    \
+         i3-autolayout autolayout --place 'class=Spotify:workspace=music'\n\n    \
+         On workspace 1 (1920x1080) with Firefox (960x1080) and two terminals\n    \
+         (960x540 each), focusing each window runs:\n",
+    );
+
+    let workspace = find_workspaces(&root_node)
+        .into_iter()
+        .next()
+        .context("Invalid fixture")?;
+
+    for window in windows(workspace) {
+        let decision = split_policy.decide(window, &root_node, None)?;
+        let split = match decision.split {
+            Some(Split::Horizontal) => "split horizontal",
+            Some(Split::Vertical) => "split vertical",
+            Some(Split::Toggle) => "split toggle",
+            None => continue,
+        };
+
+        writeln!(text, "        [con_id={}] {}", window.id, split)?;
+    }
+
+    Ok(text)
+}
+
+/// The examples of `restore-layout`: the plan restoring the nested fixture over the flat one.
+fn restore_layout_examples() -> Result<String> {
+    let saved_root = fixture(true)?;
+    let saved_workspace = find_workspaces(&saved_root)
+        .into_iter()
+        .next()
+        .context("Invalid fixture")?;
+    let saved_layout = SaveLayout::save_subtree(saved_workspace, false)?;
+
+    let root_node = fixture(false)?;
+    let windows = resolve_windows(&saved_layout, &root_node)?;
+    let plan = plan_restore(&saved_layout, &root_node, &windows, false)?;
+
+    let mut text = String::from(
+        "EXAMPLES:\n    i3-autolayout restore-layout -i ws1.layout\n    \
+         i3-autolayout restore-layout --name coding --restore-sizes\n\n    \
+         Restoring workspace 1 saved as Firefox | (terminal / terminal) while its\n    \
+         windows are side by side runs:\n",
+    );
+
+    for stage in [Stage::Structure, Stage::Size] {
+        if stage == Stage::Size {
+            writeln!(text, "    then, with --restore-sizes:")?;
+        }
+
+        for planned in plan.iter().filter(|planned| planned.stage == stage) {
+            writeln!(text, "        {}", planned)?;
+        }
+    }
+
+    Ok(text)
+}
+
+/// The examples of `tabmode`: the commands collapsing the nested fixture into tabs.
+fn tabmode_examples() -> Result<String> {
+    let root_node = fixture(true)?;
+    let workspace = find_workspaces(&root_node)
+        .into_iter()
+        .next()
+        .context("Invalid fixture")?;
+
+    let mut text = String::from(
+        "EXAMPLES:\n    i3-autolayout tabmode\n    \
+         i3-autolayout tabmode --workspace 2 --file-layout ~/.cache/i3-autolayout/tabmode/\n\n    \
+         Entering tab mode on workspace 1 laid out as Firefox | (terminal / terminal)\n    \
+         collapses its windows with:\n",
+    );
+
+    for command in NormalizePlan::of(workspace).commands() {
+        writeln!(text, "        {}", command)?;
+    }
+
+    writeln!(
+        text,
+        "        [con_id={}] {}",
+        workspace.id,
+        layout_command(Layout::Tabbed)
+    )?;
+
+    Ok(text)
+}
+
+/// The tiled windows of a subtree, in order.
+fn windows(node: &I3Node) -> Vec<&I3Node> {
+    if is_window(node) {
+        return vec![node];
+    }
+
+    node.nodes.iter().flat_map(windows).collect()
+}
+
+/// The fixture tree: workspace 1 with Firefox and two terminals.
+///
+/// The windows are side by side, or with the terminals stacked in a vertical
+/// container (`nested`).
+fn fixture(nested: bool) -> Result<RootNode> {
+    let firefox = fixture_window(11, "firefox", (0, 0, 960, 1080));
+
+    let terminals = if nested {
+        vec![fixture_node(
+            20,
+            "con",
+            "splitv",
+            (960, 0, 960, 1080),
+            vec![
+                fixture_window(12, "Alacritty", (960, 0, 960, 540)),
+                fixture_window(13, "Alacritty", (960, 540, 960, 540)),
+            ],
+        )]
+    } else {
+        vec![
+            fixture_window(12, "Alacritty", (960, 0, 960, 540)),
+            fixture_window(13, "Alacritty", (960, 540, 960, 540)),
+        ]
+    };
+
+    let mut workspace = fixture_node(
+        4,
+        "workspace",
+        "splith",
+        (0, 0, 1920, 1080),
+        [vec![firefox], terminals].concat(),
+    );
+    workspace["name"] = json!("1");
+    workspace["num"] = json!(1);
+
+    let content = fixture_node(3, "con", "splith", (0, 0, 1920, 1080), vec![workspace]);
+    let output = fixture_node(2, "output", "output", (0, 0, 1920, 1080), vec![content]);
+    let root = fixture_node(1, "root", "splith", (0, 0, 1920, 1080), vec![output]);
+
+    let root = serde_json::from_value::<I3Node>(root).context("Invalid fixture")?;
+
+    Ok(RootNode::new(root))
+}
+
+/// A window of the fixture tree.
+fn fixture_window(id: usize, class: &str, rect: (isize, isize, isize, isize)) -> Value {
+    let mut window = fixture_node(id, "con", "splith", rect, vec![]);
+    window["window"] = json!(id * 1000);
+    window["window_properties"] = json!({ "class": class, "instance": class.to_lowercase() });
+
+    window
+}
+
+/// A node of the fixture tree (as in the reply of `get_tree`).
+fn fixture_node(
+    id: usize,
+    node_type: &str,
+    layout: &str,
+    (x, y, width, height): (isize, isize, isize, isize),
+    nodes: Vec<Value>,
+) -> Value {
+    let rect = json!({ "x": x, "y": y, "width": width, "height": height });
+
+    json!({
+        "id": id,
+        "name": null,
+        "num": null,
+        "type": node_type,
+        "layout": layout,
+        "output": null,
+        "orientation": "none",
+        "border": "normal",
+        "scratchpad_state": "none",
+        "percent": null,
+        "rect": rect,
+        "window_rect": rect,
+        "deco_rect": { "x": 0, "y": 0, "width": 0, "height": 0 },
+        "geometry": rect,
+        "window": null,
+        "window_properties": null,
+        "window_type": null,
+        "current_border_width": 2,
+        "urgent": false,
+        "marks": [],
+        "focused": false,
+        "focus": nodes.iter().map(|node| node["id"].clone()).collect::<Vec<_>>(),
+        "sticky": false,
+        "floating": "auto_off",
+        "floating_nodes": [],
+        "fullscreen_mode": 0,
+        "nodes": nodes,
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::path::Path;
+
+    /// Compare generated examples with their copy in `fixtures/examples`.
+    ///
+    /// A change of the planners shows up here: review the new examples and update the copy
+    /// (`UPDATE_EXAMPLES=1 cargo test examples` rewrites it).
+    fn assert_no_drift(name: &str, generated: &str) {
+        let path = Path::new(env!("CARGO_MANIFEST_DIR"))
+            .join("fixtures")
+            .join("examples")
+            .join(name);
+
+        if std::env::var_os("UPDATE_EXAMPLES").is_some() {
+            std::fs::write(&path, generated).unwrap();
+        }
+        let expected = std::fs::read_to_string(&path)
+            .unwrap_or_else(|error| panic!("Cannot read '{}': {}", path.display(), error));
+
+        assert_eq!(
+            generated,
+            expected,
+            "The examples drifted from '{}'",
+            path.display()
+        );
+    }
+
+    #[test]
+    fn examples_generate_without_drift() {
+        let examples = HelpExamples::generate().unwrap();
+
+        for (subcommand, text) in examples.all() {
+            assert_no_drift(&format!("{}.txt", subcommand), text);
+        }
+
+        // Every copy belongs to an example (none is left over after a removal).
+        let dir = Path::new(env!("CARGO_MANIFEST_DIR"))
+            .join("fixtures")
+            .join("examples");
+        for entry in std::fs::read_dir(dir).unwrap() {
+            let file_name = entry.unwrap().file_name();
+            let file_name = file_name.to_string_lossy();
+
+            assert!(
+                examples
+                    .all()
+                    .iter()
+                    .any(|(subcommand, _)| file_name == format!("{}.txt", subcommand)),
+                "'{}' is not the copy of an example",
+                file_name
+            );
+        }
+    }
+
+    #[test]
+    fn examples_list_commands() {
+        let examples = HelpExamples::generate().unwrap();
+
+        assert!(examples.autolayout.contains("[con_id="));
+        assert!(examples
+            .restore_layout
+            .contains("then, with --restore-sizes:"));
+        assert!(examples.tabmode.ends_with("[con_id=4] layout tabbed\n"));
+    }
+}
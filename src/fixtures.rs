@@ -0,0 +1,182 @@
+/*
+    Copyright (C) 2022  Biagio Festa
+
+    This program is free software: you can redistribute it and/or modify
+    it under the terms of the GNU General Public License as published by
+    the Free Software Foundation, either version 3 of the License, or
+    (at your option) any later version.
+
+    This program is distributed in the hope that it will be useful,
+    but WITHOUT ANY WARRANTY; without even the implied warranty of
+    MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+    GNU General Public License for more details.
+
+    You should have received a copy of the GNU General Public License
+    along with this program.  If not, see <https://www.gnu.org/licenses/>.
+*/
+
+//! GET_TREE dumps (`fixtures/` directory) and assertions on their shape, for the tests.
+//!
+//! The dumps are in the format of `i3-msg -t get_tree`:
+//!   - `workspaces.json`: flat, nested, tabmode, plain tabbed, placeholder and empty workspaces.
+//!   - `multi_output.json`: workspaces on two outputs (`eDP-1` and `HDMI-1`).
+//!   - `floating.json`: workspaces mostly (or only) made of floating windows.
+//!   - `scratchpad.json`: hidden scratchpad windows and one shown on a workspace.
+
+mod shape;
+
+pub use shape::Shape;
+
+use crate::command_executor::I3Node;
+use crate::command_executor::RootNode;
+use crate::utilities::find_node_by_id_including_floating;
+use std::fs::File;
+use std::io::BufReader;
+use std::path::Path;
+use std::path::PathBuf;
+use std::sync::atomic::AtomicUsize;
+use std::sync::atomic::Ordering;
+
+/// Load a tree from the `fixtures/` directory.
+pub fn load(name: &str) -> RootNode {
+    let path = Path::new(env!("CARGO_MANIFEST_DIR"))
+        .join("fixtures")
+        .join(name);
+
+    let file = File::open(&path)
+        .unwrap_or_else(|error| panic!("Cannot open the fixture '{}': {}", path.display(), error));
+
+    let node: I3Node = serde_json::from_reader(BufReader::new(file))
+        .unwrap_or_else(|error| panic!("Cannot parse the fixture '{}': {}", path.display(), error));
+
+    RootNode::new(node)
+}
+
+/// A node of a fixture (floating nodes included).
+pub fn node(root_node: &RootNode, node_id: usize) -> &I3Node {
+    find_node_by_id_including_floating(node_id, root_node)
+        .unwrap_or_else(|| panic!("No node '{}' in the fixture", node_id))
+}
+
+/// A copy of a fixture with a node (floating nodes included) changed by `edit`.
+pub fn edit(root_node: &RootNode, node_id: usize, edit: impl FnOnce(&mut I3Node)) -> RootNode {
+    fn find(node: &mut I3Node, node_id: usize) -> Option<&mut I3Node> {
+        if node.id == node_id {
+            return Some(node);
+        }
+
+        node.nodes
+            .iter_mut()
+            .chain(node.floating_nodes.iter_mut())
+            .find_map(|child| find(child, node_id))
+    }
+
+    let mut tree = root_node.node().clone();
+    edit(
+        find(&mut tree, node_id).unwrap_or_else(|| panic!("No node '{}' in the fixture", node_id)),
+    );
+
+    RootNode::new(tree)
+}
+
+/// Assert that the shape of a subtree (see `print_tree::tree_shape`) is the expected one.
+///
+/// The expected shape is parsed (see `Shape`), so spacing does not matter:
+/// `assert_tree_shape!(workspace, "splith(win, splitv(win,win))")`.
+macro_rules! assert_tree_shape {
+    ($node:expr, $expected:expr) => {{
+        let expected = $crate::fixtures::Shape::parse($expected)
+            .unwrap_or_else(|error| panic!("Invalid expected shape: {:#}", error));
+
+        let actual = $crate::print_tree::tree_shape($node);
+        let actual = $crate::fixtures::Shape::parse(&actual)
+            .unwrap_or_else(|error| panic!("Invalid shape '{}': {:#}", actual, error));
+
+        assert_eq!(
+            actual.to_string(),
+            expected.to_string(),
+            "Unexpected tree shape"
+        );
+    }};
+}
+
+pub(crate) use assert_tree_shape;
+
+/// Parse a quoted argument as i3 does: the unescaped string and the rest of the command.
+pub fn unquote(command: &str) -> (String, &str) {
+    let mut chars = command.char_indices();
+    assert_eq!(chars.next(), Some((0, '"')), "Not quoted: {}", command);
+
+    let mut unescaped = String::new();
+
+    while let Some((index, c)) = chars.next() {
+        match c {
+            '"' => return (unescaped, &command[index + 1..]),
+            '\\' => match chars.next() {
+                Some((_, c @ ('"' | '\\'))) => unescaped.push(c),
+                Some((_, c)) => {
+                    unescaped.push('\\');
+                    unescaped.push(c);
+                }
+                None => panic!("Unterminated escape: {}", command),
+            },
+            c => unescaped.push(c),
+        }
+    }
+
+    panic!("Unterminated quote: {}", command);
+}
+
+/// A temporary directory, removed (with its content) on drop.
+pub struct TempDir {
+    path: PathBuf,
+}
+
+impl TempDir {
+    /// Create a new empty directory in the temporary directory of the system.
+    pub fn new(name: &str) -> Self {
+        static COUNTER: AtomicUsize = AtomicUsize::new(0);
+
+        let path = std::env::temp_dir().join(format!(
+            "i3-autolayout-test-{}-{}-{}",
+            std::process::id(),
+            COUNTER.fetch_add(1, Ordering::SeqCst),
+            name
+        ));
+
+        let _ = std::fs::remove_dir_all(&path);
+        std::fs::create_dir_all(&path)
+            .unwrap_or_else(|error| panic!("Cannot create '{}': {}", path.display(), error));
+
+        Self { path }
+    }
+
+    pub fn path(&self) -> &Path {
+        &self.path
+    }
+}
+
+impl Drop for TempDir {
+    fn drop(&mut self) {
+        let _ = std::fs::remove_dir_all(&self.path);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::utilities::find_workspaces;
+
+    #[test]
+    fn fixtures_load() {
+        for name in [
+            "workspaces.json",
+            "multi_output.json",
+            "floating.json",
+            "scratchpad.json",
+        ] {
+            let root_node = load(name);
+            assert!(!find_workspaces(&root_node).is_empty(), "{}", name);
+        }
+    }
+}
@@ -0,0 +1,240 @@
+/*
+    Copyright (C) 2022  Biagio Festa
+
+    This program is free software: you can redistribute it and/or modify
+    it under the terms of the GNU General Public License as published by
+    the Free Software Foundation, either version 3 of the License, or
+    (at your option) any later version.
+
+    This program is distributed in the hope that it will be useful,
+    but WITHOUT ANY WARRANTY; without even the implied warranty of
+    MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+    GNU General Public License for more details.
+
+    You should have received a copy of the GNU General Public License
+    along with this program.  If not, see <https://www.gnu.org/licenses/>.
+*/
+
+//! The compact shape of a tree (e.g., `splith(win, splitv(win, win))`).
+//!
+//! This file does not depend on the rest of the crate: the end-to-end tests include it too.
+
+use anyhow::anyhow;
+use anyhow::Result;
+use std::fmt::Display;
+
+/// The layouts which can appear in a shape (as named in i3 commands).
+const LAYOUTS: [&str; 6] = [
+    "splith", "splitv", "stacked", "tabbed", "dockarea", "output",
+];
+
+/// A parsed shape: windows (`win`) and containers with their layout and children.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum Shape {
+    Window,
+    Container(String, Vec<Shape>),
+}
+
+impl Shape {
+    /// Parse a shape, whitespace is ignored.
+    pub fn parse(text: &str) -> Result<Self> {
+        let mut parser = Parser {
+            tokens: tokenize(text)?,
+            position: 0,
+        };
+
+        let shape = parser.shape()?;
+
+        match parser.next() {
+            Some(token) => Err(anyhow!("Unexpected '{}' after the end of the shape", token)),
+            None => Ok(shape),
+        }
+    }
+}
+
+impl Display for Shape {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Shape::Window => write!(f, "win"),
+            Shape::Container(layout, children) => {
+                write!(f, "{}(", layout)?;
+
+                for (index, child) in children.iter().enumerate() {
+                    if index > 0 {
+                        write!(f, ", ")?;
+                    }
+
+                    write!(f, "{}", child)?;
+                }
+
+                write!(f, ")")
+            }
+        }
+    }
+}
+
+#[derive(Clone, Debug, PartialEq, Eq)]
+enum Token {
+    Word(String),
+    Open,
+    Close,
+    Comma,
+}
+
+impl Display for Token {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Token::Word(word) => write!(f, "{}", word),
+            Token::Open => write!(f, "("),
+            Token::Close => write!(f, ")"),
+            Token::Comma => write!(f, ","),
+        }
+    }
+}
+
+fn tokenize(text: &str) -> Result<Vec<Token>> {
+    let mut tokens = vec![];
+    let mut chars = text.chars().peekable();
+
+    while let Some(c) = chars.next() {
+        match c {
+            '(' => tokens.push(Token::Open),
+            ')' => tokens.push(Token::Close),
+            ',' => tokens.push(Token::Comma),
+            c if c.is_whitespace() => {}
+            c if c.is_ascii_alphabetic() => {
+                let mut word = c.to_string();
+
+                while let Some(c) = chars.next_if(|c| c.is_ascii_alphanumeric()) {
+                    word.push(c);
+                }
+
+                tokens.push(Token::Word(word));
+            }
+            c => return Err(anyhow!("Unexpected character '{}' in the shape", c)),
+        }
+    }
+
+    Ok(tokens)
+}
+
+struct Parser {
+    tokens: Vec<Token>,
+    position: usize,
+}
+
+impl Parser {
+    fn next(&mut self) -> Option<Token> {
+        let token = self.tokens.get(self.position).cloned();
+        self.position += 1;
+        token
+    }
+
+    fn peek(&self) -> Option<&Token> {
+        self.tokens.get(self.position)
+    }
+
+    fn expect(&mut self, expected: Token) -> Result<()> {
+        match self.next() {
+            Some(token) if token == expected => Ok(()),
+            Some(token) => Err(anyhow!("Expected '{}', found '{}'", expected, token)),
+            None => Err(anyhow!(
+                "Expected '{}', found the end of the shape",
+                expected
+            )),
+        }
+    }
+
+    fn shape(&mut self) -> Result<Shape> {
+        let word = match self.next() {
+            Some(Token::Word(word)) => word,
+            Some(token) => return Err(anyhow!("Expected a layout or 'win', found '{}'", token)),
+            None => {
+                return Err(anyhow!(
+                    "Expected a layout or 'win', found the end of the shape"
+                ))
+            }
+        };
+
+        if word == "win" {
+            return Ok(Shape::Window);
+        }
+
+        if !LAYOUTS.contains(&word.as_str()) {
+            return Err(anyhow!("Unknown layout '{}'", word));
+        }
+
+        self.expect(Token::Open)?;
+
+        let mut children = vec![];
+
+        if self.peek() == Some(&Token::Close) {
+            self.next();
+            return Ok(Shape::Container(word, children));
+        }
+
+        loop {
+            children.push(self.shape()?);
+
+            match self.next() {
+                Some(Token::Comma) => {}
+                Some(Token::Close) => return Ok(Shape::Container(word, children)),
+                Some(token) => return Err(anyhow!("Expected ',' or ')', found '{}'", token)),
+                None => return Err(anyhow!("Expected ')', found the end of the shape")),
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn container(layout: &str, children: Vec<Shape>) -> Shape {
+        Shape::Container(layout.to_string(), children)
+    }
+
+    #[test]
+    fn parse_nested() {
+        assert_eq!(
+            Shape::parse("splith(win, splitv(win, tabbed()))").unwrap(),
+            container(
+                "splith",
+                vec![
+                    Shape::Window,
+                    container("splitv", vec![Shape::Window, container("tabbed", vec![])]),
+                ]
+            )
+        );
+    }
+
+    #[test]
+    fn parse_ignores_whitespace() {
+        let shape = Shape::parse(" splith ( win ,\n  stacked(win,win) ) ").unwrap();
+
+        assert_eq!(shape.to_string(), "splith(win, stacked(win, win))");
+    }
+
+    #[test]
+    fn parse_window() {
+        assert_eq!(Shape::parse("win").unwrap(), Shape::Window);
+    }
+
+    #[test]
+    fn parse_rejects_malformed_shapes() {
+        for text in [
+            "",
+            "splith",
+            "splith(",
+            "splith(win",
+            "splith(win,)",
+            "splith(win win)",
+            "splith(win))",
+            "win()",
+            "grid(win)",
+            "splith(win; win)",
+        ] {
+            assert!(Shape::parse(text).is_err(), "'{}' parsed", text);
+        }
+    }
+}
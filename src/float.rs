@@ -0,0 +1,318 @@
+/*
+    Copyright (C) 2022  Biagio Festa
+
+    This program is free software: you can redistribute it and/or modify
+    it under the terms of the GNU General Public License as published by
+    the Free Software Foundation, either version 3 of the License, or
+    (at your option) any later version.
+
+    This program is distributed in the hope that it will be useful,
+    but WITHOUT ANY WARRANTY; without even the implied warranty of
+    MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+    GNU General Public License for more details.
+
+    You should have received a copy of the GNU General Public License
+    along with this program.  If not, see <https://www.gnu.org/licenses/>.
+*/
+
+use crate::command_executor::CommandExecutor;
+use crate::command_executor::I3Node;
+use crate::command_executor::RootNode;
+use crate::utilities::find_focused_node;
+use crate::utilities::find_workspaces;
+use crate::utilities::is_floating_container;
+use crate::workspace::WorkspaceRef;
+use anyhow::anyhow;
+use anyhow::Context;
+use anyhow::Result;
+use i3_ipc::reply::Rect;
+
+/// An action on floating windows.
+#[derive(Clone, Copy, clap::ValueEnum)]
+pub enum FloatAction {
+    /// Center the focused floating window on its output.
+    Center,
+
+    /// Arrange all floating windows of the workspace in a staggered cascade.
+    Cascade,
+
+    /// Move the focused floating window flush to the left edge of its output.
+    SnapLeft,
+
+    /// Move the focused floating window flush to the right edge of its output.
+    SnapRight,
+}
+
+impl FloatAction {
+    /// The position of a floating window (`window`) after a focused-window action.
+    fn position(self, window: &Rect, output: &Rect, margin: isize) -> (isize, isize) {
+        match self {
+            Self::SnapLeft => (output.x + margin, window.y),
+            Self::SnapRight => (output.x + output.width - window.width - margin, window.y),
+            Self::Center | Self::Cascade => (
+                output.x + (output.width - window.width) / 2,
+                output.y + (output.height - window.height) / 2,
+            ),
+        }
+    }
+}
+
+/// The placement of a window in a cascade (see `cascade`).
+#[derive(Debug, PartialEq)]
+struct CascadeStep {
+    node_id: usize,
+
+    /// The size the window is shrunk to, if it does not fit.
+    size: Option<(isize, isize)>,
+
+    /// The position of the window.
+    position: (isize, isize),
+}
+
+/// The offset between two consecutive windows in the cascade.
+const CASCADE_STEP: isize = 32;
+
+/// Stagger windows from the top-left corner of their output, each `CASCADE_STEP` further,
+/// shrinking those which would overflow the output.
+fn cascade(windows: &[&I3Node], output: &Rect, margin: isize) -> Vec<CascadeStep> {
+    windows
+        .iter()
+        .enumerate()
+        .map(|(index, window)| {
+            let offset = margin + index as isize * CASCADE_STEP;
+            let max_width = output.width - offset - margin;
+            let max_height = output.height - offset - margin;
+
+            CascadeStep {
+                node_id: window.id,
+                size: (window.rect.width > max_width || window.rect.height > max_height).then(
+                    || {
+                        (
+                            window.rect.width.min(max_width),
+                            window.rect.height.min(max_height),
+                        )
+                    },
+                ),
+                position: (output.x + offset, output.y + offset),
+            }
+        })
+        .collect()
+}
+
+/// Float executor.
+///
+/// It represents a one-shot executor which arranges floating windows.
+pub struct Float {
+    /// Command executor.
+    command_executor: CommandExecutor,
+}
+
+impl Float {
+    /// A new float executor.
+    pub fn new(command_executor: CommandExecutor) -> Self {
+        Self { command_executor }
+    }
+
+    /// Execute the action.
+    ///
+    /// `Center` and `Snap*` are applied on the focused window, while `Cascade` is applied
+    /// on all floating windows of `workspace`.
+    ///
+    /// If `force_float` is set, a focused tiled window is made floating first;
+    /// otherwise a tiled focused window is an error.
+    pub fn execute(
+        mut self,
+        action: FloatAction,
+        workspace: WorkspaceRef,
+        margin: isize,
+        force_float: bool,
+    ) -> Result<()> {
+        match action {
+            FloatAction::Cascade => self.cascade(workspace, margin),
+            FloatAction::Center | FloatAction::SnapLeft | FloatAction::SnapRight => {
+                let root_node = self.query_root_node_with_floating_focus(force_float)?;
+                let node = find_focused_node(&root_node)
+                    .ok_or_else(|| anyhow!("Cannot find the focused window"))?;
+                let output_rect = self.query_output_rect_of_node(node.id, &root_node)?;
+                let (x, y) = action.position(&node.rect, &output_rect, margin);
+
+                self.move_position(node.id, x, y)
+            }
+        }
+    }
+
+    /// Query the tree and make sure the focused window is floating.
+    fn query_root_node_with_floating_focus(&mut self, force_float: bool) -> Result<RootNode> {
+        let root_node = self.command_executor.query_root_node()?;
+        let node = find_focused_node(&root_node)
+            .ok_or_else(|| anyhow!("Cannot find the focused window"))?;
+
+        if is_floating_container(node) {
+            Ok(root_node)
+        } else if force_float {
+            self.command_executor
+                .run_on_node_id(node.id, "floating enable")
+                .context("Cannot make the focused window floating")?;
+
+            self.command_executor.query_root_node()
+        } else {
+            Err(anyhow!(
+                "The focused window is not floating (use '--force-float' to float it first)"
+            ))
+        }
+    }
+
+    /// Arrange all floating windows of a workspace in a staggered cascade.
+    fn cascade(&mut self, workspace: WorkspaceRef, margin: isize) -> Result<()> {
+        let root_node = self.command_executor.query_root_node()?;
+
+        let workspace = workspace.resolve(&root_node, &mut self.command_executor)?;
+
+        let windows = workspace
+            .floating_nodes
+            .iter()
+            .flat_map(|floating_con| floating_con.nodes.iter())
+            .collect::<Vec<_>>();
+
+        let Some(first) = windows.first() else {
+            eprintln!("No floating windows on the workspace");
+            return Ok(());
+        };
+
+        let output_rect = self.query_output_rect_of_node(first.id, &root_node)?;
+
+        for step in cascade(&windows, &output_rect, margin) {
+            if let Some((width, height)) = step.size {
+                self.command_executor
+                    .run_on_node_id(
+                        step.node_id,
+                        format!("resize set {} px {} px", width, height),
+                    )
+                    .context("Cannot resize the floating window")?;
+            }
+
+            let (x, y) = step.position;
+            self.move_position(step.node_id, x, y)?;
+        }
+
+        Ok(())
+    }
+
+    /// Find the rect of the output which displays the workspace containing the node.
+    fn query_output_rect_of_node(&mut self, node_id: usize, root_node: &RootNode) -> Result<Rect> {
+        let workspace = find_workspaces(root_node)
+            .into_iter()
+            .find(|workspace| contains_node(workspace, node_id))
+            .ok_or_else(|| anyhow!("Cannot find the workspace of the window"))?;
+
+        let output_name = self
+            .command_executor
+            .query_workspaces()?
+            .into_iter()
+            .find(|i3_workspace| Some(&i3_workspace.name) == workspace.name.as_ref())
+            .map(|i3_workspace| i3_workspace.output)
+            .ok_or_else(|| anyhow!("Cannot find the output of the workspace"))?;
+
+        self.command_executor
+            .query_outputs()?
+            .into_iter()
+            .find(|output| output.name == output_name)
+            .map(|output| output.rect)
+            .ok_or_else(|| anyhow!("Cannot find the output '{}'", output_name))
+    }
+
+    /// Move a floating window at an absolute position.
+    fn move_position(&mut self, node_id: usize, x: isize, y: isize) -> Result<()> {
+        self.command_executor
+            .run_on_node_id(node_id, format!("move position {} px {} px", x, y))
+            .context("Cannot move the floating window")
+    }
+}
+
+/// Whether the subtree (floating nodes included) contains the node.
+fn contains_node(subtree: &I3Node, node_id: usize) -> bool {
+    let mut dfs = vec![subtree];
+
+    while let Some(current) = dfs.pop() {
+        if current.id == node_id {
+            return true;
+        }
+
+        dfs.extend(current.nodes.as_slice());
+        dfs.extend(current.floating_nodes.as_slice());
+    }
+
+    false
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::fixtures;
+
+    fn rect(x: isize, y: isize, width: isize, height: isize) -> Rect {
+        Rect {
+            x,
+            y,
+            width,
+            height,
+        }
+    }
+
+    #[test]
+    fn focused_window_positions() {
+        let output = rect(1920, 0, 1920, 1080);
+        let window = rect(2000, 100, 400, 300);
+
+        assert_eq!(
+            FloatAction::Center.position(&window, &output, 10),
+            (2680, 390)
+        );
+        assert_eq!(
+            FloatAction::SnapLeft.position(&window, &output, 10),
+            (1930, 100)
+        );
+        assert_eq!(
+            FloatAction::SnapRight.position(&window, &output, 10),
+            (3430, 100)
+        );
+    }
+
+    #[test]
+    fn cascade_staggers_and_shrinks_windows() {
+        let root_node = fixtures::load("floating.json");
+        let windows = [111, 121, 131]
+            .map(|node_id| fixtures::node(&root_node, node_id))
+            .to_vec();
+
+        let output = rect(0, 0, 1920, 1080);
+        assert_eq!(
+            cascade(&windows, &output, 16),
+            [
+                CascadeStep {
+                    node_id: 111,
+                    size: None,
+                    position: (16, 16),
+                },
+                CascadeStep {
+                    node_id: 121,
+                    size: None,
+                    position: (48, 48),
+                },
+                CascadeStep {
+                    node_id: 131,
+                    size: None,
+                    position: (80, 80),
+                },
+            ]
+        );
+
+        // On a small output the windows are shrunk to the room left.
+        let output = rect(0, 0, 800, 480);
+        let steps = cascade(&windows, &output, 16);
+        assert_eq!(steps[0].size, Some((720, 448)));
+        assert_eq!(steps[1].size, None);
+        assert_eq!(steps[2].size, Some((600, 384)));
+        assert_eq!(steps[2].position, (80, 80));
+    }
+}
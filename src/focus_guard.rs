@@ -0,0 +1,221 @@
+/*
+    Copyright (C) 2022  Biagio Festa
+
+    This program is free software: you can redistribute it and/or modify
+    it under the terms of the GNU General Public License as published by
+    the Free Software Foundation, either version 3 of the License, or
+    (at your option) any later version.
+
+    This program is distributed in the hope that it will be useful,
+    but WITHOUT ANY WARRANTY; without even the implied warranty of
+    MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+    GNU General Public License for more details.
+
+    You should have received a copy of the GNU General Public License
+    along with this program.  If not, see <https://www.gnu.org/licenses/>.
+*/
+
+use crate::command_executor::CommandExecutor;
+use crate::command_executor::RootNode;
+use crate::utilities::find_focused_node;
+use crate::utilities::find_workspace_of_node;
+use crate::utilities::is_scratchpad_workspace;
+use crate::utilities::quote_ws_name;
+use anyhow::Context;
+use anyhow::Result;
+
+/// A guard restoring the focus when dropped.
+///
+/// At construction it records the focused window and the workspace visible on each
+/// output. When dropped, those workspaces are displayed again and the window focused
+/// (best effort: workspaces and windows which no longer exist are skipped).
+///
+/// Operations touching several workspaces wrap their bodies in it, so that
+/// the user is not left on whatever workspace was touched last.
+pub struct FocusGuard {
+    /// The connection of the guarded operation (shared), used to restore the focus.
+    command_executor: CommandExecutor,
+
+    /// The recorded focus.
+    focus: Focus,
+}
+
+/// The focus recorded by a guard.
+#[derive(Default)]
+struct Focus {
+    /// The workspaces visible on the unfocused outputs.
+    visible_workspaces: Vec<String>,
+
+    /// The focused workspace.
+    focused_workspace: Option<String>,
+
+    /// The focused window.
+    focused_node_id: Option<usize>,
+}
+
+impl Focus {
+    /// The commands restoring the focus, given the names of the existing workspaces and the
+    /// current tree.
+    ///
+    /// Visible workspaces are displayed first, the focused one last; then the window is
+    /// focused unless it is gone or hidden in the scratchpad.
+    fn restore_commands(&self, workspaces: &[String], root_node: &RootNode) -> Result<Vec<String>> {
+        let mut commands = vec![];
+
+        for name in self
+            .visible_workspaces
+            .iter()
+            .chain(self.focused_workspace.iter())
+        {
+            if workspaces.contains(name) {
+                commands.push(format!(
+                    "workspace --no-auto-back-and-forth {}",
+                    quote_ws_name(name)?
+                ));
+            }
+        }
+
+        if let Some(node_id) = self.focused_node_id {
+            if find_workspace_of_node(node_id, root_node)
+                .is_some_and(|workspace| !is_scratchpad_workspace(workspace))
+            {
+                commands.push(format!("[con_id={}] focus", node_id));
+            }
+        }
+
+        Ok(commands)
+    }
+}
+
+impl FocusGuard {
+    /// Record the current focus.
+    pub fn capture(command_executor: &mut CommandExecutor) -> Result<Self> {
+        let mut guard = Self::capture_window(command_executor)?;
+
+        for workspace in command_executor.query_workspaces()? {
+            if workspace.focused {
+                guard.focus.focused_workspace = Some(workspace.name);
+            } else if workspace.visible {
+                guard.focus.visible_workspaces.push(workspace.name);
+            }
+        }
+
+        Ok(guard)
+    }
+
+    /// Record the focused window only.
+    ///
+    /// Unlike [`FocusGuard::capture`], the visible workspaces are not restored: this is meant
+    /// for operations whose purpose is to change them.
+    pub fn capture_window(command_executor: &mut CommandExecutor) -> Result<Self> {
+        let root_node = command_executor.query_root_node()?;
+        let focused_node_id = find_focused_node(&root_node).map(|node| node.id);
+
+        Ok(Self {
+            command_executor: command_executor.share(),
+            focus: Focus {
+                focused_node_id,
+                ..Focus::default()
+            },
+        })
+    }
+
+    /// Restore the recorded focus.
+    fn restore(&mut self) -> Result<()> {
+        let workspaces = self
+            .command_executor
+            .query_workspaces()?
+            .into_iter()
+            .map(|workspace| workspace.name)
+            .collect::<Vec<_>>();
+        let root_node = self.command_executor.query_root_node()?;
+
+        for command in self.focus.restore_commands(&workspaces, &root_node)? {
+            self.command_executor
+                .run(&command)
+                .with_context(|| format!("Cannot restore the focus with '{}'", command))?;
+        }
+
+        Ok(())
+    }
+}
+
+impl Drop for FocusGuard {
+    fn drop(&mut self) {
+        if let Err(error) = self.restore() {
+            eprintln!("[WARN]: Failure to restore the focus: {:?}", error);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::fixtures;
+
+    fn names(names: &[&str]) -> Vec<String> {
+        names.iter().map(|name| name.to_string()).collect()
+    }
+
+    #[test]
+    fn restore_workspaces_then_window() {
+        let focus = Focus {
+            visible_workspaces: names(&["2"]),
+            focused_workspace: Some("9: chat".to_string()),
+            focused_node_id: Some(301),
+        };
+
+        let root_node = fixtures::load("multi_output.json");
+        let workspaces = names(&["1", "2", "3", "9: chat"]);
+
+        assert_eq!(
+            focus.restore_commands(&workspaces, &root_node).unwrap(),
+            [
+                r#"workspace --no-auto-back-and-forth "2""#,
+                r#"workspace --no-auto-back-and-forth "9: chat""#,
+                "[con_id=301] focus",
+            ]
+        );
+    }
+
+    #[test]
+    fn restore_skips_vanished_targets() {
+        let focus = Focus {
+            visible_workspaces: names(&["2"]),
+            focused_workspace: Some("4".to_string()),
+            focused_node_id: Some(999),
+        };
+
+        let root_node = fixtures::load("multi_output.json");
+        let workspaces = names(&["1", "2", "3"]);
+
+        assert_eq!(
+            focus.restore_commands(&workspaces, &root_node).unwrap(),
+            [r#"workspace --no-auto-back-and-forth "2""#]
+        );
+    }
+
+    #[test]
+    fn restore_leaves_scratchpad_windows_hidden() {
+        let focus = Focus {
+            focused_node_id: Some(31),
+            ..Focus::default()
+        };
+
+        let root_node = fixtures::load("scratchpad.json");
+        assert!(focus
+            .restore_commands(&names(&["1"]), &root_node)
+            .unwrap()
+            .is_empty());
+
+        // A scratchpad window shown on a workspace is focused again.
+        let focus = Focus {
+            focused_node_id: Some(111),
+            ..Focus::default()
+        };
+        assert_eq!(
+            focus.restore_commands(&names(&["1"]), &root_node).unwrap(),
+            ["[con_id=111] focus"]
+        );
+    }
+}
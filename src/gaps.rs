@@ -0,0 +1,200 @@
+/*
+    Copyright (C) 2022  Biagio Festa
+
+    This program is free software: you can redistribute it and/or modify
+    it under the terms of the GNU General Public License as published by
+    the Free Software Foundation, either version 3 of the License, or
+    (at your option) any later version.
+
+    This program is distributed in the hope that it will be useful,
+    but WITHOUT ANY WARRANTY; without even the implied warranty of
+    MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+    GNU General Public License for more details.
+
+    You should have received a copy of the GNU General Public License
+    along with this program.  If not, see <https://www.gnu.org/licenses/>.
+*/
+use crate::command_executor::CommandExecutor;
+use crate::command_executor::I3Node;
+use crate::tabmode::TabMode;
+use crate::utilities::count_windows;
+use anyhow::anyhow;
+use anyhow::Context;
+use anyhow::Result;
+use std::fmt::Display;
+use std::str::FromStr;
+
+/// The outer gap of a workspace depending on its number of windows.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct DynamicGaps {
+    /// The gaps (pixels) for specific numbers of windows.
+    by_count: Vec<(usize, u32)>,
+
+    /// The gap (pixels) for any other number of windows.
+    default: u32,
+}
+
+impl DynamicGaps {
+    /// The gap (pixels) of a workspace with `windows` windows.
+    pub fn gap(&self, windows: usize) -> u32 {
+        self.by_count
+            .iter()
+            .find(|(count, _)| *count == windows)
+            .map_or(self.default, |(_, gap)| *gap)
+    }
+
+    /// The gap (pixels) of a workspace.
+    ///
+    /// Workspaces in tabmode get the gap of a single window.
+    pub fn gap_of(&self, workspace: &I3Node) -> u32 {
+        let windows = if TabMode::is_tabmode(workspace) {
+            1
+        } else {
+            count_windows(workspace)
+        };
+
+        self.gap(windows)
+    }
+
+    /// Whether the i3 build supports the `gaps` command (i3-gaps, or i3 4.22 and later).
+    ///
+    /// The probe is a no-op on builds with gaps.
+    pub fn is_supported(command_executor: &mut CommandExecutor) -> bool {
+        command_executor.run("gaps outer current plus 0").is_ok()
+    }
+
+    /// Set the outer gap of the focused workspace.
+    pub fn apply(gap: u32, command_executor: &mut CommandExecutor) -> Result<()> {
+        command_executor
+            .run(format!("gaps outer current set {}", gap))
+            .context("Cannot set the outer gap")
+    }
+}
+
+impl FromStr for DynamicGaps {
+    type Err = anyhow::Error;
+
+    /// Parse a mapping like `1:40,2:20,default:8`.
+    ///
+    /// Without a `default` entry, other numbers of windows get no gap.
+    fn from_str(s: &str) -> Result<Self> {
+        let mut by_count = vec![];
+        let mut default = None;
+
+        for entry in s.split(',').map(str::trim) {
+            let (count, gap) = entry
+                .split_once(':')
+                .ok_or_else(|| anyhow!("Invalid dynamic gap '{}' (expected 'COUNT:GAP')", entry))?;
+
+            let gap = gap
+                .trim()
+                .parse::<u32>()
+                .with_context(|| format!("Invalid gap '{}'", gap))?;
+
+            match count.trim() {
+                "default" if default.is_none() => default = Some(gap),
+                "default" => return Err(anyhow!("Duplicated default gap")),
+                count => {
+                    let count = count
+                        .parse::<usize>()
+                        .with_context(|| format!("Invalid window count '{}'", count))?;
+
+                    if by_count.iter().any(|(other, _)| *other == count) {
+                        return Err(anyhow!("Duplicated gap for {} windows", count));
+                    }
+
+                    by_count.push((count, gap));
+                }
+            }
+        }
+
+        Ok(Self {
+            by_count,
+            default: default.unwrap_or(0),
+        })
+    }
+}
+
+impl<'de> serde::Deserialize<'de> for DynamicGaps {
+    fn deserialize<D>(deserializer: D) -> std::result::Result<Self, D::Error>
+    where
+        D: serde::Deserializer<'de>,
+    {
+        let gaps = String::deserialize(deserializer)?;
+        gaps.parse().map_err(serde::de::Error::custom)
+    }
+}
+
+impl serde::Serialize for DynamicGaps {
+    fn serialize<S>(&self, serializer: S) -> std::result::Result<S::Ok, S::Error>
+    where
+        S: serde::Serializer,
+    {
+        serializer.serialize_str(&self.to_string())
+    }
+}
+
+impl Display for DynamicGaps {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        for (count, gap) in &self.by_count {
+            write!(f, "{}:{},", count, gap)?;
+        }
+
+        write!(f, "default:{}", self.default)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::fixtures;
+
+    #[test]
+    fn parse_and_display() {
+        let gaps = "1:40, 2:20,default:8".parse::<DynamicGaps>().unwrap();
+        assert_eq!(
+            gaps,
+            DynamicGaps {
+                by_count: vec![(1, 40), (2, 20)],
+                default: 8,
+            }
+        );
+        assert_eq!(gaps.to_string(), "1:40,2:20,default:8");
+        assert_eq!(gaps.to_string().parse::<DynamicGaps>().unwrap(), gaps);
+
+        let gaps = serde_json::from_str::<DynamicGaps>("\"1:40\"").unwrap();
+        assert_eq!(gaps.to_string(), "1:40,default:0");
+    }
+
+    #[test]
+    fn parse_errors() {
+        for (gaps, expected) in [
+            ("1=40", "Invalid dynamic gap '1=40' (expected 'COUNT:GAP')"),
+            ("1:wide", "Invalid gap 'wide'"),
+            ("one:40", "Invalid window count 'one'"),
+            ("1:40,1:20", "Duplicated gap for 1 windows"),
+            ("default:8,default:4", "Duplicated default gap"),
+        ] {
+            let error = gaps.parse::<DynamicGaps>().unwrap_err();
+            assert_eq!(error.to_string(), expected, "Gaps '{}'", gaps);
+        }
+    }
+
+    #[test]
+    fn gap_of_workspaces() {
+        let root_node = fixtures::load("workspaces.json");
+        let gaps = "1:40,3:20,default:8".parse::<DynamicGaps>().unwrap();
+
+        // Three windows.
+        assert_eq!(gaps.gap_of(fixtures::node(&root_node, 100)), 20);
+
+        // Four windows, nested.
+        assert_eq!(gaps.gap_of(fixtures::node(&root_node, 200)), 8);
+
+        // Tab mode counts as a single window.
+        assert_eq!(gaps.gap_of(fixtures::node(&root_node, 300)), 40);
+
+        // Placeholders are not windows.
+        assert_eq!(gaps.gap_of(fixtures::node(&root_node, 500)), 8);
+    }
+}
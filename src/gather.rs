@@ -0,0 +1,262 @@
+/*
+    Copyright (C) 2022  Biagio Festa
+
+    This program is free software: you can redistribute it and/or modify
+    it under the terms of the GNU General Public License as published by
+    the Free Software Foundation, either version 3 of the License, or
+    (at your option) any later version.
+
+    This program is distributed in the hope that it will be useful,
+    but WITHOUT ANY WARRANTY; without even the implied warranty of
+    MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+    GNU General Public License for more details.
+
+    You should have received a copy of the GNU General Public License
+    along with this program.  If not, see <https://www.gnu.org/licenses/>.
+*/
+
+use crate::command_executor::CommandExecutor;
+use crate::command_executor::I3Node;
+use crate::command_executor::RootNode;
+use crate::focus_guard::FocusGuard;
+use crate::selector::Criteria;
+use crate::undo::record_undo;
+use crate::utilities::balance_subtree;
+use crate::utilities::find_workspaces;
+use crate::utilities::is_scratchpad_workspace;
+use crate::utilities::is_window;
+use crate::utilities::ShareRatio;
+use crate::utilities::DEFAULT_SHARE_TOLERANCE;
+use crate::workspace::resolve_workspace_arg;
+use crate::workspace::WorkspaceRef;
+use anyhow::anyhow;
+use anyhow::Context;
+use anyhow::Result;
+
+/// Gather executor.
+///
+/// It represents a one-shot executor which collects the windows matching
+/// some criteria on a single workspace.
+pub struct Gather {
+    /// Command executor.
+    command_executor: CommandExecutor,
+
+    /// The tolerance (percentage points) of the balance.
+    tolerance: f64,
+
+    /// How the balance divides the space of the containers.
+    ratio: ShareRatio,
+}
+
+impl Gather {
+    /// A new gather executor.
+    pub fn new(command_executor: CommandExecutor) -> Self {
+        Self {
+            command_executor,
+            tolerance: DEFAULT_SHARE_TOLERANCE,
+            ratio: ShareRatio::Equal,
+        }
+    }
+
+    /// How the balance divides the space of the containers (see `ratio_shares`).
+    pub fn with_ratio(mut self, ratio: ShareRatio) -> Self {
+        self.ratio = ratio;
+        self
+    }
+
+    /// The tolerance (percentage points) of the shares after balancing (see `verify_shares`).
+    pub fn with_tolerance(mut self, tolerance: f64) -> Self {
+        self.tolerance = tolerance;
+        self
+    }
+
+    /// Execute the action.
+    ///
+    /// All windows matching `criteria` (scratchpad included) are moved to the workspace
+    /// `to` (created if missing). If `None` the currently focused workspace will be used.
+    ///
+    /// With `dry_run` the windows are only listed. With `balance` the target workspace
+    /// is balanced after the windows have been moved.
+    pub fn execute(
+        mut self,
+        criteria: &Criteria,
+        to: Option<WorkspaceRef>,
+        dry_run: bool,
+        balance: bool,
+    ) -> Result<()> {
+        if criteria.is_empty() {
+            return Err(anyhow!("At least one criterion must be specified"));
+        }
+
+        let root_node = self.command_executor.query_root_node()?;
+
+        let target = match (
+            resolve_workspace_arg(to.as_ref(), &root_node, &mut self.command_executor),
+            to,
+        ) {
+            (Ok(workspace), _) => WorkspaceRef::of(workspace),
+            (Err(_), Some(to @ (WorkspaceRef::Num(_) | WorkspaceRef::Name(_)))) => to,
+            (Err(error), _) => return Err(error),
+        };
+        let move_to_target = format!("move container to {}", target.to_i3_selector()?);
+        let matches = Self::find_matching_windows(&root_node, criteria, &target);
+
+        if matches.is_empty() {
+            eprintln!("No windows match the criteria");
+            return Ok(());
+        }
+
+        let _focus_guard = if dry_run {
+            None
+        } else {
+            Self::record_undo(&root_node, &matches, &target);
+            Some(FocusGuard::capture(&mut self.command_executor)?)
+        };
+
+        for (window, workspace_name) in matches {
+            eprintln!(
+                "{} window [{}; {:?}] from workspace '{}' to '{}'",
+                if dry_run { "Would move" } else { "Moving" },
+                window.id,
+                window.name,
+                workspace_name,
+                target
+            );
+
+            if !dry_run {
+                self.command_executor
+                    .run_on_node_id(window.id, &move_to_target)
+                    .context("Cannot move window on target workspace")?;
+            }
+        }
+
+        if balance && !dry_run {
+            let root_node = self.command_executor.query_root_node()?;
+            let workspace = target.resolve(&root_node, &mut self.command_executor)?;
+
+            balance_subtree(
+                workspace,
+                &self.ratio,
+                self.tolerance,
+                &mut self.command_executor,
+            )
+            .context("Cannot balance the workspace")?;
+        }
+
+        Ok(())
+    }
+
+    /// Push a snapshot of the workspaces involved (target and sources) on the undo stack.
+    fn record_undo(root_node: &RootNode, matches: &[(&I3Node, &str)], target: &WorkspaceRef) {
+        record_undo(&Self::involved_workspaces(root_node, matches, target));
+    }
+
+    /// The workspaces changed by moving the matching windows: the target and the sources.
+    fn involved_workspaces<'a>(
+        root_node: &'a RootNode,
+        matches: &[(&I3Node, &str)],
+        target: &WorkspaceRef,
+    ) -> Vec<&'a I3Node> {
+        find_workspaces(root_node)
+            .into_iter()
+            .filter(|workspace| !is_scratchpad_workspace(workspace))
+            .filter(|workspace| {
+                let name = workspace.name.as_deref().unwrap_or_default();
+
+                target.designates(workspace)
+                    || matches
+                        .iter()
+                        .any(|(_, workspace_name)| *workspace_name == name)
+            })
+            .collect()
+    }
+
+    /// Find the windows matching the criteria which are not on the target workspace yet.
+    ///
+    /// Each window is paired with the name of the workspace containing it.
+    fn find_matching_windows<'a>(
+        root_node: &'a RootNode,
+        criteria: &Criteria,
+        target: &WorkspaceRef,
+    ) -> Vec<(&'a I3Node, &'a str)> {
+        let mut matches = vec![];
+
+        for workspace in find_workspaces(root_node) {
+            if target.designates(workspace) {
+                continue;
+            }
+
+            let workspace_name = workspace.name.as_deref().unwrap_or_default();
+
+            let mut dfs = vec![workspace];
+
+            while let Some(current) = dfs.pop() {
+                if is_window(current) && criteria.matches(current, workspace) {
+                    matches.push((current, workspace_name));
+                }
+
+                dfs.extend(current.nodes.as_slice());
+                dfs.extend(current.floating_nodes.as_slice());
+            }
+        }
+
+        matches
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::fixtures;
+
+    fn matches(criteria: &str, target: &WorkspaceRef) -> Vec<(usize, String)> {
+        let root_node = fixtures::load("workspaces.json");
+        let criteria = criteria.parse::<Criteria>().unwrap();
+
+        Gather::find_matching_windows(&root_node, &criteria, target)
+            .into_iter()
+            .map(|(window, workspace_name)| (window.id, workspace_name.to_string()))
+            .collect()
+    }
+
+    #[test]
+    fn matching_windows_skip_the_target_workspace() {
+        let mut firefox = matches("[class=Firefox]", &WorkspaceRef::Num(2));
+        firefox.sort();
+        assert_eq!(
+            firefox,
+            [
+                (101, "1".to_string()),
+                (311, "3 []".to_string()),
+                (411, "web".to_string()),
+            ]
+        );
+
+        // Already on the target.
+        let mail = matches("[title=Mail]", &WorkspaceRef::Name("web".to_string()));
+        assert!(mail.is_empty());
+
+        // Nested windows are found too.
+        assert_eq!(
+            matches("[class=Zathura title~=^notes]", &WorkspaceRef::Num(1)),
+            [(222, "2".to_string())]
+        );
+    }
+
+    #[test]
+    fn undo_covers_target_and_source_workspaces() {
+        let root_node = fixtures::load("workspaces.json");
+        let criteria = "[class=Alacritty]".parse::<Criteria>().unwrap();
+        let target = WorkspaceRef::Name("web".to_string());
+
+        let matches = Gather::find_matching_windows(&root_node, &criteria, &target);
+        let mut workspace_ids = Gather::involved_workspaces(&root_node, &matches, &target)
+            .iter()
+            .map(|workspace| workspace.id)
+            .collect::<Vec<_>>();
+        workspace_ids.sort();
+
+        // Workspaces 5 and 6 hold no terminal.
+        assert_eq!(workspace_ids, [100, 200, 300, 400]);
+    }
+}
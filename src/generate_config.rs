@@ -0,0 +1,373 @@
+/*
+    Copyright (C) 2022  Biagio Festa
+
+    This program is free software: you can redistribute it and/or modify
+    it under the terms of the GNU General Public License as published by
+    the Free Software Foundation, either version 3 of the License, or
+    (at your option) any later version.
+
+    This program is distributed in the hope that it will be useful,
+    but WITHOUT ANY WARRANTY; without even the implied warranty of
+    MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+    GNU General Public License for more details.
+
+    You should have received a copy of the GNU General Public License
+    along with this program.  If not, see <https://www.gnu.org/licenses/>.
+*/
+
+use anyhow::Context;
+use anyhow::Result;
+use std::collections::HashMap;
+use std::fmt::Write;
+use std::path::Path;
+
+/// The name of the i3 binding mode entered with the prefix key.
+const MODE_NAME: &str = "i3-autolayout";
+
+/// Keys bound inside the i3-autolayout binding mode.
+const MODE_KEYS: &[&str] = &["t", "s", "r", "p", "Shift+p", "Escape", "Return"];
+
+/// The name of the layout saved and restored by the bindings (see `LayoutStore`).
+const STORE_LAYOUT_NAME: &str = "quick";
+
+/// Options for the generated i3 config snippet.
+pub struct ConfigTemplate<'a> {
+    /// Path of the i3-autolayout binary.
+    pub binary: &'a Path,
+
+    /// The modifier key used to replace `$mod` in the prefix.
+    pub modifier: &'a str,
+
+    /// The key combination which enters the i3-autolayout binding mode.
+    pub prefix: &'a str,
+
+    /// The directory where tabmode saves the layouts (one file per workspace).
+    pub layout_dir: &'a str,
+}
+
+impl<'a> ConfigTemplate<'a> {
+    /// The prefix key combination with `$mod` expanded.
+    fn prefix_key(&self) -> String {
+        self.prefix.replace("$mod", self.modifier)
+    }
+
+    /// Render the i3 config block.
+    ///
+    /// Tabmode keeps one layout file per workspace in `layout_dir`; the save and restore
+    /// bindings use the layout store.
+    pub fn render(&self) -> String {
+        let binary = shell_quote(&self.binary.to_string_lossy());
+        let layout_dir = self.layout_dir.trim_end_matches('/');
+        let mut out = String::new();
+
+        let _ = writeln!(out, "# --- i3-autolayout (generated) ---");
+        let _ = writeln!(out, "exec_always --no-startup-id {} autolayout", binary);
+        let _ = writeln!(out, "bindsym {} mode \"{}\"", self.prefix_key(), MODE_NAME);
+        let _ = writeln!(out, "mode \"{}\" {{", MODE_NAME);
+        let _ = writeln!(
+            out,
+            "    bindsym t exec --no-startup-id {bin} tabmode --file-layout {dir}/tabmode/; mode \"default\"",
+            dir = layout_dir,
+            bin = binary
+        );
+        let _ = writeln!(
+            out,
+            "    bindsym s exec --no-startup-id {bin} save-layout --name {name}; mode \"default\"",
+            name = STORE_LAYOUT_NAME,
+            bin = binary
+        );
+        let _ = writeln!(
+            out,
+            "    bindsym r exec --no-startup-id {bin} restore-layout --name {name} --restore-sizes; mode \"default\"",
+            name = STORE_LAYOUT_NAME,
+            bin = binary
+        );
+        let _ = writeln!(
+            out,
+            "    bindsym p exec --no-startup-id {bin} ctl manage focused off; mode \"default\"",
+            bin = binary
+        );
+        let _ = writeln!(
+            out,
+            "    bindsym Shift+p exec --no-startup-id {bin} ctl manage focused on; mode \"default\"",
+            bin = binary
+        );
+        let _ = writeln!(out, "    bindsym Escape mode \"default\"");
+        let _ = writeln!(out, "    bindsym Return mode \"default\"");
+        let _ = writeln!(out, "}}");
+        let _ = writeln!(out, "# --- i3-autolayout (end) ---");
+
+        out
+    }
+
+    /// Check an existing i3 config for bindings conflicting with the template.
+    ///
+    /// It returns a list of human-readable warnings (empty if no conflict).
+    pub fn check(&self, config: &str) -> Vec<String> {
+        let bindings = parse_bindings(config);
+        let prefix_key = normalize_key(&self.prefix_key(), &HashMap::new());
+        let mut warnings = vec![];
+
+        // The generated block itself (already installed) is no conflict.
+        let generated = parse_bindings(&self.render());
+
+        for binding in bindings {
+            if generated
+                .iter()
+                .any(|generated| generated.same_as(&binding))
+            {
+                continue;
+            }
+
+            match binding.mode.as_deref() {
+                None if binding.key == prefix_key => {
+                    warnings.push(format!(
+                        "line {}: '{}' is already bound to '{}'",
+                        binding.line, self.prefix, binding.command
+                    ));
+                }
+
+                Some(MODE_NAME) if !binding.command.contains("i3-autolayout") => {
+                    let conflicting = MODE_KEYS
+                        .iter()
+                        .any(|key| normalize_key(key, &HashMap::new()) == binding.key);
+
+                    if conflicting {
+                        warnings.push(format!(
+                            "line {}: mode '{}' already binds '{}' to '{}'",
+                            binding.line, MODE_NAME, binding.key, binding.command
+                        ));
+                    }
+                }
+
+                _ => {}
+            }
+        }
+
+        warnings
+    }
+}
+
+/// Quote a word for the shell running the `exec` commands of i3 (`/bin/sh -c`).
+fn shell_quote(word: &str) -> String {
+    format!("'{}'", word.replace('\'', "'\\''"))
+}
+
+/// Read the i3 config file located at `path`.
+pub fn read_config(path: &Path) -> Result<String> {
+    std::fs::read_to_string(path)
+        .with_context(|| format!("Cannot read i3 config file '{}'", path.display()))
+}
+
+/// A `bindsym` directive found in the i3 config.
+struct Binding {
+    /// The line number (1-based).
+    line: usize,
+
+    /// The binding mode which contains the binding (`None` for the default mode).
+    mode: Option<String>,
+
+    /// The normalized key combination.
+    key: String,
+
+    /// The bound command.
+    command: String,
+}
+
+impl Binding {
+    /// Whether `other` binds the same key to the same command in the same mode.
+    fn same_as(&self, other: &Binding) -> bool {
+        self.mode == other.mode && self.key == other.key && self.command == other.command
+    }
+}
+
+/// Parse all `bindsym` directives of an i3 config.
+///
+/// `set $var value` directives are expanded in key combinations.
+fn parse_bindings(config: &str) -> Vec<Binding> {
+    let mut variables = HashMap::new();
+    let mut bindings = vec![];
+    let mut mode = None;
+
+    for (index, line) in config.lines().enumerate() {
+        let line = line.trim();
+        let mut tokens = line.split_whitespace();
+
+        match tokens.next() {
+            Some("set") => {
+                if let (Some(name), Some(value)) = (tokens.next(), tokens.next()) {
+                    variables.insert(name.to_string(), value.to_string());
+                }
+            }
+
+            Some("mode") if line.ends_with('{') => {
+                let name = line["mode".len()..line.len() - 1]
+                    .trim()
+                    .trim_start_matches("--pango_markup")
+                    .trim()
+                    .trim_matches('"');
+                mode = Some(name.to_string());
+            }
+
+            Some("}") => {
+                mode = None;
+            }
+
+            Some("bindsym") => {
+                let key = tokens.find(|token| !token.starts_with("--"));
+
+                if let Some(key) = key {
+                    bindings.push(Binding {
+                        line: index + 1,
+                        mode: mode.clone(),
+                        key: normalize_key(key, &variables),
+                        command: tokens.collect::<Vec<_>>().join(" "),
+                    });
+                }
+            }
+
+            _ => {}
+        }
+    }
+
+    bindings
+}
+
+/// Normalize a key combination so that equivalent combinations compare equal.
+///
+/// Variables are expanded, modifiers are sorted and the comparison is case-insensitive.
+fn normalize_key(key: &str, variables: &HashMap<String, String>) -> String {
+    let mut parts = key
+        .split('+')
+        .map(|part| {
+            variables
+                .get(part)
+                .map(String::as_str)
+                .unwrap_or(part)
+                .to_lowercase()
+        })
+        .collect::<Vec<_>>();
+
+    let symbol = parts.pop().unwrap_or_default();
+    parts.sort();
+    parts.push(symbol);
+    parts.join("+")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn template(binary: &Path) -> ConfigTemplate<'_> {
+        ConfigTemplate {
+            binary,
+            modifier: "Mod4",
+            prefix: "$mod+t",
+            layout_dir: "~/.cache/i3-autolayout/",
+        }
+    }
+
+    #[test]
+    fn render_quotes_the_binary() {
+        let binary = Path::new("/opt/my tools/i3-autolayout");
+        let rendered = template(binary).render();
+
+        assert!(rendered
+            .contains("exec_always --no-startup-id '/opt/my tools/i3-autolayout' autolayout"));
+        assert!(rendered.contains("bindsym Mod4+t mode \"i3-autolayout\""));
+
+        // One tabmode layout per workspace; save and restore go through the layout store.
+        assert!(rendered.contains("tabmode --file-layout ~/.cache/i3-autolayout/tabmode/;"));
+        assert!(rendered.contains("save-layout --name quick;"));
+        assert!(rendered.contains("restore-layout --name quick --restore-sizes;"));
+        assert!(rendered.contains(
+            "bindsym p exec --no-startup-id '/opt/my tools/i3-autolayout' ctl manage focused off;"
+        ));
+        assert!(rendered.contains("bindsym Shift+p exec --no-startup-id '/opt/my tools/i3-autolayout' ctl manage focused on;"));
+        assert!(!rendered.contains("saved.layout"));
+
+        // Every key of the mode is rendered.
+        for key in MODE_KEYS {
+            assert!(
+                rendered.contains(&format!("    bindsym {} ", key)),
+                "{}",
+                key
+            );
+        }
+
+        assert_eq!(shell_quote("it's"), r"'it'\''s'");
+    }
+
+    #[test]
+    fn normalize_equivalent_keys() {
+        let variables = HashMap::from([("$mod".to_string(), "Mod4".to_string())]);
+
+        assert_eq!(normalize_key("$mod+t", &variables), "mod4+t");
+        assert_eq!(normalize_key("Shift+$mod+T", &variables), "mod4+shift+t");
+        assert_eq!(normalize_key("Mod4+Shift+t", &variables), "mod4+shift+t");
+        assert_eq!(normalize_key("Escape", &HashMap::new()), "escape");
+
+        // Undefined variables are kept.
+        assert_eq!(normalize_key("$alt+x", &variables), "$alt+x");
+    }
+
+    #[test]
+    fn parse_bindings_with_modes_and_variables() {
+        let config = "\
+            set $mod Mod1\n\
+            bindsym $mod+Return exec i3-sensible-terminal\n\
+            bindsym --release $mod+x kill\n\
+            mode --pango_markup \"resize\" {\n\
+                bindsym h resize shrink width 10 px\n\
+            }\n\
+            # bindsym $mod+q kill\n";
+        let bindings = parse_bindings(config);
+
+        let described = bindings
+            .iter()
+            .map(|binding| {
+                (
+                    binding.line,
+                    binding.mode.as_deref(),
+                    binding.key.as_str(),
+                    binding.command.as_str(),
+                )
+            })
+            .collect::<Vec<_>>();
+        assert_eq!(
+            described,
+            [
+                (2, None, "mod1+return", "exec i3-sensible-terminal"),
+                (3, None, "mod1+x", "kill"),
+                (5, Some("resize"), "h", "resize shrink width 10 px"),
+            ]
+        );
+    }
+
+    #[test]
+    fn check_reports_conflicts() {
+        let binary = Path::new("/usr/bin/i3-autolayout");
+        let config = "\
+            set $m Mod4\n\
+            bindsym $m+t layout tabbed\n\
+            bindsym $m+Shift+t layout stacking\n\
+            mode \"i3-autolayout\" {\n\
+                bindsym shift+P exec notify-send paused\n\
+                bindsym r exec i3-autolayout restore-layout\n\
+                bindsym x exec other\n\
+            }\n";
+
+        assert_eq!(
+            template(binary).check(config),
+            [
+                "line 2: '$mod+t' is already bound to 'layout tabbed'",
+                "line 5: mode 'i3-autolayout' already binds 'shift+p' to 'exec notify-send paused'",
+            ]
+        );
+
+        // The generated block does not conflict with itself.
+        assert!(template(binary)
+            .check(&template(binary).render())
+            .is_empty());
+    }
+}
@@ -0,0 +1,280 @@
+/*
+    Copyright (C) 2022  Biagio Festa
+
+    This program is free software: you can redistribute it and/or modify
+    it under the terms of the GNU General Public License as published by
+    the Free Software Foundation, either version 3 of the License, or
+    (at your option) any later version.
+
+    This program is distributed in the hope that it will be useful,
+    but WITHOUT ANY WARRANTY; without even the implied warranty of
+    MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+    GNU General Public License for more details.
+
+    You should have received a copy of the GNU General Public License
+    along with this program.  If not, see <https://www.gnu.org/licenses/>.
+*/
+
+use crate::command_executor::CommandExecutor;
+use crate::utilities::config_dir;
+use crate::utilities::quote;
+use crate::workspace::WorkspaceRef;
+use anyhow::anyhow;
+use anyhow::Context;
+use anyhow::Result;
+use std::collections::BTreeMap;
+use std::path::PathBuf;
+use std::str::FromStr;
+
+/// A workspace belonging to a group, together with the output where it is displayed.
+#[derive(Clone, serde::Deserialize, serde::Serialize)]
+pub struct GroupMember {
+    /// The workspace name.
+    pub workspace: String,
+
+    /// The output name.
+    pub output: String,
+}
+
+impl FromStr for GroupMember {
+    type Err = anyhow::Error;
+
+    /// Parse a member in the form `<workspace>:<output>`.
+    fn from_str(s: &str) -> Result<Self> {
+        let (workspace, output) = s
+            .rsplit_once(':')
+            .filter(|(workspace, output)| !workspace.is_empty() && !output.is_empty())
+            .ok_or_else(|| anyhow!("Invalid group member '{}' (expected WORKSPACE:OUTPUT)", s))?;
+
+        Ok(Self {
+            workspace: workspace.to_string(),
+            output: output.to_string(),
+        })
+    }
+}
+
+/// A named set of workspaces.
+#[derive(serde::Deserialize, serde::Serialize)]
+struct Group {
+    members: Vec<GroupMember>,
+}
+
+impl Group {
+    /// The output each member is displayed on: its own if active, otherwise the focused one.
+    fn outputs<'a>(
+        &'a self,
+        active_outputs: &[String],
+        focused_output: &'a str,
+    ) -> Vec<(&'a GroupMember, &'a str)> {
+        self.members
+            .iter()
+            .map(|member| {
+                if active_outputs.contains(&member.output) {
+                    (member, member.output.as_str())
+                } else {
+                    eprintln!(
+                        "[WARN]: Unknown output '{}' for workspace '{}'; using '{}'",
+                        member.output, member.workspace, focused_output
+                    );
+                    (member, focused_output)
+                }
+            })
+            .collect()
+    }
+}
+
+/// The groups file content.
+#[derive(Default, serde::Deserialize, serde::Serialize)]
+struct GroupsFile {
+    #[serde(default)]
+    groups: BTreeMap<String, Group>,
+}
+
+/// Workspace groups executor.
+///
+/// Groups are persisted in the configuration directory (`groups.toml`).
+pub struct Groups {
+    path: PathBuf,
+    file: GroupsFile,
+}
+
+impl Groups {
+    const FILE_NAME: &'static str = "groups.toml";
+
+    /// Load the groups from the configuration directory.
+    pub fn load() -> Result<Self> {
+        Self::load_from(config_dir()?.join(Self::FILE_NAME))
+    }
+
+    /// Load the groups from a file (missing means none).
+    fn load_from(path: PathBuf) -> Result<Self> {
+        let file = if path.exists() {
+            let content = std::fs::read_to_string(&path)
+                .with_context(|| format!("Cannot read groups file '{}'", path.display()))?;
+
+            toml::from_str(&content)
+                .with_context(|| format!("Cannot parse groups file '{}'", path.display()))?
+        } else {
+            GroupsFile::default()
+        };
+
+        Ok(Self { path, file })
+    }
+
+    /// Define (or redefine) a group and persist it.
+    pub fn define(mut self, name: String, members: Vec<GroupMember>) -> Result<()> {
+        if members.is_empty() {
+            return Err(anyhow!("A group needs at least one workspace"));
+        }
+
+        self.file.groups.insert(name, Group { members });
+
+        let content = toml::to_string(&self.file).context("Cannot serialize groups")?;
+
+        std::fs::write(&self.path, content)
+            .with_context(|| format!("Cannot write groups file '{}'", self.path.display()))
+    }
+
+    /// Print all defined groups.
+    pub fn list(&self) {
+        for (name, group) in &self.file.groups {
+            let members = group
+                .members
+                .iter()
+                .map(|member| format!("{}:{}", member.workspace, member.output))
+                .collect::<Vec<_>>();
+
+            println!("{}: {}", name, members.join(" "));
+        }
+    }
+
+    /// The names of the defined groups.
+    pub fn names(&self) -> Vec<String> {
+        self.file.groups.keys().cloned().collect()
+    }
+
+    /// The names of the workspaces belonging to a group.
+    pub fn workspaces(&self, name: &str) -> Result<Vec<String>> {
+        self.file
+            .groups
+            .get(name)
+            .map(|group| {
+                group
+                    .members
+                    .iter()
+                    .map(|member| member.workspace.clone())
+                    .collect()
+            })
+            .ok_or_else(|| anyhow!("Cannot find the group '{}'", name))
+    }
+
+    /// Activate a group.
+    ///
+    /// Each workspace of the group is displayed on its output. Outputs not mentioned
+    /// by the group are left untouched.
+    pub fn activate(&self, name: &str, command_executor: &mut CommandExecutor) -> Result<()> {
+        let group = self
+            .file
+            .groups
+            .get(name)
+            .ok_or_else(|| anyhow!("Cannot find the group '{}'", name))?;
+
+        let active_outputs = command_executor
+            .query_outputs()?
+            .into_iter()
+            .filter(|output| output.active)
+            .map(|output| output.name)
+            .collect::<Vec<_>>();
+
+        let focused_output = command_executor
+            .query_workspaces()?
+            .into_iter()
+            .find(|workspace| workspace.focused)
+            .map(|workspace| workspace.output)
+            .ok_or_else(|| anyhow!("Cannot detect the current focused workspace"))?;
+
+        for (member, output) in group.outputs(&active_outputs, &focused_output) {
+            command_executor
+                .run(WorkspaceRef::from_str(&member.workspace)?.to_i3_selector()?)
+                .with_context(|| format!("Cannot focus workspace '{}'", member.workspace))?;
+
+            command_executor
+                .run(format!("move workspace to output {}", quote(output)))
+                .with_context(|| {
+                    format!(
+                        "Cannot move workspace '{}' on output '{}'",
+                        member.workspace, output
+                    )
+                })?;
+        }
+
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::fixtures::TempDir;
+
+    fn members(members: &[&str]) -> Vec<GroupMember> {
+        members
+            .iter()
+            .map(|member| member.parse().unwrap())
+            .collect()
+    }
+
+    #[test]
+    fn parse_members() {
+        let member = "3: mail:HDMI-1".parse::<GroupMember>().unwrap();
+        assert_eq!(member.workspace, "3: mail");
+        assert_eq!(member.output, "HDMI-1");
+
+        for invalid in ["web", ":HDMI-1", "web:"] {
+            assert!(invalid.parse::<GroupMember>().is_err(), "{:?}", invalid);
+        }
+    }
+
+    #[test]
+    fn groups_persist() {
+        let dir = TempDir::new("groups");
+        let path = dir.path().join(Groups::FILE_NAME);
+
+        let groups = Groups::load_from(path.clone()).unwrap();
+        assert!(groups.names().is_empty());
+        assert!(groups
+            .define("empty".to_string(), vec![])
+            .unwrap_err()
+            .to_string()
+            .contains("at least one"));
+
+        let groups = Groups::load_from(path.clone()).unwrap();
+        groups
+            .define("work".to_string(), members(&["1:eDP-1", "2:HDMI-1"]))
+            .unwrap();
+
+        let groups = Groups::load_from(path).unwrap();
+        assert_eq!(groups.names(), ["work"]);
+        assert_eq!(groups.workspaces("work").unwrap(), ["1", "2"]);
+        assert!(groups.workspaces("play").is_err());
+    }
+
+    #[test]
+    fn inactive_outputs_fall_back_to_the_focused_one() {
+        let group = Group {
+            members: members(&["1:eDP-1", "2:HDMI-1", "9: chat:DP-3"]),
+        };
+        let active_outputs = ["eDP-1".to_string(), "HDMI-1".to_string()];
+
+        let outputs = group
+            .outputs(&active_outputs, "eDP-1")
+            .into_iter()
+            .map(|(member, output)| (member.workspace.as_str(), output))
+            .collect::<Vec<_>>();
+
+        assert_eq!(
+            outputs,
+            [("1", "eDP-1"), ("2", "HDMI-1"), ("9: chat", "eDP-1")]
+        );
+    }
+}
@@ -0,0 +1,650 @@
+/*
+    Copyright (C) 2022  Biagio Festa
+
+    This program is free software: you can redistribute it and/or modify
+    it under the terms of the GNU General Public License as published by
+    the Free Software Foundation, either version 3 of the License, or
+    (at your option) any later version.
+
+    This program is distributed in the hope that it will be useful,
+    but WITHOUT ANY WARRANTY; without even the implied warranty of
+    MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+    GNU General Public License for more details.
+
+    You should have received a copy of the GNU General Public License
+    along with this program.  If not, see <https://www.gnu.org/licenses/>.
+*/
+use crate::atomic_file::AtomicFile;
+use crate::command_executor::CommandExecutor;
+use crate::command_executor::I3Node;
+use crate::command_executor::RootNode;
+use crate::restore_layout::RestoreLayout;
+use crate::save_layout::SaveLayout;
+use crate::save_layout::SavedLayout;
+use crate::utilities::find_workspace_of_node;
+use crate::utilities::find_workspaces;
+use crate::utilities::format_age;
+use crate::utilities::is_scratchpad_workspace;
+use crate::utilities::state_dir;
+use crate::workspace::WorkspaceRef;
+use anyhow::anyhow;
+use anyhow::Context;
+use anyhow::Result;
+use std::collections::HashMap;
+use std::io::Write;
+use std::path::Path;
+use std::path::PathBuf;
+use std::time::Duration;
+use std::time::SystemTime;
+use std::time::UNIX_EPOCH;
+
+/// How many snapshots of each workspace are kept.
+#[derive(Clone, Copy)]
+pub struct Retention {
+    /// The maximum number of snapshots of a workspace.
+    pub count: usize,
+
+    /// Snapshots older than this are dropped (if any).
+    pub max_age: Option<Duration>,
+}
+
+impl Retention {
+    /// The default number of snapshots kept for each workspace.
+    pub const DEFAULT_COUNT: usize = 20;
+}
+
+impl Default for Retention {
+    fn default() -> Self {
+        Self {
+            count: Self::DEFAULT_COUNT,
+            max_age: None,
+        }
+    }
+}
+
+/// The last known layout of each workspace holding windows.
+///
+/// When the last window of a workspace is closed (or moved away), the tree no longer holds
+/// its layout: the last known one is snapshotted instead (see `take_emptied`).
+#[derive(Default)]
+pub struct LastLayouts(HashMap<usize, I3Node>);
+
+impl LastLayouts {
+    /// Remember the layout of the workspace holding a node (if it holds windows).
+    pub fn remember(&mut self, root_node: &RootNode, node_id: usize) {
+        if let Some(workspace) = find_workspace_of_node(node_id, root_node) {
+            if !workspace.nodes.is_empty() {
+                self.0.insert(workspace.id, workspace.clone());
+            }
+        }
+    }
+
+    /// The last known layouts of the workspaces emptied (or removed) meanwhile, which are
+    /// forgotten.
+    pub fn take_emptied(&mut self, root_node: &RootNode) -> Vec<I3Node> {
+        let workspaces = find_workspaces(root_node);
+
+        let emptied = self
+            .0
+            .keys()
+            .filter(|&&workspace_id| {
+                !workspaces
+                    .iter()
+                    .any(|workspace| workspace.id == workspace_id && !workspace.nodes.is_empty())
+            })
+            .copied()
+            .collect::<Vec<_>>();
+
+        let mut layouts = emptied
+            .into_iter()
+            .filter_map(|workspace_id| self.0.remove(&workspace_id))
+            .collect::<Vec<_>>();
+        layouts.sort_by_key(|workspace| workspace.id);
+
+        layouts
+    }
+}
+
+/// A snapshot of the history.
+pub struct Snapshot {
+    /// The snapshot file.
+    pub path: PathBuf,
+
+    /// The workspace of the snapshot (as in the file name).
+    pub workspace: String,
+
+    /// When the snapshot has been taken (seconds since the Unix epoch).
+    pub timestamp: u64,
+
+    /// The number of windows of the workspace.
+    pub windows: usize,
+}
+
+impl Snapshot {
+    /// A one-line description (e.g., `1700000000 (3 windows, 2h ago)`).
+    pub fn describe(&self) -> String {
+        let age = SystemTime::now()
+            .duration_since(UNIX_EPOCH + Duration::from_secs(self.timestamp))
+            .unwrap_or_default();
+
+        format!(
+            "{} ({} windows, {} ago)",
+            self.timestamp,
+            self.windows,
+            format_age(age)
+        )
+    }
+}
+
+/// The periodic snapshots of the workspace layouts.
+///
+/// Snapshots are kept in binary format in the state directory, one directory per workspace
+/// (`history/<WORKSPACE>/<TIMESTAMP>-<WINDOWS>.layout`).
+pub struct LayoutHistory {
+    dir: PathBuf,
+    retention: Retention,
+}
+
+impl LayoutHistory {
+    const DIR_NAME: &'static str = "history";
+    const FILE_SUFFIX: &'static str = ".layout";
+    const JSON: bool = false;
+
+    /// Open the history in the state directory.
+    pub fn open(retention: Retention) -> Result<Self> {
+        Self::in_dir(state_dir()?.join(Self::DIR_NAME), retention)
+    }
+
+    /// Open the history in a directory other than the state directory (e.g., for tests).
+    pub fn in_dir(dir: PathBuf, retention: Retention) -> Result<Self> {
+        std::fs::create_dir_all(&dir)
+            .with_context(|| format!("Cannot create the directory '{}'", dir.display()))?;
+
+        Ok(Self { dir, retention })
+    }
+
+    /// Take a snapshot of every (non-empty) workspace.
+    ///
+    /// It returns the number of snapshots written.
+    pub fn record_all(&self, root_node: &RootNode) -> Result<usize> {
+        let mut written = 0;
+
+        for workspace in find_workspaces(root_node) {
+            if is_scratchpad_workspace(workspace) || workspace.nodes.is_empty() {
+                continue;
+            }
+
+            if self.record(workspace)? {
+                written += 1;
+            }
+        }
+
+        Ok(written)
+    }
+
+    /// Take a snapshot of a workspace, then prune its old snapshots.
+    ///
+    /// Nothing is written if the layout is the same as the most recent snapshot (it
+    /// returns `false`).
+    pub fn record(&self, workspace: &I3Node) -> Result<bool> {
+        let layout = SaveLayout::save_subtree(workspace, false)?;
+        let mut bytes = vec![];
+        layout.to_writer(&mut bytes, Self::JSON)?;
+
+        let workspace_key = WorkspaceRef::of(workspace).file_key();
+        let dir = self.dir.join(&workspace_key);
+
+        std::fs::create_dir_all(&dir)
+            .with_context(|| format!("Cannot create the directory '{}'", dir.display()))?;
+
+        let snapshots = self.snapshots_in(&dir, &workspace_key)?;
+
+        if let Some(last) = snapshots.last() {
+            if std::fs::read(&last.path).is_ok_and(|last_bytes| last_bytes == bytes) {
+                return Ok(false);
+            }
+        }
+
+        let timestamp = SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .unwrap_or_default()
+            .as_secs();
+
+        let path = dir.join(format!(
+            "{}-{}{}",
+            timestamp,
+            layout.window_count(),
+            Self::FILE_SUFFIX
+        ));
+
+        let mut file = AtomicFile::create(&path, false)?;
+        file.write_all(&bytes)
+            .with_context(|| format!("Cannot write the snapshot '{}'", path.display()))?;
+        file.commit()?;
+
+        self.prune(&dir, &workspace_key)?;
+
+        Ok(true)
+    }
+
+    /// The snapshots of a workspace (all of them if `None`), from the oldest.
+    pub fn list(&self, workspace: Option<&WorkspaceRef>) -> Result<Vec<Snapshot>> {
+        let workspace_keys = match workspace {
+            Some(workspace) => vec![workspace.file_key()],
+            None => {
+                let mut workspace_keys = std::fs::read_dir(&self.dir)
+                    .with_context(|| format!("Cannot read the directory '{}'", self.dir.display()))?
+                    .flatten()
+                    .filter(|entry| entry.path().is_dir())
+                    .map(|entry| entry.file_name().to_string_lossy().into_owned())
+                    .collect::<Vec<_>>();
+
+                workspace_keys.sort();
+                workspace_keys
+            }
+        };
+
+        let mut snapshots = vec![];
+
+        for workspace_key in workspace_keys {
+            let dir = self.dir.join(&workspace_key);
+
+            if dir.is_dir() {
+                snapshots.extend(self.snapshots_in(&dir, &workspace_key)?);
+            }
+        }
+
+        Ok(snapshots)
+    }
+
+    /// Find a snapshot of a workspace by timestamp or, failing that, by index
+    /// (`0` is the most recent).
+    pub fn find(&self, workspace: &WorkspaceRef, at: u64) -> Result<Snapshot> {
+        let mut snapshots = self.list(Some(workspace))?;
+
+        if let Some(position) = snapshots
+            .iter()
+            .position(|snapshot| snapshot.timestamp == at)
+        {
+            return Ok(snapshots.swap_remove(position));
+        }
+
+        let count = snapshots.len();
+
+        usize::try_from(at)
+            .ok()
+            .and_then(|index| count.checked_sub(index + 1))
+            .map(|position| snapshots.swap_remove(position))
+            .ok_or_else(|| {
+                anyhow!(
+                    "No snapshot '{}' of workspace '{}' ({} snapshots)",
+                    at,
+                    workspace,
+                    count
+                )
+            })
+    }
+
+    /// Drop the snapshots of a workspace beyond the retention.
+    fn prune(&self, dir: &Path, workspace_key: &str) -> Result<()> {
+        let snapshots = self.snapshots_in(dir, workspace_key)?;
+        let now = SystemTime::now();
+
+        let excess = snapshots.len().saturating_sub(self.retention.count);
+
+        for (index, snapshot) in snapshots.iter().enumerate() {
+            let expired = self.retention.max_age.is_some_and(|max_age| {
+                now.duration_since(UNIX_EPOCH + Duration::from_secs(snapshot.timestamp))
+                    .is_ok_and(|age| age > max_age)
+            });
+
+            if index < excess || expired {
+                std::fs::remove_file(&snapshot.path).with_context(|| {
+                    format!("Cannot remove the snapshot '{}'", snapshot.path.display())
+                })?;
+            }
+        }
+
+        Ok(())
+    }
+
+    /// The snapshots in the directory of a workspace, from the oldest.
+    fn snapshots_in(&self, dir: &Path, workspace_key: &str) -> Result<Vec<Snapshot>> {
+        let mut snapshots = vec![];
+
+        for entry in std::fs::read_dir(dir)
+            .with_context(|| format!("Cannot read the directory '{}'", dir.display()))?
+            .flatten()
+        {
+            let file_name = entry.file_name().to_string_lossy().into_owned();
+
+            let parsed = file_name
+                .strip_suffix(Self::FILE_SUFFIX)
+                .and_then(|stem| stem.split_once('-'))
+                .and_then(|(timestamp, windows)| {
+                    Some((
+                        timestamp.parse::<u64>().ok()?,
+                        windows.parse::<usize>().ok()?,
+                    ))
+                });
+
+            if let Some((timestamp, windows)) = parsed {
+                snapshots.push(Snapshot {
+                    path: entry.path(),
+                    workspace: workspace_key.to_string(),
+                    timestamp,
+                    windows,
+                });
+            }
+        }
+
+        snapshots.sort_by_key(|snapshot| snapshot.timestamp);
+        Ok(snapshots)
+    }
+}
+
+/// History executor.
+///
+/// It represents a one-shot executor which restores a snapshot of the history.
+pub struct History {
+    /// Command executor.
+    command_executor: CommandExecutor,
+}
+
+impl History {
+    /// A new history executor.
+    pub fn new(command_executor: CommandExecutor) -> Self {
+        Self { command_executor }
+    }
+
+    /// Print the snapshots of a workspace (of all workspaces if `None`), the most recent
+    /// first with its index.
+    pub fn execute_list(mut self, workspace: Option<&WorkspaceRef>) -> Result<()> {
+        let workspace = match workspace {
+            Some(workspace) => {
+                let root_node = self.command_executor.query_root_node()?;
+                Some(WorkspaceRef::of(
+                    workspace.resolve(&root_node, &mut self.command_executor)?,
+                ))
+            }
+            None => None,
+        };
+
+        let history = LayoutHistory::open(Retention::default())?;
+        let snapshots = history.list(workspace.as_ref())?;
+
+        if snapshots.is_empty() {
+            println!("No snapshots");
+            return Ok(());
+        }
+
+        let mut current = None;
+        let mut index = 0;
+
+        for snapshot in snapshots.iter().rev() {
+            if current != Some(&snapshot.workspace) {
+                println!("{}:", snapshot.workspace);
+                current = Some(&snapshot.workspace);
+                index = 0;
+            }
+
+            println!("  #{} {}", index, snapshot.describe());
+            index += 1;
+        }
+
+        Ok(())
+    }
+
+    /// Restore the snapshot of a workspace taken at `at` (a timestamp or an index).
+    pub fn execute_restore(mut self, workspace: &WorkspaceRef, at: u64) -> Result<()> {
+        let root_node = self.command_executor.query_root_node()?;
+        let workspace =
+            WorkspaceRef::of(workspace.resolve(&root_node, &mut self.command_executor)?);
+
+        let history = LayoutHistory::open(Retention::default())?;
+        let snapshot = history.find(&workspace, at)?;
+
+        println!(
+            "Restoring snapshot of workspace '{}' {}",
+            workspace,
+            snapshot.describe()
+        );
+
+        let layout = SavedLayout::load(&snapshot.path, LayoutHistory::JSON)?;
+
+        RestoreLayout::new(self.command_executor)
+            .execute_layout(layout, true)
+            .context("Cannot restore the snapshot")
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::fixtures;
+    use crate::fixtures::TempDir;
+
+    fn history_in(dir: &TempDir, retention: Retention) -> LayoutHistory {
+        LayoutHistory::in_dir(dir.path().join("history"), retention).unwrap()
+    }
+
+    /// Put an (empty) snapshot file taken at `timestamp` in the history of workspace 1.
+    fn plant_snapshot(dir: &TempDir, timestamp: u64) {
+        let workspace_dir = dir.path().join("history").join("1");
+        std::fs::create_dir_all(&workspace_dir).unwrap();
+        std::fs::write(workspace_dir.join(format!("{}-3.layout", timestamp)), "").unwrap();
+    }
+
+    fn timestamps(history: &LayoutHistory, workspace: &WorkspaceRef) -> Vec<u64> {
+        history
+            .list(Some(workspace))
+            .unwrap()
+            .iter()
+            .map(|snapshot| snapshot.timestamp)
+            .collect()
+    }
+
+    /// The tree without some nodes (e.g., closed windows).
+    fn without(root_node: &RootNode, node_ids: &[usize]) -> RootNode {
+        fn prune(node: &mut I3Node, node_ids: &[usize]) {
+            node.nodes.retain(|child| !node_ids.contains(&child.id));
+            node.nodes
+                .iter_mut()
+                .for_each(|child| prune(child, node_ids));
+        }
+
+        let mut tree = root_node.node().clone();
+        prune(&mut tree, node_ids);
+        RootNode::new(tree)
+    }
+
+    #[test]
+    fn last_layouts_of_emptied_workspaces() {
+        let root_node = fixtures::load("workspaces.json");
+        let mut last_layouts = LastLayouts::default();
+
+        last_layouts.remember(&root_node, 102);
+        last_layouts.remember(&root_node, 411);
+
+        // Workspace 6 holds no window.
+        last_layouts.remember(&root_node, 600);
+
+        // Closing a window of workspace 1 leaves it with windows.
+        let closed_one = without(&root_node, &[101]);
+        assert!(last_layouts.take_emptied(&closed_one).is_empty());
+
+        // Closing the last one (i3 removes the workspace, unless focused).
+        let closed_all = without(&root_node, &[101, 102, 103, 400]);
+        let emptied = last_layouts.take_emptied(&closed_all);
+        assert_eq!(
+            emptied
+                .iter()
+                .map(|workspace| (workspace.id, workspace.nodes.len()))
+                .collect::<Vec<_>>(),
+            [(100, 3), (400, 1)]
+        );
+
+        // Once taken, they are forgotten.
+        assert!(last_layouts.take_emptied(&closed_all).is_empty());
+    }
+
+    #[test]
+    fn emptied_workspace_snapshot_holds_its_windows() {
+        let dir = TempDir::new("history");
+        let history = history_in(&dir, Retention::default());
+        let root_node = fixtures::load("workspaces.json");
+        let mut last_layouts = LastLayouts::default();
+
+        last_layouts.remember(&root_node, 411);
+
+        for workspace in last_layouts.take_emptied(&without(&root_node, &[410])) {
+            assert!(history.record(&workspace).unwrap());
+        }
+
+        let snapshots = history
+            .list(Some(&WorkspaceRef::Name("web".to_string())))
+            .unwrap();
+        assert_eq!(snapshots.len(), 1);
+        assert_eq!(snapshots[0].windows, 1);
+    }
+
+    #[test]
+    fn record_skips_unchanged_layouts() {
+        let dir = TempDir::new("history");
+        let history = history_in(&dir, Retention::default());
+        let root_node = fixtures::load("workspaces.json");
+
+        assert!(history.record(fixtures::node(&root_node, 100)).unwrap());
+        assert!(!history.record(fixtures::node(&root_node, 100)).unwrap());
+
+        let snapshots = history.list(Some(&WorkspaceRef::Num(1))).unwrap();
+        assert_eq!(snapshots.len(), 1);
+        assert_eq!(snapshots[0].workspace, "1");
+        assert_eq!(snapshots[0].windows, 3);
+
+        let layout = SavedLayout::load(&snapshots[0].path, LayoutHistory::JSON).unwrap();
+        assert_eq!(layout.window_count(), 3);
+    }
+
+    #[test]
+    fn record_all_skips_empty_and_scratchpad_workspaces() {
+        let dir = TempDir::new("history");
+        let history = history_in(&dir, Retention::default());
+
+        // Workspace 6 is empty.
+        assert_eq!(
+            history
+                .record_all(&fixtures::load("workspaces.json"))
+                .unwrap(),
+            5
+        );
+
+        let workspaces = history
+            .list(None)
+            .unwrap()
+            .into_iter()
+            .map(|snapshot| snapshot.workspace)
+            .collect::<Vec<_>>();
+        assert_eq!(workspaces, ["1", "2", "3", "5", "web"]);
+
+        let dir = TempDir::new("history");
+        let history = history_in(&dir, Retention::default());
+        assert_eq!(
+            history
+                .record_all(&fixtures::load("scratchpad.json"))
+                .unwrap(),
+            1
+        );
+    }
+
+    #[test]
+    fn list_ignores_other_files() {
+        let dir = TempDir::new("history");
+        plant_snapshot(&dir, 1_700_000_000);
+
+        let workspace_dir = dir.path().join("history").join("1");
+        for name in [
+            "notes.txt",
+            "1700000001.layout",
+            "x-3.layout",
+            "1700000002-3.layout.tmp",
+        ] {
+            std::fs::write(workspace_dir.join(name), "").unwrap();
+        }
+
+        let history = history_in(&dir, Retention::default());
+        assert_eq!(timestamps(&history, &WorkspaceRef::Num(1)), [1_700_000_000]);
+        assert!(history
+            .list(Some(&WorkspaceRef::Num(2)))
+            .unwrap()
+            .is_empty());
+    }
+
+    #[test]
+    fn find_by_timestamp_or_index() {
+        let dir = TempDir::new("history");
+        for timestamp in [1_700_000_000, 1_700_000_100, 1_700_000_200] {
+            plant_snapshot(&dir, timestamp);
+        }
+
+        let history = history_in(&dir, Retention::default());
+        let workspace = WorkspaceRef::Num(1);
+
+        assert_eq!(
+            history.find(&workspace, 1_700_000_100).unwrap().timestamp,
+            1_700_000_100
+        );
+        assert_eq!(
+            history.find(&workspace, 0).unwrap().timestamp,
+            1_700_000_200
+        );
+        assert_eq!(
+            history.find(&workspace, 2).unwrap().timestamp,
+            1_700_000_000
+        );
+
+        assert_eq!(
+            history.find(&workspace, 3).err().unwrap().to_string(),
+            "No snapshot '3' of workspace '1' (3 snapshots)"
+        );
+    }
+
+    #[test]
+    fn record_prunes_beyond_count() {
+        let dir = TempDir::new("history");
+        for timestamp in [1_700_000_000, 1_700_000_100, 1_700_000_200] {
+            plant_snapshot(&dir, timestamp);
+        }
+
+        let retention = Retention {
+            count: 2,
+            max_age: None,
+        };
+        let history = history_in(&dir, retention);
+        let root_node = fixtures::load("workspaces.json");
+        assert!(history.record(fixtures::node(&root_node, 100)).unwrap());
+
+        let kept = timestamps(&history, &WorkspaceRef::Num(1));
+        assert_eq!(kept.len(), 2);
+        assert_eq!(kept[0], 1_700_000_200);
+        assert!(kept[1] > 1_700_000_200);
+    }
+
+    #[test]
+    fn record_prunes_expired() {
+        let dir = TempDir::new("history");
+        plant_snapshot(&dir, 1_000);
+
+        let retention = Retention {
+            count: Retention::DEFAULT_COUNT,
+            max_age: Some(Duration::from_secs(24 * 3600)),
+        };
+        let history = history_in(&dir, retention);
+        let root_node = fixtures::load("workspaces.json");
+        assert!(history.record(fixtures::node(&root_node, 100)).unwrap());
+
+        let kept = timestamps(&history, &WorkspaceRef::Num(1));
+        assert_eq!(kept.len(), 1);
+        assert!(kept[0] > 1_000);
+    }
+}
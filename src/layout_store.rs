@@ -0,0 +1,330 @@
+/*
+    Copyright (C) 2022  Biagio Festa
+
+    This program is free software: you can redistribute it and/or modify
+    it under the terms of the GNU General Public License as published by
+    the Free Software Foundation, either version 3 of the License, or
+    (at your option) any later version.
+
+    This program is distributed in the hope that it will be useful,
+    but WITHOUT ANY WARRANTY; without even the implied warranty of
+    MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+    GNU General Public License for more details.
+
+    You should have received a copy of the GNU General Public License
+    along with this program.  If not, see <https://www.gnu.org/licenses/>.
+*/
+
+use crate::atomic_file::AtomicFile;
+use crate::save_layout::KindNode;
+use crate::save_layout::SavedLayout;
+use crate::state_store::StateStore;
+use crate::utilities::format_age;
+use anyhow::anyhow;
+use anyhow::Context;
+use anyhow::Result;
+use std::io::Write;
+use std::path::Path;
+use std::path::PathBuf;
+use std::time::Duration;
+use std::time::SystemTime;
+
+/// A layout of the store.
+pub struct StoredLayout {
+    /// The layout name.
+    pub name: String,
+
+    /// The workspace the layout has been saved from (if readable).
+    pub workspace: Option<String>,
+
+    /// How long ago the layout has been saved.
+    pub age: Duration,
+
+    /// The project the layout is associated with (see `LayoutStore::project_path`).
+    pub project: Option<PathBuf>,
+}
+
+impl StoredLayout {
+    /// A one-line description (e.g., `work (workspace 2, 3h ago)`).
+    pub fn describe(&self) -> String {
+        let workspace = self.workspace.as_deref().unwrap_or("?");
+
+        match &self.project {
+            Some(project) => format!(
+                "{} (project '{}', workspace {}, {} ago)",
+                self.name,
+                project.display(),
+                workspace,
+                format_age(self.age)
+            ),
+            None => format!(
+                "{} (workspace {}, {} ago)",
+                self.name,
+                workspace,
+                format_age(self.age)
+            ),
+        }
+    }
+}
+
+/// The store of named layouts.
+///
+/// Layouts are kept in binary format in the state directory (`layouts/<NAME>.layout`),
+/// written through the `StateStore`. Layouts of projects are kept apart
+/// (`layouts/projects/<HASH>.layout`), each with the path of its project (`<HASH>.project`).
+pub struct LayoutStore {
+    store: StateStore,
+}
+
+impl LayoutStore {
+    const DIR_NAME: &'static str = "layouts";
+    const FILE_SUFFIX: &'static str = ".layout";
+    const PROJECTS_DIR_NAME: &'static str = "projects";
+    const PROJECT_EXTENSION: &'static str = "project";
+
+    /// Whether layouts of the store are in JSON format.
+    pub const JSON: bool = false;
+
+    /// Open the store.
+    pub fn open() -> Result<Self> {
+        Self::in_store(&StateStore::open()?)
+    }
+
+    /// Open the store in the state store `state_store`.
+    fn in_store(state_store: &StateStore) -> Result<Self> {
+        Ok(Self {
+            store: state_store.subdir(Self::DIR_NAME)?,
+        })
+    }
+
+    /// The file of a named layout.
+    pub fn path(&self, name: &str) -> Result<PathBuf> {
+        Ok(self.store.path(&Self::file_name(name)?))
+    }
+
+    /// Write a named layout with `write` (see `StateStore::write`).
+    pub fn write<F>(&self, name: &str, keep_backup: bool, write: F) -> Result<()>
+    where
+        F: FnOnce(&mut AtomicFile) -> Result<()>,
+    {
+        self.store
+            .write(&Self::file_name(name)?, keep_backup, write)
+    }
+
+    /// The file of the layout of a project (see `project_of`).
+    ///
+    /// The file is named after a hash of the project path.
+    pub fn project_path(&self, project: &Path) -> PathBuf {
+        self.store
+            .path(Self::PROJECTS_DIR_NAME)
+            .join(Self::project_file_name(project))
+    }
+
+    /// Write the layout of a project with `write`, recording the path of the project next
+    /// to it (see `project_path`), for listing.
+    pub fn write_project<F>(&self, project: &Path, keep_backup: bool, write: F) -> Result<()>
+    where
+        F: FnOnce(&mut AtomicFile) -> Result<()>,
+    {
+        let projects = self.store.subdir(Self::PROJECTS_DIR_NAME)?;
+        let file_name = Self::project_file_name(project);
+
+        projects.write(&file_name, keep_backup, write)?;
+
+        let project_file_name = Path::new(&file_name).with_extension(Self::PROJECT_EXTENSION);
+        projects.write(&project_file_name.to_string_lossy(), false, |output| {
+            Ok(output.write_all(project.as_os_str().as_encoded_bytes())?)
+        })
+    }
+
+    /// The file name of a named layout.
+    fn file_name(name: &str) -> Result<String> {
+        if name.is_empty() || name.contains('/') || name.starts_with('.') {
+            return Err(anyhow!("Invalid layout name '{}'", name));
+        }
+
+        Ok(format!("{}{}", name, Self::FILE_SUFFIX))
+    }
+
+    /// The file name of the layout of a project.
+    fn project_file_name(project: &Path) -> String {
+        format!(
+            "{:016x}{}",
+            fnv1a(project.as_os_str().as_encoded_bytes()),
+            Self::FILE_SUFFIX
+        )
+    }
+
+    /// The layouts of the store, sorted by name.
+    ///
+    /// The layouts of projects are not included (see `list_projects`).
+    pub fn list(&self) -> Result<Vec<StoredLayout>> {
+        Self::list_dir(self.store.dir())
+    }
+
+    /// The layouts of projects, sorted by project.
+    pub fn list_projects(&self) -> Result<Vec<StoredLayout>> {
+        let dir = self.store.path(Self::PROJECTS_DIR_NAME);
+        if !dir.exists() {
+            return Ok(vec![]);
+        }
+
+        let mut layouts = Self::list_dir(&dir)?;
+
+        for layout in &mut layouts {
+            layout.project = std::fs::read_to_string(
+                dir.join(&layout.name)
+                    .with_extension(Self::PROJECT_EXTENSION),
+            )
+            .ok()
+            .map(PathBuf::from);
+        }
+
+        layouts.sort_by(|a, b| a.project.cmp(&b.project));
+        Ok(layouts)
+    }
+
+    /// The layouts of a directory, sorted by name.
+    fn list_dir(dir: &Path) -> Result<Vec<StoredLayout>> {
+        let entries = std::fs::read_dir(dir)
+            .with_context(|| format!("Cannot read the directory '{}'", dir.display()))?;
+
+        let now = SystemTime::now();
+        let mut layouts = vec![];
+
+        for entry in entries.flatten() {
+            let file_name = entry.file_name().to_string_lossy().into_owned();
+            let Some(name) = file_name.strip_suffix(Self::FILE_SUFFIX) else {
+                continue;
+            };
+
+            let age = entry
+                .metadata()
+                .and_then(|metadata| metadata.modified())
+                .ok()
+                .and_then(|modified| now.duration_since(modified).ok())
+                .unwrap_or_default();
+
+            let workspace = SavedLayout::load(&entry.path(), Self::JSON)
+                .ok()
+                .and_then(|layout| match layout.root().kind() {
+                    KindNode::Workspace(num) => Some(num.to_string()),
+                    KindNode::NamedWorkspace(name) => Some(name.clone()),
+                    _ => None,
+                });
+
+            layouts.push(StoredLayout {
+                name: name.to_string(),
+                workspace,
+                age,
+                project: None,
+            });
+        }
+
+        layouts.sort_by(|a, b| a.name.cmp(&b.name));
+        Ok(layouts)
+    }
+}
+
+/// The project of a directory: its git repository (the closest ancestor holding `.git`),
+/// otherwise the directory itself. The path is canonicalized.
+pub fn project_of(dir: &Path) -> Result<PathBuf> {
+    let dir = dir
+        .canonicalize()
+        .with_context(|| format!("Cannot resolve the directory '{}'", dir.display()))?;
+
+    let project = dir
+        .ancestors()
+        .find(|ancestor| ancestor.join(".git").exists())
+        .unwrap_or(&dir);
+
+    Ok(project.to_path_buf())
+}
+
+/// The 64-bit FNV-1a hash: unlike the hasher of std, it is stable across releases.
+fn fnv1a(bytes: &[u8]) -> u64 {
+    bytes.iter().fold(0xcbf2_9ce4_8422_2325, |hash, &byte| {
+        (hash ^ u64::from(byte)).wrapping_mul(0x0100_0000_01b3)
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::fixtures;
+    use crate::fixtures::TempDir;
+    use crate::save_layout::SaveLayout;
+
+    fn layout_store_in(dir: &TempDir) -> LayoutStore {
+        LayoutStore::in_store(&StateStore::in_dir(dir.path().to_path_buf())).unwrap()
+    }
+
+    fn save(layout_store: &LayoutStore, name: &str, workspace_id: usize) -> Result<()> {
+        let root_node = fixtures::load("workspaces.json");
+        let layout = SaveLayout::save_subtree(fixtures::node(&root_node, workspace_id), false)?;
+
+        layout_store.write(name, false, |output| {
+            layout.to_writer(output, LayoutStore::JSON)
+        })
+    }
+
+    #[test]
+    fn named_layouts_are_listed_with_their_workspace() {
+        let dir = TempDir::new("layout-store");
+        let layout_store = layout_store_in(&dir);
+
+        save(&layout_store, "work", 200).unwrap();
+        save(&layout_store, "browse", 400).unwrap();
+
+        let layouts = layout_store.list().unwrap();
+        let listed = layouts
+            .iter()
+            .map(|layout| (layout.name.as_str(), layout.workspace.as_deref()))
+            .collect::<Vec<_>>();
+        assert_eq!(listed, [("browse", Some("web")), ("work", Some("2"))]);
+
+        // Only the layouts are left behind (no temporary file nor lock).
+        let names = StateStore::in_dir(dir.path().join("layouts"))
+            .entries()
+            .unwrap()
+            .into_iter()
+            .map(|entry| entry.name)
+            .collect::<Vec<_>>();
+        assert_eq!(
+            names,
+            [Path::new("browse.layout"), Path::new("work.layout")]
+        );
+    }
+
+    #[test]
+    fn invalid_names_are_rejected() {
+        let dir = TempDir::new("layout-store");
+        let layout_store = layout_store_in(&dir);
+
+        for name in ["", "../escape", ".hidden"] {
+            assert!(save(&layout_store, name, 200).is_err(), "{:?}", name);
+        }
+
+        assert!(layout_store.list().unwrap().is_empty());
+    }
+
+    #[test]
+    fn project_layouts_are_listed_with_their_project() {
+        let dir = TempDir::new("layout-store");
+        let layout_store = layout_store_in(&dir);
+        let project = Path::new("/home/user/src/i3-autolayout");
+
+        assert!(layout_store.list_projects().unwrap().is_empty());
+
+        layout_store
+            .write_project(project, false, |output| Ok(output.write_all(b"layout")?))
+            .unwrap();
+
+        assert!(layout_store.project_path(project).exists());
+        assert!(layout_store.list().unwrap().is_empty());
+
+        let layouts = layout_store.list_projects().unwrap();
+        assert_eq!(layouts.len(), 1);
+        assert_eq!(layouts[0].project.as_deref(), Some(project));
+    }
+}
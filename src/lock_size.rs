@@ -0,0 +1,88 @@
+/*
+    Copyright (C) 2022  Biagio Festa
+
+    This program is free software: you can redistribute it and/or modify
+    it under the terms of the GNU General Public License as published by
+    the Free Software Foundation, either version 3 of the License, or
+    (at your option) any later version.
+
+    This program is distributed in the hope that it will be useful,
+    but WITHOUT ANY WARRANTY; without even the implied warranty of
+    MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+    GNU General Public License for more details.
+
+    You should have received a copy of the GNU General Public License
+    along with this program.  If not, see <https://www.gnu.org/licenses/>.
+*/
+
+use crate::command_executor::CommandExecutor;
+use crate::marks::size_lock_mark;
+use crate::marks::SIZE_LOCK_MARK_PREFIX;
+use crate::utilities::find_focused_node;
+use crate::utilities::find_node_by_id;
+use crate::utilities::node_marks;
+use crate::utilities::quote;
+use anyhow::anyhow;
+use anyhow::Context;
+use anyhow::Result;
+use i3_ipc::reply::NodeType;
+
+/// LockSize executor.
+///
+/// It represents a one-shot executor which toggles the size lock of the focused container:
+/// balance, restore-layout (sizes) and the autolayout service do not resize it.
+pub struct LockSize {
+    /// Command executor.
+    command_executor: CommandExecutor,
+}
+
+impl LockSize {
+    /// A new lock-size executor.
+    pub fn new(command_executor: CommandExecutor) -> Self {
+        Self { command_executor }
+    }
+
+    /// Execute the action.
+    ///
+    /// The focused container must be tiled. Since i3 marks are unique, it is marked as
+    /// `<prefix><con_id>` (see `size_lock_mark`). If it is already locked, the lock is
+    /// removed instead.
+    pub fn execute(mut self) -> Result<()> {
+        let root_node = self.command_executor.query_root_node()?;
+        let focused = find_focused_node(&root_node)
+            .ok_or_else(|| anyhow!("Cannot find the focused container"))?;
+
+        if focused.node_type != NodeType::Con || find_node_by_id(focused.id, &root_node).is_none() {
+            return Err(anyhow!(
+                "The focused container '{}' is not a tiled container",
+                focused.id
+            ));
+        }
+
+        let locks = node_marks(focused)
+            .into_iter()
+            .filter(|mark| mark.starts_with(SIZE_LOCK_MARK_PREFIX))
+            .collect::<Vec<_>>();
+
+        if locks.is_empty() {
+            self.command_executor
+                .run_on_node_id(
+                    focused.id,
+                    format!("mark --add {}", quote(&size_lock_mark(focused.id))),
+                )
+                .context("Cannot mark the focused container")?;
+
+            eprintln!("Locked the size of container '{}'", focused.id);
+        } else {
+            for mark in locks {
+                self.command_executor
+                    .run(format!("unmark {}", quote(&mark)))
+                    .context("Cannot unmark the focused container")?;
+            }
+
+            eprintln!("Unlocked the size of container '{}'", focused.id);
+        }
+
+        Ok(())
+    }
+}
@@ -20,28 +20,102 @@
 //! i3-autolayout is a simple service which helps keep a reasonable
 //! windows layout for your i3 manager.
 
-use crate::autolayout::AutoLayout;
-use crate::command_executor::CommandExecutor;
-use crate::event_listener::EventListener;
-use crate::event_listener::EventSubscribe;
-use crate::tabmode::TabMode;
-use anyhow::anyhow;
+use crate::command_executor::Connection;
+use crate::commands::autolayout::command_autolayout;
+use crate::commands::autolayout::command_next_split;
+use crate::commands::autolayout::command_pin;
+use crate::commands::autolayout::AutolayoutCmd;
+use crate::commands::autolayout::NextSplitCmd;
+use crate::commands::autolayout::PinCmd;
+use crate::commands::inspect::command_control;
+use crate::commands::inspect::command_ctl;
+use crate::commands::inspect::command_doctor;
+use crate::commands::inspect::command_i3_version;
+use crate::commands::inspect::command_print_tree;
+use crate::commands::inspect::command_where;
+use crate::commands::inspect::CtlCmd;
+use crate::commands::inspect::DoctorCmd;
+use crate::commands::inspect::I3VersionCmd;
+use crate::commands::inspect::PrintTreeCmd;
+use crate::commands::inspect::WhereCmd;
+use crate::commands::layouts::command_history;
+use crate::commands::layouts::command_list_layouts;
+use crate::commands::layouts::command_migrate_layouts;
+use crate::commands::layouts::command_restore_layout;
+use crate::commands::layouts::command_save_layout;
+use crate::commands::layouts::command_schema;
+use crate::commands::layouts::command_show_layout;
+use crate::commands::layouts::command_undo;
+use crate::commands::layouts::HistoryCmd;
+use crate::commands::layouts::MigrateLayoutsCmd;
+use crate::commands::layouts::RestoreLayoutCmd;
+use crate::commands::layouts::SaveLayoutCmd;
+use crate::commands::layouts::SchemaCmd;
+use crate::commands::layouts::ShowLayoutCmd;
+use crate::commands::scripting::command_bar_proxy;
+use crate::commands::scripting::command_batch;
+use crate::commands::scripting::command_serve_json;
+use crate::commands::scripting::BatchCmd;
+use crate::commands::setup::command_check_config;
+use crate::commands::setup::command_generate_config;
+use crate::commands::setup::GenerateConfigCmd;
+use crate::commands::tabmode::command_normalize;
+use crate::commands::tabmode::command_tabmode;
+use crate::commands::tabmode::NormalizeCmd;
+use crate::commands::tabmode::TabModeCmd;
+use crate::commands::windows::command_float;
+use crate::commands::windows::command_gather;
+use crate::commands::windows::command_lock_size;
+use crate::commands::windows::command_park;
+use crate::commands::windows::command_resize;
+use crate::commands::windows::command_scratchpad_cycle;
+use crate::commands::windows::command_select;
+use crate::commands::windows::command_send_home;
+use crate::commands::windows::command_unpark;
+use crate::commands::windows::command_urgent_jump;
+use crate::commands::windows::FloatCmd;
+use crate::commands::windows::GatherCmd;
+use crate::commands::windows::ResizeCmd;
+use crate::commands::windows::ScratchpadCycleCmd;
+use crate::commands::windows::SelectCmd;
+use crate::commands::windows::SendHomeCmd;
+use crate::commands::windows::UnparkCmd;
+use crate::commands::windows::UrgentJumpCmd;
+use crate::commands::workspaces::command_balance;
+use crate::commands::workspaces::command_group;
+use crate::commands::workspaces::command_move_workspace;
+use crate::commands::workspaces::BalanceCmd;
+use crate::commands::workspaces::GroupCmd;
+use crate::commands::workspaces::MoveWorkspaceCmd;
+use crate::config::Config;
+use crate::control::ControlCommand;
+use crate::errors::exit_code;
+use crate::errors::Failure;
+use crate::examples::HelpExamples;
 use anyhow::Context;
 use anyhow::Result;
-use clap::Parser;
-use print_tree::print_tree;
-use restore_layout::RestoreLayout;
-use save_layout::SaveLayout;
-use std::fs::File;
-use std::io::Read;
-use std::io::Write;
+use clap::CommandFactory;
+use clap::FromArgMatches;
+use std::ffi::OsString;
 use std::path::PathBuf;
-use utilities::find_workspace_by_num;
+use std::process::ExitCode;
 
 /// CLI arguments.
 #[derive(clap::Parser)]
 #[clap(about, author, version)]
 struct CliArgs {
+    /// The configuration file. If not specified '$XDG_CONFIG_HOME/i3-autolayout/config.toml' is used (if it exists).
+    #[clap(long, global = true)]
+    config: Option<PathBuf>,
+
+    /// Terminate the output with a stable status line: 'ok' or 'error <CODE> <KIND>'.
+    /// Warnings and progress messages go to stderr.
+    /// Exit codes: 0 ok, 1 generic error, 2 workspace not found, 3 layout unreadable,
+    /// 4 cannot connect to i3, 5 layout partially restored, 6 workspace partially normalized,
+    /// 7 no layout for the project, 8 no urgent windows.
+    #[clap(long, global = true, action)]
+    porcelain: bool,
+
     /// The subcommand to apply.
     #[clap(subcommand)]
     command: Command,
@@ -52,20 +126,34 @@ struct CliArgs {
 enum Command {
     /// Run autolayout service.
     #[clap(name = "autolayout")]
-    Autolayout,
+    Autolayout(Box<AutolayoutCmd>),
 
     /// Toggle tabmode on the current focused workspace.
     #[clap(name = "tabmode")]
     TabMode(TabModeCmd),
 
+    /// Flatten a workspace's nesting into a single split.
+    #[clap(name = "normalize")]
+    Normalize(NormalizeCmd),
+
+    /// Print the split the autolayout service would apply to the focused window
+    /// ('horizontal', 'vertical', 'toggle' or 'none'), without changing anything.
+    #[clap(name = "next-split")]
+    NextSplit(NextSplitCmd),
+
     /// Display i3 information.
     #[clap(name = "i3version")]
-    I3Version,
+    I3Version(I3VersionCmd),
 
     /// Print a snapshot of the current layout as tree.
     #[clap(name = "print-tree")]
     PrintTree(PrintTreeCmd),
 
+    /// Print the chain of nodes from the output down to the focused window, one per line,
+    /// with their type, layout and position among their siblings.
+    #[clap(name = "where")]
+    Where(WhereCmd),
+
     /// Save a workspace's layout.
     #[clap(name = "save-layout")]
     SaveLayout(SaveLayoutCmd),
@@ -73,176 +161,445 @@ enum Command {
     /// Restore a workspace's layout.
     #[clap(name = "restore-layout")]
     RestoreLayout(RestoreLayoutCmd),
+
+    /// Print a saved layout, without restoring it.
+    #[clap(name = "show-layout")]
+    ShowLayout(ShowLayoutCmd),
+
+    /// List the layouts of the layout store, with the projects they are associated with.
+    #[clap(name = "list-layouts")]
+    ListLayouts,
+
+    /// Print the schema of JSON layout files (see 'save-layout --json').
+    #[clap(name = "schema")]
+    Schema(SchemaCmd),
+
+    /// Collapse the containers with a single child in the layout files of a directory,
+    /// rewriting them in place (backups are kept as '<FILE>.bak').
+    #[clap(name = "migrate-layouts")]
+    MigrateLayouts(MigrateLayoutsCmd),
+
+    /// Arrange floating windows.
+    #[clap(name = "float")]
+    Float(FloatCmd),
+
+    /// Move the windows matching some criteria on a workspace.
+    #[clap(name = "gather")]
+    Gather(GatherCmd),
+
+    /// Divide the space of each container of a workspace among its children (equally,
+    /// or by a ratio); windows with a locked size keep their share.
+    #[clap(name = "balance")]
+    Balance(BalanceCmd),
+
+    /// Move the focused workspace to another active output.
+    #[clap(name = "move-workspace")]
+    MoveWorkspace(MoveWorkspaceCmd),
+
+    /// Move the focused window (or every window of the focused workspace) back to the
+    /// workspace it first appeared on, as recorded by the autolayout service.
+    #[clap(name = "send-home")]
+    SendHome(SendHomeCmd),
+
+    /// Print the windows matching some criteria.
+    #[clap(name = "select")]
+    Select(SelectCmd),
+
+    /// Show the next scratchpad window (hiding the one currently shown).
+    #[clap(name = "scratchpad-cycle")]
+    ScratchpadCycle(ScratchpadCycleCmd),
+
+    /// Move every window but the focused one on the scratchpad, saving the layout.
+    #[clap(name = "park")]
+    Park,
+
+    /// Bring back the windows parked on a workspace and restore its layout.
+    #[clap(name = "unpark")]
+    Unpark(UnparkCmd),
+
+    /// Manage workspace groups.
+    #[clap(name = "group")]
+    Group(GroupCmd),
+
+    /// Resize the focused container along the direction of its parent split.
+    #[clap(name = "resize")]
+    Resize(ResizeCmd),
+
+    /// Toggle the opt-in mark of the focused container (see 'autolayout --opt-in-mark').
+    #[clap(name = "pin")]
+    Pin(PinCmd),
+
+    /// Toggle the size lock of the focused (tiled) container: balancing, restoring sizes
+    /// and the autolayout service leave its size alone.
+    #[clap(name = "lock-size")]
+    LockSize,
+
+    /// Restore the layout changed by the last tabmode, restore-layout, normalize,
+    /// gather or resize.
+    #[clap(name = "undo")]
+    Undo,
+
+    /// List the layout snapshots taken by the autolayout service
+    /// (see 'autolayout --snapshot-interval'), or restore one.
+    #[clap(name = "history")]
+    History(HistoryCmd),
+
+    /// Focus the next urgent window (or return to the window focused before).
+    /// It fails with exit code 8 if there is neither.
+    #[clap(name = "urgent-jump")]
+    UrgentJump(UrgentJumpCmd),
+
+    /// Find what interrupted actions left behind (temporary marks, tab mode indicators,
+    /// stale state files) and optionally repair it.
+    #[clap(name = "doctor")]
+    Doctor(DoctorCmd),
+
+    /// Send a command to the running autolayout service, or inspect its state directory
+    /// ('ctl state').
+    #[clap(name = "ctl", subcommand)]
+    Ctl(CtlCmd),
+
+    /// Switch the focused output to the workspace it displayed before (needs the service).
+    #[clap(name = "back-and-forth")]
+    BackAndForth,
+
+    /// Print an i3 config snippet with keybindings for i3-autolayout.
+    #[clap(name = "generate-config")]
+    GenerateConfig(GenerateConfigCmd),
+
+    /// Validate the configuration file and print the effective configuration.
+    #[clap(name = "check-config")]
+    CheckConfig,
+
+    /// Execute subcommands read line by line (same syntax as the command line)
+    /// over a single i3 connection.
+    #[clap(name = "batch")]
+    Batch(BatchCmd),
+
+    /// Proxy the i3bar click events (stdin to stdout), executing the subcommands bound
+    /// to the buttons of the workspace blocks (see the 'bar-proxy' config section).
+    #[clap(name = "bar-proxy")]
+    BarProxy,
+
+    /// Serve save/restore/tree/tabmode requests as newline-delimited JSON
+    /// (stdin to stdout) over a single i3 connection, until the end of the input.
+    #[clap(name = "serve-json")]
+    ServeJson,
 }
 
-/// Information about the tabmode command.
-#[derive(clap::Args)]
-struct TabModeCmd {
-    /// The workspace number to apply tab mode. If not specified the focused workspace will be used.
-    #[clap(short, long)]
-    workspace_num: Option<i32>,
+fn main() -> ExitCode {
+    let examples = if asks_for_help(std::env::args_os().skip(1)) {
+        HelpExamples::generate()
+            .map_err(|error| eprintln!("[WARN]: {:#}", error))
+            .ok()
+    } else {
+        None
+    };
 
-    /// The file where to save/load the layout.
-    #[clap(short, long)]
-    file_layout: Option<PathBuf>,
-}
+    let mut command = CliArgs::command();
 
-/// Information about the print-tree command.
-#[derive(clap::Args)]
-struct PrintTreeCmd {
-    /// The workspace number to print of. If not specified prints all workspaces.
-    workspace_num: Option<i32>,
-}
+    if let Some(examples) = &examples {
+        for (subcommand, text) in examples.all() {
+            command = command.mut_subcommand(subcommand, |command| command.after_help(text));
+        }
+    }
 
-/// Information about the save-layout command.
-#[derive(clap::Args)]
-struct SaveLayoutCmd {
-    /// The workspace number to save. If not specified the focused workspace will be used.
-    #[clap(short, long)]
-    workspace_num: Option<i32>,
+    let cli_args =
+        CliArgs::from_arg_matches(&command.get_matches()).unwrap_or_else(|error| error.exit());
+    let mut connection = Connection::default();
 
-    /// The output filename where to save the layout. If not specified stdout will be used.
-    #[clap(short, long)]
-    output: Option<PathBuf>,
+    let result = Config::load(cli_args.config.as_deref())
+        .and_then(|config| run(cli_args.command, config, &mut connection));
 
-    /// Format the output with JSON.
-    #[clap(short, long, action)]
-    json: bool,
-}
+    match result {
+        Ok(()) => {
+            if cli_args.porcelain {
+                println!("ok");
+            }
+
+            ExitCode::SUCCESS
+        }
+
+        Err(error) => {
+            let code = exit_code(&error);
 
-/// Information about the restore-layout command.
-#[derive(clap::Args)]
-struct RestoreLayoutCmd {
-    /// The input filename where layout has been stored. If not specified stdin will be used.
-    #[clap(short, long)]
-    input: Option<PathBuf>,
+            eprintln!("Error: {:?}", error);
 
-    /// Whether the input is JSON format.
-    #[clap(short, long, action)]
-    json: bool,
+            if cli_args.porcelain {
+                let kind = Failure::of(&error).map_or("generic", Failure::tag);
+                println!("error {} {}", code, kind);
+            }
+
+            ExitCode::from(code)
+        }
+    }
+}
 
-    /// Whether to attempt to restore sizes of windows.
-    #[clap(short, long, action)]
-    restore_sizes: bool,
+/// Whether the arguments ask for help (`-h`, `--help` or the `help` subcommand).
+///
+/// The examples of the help (see `HelpExamples`) are generated only then. An argument
+/// which only looks like a request of help (e.g., a workspace named `help`) generates
+/// them for nothing.
+fn asks_for_help(args: impl IntoIterator<Item = OsString>) -> bool {
+    args.into_iter()
+        .any(|arg| arg == "-h" || arg == "--help" || arg == "help")
 }
 
-fn main() -> Result<()> {
-    let cli_args = CliArgs::parse();
+/// Execute a subcommand.
+///
+/// The executors get their connection with i3 from `connection`.
+fn run(command: Command, config: Config, connection: &mut Connection) -> Result<()> {
+    match command {
+        Command::Autolayout(autolayout_cmd) => {
+            command_autolayout(*autolayout_cmd, config.autolayout, connection)
+                .context("Failure in command 'autolayout'")
+        }
+
+        Command::TabMode(tabmode_cmd) => command_tabmode(tabmode_cmd, config.tabmode, connection)
+            .context("Failure in command 'tabmode'"),
 
-    match cli_args.command {
-        Command::Autolayout => command_autolayout().context("Failure in command 'autolayout'"),
+        Command::Normalize(normalize_cmd) => {
+            command_normalize(normalize_cmd, connection).context("Failure in command 'normalize'")
+        }
+
+        Command::NextSplit(next_split_cmd) => {
+            command_next_split(next_split_cmd, config.autolayout, connection)
+                .context("Failure in command 'next-split'")
+        }
 
-        Command::TabMode(tabmode_cmd) => {
-            command_tabmode(tabmode_cmd).context("Failure in command 'tabmode'")
+        Command::I3Version(i3_version_cmd) => {
+            command_i3_version(i3_version_cmd, connection).context("Failure in command 'i3version'")
         }
 
-        Command::I3Version => command_i3_version().context("Failure in command 'i3version'"),
+        Command::PrintTree(print_tree_cmd) => command_print_tree(print_tree_cmd, connection)
+            .context("Failure in command 'print-tree'"),
 
-        Command::PrintTree(print_tree_cmd) => {
-            command_print_tree(print_tree_cmd).context("Failure in command 'print-tree'")
+        Command::Where(where_cmd) => {
+            command_where(where_cmd, connection).context("Failure in command 'where'")
         }
 
         Command::SaveLayout(save_layout_cmd) => {
-            command_save_layout(save_layout_cmd).context("Failure in command 'save-layout'")
+            command_save_layout(save_layout_cmd, config.save_layout, connection)
+                .context("Failure in command 'save-layout'")
         }
 
-        Command::RestoreLayout(restore_layout_cmd) => command_restore_layout(restore_layout_cmd)
-            .context("Failure in command 'restore-layout'"),
-    }
-}
+        Command::RestoreLayout(restore_layout_cmd) => {
+            command_restore_layout(restore_layout_cmd, config.restore_layout, connection)
+                .context("Failure in command 'restore-layout'")
+        }
+
+        Command::ShowLayout(show_layout_cmd) => {
+            command_show_layout(show_layout_cmd).context("Failure in command 'show-layout'")
+        }
 
-/// Execute autolayout service.
-fn command_autolayout() -> Result<()> {
-    let event_listener = EventListener::new(&[EventSubscribe::Window])?;
-    let command_executor = CommandExecutor::new()?;
-    let autolayout = AutoLayout::new(event_listener, command_executor);
+        Command::ListLayouts => command_list_layouts().context("Failure in command 'list-layouts'"),
 
-    autolayout.serve()
-}
+        Command::Schema(schema_cmd) => {
+            command_schema(schema_cmd).context("Failure in command 'schema'")
+        }
 
-/// Execute tabmode.
-fn command_tabmode(tabmode_cmd: TabModeCmd) -> Result<()> {
-    let command_executor = CommandExecutor::new()?;
-    let tabmode = TabMode::new(command_executor);
+        Command::MigrateLayouts(migrate_layouts_cmd) => {
+            command_migrate_layouts(migrate_layouts_cmd)
+                .context("Failure in command 'migrate-layouts'")
+        }
 
-    tabmode.execute(
-        tabmode_cmd.workspace_num,
-        tabmode_cmd.file_layout.as_deref(),
-    )
-}
+        Command::Float(float_cmd) => {
+            command_float(float_cmd, config.float, connection).context("Failure in command 'float'")
+        }
 
-/// Display i3 information.
-fn command_i3_version() -> Result<()> {
-    let mut command_executor = CommandExecutor::new()?;
-    let i3_version = command_executor.query_i3_version()?;
+        Command::Gather(gather_cmd) => command_gather(gather_cmd, config.gather, connection)
+            .context("Failure in command 'gather'"),
 
-    println!(
-        "I3 version: '{}'\n\
-         Config File: '{}'",
-        i3_version.human_readable, i3_version.loaded_config_file_name
-    );
+        Command::Balance(balance_cmd) => {
+            command_balance(balance_cmd, connection).context("Failure in command 'balance'")
+        }
 
-    Ok(())
-}
+        Command::MoveWorkspace(move_workspace_cmd) => {
+            command_move_workspace(move_workspace_cmd, config.move_workspace, connection)
+                .context("Failure in command 'move-workspace'")
+        }
 
-/// Print the snapshot of I3 layout in the tree fashion.
-fn command_print_tree(print_tree_cmd: PrintTreeCmd) -> Result<()> {
-    let mut command_executor = CommandExecutor::new()?;
-    let root_node = command_executor.query_root_node()?;
+        Command::SendHome(send_home_cmd) => {
+            command_send_home(send_home_cmd, connection).context("Failure in command 'send-home'")
+        }
 
-    let node = match print_tree_cmd.workspace_num {
-        Some(workspace_num) => find_workspace_by_num(&root_node, workspace_num)
-            .ok_or_else(|| anyhow!("Cannot find the workspace number '{}'", workspace_num))?,
+        Command::Select(select_cmd) => {
+            command_select(select_cmd, connection).context("Failure in command 'select'")
+        }
 
-        None => root_node.node(),
-    };
+        Command::ScratchpadCycle(scratchpad_cycle_cmd) => {
+            command_scratchpad_cycle(scratchpad_cycle_cmd, connection)
+                .context("Failure in command 'scratchpad-cycle'")
+        }
 
-    print_tree(node)
-}
+        Command::Park => command_park(connection).context("Failure in command 'park'"),
 
-/// Save a layout for a workspace.
-fn command_save_layout(save_layout_cmd: SaveLayoutCmd) -> Result<()> {
-    let command_executor = CommandExecutor::new()?;
-    let save_layout = SaveLayout::new(command_executor);
+        Command::Unpark(unpark_cmd) => {
+            command_unpark(unpark_cmd, connection).context("Failure in command 'unpark'")
+        }
 
-    let output: Box<dyn Write> =
-        match save_layout_cmd.output {
-            Some(output_file) => Box::new(File::create(&output_file).with_context(|| {
-                format!("Cannot create layout file '{}'", output_file.display())
-            })?),
+        Command::Group(group_cmd) => {
+            command_group(group_cmd, connection).context("Failure in command 'group'")
+        }
 
-            None => Box::new(std::io::stdout()),
-        };
+        Command::Resize(resize_cmd) => command_resize(resize_cmd, config.resize, connection)
+            .context("Failure in command 'resize'"),
 
-    save_layout.execute(save_layout_cmd.workspace_num, output, save_layout_cmd.json)
-}
+        Command::Pin(pin_cmd) => {
+            command_pin(pin_cmd, config.autolayout, connection).context("Failure in command 'pin'")
+        }
+
+        Command::LockSize => {
+            command_lock_size(connection).context("Failure in command 'lock-size'")
+        }
+
+        Command::Undo => command_undo(connection).context("Failure in command 'undo'"),
+
+        Command::History(history_cmd) => {
+            command_history(history_cmd, connection).context("Failure in command 'history'")
+        }
+
+        Command::UrgentJump(urgent_jump_cmd) => command_urgent_jump(urgent_jump_cmd, connection)
+            .context("Failure in command 'urgent-jump'"),
+
+        Command::Doctor(doctor_cmd) => command_doctor(doctor_cmd, config.tabmode, connection)
+            .context("Failure in command 'doctor'"),
+
+        Command::Ctl(ctl_cmd) => {
+            command_ctl(ctl_cmd, connection).context("Failure in command 'ctl'")
+        }
 
-/// Restore a previously saved layout on a workspace.
-fn command_restore_layout(restore_layout_cmd: RestoreLayoutCmd) -> Result<()> {
-    let command_executor = CommandExecutor::new()?;
-    let restore_layout = RestoreLayout::new(command_executor);
-
-    let input: Box<dyn Read> =
-        match restore_layout_cmd.input {
-            Some(input_file) => Box::new(File::open(&input_file).with_context(|| {
-                format!("Cannot read the layout file '{}'", input_file.display())
-            })?),
-
-            None => Box::new(std::io::stdin()),
-        };
-
-    restore_layout.execute(
-        input,
-        restore_layout_cmd.json,
-        restore_layout_cmd.restore_sizes,
-    )
+        Command::BackAndForth => command_control(ControlCommand::BackAndForth, connection)
+            .context("Failure in command 'back-and-forth'"),
+
+        Command::GenerateConfig(generate_config_cmd) => {
+            command_generate_config(generate_config_cmd, config.generate_config, connection)
+                .context("Failure in command 'generate-config'")
+        }
+
+        Command::CheckConfig => {
+            command_check_config(config).context("Failure in command 'check-config'")
+        }
+
+        Command::Batch(batch_cmd) => {
+            command_batch(batch_cmd, connection).context("Failure in command 'batch'")
+        }
+
+        Command::BarProxy => command_bar_proxy(config.bar_proxy, connection)
+            .context("Failure in command 'bar-proxy'"),
+
+        Command::ServeJson => command_serve_json(config.tabmode, connection)
+            .context("Failure in command 'serve-json'"),
+    }
 }
 
+mod atomic_file;
 mod autolayout;
+mod balance;
+mod bar_proxy;
+mod batch;
+mod birth;
+mod breadcrumb;
+mod claim;
 mod command_executor;
+mod commands;
+mod config;
+mod control;
+mod doctor;
+mod errors;
 mod event_listener;
+mod examples;
+#[cfg(test)]
+mod fixtures;
+mod float;
+mod focus_guard;
+mod gaps;
+mod gather;
+mod generate_config;
+mod group;
+mod history;
+mod layout_store;
+mod lock_size;
+mod marks;
+mod move_workspace;
+mod normalize;
+mod notify;
+mod park;
+mod picker;
+mod pin;
+mod preview;
 mod print_tree;
+mod resize;
 mod restore_layout;
 mod save_layout;
+mod schema;
+mod scratchpad;
+mod selector;
+mod send_home;
+mod serve_json;
+mod signals;
+mod split_memory;
+mod split_policy;
+mod state;
+mod state_store;
+mod stats;
+mod status;
 mod tabmode;
+mod undo;
+mod unmanaged;
+mod urgent;
 mod utilities;
+mod workspace;
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn args(args: &[&str]) -> Vec<OsString> {
+        args.iter().map(OsString::from).collect()
+    }
+
+    #[test]
+    fn asks_for_help_flags_and_subcommand() {
+        assert!(asks_for_help(args(&["--help"])));
+        assert!(asks_for_help(args(&["-h"])));
+        assert!(asks_for_help(args(&["autolayout", "--help"])));
+        assert!(asks_for_help(args(&["help", "restore-layout"])));
+
+        assert!(!asks_for_help(args(&[])));
+        assert!(!asks_for_help(args(&["autolayout"])));
+        assert!(!asks_for_help(args(&[
+            "restore-layout",
+            "-i",
+            "ws1.layout"
+        ])));
+        assert!(!asks_for_help(args(&["--helpful"])));
+    }
+
+    #[test]
+    fn porcelain_help_lists_exit_codes() {
+        let command = CliArgs::command();
+        let porcelain = command
+            .get_arguments()
+            .find(|argument| argument.get_id() == "porcelain")
+            .unwrap();
+        let help = porcelain.get_help().unwrap();
+
+        for failure in [
+            Failure::WorkspaceNotFound,
+            Failure::LayoutUnreadable,
+            Failure::Connection,
+            Failure::PartialRestore,
+            Failure::PartialNormalize,
+            Failure::NoProjectLayout,
+            Failure::NoUrgent,
+        ] {
+            let entry = format!(
+                "{} {}",
+                failure.exit_code(),
+                failure.to_string().to_lowercase()
+            );
+            assert!(help.contains(&entry), "'{}' missing in: {}", entry, help);
+        }
+    }
+}
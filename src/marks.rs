@@ -0,0 +1,150 @@
+/*
+    Copyright (C) 2022  Biagio Festa
+
+    This program is free software: you can redistribute it and/or modify
+    it under the terms of the GNU General Public License as published by
+    the Free Software Foundation, either version 3 of the License, or
+    (at your option) any later version.
+
+    This program is distributed in the hope that it will be useful,
+    but WITHOUT ANY WARRANTY; without even the implied warranty of
+    MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+    GNU General Public License for more details.
+
+    You should have received a copy of the GNU General Public License
+    along with this program.  If not, see <https://www.gnu.org/licenses/>.
+*/
+
+/// The prefix of every mark set by i3-autolayout.
+pub const MARK_PREFIX: &str = "__i3-autolayout__";
+
+/// The temporary mark of the workspace being normalized.
+pub const NORMALIZE_MARK: &str = "__i3-autolayout__tmp_ID";
+
+/// The temporary mark of the workspace being restored.
+pub const RESTORE_WORKSPACE_MARK: &str = "__i3-autolayout__restore_ws";
+
+/// The prefix of the temporary marks of the containers created by a restore.
+pub const RESTORE_CONTAINER_MARK_PREFIX: &str = "__i3-autolayout__restore_";
+
+/// The mark of the scratchpad window currently shown by `scratchpad-cycle`.
+pub const SCRATCHPAD_CURRENT_MARK: &str = "__i3-autolayout__scratchpad_current";
+
+/// The temporary mark of the column receiving a new window (mode `columns`).
+pub const COLUMN_MARK: &str = "__i3-autolayout__column";
+
+/// The prefix of the marks locking the size of a container (see `lock-size`).
+///
+/// Since i3 marks are unique, each locked container is marked with the prefix
+/// followed by its con id.
+pub const SIZE_LOCK_MARK_PREFIX: &str = "__i3-autolayout__lock_size_";
+
+/// The prefix of the marks of the tabbed containers of workspaces in tabmode.
+///
+/// Since i3 marks are unique, each container is marked with the prefix followed by its con id.
+pub const TABMODE_MARK_PREFIX: &str = "__i3-autolayout__tabmode_";
+
+/// The prefix of the marks of the tabbed containers of workspaces in tabmode, when the
+/// container was there before (e.g., i3's `workspace_layout tabbed`).
+pub const TABMODE_NATIVE_MARK_PREFIX: &str = "__i3-autolayout__tabmode_native_";
+
+/// A mark (or a family of marks sharing a prefix) owned by i3-autolayout.
+pub struct OwnedMark {
+    /// The mark, or the prefix of the family.
+    pub prefix: &'static str,
+
+    /// Who sets the mark.
+    pub owner: &'static str,
+
+    /// Whether the mark is meant to stay (otherwise it is a leftover of an interrupted action).
+    pub persistent: bool,
+}
+
+/// The inventory of the marks owned by i3-autolayout.
+///
+/// More specific prefixes come first. Any new mark must be listed here, so that
+/// `doctor` recognizes its leftovers.
+pub const OWNED_MARKS: &[OwnedMark] = &[
+    OwnedMark {
+        prefix: NORMALIZE_MARK,
+        owner: "normalize (and tabmode)",
+        persistent: false,
+    },
+    OwnedMark {
+        prefix: RESTORE_WORKSPACE_MARK,
+        owner: "restore-layout",
+        persistent: false,
+    },
+    OwnedMark {
+        prefix: RESTORE_CONTAINER_MARK_PREFIX,
+        owner: "restore-layout",
+        persistent: false,
+    },
+    OwnedMark {
+        prefix: SCRATCHPAD_CURRENT_MARK,
+        owner: "scratchpad-cycle",
+        persistent: true,
+    },
+    OwnedMark {
+        prefix: COLUMN_MARK,
+        owner: "autolayout (columns mode)",
+        persistent: false,
+    },
+    OwnedMark {
+        prefix: SIZE_LOCK_MARK_PREFIX,
+        owner: "lock-size",
+        persistent: true,
+    },
+    OwnedMark {
+        prefix: TABMODE_NATIVE_MARK_PREFIX,
+        owner: "tabmode",
+        persistent: true,
+    },
+    OwnedMark {
+        prefix: TABMODE_MARK_PREFIX,
+        owner: "tabmode",
+        persistent: true,
+    },
+];
+
+/// The temporary mark of the container created by a restore for a saved container.
+pub fn restore_container_mark(saved_id: usize) -> String {
+    format!("{}{}", RESTORE_CONTAINER_MARK_PREFIX, saved_id)
+}
+
+/// The mark locking the size of a container.
+pub fn size_lock_mark(node_id: usize) -> String {
+    format!("{}{}", SIZE_LOCK_MARK_PREFIX, node_id)
+}
+
+/// The mark of the tabbed container of a workspace in tabmode (see `TABMODE_MARK_PREFIX`).
+pub fn tabmode_mark(node_id: usize, native: bool) -> String {
+    let prefix = if native {
+        TABMODE_NATIVE_MARK_PREFIX
+    } else {
+        TABMODE_MARK_PREFIX
+    };
+
+    format!("{}{}", prefix, node_id)
+}
+
+/// The owner of a mark, if the mark belongs to i3-autolayout.
+///
+/// Marks with the i3-autolayout prefix missing from the inventory are reported as
+/// owned by an unknown action.
+pub fn owned_mark(mark: &str) -> Option<&'static OwnedMark> {
+    const UNKNOWN: OwnedMark = OwnedMark {
+        prefix: MARK_PREFIX,
+        owner: "an unknown action",
+        persistent: false,
+    };
+
+    if !mark.starts_with(MARK_PREFIX) {
+        return None;
+    }
+
+    OWNED_MARKS
+        .iter()
+        .find(|owned| mark.starts_with(owned.prefix))
+        .or(Some(&UNKNOWN))
+}
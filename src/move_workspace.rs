@@ -0,0 +1,236 @@
+/*
+    Copyright (C) 2022  Biagio Festa
+
+    This program is free software: you can redistribute it and/or modify
+    it under the terms of the GNU General Public License as published by
+    the Free Software Foundation, either version 3 of the License, or
+    (at your option) any later version.
+
+    This program is distributed in the hope that it will be useful,
+    but WITHOUT ANY WARRANTY; without even the implied warranty of
+    MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+    GNU General Public License for more details.
+
+    You should have received a copy of the GNU General Public License
+    along with this program.  If not, see <https://www.gnu.org/licenses/>.
+*/
+
+use crate::command_executor::CommandExecutor;
+use crate::utilities::balance_subtree;
+use crate::utilities::find_node_by_id;
+use crate::utilities::quote;
+use crate::utilities::ShareRatio;
+use crate::utilities::DEFAULT_SHARE_TOLERANCE;
+use anyhow::anyhow;
+use anyhow::Context;
+use anyhow::Result;
+
+/// The output a workspace is moved to.
+pub enum OutputTarget {
+    /// The output with this name.
+    Named(String),
+
+    /// The output after the current one (see `MoveWorkspace::with_order`).
+    Next,
+
+    /// The output before the current one (see `MoveWorkspace::with_order`).
+    Prev,
+}
+
+/// MoveWorkspace executor.
+///
+/// It represents a one-shot executor which moves the focused workspace to another
+/// active output.
+pub struct MoveWorkspace {
+    /// Command executor.
+    command_executor: CommandExecutor,
+
+    /// The order of the outputs for `Next` and `Prev`.
+    order: Vec<String>,
+
+    /// The tolerance (percentage points) of the balance.
+    tolerance: f64,
+}
+
+impl MoveWorkspace {
+    /// A new move-workspace executor.
+    pub fn new(command_executor: CommandExecutor) -> Self {
+        Self {
+            command_executor,
+            order: vec![],
+            tolerance: DEFAULT_SHARE_TOLERANCE,
+        }
+    }
+
+    /// The order of the outputs cycled by `Next` and `Prev` (wrapping around).
+    ///
+    /// Inactive outputs are skipped; active outputs not listed follow, from left to right.
+    pub fn with_order(mut self, order: Vec<String>) -> Self {
+        self.order = order;
+        self
+    }
+
+    /// The tolerance (percentage points) of the shares after balancing (see `verify_shares`).
+    pub fn with_tolerance(mut self, tolerance: f64) -> Self {
+        self.tolerance = tolerance;
+        self
+    }
+
+    /// Execute the action.
+    ///
+    /// With `rebalance` the workspace is balanced afterwards, so that its windows adapt to
+    /// the size of the new output.
+    pub fn execute(mut self, target: OutputTarget, rebalance: bool) -> Result<()> {
+        let workspaces = self.command_executor.query_workspaces()?;
+        let workspace = workspaces
+            .iter()
+            .find(|workspace| workspace.focused)
+            .ok_or_else(|| anyhow!("Cannot find the focused workspace"))?;
+
+        let mut outputs = self
+            .command_executor
+            .query_outputs()?
+            .into_iter()
+            .filter(|output| output.active)
+            .collect::<Vec<_>>();
+        outputs.sort_by_key(|output| (output.rect.x, output.rect.y));
+
+        let outputs = outputs
+            .into_iter()
+            .map(|output| output.name)
+            .collect::<Vec<_>>();
+
+        let output = pick_output(&workspace.output, &outputs, &self.order, target)?;
+
+        self.command_executor
+            .run(format!("move workspace to output {}", quote(&output)))
+            .with_context(|| format!("Cannot move the workspace to output '{}'", output))?;
+
+        eprintln!(
+            "Moved workspace '{}' from '{}' to '{}'",
+            workspace.name, workspace.output, output
+        );
+
+        if rebalance {
+            let root_node = self.command_executor.query_root_node()?;
+            let moved = find_node_by_id(workspace.id, &root_node)
+                .ok_or_else(|| anyhow!("Cannot find the moved workspace"))?;
+
+            balance_subtree(
+                moved,
+                &ShareRatio::Equal,
+                self.tolerance,
+                &mut self.command_executor,
+            )
+            .context("Cannot balance the workspace")?;
+        }
+
+        Ok(())
+    }
+}
+
+/// The output to move a workspace to, from `current` among the active `outputs`.
+///
+/// `Next` and `Prev` cycle `outputs` in `order` (see `MoveWorkspace::with_order`).
+fn pick_output(
+    current: &str,
+    outputs: &[String],
+    order: &[String],
+    target: OutputTarget,
+) -> Result<String> {
+    if outputs.len() < 2 {
+        return Err(anyhow!(
+            "Only one output is active ('{}'): nowhere to move the workspace",
+            outputs.first().map(String::as_str).unwrap_or(current)
+        ));
+    }
+
+    let cycle = order
+        .iter()
+        .filter(|output| outputs.contains(output))
+        .chain(outputs.iter().filter(|output| !order.contains(output)))
+        .collect::<Vec<_>>();
+
+    let position = || {
+        cycle
+            .iter()
+            .position(|output| *output == current)
+            .ok_or_else(|| anyhow!("The output of the workspace ('{}') is not active", current))
+    };
+
+    let output = match target {
+        OutputTarget::Named(name) => {
+            if !outputs.contains(&name) {
+                return Err(anyhow!(
+                    "Output '{}' is not active (active outputs: {})",
+                    name,
+                    outputs.join(", ")
+                ));
+            }
+
+            if name == current {
+                return Err(anyhow!("The workspace is already on output '{}'", name));
+            }
+
+            return Ok(name);
+        }
+
+        OutputTarget::Next => cycle[(position()? + 1) % cycle.len()],
+        OutputTarget::Prev => cycle[(position()? + cycle.len() - 1) % cycle.len()],
+    };
+
+    Ok(output.clone())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn names(names: &[&str]) -> Vec<String> {
+        names.iter().map(|name| name.to_string()).collect()
+    }
+
+    fn pick(current: &str, order: &[&str], target: OutputTarget) -> Result<String> {
+        let outputs = names(&["eDP-1", "HDMI-1", "DP-2"]);
+        pick_output(current, &outputs, &names(order), target)
+    }
+
+    #[test]
+    fn next_and_prev_cycle_outputs() {
+        // From left to right, wrapping around.
+        assert_eq!(pick("eDP-1", &[], OutputTarget::Next).unwrap(), "HDMI-1");
+        assert_eq!(pick("DP-2", &[], OutputTarget::Next).unwrap(), "eDP-1");
+        assert_eq!(pick("eDP-1", &[], OutputTarget::Prev).unwrap(), "DP-2");
+
+        // The configured order comes first, inactive outputs are skipped.
+        let order = ["DP-2", "DP-9", "eDP-1"];
+        assert_eq!(pick("DP-2", &order, OutputTarget::Next).unwrap(), "eDP-1");
+        assert_eq!(pick("eDP-1", &order, OutputTarget::Next).unwrap(), "HDMI-1");
+        assert_eq!(pick("DP-2", &order, OutputTarget::Prev).unwrap(), "HDMI-1");
+    }
+
+    #[test]
+    fn named_output_must_be_active_and_other() {
+        let named = |name: &str| OutputTarget::Named(name.to_string());
+
+        assert_eq!(pick("eDP-1", &[], named("DP-2")).unwrap(), "DP-2");
+
+        let error = pick("eDP-1", &[], named("DP-9")).unwrap_err();
+        assert_eq!(
+            error.to_string(),
+            "Output 'DP-9' is not active (active outputs: eDP-1, HDMI-1, DP-2)"
+        );
+
+        let error = pick("eDP-1", &[], named("eDP-1")).unwrap_err();
+        assert!(error.to_string().contains("already on output 'eDP-1'"));
+    }
+
+    #[test]
+    fn single_or_inactive_current_output() {
+        let error = pick_output("eDP-1", &names(&["eDP-1"]), &[], OutputTarget::Next).unwrap_err();
+        assert!(error.to_string().contains("nowhere to move"));
+
+        let error = pick("DP-9", &[], OutputTarget::Next).unwrap_err();
+        assert!(error.to_string().contains("('DP-9') is not active"));
+    }
+}
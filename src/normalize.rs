@@ -0,0 +1,287 @@
+/*
+    Copyright (C) 2022  Biagio Festa
+
+    This program is free software: you can redistribute it and/or modify
+    it under the terms of the GNU General Public License as published by
+    the Free Software Foundation, either version 3 of the License, or
+    (at your option) any later version.
+
+    This program is distributed in the hope that it will be useful,
+    but WITHOUT ANY WARRANTY; without even the implied warranty of
+    MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+    GNU General Public License for more details.
+
+    You should have received a copy of the GNU General Public License
+    along with this program.  If not, see <https://www.gnu.org/licenses/>.
+*/
+
+use crate::command_executor::CommandExecutor;
+use crate::command_executor::I3Node;
+use crate::errors::Failure;
+use crate::marks::NORMALIZE_MARK;
+use crate::undo::record_undo;
+use crate::utilities::describe_window;
+use crate::utilities::is_floating_container;
+use crate::utilities::quote;
+use crate::utilities::set_node_layout;
+use crate::utilities::Layout;
+use crate::workspace::WorkspaceRef;
+use anyhow::anyhow;
+use anyhow::Context;
+use anyhow::Result;
+use i3_ipc::reply::NodeType;
+
+/// The layout of a normalized workspace.
+#[derive(Clone, Copy, clap::ValueEnum)]
+pub enum NormalizeLayout {
+    /// Split horizontal layout.
+    Splith,
+
+    /// Split vertical layout.
+    Splitv,
+}
+
+/// Normalize executor.
+///
+/// It represents a one-shot executor which collapses the nesting of a workspace,
+/// displaying all its windows side by side in a single split.
+pub struct Normalize {
+    /// Command executor.
+    command_executor: CommandExecutor,
+}
+
+impl Normalize {
+    /// A new normalize executor.
+    pub fn new(command_executor: CommandExecutor) -> Self {
+        Self { command_executor }
+    }
+
+    /// Execute the action.
+    ///
+    /// The action will be applied on a specific workspace (argument).
+    pub fn execute(mut self, workspace: WorkspaceRef, layout: NormalizeLayout) -> Result<()> {
+        let root_node = self.command_executor.query_root_node()?;
+
+        let workspace = workspace.resolve(&root_node, &mut self.command_executor)?;
+        record_undo(&[workspace]);
+
+        let failures = normalize_workspace(workspace, &mut self.command_executor)
+            .context("Cannot normalize the workspace")?;
+
+        let layout = match layout {
+            NormalizeLayout::Splith => Layout::SplitH,
+            NormalizeLayout::Splitv => Layout::SplitV,
+        };
+
+        set_node_layout(workspace.id, layout, &mut self.command_executor)
+            .context("Cannot set layout for workspace")?;
+
+        report_normalize_failures(&failures)
+    }
+}
+
+/// Normalize a workspace.
+///
+/// Move all leaf nodes as workspace children. Floating and sticky windows are left untouched.
+/// Windows refusing to move (e.g., some Java applications) are skipped with a warning:
+/// they are returned, described (see `describe_window`) with their error.
+/// The temporary mark is removed in any case.
+pub fn normalize_workspace(
+    workspace: &I3Node,
+    command_executor: &mut CommandExecutor,
+) -> Result<Vec<String>> {
+    debug_assert!(matches!(workspace.node_type, NodeType::Workspace));
+
+    normalize_container(workspace, command_executor)
+}
+
+/// Normalize a container (e.g., a workspace, or the tabbed container wrapping it).
+///
+/// Move all leaf nodes as children of `container` (see `normalize_workspace`).
+pub fn normalize_container(
+    container: &I3Node,
+    command_executor: &mut CommandExecutor,
+) -> Result<Vec<String>> {
+    let plan = NormalizePlan::of(container);
+
+    command_executor
+        .run(&plan.mark)
+        .context("Cannot set temporary mark on focused workspace")?;
+
+    let mut failures = vec![];
+
+    for (node, command) in &plan.moves {
+        if let Err(error) = command_executor.run(command) {
+            let window = describe_window(node);
+            eprintln!("[WARN]: Cannot move window {} on mark: {:#}", window, error);
+            failures.push(format!("{}: {:#}", window, error));
+        }
+    }
+
+    command_executor
+        .run(&plan.unmark)
+        .context("Cannot unset temporary mark")?;
+
+    Ok(failures)
+}
+
+/// The i3 commands normalizing a container (see `normalize_container`).
+pub struct NormalizePlan<'a> {
+    /// Set the temporary mark on the container.
+    pub mark: String,
+
+    /// Move each nested leaf on the mark.
+    pub moves: Vec<(&'a I3Node, String)>,
+
+    /// Remove the temporary mark.
+    pub unmark: String,
+}
+
+impl<'a> NormalizePlan<'a> {
+    /// The plan normalizing `container`.
+    pub fn of(container: &'a I3Node) -> Self {
+        let mark = quote(NORMALIZE_MARK);
+
+        Self {
+            mark: format!("[con_id={}] mark {}", container.id, mark),
+            moves: nested_leaves(container)
+                .into_iter()
+                .map(|node| {
+                    let command = format!("[con_id={}] move window to mark {}", node.id, mark);
+                    (node, command)
+                })
+                .collect(),
+            unmark: format!("unmark {}", mark),
+        }
+    }
+
+    /// The commands, in order.
+    pub fn commands(&self) -> impl Iterator<Item = &str> {
+        std::iter::once(self.mark.as_str())
+            .chain(self.moves.iter().map(|(_, command)| command.as_str()))
+            .chain(std::iter::once(self.unmark.as_str()))
+    }
+}
+
+/// The leaves of a container which are not its children, in the order they are moved
+/// (see `normalize_container`). Floating and sticky nodes are skipped with their subtree.
+fn nested_leaves(container: &I3Node) -> Vec<&I3Node> {
+    let mut dfs = container
+        .nodes
+        .iter()
+        .map(|node| (node, container.id))
+        .collect::<Vec<_>>();
+    let mut leaves = vec![];
+
+    while let Some((current, parent)) = dfs.pop() {
+        if is_floating_container(current) || current.sticky {
+            continue;
+        }
+
+        if current.nodes.is_empty() && parent != container.id {
+            leaves.push(current);
+        } else {
+            dfs.extend(current.nodes.iter().map(|node| (node, current.id)));
+        }
+    }
+
+    leaves
+}
+
+/// Print the windows which could not be normalized (see `normalize_workspace`), if any.
+///
+/// It fails with `Failure::PartialNormalize` in that case.
+pub fn report_normalize_failures(failures: &[String]) -> Result<()> {
+    if failures.is_empty() {
+        return Ok(());
+    }
+
+    eprintln!("{} windows could not be normalized:", failures.len());
+    for failure in failures {
+        eprintln!("  {}", failure);
+    }
+
+    Err(
+        anyhow!("{} windows could not be normalized", failures.len())
+            .context(Failure::PartialNormalize),
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::fixtures;
+
+    fn leaf_ids(container: &I3Node) -> Vec<usize> {
+        nested_leaves(container)
+            .iter()
+            .map(|node| node.id)
+            .collect()
+    }
+
+    #[test]
+    fn nested_leaves_of_workspaces() {
+        let root_node = fixtures::load("workspaces.json");
+
+        // Already flat.
+        assert!(leaf_ids(fixtures::node(&root_node, 100)).is_empty());
+
+        assert_eq!(leaf_ids(fixtures::node(&root_node, 200)), [222, 221, 211]);
+
+        // The windows of the tabmode wrapper, or none from the wrapper itself.
+        assert_eq!(leaf_ids(fixtures::node(&root_node, 300)), [312, 311]);
+        assert!(leaf_ids(fixtures::node(&root_node, 310)).is_empty());
+
+        // Empty containers are leaves too.
+        assert!(leaf_ids(fixtures::node(&root_node, 500)).is_empty());
+        assert!(leaf_ids(fixtures::node(&root_node, 600)).is_empty());
+    }
+
+    #[test]
+    fn nested_leaves_skip_floating_and_sticky_nodes() {
+        let root_node = fixtures::load("floating.json");
+        assert!(leaf_ids(fixtures::node(&root_node, 100)).is_empty());
+
+        let root_node = fixtures::load("workspaces.json");
+        let mut workspace = fixtures::node(&root_node, 200).clone();
+        workspace.nodes[1].nodes[1].sticky = true;
+        assert_eq!(leaf_ids(&workspace), [211]);
+    }
+
+    #[test]
+    fn failures_are_reported() {
+        assert!(report_normalize_failures(&[]).is_ok());
+
+        let error = report_normalize_failures(&["'java' (42): refused".to_string()])
+            .err()
+            .unwrap();
+        assert_eq!(Failure::of(&error), Some(Failure::PartialNormalize));
+        assert_eq!(
+            error.root_cause().to_string(),
+            "1 windows could not be normalized"
+        );
+    }
+
+    #[test]
+    fn plan_moves_nested_leaves_between_marks() {
+        let root_node = fixtures::load("workspaces.json");
+        let plan = NormalizePlan::of(fixtures::node(&root_node, 200));
+        let mark = quote(NORMALIZE_MARK);
+
+        assert_eq!(
+            plan.commands().collect::<Vec<_>>(),
+            [
+                format!("[con_id=200] mark {}", mark),
+                format!("[con_id=222] move window to mark {}", mark),
+                format!("[con_id=221] move window to mark {}", mark),
+                format!("[con_id=211] move window to mark {}", mark),
+                format!("unmark {}", mark),
+            ]
+        );
+
+        // A flat workspace is only marked and unmarked.
+        let plan = NormalizePlan::of(fixtures::node(&root_node, 100));
+        assert!(plan.moves.is_empty());
+        assert_eq!(plan.commands().count(), 2);
+    }
+}
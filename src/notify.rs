@@ -0,0 +1,166 @@
+/*
+    Copyright (C) 2022  Biagio Festa
+
+    This program is free software: you can redistribute it and/or modify
+    it under the terms of the GNU General Public License as published by
+    the Free Software Foundation, either version 3 of the License, or
+    (at your option) any later version.
+
+    This program is distributed in the hope that it will be useful,
+    but WITHOUT ANY WARRANTY; without even the implied warranty of
+    MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+    GNU General Public License for more details.
+
+    You should have received a copy of the GNU General Public License
+    along with this program.  If not, see <https://www.gnu.org/licenses/>.
+*/
+
+use std::collections::HashMap;
+use std::collections::VecDeque;
+use std::process::Command;
+use std::process::Stdio;
+use std::time::Duration;
+use std::time::Instant;
+
+/// Which events of the service are notified on the desktop.
+#[derive(
+    Clone,
+    Copy,
+    PartialEq,
+    Eq,
+    PartialOrd,
+    Ord,
+    clap::ValueEnum,
+    serde::Deserialize,
+    serde::Serialize,
+)]
+#[serde(rename_all = "kebab-case")]
+pub enum NotifyLevel {
+    /// No notification.
+    None,
+
+    /// Failures: connection loss, windows left alone, failed restores.
+    Errors,
+
+    /// Failures and actions (tabmode toggles, auto-restores).
+    All,
+}
+
+/// Desktop notifications, sent through `notify-send`.
+///
+/// Sending is fire-and-forget: `notify-send` runs on its own thread and is killed after
+/// `TIMEOUT`, so that a missing binary or a hung notification daemon never blocks the
+/// service. Notifications are rate-limited: the same summary is sent at most once per
+/// `REPEAT_INTERVAL`, and at most `BURST` notifications per `BURST_INTERVAL`.
+pub struct Notifier {
+    /// The notified events.
+    level: NotifyLevel,
+
+    /// When each summary has been sent last.
+    last_sent: HashMap<String, Instant>,
+
+    /// When the recent notifications have been sent.
+    recent: VecDeque<Instant>,
+}
+
+impl Notifier {
+    /// How long `notify-send` may run.
+    const TIMEOUT: Duration = Duration::from_secs(5);
+
+    /// The minimum interval between notifications with the same summary.
+    const REPEAT_INTERVAL: Duration = Duration::from_secs(60);
+
+    /// The maximum number of notifications within `BURST_INTERVAL`.
+    const BURST: usize = 5;
+
+    /// The interval the burst is counted over.
+    const BURST_INTERVAL: Duration = Duration::from_secs(60);
+
+    /// A notifier of the events of `level`.
+    pub fn new(level: NotifyLevel) -> Self {
+        Self {
+            level,
+            last_sent: HashMap::new(),
+            recent: VecDeque::new(),
+        }
+    }
+
+    /// Notify a failure (level `errors`).
+    pub fn error(&mut self, summary: &str, body: &str) {
+        self.notify(NotifyLevel::Errors, summary, body);
+    }
+
+    /// Notify an action (level `all`).
+    pub fn info(&mut self, summary: &str, body: &str) {
+        self.notify(NotifyLevel::All, summary, body);
+    }
+
+    /// Send the notification, if its level is enabled and the rate limit allows it.
+    fn notify(&mut self, level: NotifyLevel, summary: &str, body: &str) {
+        if self.level < level {
+            return;
+        }
+
+        let now = Instant::now();
+
+        self.last_sent
+            .retain(|_, sent| now.duration_since(*sent) < Self::REPEAT_INTERVAL);
+        while self
+            .recent
+            .front()
+            .is_some_and(|sent| now.duration_since(*sent) >= Self::BURST_INTERVAL)
+        {
+            self.recent.pop_front();
+        }
+
+        if self.last_sent.contains_key(summary) || self.recent.len() >= Self::BURST {
+            println!("Notification '{}' dropped (rate limit)", summary);
+            return;
+        }
+
+        self.last_sent.insert(summary.to_string(), now);
+        self.recent.push_back(now);
+
+        let urgency = match level {
+            NotifyLevel::Errors => "critical",
+            _ => "normal",
+        };
+
+        let mut command = Command::new("notify-send");
+        command
+            .args([
+                "--app-name=i3-autolayout",
+                "--urgency",
+                urgency,
+                summary,
+                body,
+            ])
+            .stdin(Stdio::null())
+            .stdout(Stdio::null())
+            .stderr(Stdio::null());
+
+        std::thread::spawn(move || {
+            let mut child = match command.spawn() {
+                Ok(child) => child,
+                Err(error) => {
+                    eprintln!("[WARN]: Cannot run 'notify-send': {}", error);
+                    return;
+                }
+            };
+
+            let deadline = Instant::now() + Self::TIMEOUT;
+
+            while Instant::now() < deadline {
+                match child.try_wait() {
+                    Ok(Some(_)) => return,
+                    Ok(None) => std::thread::sleep(Duration::from_millis(100)),
+                    Err(_) => break,
+                }
+            }
+
+            eprintln!("[WARN]: 'notify-send' did not complete in time");
+            let _ = child.kill();
+            let _ = child.wait();
+        });
+    }
+}
@@ -0,0 +1,282 @@
+/*
+    Copyright (C) 2022  Biagio Festa
+
+    This program is free software: you can redistribute it and/or modify
+    it under the terms of the GNU General Public License as published by
+    the Free Software Foundation, either version 3 of the License, or
+    (at your option) any later version.
+
+    This program is distributed in the hope that it will be useful,
+    but WITHOUT ANY WARRANTY; without even the implied warranty of
+    MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+    GNU General Public License for more details.
+
+    You should have received a copy of the GNU General Public License
+    along with this program.  If not, see <https://www.gnu.org/licenses/>.
+*/
+
+use crate::atomic_file::AtomicFile;
+use crate::command_executor::CommandExecutor;
+use crate::command_executor::I3Node;
+use crate::command_executor::RootNode;
+use crate::restore_layout::RestoreLayout;
+use crate::save_layout::SaveLayout;
+use crate::utilities::find_node_by_id_including_floating;
+use crate::utilities::is_window;
+use crate::utilities::query_workspace_focused;
+use crate::utilities::state_dir;
+use crate::workspace::WorkspaceRef;
+use anyhow::anyhow;
+use anyhow::Context;
+use anyhow::Result;
+use std::fs::File;
+use std::path::Path;
+use std::path::PathBuf;
+
+/// The windows of a workspace parked by `Park::park`: the tiled ones but the focused one.
+fn parked_windows(workspace: &I3Node) -> Vec<usize> {
+    let mut parked = vec![];
+    let mut dfs = vec![workspace];
+
+    while let Some(current) = dfs.pop() {
+        if is_window(current) && !current.focused {
+            parked.push(current.id);
+        }
+
+        dfs.extend(current.nodes.as_slice());
+    }
+
+    parked
+}
+
+/// The state of a parked workspace.
+#[derive(serde::Deserialize, serde::Serialize)]
+struct ParkedState {
+    /// The windows moved on the scratchpad while parking.
+    parked: Vec<usize>,
+}
+
+/// Park executor.
+///
+/// It represents a one-shot executor which parks (and unparks) a workspace:
+/// every window but the focused one is moved on the scratchpad.
+pub struct Park {
+    /// Command executor.
+    command_executor: CommandExecutor,
+}
+
+impl Park {
+    const SAVE_LAYOUT_JSON: bool = false;
+    const RESTORE_SIZES: bool = true;
+
+    /// A new park executor.
+    pub fn new(command_executor: CommandExecutor) -> Self {
+        Self { command_executor }
+    }
+
+    /// Park the focused workspace.
+    ///
+    /// The layout is saved in the state directory, then every tiled window but the
+    /// focused one is moved on the scratchpad.
+    pub fn park(mut self) -> Result<()> {
+        let root_node = self.command_executor.query_root_node()?;
+        let workspace = query_workspace_focused(&root_node, &mut self.command_executor)?;
+        let workspace_ref = WorkspaceRef::of(workspace);
+
+        let (layout_path, state_path) = Self::state_paths(&workspace_ref)?;
+        if state_path.exists() {
+            return Err(anyhow!(
+                "The workspace '{}' is already parked (use 'unpark' first)",
+                workspace_ref
+            ));
+        }
+
+        let parked = parked_windows(workspace);
+        if parked.is_empty() {
+            eprintln!("Nothing to park on workspace '{}'", workspace_ref);
+            return Ok(());
+        }
+
+        let mut layout_file = AtomicFile::create(&layout_path, false).with_context(|| {
+            format!("Cannot save the layout on file '{}'", layout_path.display())
+        })?;
+
+        SaveLayout::new(self.command_executor.share())
+            .execute(workspace_ref, &mut layout_file, Self::SAVE_LAYOUT_JSON)
+            .context("Cannot save the layout")?;
+
+        layout_file.commit().context("Cannot save the layout")?;
+
+        let mut state_file = AtomicFile::create(&state_path, false)
+            .with_context(|| format!("Cannot create the park state '{}'", state_path.display()))?;
+
+        let state = ParkedState { parked };
+
+        serde_json::to_writer(&mut state_file, &state).context("Cannot write the park state")?;
+        state_file.commit().context("Cannot write the park state")?;
+
+        for &node_id in &state.parked {
+            self.command_executor
+                .run_on_node_id(node_id, "move scratchpad")
+                .context("Cannot move window on scratchpad")?;
+        }
+
+        Ok(())
+    }
+
+    /// Unpark a workspace.
+    ///
+    /// The windows parked by `park` are brought back and the saved layout is restored.
+    pub fn unpark(mut self, workspace: WorkspaceRef) -> Result<()> {
+        let root_node = self.command_executor.query_root_node()?;
+        let workspace_ref =
+            WorkspaceRef::of(workspace.resolve(&root_node, &mut self.command_executor)?);
+
+        let (layout_path, state_path) = Self::state_paths(&workspace_ref)?;
+
+        let state_file = File::open(&state_path)
+            .with_context(|| format!("The workspace '{}' is not parked", workspace_ref))?;
+        let state: ParkedState =
+            serde_json::from_reader(state_file).context("Cannot read the park state")?;
+
+        for node_id in state.parked {
+            if find_node_by_id_including_floating(node_id, &root_node).is_none() {
+                eprintln!(
+                    "[WARN]: Cannot unpark window '{}' (closed while parked)",
+                    node_id
+                );
+                continue;
+            }
+
+            self.command_executor
+                .run_on_node_id(node_id, "scratchpad show")
+                .context("Cannot show window from scratchpad")?;
+
+            self.command_executor
+                .run_on_node_id(node_id, "floating disable")
+                .context("Cannot tile window from scratchpad")?;
+        }
+
+        RestoreLayout::new(self.command_executor)
+            .execute_file(&layout_path, Self::SAVE_LAYOUT_JSON, Self::RESTORE_SIZES)
+            .context("Cannot restore layout")?;
+
+        std::fs::remove_file(&state_path).context("Cannot remove the park state")?;
+        std::fs::remove_file(&layout_path).context("Cannot remove the parked layout")
+    }
+
+    /// The park files left behind: states whose windows have all been closed while parked
+    /// (with their layout), and layouts without state.
+    pub fn leftovers(root_node: &RootNode) -> Result<Vec<PathBuf>> {
+        Self::leftovers_in(&state_dir()?, root_node)
+    }
+
+    /// The park files left behind in a directory (see `leftovers`).
+    fn leftovers_in(dir: &Path, root_node: &RootNode) -> Result<Vec<PathBuf>> {
+        let mut leftovers = vec![];
+
+        let entries = std::fs::read_dir(dir)
+            .with_context(|| format!("Cannot read the directory '{}'", dir.display()))?;
+
+        for entry in entries.flatten() {
+            let file_name = entry.file_name().to_string_lossy().into_owned();
+            let Some(key) = file_name.strip_prefix("park-") else {
+                continue;
+            };
+
+            if let Some(key) = key.strip_suffix(".json") {
+                let alive = std::fs::read_to_string(entry.path())
+                    .ok()
+                    .and_then(|content| serde_json::from_str::<ParkedState>(&content).ok())
+                    .is_some_and(|state| {
+                        state.parked.iter().any(|&node_id| {
+                            find_node_by_id_including_floating(node_id, root_node).is_some()
+                        })
+                    });
+
+                if !alive {
+                    let layout_path = dir.join(format!("park-{}.layout", key));
+
+                    leftovers.push(entry.path());
+                    if layout_path.exists() {
+                        leftovers.push(layout_path);
+                    }
+                }
+            } else if let Some(key) = key.strip_suffix(".layout") {
+                if !dir.join(format!("park-{}.json", key)).exists() {
+                    leftovers.push(entry.path());
+                }
+            }
+        }
+
+        leftovers.sort();
+        Ok(leftovers)
+    }
+
+    /// The paths of the layout file and the state file for a workspace.
+    fn state_paths(workspace: &WorkspaceRef) -> Result<(PathBuf, PathBuf)> {
+        let dir = state_dir()?;
+        let key = workspace.file_key();
+
+        Ok((
+            dir.join(format!("park-{}.layout", key)),
+            dir.join(format!("park-{}.json", key)),
+        ))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::fixtures;
+    use crate::fixtures::TempDir;
+
+    #[test]
+    fn parked_windows_leave_the_focused_one() {
+        let root_node = fixtures::load("workspaces.json");
+
+        let mut parked = parked_windows(fixtures::node(&root_node, 100));
+        parked.sort();
+        assert_eq!(parked, [101, 103]);
+
+        // Nested windows are parked too, empty containers are not.
+        let mut parked = parked_windows(fixtures::node(&root_node, 200));
+        parked.sort();
+        assert_eq!(parked, [201, 211, 221, 222]);
+        assert!(parked_windows(fixtures::node(&root_node, 500)).is_empty());
+
+        // Floating windows stay.
+        let root_node = fixtures::load("floating.json");
+        assert_eq!(parked_windows(fixtures::node(&root_node, 100)), [101]);
+    }
+
+    #[test]
+    fn leftovers_are_dead_states_and_orphan_layouts() {
+        let dir = TempDir::new("park");
+        let root_node = fixtures::load("workspaces.json");
+        let write = |name: &str, content: &str| {
+            std::fs::write(dir.path().join(name), content).unwrap();
+        };
+
+        // Parked windows still alive.
+        write("park-1.json", r#"{"parked":[101,103]}"#);
+        write("park-1.layout", "");
+
+        // Every parked window closed meanwhile.
+        write("park-2.json", r#"{"parked":[901,902]}"#);
+        write("park-2.layout", "");
+
+        // A layout without state, and other state files.
+        write("park-web.layout", "");
+        write("birth-workspaces.json", "{}");
+
+        assert_eq!(
+            Park::leftovers_in(dir.path(), &root_node).unwrap(),
+            [
+                dir.path().join("park-2.json"),
+                dir.path().join("park-2.layout"),
+                dir.path().join("park-web.layout"),
+            ]
+        );
+    }
+}
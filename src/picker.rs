@@ -0,0 +1,161 @@
+/*
+    Copyright (C) 2022  Biagio Festa
+
+    This program is free software: you can redistribute it and/or modify
+    it under the terms of the GNU General Public License as published by
+    the Free Software Foundation, either version 3 of the License, or
+    (at your option) any later version.
+
+    This program is distributed in the hope that it will be useful,
+    but WITHOUT ANY WARRANTY; without even the implied warranty of
+    MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+    GNU General Public License for more details.
+
+    You should have received a copy of the GNU General Public License
+    along with this program.  If not, see <https://www.gnu.org/licenses/>.
+*/
+
+use crate::batch::split_line;
+use anyhow::anyhow;
+use anyhow::Context;
+use anyhow::Result;
+use std::fs::File;
+use std::io::BufRead;
+use std::io::BufReader;
+use std::io::Write;
+use std::process::Command;
+use std::process::Stdio;
+
+/// The picker used when none is configured.
+pub const DEFAULT_PICKER: &str = "dmenu";
+
+/// Let the user pick an entry with an external menu (e.g., `dmenu`, `rofi -dmenu`).
+///
+/// `picker` is a command line (split as a shell would); the entries are written on its
+/// stdin, one per line, and the selected line is read from its stdout.
+/// It returns the index of the selected entry, or `None` if the picker exits with a
+/// failure (e.g., escape pressed) or the selection is empty.
+pub fn pick(picker: &str, entries: &[String]) -> Result<Option<usize>> {
+    let arguments = split_line(picker)?;
+    let (program, arguments) = arguments
+        .split_first()
+        .ok_or_else(|| anyhow!("Empty picker command"))?;
+
+    let mut child = Command::new(program)
+        .args(arguments)
+        .stdin(Stdio::piped())
+        .stdout(Stdio::piped())
+        .spawn()
+        .with_context(|| format!("Cannot run the picker '{}'", program))?;
+
+    {
+        let mut stdin = child
+            .stdin
+            .take()
+            .ok_or_else(|| anyhow!("Cannot write to the picker"))?;
+
+        for entry in entries {
+            writeln!(stdin, "{}", entry).context("Cannot write to the picker")?;
+        }
+    }
+
+    let output = child
+        .wait_with_output()
+        .with_context(|| format!("Cannot read the selection of the picker '{}'", program))?;
+
+    if !output.status.success() {
+        return Ok(None);
+    }
+
+    let selection = String::from_utf8_lossy(&output.stdout);
+    let selection = selection.trim_end_matches('\n');
+
+    if selection.is_empty() {
+        return Ok(None);
+    }
+
+    entries
+        .iter()
+        .position(|entry| entry == selection)
+        .map(Some)
+        .ok_or_else(|| anyhow!("Unknown selection '{}'", selection))
+}
+
+/// The input of the answers to `confirm`.
+///
+/// It is stdin, unless stdin carries the input of the command (e.g., a layout): then it is
+/// the controlling terminal, failing without one.
+pub fn answers(stdin_is_input: bool) -> Result<Box<dyn BufRead>> {
+    if !stdin_is_input {
+        return Ok(Box::new(BufReader::new(std::io::stdin())));
+    }
+
+    let terminal = File::open("/dev/tty").context(
+        "Cannot ask for confirmation: stdin is the input and there is no terminal \
+         (use '--yes' in scripts)",
+    )?;
+
+    Ok(Box::new(BufReader::new(terminal)))
+}
+
+/// Ask a yes/no question, reading the answer from `input`.
+///
+/// Only `y` or `yes` (any case) confirm: an empty answer or the end of the input (e.g.,
+/// Ctrl-D) decline. The question is asked before changing anything, so an interrupt
+/// (Ctrl-C) leaves nothing to clean up.
+pub fn confirm<R, W>(question: &str, mut input: R, mut output: W) -> Result<bool>
+where
+    R: BufRead,
+    W: Write,
+{
+    write!(output, "{} [y/N] ", question).context("Cannot write the question")?;
+    output.flush().context("Cannot write the question")?;
+
+    let mut answer = String::new();
+    if input
+        .read_line(&mut answer)
+        .context("Cannot read the answer")?
+        == 0
+    {
+        writeln!(output).context("Cannot write the question")?;
+        return Ok(false);
+    }
+
+    Ok(matches!(answer.trim().to_lowercase().as_str(), "y" | "yes"))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// The answer to `confirm` for the input, and what the question wrote.
+    fn ask(input: &str) -> (bool, String) {
+        let mut output = vec![];
+        let confirmed = confirm("Restore?", input.as_bytes(), &mut output).unwrap();
+
+        (confirmed, String::from_utf8(output).unwrap())
+    }
+
+    #[test]
+    fn confirm_yes() {
+        assert_eq!(ask("y\n"), (true, "Restore? [y/N] ".to_string()));
+        assert!(ask("yes\n").0);
+        assert!(ask("Y\n").0);
+        assert!(ask("  YES  \n").0);
+        assert!(ask("y").0);
+    }
+
+    #[test]
+    fn confirm_no() {
+        assert_eq!(ask("n\n"), (false, "Restore? [y/N] ".to_string()));
+        assert!(!ask("no\n").0);
+        assert!(!ask("\n").0);
+        assert!(!ask("yess\n").0);
+        assert!(!ask("n\ny\n").0);
+    }
+
+    #[test]
+    fn confirm_eof_declines() {
+        assert_eq!(ask(""), (false, "Restore? [y/N] \n".to_string()));
+    }
+}
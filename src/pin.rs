@@ -0,0 +1,76 @@
+/*
+    Copyright (C) 2022  Biagio Festa
+
+    This program is free software: you can redistribute it and/or modify
+    it under the terms of the GNU General Public License as published by
+    the Free Software Foundation, either version 3 of the License, or
+    (at your option) any later version.
+
+    This program is distributed in the hope that it will be useful,
+    but WITHOUT ANY WARRANTY; without even the implied warranty of
+    MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+    GNU General Public License for more details.
+
+    You should have received a copy of the GNU General Public License
+    along with this program.  If not, see <https://www.gnu.org/licenses/>.
+*/
+
+use crate::command_executor::CommandExecutor;
+use crate::split_policy::is_opt_in_mark;
+use crate::utilities::find_focused_node;
+use crate::utilities::node_marks;
+use crate::utilities::quote;
+use anyhow::anyhow;
+use anyhow::Context;
+use anyhow::Result;
+
+/// Pin executor.
+///
+/// It represents a one-shot executor which toggles the opt-in mark of the focused
+/// container, so that the autolayout service (with `--opt-in-mark`) manages it.
+pub struct Pin {
+    /// Command executor.
+    command_executor: CommandExecutor,
+}
+
+impl Pin {
+    /// A new pin executor.
+    pub fn new(command_executor: CommandExecutor) -> Self {
+        Self { command_executor }
+    }
+
+    /// Execute the action.
+    ///
+    /// Since i3 marks are unique, the container is marked as `<opt_in_mark>:<con_id>`.
+    /// If the container already carries the opt-in mark, it is removed instead.
+    pub fn execute(mut self, opt_in_mark: &str) -> Result<()> {
+        let root_node = self.command_executor.query_root_node()?;
+        let focused = find_focused_node(&root_node)
+            .ok_or_else(|| anyhow!("Cannot find the focused container"))?;
+
+        let pinned = node_marks(focused)
+            .into_iter()
+            .filter(|mark| is_opt_in_mark(mark, opt_in_mark))
+            .collect::<Vec<_>>();
+
+        if pinned.is_empty() {
+            let mark = format!("{}:{}", opt_in_mark, focused.id);
+
+            self.command_executor
+                .run_on_node_id(focused.id, format!("mark --add {}", quote(&mark)))
+                .context("Cannot mark the focused container")?;
+
+            eprintln!("Pinned container '{}'", focused.id);
+        } else {
+            for mark in pinned {
+                self.command_executor
+                    .run(format!("unmark {}", quote(&mark)))
+                    .context("Cannot unmark the focused container")?;
+            }
+
+            eprintln!("Unpinned container '{}'", focused.id);
+        }
+
+        Ok(())
+    }
+}
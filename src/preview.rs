@@ -0,0 +1,305 @@
+/*
+    Copyright (C) 2022  Biagio Festa
+
+    This program is free software: you can redistribute it and/or modify
+    it under the terms of the GNU General Public License as published by
+    the Free Software Foundation, either version 3 of the License, or
+    (at your option) any later version.
+
+    This program is distributed in the hope that it will be useful,
+    but WITHOUT ANY WARRANTY; without even the implied warranty of
+    MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+    GNU General Public License for more details.
+
+    You should have received a copy of the GNU General Public License
+    along with this program.  If not, see <https://www.gnu.org/licenses/>.
+*/
+
+use crate::print_tree::layout_name;
+use crate::save_layout::KindNode;
+use crate::save_layout::LayoutNode;
+use crate::save_layout::SavedLayout;
+use crate::save_layout::SavedNode;
+
+/// The rendering of a saved layout.
+#[derive(Clone, Copy, clap::ValueEnum)]
+pub enum PreviewFormat {
+    /// The tree of containers and windows.
+    Tree,
+
+    /// The tree followed by a box diagram proportional to the saved sizes.
+    AsciiArt,
+}
+
+/// Render a saved layout as a box diagram `columns` characters wide.
+///
+/// Windows are boxes proportional to their saved sizes, labeled with their class (or id)
+/// and size.
+/// Tabbed and stacked containers are a single box labeled with their number of children.
+pub fn render_box_diagram(saved_layout: &SavedLayout, columns: usize) -> String {
+    let root = saved_layout.root();
+    let (root_width, root_height) = node_size(saved_layout, root);
+
+    // Terminal cells are about twice as tall as wide.
+    let width = columns.max(2) - 1;
+    let height = ((width as f64 * root_height / root_width / 2.0).round() as usize).max(2);
+
+    let mut canvas = Canvas::new(width + 1, height + 1);
+    place(saved_layout, root, (0, 0, width, height), &mut canvas);
+
+    canvas.render()
+}
+
+/// A box of the diagram (left, top, right and bottom edges).
+type Area = (usize, usize, usize, usize);
+
+/// The size of a node, derived from the saved sizes of its windows.
+fn node_size(saved_layout: &SavedLayout, saved_node: &SavedNode) -> (f64, f64) {
+    if let KindNode::NormalWindow(saved_window) = saved_node.kind() {
+        return (
+            saved_window.width().max(1) as f64,
+            saved_window.height().max(1) as f64,
+        );
+    }
+
+    let sizes = saved_node
+        .children()
+        .iter()
+        .map(|&child_id| node_size(saved_layout, saved_layout.lookup_by_id(child_id)))
+        .collect::<Vec<_>>();
+
+    if sizes.is_empty() {
+        return (1.0, 1.0);
+    }
+
+    let sum_width = sizes.iter().map(|size| size.0).sum();
+    let sum_height = sizes.iter().map(|size| size.1).sum();
+    let max_width = sizes.iter().map(|size| size.0).fold(0.0, f64::max);
+    let max_height = sizes.iter().map(|size| size.1).fold(0.0, f64::max);
+
+    match saved_node.layout() {
+        LayoutNode::SplitV => (max_width, sum_height),
+        LayoutNode::Stacked | LayoutNode::Tabbed => (max_width, max_height),
+        // Unknown layouts are restored as `SplitH`.
+        LayoutNode::SplitH | LayoutNode::Other(_) => (sum_width, max_height),
+    }
+}
+
+/// Draw a node (and its descendants) in `area`.
+fn place(saved_layout: &SavedLayout, saved_node: &SavedNode, area: Area, canvas: &mut Canvas) {
+    let (left, top, right, bottom) = area;
+
+    let vertical = match (saved_node.kind(), saved_node.layout()) {
+        (KindNode::NormalWindow(saved_window), _) => {
+            canvas.draw_box(
+                area,
+                &[
+                    saved_window
+                        .class()
+                        .map(str::to_string)
+                        .unwrap_or_else(|| saved_node.id().to_string()),
+                    format!("{}x{}", saved_window.width(), saved_window.height()),
+                ],
+            );
+            return;
+        }
+
+        (KindNode::EmptyContainer, _) => {
+            canvas.draw_box(area, &["(empty)".to_string()]);
+            return;
+        }
+
+        (_, layout @ (LayoutNode::Stacked | LayoutNode::Tabbed)) => {
+            canvas.draw_box(
+                area,
+                &[format!(
+                    "{} ({})",
+                    layout_name(layout),
+                    saved_node.children().len()
+                )],
+            );
+            return;
+        }
+
+        (_, LayoutNode::SplitV) => true,
+        (_, LayoutNode::SplitH | LayoutNode::Other(_)) => false,
+    };
+
+    let children = saved_node
+        .children()
+        .iter()
+        .map(|&child_id| saved_layout.lookup_by_id(child_id))
+        .collect::<Vec<_>>();
+
+    if children.is_empty() {
+        canvas.draw_box(area, &["(empty)".to_string()]);
+        return;
+    }
+
+    let shares = children
+        .iter()
+        .map(|child| {
+            let (width, height) = node_size(saved_layout, child);
+            if vertical {
+                height
+            } else {
+                width
+            }
+        })
+        .collect::<Vec<_>>();
+    let total = shares.iter().sum::<f64>();
+
+    let (start, end) = if vertical {
+        (top, bottom)
+    } else {
+        (left, right)
+    };
+    let span = (end - start) as f64;
+    let mut cumulated = 0.0;
+    let mut from = start;
+
+    for (child, share) in children.into_iter().zip(shares) {
+        cumulated += share;
+        let to = start + (span * cumulated / total).round() as usize;
+
+        let child_area = if vertical {
+            (left, from, right, to)
+        } else {
+            (from, top, to, bottom)
+        };
+
+        place(saved_layout, child, child_area, canvas);
+        from = to;
+    }
+}
+
+/// A grid of characters.
+struct Canvas {
+    rows: Vec<Vec<char>>,
+}
+
+impl Canvas {
+    fn new(width: usize, height: usize) -> Self {
+        Self {
+            rows: vec![vec![' '; width]; height],
+        }
+    }
+
+    /// Draw the edges of a box with some lines of text inside (clipped to the box).
+    fn draw_box(&mut self, area: Area, lines: &[String]) {
+        let (left, top, right, bottom) = area;
+
+        for x in left..=right {
+            self.put(x, top, '-');
+            self.put(x, bottom, '-');
+        }
+
+        for y in top..=bottom {
+            self.put(left, y, '|');
+            self.put(right, y, '|');
+        }
+
+        for (x, y) in [(left, top), (right, top), (left, bottom), (right, bottom)] {
+            self.rows[y][x] = '+';
+        }
+
+        let inner_width = right.saturating_sub(left + 1);
+
+        for (index, line) in lines.iter().enumerate() {
+            let y = top + 1 + index;
+            if y >= bottom {
+                break;
+            }
+
+            for (offset, c) in line.chars().take(inner_width).enumerate() {
+                self.rows[y][left + 1 + offset] = c;
+            }
+        }
+    }
+
+    /// Put an edge character, turning crossings into corners.
+    fn put(&mut self, x: usize, y: usize, c: char) {
+        let cell = &mut self.rows[y][x];
+
+        *cell = match *cell {
+            ' ' => c,
+            current if current == c => c,
+            _ => '+',
+        };
+    }
+
+    fn render(&self) -> String {
+        self.rows
+            .iter()
+            .map(|row| row.iter().collect::<String>().trim_end().to_string())
+            .collect::<Vec<_>>()
+            .join("\n")
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::fixtures;
+    use crate::save_layout::SaveLayout;
+
+    fn saved_workspace(workspace_id: usize) -> SavedLayout {
+        let root_node = fixtures::load("workspaces.json");
+        SaveLayout::save_subtree(fixtures::node(&root_node, workspace_id), false).unwrap()
+    }
+
+    #[test]
+    fn node_size_follows_the_layout() {
+        // Emacs beside a column of Alacritty over a stack.
+        let saved_layout = saved_workspace(200);
+        assert_eq!(
+            node_size(&saved_layout, saved_layout.root()),
+            (1912.0, 1036.0)
+        );
+
+        // An empty workspace has a unit size.
+        let saved_layout = saved_workspace(500);
+        assert_eq!(node_size(&saved_layout, saved_layout.root()), (1.0, 1.0));
+    }
+
+    #[test]
+    fn box_diagram_of_nested_containers() {
+        let saved_layout = saved_workspace(200);
+
+        assert_eq!(
+            render_box_diagram(&saved_layout, 30),
+            [
+                "+--------------+-------------+",
+                "|Emacs         |Alacritty    |",
+                "|956x1036      |956x506      |",
+                "|              |             |",
+                "|              +-------------+",
+                "|              |stacked (2)  |",
+                "|              |             |",
+                "|              |             |",
+                "+--------------+-------------+",
+            ]
+            .join("\n")
+        );
+    }
+
+    #[test]
+    fn box_diagram_of_tabbed_container() {
+        let diagram = render_box_diagram(&saved_workspace(300), 20);
+        let lines = diagram.lines().collect::<Vec<_>>();
+
+        assert_eq!(lines[1], "|tabbed (2)        |");
+        assert!(lines[2..lines.len() - 1]
+            .iter()
+            .all(|line| *line == "|                  |"));
+    }
+
+    #[test]
+    fn canvas_shares_edges_and_clips_labels() {
+        let mut canvas = Canvas::new(5, 3);
+        canvas.draw_box((0, 0, 2, 2), &["a".to_string()]);
+        canvas.draw_box((2, 0, 4, 2), &["clipped".to_string()]);
+
+        assert_eq!(canvas.render(), ["+-+-+", "|a|c|", "+-+-+"].join("\n"));
+    }
+}
@@ -16,19 +16,91 @@
 */
 
 use crate::command_executor::I3Node;
+use crate::save_layout::KindNode;
+use crate::save_layout::LayoutNode;
+use crate::save_layout::SavedLayout;
+use crate::save_layout::SavedNode;
+use crate::utilities::is_window;
 use anyhow::Context;
 use anyhow::Result;
+use i3_ipc::reply::NodeLayout;
 use ptree::TreeItem;
 use std::borrow::Cow;
 use std::io::Write;
 
 /// Print the tree associated starting from a root node.
-pub fn print_tree(node: &I3Node) -> Result<()> {
-    ptree::print_tree(&TreeNode(node)).context("Cannot print i3 tree")
+///
+/// With `show_order`, each child is annotated with its position among its siblings and
+/// its rank in the focus order of its parent (0 is the most recently focused).
+pub fn print_tree(node: &I3Node, show_order: bool) -> Result<()> {
+    ptree::print_tree(&TreeNode {
+        node,
+        show_order,
+        order: None,
+    })
+    .context("Cannot print i3 tree")
+}
+
+/// Print a saved layout as tree.
+///
+/// Containers are labeled with their layout, windows with their saved size.
+pub fn print_saved_layout(saved_layout: &SavedLayout) -> Result<()> {
+    ptree::print_tree(&SavedTreeNode {
+        saved_layout,
+        saved_node: saved_layout.root(),
+    })
+    .context("Cannot print the saved layout")
+}
+
+/// The name of a saved layout (as in i3 commands).
+pub fn layout_name(layout: &LayoutNode) -> &str {
+    match layout {
+        LayoutNode::SplitH => "splith",
+        LayoutNode::SplitV => "splitv",
+        LayoutNode::Stacked => "stacked",
+        LayoutNode::Tabbed => "tabbed",
+        LayoutNode::Other(layout) => layout,
+    }
+}
+
+/// The compact shape of the tree starting from a node.
+///
+/// Windows are `win` and containers are `<layout>(<children>)`,
+/// e.g., `splith(win, splitv(win, win))`. Floating windows are not part of the shape.
+pub fn tree_shape(node: &I3Node) -> String {
+    if is_window(node) {
+        return "win".to_string();
+    }
+
+    let children = node.nodes.iter().map(tree_shape).collect::<Vec<_>>();
+
+    format!(
+        "{}({})",
+        node_layout_name(&node.layout),
+        children.join(", ")
+    )
+}
+
+/// The name of the layout of a live node (as in i3 commands).
+pub fn node_layout_name(layout: &NodeLayout) -> &'static str {
+    match layout {
+        NodeLayout::SplitH => "splith",
+        NodeLayout::SplitV => "splitv",
+        NodeLayout::Stacked => "stacked",
+        NodeLayout::Tabbed => "tabbed",
+        NodeLayout::Dockarea => "dockarea",
+        NodeLayout::Output => "output",
+    }
 }
 
 #[derive(Clone)]
-struct TreeNode<'a>(&'a I3Node);
+struct TreeNode<'a> {
+    node: &'a I3Node,
+    show_order: bool,
+
+    /// The position of the node among its siblings and its focus rank (if shown).
+    order: Option<(usize, Option<usize>)>,
+}
 
 impl<'a> TreeItem for TreeNode<'a> {
     type Child = TreeNode<'a>;
@@ -37,6 +109,13 @@ impl<'a> TreeItem for TreeNode<'a> {
     where
         W: Write,
     {
+        if let Some((index, focus_rank)) = self.order {
+            match focus_rank {
+                Some(focus_rank) => write!(f, "#{} (focus {}) ", index, focus_rank)?,
+                None => write!(f, "#{} (focus -) ", index)?,
+            }
+        }
+
         write!(f,
                "[ID: {id}; \
                 Type: {type:?}; \
@@ -44,16 +123,149 @@ impl<'a> TreeItem for TreeNode<'a> {
                 Layout: {layout:?}; \
                 WinType: {wintype:?}; \
                 NumFloatings: {num_floats}]",
-               id = self.0.id,
-               type = self.0.node_type,
-               name = self.0.name,
-               layout = self.0.layout,
-               wintype = self.0.window_type,
-               num_floats = self.0.floating_nodes.len(),
+               id = self.node.id,
+               type = self.node.node_type,
+               name = self.node.name,
+               layout = self.node.layout,
+               wintype = self.node.window_type,
+               num_floats = self.node.floating_nodes.len(),
         )
     }
 
-    fn children(&self) -> Cow<[Self::Child]> {
-        Cow::from(self.0.nodes.iter().map(TreeNode).collect::<Vec<_>>())
+    fn children(&self) -> Cow<'_, [Self::Child]> {
+        Cow::from(
+            self.node
+                .nodes
+                .iter()
+                .enumerate()
+                .map(|(index, child)| TreeNode {
+                    node: child,
+                    show_order: self.show_order,
+                    order: self
+                        .show_order
+                        .then(|| (index, self.node.focus.iter().position(|id| *id == child.id))),
+                })
+                .collect::<Vec<_>>(),
+        )
+    }
+}
+
+#[derive(Clone)]
+struct SavedTreeNode<'a> {
+    saved_layout: &'a SavedLayout,
+    saved_node: &'a SavedNode,
+}
+
+impl<'a> TreeItem for SavedTreeNode<'a> {
+    type Child = SavedTreeNode<'a>;
+
+    fn write_self<W>(&self, f: &mut W, _style: &ptree::Style) -> std::io::Result<()>
+    where
+        W: Write,
+    {
+        let layout = layout_name(self.saved_node.layout());
+
+        match self.saved_node.kind() {
+            KindNode::Workspace(num) => write!(f, "[Workspace {}; Layout: {}]", num, layout)?,
+
+            KindNode::NamedWorkspace(name) => {
+                write!(f, "[Workspace {:?}; Layout: {}]", name, layout)?
+            }
+
+            KindNode::Splitter => write!(f, "[Container; Layout: {}]", layout)?,
+            KindNode::EmptyContainer => write!(f, "[Empty container; Layout: {}]", layout)?,
+
+            KindNode::NormalWindow(saved_window) => {
+                write!(f, "[Window {}", self.saved_node.id())?;
+
+                if let Some(class) = saved_window.class() {
+                    write!(f, "; Class: {:?}", class)?;
+                }
+
+                if let Some(title) = saved_window.title() {
+                    write!(f, "; Title: {:?}", title)?;
+                }
+
+                write!(
+                    f,
+                    "; Size: {}x{}",
+                    saved_window.width(),
+                    saved_window.height()
+                )?;
+
+                if let Some(border) = saved_window.border() {
+                    write!(f, "; Border: {}", border)?;
+                }
+
+                write!(f, "]")?;
+            }
+        }
+
+        if !self.saved_node.marks().is_empty() {
+            write!(f, " marks: {}", self.saved_node.marks().join(", "))?;
+        }
+
+        if self.saved_node.focus().len() > 1 {
+            let focus = self
+                .saved_node
+                .focus()
+                .iter()
+                .map(|id| id.to_string())
+                .collect::<Vec<_>>();
+
+            write!(f, " focus: {}", focus.join(", "))?;
+        }
+
+        Ok(())
+    }
+
+    fn children(&self) -> Cow<'_, [Self::Child]> {
+        Cow::from(
+            self.saved_node
+                .children()
+                .iter()
+                .map(|&child_id| SavedTreeNode {
+                    saved_layout: self.saved_layout,
+                    saved_node: self.saved_layout.lookup_by_id(child_id),
+                })
+                .collect::<Vec<_>>(),
+        )
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::fixtures;
+    use crate::fixtures::assert_tree_shape;
+    use crate::fixtures::node;
+
+    #[test]
+    fn shape_of_workspaces() {
+        let root_node = fixtures::load("workspaces.json");
+
+        assert_tree_shape!(node(&root_node, 100), "splith(win, win, win)");
+        assert_tree_shape!(
+            node(&root_node, 200),
+            "splith(win, splitv(win, stacked(win, win)))"
+        );
+        assert_tree_shape!(node(&root_node, 300), "splith(tabbed(win, win))");
+        assert_tree_shape!(node(&root_node, 500), "splith(splith())");
+        assert_tree_shape!(node(&root_node, 600), "splith()");
+    }
+
+    #[test]
+    fn shape_skips_floating_windows() {
+        let root_node = fixtures::load("floating.json");
+
+        assert_tree_shape!(node(&root_node, 100), "splith(win)");
+        assert_tree_shape!(node(&root_node, 200), "splith()");
+    }
+
+    #[test]
+    #[should_panic(expected = "Unexpected tree shape")]
+    fn shape_mismatch_panics() {
+        let root_node = fixtures::load("workspaces.json");
+
+        assert_tree_shape!(node(&root_node, 200), "splith(win, splitv(win, win))");
     }
 }
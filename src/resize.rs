@@ -0,0 +1,231 @@
+/*
+    Copyright (C) 2022  Biagio Festa
+
+    This program is free software: you can redistribute it and/or modify
+    it under the terms of the GNU General Public License as published by
+    the Free Software Foundation, either version 3 of the License, or
+    (at your option) any later version.
+
+    This program is distributed in the hope that it will be useful,
+    but WITHOUT ANY WARRANTY; without even the implied warranty of
+    MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+    GNU General Public License for more details.
+
+    You should have received a copy of the GNU General Public License
+    along with this program.  If not, see <https://www.gnu.org/licenses/>.
+*/
+
+use crate::command_executor::CommandExecutor;
+use crate::command_executor::I3Node;
+use crate::command_executor::RootNode;
+use crate::undo::record_undo;
+use crate::utilities::find_focused_node;
+use crate::utilities::find_node_parent_including_floating;
+use crate::utilities::find_workspace_of_node;
+use crate::utilities::is_floating_container;
+use anyhow::anyhow;
+use anyhow::Context;
+use anyhow::Result;
+use i3_ipc::reply::NodeLayout;
+use i3_ipc::reply::NodeType;
+use std::fmt::Display;
+use std::str::FromStr;
+
+/// The resize operation.
+#[derive(Clone, Copy, clap::ValueEnum)]
+pub enum ResizeAction {
+    /// Make the container bigger.
+    Grow,
+
+    /// Make the container smaller.
+    Shrink,
+}
+
+/// The amount of a resize step.
+#[derive(Clone, Copy)]
+pub enum ResizeStep {
+    /// Percentage points of the parent container.
+    Ppt(u32),
+
+    /// Pixels.
+    Px(u32),
+}
+
+impl FromStr for ResizeStep {
+    type Err = anyhow::Error;
+
+    /// Parse a step like `5ppt`, `10px` or `5` (percentage points).
+    fn from_str(s: &str) -> Result<Self> {
+        let (amount, unit) = match s.find(|c: char| !c.is_ascii_digit()) {
+            Some(index) => s.split_at(index),
+            None => (s, "ppt"),
+        };
+
+        let amount = amount
+            .parse()
+            .with_context(|| format!("Invalid resize step '{}'", s))?;
+
+        match unit {
+            "ppt" => Ok(Self::Ppt(amount)),
+            "px" => Ok(Self::Px(amount)),
+            _ => Err(anyhow!(
+                "Invalid resize step unit '{}' (expected 'ppt' or 'px')",
+                unit
+            )),
+        }
+    }
+}
+
+impl<'de> serde::Deserialize<'de> for ResizeStep {
+    fn deserialize<D>(deserializer: D) -> std::result::Result<Self, D::Error>
+    where
+        D: serde::Deserializer<'de>,
+    {
+        let step = String::deserialize(deserializer)?;
+        step.parse().map_err(serde::de::Error::custom)
+    }
+}
+
+impl serde::Serialize for ResizeStep {
+    fn serialize<S>(&self, serializer: S) -> std::result::Result<S::Ok, S::Error>
+    where
+        S: serde::Serializer,
+    {
+        let step = match self {
+            Self::Ppt(amount) => format!("{}ppt", amount),
+            Self::Px(amount) => format!("{}px", amount),
+        };
+
+        serializer.serialize_str(&step)
+    }
+}
+
+impl Display for ResizeStep {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Self::Ppt(amount) => write!(f, "{} ppt", amount),
+            Self::Px(amount) => write!(f, "{} px", amount),
+        }
+    }
+}
+
+/// Resize executor.
+///
+/// It represents a one-shot executor which resizes the focused container along the
+/// direction of its split parent.
+pub struct Resize {
+    /// Command executor.
+    command_executor: CommandExecutor,
+}
+
+impl Resize {
+    /// A new resize executor.
+    pub fn new(command_executor: CommandExecutor) -> Self {
+        Self { command_executor }
+    }
+
+    /// Execute the action.
+    ///
+    /// The dimension to resize depends on the layout of the parent: width for `splith`,
+    /// height for `splitv`. Tabbed and stacked parents (and single-child containers) are
+    /// walked up until the nearest split ancestor with siblings.
+    pub fn execute(mut self, action: ResizeAction, step: ResizeStep) -> Result<()> {
+        let root_node = self.command_executor.query_root_node()?;
+        let focused = find_focused_node(&root_node)
+            .ok_or_else(|| anyhow!("Cannot find the focused window"))?;
+
+        if is_floating_container(focused) {
+            return Err(anyhow!("The focused window is floating"));
+        }
+
+        let Some((node, dimension)) = Self::find_resizable_ancestor(focused, &root_node)? else {
+            eprintln!("Nothing to resize: the focused container has no siblings");
+            return Ok(());
+        };
+
+        if let Some(workspace) = find_workspace_of_node(node.id, &root_node) {
+            record_undo(&[workspace]);
+        }
+
+        let action = match action {
+            ResizeAction::Grow => "grow",
+            ResizeAction::Shrink => "shrink",
+        };
+
+        self.command_executor
+            .run_on_node_id(node.id, format!("resize {} {} {}", action, dimension, step))
+            .context("Cannot resize the container")
+    }
+
+    /// Walk up from `node` until a container whose parent splits it with some sibling.
+    ///
+    /// It returns such container together with the dimension to resize.
+    fn find_resizable_ancestor<'a>(
+        node: &'a I3Node,
+        root_node: &'a RootNode,
+    ) -> Result<Option<(&'a I3Node, &'static str)>> {
+        let mut current = node;
+
+        loop {
+            if current.node_type == NodeType::Workspace {
+                return Ok(None);
+            }
+
+            let parent = find_node_parent_including_floating(current.id, root_node)
+                .ok_or_else(|| anyhow!("Cannot find parent of container '{}'", current.id))?;
+
+            if parent.nodes.len() > 1 {
+                match parent.layout {
+                    NodeLayout::SplitH => return Ok(Some((current, "width"))),
+                    NodeLayout::SplitV => return Ok(Some((current, "height"))),
+                    _ => {}
+                }
+            }
+
+            current = parent;
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::fixtures;
+
+    fn resizable(node_id: usize) -> Option<(usize, &'static str)> {
+        let root_node = fixtures::load("workspaces.json");
+
+        Resize::find_resizable_ancestor(fixtures::node(&root_node, node_id), &root_node)
+            .unwrap()
+            .map(|(node, dimension)| (node.id, dimension))
+    }
+
+    #[test]
+    fn parse_steps() {
+        assert_eq!("5".parse::<ResizeStep>().unwrap().to_string(), "5 ppt");
+        assert_eq!("10ppt".parse::<ResizeStep>().unwrap().to_string(), "10 ppt");
+        assert_eq!("40px".parse::<ResizeStep>().unwrap().to_string(), "40 px");
+
+        for invalid in ["", "px", "5em", "-5"] {
+            assert!(invalid.parse::<ResizeStep>().is_err(), "{:?}", invalid);
+        }
+
+        // As configured.
+        let step = serde_json::from_str::<ResizeStep>(r#""40px""#).unwrap();
+        assert_eq!(serde_json::to_string(&step).unwrap(), r#""40px""#);
+    }
+
+    #[test]
+    fn resizable_ancestor_follows_the_parent_split() {
+        // A window of a horizontal workspace.
+        assert_eq!(resizable(102), Some((102, "width")));
+
+        // Stacked parents are walked up to their vertical split.
+        assert_eq!(resizable(221), Some((220, "height")));
+        assert_eq!(resizable(211), Some((211, "height")));
+
+        // Alone in a tabbed container, alone on its workspace.
+        assert_eq!(resizable(311), None);
+        assert_eq!(resizable(501), None);
+    }
+}
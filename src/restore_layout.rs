@@ -16,210 +16,2133 @@
 */
 
 use crate::command_executor::CommandExecutor;
+use crate::command_executor::I3Node;
+use crate::command_executor::RootNode;
+use crate::control::SuspendGuard;
+use crate::errors::Failure;
+use crate::focus_guard::FocusGuard;
+use crate::marks::restore_container_mark;
+use crate::marks::RESTORE_WORKSPACE_MARK;
+use crate::picker::confirm;
 use crate::save_layout::KindNode;
 use crate::save_layout::LayoutNode;
 use crate::save_layout::SavedLayout;
+use crate::save_layout::SavedNode;
+use crate::save_layout::SavedWindow;
+use crate::selector::con_mark_criteria;
+use crate::undo::record_undo;
+use crate::utilities::apply_marks;
+use crate::utilities::describe_window;
 use crate::utilities::find_node_by_id;
+use crate::utilities::find_node_by_id_including_floating;
+use crate::utilities::find_node_by_window_including_floating;
 use crate::utilities::find_node_parent;
+use crate::utilities::find_workspace_by_name;
 use crate::utilities::find_workspace_by_num;
-use crate::utilities::query_workspace_is_focused;
+use crate::utilities::find_workspaces;
+use crate::utilities::is_scratchpad_workspace;
+use crate::utilities::is_size_locked;
+use crate::utilities::is_window;
+use crate::utilities::layout_command;
+use crate::utilities::node_marks;
+use crate::utilities::quote;
 use crate::utilities::set_node_layout;
-use crate::utilities::set_node_split;
+use crate::utilities::split_dimension;
+use crate::utilities::verify_shares;
+use crate::utilities::BorderStyle;
 use crate::utilities::Layout;
 use crate::utilities::Split;
+use crate::utilities::DEFAULT_SHARE_TOLERANCE;
+use crate::workspace::WorkspaceFilter;
+use crate::workspace::WorkspaceRef;
 use anyhow::anyhow;
 use anyhow::Context;
 use anyhow::Result;
 use std::collections::HashMap;
+use std::collections::HashSet;
+use std::fmt::Display;
+use std::io::BufRead;
 use std::io::Read;
-use std::time::Duration;
+use std::path::Path;
+use std::str::FromStr;
 
 type NodeId = usize;
 
+/// The outcome of a restore.
+pub struct RestoreReport {
+    /// The number of commands building the structure of the layout.
+    pub commands: usize,
+
+    /// The commands which failed (resizes included, after their retries), with their error.
+    pub failures: Vec<String>,
+
+    /// The resizes skipped by the safeguards (see `SizeSafeguards`), with the reason.
+    pub skipped: Vec<String>,
+}
+
+/// The outline of a restore plan, shown before asking for confirmation
+/// (see `RestoreLayout::with_confirm`).
+pub struct PlanSummary {
+    /// The workspace of the layout.
+    pub workspace: WorkspaceRef,
+
+    /// Whether the workspace does not exist yet.
+    pub new_workspace: bool,
+
+    /// The number of windows moved into place.
+    pub moved: usize,
+
+    /// The number of containers created (empty ones included).
+    pub created: usize,
+
+    /// The number of saved windows not found, and so skipped.
+    pub skipped: usize,
+
+    /// The number of resizes (if sizes are restored).
+    pub resizes: usize,
+}
+
+impl PlanSummary {
+    /// Outline the plan restoring `saved_layout`, where `windows` maps the saved windows to
+    /// the live ones (see `resolve_windows`).
+    pub fn of(
+        saved_layout: &SavedLayout,
+        windows: &HashMap<NodeId, NodeId>,
+        plan: &[PlannedCommand],
+        restore_sizes: bool,
+    ) -> Result<Self> {
+        let count = |predicate: fn(&PlannedCommand) -> bool| {
+            plan.iter().filter(|planned| predicate(planned)).count()
+        };
+
+        Ok(Self {
+            workspace: saved_workspace(saved_layout)?,
+            new_workspace: count(|planned| matches!(planned.command, Command::Workspace(_))) > 0,
+            moved: count(|planned| {
+                matches!(
+                    (&planned.target, &planned.command),
+                    (Target::Node(_), Command::MoveToMark(_))
+                )
+            }),
+            created: count(|planned| match &planned.command {
+                Command::Mark(mark) => mark != RESTORE_WORKSPACE_MARK,
+                _ => false,
+            }),
+            skipped: saved_layout.window_count().saturating_sub(windows.len()),
+            resizes: if restore_sizes {
+                count(|planned| planned.stage == Stage::Size)
+            } else {
+                0
+            },
+        })
+    }
+}
+
+impl Display for PlanSummary {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(
+            f,
+            "Restoring workspace '{}'{}: {} windows moved, {} containers created, \
+             {} windows not found (skipped)",
+            self.workspace,
+            if self.new_workspace { " (new)" } else { "" },
+            self.moved,
+            self.created,
+            self.skipped
+        )?;
+
+        if self.resizes > 0 {
+            write!(f, ", {} resizes", self.resizes)?;
+        }
+
+        Ok(())
+    }
+}
+
+/// A percentage (e.g., `70%`), parsed with or without the `%` sign.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct Percentage(pub f64);
+
+impl FromStr for Percentage {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> Result<Self> {
+        let value = s
+            .strip_suffix('%')
+            .unwrap_or(s)
+            .trim()
+            .parse::<f64>()
+            .with_context(|| format!("Invalid percentage '{}'", s))?;
+
+        if !value.is_finite() || value <= 0.0 {
+            return Err(anyhow!(
+                "Invalid percentage '{}' (expected a positive value)",
+                s
+            ));
+        }
+
+        Ok(Self(value))
+    }
+}
+
+impl<'de> serde::Deserialize<'de> for Percentage {
+    fn deserialize<D>(deserializer: D) -> std::result::Result<Self, D::Error>
+    where
+        D: serde::Deserializer<'de>,
+    {
+        let percentage = String::deserialize(deserializer)?;
+        percentage.parse().map_err(serde::de::Error::custom)
+    }
+}
+
+impl serde::Serialize for Percentage {
+    fn serialize<S>(&self, serializer: S) -> std::result::Result<S::Ok, S::Error>
+    where
+        S: serde::Serializer,
+    {
+        serializer.serialize_str(&self.to_string())
+    }
+}
+
+impl Display for Percentage {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{}%", self.0)
+    }
+}
+
+/// The safeguards against restoring sizes saved while the workspace was mid-layout
+/// (e.g., a window shrunk to a sliver).
+#[derive(Clone, Copy, Default)]
+pub struct SizeSafeguards {
+    /// Saved sizes (pixels) below this are not restored.
+    pub min_size: Option<isize>,
+
+    /// Resizes changing a dimension by more than this (of its current value) are skipped.
+    pub max_delta: Option<Percentage>,
+}
+
+impl SizeSafeguards {
+    /// Why restoring the `saved` size of a dimension currently `current` (pixels) is
+    /// unsafe, if it is.
+    fn violation(&self, saved: isize, current: isize) -> Option<String> {
+        if let Some(min_size) = self.min_size {
+            if saved < min_size {
+                return Some(format!("below {} px", min_size));
+            }
+        }
+
+        if let Some(max_delta) = self.max_delta {
+            let delta = (saved - current).abs() as f64;
+
+            if current > 0 && delta * 100.0 > current as f64 * max_delta.0 {
+                return Some(format!("changes {} px by more than {}", current, max_delta));
+            }
+        }
+
+        None
+    }
+}
+
 /// RestoreLayout executor.
 ///
 /// Restore a previosly saved layout for a workspace.
 pub struct RestoreLayout {
     command_executor: CommandExecutor,
+    record_undo: bool,
+    simplify: bool,
+    tolerance: f64,
+    fail_partial: bool,
+    size_safeguards: SizeSafeguards,
+    filter: WorkspaceFilter,
+    skip_empty: bool,
+
+    /// Where the answer to the confirmation is read from (no confirmation if `None`).
+    confirm: Option<Box<dyn BufRead>>,
 }
 
 impl RestoreLayout {
-    const SLEEPTIME_BEFORE_RESIZE: Duration = Duration::from_millis(200);
-    const SLEEPTIME_INTRA_RESIZE: Duration = Duration::from_micros(200);
+    const RESIZE_RETRY_PX: isize = 4;
 
     /// Construct the new executor.
     pub fn new(command_executor: CommandExecutor) -> Self {
-        Self { command_executor }
+        Self {
+            command_executor,
+            record_undo: true,
+            simplify: false,
+            tolerance: DEFAULT_SHARE_TOLERANCE,
+            fail_partial: false,
+            size_safeguards: SizeSafeguards::default(),
+            filter: WorkspaceFilter::default(),
+            skip_empty: false,
+            confirm: None,
+        }
+    }
+
+    /// Restore the layout only if its workspace is selected by `filter`.
+    pub fn with_filter(mut self, filter: WorkspaceFilter) -> Self {
+        self.filter = filter;
+        self
+    }
+
+    /// Do not recreate the saved empty containers (see `KindNode::EmptyContainer`).
+    pub fn with_skip_empty(mut self, skip_empty: bool) -> Self {
+        self.skip_empty = skip_empty;
+        self
+    }
+
+    /// Print the outline of the plan (see `PlanSummary`) and ask for confirmation before
+    /// changing anything, reading the answer from `answers` (see `picker::answers`).
+    pub fn with_confirm(mut self, answers: Option<Box<dyn BufRead>>) -> Self {
+        self.confirm = answers;
+        self
+    }
+
+    /// Skip the resizes deemed unsafe (see `SizeSafeguards`).
+    pub fn with_size_safeguards(mut self, size_safeguards: SizeSafeguards) -> Self {
+        self.size_safeguards = size_safeguards;
+        self
+    }
+
+    /// Fail (with `Failure::PartialRestore`) if some command of the restore fails.
+    ///
+    /// The restore is carried on anyway: the error is returned at the end.
+    pub fn with_fail_partial(mut self, fail_partial: bool) -> Self {
+        self.fail_partial = fail_partial;
+        self
+    }
+
+    /// The tolerance (percentage points) of the shares after restoring sizes
+    /// (see `verify_shares`).
+    pub fn with_tolerance(mut self, tolerance: f64) -> Self {
+        self.tolerance = tolerance;
+        self
+    }
+
+    /// Collapse the containers with a single child before restoring
+    /// (see `SavedLayout::simplify`).
+    pub fn with_simplify(mut self, simplify: bool) -> Self {
+        self.simplify = simplify;
+        self
+    }
+
+    /// Do not push a snapshot of the workspace on the undo stack before restoring.
+    ///
+    /// For callers which already took the snapshot (or are undoing).
+    pub fn without_undo(mut self) -> Self {
+        self.record_undo = false;
+        self
     }
 
     /// It reads the saved workspace from `input`.
     ///
     /// Then it tries to restore the layout saved with a best-effort approach.
-    pub fn execute<R>(mut self, input: R, json_input: bool, restore_sizes: bool) -> Result<()>
+    pub fn execute<R>(self, input: R, json_input: bool, restore_sizes: bool) -> Result<()>
     where
         R: Read,
     {
-        let saved_layout = SavedLayout::deserialize(input, json_input)?;
-
-        let workspace_num = match saved_layout.root().kind() {
-            KindNode::Workspace(workspace_num) => Ok(*workspace_num),
-            _ => Err(anyhow!("Invalid layout. Workspace is missing")),
-        }?;
-
-        let mut created_paths = HashMap::new();
-        let mut dfs = vec![(saved_layout.root(), Vec::<(NodeId, LayoutNode)>::new())];
-
-        while let Some((saved_node, mut path)) = dfs.pop() {
-            if saved_node.children().is_empty() {
-                let node_exists = self
-                    .move_node_on_ws_if_exists(saved_node.id(), workspace_num)
-                    .with_context(|| format!("Cannot move node '{}'", saved_node.id()))?;
-
-                if node_exists {
-                    self.create_path_tree_for_node(saved_node.id(), &path, &mut created_paths)?;
-                } else {
-                    println!(
-                        "[WARN]: Cannot restore node '{}' (not found)",
-                        saved_node.id()
-                    );
-                }
-            } else {
-                path.push((saved_node.id(), saved_node.layout()));
-
-                dfs.extend(
-                    saved_node
-                        .children()
-                        .iter()
-                        .rev()
-                        .map(|&child_id| (saved_layout.lookup_by_id(child_id), path.clone())),
-                )
+        let saved_layout = SavedLayout::from_reader(input, json_input)?;
+
+        self.execute_layout(saved_layout, restore_sizes)
+    }
+
+    /// It reads the saved workspace from the file `path` (or from its backup).
+    ///
+    /// Then it tries to restore the layout saved with a best-effort approach.
+    pub fn execute_file(self, path: &Path, json_input: bool, restore_sizes: bool) -> Result<()> {
+        let saved_layout = SavedLayout::load(path, json_input)?;
+
+        self.execute_layout(saved_layout, restore_sizes)
+    }
+
+    /// It tries to restore the layout `saved_layout` with a best-effort approach.
+    ///
+    /// With `with_fail_partial`, failed commands make it fail (see `apply`).
+    pub fn execute_layout(mut self, saved_layout: SavedLayout, restore_sizes: bool) -> Result<()> {
+        let root = saved_layout.root();
+        let num = match root.kind() {
+            KindNode::Workspace(num) => Some(*num),
+            _ => None,
+        };
+
+        if !self.filter.matches(num, root.name()) {
+            eprintln!(
+                "Workspace '{}' filtered out: nothing to restore",
+                saved_workspace(&saved_layout)?
+            );
+            return Ok(());
+        }
+
+        if let Some(answers) = self.confirm.take() {
+            eprintln!("{}", self.summarize(&saved_layout, restore_sizes)?);
+
+            if !confirm("Restore the layout?", answers, std::io::stderr())? {
+                eprintln!("Restore cancelled");
+                return Ok(());
             }
         }
 
-        if restore_sizes {
-            std::thread::sleep(Self::SLEEPTIME_BEFORE_RESIZE);
+        let report = self.apply(&saved_layout, restore_sizes)?;
+
+        if !report.skipped.is_empty() {
+            eprintln!("{} resizes skipped (safety)", report.skipped.len());
+        }
 
-            self.restore_sizes(&saved_layout)
-                .context("Cannot restore sizes of layout")?;
+        if self.fail_partial && !report.failures.is_empty() {
+            return Err(anyhow!(
+                "{} of {} commands failed",
+                report.failures.len(),
+                report.commands
+            )
+            .context(Failure::PartialRestore));
         }
 
         Ok(())
     }
 
-    fn move_node_on_ws_if_exists(&mut self, node_id: usize, workspace_num: i32) -> Result<bool> {
-        const MARK_ID: &str = "MARK_TMP_RESTORE";
+    /// Outline the plan restoring `saved_layout` on the current tree, without changing
+    /// anything.
+    pub fn summarize(
+        &mut self,
+        saved_layout: &SavedLayout,
+        restore_sizes: bool,
+    ) -> Result<PlanSummary> {
+        let simplified;
+        let saved_layout = if self.simplify {
+            simplified = saved_layout.clone().simplify()?;
+            &simplified
+        } else {
+            saved_layout
+        };
+
+        let root_node = self.command_executor.query_root_node()?;
+        let windows = resolve_windows(saved_layout, &root_node)?;
+        let plan = plan_restore(saved_layout, &root_node, &windows, self.skip_empty)?;
+
+        PlanSummary::of(saved_layout, &windows, &plan, restore_sizes)
+    }
+
+    /// Restore the layout `saved_layout` with a best-effort approach.
+    ///
+    /// Failed commands do not stop the restore: they are printed as warnings and
+    /// returned in the report. It fails only if the layout cannot be restored at all.
+    pub fn apply(
+        &mut self,
+        saved_layout: &SavedLayout,
+        restore_sizes: bool,
+    ) -> Result<RestoreReport> {
+        let simplified;
+        let saved_layout = if self.simplify {
+            simplified = saved_layout.clone().simplify()?;
+            &simplified
+        } else {
+            saved_layout
+        };
+
+        let workspace = saved_workspace(saved_layout)?;
 
         let root_node = self.command_executor.query_root_node()?;
-        let change_focus = query_workspace_is_focused(workspace_num, &mut self.command_executor)
-            .context("Cannot check whether workspace is focused")?;
+        let workspace_node = find_saved_workspace(&workspace, &root_node);
+        let workspace_name = workspace_node
+            .and_then(|workspace| workspace.name.clone())
+            .unwrap_or_else(|| workspace.to_string());
+
+        if self.record_undo {
+            if let Some(workspace_node) = workspace_node {
+                record_undo(&[workspace_node]);
+            }
+        }
 
-        if find_node_by_id(node_id, &root_node).is_some() {
-            self.command_executor
-                .run_on_node_id(node_id, format!("move to workspace {}", workspace_num))?;
+        let _suspend_guard = SuspendGuard::acquire(&self.command_executor, &workspace_name)?;
+        let _focus_guard = FocusGuard::capture(&mut self.command_executor)?;
 
-            let workspace = find_workspace_by_num(&root_node, workspace_num)
-                .expect("Expected WS to exist after move on it");
+        let mut failures = vec![];
+        self.restore_workspace_layout(saved_layout, &root_node, &mut failures);
 
-            self.command_executor
-                .run_on_node_id(workspace.id, format!("mark {}", MARK_ID))?;
+        let windows = resolve_windows(saved_layout, &root_node)?;
+        let plan = plan_restore(saved_layout, &root_node, &windows, self.skip_empty)?;
+        let (structure, sizes): (Vec<_>, Vec<_>) = plan
+            .into_iter()
+            .partition(|planned| planned.stage == Stage::Structure);
 
-            if change_focus {
-                self.command_executor
-                    .run_on_node_id(workspace.id, "focus")?;
+        // A window refusing to move does not stop the others: its failure names it.
+        let (failed, errors): (Vec<_>, Vec<_>) = self.run_plan(&structure)?.into_iter().unzip();
+        failures.extend(failed.iter().zip(errors).map(|(planned, error)| {
+            let window = match planned.target {
+                Target::Node(node_id) => find_node_by_id_including_floating(node_id, &root_node)
+                    .filter(|node| is_window(node))
+                    .map(|node| format!(" (window {})", describe_window(node))),
+                _ => None,
             }
+            .unwrap_or_default();
 
-            let _ = self
-                .command_executor
-                .run_on_node_id(node_id, format!("move to mark {}", MARK_ID));
+            eprintln!(
+                "[WARN]: Cannot execute '{}'{}: {:#}",
+                planned, window, error
+            );
+            format!("{}{}: {:#}", planned, window, error)
+        }));
 
-            Ok(true)
-        } else {
-            Ok(false)
+        // i3 may still be applying the moves: the sizes are only meaningful once the
+        // structure is in place.
+        let settled = self.command_executor.wait_for_tree(|root_node| {
+            is_structure_restored(saved_layout, &windows, &structure, &failed, root_node)
+        });
+        let (root_node, restore_sizes) = match settled {
+            Ok(root_node) => (root_node, restore_sizes),
+            Err(error) => {
+                eprintln!(
+                    "[WARN]: The restored structure did not settle; sizes are not restored: {:#}",
+                    error
+                );
+                failures.push(format!("structure not settled: {:#}", error));
+                (self.command_executor.query_root_node()?, false)
+            }
+        };
+
+        // The moves of the windows may have reset the layout of the workspace.
+        self.restore_workspace_layout(saved_layout, &root_node, &mut failures);
+        let created_paths = find_created_containers(saved_layout, &root_node);
+
+        self.restore_marks(saved_layout, &windows, &created_paths)
+            .context("Cannot restore marks")?;
+
+        let unmarks = created_paths
+            .keys()
+            .map(|&saved_id| restore_container_mark(saved_id))
+            .chain([RESTORE_WORKSPACE_MARK.to_string()])
+            .map(|mark| format!("unmark {}", quote(&mark)))
+            .collect::<Vec<_>>();
+
+        self.command_executor
+            .run_batch(&unmarks)
+            .context("Cannot remove temporary marks")?;
+
+        let mut skipped = vec![];
+
+        if restore_sizes {
+            let (sizes, skipped_nodes) = self.safe_sizes(sizes, &mut skipped)?;
+
+            failures.extend(
+                self.restore_sizes(&sizes)
+                    .context("Cannot restore sizes of layout")?,
+            );
+
+            self.verify_sizes(saved_layout, &windows, &skipped_nodes)
+                .context("Cannot verify sizes of layout")?;
         }
+
+        Ok(RestoreReport {
+            commands: structure.len(),
+            failures,
+            skipped,
+        })
     }
 
-    fn create_path_tree_for_node(
+    /// Set the saved layout of the workspace node itself (e.g., `splitv`, `tabbed`), if the
+    /// live one differs.
+    ///
+    /// A workspace not created yet is skipped. A failure is recorded in `failures`.
+    fn restore_workspace_layout(
+        &mut self,
+        saved_layout: &SavedLayout,
+        root_node: &RootNode,
+        failures: &mut Vec<String>,
+    ) {
+        let Some((workspace_id, layout)) = workspace_layout_change(saved_layout, root_node) else {
+            return;
+        };
+
+        if let Err(error) = set_node_layout(workspace_id, layout, &mut self.command_executor) {
+            eprintln!(
+                "[WARN]: Cannot restore the layout of the workspace: {:#}",
+                error
+            );
+            failures.push(format!("{}: {:#}", layout_command(layout), error));
+        }
+    }
+
+    /// Execute planned commands in a single message.
+    ///
+    /// It returns the commands which failed, with their error.
+    fn run_plan<'a>(
+        &mut self,
+        plan: &'a [PlannedCommand],
+    ) -> Result<Vec<(&'a PlannedCommand, anyhow::Error)>> {
+        let commands = plan
+            .iter()
+            .map(|planned| planned.to_string())
+            .collect::<Vec<_>>();
+        let results = self.command_executor.run_batch(&commands)?;
+
+        Ok(plan
+            .iter()
+            .zip(results)
+            .filter_map(|(planned, result)| result.err().map(|error| (planned, error)))
+            .collect())
+    }
+
+    /// Apply the saved marks of windows and containers.
+    ///
+    /// `windows` maps the saved windows to the live ones (see `resolve_windows`),
+    /// `created_paths` maps the saved containers to the ones created by the restore.
+    fn restore_marks(
         &mut self,
-        node_id: usize,
-        path: &[(NodeId, LayoutNode)],
-        created_paths: &mut HashMap<NodeId, NodeId>,
+        saved_layout: &SavedLayout,
+        windows: &HashMap<NodeId, NodeId>,
+        created_paths: &HashMap<NodeId, NodeId>,
     ) -> Result<()> {
-        const MARK_ID: &str = "MARK_MOVE";
+        let root_node = self.command_executor.query_root_node()?;
+        let mut marks = vec![];
+        let mut dfs = vec![saved_layout.root()];
+
+        while let Some(saved_node) = dfs.pop() {
+            let node_id = match saved_node.kind() {
+                KindNode::NormalWindow(_) => windows.get(&saved_node.id()).copied(),
+                KindNode::Splitter | KindNode::EmptyContainer => {
+                    created_paths.get(&saved_node.id()).copied()
+                }
+                _ => None,
+            };
+
+            if let Some(node_id) = node_id {
+                if !saved_node.marks().is_empty() {
+                    marks.push((node_id, saved_node.marks().to_vec()));
+                }
+            }
+
+            dfs.extend(
+                saved_node
+                    .children()
+                    .iter()
+                    .rev()
+                    .map(|&child_id| saved_layout.lookup_by_id(child_id)),
+            )
+        }
+
+        apply_marks(&marks, &root_node, &mut self.command_executor)
+    }
+
+    /// Execute the planned resizes.
+    ///
+    /// Resizes refused by i3 are retried with nearby sizes (see `retry_sizes`). It returns
+    /// the resizes which failed anyway, with their error.
+    fn restore_sizes(&mut self, sizes: &[PlannedCommand]) -> Result<Vec<String>> {
+        let failed = self.run_plan(sizes)?;
+        let command_executor = &mut self.command_executor;
+
+        Ok(retry_sizes(
+            failed.into_iter().map(|(planned, _)| planned),
+            |node_id, command| command_executor.run_on_node_id(node_id, command),
+        ))
+    }
 
-        let mut last_id = node_id;
+    /// The resizes deemed safe (see `SizeSafeguards`) and not touching a locked size
+    /// (see `is_size_locked`), and the windows with skipped ones.
+    ///
+    /// Each skipped resize is printed as a warning and added to `skipped`.
+    fn safe_sizes(
+        &mut self,
+        sizes: Vec<PlannedCommand>,
+        skipped: &mut Vec<String>,
+    ) -> Result<(Vec<PlannedCommand>, HashSet<usize>)> {
+        let mut skipped_nodes = HashSet::new();
 
-        for (split_id, split_layout) in path.iter().skip(1).rev() {
-            match created_paths.get(split_id) {
-                Some(&new_splitter_id) => {
-                    self.command_executor
-                        .run_on_node_id(new_splitter_id, format!("mark {}", MARK_ID))?;
+        let root_node = self.command_executor.query_root_node()?;
+        let mut safe = vec![];
 
-                    self.command_executor
-                        .run_on_node_id(last_id, format!("move to mark {}", MARK_ID))?;
+        for planned in sizes {
+            let violation = match (&planned.target, &planned.command) {
+                (Target::Node(node_id), Command::Resize(dimension, size))
+                    if is_size_locked(*node_id, &root_node) =>
+                {
+                    Some((
+                        *node_id,
+                        *dimension,
+                        *size,
+                        "the size is locked".to_string(),
+                    ))
+                }
+                (Target::Node(node_id), Command::Resize(dimension, size)) => {
+                    find_node_by_id(*node_id, &root_node).and_then(|node| {
+                        let current = match *dimension {
+                            "width" => node.window_rect.width,
+                            _ => node.window_rect.height,
+                        };
 
-                    return Ok(());
+                        self.size_safeguards
+                            .violation(*size, current)
+                            .map(|reason| (*node_id, *dimension, *size, reason))
+                    })
                 }
-                None => {
-                    let layout = match split_layout {
-                        LayoutNode::SplitH => Layout::SplitH,
-                        LayoutNode::SplitV => Layout::SplitV,
-                        LayoutNode::Stacked => Layout::Stacked,
-                        LayoutNode::Tabbed => Layout::Tabbed,
-                    };
-
-                    set_node_split(last_id, Split::Horizontal, &mut self.command_executor)?;
-                    set_node_layout(last_id, layout, &mut self.command_executor)?;
-
-                    let root_node = self.command_executor.query_root_node()?;
-                    last_id = find_node_parent(last_id, &root_node)
-                        .expect("Expected parent just created")
-                        .id;
-
-                    created_paths.insert(*split_id, last_id);
+                _ => None,
+            };
+
+            match violation {
+                Some((node_id, dimension, size, reason)) => {
+                    let message = format!(
+                        "{} {} px of node '{}' skipped (safety): {}",
+                        dimension, size, node_id, reason
+                    );
+
+                    eprintln!("[WARN]: Restoring the {}", message);
+                    skipped.push(message);
+                    skipped_nodes.insert(node_id);
                 }
+                None => safe.push(planned),
             }
         }
 
-        Ok(())
+        Ok((safe, skipped_nodes))
     }
 
-    fn restore_sizes(&mut self, saved_layout: &SavedLayout) -> Result<()> {
+    /// Verify the shares of the restored windows, correcting the drift (see `verify_shares`).
+    ///
+    /// Only the saved containers whose children are all windows are verified: their
+    /// targets are the shares implied by the saved sizes of the windows. Containers with
+    /// a window in `skipped_nodes` (whose resize was deemed unsafe) are not verified.
+    fn verify_sizes(
+        &mut self,
+        saved_layout: &SavedLayout,
+        windows: &HashMap<NodeId, NodeId>,
+        skipped_nodes: &HashSet<usize>,
+    ) -> Result<()> {
+        let root_node = self.command_executor.query_root_node()?;
+        let mut verifications = vec![];
         let mut dfs = vec![saved_layout.root()];
 
         while let Some(saved_node) = dfs.pop() {
-            if let KindNode::NormalWindow(saved_window) = saved_node.kind() {
-                let root_node = self.command_executor.query_root_node()?;
-
-                let saved_width = saved_window.width();
-                let saved_height = saved_window.height();
-
-                if let Some(node) = find_node_by_id(saved_node.id(), &root_node) {
-                    if node.window_rect.width != saved_width {
-                        let _ = self.command_executor.run_on_node_id(
-                            node.id,
-                            format!("resize set width {} px", saved_width),
-                        );
-                    }
-
-                    if node.window_rect.height != saved_height {
-                        let _ = self.command_executor.run_on_node_id(
-                            node.id,
-                            format!("resize set height {} px", saved_height),
-                        );
-                    }
+            let children = saved_node
+                .children()
+                .iter()
+                .map(|&child_id| saved_layout.lookup_by_id(child_id))
+                .collect::<Vec<_>>();
+
+            dfs.extend(children.iter().rev());
+
+            let windows = children
+                .iter()
+                .filter_map(|&child| match child.kind() {
+                    KindNode::NormalWindow(saved_window) => windows
+                        .get(&child.id())
+                        .map(|&node_id| (node_id, saved_window)),
+                    _ => None,
+                })
+                .collect::<Vec<_>>();
+
+            if windows.len() < 2
+                || windows.len() != children.len()
+                || windows
+                    .iter()
+                    .any(|(node_id, _)| skipped_nodes.contains(node_id))
+            {
+                continue;
+            }
+
+            let Some(parent) = find_node_parent(windows[0].0, &root_node) else {
+                continue;
+            };
+
+            let sizes = windows
+                .iter()
+                .map(|(node_id, saved_window)| match split_dimension(parent) {
+                    Some("width") => (*node_id, saved_window.width() as f64),
+                    _ => (*node_id, saved_window.height() as f64),
+                })
+                .collect::<Vec<_>>();
+            let total = sizes.iter().map(|(_, size)| size).sum::<f64>();
+
+            if total > 0.0 {
+                let targets = sizes
+                    .into_iter()
+                    .map(|(node_id, size)| (node_id, size * 100.0 / total))
+                    .collect::<Vec<_>>();
+
+                verifications.push((parent.id, targets));
+            }
+        }
+
+        for (parent_id, targets) in verifications {
+            verify_shares(
+                parent_id,
+                &targets,
+                self.tolerance,
+                &mut self.command_executor,
+            )?;
+        }
+
+        Ok(())
+    }
+}
+
+/// Retry the resizes refused by i3 with nearby sizes, executing them with `run`.
+///
+/// Windows with size hints (e.g., terminals resizing by character cells) may refuse
+/// the exact size: i3 does not expose the hints, so the nearest sizes within
+/// `RESIZE_RETRY_PX` pixels are tried before giving up. It returns the resizes which
+/// failed anyway, with their error.
+fn retry_sizes<'a, P, R>(failed: P, mut run: R) -> Vec<String>
+where
+    P: IntoIterator<Item = &'a PlannedCommand>,
+    R: FnMut(usize, String) -> Result<()>,
+{
+    let mut failures = vec![];
+
+    for planned in failed {
+        let (Target::Node(node_id), Command::Resize(dimension, size)) =
+            (&planned.target, &planned.command)
+        else {
+            continue;
+        };
+
+        let mut result = run(*node_id, format!("resize set {} {} px", dimension, size));
+
+        for offset in 1..=RestoreLayout::RESIZE_RETRY_PX {
+            if result.is_ok() {
+                break;
+            }
+
+            for candidate in [size - offset, size + offset] {
+                result = run(
+                    *node_id,
+                    format!("resize set {} {} px", dimension, candidate),
+                );
+
+                if result.is_ok() {
+                    break;
                 }
+            }
+        }
 
-                std::thread::sleep(Self::SLEEPTIME_INTRA_RESIZE);
-            } else {
-                dfs.extend(
-                    saved_node
-                        .children()
-                        .iter()
-                        .map(|&child_id| saved_layout.lookup_by_id(child_id)),
+        if let Err(error) = result {
+            let failure = format!(
+                "resize {} {} px of node '{}': {:#}",
+                dimension, size, node_id, error
+            );
+
+            eprintln!("[WARN]: Cannot {}", failure);
+            failures.push(failure);
+        }
+    }
+
+    failures
+}
+
+/// The stage of a planned command.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Stage {
+    /// Commands arranging the tree (moves, splits, layouts, borders).
+    Structure,
+
+    /// Resizes, executed once the structure is in place.
+    Size,
+}
+
+/// The container a planned command applies to.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum Target {
+    /// An existing container.
+    Node(usize),
+
+    /// A container holding a temporary mark set by an earlier command of the plan.
+    Mark(String),
+
+    /// The focused container.
+    Focused,
+}
+
+/// A planned i3 command.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum Command {
+    /// Switch to a workspace (creating it).
+    Workspace(String),
+
+    /// Move the container into the container holding a mark.
+    MoveToMark(String),
+
+    /// Split the container.
+    Split(Split),
+
+    /// Set the layout of the parent of the container.
+    Layout(LayoutNode),
+
+    /// Focus the container.
+    Focus,
+
+    /// Focus the parent of the focused container.
+    FocusParent,
+
+    /// Open an empty container next to the focused one (inside it, if a workspace).
+    Open,
+
+    /// Add a mark to the container.
+    Mark(String),
+
+    /// Set the border style of the container.
+    Border(BorderStyle),
+
+    /// Make the (floating) container tiled.
+    FloatingDisable,
+
+    /// Set a dimension (`width` or `height`) of the container.
+    Resize(&'static str, isize),
+}
+
+/// A command of a restore plan.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct PlannedCommand {
+    /// When the command is executed.
+    pub stage: Stage,
+
+    /// The container the command applies to.
+    pub target: Target,
+
+    /// The command.
+    pub command: Command,
+}
+
+impl PlannedCommand {
+    fn new(stage: Stage, target: Target, command: Command) -> Self {
+        Self {
+            stage,
+            target,
+            command,
+        }
+    }
+}
+
+impl Display for PlannedCommand {
+    /// The i3 command text (a single command, so that results map one to one).
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match &self.target {
+            Target::Node(node_id) => write!(f, "[con_id={}] ", node_id)?,
+            Target::Mark(mark) => write!(f, "{} ", con_mark_criteria(mark))?,
+            Target::Focused => {}
+        }
+
+        match &self.command {
+            Command::Workspace(selector) => write!(f, "{}", selector),
+            Command::MoveToMark(mark) => write!(f, "move to mark {}", quote(mark)),
+            Command::Split(Split::Horizontal) => write!(f, "split horizontal"),
+            Command::Split(Split::Vertical) => write!(f, "split vertical"),
+            Command::Split(Split::Toggle) => write!(f, "split toggle"),
+            Command::Layout(layout) => write!(f, "{}", layout_command(Layout::from(layout))),
+            Command::Focus => write!(f, "focus"),
+            Command::FocusParent => write!(f, "focus parent"),
+            Command::Open => write!(f, "open"),
+            Command::Mark(mark) => write!(f, "mark --add {}", quote(mark)),
+            Command::Border(border) => write!(f, "border {}", border),
+            Command::FloatingDisable => write!(f, "floating disable"),
+            Command::Resize(dimension, size) => {
+                write!(f, "resize set {} {} px", dimension, size)
+            }
+        }
+    }
+}
+
+impl From<&LayoutNode> for Layout {
+    fn from(layout: &LayoutNode) -> Self {
+        match layout {
+            LayoutNode::SplitH | LayoutNode::Other(_) => Layout::SplitH,
+            LayoutNode::SplitV => Layout::SplitV,
+            LayoutNode::Stacked => Layout::Stacked,
+            LayoutNode::Tabbed => Layout::Tabbed,
+        }
+    }
+}
+
+/// The workspace of a saved layout.
+pub fn saved_workspace(saved_layout: &SavedLayout) -> Result<WorkspaceRef> {
+    match saved_layout.root().kind() {
+        KindNode::Workspace(workspace_num) => Ok(WorkspaceRef::Num(*workspace_num)),
+        KindNode::NamedWorkspace(name) => Ok(WorkspaceRef::Name(name.clone())),
+        _ => Err(anyhow!("Invalid layout. Workspace is missing")),
+    }
+}
+
+/// The live workspace of a saved layout, if it exists.
+pub fn find_saved_workspace<'a>(
+    workspace: &WorkspaceRef,
+    root_node: &'a RootNode,
+) -> Option<&'a I3Node> {
+    match workspace {
+        WorkspaceRef::Num(num) => find_workspace_by_num(root_node, *num),
+        WorkspaceRef::Name(name) => find_workspace_by_name(root_node, name),
+        WorkspaceRef::Focused => None,
+    }
+}
+
+/// The containers created by a restore, found by their temporary marks.
+///
+/// It maps the saved containers to the live ones.
+fn find_created_containers(
+    saved_layout: &SavedLayout,
+    root_node: &RootNode,
+) -> HashMap<NodeId, NodeId> {
+    let mut marked = HashMap::new();
+    let mut dfs = vec![root_node.node()];
+
+    while let Some(current) = dfs.pop() {
+        for mark in node_marks(current) {
+            marked.insert(mark, current.id);
+        }
+
+        dfs.extend(current.nodes.as_slice());
+    }
+
+    let mut created_paths = HashMap::new();
+    let mut dfs = vec![saved_layout.root()];
+
+    while let Some(saved_node) = dfs.pop() {
+        if let Some(&node_id) = marked.get(&restore_container_mark(saved_node.id())) {
+            created_paths.insert(saved_node.id(), node_id);
+        }
+
+        dfs.extend(
+            saved_node
+                .children()
+                .iter()
+                .map(|&child_id| saved_layout.lookup_by_id(child_id)),
+        );
+    }
+
+    created_paths
+}
+
+/// Whether the structure commands of a restore are visible in the tree.
+///
+/// The marks of the created containers must exist, and each planned window must be a
+/// child of its saved parent (the marked workspace or created container). Windows and
+/// marks whose commands are in `failed` are not waited for.
+fn is_structure_restored(
+    saved_layout: &SavedLayout,
+    windows: &HashMap<NodeId, NodeId>,
+    structure: &[PlannedCommand],
+    failed: &[&PlannedCommand],
+    root_node: &RootNode,
+) -> bool {
+    let succeeded = structure
+        .iter()
+        .filter(|planned| !failed.contains(planned))
+        .collect::<Vec<_>>();
+
+    let mut marked = HashMap::new();
+    let mut dfs = vec![root_node.node()];
+
+    while let Some(current) = dfs.pop() {
+        for mark in node_marks(current) {
+            marked.insert(mark, current.id);
+        }
+
+        dfs.extend(current.nodes.as_slice());
+    }
+
+    let expected_marks = succeeded
+        .iter()
+        .filter_map(|planned| match &planned.command {
+            Command::Mark(mark) => Some(mark.clone()),
+            _ => None,
+        })
+        .collect::<HashSet<_>>();
+
+    if !expected_marks.iter().all(|mark| marked.contains_key(mark)) {
+        return false;
+    }
+
+    let mut parents = HashMap::new();
+    let mut dfs = vec![saved_layout.root()];
+
+    while let Some(saved_node) = dfs.pop() {
+        for &child_id in saved_node.children() {
+            parents.insert(child_id, saved_node.id());
+            dfs.push(saved_layout.lookup_by_id(child_id));
+        }
+    }
+
+    windows.iter().all(|(saved_id, &node_id)| {
+        let target = Target::Node(node_id);
+        let planned = succeeded.iter().any(|planned| planned.target == target);
+        let refused = failed.iter().any(|planned| planned.target == target);
+
+        let Some(&parent_id) = parents.get(saved_id) else {
+            return true;
+        };
+
+        let parent_mark = if parent_id == saved_layout.root().id() {
+            RESTORE_WORKSPACE_MARK.to_string()
+        } else {
+            restore_container_mark(parent_id)
+        };
+
+        if !planned || refused || !expected_marks.contains(&parent_mark) {
+            return true;
+        }
+
+        find_node_parent(node_id, root_node)
+            .is_some_and(|parent| marked.get(&parent_mark) == Some(&parent.id))
+    })
+}
+
+/// The layout to set on the live workspace node of a saved layout, with the workspace id
+/// (see `RestoreLayout::restore_workspace_layout`).
+///
+/// It is `None` if the workspace does not exist yet, if it already has the saved layout,
+/// or if the saved layout is unknown.
+fn workspace_layout_change(
+    saved_layout: &SavedLayout,
+    root_node: &RootNode,
+) -> Option<(usize, Layout)> {
+    let layout = saved_layout.root().layout();
+    if matches!(layout, LayoutNode::Other(_)) {
+        return None;
+    }
+
+    let workspace_node = saved_workspace(saved_layout)
+        .ok()
+        .and_then(|workspace| find_saved_workspace(&workspace, root_node))?;
+
+    if LayoutNode::try_from(workspace_node.layout).ok().as_ref() == Some(layout) {
+        return None;
+    }
+
+    Some((workspace_node.id, Layout::from(layout)))
+}
+
+/// Find the live windows of a saved layout.
+///
+/// It maps the saved windows to the live ones. The con id does not survive i3 restarts:
+/// in that case the window is located by its X11 window id. Windows of portable layouts
+/// (see `SavedLayout::portable`) are matched by class, instance and title instead
+/// (see `match_portable_windows`), among the tiled windows of the saved workspace first,
+/// then of the other workspaces.
+pub fn resolve_windows(
+    saved_layout: &SavedLayout,
+    root_node: &RootNode,
+) -> Result<HashMap<NodeId, NodeId>> {
+    let mut windows = HashMap::new();
+    let mut portable = vec![];
+    let mut dfs = vec![saved_layout.root()];
+
+    while let Some(saved_node) = dfs.pop() {
+        if let KindNode::NormalWindow(saved_window) = saved_node.kind() {
+            if saved_window.is_portable() {
+                portable.push((saved_node.id(), saved_window));
+            } else if let Some(node) =
+                find_node_by_id_including_floating(saved_node.id(), root_node).or_else(|| {
+                    saved_window.window().and_then(|window| {
+                        find_node_by_window_including_floating(window, root_node)
+                    })
+                })
+            {
+                windows.insert(saved_node.id(), node.id);
+            }
+        }
+
+        dfs.extend(
+            saved_node
+                .children()
+                .iter()
+                .rev()
+                .map(|&child_id| saved_layout.lookup_by_id(child_id)),
+        );
+    }
+
+    if !portable.is_empty() {
+        let workspace = find_saved_workspace(&saved_workspace(saved_layout)?, root_node);
+
+        let mut workspaces = find_workspaces(root_node)
+            .into_iter()
+            .filter(|candidate| !is_scratchpad_workspace(candidate))
+            .collect::<Vec<_>>();
+        workspaces.sort_by_key(|candidate| Some(candidate.id) != workspace.map(|ws| ws.id));
+
+        let candidates = workspaces
+            .into_iter()
+            .flat_map(windows_in_focus_order)
+            .filter(|candidate| !windows.values().any(|&node_id| node_id == candidate.id))
+            .collect::<Vec<_>>();
+
+        windows.extend(match_portable_windows(&portable, &candidates));
+    }
+
+    Ok(windows)
+}
+
+/// The tiled windows of a subtree, the most recently focused first.
+fn windows_in_focus_order(node: &I3Node) -> Vec<&I3Node> {
+    if is_window(node) {
+        return vec![node];
+    }
+
+    let mut children = node.nodes.iter().collect::<Vec<_>>();
+    children.sort_by_key(|child| {
+        node.focus
+            .iter()
+            .position(|&focused_id| focused_id == child.id)
+            .unwrap_or(usize::MAX)
+    });
+
+    children
+        .into_iter()
+        .flat_map(windows_in_focus_order)
+        .collect()
+}
+
+/// Match the windows of a portable layout with live windows.
+///
+/// `saved_windows` are in saved order and `candidates` in order of preference. Windows
+/// with the same class, instance and title are matched first, then the ones with the same
+/// class and instance, then the ones with the same class. Within each pass, the saved
+/// windows take the candidates in order, so that identical windows (e.g., three terminals)
+/// are matched deterministically.
+pub fn match_portable_windows(
+    saved_windows: &[(NodeId, &SavedWindow)],
+    candidates: &[&I3Node],
+) -> HashMap<NodeId, NodeId> {
+    let properties = |candidate: &I3Node| {
+        let properties = candidate.window_properties.as_ref();
+
+        (
+            properties.and_then(|properties| properties.class.clone()),
+            properties.and_then(|properties| properties.instance.clone()),
+            properties.and_then(|properties| properties.title.clone()),
+        )
+    };
+
+    let candidates = candidates
+        .iter()
+        .map(|candidate| (candidate.id, properties(candidate)))
+        .collect::<Vec<_>>();
+
+    let mut matches = HashMap::new();
+    let mut taken = HashSet::new();
+
+    for pass in 0..3 {
+        for (saved_id, saved_window) in saved_windows {
+            if matches.contains_key(saved_id) || saved_window.class().is_none() {
+                continue;
+            }
+
+            let found = candidates
+                .iter()
+                .find(|(node_id, (class, instance, title))| {
+                    !taken.contains(node_id)
+                        && class.as_deref() == saved_window.class()
+                        && (pass > 1 || instance.as_deref() == saved_window.instance())
+                        && (pass > 0 || title.as_deref() == saved_window.title())
+                });
+
+            if let Some((node_id, _)) = found {
+                taken.insert(*node_id);
+                matches.insert(*saved_id, *node_id);
+            }
+        }
+    }
+
+    matches
+}
+
+/// Plan the restore of a saved layout on the tree `root_node`.
+///
+/// Windows are visited in order and each one moves exactly once: into the container
+/// created for its saved parent if it already exists, otherwise on the workspace where the
+/// containers of its path are created around it (each marked, so that the next windows
+/// can move into it). `windows` maps the saved windows to the live ones (see
+/// `resolve_windows`): the others are skipped with a warning.
+///
+/// Empty containers are opened once the windows are in place, unless `skip_empty`
+/// (see `plan_empty_container`).
+///
+/// Resizes (stage `Size`) are planned for every window, as sizes are only known
+/// once the structure is in place.
+pub fn plan_restore(
+    saved_layout: &SavedLayout,
+    root_node: &RootNode,
+    windows: &HashMap<NodeId, NodeId>,
+    skip_empty: bool,
+) -> Result<Vec<PlannedCommand>> {
+    let workspace = saved_workspace(saved_layout)?;
+    let mut plan = vec![];
+    let mut sizes = vec![];
+
+    match find_saved_workspace(&workspace, root_node) {
+        Some(workspace_node) => plan.push(PlannedCommand::new(
+            Stage::Structure,
+            Target::Node(workspace_node.id),
+            Command::Mark(RESTORE_WORKSPACE_MARK.to_string()),
+        )),
+
+        None => plan.extend([
+            PlannedCommand::new(
+                Stage::Structure,
+                Target::Focused,
+                Command::Workspace(workspace.to_i3_selector()?),
+            ),
+            PlannedCommand::new(
+                Stage::Structure,
+                Target::Focused,
+                Command::Mark(RESTORE_WORKSPACE_MARK.to_string()),
+            ),
+        ]),
+    }
+
+    let mut created = HashSet::new();
+    let mut empty_containers = vec![];
+    let mut dfs = vec![(saved_layout.root(), Vec::<&SavedNode>::new())];
+
+    while let Some((saved_node, mut path)) = dfs.pop() {
+        if let KindNode::EmptyContainer = saved_node.kind() {
+            if !skip_empty {
+                empty_containers.push((saved_node, path));
+            }
+
+            continue;
+        }
+
+        // A workspace without tiled windows is not a leaf: there is nothing to move.
+        let KindNode::NormalWindow(saved_window) = saved_node.kind() else {
+            path.push(saved_node);
+
+            dfs.extend(
+                saved_node
+                    .children()
+                    .iter()
+                    .rev()
+                    .map(|&child_id| (saved_layout.lookup_by_id(child_id), path.clone())),
+            );
+
+            continue;
+        };
+
+        let Some(node) = windows
+            .get(&saved_node.id())
+            .and_then(|&node_id| find_node_by_id_including_floating(node_id, root_node))
+        else {
+            eprintln!(
+                "[WARN]: Cannot restore node '{}' (not found)",
+                saved_node.id()
+            );
+            continue;
+        };
+
+        // A window floating now is made tiled first: it cannot be moved into a tiled
+        // container otherwise.
+        if find_node_by_id(node.id, root_node).is_none() {
+            plan.push(PlannedCommand::new(
+                Stage::Structure,
+                Target::Node(node.id),
+                Command::FloatingDisable,
+            ));
+        }
+
+        plan_path(node.id, &path, &mut created, &mut plan);
+
+        if let Some(border) = saved_window.border() {
+            if BorderStyle::of(node) != border {
+                plan.push(PlannedCommand::new(
+                    Stage::Structure,
+                    Target::Node(node.id),
+                    Command::Border(border),
+                ));
+            }
+        }
+
+        for (dimension, size) in [
+            ("width", saved_window.width()),
+            ("height", saved_window.height()),
+        ] {
+            sizes.push(PlannedCommand::new(
+                Stage::Size,
+                Target::Node(node.id),
+                Command::Resize(dimension, size),
+            ));
+        }
+    }
+
+    for (saved_node, path) in empty_containers {
+        plan_empty_container(saved_node, &path, &mut created, &mut plan);
+    }
+
+    plan.extend(sizes);
+    Ok(plan)
+}
+
+/// Plan the creation of an empty container into its saved parent (last of `path`).
+///
+/// The container is opened on the workspace, then moved into its parent: that must be
+/// the workspace or a container created for the windows, otherwise it is skipped with a
+/// warning.
+fn plan_empty_container(
+    saved_node: &SavedNode,
+    path: &[&SavedNode],
+    created: &mut HashSet<NodeId>,
+    plan: &mut Vec<PlannedCommand>,
+) {
+    let destination = match path {
+        [_workspace] => RESTORE_WORKSPACE_MARK.to_string(),
+        [.., parent] if created.contains(&parent.id()) => restore_container_mark(parent.id()),
+        _ => {
+            eprintln!(
+                "[WARN]: Cannot restore empty container '{}' (its parent holds no window)",
+                saved_node.id()
+            );
+            return;
+        }
+    };
+
+    let mark = restore_container_mark(saved_node.id());
+
+    plan.extend([
+        PlannedCommand::new(
+            Stage::Structure,
+            Target::Mark(RESTORE_WORKSPACE_MARK.to_string()),
+            Command::Focus,
+        ),
+        PlannedCommand::new(Stage::Structure, Target::Focused, Command::Open),
+        PlannedCommand::new(
+            Stage::Structure,
+            Target::Focused,
+            Command::Mark(mark.clone()),
+        ),
+    ]);
+
+    if destination != RESTORE_WORKSPACE_MARK {
+        plan.push(PlannedCommand::new(
+            Stage::Structure,
+            Target::Mark(mark),
+            Command::MoveToMark(destination),
+        ));
+    }
+
+    created.insert(saved_node.id());
+}
+
+/// Plan the move of a window into its saved path (workspace first).
+///
+/// The containers of the path not created yet are created around the window, innermost first.
+fn plan_path(
+    node_id: usize,
+    path: &[&SavedNode],
+    created: &mut HashSet<NodeId>,
+    plan: &mut Vec<PlannedCommand>,
+) {
+    let containers = path.iter().skip(1).rev();
+
+    let mut target = Target::Node(node_id);
+    let mut destination = RESTORE_WORKSPACE_MARK.to_string();
+    let mut wraps = vec![];
+
+    for container in containers {
+        if created.contains(&container.id()) {
+            destination = restore_container_mark(container.id());
+            break;
+        }
+
+        wraps.push(*container);
+    }
+
+    if wraps.is_empty() {
+        plan.push(PlannedCommand::new(
+            Stage::Structure,
+            target,
+            Command::MoveToMark(destination),
+        ));
+
+        return;
+    }
+
+    plan.push(PlannedCommand::new(
+        Stage::Structure,
+        target.clone(),
+        Command::MoveToMark(RESTORE_WORKSPACE_MARK.to_string()),
+    ));
+
+    for container in wraps {
+        // The split already matches the target orientation, so the new
+        // container is never transiently mis-oriented.
+        let split = match container.layout() {
+            LayoutNode::SplitV | LayoutNode::Stacked => Split::Vertical,
+            LayoutNode::SplitH | LayoutNode::Tabbed | LayoutNode::Other(_) => Split::Horizontal,
+        };
+
+        if let LayoutNode::Other(other) = container.layout() {
+            eprintln!(
+                "[WARN]: Unknown layout '{}' of node '{}'; using 'splith'",
+                other,
+                container.id()
+            );
+        }
+
+        let mark = restore_container_mark(container.id());
+
+        plan.extend([
+            PlannedCommand::new(Stage::Structure, target.clone(), Command::Split(split)),
+            PlannedCommand::new(
+                Stage::Structure,
+                target.clone(),
+                Command::Layout(container.layout().clone()),
+            ),
+            PlannedCommand::new(Stage::Structure, target, Command::Focus),
+            PlannedCommand::new(Stage::Structure, Target::Focused, Command::FocusParent),
+            PlannedCommand::new(
+                Stage::Structure,
+                Target::Focused,
+                Command::Mark(mark.clone()),
+            ),
+        ]);
+
+        created.insert(container.id());
+        target = Target::Mark(mark);
+    }
+
+    if destination != RESTORE_WORKSPACE_MARK {
+        plan.push(PlannedCommand::new(
+            Stage::Structure,
+            target,
+            Command::MoveToMark(destination),
+        ));
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::fixtures;
+    use crate::save_layout::SaveLayout;
+
+    fn saved(fixture: &str, workspace_id: usize) -> SavedLayout {
+        let root_node = fixtures::load(fixture);
+        SaveLayout::save_subtree(fixtures::node(&root_node, workspace_id), false).unwrap()
+    }
+
+    fn count(plan: &[PlannedCommand], stage: Stage) -> usize {
+        plan.iter().filter(|planned| planned.stage == stage).count()
+    }
+
+    #[test]
+    fn plan_nested_layout() {
+        let saved_layout = saved("workspaces.json", 200);
+        let root_node = fixtures::load("workspaces.json");
+        let windows = resolve_windows(&saved_layout, &root_node).unwrap();
+        assert_eq!(windows.len(), 4);
+
+        let plan = plan_restore(&saved_layout, &root_node, &windows, false).unwrap();
+
+        // Mark the workspace (1), move 201 (1), wrap 211 into the splitv (1 + 5), wrap 221
+        // into the stacked and move it into the splitv (1 + 5 + 1), move 222 (1).
+        assert_eq!(count(&plan, Stage::Structure), 16);
+        assert_eq!(count(&plan, Stage::Size), 8);
+        assert_eq!(plan.len(), 24);
+
+        // Sizes come last.
+        assert!(plan[..16]
+            .iter()
+            .all(|planned| planned.stage == Stage::Structure));
+
+        let commands = plan
+            .iter()
+            .map(|planned| planned.to_string())
+            .collect::<Vec<_>>();
+        assert_eq!(
+            commands[..3],
+            [
+                "[con_id=200] mark --add \"__i3-autolayout__restore_ws\"",
+                "[con_id=201] move to mark \"__i3-autolayout__restore_ws\"",
+                "[con_id=211] move to mark \"__i3-autolayout__restore_ws\"",
+            ]
+        );
+        assert_eq!(
+            commands[8..16],
+            [
+                "[con_id=221] move to mark \"__i3-autolayout__restore_ws\"",
+                "[con_id=221] split vertical",
+                "[con_id=221] layout stacked",
+                "[con_id=221] focus",
+                "focus parent",
+                "mark --add \"__i3-autolayout__restore_220\"",
+                r#"[con_mark="^__i3\\-autolayout__restore_220$"] move to mark "__i3-autolayout__restore_210""#,
+                "[con_id=222] move to mark \"__i3-autolayout__restore_220\"",
+            ]
+        );
+
+        let summary = PlanSummary::of(&saved_layout, &windows, &plan, true).unwrap();
+        assert!(!summary.new_workspace);
+        assert_eq!(summary.moved, 4);
+        assert_eq!(summary.created, 2);
+        assert_eq!(summary.skipped, 0);
+        assert_eq!(summary.resizes, 8);
+    }
+
+    #[test]
+    fn plan_splitv_in_splith() {
+        let saved_layout = saved("multi_output.json", 200);
+        let root_node = fixtures::load("multi_output.json");
+        let windows = resolve_windows(&saved_layout, &root_node).unwrap();
+
+        let plan = plan_restore(&saved_layout, &root_node, &windows, false).unwrap();
+        let structure = plan
+            .iter()
+            .filter(|planned| planned.stage == Stage::Structure)
+            .map(|planned| planned.to_string())
+            .collect::<Vec<_>>();
+
+        // The first window is split vertically before the layout is set: the container
+        // is never horizontal in between.
+        assert_eq!(
+            structure,
+            [
+                r#"[con_id=200] mark --add "__i3-autolayout__restore_ws""#,
+                r#"[con_id=211] move to mark "__i3-autolayout__restore_ws""#,
+                "[con_id=211] split vertical",
+                "[con_id=211] layout splitv",
+                "[con_id=211] focus",
+                "focus parent",
+                r#"mark --add "__i3-autolayout__restore_210""#,
+                r#"[con_id=212] move to mark "__i3-autolayout__restore_210""#,
+            ]
+        );
+    }
+
+    #[test]
+    fn plan_split_matches_target_layout() {
+        let root_node = fixtures::load("workspaces.json");
+
+        // Tabbed containers start horizontal, stacked ones vertical.
+        for (workspace_id, window_id, split, layout) in [
+            (400, 411, "split horizontal", "layout tabbed"),
+            (200, 221, "split vertical", "layout stacked"),
+        ] {
+            let saved_layout = saved("workspaces.json", workspace_id);
+            let windows = resolve_windows(&saved_layout, &root_node).unwrap();
+            let plan = plan_restore(&saved_layout, &root_node, &windows, false).unwrap();
+
+            let commands = plan
+                .iter()
+                .filter(|planned| planned.target == Target::Node(window_id))
+                .map(|planned| planned.command.clone())
+                .collect::<Vec<_>>();
+            let split_index = commands
+                .iter()
+                .position(|command| matches!(command, Command::Split(_)))
+                .unwrap();
+
+            assert_eq!(
+                PlannedCommand::new(
+                    Stage::Structure,
+                    Target::Focused,
+                    commands[split_index].clone()
+                )
+                .to_string(),
+                split
+            );
+            assert_eq!(
+                PlannedCommand::new(
+                    Stage::Structure,
+                    Target::Focused,
+                    commands[split_index + 1].clone()
                 )
+                .to_string(),
+                layout
+            );
+        }
+    }
+
+    #[test]
+    fn plan_new_workspace_without_windows() {
+        let saved_layout = saved("workspaces.json", 200);
+        let root_node = fixtures::load("scratchpad.json");
+        let windows = resolve_windows(&saved_layout, &root_node).unwrap();
+        assert!(windows.is_empty());
+
+        let plan = plan_restore(&saved_layout, &root_node, &windows, false).unwrap();
+        assert_eq!(
+            plan.iter()
+                .map(|planned| planned.to_string())
+                .collect::<Vec<_>>(),
+            [
+                "workspace number 2",
+                "mark --add \"__i3-autolayout__restore_ws\""
+            ]
+        );
+
+        let summary = PlanSummary::of(&saved_layout, &windows, &plan, true).unwrap();
+        assert!(summary.new_workspace);
+        assert_eq!(summary.skipped, 4);
+    }
+
+    #[test]
+    fn plan_workspace_without_tiled_windows() {
+        let saved_layout = saved("floating.json", 200);
+        let root_node = fixtures::load("floating.json");
+        let windows = resolve_windows(&saved_layout, &root_node).unwrap();
+
+        let plan = plan_restore(&saved_layout, &root_node, &windows, false).unwrap();
+        assert_eq!(
+            plan.iter()
+                .map(|planned| planned.to_string())
+                .collect::<Vec<_>>(),
+            [r#"[con_id=200] mark --add "__i3-autolayout__restore_ws""#]
+        );
+    }
+
+    #[test]
+    fn portable_restore_onto_another_tree() {
+        let saved_layout = saved("workspaces.json", 100).portable().unwrap();
+        let root_node = fixtures::load("multi_output.json");
+        let windows = resolve_windows(&saved_layout, &root_node).unwrap();
+
+        // Firefox and `~` are found on the saved workspace, `~/src` by class and instance
+        // on another one.
+        assert_eq!(windows, HashMap::from([(2, 101), (3, 102), (4, 211)]));
+
+        let plan = plan_restore(&saved_layout, &root_node, &windows, false).unwrap();
+        let commands = plan
+            .iter()
+            .map(|planned| planned.to_string())
+            .collect::<Vec<_>>();
+        assert_eq!(
+            commands[..4],
+            [
+                "[con_id=100] mark --add \"__i3-autolayout__restore_ws\"",
+                "[con_id=101] move to mark \"__i3-autolayout__restore_ws\"",
+                "[con_id=102] move to mark \"__i3-autolayout__restore_ws\"",
+                "[con_id=211] move to mark \"__i3-autolayout__restore_ws\"",
+            ]
+        );
+
+        let summary = PlanSummary::of(&saved_layout, &windows, &plan, true).unwrap();
+        assert_eq!(summary.moved, 3);
+        assert_eq!(summary.skipped, 0);
+    }
+
+    #[test]
+    fn portable_restore_skips_unknown_windows() {
+        let saved_layout = saved("workspaces.json", 200).portable().unwrap();
+        let root_node = fixtures::load("multi_output.json");
+        let windows = resolve_windows(&saved_layout, &root_node).unwrap();
+
+        // Only the terminal has a live counterpart (Emacs and Zathura are not open).
+        assert_eq!(windows.values().collect::<Vec<_>>(), [&211]);
+
+        let plan = plan_restore(&saved_layout, &root_node, &windows, false).unwrap();
+        let summary = PlanSummary::of(&saved_layout, &windows, &plan, true).unwrap();
+        assert_eq!(summary.moved, 1);
+        assert_eq!(summary.skipped, 3);
+    }
+
+    #[test]
+    fn portable_windows_match_in_order() {
+        let saved_layout = saved("workspaces.json", 100).portable().unwrap();
+        let root_node = fixtures::load("workspaces.json");
+        let saved_windows = [2, 3, 4]
+            .into_iter()
+            .map(
+                |saved_id| match saved_layout.lookup_by_id(saved_id).kind() {
+                    KindNode::NormalWindow(saved_window) => (saved_id, saved_window),
+                    _ => panic!("Expected a window"),
+                },
+            )
+            .collect::<Vec<_>>();
+
+        // Exact titles win over the order of the candidates.
+        let candidates = [312, 103, 102, 101]
+            .map(|node_id| fixtures::node(&root_node, node_id))
+            .to_vec();
+        assert_eq!(
+            match_portable_windows(&saved_windows, &candidates),
+            HashMap::from([(2, 101), (3, 102), (4, 103)])
+        );
+
+        // Otherwise, the candidates are taken in order.
+        let candidates = [411, 312, 211]
+            .map(|node_id| fixtures::node(&root_node, node_id))
+            .to_vec();
+        assert_eq!(
+            match_portable_windows(&saved_windows, &candidates),
+            HashMap::from([(2, 411), (3, 312), (4, 211)])
+        );
+    }
+
+    #[test]
+    fn restore_floating_windows_into_the_tiled_layout() {
+        // The window 111 was tiled next to 101 when saved; it is floating now.
+        let saved_layout = SavedLayout::from_reader(
+            r#"[
+                { "id": 100, "kind": { "Workspace": 1 }, "layout": "SplitH", "children": [101, 111] },
+                { "id": 101, "kind": { "NormalWindow": { "width": 960, "height": 1080 } }, "layout": "SplitH", "children": [] },
+                { "id": 111, "kind": { "NormalWindow": { "width": 960, "height": 1080, "window": 20971631 } }, "layout": "SplitH", "children": [] }
+            ]"#
+            .as_bytes(),
+            true,
+        )
+        .unwrap();
+        let root_node = fixtures::load("floating.json");
+        let windows = resolve_windows(&saved_layout, &root_node).unwrap();
+        assert_eq!(windows, HashMap::from([(101, 101), (111, 111)]));
+
+        let plan = plan_restore(&saved_layout, &root_node, &windows, false).unwrap();
+        assert_eq!(
+            plan.iter()
+                .filter(|planned| planned.stage == Stage::Structure)
+                .map(|planned| planned.to_string())
+                .collect::<Vec<_>>(),
+            [
+                "[con_id=100] mark --add \"__i3-autolayout__restore_ws\"",
+                "[con_id=101] move to mark \"__i3-autolayout__restore_ws\"",
+                "[con_id=111] floating disable",
+                "[con_id=111] move to mark \"__i3-autolayout__restore_ws\"",
+            ]
+        );
+
+        // Found by X11 window id when the con id changed (e.g., after an i3 restart).
+        let saved_layout = SavedLayout::from_reader(
+            r#"[
+                { "id": 1, "kind": { "Workspace": 1 }, "layout": "SplitH", "children": [2] },
+                { "id": 2, "kind": { "NormalWindow": { "width": 960, "height": 1080, "window": 20971641 } }, "layout": "SplitH", "children": [] }
+            ]"#
+            .as_bytes(),
+            true,
+        )
+        .unwrap();
+        let windows = resolve_windows(&saved_layout, &root_node).unwrap();
+        assert_eq!(windows, HashMap::from([(2, 121)]));
+    }
+
+    #[test]
+    fn workspace_layout_changes() {
+        let root_node = fixtures::load("workspaces.json");
+        let layout = |workspace: &str, layout: &str| {
+            let json = format!(
+                r#"[{{ "id": 1, "kind": {}, "layout": {}, "children": [] }}]"#,
+                workspace, layout
+            );
+            SavedLayout::from_reader(json.as_bytes(), true).unwrap()
+        };
+
+        for (workspace, saved, change) in [
+            (
+                r#"{ "Workspace": 1 }"#,
+                r#""SplitV""#,
+                Some((100, Layout::SplitV)),
+            ),
+            (
+                r#"{ "Workspace": 1 }"#,
+                r#""Tabbed""#,
+                Some((100, Layout::Tabbed)),
+            ),
+            (r#"{ "Workspace": 1 }"#, r#""SplitH""#, None),
+            (r#"{ "Workspace": 1 }"#, r#"{ "Other": "output" }"#, None),
+            (
+                r#"{ "NamedWorkspace": "web" }"#,
+                r#""Stacked""#,
+                Some((400, Layout::Stacked)),
+            ),
+            (r#"{ "NamedWorkspace": "web" }"#, r#""SplitH""#, None),
+            // Not created yet.
+            (r#"{ "Workspace": 7 }"#, r#""SplitV""#, None),
+        ] {
+            assert_eq!(
+                workspace_layout_change(&layout(workspace, saved), &root_node),
+                change,
+                "{} {}",
+                workspace,
+                saved
+            );
+        }
+    }
+
+    #[test]
+    fn workspace_layout_of_saved_workspaces() {
+        // A workspace restored onto itself keeps its layout.
+        let root_node = fixtures::load("workspaces.json");
+        for workspace_id in [100, 200, 400, 500] {
+            assert_eq!(
+                workspace_layout_change(&saved("workspaces.json", workspace_id), &root_node),
+                None
+            );
+        }
+    }
+
+    #[test]
+    fn plan_empty_containers() {
+        let saved_layout = saved("workspaces.json", 500);
+        let root_node = fixtures::load("workspaces.json");
+        let windows = HashMap::new();
+
+        let plan = plan_restore(&saved_layout, &root_node, &windows, false).unwrap();
+        assert_eq!(
+            plan.iter()
+                .map(|planned| planned.to_string())
+                .collect::<Vec<_>>(),
+            [
+                "[con_id=500] mark --add \"__i3-autolayout__restore_ws\"",
+                r#"[con_mark="^__i3\\-autolayout__restore_ws$"] focus"#,
+                "open",
+                "mark --add \"__i3-autolayout__restore_501\"",
+            ]
+        );
+
+        let plan = plan_restore(&saved_layout, &root_node, &windows, true).unwrap();
+        assert_eq!(plan.len(), 1);
+    }
+
+    #[test]
+    fn plan_nested_empty_containers() {
+        let saved_layout = SavedLayout::from_reader(
+            r#"[
+                { "id": 100, "kind": { "Workspace": 1 }, "layout": "SplitH", "children": [101, 10, 20] },
+                { "id": 101, "kind": { "NormalWindow": { "width": 640, "height": 1080 } }, "layout": "SplitH", "children": [] },
+                { "id": 10, "kind": "Splitter", "layout": "SplitV", "children": [102, 11] },
+                { "id": 102, "kind": { "NormalWindow": { "width": 640, "height": 540 } }, "layout": "SplitH", "children": [] },
+                { "id": 11, "kind": "EmptyContainer", "layout": "SplitH", "children": [], "marks": ["slot"] },
+                { "id": 20, "kind": "Splitter", "layout": "SplitV", "children": [21] },
+                { "id": 21, "kind": "EmptyContainer", "layout": "SplitH", "children": [] }
+            ]"#
+            .as_bytes(),
+            true,
+        )
+        .unwrap();
+        let root_node = fixtures::load("workspaces.json");
+        let windows = resolve_windows(&saved_layout, &root_node).unwrap();
+        assert_eq!(windows.len(), 2);
+
+        let plan = plan_restore(&saved_layout, &root_node, &windows, false).unwrap();
+        let commands = plan
+            .iter()
+            .filter(|planned| planned.stage == Stage::Structure)
+            .map(|planned| planned.to_string())
+            .collect::<Vec<_>>();
+
+        // The empty container goes into the container created for 102; the one in a
+        // container without windows (20) is skipped.
+        assert_eq!(
+            commands[commands.len() - 4..],
+            [
+                r#"[con_mark="^__i3\\-autolayout__restore_ws$"] focus"#,
+                "open",
+                "mark --add \"__i3-autolayout__restore_11\"",
+                r#"[con_mark="^__i3\\-autolayout__restore_11$"] move to mark "__i3-autolayout__restore_10""#,
+            ]
+        );
+        assert_eq!(
+            commands.iter().filter(|command| *command == "open").count(),
+            1
+        );
+
+        // Only windows get resized.
+        assert_eq!(count(&plan, Stage::Size), 4);
+    }
+
+    #[test]
+    fn parse_percentage() {
+        assert_eq!("70%".parse::<Percentage>().unwrap(), Percentage(70.0));
+        assert_eq!("12.5".parse::<Percentage>().unwrap(), Percentage(12.5));
+        assert_eq!(Percentage(70.0).to_string(), "70%");
+
+        for invalid in ["", "%", "0", "-5%", "abc", "inf", "NaN"] {
+            assert!(invalid.parse::<Percentage>().is_err(), "{}", invalid);
+        }
+    }
+
+    #[test]
+    fn no_safeguards_allow_anything() {
+        let safeguards = SizeSafeguards::default();
+
+        assert_eq!(safeguards.violation(1, 1000), None);
+        assert_eq!(safeguards.violation(5000, 10), None);
+    }
+
+    #[test]
+    fn safeguard_min_size() {
+        let safeguards = SizeSafeguards {
+            min_size: Some(100),
+            max_delta: None,
+        };
+
+        assert_eq!(
+            safeguards.violation(99, 500).as_deref(),
+            Some("below 100 px")
+        );
+        assert_eq!(safeguards.violation(100, 500), None);
+        assert_eq!(safeguards.violation(0, 0).as_deref(), Some("below 100 px"));
+    }
+
+    #[test]
+    fn safeguard_max_delta() {
+        let safeguards = SizeSafeguards {
+            min_size: None,
+            max_delta: Some(Percentage(50.0)),
+        };
+
+        // At most 500 px either way from 1000 px.
+        assert_eq!(safeguards.violation(1500, 1000), None);
+        assert_eq!(safeguards.violation(500, 1000), None);
+        assert_eq!(
+            safeguards.violation(1501, 1000).as_deref(),
+            Some("changes 1000 px by more than 50%")
+        );
+        assert_eq!(
+            safeguards.violation(499, 1000).as_deref(),
+            Some("changes 1000 px by more than 50%")
+        );
+
+        // Without a current size there is nothing to compare with.
+        assert_eq!(safeguards.violation(800, 0), None);
+    }
+
+    #[test]
+    fn safeguard_min_size_comes_first() {
+        let safeguards = SizeSafeguards {
+            min_size: Some(100),
+            max_delta: Some(Percentage(10.0)),
+        };
+
+        assert_eq!(
+            safeguards.violation(50, 1000).as_deref(),
+            Some("below 100 px")
+        );
+    }
+
+    fn with_marks(fixture: &str, marks: &[(usize, &str)]) -> RootNode {
+        let root_node = fixtures::load(fixture);
+        let mut value = serde_json::to_value(root_node.node()).unwrap();
+
+        let mut dfs = vec![&mut value];
+        while let Some(current) = dfs.pop() {
+            for (node_id, mark) in marks {
+                if current["id"] == *node_id {
+                    current["marks"] = serde_json::json!([mark]);
+                }
+            }
+            if let Some(nodes) = current["nodes"].as_array_mut() {
+                dfs.extend(nodes.iter_mut());
             }
         }
 
-        Ok(())
+        RootNode::new(serde_json::from_value(value).unwrap())
+    }
+
+    #[test]
+    fn structure_restored_once_marks_and_parents_match() {
+        let saved_layout = saved("workspaces.json", 200);
+        let root_node = fixtures::load("workspaces.json");
+        let windows = resolve_windows(&saved_layout, &root_node).unwrap();
+        let plan = plan_restore(&saved_layout, &root_node, &windows, false).unwrap();
+        let structure = plan
+            .into_iter()
+            .filter(|planned| planned.stage == Stage::Structure)
+            .collect::<Vec<_>>();
+
+        let restored = |root_node: &RootNode| {
+            is_structure_restored(&saved_layout, &windows, &structure, &[], root_node)
+        };
+
+        // No temporary mark yet: i3 has not applied the commands.
+        assert!(!restored(&root_node));
+
+        let ws_mark = RESTORE_WORKSPACE_MARK;
+        let mark_210 = restore_container_mark(210);
+        let mark_220 = restore_container_mark(220);
+
+        // A created container is still missing.
+        let partial = with_marks("workspaces.json", &[(200, ws_mark), (210, &mark_210)]);
+        assert!(!restored(&partial));
+
+        // All the marks exist, but window 201 is not under the marked workspace.
+        let misplaced = with_marks(
+            "workspaces.json",
+            &[(210, ws_mark), (220, &mark_220), (221, &mark_210)],
+        );
+        assert!(!restored(&misplaced));
+
+        let complete = with_marks(
+            "workspaces.json",
+            &[(200, ws_mark), (210, &mark_210), (220, &mark_220)],
+        );
+        assert!(restored(&complete));
+    }
+
+    #[test]
+    fn structure_restored_ignores_failed_commands() {
+        let saved_layout = saved("workspaces.json", 200);
+        let root_node = fixtures::load("workspaces.json");
+        let windows = resolve_windows(&saved_layout, &root_node).unwrap();
+        let plan = plan_restore(&saved_layout, &root_node, &windows, false).unwrap();
+        let structure = plan
+            .into_iter()
+            .filter(|planned| planned.stage == Stage::Structure)
+            .collect::<Vec<_>>();
+
+        // The stacked container was never marked: its windows are not waited for.
+        let mark_220 = Command::Mark(restore_container_mark(220));
+        let failed = structure
+            .iter()
+            .filter(|planned| planned.command == mark_220)
+            .collect::<Vec<_>>();
+        assert_eq!(failed.len(), 1);
+
+        let root_node = with_marks(
+            "workspaces.json",
+            &[
+                (200, RESTORE_WORKSPACE_MARK),
+                (210, &restore_container_mark(210)),
+            ],
+        );
+        assert!(!is_structure_restored(
+            &saved_layout,
+            &windows,
+            &structure,
+            &[],
+            &root_node
+        ));
+        assert!(is_structure_restored(
+            &saved_layout,
+            &windows,
+            &structure,
+            &failed,
+            &root_node
+        ));
+    }
+
+    #[test]
+    fn refused_resizes_are_retried_and_reported() {
+        let saved_layout = saved("workspaces.json", 200);
+        let root_node = fixtures::load("workspaces.json");
+        let windows = resolve_windows(&saved_layout, &root_node).unwrap();
+        let plan = plan_restore(&saved_layout, &root_node, &windows, false).unwrap();
+        let sizes = plan
+            .iter()
+            .filter(|planned| planned.stage == Stage::Size)
+            .collect::<Vec<_>>();
+
+        // Window 201 refuses any width; window 211 refuses the exact size only.
+        let mut commands = vec![];
+        let failures = retry_sizes(sizes.iter().copied(), |node_id, command| {
+            let first = !commands.iter().any(|(id, _)| *id == node_id);
+            commands.push((node_id, command.clone()));
+
+            let accepted = match node_id {
+                201 => !command.contains("width"),
+                211 => !first,
+                _ => true,
+            };
+
+            if accepted {
+                Ok(())
+            } else {
+                Err(anyhow!("Cannot resize"))
+            }
+        });
+
+        assert_eq!(failures.len(), 1);
+        assert!(failures[0].starts_with("resize width "));
+        assert!(failures[0].ends_with(" px of node '201': Cannot resize"));
+
+        // The height, the exact width, then 2 widths per pixel of the retry range.
+        let retries = 2 * RestoreLayout::RESIZE_RETRY_PX as usize;
+        let count = |node_id: usize| commands.iter().filter(|(id, _)| *id == node_id).count();
+        assert_eq!(count(201), 2 + retries);
+
+        // The first retry of the width is accepted.
+        assert_eq!(count(211), 3);
     }
 }
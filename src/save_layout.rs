@@ -15,10 +15,14 @@
     along with this program.  If not, see <https://www.gnu.org/licenses/>.
 */
 
+use crate::atomic_file::backup_path;
+use crate::atomic_file::AtomicFile;
 use crate::command_executor::CommandExecutor;
 use crate::command_executor::I3Node;
-use crate::utilities::find_workspace_by_num;
-use crate::utilities::query_workspace_focused;
+use crate::errors::Failure;
+use crate::utilities::node_marks;
+use crate::utilities::BorderStyle;
+use crate::workspace::WorkspaceRef;
 use anyhow::anyhow;
 use anyhow::Context;
 use anyhow::Result;
@@ -28,8 +32,11 @@ use i3_ipc::reply::NodeType as I3NodeType;
 use serde::Deserialize;
 use serde::Serialize;
 use std::collections::HashMap;
+use std::fs::File;
+use std::io::BufReader;
 use std::io::Read;
 use std::io::Write;
+use std::path::Path;
 
 type NodeId = usize;
 type NodeIndex = usize;
@@ -43,53 +50,104 @@ type WorkspaceNum = i32;
 /// It supports two output format: binary and JSON.
 pub struct SaveLayout {
     command_executor: CommandExecutor,
+    strict: bool,
+    portable: bool,
 }
 
 impl SaveLayout {
     /// A new SaveLayout executor.
     pub fn new(command_executor: CommandExecutor) -> Self {
-        Self { command_executor }
+        Self {
+            command_executor,
+            strict: false,
+            portable: false,
+        }
+    }
+
+    /// Save a layout which can be restored on another machine (see `SavedLayout::portable`).
+    pub fn with_portable(mut self, portable: bool) -> Self {
+        self.portable = portable;
+        self
+    }
+
+    /// Fail on nodes which cannot be saved, instead of skipping them with a warning.
+    pub fn with_strict(mut self, strict: bool) -> Self {
+        self.strict = strict;
+        self
     }
 
     /// Write the workspace's layout on `output`.
     ///
-    /// Specify the workspace with `workspace_num`. If `None` the currently focused
-    /// workspace will be saved.
+    /// Specify the workspace with `workspace`.
     ///
     /// `json_output` for JSON format, otherwise binary format will be used.
-    pub fn execute<W>(
-        mut self,
-        workspace_num: Option<i32>,
-        output: W,
-        json_output: bool,
-    ) -> Result<()>
+    pub fn execute<W>(mut self, workspace: WorkspaceRef, output: W, json_output: bool) -> Result<()>
     where
         W: Write,
     {
+        self.capture(&workspace)?.to_writer(output, json_output)
+    }
+
+    /// The layout of the workspace `workspace`, in memory.
+    pub fn capture(&mut self, workspace: &WorkspaceRef) -> Result<SavedLayout> {
         let root_node = self.command_executor.query_root_node()?;
 
-        let workspace = match workspace_num {
-            Some(workspace_num) => find_workspace_by_num(&root_node, workspace_num)
-                .ok_or_else(|| anyhow!("Cannot find the workspace number '{}'", workspace_num))?,
-            None => query_workspace_focused(&root_node, &mut self.command_executor)?,
-        };
+        let workspace = workspace.resolve(&root_node, &mut self.command_executor)?;
+
+        let saved_layout = Self::save_subtree(workspace, self.strict)?;
 
-        Self::save_subtree(workspace)?.serialize(output, json_output)
+        if self.portable {
+            saved_layout.portable()
+        } else {
+            Ok(saved_layout)
+        }
     }
 
-    fn save_subtree(subtree: &I3Node) -> Result<SavedLayout> {
+    /// The layout of a subtree (e.g., a workspace).
+    ///
+    /// Unless `strict`, unexpected layouts are saved as `LayoutNode::Other` and nodes which
+    /// are neither workspaces nor containers (e.g., dockareas) are skipped.
+    /// Warnings go to stderr, as the layout may be written on stdout.
+    pub fn save_subtree(subtree: &I3Node, strict: bool) -> Result<SavedLayout> {
         let mut nodes = vec![];
         let mut dfs = vec![subtree];
 
         while let Some(current) = dfs.pop() {
+            let children = current
+                .nodes
+                .iter()
+                .filter(|child| {
+                    let savable = strict || KindNode::is_savable(child);
+
+                    if !savable {
+                        eprintln!(
+                            "[WARN]: Skipping node '{}' of type '{:?}'",
+                            child.id, child.node_type
+                        );
+                    }
+
+                    savable
+                })
+                .collect::<Vec<_>>();
+
             nodes.push(SavedNode {
                 id: current.id,
                 kind: KindNode::new(current)?,
-                layout: current.layout.try_into()?,
-                children: current.nodes.iter().map(|node| node.id).collect(),
+                layout: LayoutNode::new(current, strict)?,
+                children: children.iter().map(|node| node.id).collect(),
+                marks: node_marks(current),
+                name: (current.node_type == I3NodeType::Workspace)
+                    .then(|| current.name.clone())
+                    .flatten(),
+                focus: current
+                    .focus
+                    .iter()
+                    .filter(|id| children.iter().any(|child| child.id == **id))
+                    .copied()
+                    .collect(),
             });
 
-            dfs.extend(current.nodes.as_slice());
+            dfs.extend(children);
         }
 
         SavedLayout::new(SavedNodes(nodes))
@@ -99,6 +157,7 @@ impl SaveLayout {
 /// SavedLayout
 ///
 /// Representation of a layout of a workspace.
+#[derive(Clone)]
 pub struct SavedLayout {
     nodes: SavedNodes,
     map_id: HashMap<NodeId, NodeIndex>,
@@ -134,11 +193,26 @@ impl SavedLayout {
             ));
         }
 
+        // Windows and empty containers are leaves.
+        let leaf_with_children = nodes.0.iter().find(|node| {
+            matches!(
+                node.kind,
+                KindNode::NormalWindow(_) | KindNode::EmptyContainer
+            ) && !node.children.is_empty()
+        });
+
+        if let Some(node) = leaf_with_children {
+            return Err(anyhow!(
+                "The node '{}' is a leaf but has children",
+                node.id()
+            ));
+        }
+
         Ok(Self { nodes, map_id })
     }
 
     /// Serialize the layout into `output`.
-    pub fn serialize<W>(&self, output: W, json_output: bool) -> Result<()>
+    pub fn to_writer<W>(&self, output: W, json_output: bool) -> Result<()>
     where
         W: Write,
     {
@@ -161,23 +235,169 @@ impl SavedLayout {
     }
 
     /// Load a layout from `input`.
-    pub fn deserialize<R>(input: R, json_input: bool) -> Result<Self>
+    pub fn from_reader<R>(input: R, json_input: bool) -> Result<Self>
     where
         R: Read,
     {
         let nodes = if json_input {
             let mut deserializer = serde_json::de::Deserializer::from_reader(input);
 
-            SavedNodes::deserialize(&mut deserializer).context("Cannot JSON deserialize layout")?
+            SavedNodes::deserialize(&mut deserializer)
+                .context("Cannot JSON deserialize layout")
+                .context(Failure::LayoutUnreadable)?
         } else {
             let options = Self::bincode_options();
             let mut deserializer = bincode::de::Deserializer::with_reader(input, options);
 
             SavedNodes::deserialize(&mut deserializer)
-                .context("Cannot binary deserialize layout")?
+                .context("Cannot binary deserialize layout")
+                .context(Failure::LayoutUnreadable)?
         };
 
-        Self::new(nodes)
+        Self::new(nodes).context(Failure::LayoutUnreadable)
+    }
+
+    /// Load a layout from the file `path`.
+    ///
+    /// If the file cannot be read or deserialized, its backup (`<path>.bak`) is used instead.
+    pub fn load(path: &Path, json_input: bool) -> Result<Self> {
+        let load_file = |path: &Path| {
+            let file = File::open(path)
+                .with_context(|| format!("Cannot open the layout file '{}'", path.display()))
+                .context(Failure::LayoutUnreadable)?;
+
+            Self::from_reader(BufReader::new(file), json_input)
+        };
+
+        load_file(path).or_else(|error| {
+            let backup_path = backup_path(path);
+
+            if !backup_path.exists() {
+                return Err(error);
+            }
+
+            eprintln!(
+                "[WARN]: Cannot load the layout file '{}' ({:#}); using backup '{}'",
+                path.display(),
+                error,
+                backup_path.display()
+            );
+
+            load_file(&backup_path)
+        })
+    }
+
+    /// Collapse the containers with a single child (e.g., the wrappers created around
+    /// each window by older versions).
+    ///
+    /// A chain of such containers is replaced by its innermost node, which keeps its own
+    /// layout and gets the marks of the collapsed containers.
+    pub fn simplify(self) -> Result<Self> {
+        let collapse = |mut node_id: NodeId| {
+            let mut marks = vec![];
+
+            loop {
+                let node = self.lookup_by_id(node_id);
+
+                match (node.kind(), node.children()) {
+                    (KindNode::Splitter, &[child_id]) => {
+                        marks.extend_from_slice(node.marks());
+                        node_id = child_id;
+                    }
+                    _ => return (node, marks),
+                }
+            }
+        };
+
+        let mut nodes = vec![];
+        let mut dfs = vec![(self.root(), vec![])];
+
+        while let Some((current, marks)) = dfs.pop() {
+            let children = current
+                .children()
+                .iter()
+                .map(|&child_id| collapse(child_id))
+                .collect::<Vec<_>>();
+
+            let mut node = current.clone();
+            node.focus = node
+                .focus
+                .iter()
+                .filter_map(|focus_id| {
+                    let index = current.children().iter().position(|id| id == focus_id)?;
+                    Some(children[index].0.id())
+                })
+                .collect();
+            node.children = children.iter().map(|(child, _)| child.id()).collect();
+            node.marks.extend(marks);
+            nodes.push(node);
+
+            dfs.extend(children);
+        }
+
+        Self::new(SavedNodes(nodes))
+    }
+
+    /// Drop the ids which are meaningful only in the current i3 session.
+    ///
+    /// Nodes are renumbered by their position in the tree and windows forget their X11 id:
+    /// they are restored by class, instance and title only (see `SavedWindow::is_portable`).
+    pub fn portable(self) -> Result<Self> {
+        let mut positions = HashMap::new();
+        let mut dfs = vec![self.root()];
+
+        while let Some(current) = dfs.pop() {
+            positions.insert(current.id(), positions.len() + 1);
+
+            dfs.extend(
+                current
+                    .children()
+                    .iter()
+                    .rev()
+                    .map(|&child_id| self.lookup_by_id(child_id)),
+            );
+        }
+
+        let mut nodes = self
+            .nodes
+            .0
+            .iter()
+            .filter(|node| positions.contains_key(&node.id()))
+            .cloned()
+            .collect::<Vec<_>>();
+
+        nodes.sort_by_key(|node| positions[&node.id()]);
+
+        for node in &mut nodes {
+            node.id = positions[&node.id];
+            node.children = node.children.iter().map(|id| positions[id]).collect();
+            node.focus = node
+                .focus
+                .iter()
+                .filter_map(|id| positions.get(id).copied())
+                .collect();
+
+            if let KindNode::NormalWindow(saved_window) = &mut node.kind {
+                saved_window.window = None;
+                saved_window.portable = true;
+            }
+        }
+
+        Self::new(SavedNodes(nodes))
+    }
+
+    /// The number of nodes.
+    pub fn node_count(&self) -> usize {
+        self.nodes.0.len()
+    }
+
+    /// The number of windows.
+    pub fn window_count(&self) -> usize {
+        self.nodes
+            .0
+            .iter()
+            .filter(|node| matches!(node.kind, KindNode::NormalWindow(_)))
+            .count()
     }
 
     /// Get the first node (this should be the workspace).
@@ -203,18 +423,31 @@ impl SavedLayout {
     }
 }
 
-#[derive(Debug, serde::Deserialize, serde::Serialize)]
+#[derive(Clone, Debug, serde::Deserialize, serde::Serialize)]
 struct SavedNodes(Vec<SavedNode>);
 
 /// SavedNode
 ///
 /// Representation of a node in the saved layout.
-#[derive(Debug, serde::Deserialize, serde::Serialize)]
+#[derive(Clone, Debug, serde::Deserialize, serde::Serialize)]
 pub struct SavedNode {
     id: NodeId,
     kind: KindNode,
     layout: LayoutNode,
     children: Vec<NodeId>,
+
+    /// The marks (missing in JSON layouts saved by older versions).
+    #[serde(default)]
+    marks: Vec<String>,
+
+    /// The name of a workspace node, as it was when saved.
+    #[serde(default)]
+    name: Option<String>,
+
+    /// The children in focus order, most recently focused first (missing in JSON layouts
+    /// saved by older versions).
+    #[serde(default)]
+    focus: Vec<NodeId>,
 }
 
 impl SavedNode {
@@ -229,23 +462,63 @@ impl SavedNode {
     }
 
     /// The layout saved for this node.
-    pub fn layout(&self) -> LayoutNode {
-        self.layout
+    pub fn layout(&self) -> &LayoutNode {
+        &self.layout
     }
 
     /// Node children.
     pub fn children(&self) -> &[NodeId] {
         self.children.as_slice()
     }
+
+    /// The marks of the node.
+    pub fn marks(&self) -> &[String] {
+        self.marks.as_slice()
+    }
+
+    /// The children in focus order (most recently focused first).
+    pub fn focus(&self) -> &[NodeId] {
+        self.focus.as_slice()
+    }
+
+    /// The name of the workspace (if the node is a workspace).
+    pub fn name(&self) -> Option<&str> {
+        self.name.as_deref()
+    }
 }
 
 /// Saved layout applied for a saved node.
-#[derive(Clone, Copy, Debug, serde::Deserialize, serde::Serialize)]
+#[derive(Clone, Debug, PartialEq, Eq, serde::Deserialize, serde::Serialize)]
 pub enum LayoutNode {
     SplitH,
     SplitV,
     Stacked,
     Tabbed,
+
+    /// A layout unknown to the restore (restored as `SplitH`).
+    ///
+    /// It is the last variant so that layouts saved before its introduction can still be read.
+    Other(String),
+}
+
+impl LayoutNode {
+    /// The saved layout of a node.
+    ///
+    /// Unless `strict`, an unexpected layout is saved as `Other` with a warning.
+    fn new(node: &I3Node, strict: bool) -> Result<Self> {
+        match Self::try_from(node.layout) {
+            Err(_) if !strict => {
+                eprintln!(
+                    "[WARN]: Unexpected layout '{:?}' of node '{}'",
+                    node.layout, node.id
+                );
+
+                Ok(Self::Other(format!("{:?}", node.layout).to_lowercase()))
+            }
+
+            layout => layout,
+        }
+    }
 }
 
 impl TryFrom<I3NodeLayout> for LayoutNode {
@@ -263,7 +536,7 @@ impl TryFrom<I3NodeLayout> for LayoutNode {
 }
 
 /// Type of saved node.
-#[derive(Debug, serde::Deserialize, serde::Serialize)]
+#[derive(Clone, Debug, serde::Deserialize, serde::Serialize)]
 pub enum KindNode {
     /// The saved node is a workspace.
     Workspace(WorkspaceNum),
@@ -273,21 +546,47 @@ pub enum KindNode {
 
     /// The node is a container (children >= 1; intermediate node in tree).
     Splitter,
+
+    /// The saved node is a workspace without a number.
+    ///
+    /// It is the last variant so that layouts saved before its introduction can still be read.
+    NamedWorkspace(String),
+
+    /// The node is a container without children nor window (e.g., opened with `open`).
+    ///
+    /// Saved as windows by older versions; appended for the same reason as `NamedWorkspace`.
+    EmptyContainer,
 }
 
 impl KindNode {
+    /// Whether a node can be saved (i.e., it is a workspace or a container).
+    fn is_savable(node: &I3Node) -> bool {
+        matches!(node.node_type, I3NodeType::Workspace | I3NodeType::Con)
+    }
+
     fn new(node: &I3Node) -> Result<Self> {
         match node.node_type {
-            I3NodeType::Workspace => {
-                let workspace_num = node.num.expect("Expected workspace having 'num' field");
-                Ok(Self::Workspace(workspace_num))
-            }
+            I3NodeType::Workspace => match WorkspaceRef::of(node) {
+                WorkspaceRef::Num(workspace_num) => Ok(Self::Workspace(workspace_num)),
+                WorkspaceRef::Name(name) => Ok(Self::NamedWorkspace(name)),
+                WorkspaceRef::Focused => unreachable!("Expected a concrete workspace reference"),
+            },
 
             I3NodeType::Con => {
-                if node.nodes.is_empty() {
+                if node.nodes.is_empty() && node.window.is_none() {
+                    Ok(Self::EmptyContainer)
+                } else if node.nodes.is_empty() {
+                    let properties = node.window_properties.as_ref();
+
                     Ok(Self::NormalWindow(SavedWindow {
                         width: node.window_rect.width,
                         height: node.window_rect.height,
+                        border: Some(BorderStyle::of(node)),
+                        window: node.window,
+                        class: properties.and_then(|properties| properties.class.clone()),
+                        instance: properties.and_then(|properties| properties.instance.clone()),
+                        title: properties.and_then(|properties| properties.title.clone()),
+                        portable: false,
                     }))
                 } else {
                     Ok(Self::Splitter)
@@ -300,10 +599,34 @@ impl KindNode {
 }
 
 /// Information about the saved window.
-#[derive(Debug, serde::Deserialize, serde::Serialize)]
+#[derive(Clone, Debug, serde::Deserialize, serde::Serialize)]
 pub struct SavedWindow {
     width: isize,
     height: isize,
+
+    /// The border style (missing in JSON layouts saved by older versions).
+    #[serde(default)]
+    border: Option<BorderStyle>,
+
+    /// The X11 window id (missing in JSON layouts saved by older versions).
+    #[serde(default)]
+    window: Option<usize>,
+
+    /// The window class (missing in layouts saved by older versions).
+    #[serde(default)]
+    class: Option<String>,
+
+    /// The window instance (missing in layouts saved by older versions).
+    #[serde(default)]
+    instance: Option<String>,
+
+    /// The window title (missing in layouts saved by older versions).
+    #[serde(default)]
+    title: Option<String>,
+
+    /// Whether the ids of the layout are not i3 ones (see `SavedLayout::portable`).
+    #[serde(default)]
+    portable: bool,
 }
 
 impl SavedWindow {
@@ -314,4 +637,320 @@ impl SavedWindow {
     pub fn height(&self) -> isize {
         self.height
     }
+
+    pub fn border(&self) -> Option<BorderStyle> {
+        self.border
+    }
+
+    pub fn window(&self) -> Option<usize> {
+        self.window
+    }
+
+    pub fn class(&self) -> Option<&str> {
+        self.class.as_deref()
+    }
+
+    pub fn instance(&self) -> Option<&str> {
+        self.instance.as_deref()
+    }
+
+    pub fn title(&self) -> Option<&str> {
+        self.title.as_deref()
+    }
+
+    /// Whether the window has to be found by class, instance and title (its ids are not
+    /// meaningful in the current i3 session).
+    pub fn is_portable(&self) -> bool {
+        self.portable
+    }
+}
+
+/// Simplify the layout files of a directory in place (see `SavedLayout::simplify`).
+///
+/// The previous version of each rewritten file is kept as backup. Files which cannot be
+/// loaded are reported and left untouched.
+pub fn migrate_layouts(dir: &Path, json: bool) -> Result<()> {
+    let entries = std::fs::read_dir(dir)
+        .with_context(|| format!("Cannot read the directory '{}'", dir.display()))?;
+
+    let mut paths = entries
+        .flatten()
+        .map(|entry| entry.path())
+        .filter(|path| path.is_file())
+        .filter(|path| {
+            let extension = path.extension().and_then(|extension| extension.to_str());
+            !matches!(extension, Some("bak" | "tmp"))
+        })
+        .collect::<Vec<_>>();
+    paths.sort();
+
+    for path in paths {
+        let saved_layout = match File::open(&path)
+            .map_err(anyhow::Error::from)
+            .and_then(|file| SavedLayout::from_reader(BufReader::new(file), json))
+        {
+            Ok(saved_layout) => saved_layout,
+            Err(error) => {
+                eprintln!("[WARN]: Skipping '{}': {:#}", path.display(), error);
+                continue;
+            }
+        };
+
+        let node_count = saved_layout.node_count();
+        let saved_layout = saved_layout.simplify()?;
+        let removed = node_count - saved_layout.node_count();
+
+        if removed == 0 {
+            println!("{}: unchanged", path.display());
+            continue;
+        }
+
+        let mut file = AtomicFile::create(&path, true)
+            .with_context(|| format!("Cannot rewrite the layout file '{}'", path.display()))?;
+
+        saved_layout.to_writer(&mut file, json)?;
+        file.commit()?;
+
+        println!("{}: {} containers removed", path.display(), removed);
+    }
+
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::fixtures;
+
+    fn saved(fixture: &str, workspace_id: usize) -> SavedLayout {
+        let root_node = fixtures::load(fixture);
+        SaveLayout::save_subtree(fixtures::node(&root_node, workspace_id), false).unwrap()
+    }
+
+    #[test]
+    fn save_nested_workspace() {
+        let saved_layout = saved("workspaces.json", 200);
+
+        assert_eq!(saved_layout.node_count(), 7);
+        assert_eq!(saved_layout.window_count(), 4);
+        assert!(matches!(saved_layout.root().kind(), KindNode::Workspace(2)));
+        assert_eq!(saved_layout.root().children(), [201, 210]);
+        assert_eq!(saved_layout.root().focus(), [210, 201]);
+
+        let stacked = saved_layout.lookup_by_id(220);
+        assert!(matches!(stacked.kind(), KindNode::Splitter));
+        assert_eq!(stacked.layout(), &LayoutNode::Stacked);
+        assert_eq!(stacked.children(), [221, 222]);
+        assert_eq!(stacked.focus(), [222, 221]);
+
+        let KindNode::NormalWindow(window) = saved_layout.lookup_by_id(221).kind() else {
+            panic!("Expected a window");
+        };
+        assert_eq!(window.class(), Some("Zathura"));
+        assert_eq!(window.title(), Some("manual.pdf"));
+        assert_eq!(window.border(), Some(BorderStyle::Normal(Some(2))));
+    }
+
+    #[test]
+    fn save_named_and_placeholder_workspaces() {
+        let saved_layout = saved("workspaces.json", 400);
+        assert!(
+            matches!(saved_layout.root().kind(), KindNode::NamedWorkspace(name) if name == "web")
+        );
+        assert_eq!(saved_layout.root().name(), Some("web"));
+
+        let saved_layout = saved("workspaces.json", 500);
+        assert!(matches!(
+            saved_layout.lookup_by_id(501).kind(),
+            KindNode::EmptyContainer
+        ));
+        assert_eq!(saved_layout.lookup_by_id(501).marks(), ["placeholder"]);
+        assert_eq!(saved_layout.window_count(), 0);
+    }
+
+    #[test]
+    fn save_keeps_tabmode_mark() {
+        let saved_layout = saved("workspaces.json", 300);
+
+        let wrapper = saved_layout.lookup_by_id(310);
+        assert_eq!(wrapper.layout(), &LayoutNode::Tabbed);
+        assert_eq!(wrapper.marks(), ["__i3-autolayout__tabmode_300"]);
+    }
+
+    #[test]
+    fn save_skips_floating_windows() {
+        let saved_layout = saved("floating.json", 100);
+        assert_eq!(saved_layout.node_count(), 2);
+        assert_eq!(saved_layout.root().children(), [101]);
+
+        let saved_layout = saved("floating.json", 200);
+        assert_eq!(saved_layout.node_count(), 1);
+        assert_eq!(saved_layout.window_count(), 0);
+    }
+
+    fn to_json(saved_layout: &SavedLayout) -> String {
+        let mut bytes = vec![];
+        saved_layout.to_writer(&mut bytes, true).unwrap();
+        String::from_utf8(bytes).unwrap()
+    }
+
+    fn round_trip(saved_layout: &SavedLayout, json: bool) -> SavedLayout {
+        let mut bytes = vec![];
+        saved_layout.to_writer(&mut bytes, json).unwrap();
+        SavedLayout::from_reader(bytes.as_slice(), json).unwrap()
+    }
+
+    #[test]
+    fn round_trip_keeps_child_and_focus_order() {
+        let saved_layout = saved("workspaces.json", 200);
+
+        for json in [true, false] {
+            let loaded = round_trip(&saved_layout, json);
+
+            assert_eq!(to_json(&loaded), to_json(&saved_layout));
+            assert_eq!(loaded.root().children(), [201, 210]);
+            assert_eq!(loaded.root().focus(), [210, 201]);
+            assert_eq!(loaded.lookup_by_id(220).children(), [221, 222]);
+            assert_eq!(loaded.lookup_by_id(220).focus(), [222, 221]);
+        }
+    }
+
+    #[test]
+    fn round_trip_of_portable_layouts() {
+        let saved_layout = saved("workspaces.json", 200).portable().unwrap();
+
+        for json in [true, false] {
+            let loaded = round_trip(&saved_layout, json);
+            assert_eq!(to_json(&loaded), to_json(&saved_layout));
+
+            let KindNode::NormalWindow(window) = loaded.lookup_by_id(2).kind() else {
+                panic!("Expected a window");
+            };
+            assert!(window.is_portable());
+            assert_eq!(window.window(), None);
+        }
+    }
+
+    #[test]
+    fn load_file_falls_back_to_backup() {
+        let dir = fixtures::TempDir::new("save-layout");
+        let path = dir.path().join("layout.json");
+        let saved_layout = saved("workspaces.json", 100);
+
+        std::fs::write(&path, "{ truncated").unwrap();
+        assert_eq!(
+            Failure::of(&SavedLayout::load(&path, true).err().unwrap()),
+            Some(Failure::LayoutUnreadable)
+        );
+
+        std::fs::write(backup_path(&path), to_json(&saved_layout)).unwrap();
+        let loaded = SavedLayout::load(&path, true).unwrap();
+        assert_eq!(to_json(&loaded), to_json(&saved_layout));
+    }
+
+    #[test]
+    fn load_rejects_invalid_layouts() {
+        for invalid in [
+            "[]",
+            // A missing child.
+            r#"[{ "id": 1, "kind": { "Workspace": 1 }, "layout": "SplitH", "children": [2] }]"#,
+            // A leaf with children.
+            r#"[
+                { "id": 1, "kind": { "Workspace": 1 }, "layout": "SplitH", "children": [2] },
+                { "id": 2, "kind": "EmptyContainer", "layout": "SplitH", "children": [1] }
+            ]"#,
+        ] {
+            let error = SavedLayout::from_reader(invalid.as_bytes(), true)
+                .err()
+                .unwrap();
+            assert_eq!(Failure::of(&error), Some(Failure::LayoutUnreadable));
+            assert!(
+                !format!("{:#}", error).contains("deserialize"),
+                "{:#}",
+                error
+            );
+        }
+    }
+
+    /// A layout saved by older versions: each window wrapped in single-child containers.
+    const WRAPPED: &str = r#"[
+        { "id": 1, "kind": { "Workspace": 1 }, "layout": "SplitH", "children": [10, 20], "focus": [20, 10] },
+        { "id": 10, "kind": "Splitter", "layout": "SplitV", "children": [11], "marks": ["outer"] },
+        { "id": 11, "kind": "Splitter", "layout": "SplitH", "children": [12], "marks": ["inner"] },
+        { "id": 12, "kind": { "NormalWindow": { "width": 960, "height": 1080 } }, "layout": "SplitH", "children": [] },
+        { "id": 20, "kind": "Splitter", "layout": "SplitV", "children": [21, 30], "focus": [30, 21] },
+        { "id": 21, "kind": { "NormalWindow": { "width": 960, "height": 540 } }, "layout": "SplitH", "children": [] },
+        { "id": 30, "kind": "Splitter", "layout": "Tabbed", "children": [31], "focus": [31] },
+        { "id": 31, "kind": "Splitter", "layout": "Stacked", "children": [32, 33], "focus": [33, 32] },
+        { "id": 32, "kind": { "NormalWindow": { "width": 960, "height": 540 } }, "layout": "SplitH", "children": [] },
+        { "id": 33, "kind": { "NormalWindow": { "width": 960, "height": 540 } }, "layout": "SplitH", "children": [] }
+    ]"#;
+
+    #[test]
+    fn simplify_collapses_wrappers() {
+        let saved_layout = SavedLayout::from_reader(WRAPPED.as_bytes(), true).unwrap();
+        assert_eq!(saved_layout.node_count(), 10);
+
+        let simplified = saved_layout.simplify().unwrap();
+        assert_eq!(simplified.node_count(), 7);
+        assert_eq!(simplified.window_count(), 4);
+
+        // The chain 10 -> 11 -> 12 is replaced by the window, which gets the marks.
+        assert_eq!(simplified.root().children(), [12, 20]);
+        assert_eq!(simplified.root().focus(), [20, 12]);
+        assert_eq!(simplified.lookup_by_id(12).marks(), ["outer", "inner"]);
+
+        // The tabbed wrapper goes, the stacked container keeps its own layout.
+        let split = simplified.lookup_by_id(20);
+        assert_eq!(split.children(), [21, 31]);
+        assert_eq!(split.focus(), [31, 21]);
+
+        let stacked = simplified.lookup_by_id(31);
+        assert_eq!(stacked.layout(), &LayoutNode::Stacked);
+        assert_eq!(stacked.children(), [32, 33]);
+        assert_eq!(stacked.focus(), [33, 32]);
+        assert!(stacked.marks().is_empty());
+
+        // Simplifying again changes nothing.
+        let again = simplified.clone().simplify().unwrap();
+        assert_eq!(to_json(&again), to_json(&simplified));
+    }
+
+    #[test]
+    fn simplify_keeps_saved_workspaces() {
+        for workspace_id in [100, 200, 300, 400, 500] {
+            let saved_layout = saved("workspaces.json", workspace_id);
+            let simplified = saved_layout.clone().simplify().unwrap();
+
+            assert_eq!(simplified.window_count(), saved_layout.window_count());
+            assert_eq!(
+                simplified.node_count(),
+                saved_layout.node_count() - usize::from(workspace_id == 400)
+            );
+        }
+    }
+
+    #[test]
+    fn migrate_layouts_rewrites_files() {
+        let dir = fixtures::TempDir::new("migrate-layouts");
+        let wrapped = dir.path().join("wrapped");
+        let simple = dir.path().join("simple");
+        let broken = dir.path().join("broken");
+
+        std::fs::write(&wrapped, WRAPPED).unwrap();
+        std::fs::write(&simple, to_json(&saved("workspaces.json", 200))).unwrap();
+        std::fs::write(&broken, "{ truncated").unwrap();
+
+        migrate_layouts(dir.path(), true).unwrap();
+
+        let migrated = SavedLayout::load(&wrapped, true).unwrap();
+        assert_eq!(migrated.node_count(), 7);
+        assert_eq!(
+            std::fs::read_to_string(backup_path(&wrapped)).unwrap(),
+            WRAPPED
+        );
+
+        assert!(!backup_path(&simple).exists());
+        assert_eq!(std::fs::read_to_string(&broken).unwrap(), "{ truncated");
+    }
 }
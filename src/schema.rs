@@ -0,0 +1,364 @@
+/*
+    Copyright (C) 2022  Biagio Festa
+
+    This program is free software: you can redistribute it and/or modify
+    it under the terms of the GNU General Public License as published by
+    the Free Software Foundation, either version 3 of the License, or
+    (at your option) any later version.
+
+    This program is distributed in the hope that it will be useful,
+    but WITHOUT ANY WARRANTY; without even the implied warranty of
+    MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+    GNU General Public License for more details.
+
+    You should have received a copy of the GNU General Public License
+    along with this program.  If not, see <https://www.gnu.org/licenses/>.
+*/
+
+use serde_json::json;
+use serde_json::Value;
+
+/// The format of the schema of layout files.
+#[derive(Clone, Copy, clap::ValueEnum)]
+pub enum SchemaFormat {
+    /// A JSON Schema (draft 2020-12).
+    JsonSchema,
+}
+
+/// The JSON Schema of JSON layout files (see `SavedLayout::to_writer`).
+///
+/// It is maintained by hand: it must follow the serde representation of `SavedNodes`,
+/// `SavedNode`, `KindNode`, `SavedWindow`, `LayoutNode` and `BorderStyle`. Layout files
+/// carry no version: fields introduced later are optional, variants are only appended.
+pub fn layout_schema() -> Value {
+    json!({
+        "$schema": "https://json-schema.org/draft/2020-12/schema",
+        "title": "i3-autolayout layout",
+        "description": format!(
+            "A layout of a workspace, as saved by i3-autolayout {} with 'save-layout --json'. \
+             The nodes are listed parent first: the first one is the workspace.",
+            env!("CARGO_PKG_VERSION")
+        ),
+        "type": "array",
+        "minItems": 1,
+        "items": { "$ref": "#/$defs/node" },
+        "$defs": {
+            "node": {
+                "type": "object",
+                "required": ["id", "kind", "layout", "children"],
+                "additionalProperties": false,
+                "properties": {
+                    "id": {
+                        "description": "The con id (an index for portable layouts).",
+                        "$ref": "#/$defs/id"
+                    },
+                    "kind": { "$ref": "#/$defs/kind" },
+                    "layout": { "$ref": "#/$defs/layout" },
+                    "children": {
+                        "description": "The ids of the children, in order.",
+                        "type": "array",
+                        "items": { "$ref": "#/$defs/id" }
+                    },
+                    "marks": {
+                        "description": "The marks (optional).",
+                        "type": "array",
+                        "items": { "type": "string" }
+                    },
+                    "name": {
+                        "description": "The name of a workspace node (optional).",
+                        "type": ["string", "null"]
+                    },
+                    "focus": {
+                        "description": "The children in focus order, most recently focused \
+                                        first (optional).",
+                        "type": "array",
+                        "items": { "$ref": "#/$defs/id" }
+                    }
+                }
+            },
+            "id": { "type": "integer", "minimum": 0 },
+            "kind": {
+                "oneOf": [
+                    {
+                        "description": "A workspace with a number.",
+                        "type": "object",
+                        "required": ["Workspace"],
+                        "additionalProperties": false,
+                        "properties": { "Workspace": { "type": "integer" } }
+                    },
+                    {
+                        "description": "A window (leaf).",
+                        "type": "object",
+                        "required": ["NormalWindow"],
+                        "additionalProperties": false,
+                        "properties": { "NormalWindow": { "$ref": "#/$defs/window" } }
+                    },
+                    {
+                        "description": "A container with children.",
+                        "const": "Splitter"
+                    },
+                    {
+                        "description": "A workspace without a number.",
+                        "type": "object",
+                        "required": ["NamedWorkspace"],
+                        "additionalProperties": false,
+                        "properties": { "NamedWorkspace": { "type": "string" } }
+                    },
+                    {
+                        "description": "A container without children nor window (leaf).",
+                        "const": "EmptyContainer"
+                    }
+                ]
+            },
+            "window": {
+                "type": "object",
+                "required": ["width", "height"],
+                "additionalProperties": false,
+                "properties": {
+                    "width": { "type": "integer" },
+                    "height": { "type": "integer" },
+                    "border": {
+                        "description": "The border style (optional).",
+                        "oneOf": [{ "$ref": "#/$defs/border" }, { "type": "null" }]
+                    },
+                    "window": {
+                        "description": "The X11 window id (optional).",
+                        "type": ["integer", "null"],
+                        "minimum": 0
+                    },
+                    "class": {
+                        "description": "The window class, matched by portable layouts \
+                                        (optional).",
+                        "type": ["string", "null"]
+                    },
+                    "instance": {
+                        "description": "The window instance, matched by portable layouts \
+                                        (optional).",
+                        "type": ["string", "null"]
+                    },
+                    "title": {
+                        "description": "The window title, matched by portable layouts \
+                                        (optional).",
+                        "type": ["string", "null"]
+                    },
+                    "portable": {
+                        "description": "Whether ids are not i3 ones (optional).",
+                        "type": "boolean"
+                    }
+                }
+            },
+            "layout": {
+                "oneOf": [
+                    { "enum": ["SplitH", "SplitV", "Stacked", "Tabbed"] },
+                    {
+                        "description": "A layout unknown to the restore (restored as splith).",
+                        "type": "object",
+                        "required": ["Other"],
+                        "additionalProperties": false,
+                        "properties": { "Other": { "type": "string" } }
+                    }
+                ]
+            },
+            "border": {
+                "description": "A border style, with an optional width in pixels.",
+                "type": "string",
+                "pattern": "^(none|(normal|pixel)( [0-9]+)?)$"
+            }
+        }
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::fixtures;
+    use crate::save_layout::SaveLayout;
+    use crate::save_layout::SavedLayout;
+    use regex::Regex;
+
+    /// Validate a value against the subset of JSON Schema used by `layout_schema`.
+    fn validate(schema: &Value, value: &Value, root: &Value, path: &str) -> Result<(), String> {
+        if let Some(reference) = schema.get("$ref").and_then(Value::as_str) {
+            let name = reference.strip_prefix("#/$defs/").unwrap();
+            return validate(&root["$defs"][name], value, root, path);
+        }
+
+        if let Some(types) = schema.get("type") {
+            let types = match types {
+                Value::Array(types) => types.iter().filter_map(Value::as_str).collect(),
+                types => vec![types.as_str().unwrap()],
+            };
+
+            let matches = types.iter().any(|expected| match *expected {
+                "object" => value.is_object(),
+                "array" => value.is_array(),
+                "string" => value.is_string(),
+                "integer" => value.is_i64() || value.is_u64(),
+                "boolean" => value.is_boolean(),
+                "null" => value.is_null(),
+                other => panic!("Unsupported type '{}'", other),
+            });
+
+            if !matches {
+                return Err(format!("{}: expected {:?}, found {}", path, types, value));
+            }
+        }
+
+        if let Some(expected) = schema.get("const") {
+            if value != expected {
+                return Err(format!("{}: expected {}, found {}", path, expected, value));
+            }
+        }
+
+        if let Some(variants) = schema.get("enum").and_then(Value::as_array) {
+            if !variants.contains(value) {
+                return Err(format!("{}: {} is not one of {:?}", path, value, variants));
+            }
+        }
+
+        if let (Some(minimum), Some(number)) = (
+            schema.get("minimum").and_then(Value::as_i64),
+            value.as_i64(),
+        ) {
+            if number < minimum {
+                return Err(format!("{}: {} is below {}", path, number, minimum));
+            }
+        }
+
+        if let (Some(pattern), Some(text)) = (
+            schema.get("pattern").and_then(Value::as_str),
+            value.as_str(),
+        ) {
+            if !Regex::new(pattern).unwrap().is_match(text) {
+                return Err(format!("{}: '{}' does not match '{}'", path, text, pattern));
+            }
+        }
+
+        if let Some(alternatives) = schema.get("oneOf").and_then(Value::as_array) {
+            let matching = alternatives
+                .iter()
+                .filter(|alternative| validate(alternative, value, root, path).is_ok())
+                .count();
+
+            if matching != 1 {
+                return Err(format!(
+                    "{}: {} matches {} alternatives of oneOf",
+                    path, value, matching
+                ));
+            }
+        }
+
+        if let Some(object) = value.as_object() {
+            let properties = schema.get("properties").and_then(Value::as_object);
+
+            for required in schema
+                .get("required")
+                .and_then(Value::as_array)
+                .into_iter()
+                .flatten()
+            {
+                let required = required.as_str().unwrap();
+                if !object.contains_key(required) {
+                    return Err(format!("{}: missing '{}'", path, required));
+                }
+            }
+
+            for (key, field) in object {
+                match properties.and_then(|properties| properties.get(key)) {
+                    Some(property) => {
+                        validate(property, field, root, &format!("{}.{}", path, key))?
+                    }
+                    None if schema.get("additionalProperties") == Some(&Value::Bool(false)) => {
+                        return Err(format!("{}: unexpected key '{}'", path, key));
+                    }
+                    None => {}
+                }
+            }
+        }
+
+        if let Some(items) = value.as_array() {
+            if let Some(minimum) = schema.get("minItems").and_then(Value::as_u64) {
+                if (items.len() as u64) < minimum {
+                    return Err(format!("{}: less than {} items", path, minimum));
+                }
+            }
+
+            if let Some(item_schema) = schema.get("items") {
+                for (index, item) in items.iter().enumerate() {
+                    validate(item_schema, item, root, &format!("{}[{}]", path, index))?;
+                }
+            }
+        }
+
+        Ok(())
+    }
+
+    fn validate_layout(saved_layout: &SavedLayout) -> Result<(), String> {
+        let mut output = vec![];
+        saved_layout.to_writer(&mut output, true).unwrap();
+
+        let schema = layout_schema();
+        let value: Value = serde_json::from_slice(&output).unwrap();
+
+        validate(&schema, &value, &schema, "$")
+    }
+
+    /// Layouts of the fixtures covering every kind and layout of node.
+    fn saved_layouts() -> Vec<SavedLayout> {
+        let root_node = fixtures::load("workspaces.json");
+
+        [100, 200, 300, 400, 500]
+            .into_iter()
+            .map(|workspace_id| {
+                let workspace = fixtures::node(&root_node, workspace_id);
+                SaveLayout::save_subtree(workspace, false).unwrap()
+            })
+            .collect()
+    }
+
+    #[test]
+    fn saved_layouts_match_schema() {
+        for saved_layout in saved_layouts() {
+            validate_layout(&saved_layout).unwrap();
+        }
+    }
+
+    #[test]
+    fn portable_layouts_match_schema() {
+        for saved_layout in saved_layouts() {
+            validate_layout(&saved_layout.portable().unwrap()).unwrap();
+        }
+    }
+
+    #[test]
+    fn other_layout_matches_schema() {
+        let input = r#"[
+            { "id": 1, "kind": { "Workspace": 1 }, "layout": { "Other": "dockarea" }, "children": [] }
+        ]"#;
+        let saved_layout = SavedLayout::from_reader(input.as_bytes(), true).unwrap();
+
+        validate_layout(&saved_layout).unwrap();
+    }
+
+    #[test]
+    fn schema_rejects_unknown_keys_and_variants() {
+        let schema = layout_schema();
+        let node =
+            json!({ "id": 1, "kind": { "Workspace": 1 }, "layout": "SplitH", "children": [] });
+
+        assert!(validate(&schema, &json!([node]), &schema, "$").is_ok());
+        assert!(validate(&schema, &json!([]), &schema, "$").is_err());
+
+        let mut unknown_key = node.clone();
+        unknown_key["size"] = json!(10);
+        assert!(validate(&schema, &json!([unknown_key]), &schema, "$").is_err());
+
+        let mut unknown_layout = node.clone();
+        unknown_layout["layout"] = json!("Grid");
+        assert!(validate(&schema, &json!([unknown_layout]), &schema, "$").is_err());
+
+        let mut unknown_kind = node;
+        unknown_kind["kind"] = json!("Floating");
+        assert!(validate(&schema, &json!([unknown_kind]), &schema, "$").is_err());
+    }
+}
@@ -0,0 +1,370 @@
+/*
+    Copyright (C) 2022  Biagio Festa
+
+    This program is free software: you can redistribute it and/or modify
+    it under the terms of the GNU General Public License as published by
+    the Free Software Foundation, either version 3 of the License, or
+    (at your option) any later version.
+
+    This program is distributed in the hope that it will be useful,
+    but WITHOUT ANY WARRANTY; without even the implied warranty of
+    MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+    GNU General Public License for more details.
+
+    You should have received a copy of the GNU General Public License
+    along with this program.  If not, see <https://www.gnu.org/licenses/>.
+*/
+
+use crate::command_executor::CommandExecutor;
+use crate::command_executor::I3Node;
+use crate::command_executor::RootNode;
+use crate::marks::SCRATCHPAD_CURRENT_MARK;
+use crate::selector::Criteria;
+use crate::utilities::find_node_by_id_including_floating;
+use crate::utilities::find_workspace_by_name;
+use crate::utilities::find_workspaces;
+use crate::utilities::is_scratchpad_workspace;
+use crate::utilities::is_window;
+use crate::utilities::node_marks;
+use crate::utilities::query_workspace_focused;
+use crate::utilities::quote;
+use crate::utilities::quote_ws_name;
+use crate::utilities::runtime_dir;
+use anyhow::Context;
+use anyhow::Result;
+use i3_ipc::reply::ScratchpadState;
+use std::collections::BTreeMap;
+use std::path::PathBuf;
+
+/// A scratchpad window.
+struct ScratchpadWindow<'a> {
+    /// The window.
+    node: &'a I3Node,
+
+    /// The workspace where the window is shown (`None` if hidden).
+    shown_on: Option<&'a I3Node>,
+}
+
+/// What a cycle does (see `ScratchpadCycle::execute`), by index of the windows.
+#[derive(Debug, PartialEq)]
+struct CyclePlan {
+    /// The windows shown, hidden first.
+    hide: Vec<usize>,
+
+    /// The window shown next (none when a single window is toggled off).
+    show: Option<usize>,
+}
+
+impl CyclePlan {
+    /// The plan cycling `windows` (sorted by id).
+    ///
+    /// The shown windows are hidden and the one after the first of them is shown. If none
+    /// is shown, the one after the last shown (marked as current) is.
+    fn of(windows: &[ScratchpadWindow]) -> Self {
+        let hide = windows
+            .iter()
+            .enumerate()
+            .filter(|(_, window)| window.shown_on.is_some())
+            .map(|(index, _)| index)
+            .collect::<Vec<_>>();
+
+        let show = match hide.first() {
+            Some(_) if windows.len() == 1 => None,
+            Some(shown) => Some((shown + 1) % windows.len()),
+            None => {
+                let current = windows.iter().position(|window| {
+                    node_marks(window.node)
+                        .iter()
+                        .any(|mark| mark == SCRATCHPAD_CURRENT_MARK)
+                });
+
+                Some(current.map_or(0, |current| (current + 1) % windows.len()))
+            }
+        };
+
+        Self { hide, show }
+    }
+}
+
+/// The workspace a window is shown on again: the one it has been hidden from, if it still
+/// exists and is not the focused one (`None` shows it on the focused workspace).
+fn return_workspace<'a>(
+    window: &ScratchpadWindow,
+    root_node: &RootNode,
+    focused: Option<&str>,
+    remembered: &'a BTreeMap<usize, String>,
+) -> Option<&'a String> {
+    remembered
+        .get(&window.node.id)
+        .filter(|name| find_workspace_by_name(root_node, name).is_some())
+        .filter(|name| focused != Some(name.as_str()))
+}
+
+/// ScratchpadCycle executor.
+///
+/// It represents a one-shot executor which shows the next scratchpad window (among the
+/// ones matching some criteria), hiding the one currently shown.
+///
+/// The last window shown is tracked with a mark. The workspace where each window has been
+/// hidden from is remembered, so that it is shown there again.
+pub struct ScratchpadCycle {
+    /// Command executor.
+    command_executor: CommandExecutor,
+}
+
+impl ScratchpadCycle {
+    const STATE_FILE_NAME: &'static str = "scratchpad-workspaces";
+
+    /// A new scratchpad-cycle executor.
+    pub fn new(command_executor: CommandExecutor) -> Self {
+        Self { command_executor }
+    }
+
+    /// Execute the action.
+    ///
+    /// With `here` the window is shown on the focused workspace instead of the one
+    /// it has been hidden from.
+    pub fn execute(mut self, criteria: &Criteria, here: bool) -> Result<()> {
+        let root_node = self.command_executor.query_root_node()?;
+        let windows = Self::find_scratchpad_windows(&root_node, criteria);
+
+        if windows.is_empty() {
+            eprintln!("No scratchpad windows match the criteria");
+            return Ok(());
+        }
+
+        let mut remembered = Self::load_state()?;
+
+        let plan = CyclePlan::of(&windows);
+
+        for window in plan.hide.iter().map(|&index| &windows[index]) {
+            if let Some(name) = window.shown_on.and_then(|workspace| workspace.name.clone()) {
+                remembered.insert(window.node.id, name);
+            }
+
+            self.command_executor
+                .run_on_node_id(window.node.id, "move scratchpad")
+                .context("Cannot hide the scratchpad window")?;
+        }
+
+        // A single window is just toggled.
+        let Some(next) = plan.show else {
+            return Self::store_state(&remembered);
+        };
+
+        Self::show(
+            &mut self.command_executor,
+            &windows[next],
+            &root_node,
+            here,
+            &remembered,
+        )?;
+
+        remembered.retain(|node_id, _| windows.iter().any(|window| window.node.id == *node_id));
+        Self::store_state(&remembered)
+    }
+
+    /// Show a hidden scratchpad window and mark it as current.
+    fn show(
+        command_executor: &mut CommandExecutor,
+        window: &ScratchpadWindow,
+        root_node: &RootNode,
+        here: bool,
+        remembered: &BTreeMap<usize, String>,
+    ) -> Result<()> {
+        if !here {
+            let focused = query_workspace_focused(root_node, command_executor)?;
+            let workspace =
+                return_workspace(window, root_node, focused.name.as_deref(), remembered);
+
+            if let Some(workspace) = workspace {
+                command_executor
+                    .run(format!(
+                        "workspace --no-auto-back-and-forth {}",
+                        quote_ws_name(workspace)?
+                    ))
+                    .with_context(|| format!("Cannot focus workspace '{}'", workspace))?;
+            }
+        }
+
+        command_executor
+            .run_on_node_id(window.node.id, "scratchpad show")
+            .context("Cannot show the scratchpad window")?;
+
+        command_executor
+            .run_on_node_id(
+                window.node.id,
+                format!("mark --add {}", quote(SCRATCHPAD_CURRENT_MARK)),
+            )
+            .context("Cannot mark the scratchpad window")
+    }
+
+    /// Find the scratchpad windows matching the criteria, hidden or shown, sorted by id.
+    ///
+    /// The scratchpad state belongs to the floating container around the window.
+    fn find_scratchpad_windows<'a>(
+        root_node: &'a RootNode,
+        criteria: &Criteria,
+    ) -> Vec<ScratchpadWindow<'a>> {
+        let mut windows = vec![];
+
+        for workspace in find_workspaces(root_node) {
+            let shown_on = (!is_scratchpad_workspace(workspace)).then_some(workspace);
+
+            for floating_con in &workspace.floating_nodes {
+                if floating_con.scratchpad_state == ScratchpadState::None {
+                    continue;
+                }
+
+                let mut dfs = vec![floating_con];
+
+                while let Some(current) = dfs.pop() {
+                    if is_window(current) && criteria.matches(current, workspace) {
+                        windows.push(ScratchpadWindow {
+                            node: current,
+                            shown_on,
+                        });
+                    }
+
+                    dfs.extend(current.nodes.as_slice());
+                }
+            }
+        }
+
+        windows.sort_by_key(|window| window.node.id);
+        windows
+    }
+
+    /// The state file, if all the windows it remembers have been closed.
+    pub fn leftovers(root_node: &RootNode) -> Result<Vec<PathBuf>> {
+        let state_path = Self::state_path()?;
+
+        if !state_path.exists() {
+            return Ok(vec![]);
+        }
+
+        let alive = Self::load_state()?
+            .keys()
+            .any(|&node_id| find_node_by_id_including_floating(node_id, root_node).is_some());
+
+        Ok(if alive { vec![] } else { vec![state_path] })
+    }
+
+    /// The file where the workspaces of the windows are remembered.
+    fn state_path() -> Result<PathBuf> {
+        Ok(runtime_dir()?.join(Self::STATE_FILE_NAME))
+    }
+
+    /// Load the workspaces remembered for each window.
+    fn load_state() -> Result<BTreeMap<usize, String>> {
+        let path = Self::state_path()?;
+
+        Ok(std::fs::read_to_string(path)
+            .ok()
+            .and_then(|content| serde_json::from_str(&content).ok())
+            .unwrap_or_default())
+    }
+
+    /// Store the workspaces remembered for each window.
+    fn store_state(remembered: &BTreeMap<usize, String>) -> Result<()> {
+        let path = Self::state_path()?;
+        let content =
+            serde_json::to_string(remembered).context("Cannot serialize the scratchpad state")?;
+
+        std::fs::write(&path, content)
+            .with_context(|| format!("Cannot write the state file '{}'", path.display()))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::fixtures;
+
+    fn window_ids(windows: &[ScratchpadWindow]) -> Vec<usize> {
+        windows.iter().map(|window| window.node.id).collect()
+    }
+
+    #[test]
+    fn find_hidden_and_shown_windows() {
+        let root_node = fixtures::load("scratchpad.json");
+
+        let windows = ScratchpadCycle::find_scratchpad_windows(&root_node, &Criteria::default());
+        assert_eq!(window_ids(&windows), [31, 41, 111]);
+        assert!(windows[0].shown_on.is_none());
+        assert_eq!(windows[2].shown_on.map(|workspace| workspace.id), Some(100));
+
+        let criteria = "[title=Passwords]".parse::<Criteria>().unwrap();
+        let windows = ScratchpadCycle::find_scratchpad_windows(&root_node, &criteria);
+        assert_eq!(window_ids(&windows), [31]);
+
+        // Floating windows never sent to the scratchpad are not.
+        let root_node = fixtures::load("floating.json");
+        let windows = ScratchpadCycle::find_scratchpad_windows(&root_node, &Criteria::default());
+        assert!(windows.is_empty());
+    }
+
+    #[test]
+    fn cycle_hides_the_shown_window_and_shows_the_next() {
+        let root_node = fixtures::load("scratchpad.json");
+        let windows = ScratchpadCycle::find_scratchpad_windows(&root_node, &Criteria::default());
+
+        assert_eq!(
+            CyclePlan::of(&windows),
+            CyclePlan {
+                hide: vec![2],
+                show: Some(0),
+            }
+        );
+
+        // A single shown window is toggled off.
+        assert_eq!(
+            CyclePlan::of(&windows[2..]),
+            CyclePlan {
+                hide: vec![0],
+                show: None,
+            }
+        );
+    }
+
+    #[test]
+    fn cycle_resumes_after_the_current_window() {
+        let root_node = fixtures::load("scratchpad.json");
+        let root_node = fixtures::edit(&root_node, 110, |floating_con| {
+            floating_con.scratchpad_state = ScratchpadState::None;
+        });
+
+        let windows = ScratchpadCycle::find_scratchpad_windows(&root_node, &Criteria::default());
+        assert_eq!(window_ids(&windows), [31, 41]);
+        assert_eq!(CyclePlan::of(&windows).show, Some(0));
+
+        let root_node = fixtures::edit(&root_node, 31, |node| {
+            node.marks =
+                serde_json::from_value(serde_json::json!([SCRATCHPAD_CURRENT_MARK])).unwrap();
+        });
+        let windows = ScratchpadCycle::find_scratchpad_windows(&root_node, &Criteria::default());
+        assert_eq!(
+            CyclePlan::of(&windows),
+            CyclePlan {
+                hide: vec![],
+                show: Some(1),
+            }
+        );
+    }
+
+    #[test]
+    fn windows_return_to_their_workspace() {
+        let root_node = fixtures::load("scratchpad.json");
+        let windows = ScratchpadCycle::find_scratchpad_windows(&root_node, &Criteria::default());
+        let remembered = BTreeMap::from([(31, "1".to_string()), (41, "gone".to_string())]);
+
+        let workspace = |index: usize, focused| {
+            return_workspace(&windows[index], &root_node, focused, &remembered).cloned()
+        };
+
+        assert_eq!(workspace(0, Some("2")), Some("1".to_string()));
+        assert_eq!(workspace(0, Some("1")), None);
+        assert_eq!(workspace(1, Some("2")), None);
+        assert_eq!(workspace(2, Some("2")), None);
+    }
+}
@@ -0,0 +1,524 @@
+/*
+    Copyright (C) 2022  Biagio Festa
+
+    This program is free software: you can redistribute it and/or modify
+    it under the terms of the GNU General Public License as published by
+    the Free Software Foundation, either version 3 of the License, or
+    (at your option) any later version.
+
+    This program is distributed in the hope that it will be useful,
+    but WITHOUT ANY WARRANTY; without even the implied warranty of
+    MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+    GNU General Public License for more details.
+
+    You should have received a copy of the GNU General Public License
+    along with this program.  If not, see <https://www.gnu.org/licenses/>.
+*/
+
+use crate::command_executor::I3Node;
+use crate::command_executor::RootNode;
+use crate::utilities::find_workspaces;
+use crate::utilities::is_window;
+use crate::utilities::node_marks;
+use crate::utilities::quote;
+use crate::workspace::edit_distance;
+use anyhow::anyhow;
+use anyhow::Context;
+use anyhow::Result;
+use regex::Regex;
+use std::str::FromStr;
+
+/// The keys of the criteria (see `Criteria::from_str`).
+const CRITERIA_KEYS: [&str; 7] = [
+    "class",
+    "instance",
+    "title",
+    "title~",
+    "mark",
+    "workspace",
+    "con_id",
+];
+
+/// The criteria a window has to satisfy for being selected.
+///
+/// It is parsed from an i3-like syntax, e.g., `[class="Firefox" title~="^Inbox"]`
+/// (brackets are optional). All specified fields must match.
+#[derive(Clone, Default)]
+pub struct Criteria {
+    /// The window class.
+    pub class: Option<String>,
+
+    /// The window instance.
+    pub instance: Option<String>,
+
+    /// A regular expression matching the window title.
+    pub title: Option<Regex>,
+
+    /// A mark of the window.
+    pub mark: Option<String>,
+
+    /// The name of the workspace containing the window.
+    pub workspace: Option<String>,
+
+    /// The container id.
+    pub con_id: Option<usize>,
+}
+
+impl Criteria {
+    /// Whether no criterion is specified (i.e., every window matches).
+    pub fn is_empty(&self) -> bool {
+        self.class.is_none()
+            && self.instance.is_none()
+            && self.title.is_none()
+            && self.mark.is_none()
+            && self.workspace.is_none()
+            && self.con_id.is_none()
+    }
+
+    /// Whether the `window` (contained by `workspace`) matches the criteria.
+    pub fn matches(&self, window: &I3Node, workspace: &I3Node) -> bool {
+        let properties = window.window_properties.as_ref();
+
+        let class_matches = match &self.class {
+            Some(expected) => {
+                properties.and_then(|properties| properties.class.as_ref()) == Some(expected)
+            }
+            None => true,
+        };
+
+        let instance_matches = match &self.instance {
+            Some(expected) => {
+                properties.and_then(|properties| properties.instance.as_ref()) == Some(expected)
+            }
+            None => true,
+        };
+
+        let title_matches = match &self.title {
+            Some(regex) => window
+                .name
+                .as_deref()
+                .is_some_and(|title| regex.is_match(title)),
+            None => true,
+        };
+
+        let mark_matches = match &self.mark {
+            Some(expected) => node_marks(window).contains(expected),
+            None => true,
+        };
+
+        let workspace_matches = match &self.workspace {
+            Some(expected) => workspace.name.as_ref() == Some(expected),
+            None => true,
+        };
+
+        let con_id_matches = match self.con_id {
+            Some(expected) => window.id == expected,
+            None => true,
+        };
+
+        class_matches
+            && instance_matches
+            && title_matches
+            && mark_matches
+            && workspace_matches
+            && con_id_matches
+    }
+}
+
+impl FromStr for Criteria {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> Result<Self> {
+        let s = s.trim();
+        let s = match s.strip_prefix('[') {
+            Some(inner) => inner
+                .strip_suffix(']')
+                .ok_or_else(|| anyhow!("Invalid criteria '{}' (missing ']')", s))?,
+            None => s,
+        };
+
+        let mut criteria = Criteria::default();
+
+        for (key, value) in tokenize(s)? {
+            match key.as_str() {
+                "class" => criteria.class = Some(value),
+                "instance" => criteria.instance = Some(value),
+                "title" => {
+                    criteria.title = Some(
+                        Regex::new(&format!("^{}$", regex::escape(&value)))
+                            .expect("Expected escaped title to be a valid regex"),
+                    )
+                }
+                "title~" => {
+                    criteria.title = Some(
+                        Regex::new(&value)
+                            .with_context(|| format!("Invalid title regex '{}'", value))?,
+                    )
+                }
+                "mark" => criteria.mark = Some(value),
+                "workspace" => criteria.workspace = Some(value),
+                "con_id" => {
+                    criteria.con_id = Some(
+                        value
+                            .parse()
+                            .with_context(|| format!("Invalid con_id '{}'", value))?,
+                    )
+                }
+                _ => return Err(unknown_criterion(&key)),
+            }
+        }
+
+        Ok(criteria)
+    }
+}
+
+/// The error of an unknown criterion, suggesting the closest key (if close enough).
+fn unknown_criterion(key: &str) -> anyhow::Error {
+    let suggestion = CRITERIA_KEYS
+        .iter()
+        .map(|candidate| (edit_distance(key, candidate), candidate))
+        .filter(|(distance, _)| *distance <= 2)
+        .min_by_key(|(distance, _)| *distance);
+
+    match suggestion {
+        Some((_, candidate)) => {
+            anyhow!("Unknown criterion '{}'; did you mean '{}'?", key, candidate)
+        }
+        None => anyhow!(
+            "Unknown criterion '{}' (expected one of: {})",
+            key,
+            CRITERIA_KEYS.join(", ")
+        ),
+    }
+}
+
+/// Split criteria into `key=value` pairs.
+///
+/// Values can be double-quoted (with `\"` and `\\` escapes) to contain spaces.
+fn tokenize(s: &str) -> Result<Vec<(String, String)>> {
+    let mut tokens = vec![];
+    let mut chars = s.chars().peekable();
+
+    loop {
+        while chars.next_if(|c| c.is_whitespace()).is_some() {}
+
+        if chars.peek().is_none() {
+            return Ok(tokens);
+        }
+
+        let mut key = String::new();
+        while let Some(c) = chars.next_if(|&c| c != '=' && !c.is_whitespace()) {
+            key.push(c);
+        }
+
+        if chars.next() != Some('=') {
+            return Err(anyhow!("Invalid criterion '{}' (expected KEY=VALUE)", key));
+        }
+
+        let mut value = String::new();
+
+        if chars.next_if_eq(&'"').is_some() {
+            loop {
+                match chars.next() {
+                    Some('"') => break,
+                    Some('\\') => match chars.next() {
+                        Some(c @ ('"' | '\\')) => value.push(c),
+                        Some(c) => {
+                            value.push('\\');
+                            value.push(c);
+                        }
+                        None => return Err(anyhow!("Unterminated value for '{}'", key)),
+                    },
+                    Some(c) => value.push(c),
+                    None => return Err(anyhow!("Unterminated value for '{}'", key)),
+                }
+            }
+
+            if chars.peek().is_some_and(|c| !c.is_whitespace()) {
+                return Err(anyhow!("Expected a space after the value of '{}'", key));
+            }
+        } else {
+            while let Some(c) = chars.next_if(|c| !c.is_whitespace()) {
+                value.push(c);
+            }
+        }
+
+        tokens.push((key, value));
+    }
+}
+
+/// An i3 criteria block selecting the container with a mark (e.g., `[con_mark="^m$"]`).
+pub fn con_mark_criteria(mark: &str) -> String {
+    format!("[con_mark={}]", literal_value(mark))
+}
+
+/// A criteria value (as a quoted i3 argument) matching exactly `value`.
+///
+/// i3 matches criteria values as regular expressions, and reads them up to the closing
+/// quote. The value is regex-escaped, anchored and quoted (see `quote`): any string (e.g.,
+/// a window title like `"] kill, [class=".`) neither ends the criteria block nor matches
+/// other values. Control characters, which would end the command, are written as regex
+/// escapes.
+///
+/// Strings from windows (titles, classes, marks) must never be formatted into criteria
+/// otherwise.
+pub fn literal_value(value: &str) -> String {
+    let mut regex = String::from("^");
+
+    for c in value.chars() {
+        if c.is_control() {
+            regex.push_str(&format!("\\x{{{:x}}}", c as u32));
+        } else {
+            regex.push_str(&regex::escape(c.encode_utf8(&mut [0; 4])));
+        }
+    }
+
+    regex.push('$');
+
+    quote(&regex)
+}
+
+/// Select the windows matching the criteria.
+///
+/// Tiled, floating and scratchpad windows are considered.
+pub fn select<'a>(criteria: &Criteria, root_node: &'a RootNode) -> Vec<&'a I3Node> {
+    let mut selected = vec![];
+
+    for workspace in find_workspaces(root_node) {
+        let mut dfs = vec![workspace];
+
+        while let Some(current) = dfs.pop() {
+            if is_window(current) && criteria.matches(current, workspace) {
+                selected.push(current);
+            }
+
+            dfs.extend(current.floating_nodes.iter().rev());
+            dfs.extend(current.nodes.iter().rev());
+        }
+    }
+
+    selected
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::fixtures;
+
+    fn parse(s: &str) -> Criteria {
+        s.parse()
+            .unwrap_or_else(|error| panic!("Cannot parse '{}': {:#}", s, error))
+    }
+
+    fn parse_error(s: &str) -> String {
+        match s.parse::<Criteria>() {
+            Ok(_) => panic!("'{}' parsed", s),
+            Err(error) => format!("{:#}", error),
+        }
+    }
+
+    fn selected_ids(s: &str, fixture: &str) -> Vec<usize> {
+        let root_node = fixtures::load(fixture);
+
+        select(&parse(s), &root_node)
+            .into_iter()
+            .map(|window| window.id)
+            .collect()
+    }
+
+    #[test]
+    fn parse_valid_criteria() {
+        let criteria = parse(r#"[class="Firefox" instance=firefox title~="^Inbox" mark=m]"#);
+        assert_eq!(criteria.class.as_deref(), Some("Firefox"));
+        assert_eq!(criteria.instance.as_deref(), Some("firefox"));
+        assert_eq!(criteria.title.unwrap().as_str(), "^Inbox");
+        assert_eq!(criteria.mark.as_deref(), Some("m"));
+
+        let criteria = parse(r#"  workspace="3: mail"   con_id=42  "#);
+        assert_eq!(criteria.workspace.as_deref(), Some("3: mail"));
+        assert_eq!(criteria.con_id, Some(42));
+
+        assert!(parse("").is_empty());
+        assert!(parse("[]").is_empty());
+        assert!(!parse("class=a").is_empty());
+    }
+
+    #[test]
+    fn parse_quoted_values() {
+        assert_eq!(
+            parse(r#"class="a \"b\" c""#).class.as_deref(),
+            Some(r#"a "b" c"#)
+        );
+        assert_eq!(parse(r#"class="a\\b""#).class.as_deref(), Some(r"a\b"));
+        assert_eq!(parse(r#"class="a\nb""#).class.as_deref(), Some(r"a\nb"));
+        assert_eq!(parse(r#"class="""#).class.as_deref(), Some(""));
+        assert_eq!(parse(r#"class="a]b""#).class.as_deref(), Some("a]b"));
+    }
+
+    #[test]
+    fn parse_exact_title_is_escaped() {
+        let criteria = parse(r#"title="a.b (1)""#);
+        let title = criteria.title.unwrap();
+
+        assert!(title.is_match("a.b (1)"));
+        assert!(!title.is_match("axb (1)"));
+        assert!(!title.is_match("a.b (1) - more"));
+    }
+
+    #[test]
+    fn parse_last_value_wins() {
+        assert_eq!(parse("class=a class=b").class.as_deref(), Some("b"));
+    }
+
+    #[test]
+    fn parse_invalid_criteria() {
+        for s in [
+            "[class=a",
+            "class",
+            "class =a",
+            r#"class="a"b"#,
+            r#"class="a"#,
+            r#"class="a\"#,
+            "title~=(",
+            "con_id=abc",
+            "con_id=-1",
+        ] {
+            parse_error(s);
+        }
+    }
+
+    #[test]
+    fn parse_unknown_criterion_did_you_mean() {
+        assert_eq!(
+            parse_error("clas=a"),
+            "Unknown criterion 'clas'; did you mean 'class'?"
+        );
+        assert_eq!(
+            parse_error("instanc=a"),
+            "Unknown criterion 'instanc'; did you mean 'instance'?"
+        );
+        assert_eq!(
+            parse_error("conid=1"),
+            "Unknown criterion 'conid'; did you mean 'con_id'?"
+        );
+        assert_eq!(
+            parse_error("window_role=x"),
+            "Unknown criterion 'window_role' (expected one of: class, instance, title, \
+             title~, mark, workspace, con_id)"
+        );
+    }
+
+    #[test]
+    fn parse_round_trips_quoted_values() {
+        for value in ["a", "a b", r#"a"b"#, r"a\b", r"a\", "] kill, [", "日本語"] {
+            let s = format!(
+                "class=\"{}\"",
+                value.replace('\\', "\\\\").replace('"', "\\\"")
+            );
+            assert_eq!(parse(&s).class.as_deref(), Some(value), "{}", s);
+        }
+    }
+
+    #[test]
+    fn select_on_fixtures() {
+        assert_eq!(selected_ids("class=Zathura", "workspaces.json"), [221, 222]);
+        assert_eq!(
+            selected_ids("class=Alacritty workspace=2", "workspaces.json"),
+            [211]
+        );
+        assert_eq!(selected_ids("title~=pdf$", "workspaces.json"), [221, 222]);
+        assert_eq!(selected_ids("con_id=312", "workspaces.json"), [312]);
+        assert!(selected_ids("class=Nope", "workspaces.json").is_empty());
+
+        // Docks are not in workspaces.
+        assert!(selected_ids("class=i3bar", "workspaces.json").is_empty());
+    }
+
+    #[test]
+    fn select_floating_and_scratchpad() {
+        assert_eq!(selected_ids("class=Alacritty", "floating.json"), [101, 131]);
+        assert_eq!(selected_ids("workspace=2", "floating.json"), [211, 221]);
+
+        assert_eq!(selected_ids("mark=dropdown", "scratchpad.json"), [111]);
+        assert_eq!(
+            selected_ids("workspace=__i3_scratch", "scratchpad.json"),
+            [31, 41]
+        );
+    }
+
+    /// The regex i3 compiles from a criteria value, checking nothing follows the quotes.
+    fn criteria_regex(value: &str) -> Regex {
+        let quoted = literal_value(value);
+        let (regex, rest) = fixtures::unquote(&quoted);
+        assert_eq!(rest, "", "{:?} escaped the quotes", value);
+
+        Regex::new(&regex).unwrap_or_else(|error| panic!("Invalid regex '{}': {}", regex, error))
+    }
+
+    #[test]
+    fn literal_value_exact_text() {
+        assert_eq!(literal_value("m"), r#""^m$""#);
+        assert_eq!(literal_value("a.b*"), r#""^a\\.b\\*$""#);
+        assert_eq!(literal_value("(x)|[y]"), r#""^\\(x\\)\\|\\[y\\]$""#);
+        assert_eq!(literal_value("^$"), r#""^\\^\\$$""#);
+        assert_eq!(literal_value(""), r#""^$""#);
+    }
+
+    #[test]
+    fn literal_value_encodes_control_characters() {
+        assert_eq!(literal_value("a\nb"), r#""^a\\x{a}b$""#);
+        assert_eq!(literal_value("\t"), r#""^\\x{9}$""#);
+        assert_eq!(literal_value("\u{7f}"), r#""^\\x{7f}$""#);
+        assert!(!literal_value("a\r\nb").contains(['\n', '\r']));
+
+        assert!(criteria_regex("a\nb").is_match("a\nb"));
+        assert!(!criteria_regex("a\nb").is_match("anb"));
+    }
+
+    #[test]
+    fn literal_value_quote_injection() {
+        assert_eq!(literal_value("\"]"), r#""^\"\\]$""#);
+        assert_eq!(literal_value("\\\""), r#""^\\\\\"$""#);
+        assert_eq!(literal_value("a;b"), r#""^a;b$""#);
+        assert_eq!(literal_value("a,b"), r#""^a,b$""#);
+
+        for value in ["\"]", "\\\"", "a;b", "a,b", "\"] kill, [class=\"", "x\\"] {
+            let command = format!("[title={}] focus; nop", literal_value(value));
+            let (_, rest) = fixtures::unquote(command.strip_prefix("[title=").unwrap());
+            assert_eq!(rest, "] focus; nop", "{:?} escaped the quotes", value);
+        }
+    }
+
+    #[test]
+    fn literal_value_matches_exactly() {
+        for value in [
+            "Mozilla Firefox",
+            "a.b (1) [2] {3} ^$ | + ? *",
+            "\"] kill, [class=\"",
+            "back\\slash\\",
+            "日本語 ✉",
+            "tab\there",
+        ] {
+            let regex = criteria_regex(value);
+
+            assert!(regex.is_match(value), "{:?}", value);
+            assert!(!regex.is_match(&format!("{} ", value)), "{:?}", value);
+            assert!(!regex.is_match(&format!("x{}", value)), "{:?}", value);
+        }
+
+        assert!(!criteria_regex("a.b").is_match("axb"));
+    }
+
+    #[test]
+    fn con_mark_criteria_exact_text() {
+        assert_eq!(con_mark_criteria("m"), r#"[con_mark="^m$"]"#);
+        assert_eq!(
+            con_mark_criteria("__i3-autolayout__tabmode_3"),
+            r#"[con_mark="^__i3\\-autolayout__tabmode_3$"]"#
+        );
+        assert_eq!(
+            con_mark_criteria("\"] kill"),
+            r#"[con_mark="^\"\\] kill$"]"#
+        );
+    }
+}
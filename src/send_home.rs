@@ -0,0 +1,260 @@
+/*
+    Copyright (C) 2022  Biagio Festa
+
+    This program is free software: you can redistribute it and/or modify
+    it under the terms of the GNU General Public License as published by
+    the Free Software Foundation, either version 3 of the License, or
+    (at your option) any later version.
+
+    This program is distributed in the hope that it will be useful,
+    but WITHOUT ANY WARRANTY; without even the implied warranty of
+    MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+    GNU General Public License for more details.
+
+    You should have received a copy of the GNU General Public License
+    along with this program.  If not, see <https://www.gnu.org/licenses/>.
+*/
+
+use crate::birth::BirthWorkspaces;
+use crate::command_executor::CommandExecutor;
+use crate::command_executor::I3Node;
+use crate::command_executor::RootNode;
+use crate::focus_guard::FocusGuard;
+use crate::undo::record_undo;
+use crate::utilities::describe_window;
+use crate::utilities::find_focused_node;
+use crate::utilities::find_workspace_by_name;
+use crate::utilities::find_workspace_of_node;
+use crate::utilities::find_workspaces;
+use crate::utilities::is_window;
+use crate::utilities::query_workspace_focused;
+use crate::utilities::quote_ws_name;
+use anyhow::anyhow;
+use anyhow::Context;
+use anyhow::Result;
+
+/// SendHome executor.
+///
+/// It moves windows back to the workspace they first appeared on, as recorded by the
+/// service (see `BirthWorkspaces`).
+pub struct SendHome {
+    /// Command executor.
+    command_executor: CommandExecutor,
+}
+
+impl SendHome {
+    /// A new send-home executor.
+    pub fn new(command_executor: CommandExecutor) -> Self {
+        Self { command_executor }
+    }
+
+    /// Execute the action.
+    ///
+    /// The focused window (or, with `all_on_workspace`, every window of the focused
+    /// workspace, floating ones included) is moved to its birth workspace. Windows without
+    /// a record, or whose birth workspace no longer exists, are skipped and listed.
+    pub fn execute(mut self, all_on_workspace: bool) -> Result<()> {
+        let birth_workspaces = BirthWorkspaces::load()?;
+        let root_node = self.command_executor.query_root_node()?;
+
+        let windows = if all_on_workspace {
+            windows(query_workspace_focused(
+                &root_node,
+                &mut self.command_executor,
+            )?)
+        } else {
+            vec![find_focused_node(&root_node)
+                .filter(|node| is_window(node))
+                .ok_or_else(|| anyhow!("Cannot find the focused window"))?]
+        };
+
+        let plan = SendHomePlan::of(windows, &root_node, |window| {
+            birth_workspaces.workspace_of(window)
+        });
+
+        if !plan.moves.is_empty() {
+            record_undo(&plan.workspaces(&root_node));
+
+            let _focus_guard = FocusGuard::capture(&mut self.command_executor)?;
+
+            for &(window, current, home) in &plan.moves {
+                eprintln!(
+                    "Moving window {} from workspace '{}' back to '{}'",
+                    describe_window(window),
+                    current,
+                    home
+                );
+
+                self.command_executor
+                    .run_on_node_id(
+                        window.id,
+                        format!("move container to workspace {}", quote_ws_name(home)?),
+                    )
+                    .context("Cannot move window to its birth workspace")?;
+            }
+        } else if plan.skipped.is_empty() {
+            eprintln!("All windows are already home");
+        }
+
+        for skipped in plan.skipped {
+            eprintln!("[WARN]: Window skipped: {}", skipped);
+        }
+
+        Ok(())
+    }
+}
+
+/// The windows sent home (see `SendHome::execute`).
+struct SendHomePlan<'a> {
+    /// Each window to move, with its current workspace and its birth one.
+    moves: Vec<(&'a I3Node, &'a str, &'a str)>,
+
+    /// The windows skipped, with the reason.
+    skipped: Vec<String>,
+}
+
+impl<'a> SendHomePlan<'a> {
+    /// The plan sending `windows` home, `home_of` being the birth workspace of a window
+    /// (by X11 window id).
+    fn of<F>(windows: Vec<&'a I3Node>, root_node: &'a RootNode, home_of: F) -> Self
+    where
+        F: Fn(usize) -> Option<&'a str>,
+    {
+        let mut moves = vec![];
+        let mut skipped = vec![];
+
+        for window in windows {
+            let Some(home) = window.window.and_then(&home_of) else {
+                skipped.push(format!(
+                    "{}: no birth workspace recorded",
+                    describe_window(window)
+                ));
+                continue;
+            };
+
+            let current = find_workspace_of_node(window.id, root_node)
+                .and_then(|workspace| workspace.name.as_deref());
+
+            if current == Some(home) {
+                continue;
+            }
+
+            if find_workspace_by_name(root_node, home).is_none() {
+                skipped.push(format!(
+                    "{}: workspace '{}' no longer exists",
+                    describe_window(window),
+                    home
+                ));
+                continue;
+            }
+
+            moves.push((window, current.unwrap_or_default(), home));
+        }
+
+        Self { moves, skipped }
+    }
+
+    /// The workspaces changed by the moves: those left and the birth ones.
+    fn workspaces(&self, root_node: &'a RootNode) -> Vec<&'a I3Node> {
+        find_workspaces(root_node)
+            .into_iter()
+            .filter(|workspace| {
+                let name = workspace.name.as_deref().unwrap_or_default();
+
+                self.moves
+                    .iter()
+                    .any(|&(_, current, home)| name == current || name == home)
+            })
+            .collect()
+    }
+}
+
+/// The windows of a subtree (e.g., a workspace), floating ones included.
+fn windows(node: &I3Node) -> Vec<&I3Node> {
+    if is_window(node) {
+        return vec![node];
+    }
+
+    node.nodes
+        .iter()
+        .chain(node.floating_nodes.iter())
+        .flat_map(windows)
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::fixtures;
+
+    /// The birth workspaces of the tests, by X11 window id.
+    fn home_of<'a>(window: usize) -> Option<&'a str> {
+        match window {
+            // Firefox and one terminal of workspace 1.
+            20971621 => Some("web"),
+            20971622 => Some("1"),
+            20971623 => Some("gone"),
+
+            // Mail, on workspace web.
+            20971931 => Some("2"),
+            _ => None,
+        }
+    }
+
+    #[test]
+    fn plan_moves_windows_away_from_home() {
+        let root_node = fixtures::load("workspaces.json");
+        let plan = SendHomePlan::of(
+            windows(fixtures::node(&root_node, 100)),
+            &root_node,
+            home_of,
+        );
+
+        let moves = plan
+            .moves
+            .iter()
+            .map(|&(window, current, home)| (window.id, current, home))
+            .collect::<Vec<_>>();
+        assert_eq!(moves, [(101, "1", "web")]);
+
+        assert_eq!(plan.skipped.len(), 1);
+        assert!(plan.skipped[0].ends_with("workspace 'gone' no longer exists"));
+
+        let mut workspace_ids = plan
+            .workspaces(&root_node)
+            .iter()
+            .map(|workspace| workspace.id)
+            .collect::<Vec<_>>();
+        workspace_ids.sort();
+        assert_eq!(workspace_ids, [100, 400]);
+    }
+
+    #[test]
+    fn plan_skips_windows_without_record() {
+        let root_node = fixtures::load("workspaces.json");
+        let plan = SendHomePlan::of(
+            windows(fixtures::node(&root_node, 200)),
+            &root_node,
+            home_of,
+        );
+
+        assert!(plan.moves.is_empty());
+        assert_eq!(plan.skipped.len(), 4);
+        assert!(plan
+            .skipped
+            .iter()
+            .all(|skipped| skipped.ends_with("no birth workspace recorded")));
+    }
+
+    #[test]
+    fn windows_include_floating_ones() {
+        let root_node = fixtures::load("floating.json");
+        let mut window_ids = windows(fixtures::node(&root_node, 100))
+            .iter()
+            .map(|window| window.id)
+            .collect::<Vec<_>>();
+        window_ids.sort();
+
+        assert_eq!(window_ids, [101, 111, 121, 131]);
+    }
+}
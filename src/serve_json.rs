@@ -0,0 +1,425 @@
+/*
+    Copyright (C) 2022  Biagio Festa
+
+    This program is free software: you can redistribute it and/or modify
+    it under the terms of the GNU General Public License as published by
+    the Free Software Foundation, either version 3 of the License, or
+    (at your option) any later version.
+
+    This program is distributed in the hope that it will be useful,
+    but WITHOUT ANY WARRANTY; without even the implied warranty of
+    MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+    GNU General Public License for more details.
+
+    You should have received a copy of the GNU General Public License
+    along with this program.  If not, see <https://www.gnu.org/licenses/>.
+*/
+use crate::command_executor::CommandExecutor;
+use crate::restore_layout::RestoreLayout;
+use crate::save_layout::SaveLayout;
+use crate::save_layout::SavedLayout;
+use crate::tabmode::TabMode;
+use crate::tabmode::TabModeOptions;
+use crate::workspace::resolve_workspace_arg;
+use crate::workspace::WorkspaceRef;
+use anyhow::Context;
+use anyhow::Result;
+use serde_json::Value;
+use std::io::BufRead;
+use std::io::Write;
+
+/// A request of the JSON protocol (one per line).
+#[derive(Debug, serde::Deserialize, serde::Serialize)]
+#[serde(tag = "op", rename_all = "kebab-case", deny_unknown_fields)]
+pub enum Request {
+    /// Capture the layout of a workspace (the focused one by default).
+    Save {
+        #[serde(default, skip_serializing_if = "Option::is_none")]
+        workspace: Option<String>,
+    },
+
+    /// Restore a layout, as returned by `save`.
+    Restore {
+        layout: Value,
+
+        #[serde(default, rename = "restore-sizes")]
+        restore_sizes: bool,
+    },
+
+    /// The i3 tree (or the subtree of a workspace).
+    Tree {
+        #[serde(default, skip_serializing_if = "Option::is_none")]
+        workspace: Option<String>,
+    },
+
+    /// Toggle the tab-mode of a workspace (the focused one by default).
+    Tabmode {
+        #[serde(default, skip_serializing_if = "Option::is_none")]
+        workspace: Option<String>,
+    },
+}
+
+impl Request {
+    /// The operations of the protocol.
+    const OPS: &'static [&'static str] = &["save", "restore", "tree", "tabmode"];
+}
+
+/// The kind of a failed request.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, serde::Deserialize, serde::Serialize)]
+#[serde(rename_all = "kebab-case")]
+pub enum ErrorKind {
+    /// The line is not a JSON object.
+    InvalidJson,
+
+    /// The `op` field is missing or names no operation.
+    UnknownOp,
+
+    /// The fields do not match the operation.
+    InvalidRequest,
+
+    /// The operation failed.
+    Failed,
+}
+
+/// The error of a failed request.
+#[derive(Debug, serde::Deserialize, serde::Serialize)]
+pub struct ResponseError {
+    pub kind: ErrorKind,
+    pub message: String,
+}
+
+/// The response to a request (one per line).
+///
+/// The `id` of the request, if any, is echoed back.
+#[derive(Debug, serde::Deserialize, serde::Serialize)]
+pub struct Response {
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub id: Option<Value>,
+
+    pub ok: bool,
+
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub result: Option<Value>,
+
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub error: Option<ResponseError>,
+}
+
+impl Response {
+    fn success(id: Option<Value>, result: Value) -> Self {
+        Self {
+            id,
+            ok: true,
+            result: Some(result),
+            error: None,
+        }
+    }
+
+    fn failure(id: Option<Value>, kind: ErrorKind, message: String) -> Self {
+        Self {
+            id,
+            ok: false,
+            result: None,
+            error: Some(ResponseError { kind, message }),
+        }
+    }
+}
+
+/// JSON server executor.
+///
+/// It reads newline-delimited JSON requests and writes one JSON response per request,
+/// reusing a single connection to i3. It stops at the end of the input.
+///
+/// Note that warnings of the operations are still printed on the standard output:
+/// clients should skip the lines which are not JSON objects.
+pub struct ServeJson {
+    command_executor: CommandExecutor,
+    tabmode_options: TabModeOptions,
+}
+
+impl ServeJson {
+    /// A new JSON server executor.
+    ///
+    /// `tabmode_options` are the options of the `tabmode` operation.
+    pub fn new(command_executor: CommandExecutor, tabmode_options: TabModeOptions) -> Self {
+        Self {
+            command_executor,
+            tabmode_options,
+        }
+    }
+
+    /// Serve the requests of `input`, writing the responses into `output`.
+    pub fn execute<R, W>(mut self, input: R, mut output: W) -> Result<()>
+    where
+        R: BufRead,
+        W: Write,
+    {
+        for line in input.lines() {
+            let line = line.context("Cannot read the request")?;
+
+            if line.trim().is_empty() {
+                continue;
+            }
+
+            let response = self.handle_line(&line);
+
+            serde_json::to_writer(&mut output, &response).context("Cannot write the response")?;
+            writeln!(output).context("Cannot write the response")?;
+            output.flush().context("Cannot write the response")?;
+        }
+
+        Ok(())
+    }
+
+    fn handle_line(&mut self, line: &str) -> Response {
+        let (id, request) = match parse_line(line) {
+            Ok(parsed) => parsed,
+            Err(response) => return response,
+        };
+
+        match self.handle(request) {
+            Ok(result) => Response::success(id, result),
+            Err(error) => Response::failure(id, ErrorKind::Failed, format!("{:#}", error)),
+        }
+    }
+
+    fn handle(&mut self, request: Request) -> Result<Value> {
+        match request {
+            Request::Save { workspace } => {
+                let workspace = parse_workspace(workspace.as_deref())?;
+                let saved_layout =
+                    SaveLayout::new(self.command_executor.share()).capture(&workspace)?;
+
+                let mut json = vec![];
+                saved_layout.to_writer(&mut json, true)?;
+
+                serde_json::from_slice(&json).context("Cannot JSON serialize layout")
+            }
+
+            Request::Restore {
+                layout,
+                restore_sizes,
+            } => {
+                let json = serde_json::to_vec(&layout).context("Cannot JSON serialize layout")?;
+                let saved_layout = SavedLayout::from_reader(json.as_slice(), true)?;
+
+                let report = RestoreLayout::new(self.command_executor.share())
+                    .apply(&saved_layout, restore_sizes)?;
+
+                Ok(serde_json::json!({
+                    "commands": report.commands,
+                    "failures": report.failures,
+                    "skipped": report.skipped,
+                }))
+            }
+
+            Request::Tree { workspace } => {
+                let root_node = self.command_executor.query_root_node()?;
+
+                let node = match workspace {
+                    Some(workspace) => resolve_workspace_arg(
+                        Some(&workspace.parse()?),
+                        &root_node,
+                        &mut self.command_executor,
+                    )?,
+                    None => root_node.node(),
+                };
+
+                serde_json::to_value(node).context("Cannot JSON serialize the tree")
+            }
+
+            Request::Tabmode { workspace } => {
+                let workspace = parse_workspace(workspace.as_deref())?;
+
+                TabMode::new(self.command_executor.share())
+                    .execute(workspace, self.tabmode_options.clone())?;
+
+                Ok(Value::Null)
+            }
+        }
+    }
+}
+
+/// Parse a request line into its `id` (if any) and the request.
+///
+/// If the line is not a valid request, the error is the response to write back.
+fn parse_line(line: &str) -> Result<(Option<Value>, Request), Response> {
+    let mut value: Value = match serde_json::from_str(line) {
+        Ok(value @ Value::Object(_)) => value,
+        Ok(_) => {
+            return Err(Response::failure(
+                None,
+                ErrorKind::InvalidJson,
+                "The request is not a JSON object".to_string(),
+            ))
+        }
+        Err(error) => {
+            return Err(Response::failure(
+                None,
+                ErrorKind::InvalidJson,
+                error.to_string(),
+            ))
+        }
+    };
+
+    let id = value.as_object_mut().and_then(|object| object.remove("id"));
+
+    let op = value.get("op").and_then(Value::as_str);
+    if !op.is_some_and(|op| Request::OPS.contains(&op)) {
+        let message = match op {
+            Some(op) => format!(
+                "Unknown op '{}' (expected one of: {})",
+                op,
+                Request::OPS.join(", ")
+            ),
+            None => "Missing the 'op' field".to_string(),
+        };
+
+        return Err(Response::failure(id, ErrorKind::UnknownOp, message));
+    }
+
+    match serde_json::from_value(value) {
+        Ok(request) => Ok((id, request)),
+        Err(error) => Err(Response::failure(
+            id,
+            ErrorKind::InvalidRequest,
+            error.to_string(),
+        )),
+    }
+}
+
+/// The workspace of a request (the focused one if not specified).
+fn parse_workspace(workspace: Option<&str>) -> Result<WorkspaceRef> {
+    workspace.map_or(Ok(WorkspaceRef::Focused), str::parse)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::fixtures;
+    use serde_json::json;
+
+    fn to_json(response: &Response) -> Value {
+        serde_json::to_value(response).unwrap()
+    }
+
+    #[test]
+    fn requests_round_trip() {
+        for (line, id) in [
+            (r#"{"op":"save"}"#, None),
+            (r#"{"op":"save","workspace":"3: mail"}"#, None),
+            (r#"{"op":"tree","workspace":"1"}"#, None),
+            (r#"{"op":"tabmode"}"#, None),
+            (r#"{"op":"restore","layout":[],"restore-sizes":true}"#, None),
+            (r#"{"id":7,"op":"tree"}"#, Some(json!(7))),
+            (r#"{"op":"tree","id":"a"}"#, Some(json!("a"))),
+        ] {
+            let (parsed_id, request) = parse_line(line).unwrap();
+            assert_eq!(parsed_id, id, "{}", line);
+
+            let mut expected: Value = serde_json::from_str(line).unwrap();
+            expected.as_object_mut().unwrap().remove("id");
+            assert_eq!(
+                serde_json::to_value(&request).unwrap(),
+                expected,
+                "{}",
+                line
+            );
+        }
+    }
+
+    #[test]
+    fn restore_requests_carry_saved_layouts() {
+        let root_node = fixtures::load("workspaces.json");
+        let saved_layout =
+            SaveLayout::save_subtree(fixtures::node(&root_node, 200), false).unwrap();
+
+        let mut json = vec![];
+        saved_layout.to_writer(&mut json, true).unwrap();
+        let layout: Value = serde_json::from_slice(&json).unwrap();
+
+        let line = json!({ "op": "restore", "layout": layout }).to_string();
+        let Ok((
+            None,
+            Request::Restore {
+                layout,
+                restore_sizes,
+            },
+        )) = parse_line(&line)
+        else {
+            panic!("Expected a restore request");
+        };
+        assert!(!restore_sizes);
+
+        let json = serde_json::to_vec(&layout).unwrap();
+        let loaded = SavedLayout::from_reader(json.as_slice(), true).unwrap();
+        assert_eq!(loaded.node_count(), saved_layout.node_count());
+        assert_eq!(loaded.root().children(), [201, 210]);
+    }
+
+    #[test]
+    fn invalid_requests() {
+        for (line, id, kind) in [
+            ("{ truncated", None, ErrorKind::InvalidJson),
+            ("[1, 2]", None, ErrorKind::InvalidJson),
+            (r#"{"id":1}"#, Some(json!(1)), ErrorKind::UnknownOp),
+            (
+                r#"{"id":2,"op":"kill"}"#,
+                Some(json!(2)),
+                ErrorKind::UnknownOp,
+            ),
+            (r#"{"op":42}"#, None, ErrorKind::UnknownOp),
+            (r#"{"op":"restore"}"#, None, ErrorKind::InvalidRequest),
+            (
+                r#"{"id":3,"op":"save","workspaces":"1"}"#,
+                Some(json!(3)),
+                ErrorKind::InvalidRequest,
+            ),
+        ] {
+            let response = parse_line(line).err().unwrap();
+            assert!(!response.ok, "{}", line);
+            assert!(response.result.is_none(), "{}", line);
+            assert_eq!(response.id, id, "{}", line);
+            assert_eq!(response.error.unwrap().kind, kind, "{}", line);
+        }
+
+        let response = parse_line(r#"{"op":"kill"}"#).err().unwrap();
+        assert_eq!(
+            response.error.unwrap().message,
+            "Unknown op 'kill' (expected one of: save, restore, tree, tabmode)"
+        );
+    }
+
+    #[test]
+    fn responses_format() {
+        assert_eq!(
+            to_json(&Response::success(Some(json!(1)), Value::Null)),
+            json!({ "id": 1, "ok": true, "result": null })
+        );
+        assert_eq!(
+            to_json(&Response::success(None, json!({ "skipped": 0 }))),
+            json!({ "ok": true, "result": { "skipped": 0 } })
+        );
+        assert_eq!(
+            to_json(&Response::failure(
+                None,
+                ErrorKind::InvalidRequest,
+                "missing field `layout`".to_string()
+            )),
+            json!({
+                "ok": false,
+                "error": { "kind": "invalid-request", "message": "missing field `layout`" },
+            })
+        );
+
+        let line = serde_json::to_string(&Response::failure(
+            Some(json!("a")),
+            ErrorKind::Failed,
+            "Cannot find the workspace 'x'".to_string(),
+        ))
+        .unwrap();
+        let response: Response = serde_json::from_str(&line).unwrap();
+        assert_eq!(response.id, Some(json!("a")));
+        assert_eq!(response.error.unwrap().kind, ErrorKind::Failed);
+    }
+}
@@ -0,0 +1,74 @@
+/*
+    Copyright (C) 2022  Biagio Festa
+
+    This program is free software: you can redistribute it and/or modify
+    it under the terms of the GNU General Public License as published by
+    the Free Software Foundation, either version 3 of the License, or
+    (at your option) any later version.
+
+    This program is distributed in the hope that it will be useful,
+    but WITHOUT ANY WARRANTY; without even the implied warranty of
+    MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+    GNU General Public License for more details.
+
+    You should have received a copy of the GNU General Public License
+    along with this program.  If not, see <https://www.gnu.org/licenses/>.
+*/
+
+use anyhow::anyhow;
+use anyhow::Result;
+use std::sync::atomic::AtomicBool;
+use std::sync::atomic::Ordering;
+
+/// Whether a dump of the status has been requested (`SIGUSR2`) and not served yet.
+static DUMP_REQUESTED: AtomicBool = AtomicBool::new(false);
+
+/// The handler of `SIGUSR2`: it only sets a flag (async-signal-safe).
+extern "C" fn on_dump_signal(_signal: libc::c_int) {
+    DUMP_REQUESTED.store(true, Ordering::SeqCst);
+}
+
+/// Request a dump of the status on `SIGUSR2` (see `take_dump_request`).
+pub fn watch_dump_signal() -> Result<()> {
+    // SAFETY: the handler only stores to an atomic, which is async-signal-safe; the
+    // `sigaction` structure is fully initialized (zeroed, then set).
+    let installed = unsafe {
+        let mut action: libc::sigaction = std::mem::zeroed();
+        action.sa_sigaction = on_dump_signal as extern "C" fn(libc::c_int) as libc::sighandler_t;
+        action.sa_flags = libc::SA_RESTART;
+        libc::sigemptyset(&mut action.sa_mask);
+
+        libc::sigaction(libc::SIGUSR2, &action, std::ptr::null_mut())
+    };
+
+    if installed != 0 {
+        return Err(anyhow!(
+            "Cannot handle SIGUSR2: {}",
+            std::io::Error::last_os_error()
+        ));
+    }
+
+    Ok(())
+}
+
+/// Whether a dump has been requested since the last call.
+pub fn take_dump_request() -> bool {
+    DUMP_REQUESTED.swap(false, Ordering::SeqCst)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn dump_requested_by_sigusr2() {
+        watch_dump_signal().unwrap();
+        assert!(!take_dump_request());
+
+        // SAFETY: the handler installed above only sets a flag.
+        assert_eq!(unsafe { libc::raise(libc::SIGUSR2) }, 0);
+
+        assert!(take_dump_request());
+        assert!(!take_dump_request());
+    }
+}
@@ -0,0 +1,241 @@
+/*
+    Copyright (C) 2022  Biagio Festa
+
+    This program is free software: you can redistribute it and/or modify
+    it under the terms of the GNU General Public License as published by
+    the Free Software Foundation, either version 3 of the License, or
+    (at your option) any later version.
+
+    This program is distributed in the hope that it will be useful,
+    but WITHOUT ANY WARRANTY; without even the implied warranty of
+    MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+    GNU General Public License for more details.
+
+    You should have received a copy of the GNU General Public License
+    along with this program.  If not, see <https://www.gnu.org/licenses/>.
+*/
+
+use crate::state::StateComponent;
+use crate::state_store::StateStore;
+use crate::utilities::Split;
+use anyhow::Context;
+use anyhow::Result;
+
+/// The situation of a split decision.
+#[derive(Clone, Debug, PartialEq, Eq, serde::Deserialize, serde::Serialize)]
+#[serde(rename_all = "kebab-case")]
+pub struct SplitKey {
+    /// The window class.
+    pub class: String,
+
+    /// The layout of the parent container (`splith` or `splitv`).
+    pub parent_layout: String,
+
+    /// Whether the workspace is vertical (e.g., on a portrait monitor).
+    pub workspace_vertical: bool,
+}
+
+/// A remembered decision.
+#[derive(Clone, Debug, serde::Deserialize, serde::Serialize)]
+struct Entry {
+    #[serde(flatten)]
+    key: SplitKey,
+
+    split: Split,
+}
+
+/// The file content.
+#[derive(Default, serde::Deserialize, serde::Serialize)]
+struct SplitMemoryFile {
+    /// The entries, least recently used first.
+    entries: Vec<Entry>,
+}
+
+/// The split decisions remembered per application.
+///
+/// Decisions are persisted in the state directory (`split-memory.json`), so that they
+/// survive restarts. At most `CAPACITY` decisions are kept: the least recently used
+/// one is evicted first.
+pub struct SplitMemory {
+    store: StateStore,
+    file: SplitMemoryFile,
+}
+
+impl SplitMemory {
+    const FILE_NAME: &'static str = "split-memory.json";
+
+    /// The version of the file format.
+    const VERSION: u32 = 1;
+
+    /// The maximum number of remembered decisions.
+    pub const CAPACITY: usize = 256;
+
+    /// Load the remembered decisions.
+    ///
+    /// A missing file means no decisions; an unreadable one is quarantined
+    /// (see `StateStore::load`).
+    pub fn load() -> Result<Self> {
+        Ok(Self::load_from(StateStore::open()?))
+    }
+
+    /// Load the remembered decisions of a store.
+    fn load_from(store: StateStore) -> Self {
+        let mut file: SplitMemoryFile = store.load(Self::FILE_NAME, Self::VERSION);
+
+        let excess = file.entries.len().saturating_sub(Self::CAPACITY);
+        file.entries.drain(..excess);
+
+        Self { store, file }
+    }
+
+    /// The decision remembered for a situation.
+    pub fn get(&self, key: &SplitKey) -> Option<Split> {
+        self.file
+            .entries
+            .iter()
+            .find(|entry| entry.key == *key)
+            .map(|entry| entry.split)
+    }
+
+    /// Remember a decision (as the most recently used one) and persist it.
+    pub fn remember(&mut self, key: SplitKey, split: Split) -> Result<()> {
+        if self
+            .file
+            .entries
+            .last()
+            .is_some_and(|entry| entry.key == key && entry.split == split)
+        {
+            return Ok(());
+        }
+
+        self.file.entries.retain(|entry| entry.key != key);
+        self.file.entries.push(Entry { key, split });
+
+        let excess = self.file.entries.len().saturating_sub(Self::CAPACITY);
+        self.file.entries.drain(..excess);
+
+        self.store()
+    }
+
+    /// Forget all decisions.
+    pub fn forget(&mut self) -> Result<()> {
+        self.file.entries.clear();
+        self.store()
+    }
+
+    fn store(&self) -> Result<()> {
+        self.store
+            .store(Self::FILE_NAME, Self::VERSION, &self.file)
+            .context("Cannot write the split memory")
+    }
+}
+
+impl StateComponent for SplitMemory {
+    fn name(&self) -> &'static str {
+        "split-memory"
+    }
+
+    fn entries(&self) -> usize {
+        self.file.entries.len()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::fixtures::TempDir;
+
+    fn key(class: &str) -> SplitKey {
+        SplitKey {
+            class: class.to_string(),
+            parent_layout: "splith".to_string(),
+            workspace_vertical: false,
+        }
+    }
+
+    fn memory_in(dir: &TempDir) -> SplitMemory {
+        SplitMemory::load_from(StateStore::in_dir(dir.path().to_path_buf()))
+    }
+
+    #[test]
+    fn remembered_splits_survive_reload() {
+        let dir = TempDir::new("split-memory");
+
+        let mut memory = memory_in(&dir);
+        memory.remember(key("firefox"), Split::Vertical).unwrap();
+        memory
+            .remember(key("Alacritty"), Split::Horizontal)
+            .unwrap();
+
+        let vertical_workspace = SplitKey {
+            workspace_vertical: true,
+            ..key("firefox")
+        };
+        memory
+            .remember(vertical_workspace.clone(), Split::Horizontal)
+            .unwrap();
+
+        let memory = memory_in(&dir);
+        assert_eq!(memory.entries(), 3);
+        assert_eq!(memory.get(&key("firefox")), Some(Split::Vertical));
+        assert_eq!(memory.get(&key("Alacritty")), Some(Split::Horizontal));
+        assert_eq!(memory.get(&vertical_workspace), Some(Split::Horizontal));
+        assert_eq!(memory.get(&key("Gimp")), None);
+    }
+
+    #[test]
+    fn file_format() {
+        let dir = TempDir::new("split-memory");
+
+        memory_in(&dir)
+            .remember(key("firefox"), Split::Vertical)
+            .unwrap();
+
+        let content = std::fs::read_to_string(dir.path().join(SplitMemory::FILE_NAME)).unwrap();
+        assert_eq!(
+            serde_json::from_str::<serde_json::Value>(&content).unwrap(),
+            serde_json::json!({
+                "version": 1,
+                "entries": [{
+                    "class": "firefox",
+                    "parent-layout": "splith",
+                    "workspace-vertical": false,
+                    "split": "vertical",
+                }],
+            })
+        );
+    }
+
+    #[test]
+    fn least_recently_used_is_evicted() {
+        let dir = TempDir::new("split-memory");
+        let mut memory = memory_in(&dir);
+
+        for index in 0..SplitMemory::CAPACITY {
+            memory
+                .remember(key(&index.to_string()), Split::Vertical)
+                .unwrap();
+        }
+
+        // Used again: now the most recent one.
+        memory.remember(key("0"), Split::Horizontal).unwrap();
+        memory.remember(key("new"), Split::Vertical).unwrap();
+
+        let memory = memory_in(&dir);
+        assert_eq!(memory.entries(), SplitMemory::CAPACITY);
+        assert_eq!(memory.get(&key("0")), Some(Split::Horizontal));
+        assert_eq!(memory.get(&key("1")), None);
+        assert_eq!(memory.get(&key("new")), Some(Split::Vertical));
+    }
+
+    #[test]
+    fn forget_clears_the_file() {
+        let dir = TempDir::new("split-memory");
+
+        let mut memory = memory_in(&dir);
+        memory.remember(key("firefox"), Split::Vertical).unwrap();
+        memory.forget().unwrap();
+
+        assert_eq!(memory_in(&dir).entries(), 0);
+    }
+}
@@ -0,0 +1,471 @@
+/*
+    Copyright (C) 2022  Biagio Festa
+
+    This program is free software: you can redistribute it and/or modify
+    it under the terms of the GNU General Public License as published by
+    the Free Software Foundation, either version 3 of the License, or
+    (at your option) any later version.
+
+    This program is distributed in the hope that it will be useful,
+    but WITHOUT ANY WARRANTY; without even the implied warranty of
+    MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+    GNU General Public License for more details.
+
+    You should have received a copy of the GNU General Public License
+    along with this program.  If not, see <https://www.gnu.org/licenses/>.
+*/
+
+use crate::command_executor::I3Node;
+use crate::command_executor::RootNode;
+use crate::selector::Criteria;
+use crate::split_memory::SplitKey;
+use crate::split_memory::SplitMemory;
+use crate::utilities::cells_split;
+use crate::utilities::find_node_ancestors;
+use crate::utilities::find_node_parent;
+use crate::utilities::find_workspace_of_node;
+use crate::utilities::glob_regex;
+use crate::utilities::is_floating_container;
+use crate::utilities::node_marks;
+use crate::utilities::OrientationPolicy;
+use crate::utilities::Split;
+use anyhow::anyhow;
+use anyhow::Result;
+use i3_ipc::reply::NodeLayout;
+use i3_ipc::reply::NodeType;
+use regex::Regex;
+use std::fmt::Display;
+use std::str::FromStr;
+
+/// The auto-layout behaviour on a workspace.
+#[derive(
+    Clone,
+    Copy,
+    Debug,
+    Default,
+    PartialEq,
+    Eq,
+    clap::ValueEnum,
+    serde::Deserialize,
+    serde::Serialize,
+)]
+#[serde(rename_all = "kebab-case")]
+pub enum LayoutMode {
+    /// Split windows according to the rules and their orientation.
+    #[default]
+    Auto,
+
+    /// Leave the layout untouched.
+    Off,
+
+    /// Keep the workspace as `splith` columns: new windows stack vertically in the column
+    /// of the window focused before (see `SplitPolicy::columns`).
+    Columns,
+
+    /// Split in the direction whose resulting cells are closest to a target aspect ratio,
+    /// given the siblings of the window (see `SplitPolicy::cell_aspect`).
+    Cells,
+}
+
+impl Display for LayoutMode {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Self::Auto => write!(f, "auto"),
+            Self::Off => write!(f, "off"),
+            Self::Columns => write!(f, "columns"),
+            Self::Cells => write!(f, "cells"),
+        }
+    }
+}
+
+/// The layout mode of the workspaces whose name matches a pattern.
+///
+/// It is parsed from `<pattern>:<mode>` (e.g., `dev*:auto`), where the pattern
+/// supports the `*` and `?` wildcards.
+#[derive(Clone)]
+pub struct Profile {
+    /// The workspace name pattern.
+    pattern: String,
+
+    /// The compiled pattern.
+    regex: Regex,
+
+    /// The mode of the matching workspaces.
+    mode: LayoutMode,
+}
+
+impl Profile {
+    /// The workspace name pattern.
+    pub fn pattern(&self) -> &str {
+        &self.pattern
+    }
+
+    /// Whether the profile applies to the workspace.
+    fn matches(&self, workspace_name: &str) -> bool {
+        self.regex.is_match(workspace_name)
+    }
+}
+
+impl FromStr for Profile {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> Result<Self> {
+        let (pattern, mode) = s
+            .rsplit_once(':')
+            .filter(|(pattern, _)| !pattern.is_empty())
+            .ok_or_else(|| anyhow!("Invalid profile '{}' (expected PATTERN:MODE)", s))?;
+
+        let mode = <LayoutMode as clap::ValueEnum>::from_str(mode, false).map_err(|_| {
+            let modes = <LayoutMode as clap::ValueEnum>::value_variants()
+                .iter()
+                .map(|mode| format!("'{}'", mode))
+                .collect::<Vec<_>>();
+
+            anyhow!(
+                "Invalid mode '{}' in profile (expected one of {})",
+                mode,
+                modes.join(", ")
+            )
+        })?;
+
+        Ok(Self {
+            pattern: pattern.to_string(),
+            regex: glob_regex(pattern),
+            mode,
+        })
+    }
+}
+
+impl<'de> serde::Deserialize<'de> for Profile {
+    fn deserialize<D>(deserializer: D) -> std::result::Result<Self, D::Error>
+    where
+        D: serde::Deserializer<'de>,
+    {
+        let profile = String::deserialize(deserializer)?;
+        profile.parse().map_err(serde::de::Error::custom)
+    }
+}
+
+impl serde::Serialize for Profile {
+    fn serialize<S>(&self, serializer: S) -> std::result::Result<S::Ok, S::Error>
+    where
+        S: serde::Serializer,
+    {
+        serializer.serialize_str(&format!("{}:{}", self.pattern, self.mode))
+    }
+}
+
+/// A rule overriding the split of the focused window.
+///
+/// It is parsed from `<criteria>:<vertical|horizontal>[:<ppt>]` (e.g., `class=Alacritty:vertical:30`).
+/// With `ppt`, the next window opened next to the matching one takes that percentage.
+#[derive(Clone)]
+pub struct SplitRule {
+    /// The windows the rule applies to.
+    criteria: Criteria,
+
+    /// The split to apply.
+    split: Split,
+
+    /// The size (percentage points) of the window opened after the split.
+    ppt: Option<u32>,
+}
+
+impl FromStr for SplitRule {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> Result<Self> {
+        let invalid = || anyhow!("Invalid rule '{}' (expected CRITERIA:SPLIT[:PPT])", s);
+
+        let (rest, last) = s.rsplit_once(':').ok_or_else(invalid)?;
+
+        let (criteria, split, ppt) = match last.parse::<u32>() {
+            Ok(ppt) => {
+                let (criteria, split) = rest.rsplit_once(':').ok_or_else(invalid)?;
+                (criteria, split, Some(ppt))
+            }
+            Err(_) => (rest, last, None),
+        };
+
+        let split = match split {
+            "vertical" => Split::Vertical,
+            "horizontal" => Split::Horizontal,
+            _ => {
+                return Err(anyhow!(
+                    "Invalid split '{}' in rule (expected 'vertical' or 'horizontal')",
+                    split
+                ))
+            }
+        };
+
+        if let Some(ppt) = ppt.filter(|ppt| !(1..100).contains(ppt)) {
+            return Err(anyhow!("Invalid size '{}' in rule (expected 1-99)", ppt));
+        }
+
+        let criteria: Criteria = criteria.parse()?;
+        if criteria.is_empty() {
+            return Err(invalid());
+        }
+
+        Ok(Self {
+            criteria,
+            split,
+            ppt,
+        })
+    }
+}
+
+/// How the split of a window is decided.
+#[derive(Default)]
+pub struct SplitPolicy {
+    /// The split rules (the first matching applies).
+    pub rules: Vec<SplitRule>,
+
+    /// The policy deciding the split when no rule applies.
+    pub orientation_policy: OrientationPolicy,
+
+    /// The mode of the workspaces not matching any profile.
+    pub mode: LayoutMode,
+
+    /// The per-workspace modes (the first matching applies).
+    pub profiles: Vec<Profile>,
+
+    /// When set, only windows inside a container marked with it are managed.
+    ///
+    /// Since i3 marks are unique, containers are also marked as `<mark>:<suffix>`.
+    pub opt_in_mark: Option<String>,
+
+    /// Remember the splits decided for each application (see `SplitMemory`).
+    pub remember: bool,
+
+    /// On ultrawide workspaces, split horizontally the windows of the top-level `splith`
+    /// container while it has fewer children than this.
+    pub ultrawide_columns: Option<usize>,
+
+    /// The minimum width (pixels) of a workspace considered ultrawide.
+    pub ultrawide_min_width: isize,
+
+    /// The number of columns of the workspaces in `columns` mode
+    /// (`DEFAULT_COLUMNS` if `None`).
+    pub columns: Option<usize>,
+
+    /// The target aspect ratio (width / height) of the cells of the workspaces in `cells`
+    /// mode (`DEFAULT_CELL_ASPECT` if `None`).
+    pub cell_aspect: Option<f64>,
+}
+
+/// Whether `mark` is the opt-in mark (either `<opt_in_mark>` or `<opt_in_mark>:<suffix>`).
+pub fn is_opt_in_mark(mark: &str, opt_in_mark: &str) -> bool {
+    match mark.strip_prefix(opt_in_mark) {
+        Some(suffix) => suffix.is_empty() || suffix.starts_with(':'),
+        None => false,
+    }
+}
+
+/// The outcome of a split decision.
+pub struct SplitDecision<'a> {
+    /// The name of the workspace of the window.
+    pub workspace: String,
+
+    /// The layout mode of the workspace.
+    pub mode: LayoutMode,
+
+    /// The profile the mode comes from (`None` for the global mode).
+    pub profile: Option<&'a Profile>,
+
+    /// The split to apply (`None` if the window is left untouched).
+    pub split: Option<Split>,
+
+    /// The size (percentage points) of the window opened after the split.
+    pub ppt: Option<u32>,
+
+    /// The situation of the split, when decided by the orientation with a split memory.
+    pub key: Option<SplitKey>,
+
+    /// Whether the split comes from the split memory.
+    pub remembered: bool,
+}
+
+impl SplitPolicy {
+    /// The default minimum width of an ultrawide workspace.
+    pub const DEFAULT_ULTRAWIDE_MIN_WIDTH: isize = 3000;
+
+    /// The default number of columns of the workspaces in `columns` mode.
+    pub const DEFAULT_COLUMNS: usize = 2;
+
+    /// The default target aspect ratio of the cells of the workspaces in `cells` mode.
+    pub const DEFAULT_CELL_ASPECT: f64 = 1.2;
+
+    /// The mode of a workspace, together with the profile it comes from (if any).
+    pub fn mode_of(&self, workspace_name: &str) -> (LayoutMode, Option<&Profile>) {
+        match self
+            .profiles
+            .iter()
+            .find(|profile| profile.matches(workspace_name))
+        {
+            Some(profile) => (profile.mode, Some(profile)),
+            None => (self.mode, None),
+        }
+    }
+
+    /// Whether the window or one of its ancestors carries the opt-in mark (if any).
+    fn is_opted_in(&self, node: &I3Node, root_node: &RootNode) -> bool {
+        let Some(opt_in_mark) = self.opt_in_mark.as_deref() else {
+            return true;
+        };
+
+        std::iter::once(node)
+            .chain(find_node_ancestors(node.id, root_node))
+            .flat_map(node_marks)
+            .any(|mark| is_opt_in_mark(&mark, opt_in_mark))
+    }
+
+    /// Decide the split of a window, without changing anything.
+    ///
+    /// Floating windows, windows in tabbed or stacked containers and windows on workspaces
+    /// whose mode is `off` are left untouched, as are the windows of workspaces in `columns`
+    /// mode (arranged when opened instead).
+    ///
+    /// Without a matching rule, the split remembered in `memory` (if any) for the same
+    /// situation takes precedence over the orientation of the window. In `cells` mode the
+    /// split follows the cells it would make instead (see `cells_split`).
+    pub fn decide(
+        &self,
+        node: &I3Node,
+        root_node: &RootNode,
+        memory: Option<&SplitMemory>,
+    ) -> Result<SplitDecision<'_>> {
+        let workspace = find_workspace_of_node(node.id, root_node);
+        let workspace_name = workspace
+            .and_then(|workspace| workspace.name.clone())
+            .unwrap_or_default();
+        let (mode, profile) = self.mode_of(&workspace_name);
+
+        let mut decision = SplitDecision {
+            workspace: workspace_name,
+            mode,
+            profile,
+            split: None,
+            ppt: None,
+            key: None,
+            remembered: false,
+        };
+
+        if !matches!(mode, LayoutMode::Auto | LayoutMode::Cells)
+            || is_floating_container(node)
+            || !self.is_opted_in(node, root_node)
+        {
+            return Ok(decision);
+        }
+
+        let parent_node = find_node_parent(node.id, root_node)
+            .ok_or_else(|| anyhow!("Cannot find parent of focused window"))?;
+
+        if let NodeLayout::SplitH | NodeLayout::SplitV = parent_node.layout {
+            let rule = workspace.and_then(|workspace| {
+                self.rules
+                    .iter()
+                    .find(|rule| rule.criteria.matches(node, workspace))
+            });
+
+            decision.split = match rule {
+                Some(rule) => Some(rule.split),
+                None if self.is_ultrawide_column(parent_node) => Some(Split::Horizontal),
+                None if mode == LayoutMode::Cells => cells_split(
+                    node,
+                    parent_node,
+                    self.cell_aspect.unwrap_or(Self::DEFAULT_CELL_ASPECT),
+                ),
+                None => {
+                    decision.key = memory
+                        .and(workspace)
+                        .and_then(|workspace| self.split_key(node, parent_node, workspace));
+
+                    let remembered = memory
+                        .zip(decision.key.as_ref())
+                        .and_then(|(memory, key)| memory.get(key));
+                    decision.remembered = remembered.is_some();
+
+                    remembered.or_else(|| self.orientation_policy.decide(node, workspace))
+                }
+            };
+            decision.ppt = rule.and_then(|rule| rule.ppt);
+        }
+
+        Ok(decision)
+    }
+
+    /// Whether a window under `parent_node` is one of the first columns of an ultrawide
+    /// workspace.
+    ///
+    /// Only windows directly under the workspace are concerned: nested containers are
+    /// left to the other policies.
+    fn is_ultrawide_column(&self, parent_node: &I3Node) -> bool {
+        let Some(columns) = self.ultrawide_columns else {
+            return false;
+        };
+
+        parent_node.node_type == NodeType::Workspace
+            && parent_node.layout == NodeLayout::SplitH
+            && parent_node.rect.width >= self.ultrawide_min_width
+            && parent_node.nodes.len() < columns
+    }
+
+    /// The situation of a split, as remembered by the split memory.
+    ///
+    /// It is `None` for windows without class or on workspaces without a sane rect.
+    fn split_key(
+        &self,
+        node: &I3Node,
+        parent_node: &I3Node,
+        workspace: &I3Node,
+    ) -> Option<SplitKey> {
+        let class = node
+            .window_properties
+            .as_ref()
+            .and_then(|properties| properties.class.clone())?;
+
+        let parent_layout = match parent_node.layout {
+            NodeLayout::SplitH => "splith",
+            _ => "splitv",
+        };
+
+        Some(SplitKey {
+            class,
+            parent_layout: parent_layout.to_string(),
+            workspace_vertical: self.orientation_policy.is_node_vertical(workspace)?,
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn profile_parse() {
+        let profile: Profile = "dev*:cells".parse().unwrap();
+        assert_eq!(profile.pattern(), "dev*");
+        assert_eq!(profile.mode, LayoutMode::Cells);
+        assert!(profile.matches("dev-1"));
+        assert!(!profile.matches("mail"));
+
+        // The pattern can contain ':' (e.g., numbered workspaces).
+        let profile: Profile = "3: mail:off".parse().unwrap();
+        assert_eq!(profile.pattern(), "3: mail");
+        assert_eq!(profile.mode, LayoutMode::Off);
+    }
+
+    #[test]
+    fn profile_parse_errors_list_every_mode() {
+        let error = "dev*:grid".parse::<Profile>().err().unwrap().to_string();
+
+        assert_eq!(
+            error,
+            "Invalid mode 'grid' in profile (expected one of 'auto', 'off', 'columns', 'cells')"
+        );
+
+        assert!(":auto".parse::<Profile>().is_err());
+        assert!("auto".parse::<Profile>().is_err());
+    }
+}
@@ -0,0 +1,187 @@
+/*
+    Copyright (C) 2022  Biagio Festa
+
+    This program is free software: you can redistribute it and/or modify
+    it under the terms of the GNU General Public License as published by
+    the Free Software Foundation, either version 3 of the License, or
+    (at your option) any later version.
+
+    This program is distributed in the hope that it will be useful,
+    but WITHOUT ANY WARRANTY; without even the implied warranty of
+    MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+    GNU General Public License for more details.
+
+    You should have received a copy of the GNU General Public License
+    along with this program.  If not, see <https://www.gnu.org/licenses/>.
+*/
+
+use i3_ipc::event::Event;
+use i3_ipc::event::WindowChange;
+use i3_ipc::event::WorkspaceChange;
+
+/// A piece of the service state tracking windows or workspaces.
+///
+/// Components are notified when windows are closed and workspaces are destroyed,
+/// so that they can drop the entries which no longer refer to anything.
+pub trait StateComponent {
+    /// The name of the component (as reported by `ctl dump-state`).
+    fn name(&self) -> &'static str;
+
+    /// The number of entries currently held.
+    fn entries(&self) -> usize;
+
+    /// The description of the entries (as reported by `ctl dump-state`).
+    fn describe(&self) -> String {
+        self.entries().to_string()
+    }
+
+    /// A window has been closed.
+    fn on_window_closed(&mut self, _node_id: usize) {}
+
+    /// A workspace has been emptied (i.e., destroyed).
+    fn on_workspace_emptied(&mut self, _workspace: &str) {}
+}
+
+/// Route an event to the state components.
+///
+/// Only Window/Close and Workspace/Empty events are relevant, others are ignored.
+pub fn gc(event: &Event, components: &mut [&mut dyn StateComponent]) {
+    match event {
+        Event::Window(window_data) if window_data.change == WindowChange::Close => {
+            for component in components {
+                component.on_window_closed(window_data.container.id);
+            }
+        }
+
+        Event::Workspace(workspace_data) if workspace_data.change == WorkspaceChange::Empty => {
+            let Some(name) = workspace_data
+                .current
+                .as_ref()
+                .and_then(|node| node.name.as_deref())
+            else {
+                return;
+            };
+
+            for component in components {
+                component.on_workspace_emptied(name);
+            }
+        }
+
+        _ => {}
+    }
+}
+
+/// Describe the size of each state component (one per line).
+pub fn dump(components: &[&dyn StateComponent]) -> String {
+    components
+        .iter()
+        .map(|component| format!("{}: {}", component.name(), component.describe()))
+        .collect::<Vec<_>>()
+        .join("\n")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::fixtures;
+    use i3_ipc::event::WindowData;
+    use i3_ipc::event::WorkspaceData;
+
+    /// A component recording its notifications.
+    #[derive(Default)]
+    struct Recorder {
+        closed: Vec<usize>,
+        emptied: Vec<String>,
+    }
+
+    impl StateComponent for Recorder {
+        fn name(&self) -> &'static str {
+            "recorder"
+        }
+
+        fn entries(&self) -> usize {
+            self.closed.len() + self.emptied.len()
+        }
+
+        fn on_window_closed(&mut self, node_id: usize) {
+            self.closed.push(node_id);
+        }
+
+        fn on_workspace_emptied(&mut self, workspace: &str) {
+            self.emptied.push(workspace.to_string());
+        }
+    }
+
+    fn window_event(change: WindowChange, node_id: usize) -> Event {
+        let root_node = fixtures::load("workspaces.json");
+
+        Event::Window(Box::new(WindowData {
+            change,
+            container: fixtures::node(&root_node, node_id).clone(),
+        }))
+    }
+
+    fn workspace_event(change: WorkspaceChange, workspace_id: usize) -> Event {
+        let root_node = fixtures::load("workspaces.json");
+
+        Event::Workspace(Box::new(WorkspaceData {
+            change,
+            current: Some(fixtures::node(&root_node, workspace_id).clone()),
+            old: None,
+        }))
+    }
+
+    #[test]
+    fn gc_routes_closes_and_empties_to_every_component() {
+        let mut first = Recorder::default();
+        let mut second = Recorder::default();
+
+        for event in [
+            window_event(WindowChange::Close, 101),
+            workspace_event(WorkspaceChange::Empty, 400),
+        ] {
+            gc(&event, &mut [&mut first, &mut second]);
+        }
+
+        for recorder in [first, second] {
+            assert_eq!(recorder.closed, [101]);
+            assert_eq!(recorder.emptied, ["web"]);
+        }
+    }
+
+    #[test]
+    fn gc_ignores_other_events() {
+        let mut recorder = Recorder::default();
+
+        for event in [
+            window_event(WindowChange::New, 101),
+            window_event(WindowChange::Focus, 101),
+            workspace_event(WorkspaceChange::Focus, 100),
+            workspace_event(WorkspaceChange::Init, 600),
+            Event::Workspace(Box::new(WorkspaceData {
+                change: WorkspaceChange::Empty,
+                current: None,
+                old: None,
+            })),
+        ] {
+            gc(&event, &mut [&mut recorder]);
+        }
+
+        assert_eq!(recorder.entries(), 0);
+    }
+
+    #[test]
+    fn dump_one_line_per_component() {
+        let mut recorder = Recorder::default();
+        gc(
+            &window_event(WindowChange::Close, 101),
+            &mut [&mut recorder],
+        );
+
+        assert_eq!(dump(&[]), "");
+        assert_eq!(
+            dump(&[&recorder, &Recorder::default()]),
+            "recorder: 1\nrecorder: 0"
+        );
+    }
+}
@@ -0,0 +1,592 @@
+/*
+    Copyright (C) 2022  Biagio Festa
+
+    This program is free software: you can redistribute it and/or modify
+    it under the terms of the GNU General Public License as published by
+    the Free Software Foundation, either version 3 of the License, or
+    (at your option) any later version.
+
+    This program is distributed in the hope that it will be useful,
+    but WITHOUT ANY WARRANTY; without even the implied warranty of
+    MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+    GNU General Public License for more details.
+
+    You should have received a copy of the GNU General Public License
+    along with this program.  If not, see <https://www.gnu.org/licenses/>.
+*/
+
+use crate::atomic_file::AtomicFile;
+use crate::utilities::format_age;
+use crate::utilities::runtime_dir;
+use crate::utilities::state_dir;
+use anyhow::anyhow;
+use anyhow::Context;
+use anyhow::Result;
+use serde::de::DeserializeOwned;
+use serde::Serialize;
+use std::fs::OpenOptions;
+use std::io::ErrorKind;
+use std::io::Write;
+use std::path::Path;
+use std::path::PathBuf;
+use std::time::Duration;
+use std::time::SystemTime;
+use std::time::UNIX_EPOCH;
+
+/// A state file as written: its content together with the schema version.
+#[derive(serde::Serialize)]
+struct VersionedRef<'a, T> {
+    version: u32,
+
+    #[serde(flatten)]
+    data: &'a T,
+}
+
+/// A state file as read.
+#[derive(serde::Deserialize)]
+struct Versioned<T> {
+    /// Files written before versioning are of the first version.
+    #[serde(default = "first_version")]
+    version: u32,
+
+    #[serde(flatten)]
+    data: T,
+}
+
+fn first_version() -> u32 {
+    1
+}
+
+/// A file (or directory) of the state directory (see `StateStore::entries`).
+pub struct StateEntry {
+    /// The path, relative to the state directory.
+    pub name: PathBuf,
+
+    /// The size (bytes) of a file.
+    pub size: Option<u64>,
+
+    /// How long ago the entry has been modified.
+    pub age: Duration,
+
+    /// The schema version of a JSON state file, if readable.
+    pub version: Option<u32>,
+}
+
+impl StateEntry {
+    /// A one-line description (e.g., `split-memory.json (312 bytes, version 1, 3h ago)`).
+    pub fn describe(&self) -> String {
+        let mut details = vec![];
+
+        match self.size {
+            Some(size) => details.push(format!("{} bytes", size)),
+            None => details.push("directory".to_string()),
+        }
+
+        if let Some(version) = self.version {
+            details.push(format!("version {}", version));
+        }
+
+        details.push(format!("{} ago", format_age(self.age)));
+
+        format!("{} ({})", self.name.display(), details.join(", "))
+    }
+}
+
+/// The store of the state of the service.
+///
+/// It owns the state directory (`$XDG_STATE_HOME/i3-autolayout`). Its JSON files are
+/// tagged with the version of their schema and written atomically, one writer at a time
+/// (`<FILE>.lock`). A file which cannot be read (e.g., truncated, or of another version)
+/// is moved into `corrupt/` on load, so that the service starts anyway.
+pub struct StateStore {
+    dir: PathBuf,
+}
+
+impl StateStore {
+    const CORRUPT_DIR_NAME: &'static str = "corrupt";
+    const LOCK_SUFFIX: &'static str = ".lock";
+    const TMP_SUFFIX: &'static str = ".tmp";
+
+    /// How many times the lock of a file is tried, waiting `LOCK_RETRY` in between.
+    const LOCK_ATTEMPTS: u32 = 50;
+    const LOCK_RETRY: Duration = Duration::from_millis(10);
+
+    /// A lock older than this is left by a crashed writer.
+    const LOCK_STALE: Duration = Duration::from_secs(10);
+
+    /// Open the store.
+    pub fn open() -> Result<Self> {
+        Ok(Self::in_dir(state_dir()?))
+    }
+
+    /// Open the store of the runtime (non persistent) state (see `runtime_dir`).
+    pub fn runtime() -> Result<Self> {
+        Ok(Self::in_dir(runtime_dir()?))
+    }
+
+    /// Open the store in a directory other than the state directory (e.g., for tests).
+    pub fn in_dir(dir: PathBuf) -> Self {
+        Self { dir }
+    }
+
+    /// The store of a subdirectory (e.g., `layouts`), created if it does not exist.
+    pub fn subdir(&self, name: &str) -> Result<Self> {
+        let dir = self.dir.join(name);
+
+        std::fs::create_dir_all(&dir)
+            .with_context(|| format!("Cannot create the directory '{}'", dir.display()))?;
+
+        Ok(Self::in_dir(dir))
+    }
+
+    /// The directory of the store.
+    pub fn dir(&self) -> &Path {
+        &self.dir
+    }
+
+    /// The path of a state file.
+    pub fn path(&self, file_name: &str) -> PathBuf {
+        self.dir.join(file_name)
+    }
+
+    /// Load the state file `file_name`, of schema `version`.
+    ///
+    /// A missing file means the default state. An unreadable one is quarantined with a
+    /// warning (see `quarantine`), and the default state is used.
+    pub fn load<T>(&self, file_name: &str, version: u32) -> T
+    where
+        T: DeserializeOwned + Default,
+    {
+        let path = self.path(file_name);
+
+        let content = match std::fs::read_to_string(&path) {
+            Ok(content) => content,
+            Err(error) if error.kind() == ErrorKind::NotFound => return T::default(),
+            Err(error) => {
+                eprintln!(
+                    "[WARN]: Cannot read the state file '{}': {}",
+                    path.display(),
+                    error
+                );
+                return T::default();
+            }
+        };
+
+        let parsed = serde_json::from_str::<Versioned<T>>(&content)
+            .map_err(anyhow::Error::from)
+            .and_then(|versioned| {
+                if versioned.version == version {
+                    Ok(versioned.data)
+                } else {
+                    Err(anyhow!(
+                        "version {} instead of {}",
+                        versioned.version,
+                        version
+                    ))
+                }
+            });
+
+        parsed.unwrap_or_else(|error| {
+            self.quarantine(file_name, &format!("{:#}", error));
+            T::default()
+        })
+    }
+
+    /// Write the state file `file_name`, of schema `version`.
+    pub fn store<T>(&self, file_name: &str, version: u32, data: &T) -> Result<()>
+    where
+        T: Serialize,
+    {
+        self.write(file_name, false, |output| {
+            serde_json::to_writer(output, &VersionedRef { version, data })
+                .context("Cannot serialize the state file")
+        })
+    }
+
+    /// Write the file `file_name` with `write`, atomically and holding its lock (the
+    /// content is not versioned, e.g., binary layouts).
+    ///
+    /// With `keep_backup` the previous version of the file is kept (see `AtomicFile`).
+    pub fn write<F>(&self, file_name: &str, keep_backup: bool, write: F) -> Result<()>
+    where
+        F: FnOnce(&mut AtomicFile) -> Result<()>,
+    {
+        let path = self.path(file_name);
+        let _lock = StateLock::acquire(&self.path(&format!("{}{}", file_name, Self::LOCK_SUFFIX)))?;
+
+        let mut output = AtomicFile::create(&path, keep_backup)
+            .with_context(|| format!("Cannot write the state file '{}'", path.display()))?;
+
+        write(&mut output)
+            .and_then(|()| output.commit())
+            .with_context(|| format!("Cannot write the state file '{}'", path.display()))
+    }
+
+    /// Move an unreadable state file into `corrupt/` (as `<FILE>.<UNIX-TIME>`), with a warning.
+    fn quarantine(&self, file_name: &str, reason: &str) {
+        let corrupt_dir = self.dir.join(Self::CORRUPT_DIR_NAME);
+        let timestamp = SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .unwrap_or_default()
+            .as_secs();
+        let destination = corrupt_dir.join(format!("{}.{}", file_name, timestamp));
+
+        let moved = std::fs::create_dir_all(&corrupt_dir)
+            .and_then(|()| std::fs::rename(self.path(file_name), &destination));
+
+        match moved {
+            Ok(()) => eprintln!(
+                "[WARN]: The state file '{}' is unreadable ({}): moved to '{}'",
+                file_name,
+                reason,
+                destination.display()
+            ),
+            Err(error) => eprintln!(
+                "[WARN]: The state file '{}' is unreadable ({}) and cannot be moved: {}",
+                file_name, reason, error
+            ),
+        }
+    }
+
+    /// The files and directories of the state directory, quarantined files included,
+    /// sorted by name.
+    pub fn entries(&self) -> Result<Vec<StateEntry>> {
+        let now = SystemTime::now();
+        let mut entries = vec![];
+
+        for dir in [self.dir.clone(), self.dir.join(Self::CORRUPT_DIR_NAME)] {
+            if !dir.exists() {
+                continue;
+            }
+
+            let dir_entries = std::fs::read_dir(&dir)
+                .with_context(|| format!("Cannot read the directory '{}'", dir.display()))?;
+
+            for dir_entry in dir_entries.flatten() {
+                let path = dir_entry.path();
+                let Ok(metadata) = dir_entry.metadata() else {
+                    continue;
+                };
+
+                let version = (path
+                    .extension()
+                    .is_some_and(|extension| extension == "json"))
+                .then(|| std::fs::read_to_string(&path).ok())
+                .flatten()
+                .and_then(|content| {
+                    serde_json::from_str::<Versioned<serde_json::Value>>(&content).ok()
+                })
+                .map(|versioned| versioned.version);
+
+                entries.push(StateEntry {
+                    name: path.strip_prefix(&self.dir).unwrap_or(&path).to_path_buf(),
+                    size: metadata.is_file().then_some(metadata.len()),
+                    age: metadata
+                        .modified()
+                        .ok()
+                        .and_then(|modified| now.duration_since(modified).ok())
+                        .unwrap_or_default(),
+                    version,
+                });
+            }
+        }
+
+        entries.sort_by(|a, b| a.name.cmp(&b.name));
+        Ok(entries)
+    }
+
+    /// Remove the quarantined files and the leftovers of crashed writers (temporary files
+    /// and stale locks).
+    ///
+    /// It returns the removed files.
+    pub fn prune(&self) -> Result<Vec<PathBuf>> {
+        let mut removed = vec![];
+
+        for entry in self.entries()? {
+            let name = entry.name.to_string_lossy();
+
+            let prunable = (entry.name.starts_with(Self::CORRUPT_DIR_NAME) && entry.size.is_some())
+                || name.ends_with(Self::TMP_SUFFIX)
+                || (name.ends_with(Self::LOCK_SUFFIX) && entry.age > Self::LOCK_STALE);
+
+            if prunable {
+                let path = self.dir.join(&entry.name);
+
+                std::fs::remove_file(&path)
+                    .with_context(|| format!("Cannot remove the file '{}'", path.display()))?;
+
+                removed.push(path);
+            }
+        }
+
+        Ok(removed)
+    }
+}
+
+/// The lock of a state file: only its holder writes the file.
+///
+/// The lock file holds the process id of the holder. It is removed on drop.
+struct StateLock {
+    path: PathBuf,
+}
+
+impl StateLock {
+    /// Acquire the lock `path`, waiting for the current holder (if any).
+    ///
+    /// A lock older than `StateStore::LOCK_STALE` is taken over.
+    fn acquire(path: &Path) -> Result<Self> {
+        for _ in 0..StateStore::LOCK_ATTEMPTS {
+            match OpenOptions::new().write(true).create_new(true).open(path) {
+                Ok(mut file) => {
+                    let _ = write!(file, "{}", std::process::id());
+
+                    return Ok(Self {
+                        path: path.to_path_buf(),
+                    });
+                }
+
+                Err(error) if error.kind() == ErrorKind::AlreadyExists => {
+                    let stale = std::fs::metadata(path)
+                        .and_then(|metadata| metadata.modified())
+                        .ok()
+                        .and_then(|modified| modified.elapsed().ok())
+                        .is_some_and(|age| age > StateStore::LOCK_STALE);
+
+                    if stale {
+                        eprintln!("[WARN]: Taking over the stale lock '{}'", path.display());
+                        let _ = std::fs::remove_file(path);
+                    } else {
+                        std::thread::sleep(StateStore::LOCK_RETRY);
+                    }
+                }
+
+                Err(error) => {
+                    return Err(error)
+                        .with_context(|| format!("Cannot create the lock '{}'", path.display()))
+                }
+            }
+        }
+
+        Err(anyhow!(
+            "The lock '{}' is held by another writer",
+            path.display()
+        ))
+    }
+}
+
+impl Drop for StateLock {
+    fn drop(&mut self) {
+        let _ = std::fs::remove_file(&self.path);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::fixtures::TempDir;
+    use std::fs::File;
+
+    const FILE_NAME: &str = "counter.json";
+
+    #[derive(Debug, Default, PartialEq, serde::Deserialize, serde::Serialize)]
+    struct Counter {
+        count: u32,
+    }
+
+    fn store_in(dir: &TempDir) -> StateStore {
+        StateStore::in_dir(dir.path().to_path_buf())
+    }
+
+    fn names(store: &StateStore) -> Vec<String> {
+        store
+            .entries()
+            .unwrap()
+            .into_iter()
+            .map(|entry| entry.name.to_string_lossy().into_owned())
+            .collect()
+    }
+
+    /// Make a file look modified `age` ago.
+    fn age_file(path: &Path, age: Duration) {
+        File::options()
+            .write(true)
+            .open(path)
+            .unwrap()
+            .set_modified(SystemTime::now() - age)
+            .unwrap();
+    }
+
+    #[test]
+    fn store_and_load() {
+        let dir = TempDir::new("state-store");
+        let store = store_in(&dir);
+
+        store.store(FILE_NAME, 1, &Counter { count: 3 }).unwrap();
+
+        assert_eq!(store.load::<Counter>(FILE_NAME, 1), Counter { count: 3 });
+        assert_eq!(
+            std::fs::read_to_string(store.path(FILE_NAME)).unwrap(),
+            r#"{"version":1,"count":3}"#
+        );
+    }
+
+    #[test]
+    fn store_replaces_atomically() {
+        let dir = TempDir::new("state-store");
+        let store = store_in(&dir);
+
+        store.store(FILE_NAME, 1, &Counter { count: 1 }).unwrap();
+        store.store(FILE_NAME, 1, &Counter { count: 2 }).unwrap();
+
+        assert_eq!(store.load::<Counter>(FILE_NAME, 1), Counter { count: 2 });
+
+        // Neither the temporary file nor the lock is left behind.
+        assert_eq!(names(&store), [FILE_NAME]);
+    }
+
+    #[test]
+    fn load_missing_file_is_default() {
+        let dir = TempDir::new("state-store");
+        let store = store_in(&dir);
+
+        assert_eq!(store.load::<Counter>(FILE_NAME, 1), Counter::default());
+        assert!(names(&store).is_empty());
+    }
+
+    #[test]
+    fn load_unversioned_file_is_first_version() {
+        let dir = TempDir::new("state-store");
+        let store = store_in(&dir);
+
+        std::fs::write(store.path(FILE_NAME), r#"{"count":7}"#).unwrap();
+
+        assert_eq!(store.load::<Counter>(FILE_NAME, 1), Counter { count: 7 });
+        assert_eq!(names(&store), [FILE_NAME]);
+
+        // Read by a newer schema, it is quarantined.
+        assert_eq!(store.load::<Counter>(FILE_NAME, 2), Counter::default());
+        assert!(!store.path(FILE_NAME).exists());
+    }
+
+    #[test]
+    fn load_quarantines_corrupt_file() {
+        let dir = TempDir::new("state-store");
+        let store = store_in(&dir);
+
+        std::fs::write(store.path(FILE_NAME), r#"{"version":1,"cou"#).unwrap();
+
+        assert_eq!(store.load::<Counter>(FILE_NAME, 1), Counter::default());
+        assert!(!store.path(FILE_NAME).exists());
+
+        let names = names(&store);
+        assert_eq!(names.len(), 2);
+        assert_eq!(names[0], "corrupt");
+        assert!(names[1].starts_with("corrupt/counter.json."));
+
+        // The next store starts afresh.
+        store.store(FILE_NAME, 1, &Counter { count: 1 }).unwrap();
+        assert_eq!(store.load::<Counter>(FILE_NAME, 1), Counter { count: 1 });
+    }
+
+    #[test]
+    fn load_quarantines_future_version() {
+        let dir = TempDir::new("state-store");
+        let store = store_in(&dir);
+
+        store.store(FILE_NAME, 3, &Counter { count: 5 }).unwrap();
+
+        assert_eq!(store.load::<Counter>(FILE_NAME, 1), Counter::default());
+        assert!(!store.path(FILE_NAME).exists());
+
+        // The file is kept as it was, for a newer version to read it.
+        let quarantined = store
+            .entries()
+            .unwrap()
+            .into_iter()
+            .find(|entry| entry.name.starts_with("corrupt") && entry.size.is_some())
+            .unwrap();
+        assert_eq!(
+            std::fs::read_to_string(store.path(&quarantined.name.to_string_lossy())).unwrap(),
+            r#"{"version":3,"count":5}"#
+        );
+    }
+
+    #[test]
+    fn store_waits_for_the_lock() {
+        let dir = TempDir::new("state-store");
+        let store = store_in(&dir);
+        let lock_path = store.path("counter.json.lock");
+
+        let lock = StateLock::acquire(&lock_path).unwrap();
+        assert_eq!(
+            std::fs::read_to_string(&lock_path).unwrap(),
+            std::process::id().to_string()
+        );
+
+        let error = store
+            .store(FILE_NAME, 1, &Counter { count: 1 })
+            .unwrap_err();
+        assert!(error.to_string().contains("held by another writer"));
+        assert!(!store.path(FILE_NAME).exists());
+
+        drop(lock);
+        assert!(!lock_path.exists());
+
+        store.store(FILE_NAME, 1, &Counter { count: 1 }).unwrap();
+    }
+
+    #[test]
+    fn store_takes_over_stale_lock() {
+        let dir = TempDir::new("state-store");
+        let store = store_in(&dir);
+        let lock_path = store.path("counter.json.lock");
+
+        std::fs::write(&lock_path, "1").unwrap();
+        age_file(&lock_path, Duration::from_secs(60));
+
+        store.store(FILE_NAME, 1, &Counter { count: 1 }).unwrap();
+        assert!(!lock_path.exists());
+    }
+
+    #[test]
+    fn failed_write_keeps_previous_content() {
+        let dir = TempDir::new("state-store");
+        let store = store_in(&dir).subdir("layouts").unwrap();
+
+        store
+            .write("work.layout", false, |output| Ok(output.write_all(b"old")?))
+            .unwrap();
+
+        let error = store
+            .write("work.layout", false, |output| {
+                output.write_all(b"partial")?;
+                Err(anyhow!("interrupted"))
+            })
+            .unwrap_err();
+        assert!(format!("{:#}", error).contains("interrupted"));
+
+        assert_eq!(std::fs::read(store.path("work.layout")).unwrap(), b"old");
+        assert_eq!(names(&store), ["work.layout"]);
+        assert_eq!(names(&store_in(&dir)), ["layouts"]);
+    }
+
+    #[test]
+    fn prune_removes_leftovers_only() {
+        let dir = TempDir::new("state-store");
+        let store = store_in(&dir);
+
+        store.store(FILE_NAME, 1, &Counter { count: 1 }).unwrap();
+        std::fs::write(store.path("broken.json"), "{").unwrap();
+        store.load::<Counter>("broken.json", 1);
+
+        std::fs::write(store.path("other.json.tmp"), "{").unwrap();
+        std::fs::write(store.path("fresh.json.lock"), "1").unwrap();
+        std::fs::write(store.path("stale.json.lock"), "1").unwrap();
+        age_file(&store.path("stale.json.lock"), Duration::from_secs(60));
+
+        let removed = store.prune().unwrap();
+        assert_eq!(removed.len(), 3);
+
+        assert_eq!(names(&store), ["corrupt", FILE_NAME, "fresh.json.lock"]);
+    }
+}
@@ -0,0 +1,229 @@
+/*
+    Copyright (C) 2022  Biagio Festa
+
+    This program is free software: you can redistribute it and/or modify
+    it under the terms of the GNU General Public License as published by
+    the Free Software Foundation, either version 3 of the License, or
+    (at your option) any later version.
+
+    This program is distributed in the hope that it will be useful,
+    but WITHOUT ANY WARRANTY; without even the implied warranty of
+    MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+    GNU General Public License for more details.
+
+    You should have received a copy of the GNU General Public License
+    along with this program.  If not, see <https://www.gnu.org/licenses/>.
+*/
+
+use crate::command_executor::IpcTimings;
+use std::fmt::Write;
+use std::time::Duration;
+
+/// The upper bounds (microseconds) of the buckets of a histogram, the last one excluded
+/// (unbounded).
+const BUCKET_BOUNDS_US: [u64; 8] = [250, 500, 1_000, 2_500, 5_000, 10_000, 25_000, 100_000];
+
+/// A histogram of durations with fixed buckets (see `BUCKET_BOUNDS_US`).
+#[derive(Default)]
+pub struct Histogram {
+    counts: [u64; BUCKET_BOUNDS_US.len() + 1],
+    total: Duration,
+    max: Duration,
+}
+
+impl Histogram {
+    /// Record a duration.
+    pub fn record(&mut self, duration: Duration) {
+        let micros = u64::try_from(duration.as_micros()).unwrap_or(u64::MAX);
+        let bucket = BUCKET_BOUNDS_US
+            .iter()
+            .position(|&bound| micros <= bound)
+            .unwrap_or(BUCKET_BOUNDS_US.len());
+
+        self.counts[bucket] += 1;
+        self.total += duration;
+        self.max = self.max.max(duration);
+    }
+
+    /// The number of recorded durations.
+    pub fn count(&self) -> u64 {
+        self.counts.iter().sum()
+    }
+
+    /// The mean of the recorded durations.
+    pub fn mean(&self) -> Duration {
+        match u32::try_from(self.count()) {
+            Ok(0) => Duration::ZERO,
+            Ok(count) => self.total / count,
+            Err(_) => Duration::from_secs_f64(self.total.as_secs_f64() / self.count() as f64),
+        }
+    }
+
+    /// A one-line description (e.g., `mean 1.2ms, max 8.0ms; <=250us: 3, <=500us: 1`),
+    /// skipping empty buckets.
+    pub fn describe(&self) -> String {
+        let mut text = format!("mean {:.1?}, max {:.1?}", self.mean(), self.max);
+        let mut separator = "; ";
+
+        for (index, &count) in self.counts.iter().enumerate() {
+            if count == 0 {
+                continue;
+            }
+
+            let label = match BUCKET_BOUNDS_US.get(index) {
+                Some(bound) => format!("<={:?}", Duration::from_micros(*bound)),
+                None => format!(">{:?}", Duration::from_micros(BUCKET_BOUNDS_US[index - 1])),
+            };
+
+            let _ = write!(text, "{}{}: {}", separator, label, count);
+            separator = ", ";
+        }
+
+        text
+    }
+}
+
+/// How the service dealt with an event.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum EventOutcome {
+    /// The event has been handled.
+    Handled,
+
+    /// The event is of no interest to the service.
+    Skipped,
+
+    /// Handling the event failed (a warning has been printed).
+    Failed,
+}
+
+/// The time spent by the service on the events it handled.
+///
+/// The time of each event is split into tree (and other) queries, command execution and
+/// decision logic (the rest). Recording does not allocate.
+#[derive(Default)]
+pub struct EventStats {
+    handled: u64,
+    skipped: u64,
+    failed: u64,
+    total: Histogram,
+    queries: Histogram,
+    commands: Histogram,
+    decisions: Histogram,
+}
+
+impl EventStats {
+    /// Record an event which took `elapsed`, `ipc` of which talking with i3.
+    ///
+    /// Skipped events are only counted.
+    pub fn record(&mut self, outcome: EventOutcome, elapsed: Duration, ipc: IpcTimings) {
+        match outcome {
+            EventOutcome::Handled => self.handled += 1,
+            EventOutcome::Skipped => {
+                self.skipped += 1;
+                return;
+            }
+            EventOutcome::Failed => self.failed += 1,
+        }
+
+        self.total.record(elapsed);
+        self.queries.record(ipc.query);
+        self.commands.record(ipc.command);
+        self.decisions
+            .record(elapsed.saturating_sub(ipc.query + ipc.command));
+    }
+
+    /// A one-line description of the counters (e.g., `3 handled, 1 skipped, 0 failed`).
+    pub fn counters(&self) -> String {
+        format!(
+            "{} handled, {} skipped, {} failed",
+            self.handled, self.skipped, self.failed
+        )
+    }
+
+    /// A multi-line description of the counters and histograms.
+    pub fn describe(&self) -> String {
+        format!(
+            "events: {}\n\
+             total: {}\n\
+             queries: {}\n\
+             commands: {}\n\
+             decisions: {}",
+            self.counters(),
+            self.total.describe(),
+            self.queries.describe(),
+            self.commands.describe(),
+            self.decisions.describe()
+        )
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn micros(micros: u64) -> Duration {
+        Duration::from_micros(micros)
+    }
+
+    #[test]
+    fn histogram_buckets() {
+        let mut histogram = Histogram::default();
+        assert_eq!(histogram.count(), 0);
+        assert_eq!(histogram.mean(), Duration::ZERO);
+        assert_eq!(histogram.describe(), "mean 0.0ns, max 0.0ns");
+
+        // Bounds are inclusive.
+        for duration in [0, 250, 251, 100_000, 100_001, 5_000_000] {
+            histogram.record(micros(duration));
+        }
+
+        assert_eq!(histogram.count(), 6);
+        assert_eq!(histogram.counts, [2, 1, 0, 0, 0, 0, 0, 1, 2]);
+        assert_eq!(histogram.max, Duration::from_secs(5));
+        assert_eq!(histogram.mean(), micros(5_200_502) / 6);
+        assert_eq!(
+            histogram.describe(),
+            "mean 866.8ms, max 5.0s; <=250µs: 2, <=500µs: 1, <=100ms: 1, >100ms: 2"
+        );
+    }
+
+    #[test]
+    fn events_split_by_part() {
+        let mut stats = EventStats::default();
+
+        stats.record(
+            EventOutcome::Handled,
+            micros(3_000),
+            IpcTimings {
+                query: micros(1_000),
+                command: micros(1_500),
+            },
+        );
+        stats.record(EventOutcome::Skipped, micros(50), IpcTimings::default());
+        stats.record(
+            EventOutcome::Failed,
+            micros(400),
+            IpcTimings {
+                query: micros(600),
+                command: Duration::ZERO,
+            },
+        );
+
+        assert_eq!((stats.handled, stats.skipped, stats.failed), (1, 1, 1));
+        assert_eq!(stats.counters(), "1 handled, 1 skipped, 1 failed");
+
+        // Skipped events are not timed.
+        assert_eq!(stats.total.count(), 2);
+        assert_eq!(stats.total.total, micros(3_400));
+        assert_eq!(stats.queries.total, micros(1_600));
+        assert_eq!(stats.commands.total, micros(1_500));
+
+        // The decisions are the rest (never negative, despite clock skews).
+        assert_eq!(stats.decisions.total, micros(500));
+        assert_eq!(stats.decisions.counts[0], 1);
+
+        assert!(stats
+            .describe()
+            .starts_with("events: 1 handled, 1 skipped, 1 failed\ntotal: mean 1.7ms"));
+    }
+}
@@ -0,0 +1,122 @@
+/*
+    Copyright (C) 2022  Biagio Festa
+
+    This program is free software: you can redistribute it and/or modify
+    it under the terms of the GNU General Public License as published by
+    the Free Software Foundation, either version 3 of the License, or
+    (at your option) any later version.
+
+    This program is distributed in the hope that it will be useful,
+    but WITHOUT ANY WARRANTY; without even the implied warranty of
+    MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+    GNU General Public License for more details.
+
+    You should have received a copy of the GNU General Public License
+    along with this program.  If not, see <https://www.gnu.org/licenses/>.
+*/
+
+use std::fmt::Write;
+use std::time::Duration;
+
+/// The status of the autolayout service, as replied to `ctl status`.
+#[derive(Default)]
+pub struct ServiceStatus {
+    /// The process id of the service.
+    pub pid: u32,
+
+    /// How long the service has been running.
+    pub uptime: Duration,
+
+    /// The counters of the events (see `EventStats::counters`).
+    pub events: String,
+
+    /// The windows whose split keeps failing, with their failures and cooldown.
+    pub backoffs: String,
+
+    /// The workspaces handed back to manual control (see `ctl manage`).
+    pub unmanaged: Vec<String>,
+
+    /// The description of the time spent on events (`--verbose` only, see `EventStats`).
+    pub stats: Option<String>,
+
+    /// The description of the state held by the service (`--verbose` only, see
+    /// `state::dump`).
+    pub state: Option<String>,
+}
+
+impl ServiceStatus {
+    /// A multi-line description, the verbose sections last.
+    pub fn describe(&self) -> String {
+        let unmanaged = if self.unmanaged.is_empty() {
+            "none".to_string()
+        } else {
+            self.unmanaged
+                .iter()
+                .map(|workspace| format!("'{}'", workspace))
+                .collect::<Vec<_>>()
+                .join(", ")
+        };
+
+        let mut text = format!(
+            "pid: {}\nuptime: {}s\nevents: {}\nbackoffs: {}\nunmanaged workspaces: {}",
+            self.pid,
+            self.uptime.as_secs(),
+            self.events,
+            self.backoffs,
+            unmanaged
+        );
+
+        for (title, section) in [("stats", &self.stats), ("state", &self.state)] {
+            if let Some(section) = section {
+                let _ = write!(text, "\n\n[{}]\n{}", title, section.trim_end());
+            }
+        }
+
+        text
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn counters_come_first() {
+        let status = ServiceStatus {
+            pid: 4242,
+            uptime: Duration::from_millis(61_500),
+            events: "3 handled, 1 skipped, 2 failed".to_string(),
+            backoffs: "1 [101 (2 failures)]".to_string(),
+            unmanaged: vec!["3: mail".to_string(), "web".to_string()],
+            ..ServiceStatus::default()
+        };
+        assert_eq!(
+            status.describe(),
+            "pid: 4242\n\
+             uptime: 61s\n\
+             events: 3 handled, 1 skipped, 2 failed\n\
+             backoffs: 1 [101 (2 failures)]\n\
+             unmanaged workspaces: '3: mail', 'web'"
+        );
+    }
+
+    #[test]
+    fn verbose_sections_come_last() {
+        let status = ServiceStatus {
+            pid: 4242,
+            uptime: Duration::from_millis(61_500),
+            events: "3 handled, 1 skipped, 0 failed".to_string(),
+            backoffs: "0".to_string(),
+            unmanaged: vec![],
+            stats: Some("events: 3 handled, 1 skipped, 0 failed\n".to_string()),
+            state: Some("backoffs: 0 entries".to_string()),
+        };
+        assert_eq!(
+            status.describe(),
+            "pid: 4242\nuptime: 61s\nevents: 3 handled, 1 skipped, 0 failed\nbackoffs: 0\n\
+             unmanaged workspaces: none\n\n\
+             [stats]\nevents: 3 handled, 1 skipped, 0 failed\n\n\
+             [state]\nbackoffs: 0 entries"
+        );
+    }
+}
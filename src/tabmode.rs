@@ -15,21 +15,91 @@
     along with this program.  If not, see <https://www.gnu.org/licenses/>.
 */
 
+use crate::atomic_file::backup_path;
+use crate::atomic_file::AtomicFile;
 use crate::command_executor::CommandExecutor;
 use crate::command_executor::I3Node;
+use crate::command_executor::RootNode;
+use crate::control::SuspendGuard;
+use crate::marks::tabmode_mark;
+use crate::marks::TABMODE_MARK_PREFIX;
+use crate::marks::TABMODE_NATIVE_MARK_PREFIX;
+use crate::normalize::normalize_container;
+use crate::normalize::normalize_workspace;
+use crate::normalize::report_normalize_failures;
 use crate::restore_layout::RestoreLayout;
 use crate::save_layout::SaveLayout;
-use crate::utilities::find_workspace_by_num;
-use crate::utilities::query_workspace_focused;
+use crate::save_layout::SavedLayout;
+use crate::undo::record_undo;
+use crate::utilities::apply_marks;
+use crate::utilities::find_node_by_id;
+use crate::utilities::find_workspaces;
+use crate::utilities::is_window;
+use crate::utilities::node_marks;
+use crate::utilities::quote;
+use crate::utilities::quote_ws_name;
 use crate::utilities::set_node_layout;
+use crate::utilities::BorderStyle;
 use crate::utilities::Layout;
+use crate::workspace::WorkspaceRef;
 use anyhow::anyhow;
 use anyhow::Context;
 use anyhow::Result;
 use i3_ipc::reply::NodeLayout;
-use i3_ipc::reply::NodeType;
-use std::fs::File;
+use std::io::IsTerminal;
 use std::path::Path;
+use std::path::PathBuf;
+
+/// Options of the TabMode executor.
+#[derive(Clone)]
+pub struct TabModeOptions {
+    /// The file where to save/load the layout.
+    ///
+    /// If it ends with `/` it is a directory holding one layout file per workspace.
+    /// The layout file is written atomically.
+    pub file_layout: Option<PathBuf>,
+
+    /// Keep the previous version of the layout file.
+    pub keep_backup: bool,
+
+    /// Restore sizes of windows too when leaving tab-mode.
+    pub restore_sizes: bool,
+
+    /// The title format of the tabs.
+    pub title_format: Option<String>,
+
+    /// Reset the title format (`%title`) when leaving tab-mode.
+    pub reset_title_format: bool,
+
+    /// The border style of windows in tab-mode.
+    pub border_tabbed: Option<BorderStyle>,
+
+    /// The border style of windows when leaving tab-mode.
+    ///
+    /// When the layout is restored from file, each window gets its saved style instead.
+    pub border_split: Option<BorderStyle>,
+
+    /// The suffix appended to the workspace name while in tab-mode (e.g., `[]`).
+    ///
+    /// It makes tab-mode visible on the workspace buttons of i3bar.
+    pub indicator_suffix: Option<String>,
+
+    /// Save/load the layout as JSON.
+    pub json: bool,
+
+    /// Enter (save) or leave (restore) tab-mode regardless of the current state.
+    pub direction: Option<TabModeDirection>,
+}
+
+/// The direction of a tab-mode toggle.
+#[derive(Clone, Copy, PartialEq, Eq, clap::ValueEnum)]
+pub enum TabModeDirection {
+    /// Save the layout and enter tab-mode.
+    Save,
+
+    /// Restore the layout and leave tab-mode.
+    Restore,
+}
 
 /// TabMode executor.
 ///
@@ -43,10 +113,12 @@ pub struct TabMode {
 }
 
 impl TabMode {
-    /// A temporary mark for moving nodes.
-    const MARK_ID: &'static str = "__i3-autolayout__tmp_ID";
-    const SAVE_LAYOUT_JSON: bool = false;
-    const SAVE_LAYOUT_RESTORE_SIZE: bool = false;
+    const LAYOUT_FILE_PREFIX: &'static str = "tabmode-";
+    const LAYOUT_FILE_SUFFIX: &'static str = ".layout";
+    const DEFAULT_TITLE_FORMAT: &'static str = "%title";
+
+    /// The layout file meaning the standard input (restore) or output (save).
+    const STDIO_LAYOUT: &'static str = "-";
 
     /// A new tabmode executor.
     pub fn new(command_executor: CommandExecutor) -> Self {
@@ -58,102 +130,631 @@ impl TabMode {
     /// It normalizes a workspace and displays all nodes it a tabbed layout.
     /// It can be toggled: if the workspace is already in tab-mode it will restore the default layout.
     ///
-    /// The action will be appliced on a specific workspace number (argument).
-    /// If `workspace_num` is `None` the currently focused workspace will be used.
-    pub fn execute(mut self, workspace_num: Option<i32>, file_layout: Option<&Path>) -> Result<()> {
+    /// The action will be appliced on a specific workspace (argument).
+    pub fn execute(mut self, workspace: WorkspaceRef, options: TabModeOptions) -> Result<()> {
         let root_node = self.command_executor.query_root_node()?;
 
-        let workspace = match workspace_num {
-            Some(workspace_num) => find_workspace_by_num(&root_node, workspace_num)
-                .ok_or_else(|| anyhow!("Cannot find the workspace number '{}'", workspace_num))?,
-            None => query_workspace_focused(&root_node, &mut self.command_executor)?,
+        let workspace = workspace.resolve(&root_node, &mut self.command_executor)?;
+        let _suspend_guard = SuspendGuard::acquire(
+            &self.command_executor,
+            workspace.name.as_deref().unwrap_or_default(),
+        )?;
+        record_undo(&[workspace]);
+
+        let indicator_suffix = options.indicator_suffix.as_deref();
+        let current_name = workspace.name.as_deref().unwrap_or_default();
+
+        let stdio = options.file_layout.as_deref() == Some(Path::new(Self::STDIO_LAYOUT));
+
+        let file_layout = match options.file_layout.as_deref() {
+            Some(_) if stdio => None,
+            Some(file_layout) if Self::is_layout_dir(file_layout) => Some(
+                Self::layout_path_in_dir(file_layout, workspace, &root_node, indicator_suffix)
+                    .context("Cannot prepare the layout directory")?,
+            ),
+            Some(file_layout) => Some(file_layout.to_path_buf()),
+            None => None,
         };
-        let workspace_num = workspace.num.expect("Expected workspace have number");
+        let file_layout = file_layout.as_deref();
 
-        if Self::is_tabmode(workspace) {
-            if let Some(file_layout) = file_layout {
-                let file = File::open(file_layout).with_context(|| {
-                    format!("Cannot open the layout file '{}'", file_layout.display())
-                })?;
+        let leaving = match options.direction {
+            Some(direction) => direction == TabModeDirection::Restore,
+            None => Self::is_tabmode(workspace),
+        };
 
-                let restore_layout = RestoreLayout::new(self.command_executor);
+        if leaving {
+            // The layout is loaded first: without it, the workspace is left untouched.
+            let saved_layout = if stdio {
+                let stdin = std::io::stdin();
+                Self::refuse_binary_terminal(stdin.is_terminal(), options.json)?;
+
+                Some(
+                    SavedLayout::from_reader(stdin.lock(), options.json)
+                        .context("Cannot restore layout")?,
+                )
+            } else {
+                Self::load_layout(workspace, file_layout, options.json)?
+            };
+
+            // The tabbed container was there before tabmode: it is left in place.
+            let tabmode_mark = Self::tabmode_mark_of(workspace);
+            let native = tabmode_mark
+                .as_deref()
+                .is_some_and(|mark| mark.starts_with(TABMODE_NATIVE_MARK_PREFIX));
+
+            if options.title_format.is_some() && options.reset_title_format {
+                self.set_title_format(workspace, Self::DEFAULT_TITLE_FORMAT)
+                    .context("Cannot reset the title format of windows")?;
+            }
+
+            if let (None, false, Some(border)) = (file_layout, stdio, options.border_split) {
+                self.run_on_windows(workspace, format!("border {}", border))
+                    .context("Cannot set the border of windows")?;
+            }
+
+            let failures = if let Some(saved_layout) = saved_layout {
+                if let Some(suffix) = indicator_suffix {
+                    // Named workspaces are restored by name: rename back first.
+                    let original_name = saved_layout.root().name().unwrap_or(current_name);
+
+                    self.rename_workspace(
+                        workspace.id,
+                        current_name,
+                        &strip_indicator(original_name, suffix),
+                    )?;
+                }
+
+                let restore_layout =
+                    RestoreLayout::new(self.command_executor.share()).without_undo();
 
                 restore_layout
-                    .execute(file, Self::SAVE_LAYOUT_JSON, Self::SAVE_LAYOUT_RESTORE_SIZE)
-                    .context("Cannot restore layout")
+                    .execute_layout(saved_layout, options.restore_sizes)
+                    .context("Cannot restore layout")?;
+
+                vec![]
             } else {
-                self.normalize_workspace(workspace)
-                    .context("Cannot normalize the workspace for tabmode")?;
+                if let Some(suffix) = indicator_suffix {
+                    self.rename_workspace(
+                        workspace.id,
+                        current_name,
+                        &strip_indicator(current_name, suffix),
+                    )?;
+                }
+
+                if native {
+                    vec![]
+                } else {
+                    let marks = Self::window_marks(workspace);
 
-                set_node_layout(workspace.id, Layout::Default, &mut self.command_executor)
-                    .context("Cannot set default layout for workspace")
+                    let failures = normalize_workspace(workspace, &mut self.command_executor)
+                        .context("Cannot normalize the workspace for tabmode")?;
+
+                    set_node_layout(workspace.id, Layout::Default, &mut self.command_executor)
+                        .context("Cannot set default layout for workspace")?;
+
+                    self.reapply_marks(&marks)?;
+
+                    failures
+                }
+            };
+
+            // Only now tabmode is left: a failure above keeps the workspace in tabmode.
+            if let Some(tabmode_mark) = tabmode_mark {
+                self.command_executor
+                    .run(format!("unmark {}", quote(&tabmode_mark)))
+                    .context("Cannot remove the tabmode mark")?;
             }
+
+            report_normalize_failures(&failures)
         } else {
-            if let Some(file_layout) = file_layout {
-                let file = File::create(file_layout).with_context(|| {
-                    format!("Cannot save the layout on file '{}'", file_layout.display())
-                })?;
+            if stdio {
+                let stdout = std::io::stdout();
+                Self::refuse_binary_terminal(stdout.is_terminal(), options.json)?;
 
-                let save_layout = SaveLayout::new(
-                    CommandExecutor::new()
-                        .context("Cannot create a new executor for saving layout")?,
-                );
+                let save_layout = SaveLayout::new(self.command_executor.share());
 
                 save_layout
-                    .execute(Some(workspace_num), file, Self::SAVE_LAYOUT_JSON)
+                    .execute(WorkspaceRef::of(workspace), stdout.lock(), options.json)
                     .context("Cannot save the layout")?;
+            } else if let Some(file_layout) = file_layout {
+                let mut file =
+                    AtomicFile::create(file_layout, options.keep_backup).with_context(|| {
+                        format!("Cannot save the layout on file '{}'", file_layout.display())
+                    })?;
+
+                let save_layout = SaveLayout::new(self.command_executor.share());
+
+                save_layout
+                    .execute(WorkspaceRef::of(workspace), &mut file, options.json)
+                    .context("Cannot save the layout")?;
+
+                file.commit().context("Cannot save the layout")?;
             }
 
-            self.normalize_workspace(workspace)
-                .context("Cannot normalize the workspace for tabmode")?;
+            let marks = Self::window_marks(workspace);
 
-            set_node_layout(workspace.id, Layout::Tabbed, &mut self.command_executor)
-                .context("Cannot set tab layout for workspace")
+            // A tabbed container created by i3 (e.g., `workspace_layout tabbed`) is kept:
+            // the windows are collapsed into it.
+            let native = Self::tabbed_wrapper(workspace).filter(|_| !Self::is_tabmode(workspace));
+
+            let failures = match native {
+                Some(wrapper) => normalize_container(wrapper, &mut self.command_executor),
+                None => normalize_workspace(workspace, &mut self.command_executor),
+            }
+            .context("Cannot normalize the workspace for tabmode")?;
+
+            if native.is_none() {
+                set_node_layout(workspace.id, Layout::Tabbed, &mut self.command_executor)
+                    .context("Cannot set tab layout for workspace")?;
+            }
+
+            self.reapply_marks(&marks)?;
+            self.mark_tabmode(workspace.id, native.is_some())?;
+
+            if let Some(title_format) = options.title_format.as_deref() {
+                self.set_title_format(workspace, title_format)
+                    .context("Cannot set the title format of windows")?;
+            }
+
+            if let Some(border) = options.border_tabbed {
+                self.run_on_windows(workspace, format!("border {}", border))
+                    .context("Cannot set the border of windows")?;
+            }
+
+            if let Some(suffix) = indicator_suffix {
+                self.rename_workspace(
+                    workspace.id,
+                    current_name,
+                    &with_indicator(current_name, suffix),
+                )?;
+            }
+
+            report_normalize_failures(&failures)
         }
     }
 
-    /// Whether the workspace is already in tabmode or not.
-    fn is_tabmode(workspace: &I3Node) -> bool {
-        if workspace.nodes.len() == 1 {
-            let child = unsafe { workspace.nodes.get_unchecked(0) };
+    /// Load the layout saved for `workspace` from `file_layout` (none without a file).
+    ///
+    /// A missing file is an error: leaving tabmode must not lose the saved layout.
+    fn load_layout(
+        workspace: &I3Node,
+        file_layout: Option<&Path>,
+        json: bool,
+    ) -> Result<Option<SavedLayout>> {
+        let Some(file_layout) = file_layout else {
+            return Ok(None);
+        };
 
-            child.window_type.is_none() && matches!(child.layout, NodeLayout::Tabbed)
-        } else {
-            false
+        if !file_layout.exists() && !backup_path(file_layout).exists() {
+            return Err(anyhow!(
+                "No saved layout for workspace '{}' ('{}' does not exist)",
+                WorkspaceRef::of(workspace),
+                file_layout.display()
+            ));
         }
+
+        SavedLayout::load(file_layout, json)
+            .context("Cannot restore layout")
+            .map(Some)
     }
 
-    /// Normalize a workspace.
+    /// Refuse to pipe a binary layout from/to a terminal.
+    fn refuse_binary_terminal(is_terminal: bool, json: bool) -> Result<()> {
+        if is_terminal && !json {
+            return Err(anyhow!(
+                "Refusing to pipe a binary layout from/to a terminal (use '--json')"
+            ));
+        }
+
+        Ok(())
+    }
+
+    /// Rename the workspace `workspace_id` from `from` to `to`.
     ///
-    /// Move all leaf nodes as workspace children.
-    fn normalize_workspace(&mut self, workspace: &I3Node) -> Result<()> {
-        debug_assert!(matches!(workspace.node_type, NodeType::Workspace));
+    /// The rename is skipped (with a warning) if the workspace has been renamed meanwhile
+    /// by someone else, or if i3 refuses it (e.g., the name is already taken).
+    fn rename_workspace(&mut self, workspace_id: usize, from: &str, to: &str) -> Result<()> {
+        if from == to {
+            return Ok(());
+        }
+
+        let root_node = self.command_executor.query_root_node()?;
+        let actual_name = find_workspaces(&root_node)
+            .into_iter()
+            .find(|workspace| workspace.id == workspace_id)
+            .and_then(|workspace| workspace.name.as_deref());
+
+        if actual_name != Some(from) {
+            eprintln!(
+                "[WARN]: Workspace '{}' has been renamed meanwhile; not renaming it to '{}'",
+                from, to
+            );
+            return Ok(());
+        }
+
+        let command = format!(
+            "rename workspace {} to {}",
+            quote_ws_name(from)?,
+            quote_ws_name(to)?
+        );
+
+        if let Err(error) = self.command_executor.run(command) {
+            eprintln!(
+                "[WARN]: Cannot rename workspace '{}' to '{}': {:#}",
+                from, to, error
+            );
+        }
+
+        Ok(())
+    }
+
+    /// Set the title format of all tiled windows of a workspace.
+    ///
+    /// Placeholders (`%title`, `%class`, `%instance`) are resolved by i3 itself,
+    /// so the tabs keep following title changes.
+    fn set_title_format(&mut self, workspace: &I3Node, title_format: &str) -> Result<()> {
+        self.run_on_windows(workspace, format!("title_format {}", quote(title_format)))
+    }
+
+    /// Run a command on all tiled windows of a workspace.
+    fn run_on_windows(&mut self, workspace: &I3Node, command: String) -> Result<()> {
+        let mut dfs = vec![workspace];
+
+        while let Some(current) = dfs.pop() {
+            if is_window(current) {
+                self.command_executor.run_on_node_id(current.id, &command)?;
+            }
+
+            dfs.extend(current.nodes.as_slice());
+        }
+
+        Ok(())
+    }
+
+    /// The marks of the tiled windows of a workspace.
+    fn window_marks(workspace: &I3Node) -> Vec<(usize, Vec<String>)> {
+        let mut marks = vec![];
+        let mut dfs = vec![workspace];
+
+        while let Some(current) = dfs.pop() {
+            if is_window(current) {
+                let window_marks = node_marks(current);
+
+                if !window_marks.is_empty() {
+                    marks.push((current.id, window_marks));
+                }
+            }
+
+            dfs.extend(current.nodes.as_slice());
+        }
+
+        marks
+    }
+
+    /// Mark the tabbed container of a workspace entering tabmode (see `tabmode_mark`).
+    ///
+    /// `native` tells that the container was there before (e.g., created by i3).
+    fn mark_tabmode(&mut self, workspace_id: usize, native: bool) -> Result<()> {
+        let root_node = self.command_executor.query_root_node()?;
+
+        let wrapper = find_node_by_id(workspace_id, &root_node)
+            .and_then(Self::tabbed_wrapper)
+            .ok_or_else(|| anyhow!("Cannot find the tabbed container of the workspace"))?;
 
         self.command_executor
-            .run_on_node_id(workspace.id, format!("mark \"{}\"", Self::MARK_ID))
-            .context("Cannot set temporary mark on focused workspace")?;
+            .run_on_node_id(
+                wrapper.id,
+                format!("mark --add {}", quote(&tabmode_mark(wrapper.id, native))),
+            )
+            .context("Cannot set the tabmode mark")
+    }
 
-        let mut dfs = workspace
-            .nodes
-            .iter()
-            .map(|node| (node, workspace.id))
+    /// Apply again the marks of windows dropped by the normalization.
+    fn reapply_marks(&mut self, marks: &[(usize, Vec<String>)]) -> Result<()> {
+        let root_node = self.command_executor.query_root_node()?;
+
+        apply_marks(marks, &root_node, &mut self.command_executor)
+            .context("Cannot restore the marks of windows")
+    }
+
+    /// Whether the layout file argument designates a directory.
+    fn is_layout_dir(file_layout: &Path) -> bool {
+        file_layout.as_os_str().to_string_lossy().ends_with('/') || file_layout.is_dir()
+    }
+
+    /// The layout file of `workspace` inside `dir`.
+    ///
+    /// The directory is created if missing. Layout files of workspaces which no longer exist
+    /// are pruned. Names are considered without the tab-mode indicator, so that a named
+    /// workspace keeps its layout file across the toggle.
+    fn layout_path_in_dir(
+        dir: &Path,
+        workspace: &I3Node,
+        root_node: &RootNode,
+        indicator_suffix: Option<&str>,
+    ) -> Result<PathBuf> {
+        std::fs::create_dir_all(dir)
+            .with_context(|| format!("Cannot create the directory '{}'", dir.display()))?;
+
+        let alive = find_workspaces(root_node)
+            .into_iter()
+            .map(|workspace| Self::layout_file_name(&Self::layout_key(workspace, indicator_suffix)))
             .collect::<Vec<_>>();
 
-        while let Some((current, parent)) = dfs.pop() {
-            if current.nodes.is_empty() && parent != workspace.id {
-                self.command_executor
-                    .run_on_node_id(
-                        current.id,
-                        format!("move window to mark \"{}\"", Self::MARK_ID),
-                    )
-                    .context("Cannot mode window on mark")?;
-            } else {
-                dfs.extend(current.nodes.iter().map(|node| (node, current.id)));
+        let entries = std::fs::read_dir(dir)
+            .with_context(|| format!("Cannot read the directory '{}'", dir.display()))?;
+
+        for entry in entries.flatten() {
+            let file_name = entry.file_name().to_string_lossy().into_owned();
+            let layout_name = file_name.strip_suffix(".bak").unwrap_or(&file_name);
+
+            if layout_name.starts_with(Self::LAYOUT_FILE_PREFIX)
+                && layout_name.ends_with(Self::LAYOUT_FILE_SUFFIX)
+                && !alive.iter().any(|alive| alive == layout_name)
+            {
+                let _ = std::fs::remove_file(entry.path());
             }
         }
 
-        self.command_executor
-            .run(format!("unmark \"{}\"", Self::MARK_ID))
-            .context("Cannot unset temporary mark")
+        Ok(dir.join(Self::layout_file_name(&Self::layout_key(
+            workspace,
+            indicator_suffix,
+        ))))
+    }
+
+    /// The reference of a workspace identifying its layout file.
+    fn layout_key(workspace: &I3Node, indicator_suffix: Option<&str>) -> WorkspaceRef {
+        match (WorkspaceRef::of(workspace), indicator_suffix) {
+            (WorkspaceRef::Name(name), Some(suffix)) => {
+                WorkspaceRef::Name(strip_indicator(&name, suffix))
+            }
+            (workspace, _) => workspace,
+        }
+    }
+
+    /// The name of the layout file of a workspace inside the layout directory.
+    fn layout_file_name(workspace: &WorkspaceRef) -> String {
+        format!(
+            "{}{}{}",
+            Self::LAYOUT_FILE_PREFIX,
+            workspace.file_key(),
+            Self::LAYOUT_FILE_SUFFIX
+        )
+    }
+
+    /// Whether the workspace is already in tabmode or not.
+    ///
+    /// The tabbed container wrapping the workspace must hold the tabmode mark: a tabbed
+    /// container created by i3 itself (e.g., `workspace_layout tabbed`) is not tabmode.
+    ///
+    /// Workspaces put in tabmode by versions without the mark look like i3's own tabbed
+    /// workspaces: toggling them enters tabmode again. They are left with
+    /// `--direction restore`.
+    pub fn is_tabmode(workspace: &I3Node) -> bool {
+        Self::tabmode_mark_of(workspace).is_some()
+    }
+
+    /// The tabbed container wrapping all the nodes of a workspace, if any.
+    fn tabbed_wrapper(workspace: &I3Node) -> Option<&I3Node> {
+        match workspace.nodes.as_slice() {
+            [child]
+                if child.window_type.is_none() && matches!(child.layout, NodeLayout::Tabbed) =>
+            {
+                Some(child)
+            }
+            _ => None,
+        }
+    }
+
+    /// The tabmode mark of a workspace (see `tabmode_mark`), if any.
+    fn tabmode_mark_of(workspace: &I3Node) -> Option<String> {
+        Self::tabbed_wrapper(workspace).and_then(|wrapper| {
+            node_marks(wrapper)
+                .into_iter()
+                .find(|mark| mark.starts_with(TABMODE_MARK_PREFIX))
+        })
+    }
+}
+
+/// The workspace name with the tab-mode indicator (`3` becomes `3 []`).
+///
+/// A name which already has the indicator is left as it is.
+fn with_indicator(name: &str, suffix: &str) -> String {
+    if suffix.is_empty() || name.ends_with(&format!(" {}", suffix)) {
+        name.to_string()
+    } else {
+        format!("{} {}", name, suffix)
+    }
+}
+
+/// The workspace name without the tab-mode indicator (`3 []` becomes `3`).
+pub fn strip_indicator(name: &str, suffix: &str) -> String {
+    name.strip_suffix(suffix)
+        .and_then(|name| name.strip_suffix(' '))
+        .filter(|_| !suffix.is_empty())
+        .unwrap_or(name)
+        .to_string()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::fixtures;
+
+    #[test]
+    fn is_tabmode_requires_the_mark() {
+        let root_node = fixtures::load("workspaces.json");
+
+        // Tabbed wrapper with the tabmode mark.
+        assert!(TabMode::is_tabmode(fixtures::node(&root_node, 300)));
+
+        // Tabbed wrapper created by i3 (`workspace_layout tabbed`).
+        assert!(!TabMode::is_tabmode(fixtures::node(&root_node, 400)));
+
+        // Flat, nested, placeholder and empty workspaces.
+        for workspace_id in [100, 200, 500, 600] {
+            assert!(!TabMode::is_tabmode(fixtures::node(
+                &root_node,
+                workspace_id
+            )));
+        }
+    }
+
+    #[test]
+    fn leaving_requires_the_saved_layout() {
+        let dir = fixtures::TempDir::new("tabmode-leave");
+        let root_node = fixtures::load("workspaces.json");
+        let workspace = fixtures::node(&root_node, 300);
+        let file_layout = dir.path().join("tabmode-3.layout");
+
+        // No file given: the workspace is only normalized.
+        assert!(TabMode::load_layout(workspace, None, true)
+            .unwrap()
+            .is_none());
+
+        // The file is missing: nothing is touched (the tabmode mark included).
+        let Err(error) = TabMode::load_layout(workspace, Some(&file_layout), true) else {
+            panic!("A missing layout file must be an error");
+        };
+        assert_eq!(
+            error.to_string(),
+            format!(
+                "No saved layout for workspace '3' ('{}' does not exist)",
+                file_layout.display()
+            )
+        );
+
+        let saved_layout =
+            SaveLayout::save_subtree(fixtures::node(&root_node, 200), false).unwrap();
+        let file = std::fs::File::create(&file_layout).unwrap();
+        saved_layout.to_writer(file, true).unwrap();
+
+        let loaded = TabMode::load_layout(workspace, Some(&file_layout), true)
+            .unwrap()
+            .unwrap();
+        assert_eq!(loaded.root().id(), saved_layout.root().id());
+    }
+
+    #[test]
+    fn indicator_round_trip() {
+        assert_eq!(with_indicator("3", "[]"), "3 []");
+        assert_eq!(with_indicator("3 []", "[]"), "3 []");
+        assert_eq!(with_indicator("3", ""), "3");
+
+        assert_eq!(strip_indicator("3 []", "[]"), "3");
+        assert_eq!(strip_indicator("3", "[]"), "3");
+        assert_eq!(strip_indicator("3[]", "[]"), "3[]");
+        assert_eq!(strip_indicator("3 []", ""), "3 []");
+    }
+
+    #[test]
+    fn layout_files_ignore_the_indicator() {
+        let dir = fixtures::TempDir::new("tabmode-layouts");
+        let root_node = fixtures::load("workspaces.json");
+
+        // Numbered workspaces are keyed by number, named ones by name without indicator.
+        for (workspace_id, file_name) in [(300, "tabmode-3.layout"), (400, "tabmode-web.layout")] {
+            let path = TabMode::layout_path_in_dir(
+                dir.path(),
+                fixtures::node(&root_node, workspace_id),
+                &root_node,
+                Some("[]"),
+            )
+            .unwrap();
+            assert_eq!(path, dir.path().join(file_name));
+        }
+
+        let mut workspace = fixtures::node(&root_node, 400).clone();
+        workspace.name = Some("web []".to_string());
+        assert_eq!(
+            TabMode::layout_key(&workspace, Some("[]")),
+            WorkspaceRef::Name("web".to_string())
+        );
+        assert_eq!(
+            TabMode::layout_key(&workspace, None),
+            WorkspaceRef::Name("web []".to_string())
+        );
+    }
+
+    #[test]
+    fn layout_files_of_gone_workspaces_are_pruned() {
+        let dir = fixtures::TempDir::new("tabmode-layouts");
+        let root_node = fixtures::load("workspaces.json");
+
+        for file_name in [
+            "tabmode-1.layout",
+            "tabmode-web.layout",
+            "tabmode-7.layout",
+            "tabmode-7.layout.bak",
+            "tabmode-mail.layout",
+            "notes.txt",
+        ] {
+            std::fs::write(dir.path().join(file_name), "").unwrap();
+        }
+
+        TabMode::layout_path_in_dir(
+            dir.path(),
+            fixtures::node(&root_node, 100),
+            &root_node,
+            Some("[]"),
+        )
+        .unwrap();
+
+        let mut remaining = std::fs::read_dir(dir.path())
+            .unwrap()
+            .map(|entry| entry.unwrap().file_name().into_string().unwrap())
+            .collect::<Vec<_>>();
+        remaining.sort();
+        assert_eq!(
+            remaining,
+            ["notes.txt", "tabmode-1.layout", "tabmode-web.layout"]
+        );
+    }
+
+    /// The workspace `workspace_id` with marks on the container `node_id`.
+    fn with_marks(workspace_id: usize, node_id: usize, marks: &[String]) -> I3Node {
+        let root_node = fixtures::load("workspaces.json");
+        let mut value = serde_json::to_value(fixtures::node(&root_node, workspace_id)).unwrap();
+
+        let mut dfs = vec![&mut value];
+        while let Some(current) = dfs.pop() {
+            if current["id"] == node_id {
+                current["marks"] = serde_json::json!(marks);
+            }
+            if let Some(nodes) = current["nodes"].as_array_mut() {
+                dfs.extend(nodes.iter_mut());
+            }
+        }
+
+        serde_json::from_value(value).unwrap()
+    }
+
+    #[test]
+    fn native_tabbed_containers_in_tabmode() {
+        // i3's own tabbed container, kept by tabmode.
+        let native = tabmode_mark(410, true);
+        assert_eq!(native, "__i3-autolayout__tabmode_native_410");
+
+        let workspace = with_marks(400, 410, std::slice::from_ref(&native));
+        assert!(TabMode::is_tabmode(&workspace));
+        assert_eq!(TabMode::tabmode_mark_of(&workspace), Some(native));
+
+        // The tabbed container created by tabmode.
+        let workspace = with_marks(400, 410, &["other".to_string(), tabmode_mark(410, false)]);
+        assert_eq!(
+            TabMode::tabmode_mark_of(&workspace).as_deref(),
+            Some("__i3-autolayout__tabmode_410")
+        );
+    }
+
+    #[test]
+    fn tabmode_marks_only_count_on_the_wrapper() {
+        // A marked tabbed container which does not wrap the whole workspace.
+        let workspace = with_marks(200, 220, &[tabmode_mark(220, false)]);
+        assert!(!TabMode::is_tabmode(&workspace));
+
+        // A look-alike mark.
+        let workspace = with_marks(400, 410, &["tabmode_410".to_string()]);
+        assert!(!TabMode::is_tabmode(&workspace));
     }
 }
@@ -0,0 +1,307 @@
+/*
+    Copyright (C) 2022  Biagio Festa
+
+    This program is free software: you can redistribute it and/or modify
+    it under the terms of the GNU General Public License as published by
+    the Free Software Foundation, either version 3 of the License, or
+    (at your option) any later version.
+
+    This program is distributed in the hope that it will be useful,
+    but WITHOUT ANY WARRANTY; without even the implied warranty of
+    MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+    GNU General Public License for more details.
+
+    You should have received a copy of the GNU General Public License
+    along with this program.  If not, see <https://www.gnu.org/licenses/>.
+*/
+
+use crate::command_executor::CommandExecutor;
+use crate::command_executor::I3Node;
+use crate::command_executor::RootNode;
+use crate::restore_layout::find_saved_workspace;
+use crate::restore_layout::saved_workspace;
+use crate::restore_layout::RestoreLayout;
+use crate::save_layout::SaveLayout;
+use crate::save_layout::SavedLayout;
+use crate::state_store::StateStore;
+use anyhow::Context;
+use anyhow::Result;
+use std::fs::File;
+use std::path::Path;
+use std::path::PathBuf;
+
+/// The stack of the snapshots taken before each layout change.
+///
+/// Each snapshot is a directory of the runtime state (named by an increasing sequence
+/// number) holding the binary layout of every affected workspace, written through the
+/// `StateStore`. Only the most recent snapshots are kept.
+pub struct UndoStack {
+    store: StateStore,
+}
+
+impl UndoStack {
+    const DIR_NAME: &'static str = "undo";
+    const CAPACITY: usize = 5;
+    const SAVE_LAYOUT_JSON: bool = false;
+
+    /// Open the stack in the runtime directory.
+    pub fn open() -> Result<Self> {
+        Self::in_store(&StateStore::runtime()?)
+    }
+
+    /// Open the stack in the state store `state_store`.
+    fn in_store(state_store: &StateStore) -> Result<Self> {
+        Ok(Self {
+            store: state_store.subdir(Self::DIR_NAME)?,
+        })
+    }
+
+    /// Push a snapshot of some workspaces, dropping the oldest snapshots beyond capacity.
+    ///
+    /// The snapshot is written aside (`<SEQUENCE>.tmp`) and renamed once complete.
+    pub fn push(&self, workspaces: &[&I3Node]) -> Result<()> {
+        let mut snapshots = self.snapshots()?;
+        let sequence = snapshots.last().map_or(0, |(sequence, _)| sequence + 1);
+
+        let tmp_store = self.store.subdir(&format!("{}.tmp", sequence))?;
+
+        for (index, workspace) in workspaces.iter().enumerate() {
+            let layout = SaveLayout::save_subtree(workspace, false)?;
+
+            tmp_store.write(&format!("{}.layout", index), false, |output| {
+                layout.to_writer(output, Self::SAVE_LAYOUT_JSON)
+            })?;
+        }
+
+        let path = self.store.path(&sequence.to_string());
+        std::fs::rename(tmp_store.dir(), &path)
+            .with_context(|| format!("Cannot create the snapshot '{}'", path.display()))?;
+
+        snapshots.push((sequence, path));
+
+        let excess = snapshots.len().saturating_sub(Self::CAPACITY);
+        for (_, path) in &snapshots[..excess] {
+            std::fs::remove_dir_all(path)
+                .with_context(|| format!("Cannot remove the snapshot '{}'", path.display()))?;
+        }
+
+        Ok(())
+    }
+
+    /// The most recent snapshot (if any), with its layouts.
+    pub fn last(&self) -> Result<Option<(PathBuf, Vec<SavedLayout>)>> {
+        let Some((_, path)) = self.snapshots()?.pop() else {
+            return Ok(None);
+        };
+
+        let layouts = Self::layouts(&path)?;
+
+        Ok(Some((path, layouts)))
+    }
+
+    /// The snapshots left behind: incomplete ones (interrupted while taken) and those
+    /// whose workspaces no longer exist (or which cannot be read).
+    pub fn leftovers(&self, root_node: &RootNode) -> Result<Vec<PathBuf>> {
+        let mut leftovers = vec![];
+
+        let dir = self.store.dir();
+
+        for entry in std::fs::read_dir(dir)
+            .with_context(|| format!("Cannot read the directory '{}'", dir.display()))?
+            .flatten()
+        {
+            if entry.file_name().to_string_lossy().ends_with(".tmp") {
+                leftovers.push(entry.path());
+            }
+        }
+
+        for (_, path) in self.snapshots()? {
+            let alive = Self::layouts(&path).is_ok_and(|layouts| {
+                layouts.iter().any(|layout| {
+                    saved_workspace(layout).is_ok_and(|workspace| {
+                        find_saved_workspace(&workspace, root_node).is_some()
+                    })
+                })
+            });
+
+            if !alive {
+                leftovers.push(path);
+            }
+        }
+
+        leftovers.sort();
+        Ok(leftovers)
+    }
+
+    /// The layouts of a snapshot.
+    fn layouts(path: &Path) -> Result<Vec<SavedLayout>> {
+        let mut files = std::fs::read_dir(path)
+            .with_context(|| format!("Cannot read the snapshot '{}'", path.display()))?
+            .map(|entry| entry.map(|entry| entry.path()))
+            .collect::<std::io::Result<Vec<_>>>()
+            .with_context(|| format!("Cannot read the snapshot '{}'", path.display()))?;
+        files.sort();
+
+        files
+            .iter()
+            .map(|file| {
+                let input = File::open(file)
+                    .with_context(|| format!("Cannot open the file '{}'", file.display()))?;
+
+                SavedLayout::from_reader(input, Self::SAVE_LAYOUT_JSON)
+            })
+            .collect()
+    }
+
+    /// Remove a snapshot returned by `last`.
+    pub fn remove(&self, path: &Path) -> Result<()> {
+        std::fs::remove_dir_all(path)
+            .with_context(|| format!("Cannot remove the snapshot '{}'", path.display()))
+    }
+
+    /// The complete snapshots, from the oldest.
+    fn snapshots(&self) -> Result<Vec<(u64, PathBuf)>> {
+        let dir = self.store.dir();
+        let mut snapshots = vec![];
+
+        for entry in std::fs::read_dir(dir)
+            .with_context(|| format!("Cannot read the directory '{}'", dir.display()))?
+        {
+            let path = entry
+                .with_context(|| format!("Cannot read the directory '{}'", dir.display()))?
+                .path();
+
+            let sequence = path
+                .file_name()
+                .and_then(|name| name.to_str())
+                .and_then(|name| name.parse::<u64>().ok());
+
+            if let Some(sequence) = sequence {
+                snapshots.push((sequence, path));
+            }
+        }
+
+        snapshots.sort();
+        Ok(snapshots)
+    }
+}
+
+/// Push a snapshot of the workspaces about to change on the undo stack.
+///
+/// A failure only prints a warning: it never prevents the change.
+pub fn record_undo(workspaces: &[&I3Node]) {
+    if let Err(error) = UndoStack::open().and_then(|undo_stack| undo_stack.push(workspaces)) {
+        eprintln!("[WARN]: Cannot save the undo snapshot: {:?}", error);
+    }
+}
+
+/// Undo executor.
+///
+/// It represents a one-shot executor which restores the layouts of the most recent
+/// snapshot of the undo stack, and drops the snapshot.
+pub struct Undo {
+    /// Command executor.
+    command_executor: CommandExecutor,
+}
+
+impl Undo {
+    /// A new undo executor.
+    pub fn new(command_executor: CommandExecutor) -> Self {
+        Self { command_executor }
+    }
+
+    /// Execute the action.
+    pub fn execute(self) -> Result<()> {
+        let undo_stack = UndoStack::open()?;
+
+        let Some((path, layouts)) = undo_stack.last()? else {
+            eprintln!("Nothing to undo");
+            return Ok(());
+        };
+
+        for layout in layouts {
+            RestoreLayout::new(self.command_executor.share())
+                .without_undo()
+                .execute_layout(layout, true)
+                .context("Cannot restore the snapshot")?;
+        }
+
+        undo_stack.remove(&path)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::fixtures;
+    use crate::fixtures::TempDir;
+
+    fn undo_stack_in(dir: &TempDir) -> UndoStack {
+        UndoStack::in_store(&StateStore::in_dir(dir.path().to_path_buf())).unwrap()
+    }
+
+    fn sequences(undo_stack: &UndoStack) -> Vec<u64> {
+        undo_stack
+            .snapshots()
+            .unwrap()
+            .into_iter()
+            .map(|(sequence, _)| sequence)
+            .collect()
+    }
+
+    #[test]
+    fn push_keeps_the_most_recent_snapshots() {
+        let dir = TempDir::new("undo");
+        let undo_stack = undo_stack_in(&dir);
+        let root_node = fixtures::load("workspaces.json");
+
+        assert!(undo_stack.last().unwrap().is_none());
+
+        for _ in 0..UndoStack::CAPACITY + 2 {
+            undo_stack.push(&[fixtures::node(&root_node, 100)]).unwrap();
+        }
+        undo_stack
+            .push(&[
+                fixtures::node(&root_node, 200),
+                fixtures::node(&root_node, 400),
+            ])
+            .unwrap();
+
+        assert_eq!(sequences(&undo_stack), [3, 4, 5, 6, 7]);
+        assert!(undo_stack.leftovers(&root_node).unwrap().is_empty());
+
+        let (path, layouts) = undo_stack.last().unwrap().unwrap();
+        let workspaces = layouts
+            .iter()
+            .map(|layout| saved_workspace(layout).unwrap().to_string())
+            .collect::<Vec<_>>();
+        assert_eq!(workspaces, ["2", "web"]);
+
+        undo_stack.remove(&path).unwrap();
+        assert_eq!(sequences(&undo_stack), [3, 4, 5, 6]);
+    }
+
+    #[test]
+    fn interrupted_and_dead_snapshots_are_leftovers() {
+        let dir = TempDir::new("undo");
+        let undo_stack = undo_stack_in(&dir);
+        let root_node = fixtures::load("workspaces.json");
+
+        undo_stack.push(&[fixtures::node(&root_node, 100)]).unwrap();
+        std::fs::create_dir(dir.path().join("undo/1.tmp")).unwrap();
+
+        // The same tree without the workspaces of eDP-1.
+        let mut other_tree = root_node.node().clone();
+        other_tree.nodes.retain(|output| output.id != 10);
+        let other_root_node = RootNode::new(other_tree);
+
+        assert_eq!(
+            undo_stack.leftovers(&root_node).unwrap(),
+            [dir.path().join("undo/1.tmp")]
+        );
+        assert_eq!(
+            undo_stack.leftovers(&other_root_node).unwrap(),
+            [dir.path().join("undo/0"), dir.path().join("undo/1.tmp")]
+        );
+    }
+}
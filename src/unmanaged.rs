@@ -0,0 +1,306 @@
+/*
+    Copyright (C) 2022  Biagio Festa
+
+    This program is free software: you can redistribute it and/or modify
+    it under the terms of the GNU General Public License as published by
+    the Free Software Foundation, either version 3 of the License, or
+    (at your option) any later version.
+
+    This program is distributed in the hope that it will be useful,
+    but WITHOUT ANY WARRANTY; without even the implied warranty of
+    MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+    GNU General Public License for more details.
+
+    You should have received a copy of the GNU General Public License
+    along with this program.  If not, see <https://www.gnu.org/licenses/>.
+*/
+use crate::command_executor::I3Node;
+use crate::state::StateComponent;
+use crate::state_store::StateStore;
+use anyhow::Context;
+use anyhow::Result;
+use std::collections::BTreeSet;
+use std::collections::HashMap;
+
+/// How `ctl manage` changes the managed flag of a workspace.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, clap::ValueEnum)]
+pub enum ManageState {
+    /// The service manages the workspace.
+    On,
+
+    /// The service leaves the workspace alone.
+    Off,
+
+    /// Flip the flag.
+    Toggle,
+}
+
+impl ManageState {
+    /// The state as delivered in a tick payload.
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            Self::On => "on",
+            Self::Off => "off",
+            Self::Toggle => "toggle",
+        }
+    }
+
+    /// Parse a state delivered in a tick payload.
+    pub fn parse(state: &str) -> Option<Self> {
+        match state {
+            "on" => Some(Self::On),
+            "off" => Some(Self::Off),
+            "toggle" => Some(Self::Toggle),
+            _ => None,
+        }
+    }
+}
+
+/// The file content.
+#[derive(Default, serde::Deserialize, serde::Serialize)]
+struct UnmanagedFile {
+    /// The names of the unmanaged workspaces.
+    workspaces: BTreeSet<String>,
+}
+
+/// The workspaces handed back to manual control.
+///
+/// Workspaces are tracked by name and persisted in the state directory
+/// (`unmanaged-workspaces.json`), so that they stay unmanaged across restarts until
+/// managed again (also when destroyed and created again meanwhile).
+#[derive(Default)]
+pub struct UnmanagedWorkspaces {
+    /// The store of the file (the state directory if not loaded yet).
+    store: Option<StateStore>,
+
+    file: UnmanagedFile,
+
+    /// The names of the live unmanaged workspaces by id, to follow their renames.
+    ids: HashMap<usize, String>,
+}
+
+impl UnmanagedWorkspaces {
+    const FILE_NAME: &'static str = "unmanaged-workspaces.json";
+
+    /// The version of the file format.
+    const VERSION: u32 = 1;
+
+    /// Load the unmanaged workspaces.
+    ///
+    /// A missing file means none; an unreadable one is quarantined (see `StateStore::load`).
+    pub fn load() -> Result<Self> {
+        Ok(Self::load_from(StateStore::open()?))
+    }
+
+    /// Load the unmanaged workspaces of a store.
+    fn load_from(store: StateStore) -> Self {
+        Self {
+            file: store.load(Self::FILE_NAME, Self::VERSION),
+            store: Some(store),
+            ids: HashMap::new(),
+        }
+    }
+
+    /// Whether the workspace is unmanaged.
+    pub fn contains(&self, workspace: &str) -> bool {
+        self.file.workspaces.contains(workspace)
+    }
+
+    /// The names of the unmanaged workspaces (sorted).
+    pub fn names(&self) -> Vec<String> {
+        self.file.workspaces.iter().cloned().collect()
+    }
+
+    /// Follow the renames of a live workspace, if unmanaged.
+    pub fn track(&mut self, workspace: &I3Node) {
+        if let Some(name) = workspace.name.as_deref().filter(|name| self.contains(name)) {
+            self.ids.insert(workspace.id, name.to_string());
+        }
+    }
+
+    /// Change the managed flag of a workspace and persist it.
+    ///
+    /// It returns whether the workspace is managed afterwards.
+    pub fn set(&mut self, workspace: &str, state: ManageState) -> Result<bool> {
+        let managed = match state {
+            ManageState::On => true,
+            ManageState::Off => false,
+            ManageState::Toggle => self.contains(workspace),
+        };
+
+        let changed = if managed {
+            self.ids.retain(|_, name| name != workspace);
+            self.file.workspaces.remove(workspace)
+        } else {
+            self.file.workspaces.insert(workspace.to_string())
+        };
+
+        if changed {
+            self.store()?;
+        }
+
+        Ok(managed)
+    }
+
+    /// Keep tracking an unmanaged workspace after it has been renamed.
+    pub fn on_renamed(&mut self, workspace: &I3Node) -> Result<()> {
+        let Some(new_name) = workspace.name.as_deref() else {
+            return Ok(());
+        };
+
+        let Some(old_name) = self.ids.get_mut(&workspace.id) else {
+            return Ok(());
+        };
+
+        if old_name == new_name {
+            return Ok(());
+        }
+
+        self.file.workspaces.remove(old_name.as_str());
+        self.file.workspaces.insert(new_name.to_string());
+        *old_name = new_name.to_string();
+
+        self.store()
+    }
+
+    fn store(&mut self) -> Result<()> {
+        let store = match self.store.take() {
+            Some(store) => store,
+            None => StateStore::open()?,
+        };
+
+        let result = store
+            .store(Self::FILE_NAME, Self::VERSION, &self.file)
+            .context("Cannot write the unmanaged workspaces");
+        self.store = Some(store);
+
+        result
+    }
+}
+
+impl StateComponent for UnmanagedWorkspaces {
+    fn name(&self) -> &'static str {
+        "unmanaged-workspaces"
+    }
+
+    fn entries(&self) -> usize {
+        self.file.workspaces.len()
+    }
+
+    fn describe(&self) -> String {
+        format!("{} [{}]", self.entries(), self.names().join(", "))
+    }
+
+    /// The workspace stays unmanaged: only the tracking of its renames is dropped.
+    fn on_workspace_emptied(&mut self, workspace: &str) {
+        self.ids.retain(|_, name| name != workspace);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::fixtures;
+    use crate::fixtures::TempDir;
+    use crate::status::ServiceStatus;
+
+    fn unmanaged_in(dir: &TempDir) -> UnmanagedWorkspaces {
+        UnmanagedWorkspaces::load_from(StateStore::in_dir(dir.path().to_path_buf()))
+    }
+
+    #[test]
+    fn manage_states() {
+        let dir = TempDir::new("unmanaged");
+        let mut unmanaged = unmanaged_in(&dir);
+
+        assert!(!unmanaged.set("web", ManageState::Off).unwrap());
+        assert!(unmanaged.contains("web"));
+        assert!(!unmanaged.set("web", ManageState::Off).unwrap());
+
+        assert!(unmanaged.set("web", ManageState::Toggle).unwrap());
+        assert!(!unmanaged.contains("web"));
+        assert!(!unmanaged.set("web", ManageState::Toggle).unwrap());
+        assert!(unmanaged.contains("web"));
+
+        assert!(unmanaged.set("web", ManageState::On).unwrap());
+        assert!(unmanaged.set("2", ManageState::On).unwrap());
+        assert_eq!(unmanaged.entries(), 0);
+
+        for state in [ManageState::On, ManageState::Off, ManageState::Toggle] {
+            assert_eq!(ManageState::parse(state.as_str()), Some(state));
+        }
+        assert_eq!(ManageState::parse("maybe"), None);
+    }
+
+    #[test]
+    fn unmanaged_workspaces_survive_reload() {
+        let dir = TempDir::new("unmanaged");
+
+        let mut unmanaged = unmanaged_in(&dir);
+        unmanaged.set("web", ManageState::Off).unwrap();
+        unmanaged.set("3: mail", ManageState::Off).unwrap();
+
+        let unmanaged = unmanaged_in(&dir);
+        assert!(unmanaged.contains("web"));
+        assert!(unmanaged.contains("3: mail"));
+        assert_eq!(unmanaged.describe(), "2 [3: mail, web]");
+
+        // The reloaded service reports them in `ctl status`.
+        let status = ServiceStatus {
+            unmanaged: unmanaged.names(),
+            ..ServiceStatus::default()
+        };
+        assert!(status
+            .describe()
+            .contains("\nunmanaged workspaces: '3: mail', 'web'"));
+
+        let content =
+            std::fs::read_to_string(dir.path().join(UnmanagedWorkspaces::FILE_NAME)).unwrap();
+        let value: serde_json::Value = serde_json::from_str(&content).unwrap();
+        assert_eq!(value["version"], 1);
+    }
+
+    #[test]
+    fn renames_are_followed() {
+        let dir = TempDir::new("unmanaged");
+        let root_node = fixtures::load("workspaces.json");
+
+        let mut unmanaged = unmanaged_in(&dir);
+        unmanaged.set("web", ManageState::Off).unwrap();
+        unmanaged.track(fixtures::node(&root_node, 400));
+        unmanaged.track(fixtures::node(&root_node, 100));
+
+        let mut workspace = fixtures::node(&root_node, 400).clone();
+        workspace.name = Some("9: web".to_string());
+        unmanaged.on_renamed(&workspace).unwrap();
+        assert!(!unmanaged.contains("web"));
+        assert!(unmanaged.contains("9: web"));
+
+        // Managed workspaces are not tracked.
+        let mut workspace = fixtures::node(&root_node, 100).clone();
+        workspace.name = Some("1: term".to_string());
+        unmanaged.on_renamed(&workspace).unwrap();
+        assert!(!unmanaged.contains("1: term"));
+
+        assert!(unmanaged_in(&dir).contains("9: web"));
+    }
+
+    #[test]
+    fn emptied_workspaces_stay_unmanaged() {
+        let dir = TempDir::new("unmanaged");
+        let root_node = fixtures::load("workspaces.json");
+
+        let mut unmanaged = unmanaged_in(&dir);
+        unmanaged.set("web", ManageState::Off).unwrap();
+        unmanaged.track(fixtures::node(&root_node, 400));
+        unmanaged.on_workspace_emptied("web");
+        assert!(unmanaged.contains("web"));
+
+        // Its renames are not followed anymore (the con id may be reused).
+        let mut workspace = fixtures::node(&root_node, 400).clone();
+        workspace.name = Some("mail".to_string());
+        unmanaged.on_renamed(&workspace).unwrap();
+        assert!(unmanaged.contains("web"));
+        assert!(!unmanaged.contains("mail"));
+    }
+}
@@ -0,0 +1,208 @@
+/*
+    Copyright (C) 2022  Biagio Festa
+
+    This program is free software: you can redistribute it and/or modify
+    it under the terms of the GNU General Public License as published by
+    the Free Software Foundation, either version 3 of the License, or
+    (at your option) any later version.
+
+    This program is distributed in the hope that it will be useful,
+    but WITHOUT ANY WARRANTY; without even the implied warranty of
+    MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+    GNU General Public License for more details.
+
+    You should have received a copy of the GNU General Public License
+    along with this program.  If not, see <https://www.gnu.org/licenses/>.
+*/
+
+use crate::command_executor::CommandExecutor;
+use crate::command_executor::I3Node;
+use crate::command_executor::RootNode;
+use crate::utilities::find_focused_node;
+use crate::utilities::find_node_by_id_including_floating;
+use crate::utilities::find_workspaces;
+use crate::utilities::is_scratchpad_workspace;
+use crate::utilities::is_window;
+use crate::utilities::runtime_dir;
+use anyhow::Context;
+use anyhow::Result;
+use std::path::PathBuf;
+
+/// The outcome of an urgent jump.
+pub enum UrgentJumpOutcome {
+    /// The focus moved to an urgent window.
+    Jumped,
+
+    /// The focus returned to the window focused before the jump.
+    Returned,
+
+    /// There are no urgent windows (and nowhere to return).
+    NoUrgent,
+}
+
+/// UrgentJump executor.
+///
+/// It represents a one-shot executor which focuses the next urgent window,
+/// remembering the previously focused one so that a later invocation can return to it.
+pub struct UrgentJump {
+    /// Command executor.
+    command_executor: CommandExecutor,
+}
+
+impl UrgentJump {
+    const STATE_FILE_NAME: &'static str = "urgent-jump";
+
+    /// A new urgent-jump executor.
+    pub fn new(command_executor: CommandExecutor) -> Self {
+        Self { command_executor }
+    }
+
+    /// Execute the action.
+    ///
+    /// It focuses the first urgent window. If there are no urgent windows (or `back` is set)
+    /// the focus returns to the window remembered by the previous jump (if it still exists).
+    pub fn execute(mut self, back: bool) -> Result<UrgentJumpOutcome> {
+        let root_node = self.command_executor.query_root_node()?;
+        let state_path = Self::state_path()?;
+
+        if !back {
+            if let Some((urgent, in_scratchpad)) = Self::find_urgent_window(&root_node) {
+                if let Some(focused) = find_focused_node(&root_node) {
+                    std::fs::write(&state_path, focused.id.to_string()).with_context(|| {
+                        format!("Cannot write the state file '{}'", state_path.display())
+                    })?;
+                }
+
+                let command = if in_scratchpad {
+                    "scratchpad show"
+                } else {
+                    "focus"
+                };
+
+                self.command_executor
+                    .run_on_node_id(urgent.id, command)
+                    .context("Cannot focus the urgent window")?;
+
+                return Ok(UrgentJumpOutcome::Jumped);
+            }
+        }
+
+        let remembered = std::fs::read_to_string(&state_path)
+            .ok()
+            .and_then(|content| content.trim().parse::<usize>().ok());
+        let _ = std::fs::remove_file(&state_path);
+
+        match remembered {
+            Some(node_id) if find_node_by_id_including_floating(node_id, &root_node).is_some() => {
+                self.command_executor
+                    .run_on_node_id(node_id, "focus")
+                    .context("Cannot focus the remembered window")?;
+
+                Ok(UrgentJumpOutcome::Returned)
+            }
+
+            Some(node_id) => {
+                eprintln!("[WARN]: Cannot return to window '{}' (not found)", node_id);
+                Ok(UrgentJumpOutcome::NoUrgent)
+            }
+
+            None => Ok(UrgentJumpOutcome::NoUrgent),
+        }
+    }
+
+    /// Find the first urgent window in the tree.
+    ///
+    /// It also returns whether the window is hidden in the scratchpad.
+    fn find_urgent_window(root_node: &RootNode) -> Option<(&I3Node, bool)> {
+        for workspace in find_workspaces(root_node) {
+            let mut dfs = vec![workspace];
+
+            while let Some(current) = dfs.pop() {
+                if is_window(current) && current.urgent {
+                    return Some((current, is_scratchpad_workspace(workspace)));
+                }
+
+                dfs.extend(current.floating_nodes.iter().rev());
+                dfs.extend(current.nodes.iter().rev());
+            }
+        }
+
+        None
+    }
+
+    /// The state file, if the window it remembers has been closed.
+    pub fn leftovers(root_node: &RootNode) -> Result<Vec<PathBuf>> {
+        Ok(Self::leftovers_at(Self::state_path()?, root_node))
+    }
+
+    /// The state file `state_path`, if the window it remembers has been closed.
+    fn leftovers_at(state_path: PathBuf, root_node: &RootNode) -> Vec<PathBuf> {
+        let Ok(content) = std::fs::read_to_string(&state_path) else {
+            return vec![];
+        };
+
+        let alive = content
+            .trim()
+            .parse::<usize>()
+            .is_ok_and(|node_id| find_node_by_id_including_floating(node_id, root_node).is_some());
+
+        if alive {
+            vec![]
+        } else {
+            vec![state_path]
+        }
+    }
+
+    /// The file where the window focused before the jump is remembered.
+    fn state_path() -> Result<PathBuf> {
+        Ok(runtime_dir()?.join(Self::STATE_FILE_NAME))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::fixtures;
+    use crate::fixtures::TempDir;
+
+    fn urgent_window(root_node: &RootNode) -> Option<(usize, bool)> {
+        UrgentJump::find_urgent_window(root_node)
+            .map(|(node, in_scratchpad)| (node.id, in_scratchpad))
+    }
+
+    #[test]
+    fn find_urgent_windows() {
+        let root_node = fixtures::load("workspaces.json");
+        assert_eq!(urgent_window(&root_node), None);
+
+        let nested = fixtures::edit(&root_node, 222, |node| node.urgent = true);
+        assert_eq!(urgent_window(&nested), Some((222, false)));
+
+        // An urgent container is not a window.
+        let container = fixtures::edit(&root_node, 220, |node| node.urgent = true);
+        assert_eq!(urgent_window(&container), None);
+
+        // Hidden windows are shown rather than focused.
+        let root_node = fixtures::load("scratchpad.json");
+        let hidden = fixtures::edit(&root_node, 41, |node| node.urgent = true);
+        assert_eq!(urgent_window(&hidden), Some((41, true)));
+    }
+
+    #[test]
+    fn leftover_state_remembers_a_closed_window() {
+        let dir = TempDir::new("urgent");
+        let root_node = fixtures::load("workspaces.json");
+        let state_path = dir.path().join(UrgentJump::STATE_FILE_NAME);
+
+        assert!(UrgentJump::leftovers_at(state_path.clone(), &root_node).is_empty());
+
+        std::fs::write(&state_path, "102").unwrap();
+        assert!(UrgentJump::leftovers_at(state_path.clone(), &root_node).is_empty());
+
+        std::fs::write(&state_path, "999").unwrap();
+        assert_eq!(
+            UrgentJump::leftovers_at(state_path.clone(), &root_node),
+            [state_path]
+        );
+    }
+}
@@ -17,14 +17,36 @@
 
 use crate::command_executor::CommandExecutor;
 use crate::command_executor::I3Node;
+use crate::command_executor::I3Output;
+use crate::command_executor::I3Workspace;
 use crate::command_executor::RootNode;
+use crate::marks::SIZE_LOCK_MARK_PREFIX;
 use anyhow::anyhow;
 use anyhow::Context;
 use anyhow::Result;
 use i3_ipc::reply::Floating;
+use i3_ipc::reply::NodeBorder;
+use i3_ipc::reply::NodeLayout;
 use i3_ipc::reply::NodeType;
+use i3_ipc::reply::Rect;
+use regex::Regex;
+use std::collections::HashMap;
+use std::fmt::Display;
+use std::fs::DirBuilder;
+use std::fs::OpenOptions;
+use std::fs::Permissions;
+use std::io::ErrorKind;
+use std::os::unix::fs::DirBuilderExt;
+use std::os::unix::fs::MetadataExt;
+use std::os::unix::fs::OpenOptionsExt;
+use std::os::unix::fs::PermissionsExt;
+use std::path::Path;
+use std::path::PathBuf;
+use std::str::FromStr;
+use std::time::Duration;
 
 /// The node layout.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
 pub enum Layout {
     /// Default layout.
     Default,
@@ -42,30 +64,177 @@ pub enum Layout {
     Stacked,
 }
 
+impl FromStr for Layout {
+    type Err = anyhow::Error;
+
+    /// Parse a layout as named by i3 (`splith`, `splitv`, `tabbed` or `stacked`).
+    fn from_str(s: &str) -> Result<Self> {
+        match s {
+            "splith" => Ok(Self::SplitH),
+            "splitv" => Ok(Self::SplitV),
+            "tabbed" => Ok(Self::Tabbed),
+            "stacked" => Ok(Self::Stacked),
+            _ => Err(anyhow!(
+                "Invalid layout '{}' (expected 'splith', 'splitv', 'tabbed' or 'stacked')",
+                s
+            )),
+        }
+    }
+}
+
 /// A split operation request.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, serde::Deserialize, serde::Serialize)]
+#[serde(rename_all = "lowercase")]
 pub enum Split {
     /// Split horizontal.
     Horizontal,
 
     /// Split vertical.
     Vertical,
+
+    /// Split in the direction opposite to the parent container.
+    Toggle,
 }
 
-/// The size ratio for a rectangle container.
-pub enum RectRatio {
-    /// Width greater or equal than height.
-    Horizontal,
+/// Which rectangle of a node is considered for its orientation.
+#[derive(Clone, Copy, clap::ValueEnum, serde::Deserialize, serde::Serialize)]
+#[serde(rename_all = "kebab-case")]
+pub enum RectSource {
+    /// The window content (without decorations).
+    Window,
 
-    /// Height greater than width.
-    Vertical,
+    /// The whole container.
+    Container,
+}
+
+/// The policy deciding how to split a node.
+#[derive(Clone, Copy)]
+pub struct OrientationPolicy {
+    /// A node is vertical if its height is greater than its width times the threshold.
+    pub threshold: f64,
+
+    /// The rectangle considered for the orientation.
+    pub rect_source: RectSource,
+
+    /// A node whose aspect ratio (height / width) is within this distance from 1.0
+    /// is split with `Split::Toggle` (0.0 disables it).
+    pub square_band: f64,
+}
+
+impl Default for OrientationPolicy {
+    fn default() -> Self {
+        Self {
+            threshold: 1.0,
+            rect_source: RectSource::Window,
+            square_band: 0.0,
+        }
+    }
 }
 
-impl RectRatio {
-    /// If ratio is vertical.
+impl OrientationPolicy {
+    /// Decide the split for a node.
     ///
-    /// Same as: `matches!(sefl, RectRatio::Vertical)`.
-    pub fn is_vertical(&self) -> bool {
-        matches!(self, RectRatio::Vertical)
+    /// A vertical workspace (e.g., a portrait monitor) always splits vertically;
+    /// otherwise the split follows the orientation of the node itself.
+    /// Nearly square nodes (see `square_band`) toggle the direction of their parent.
+    ///
+    /// It is `None` when the node (or the workspace) has no sane rect to decide on,
+    /// e.g., while an output is being reconfigured.
+    pub fn decide(&self, node: &I3Node, workspace: Option<&I3Node>) -> Option<Split> {
+        if let Some(workspace) = workspace {
+            if self.is_vertical(self.rect(workspace)?) {
+                return Some(Split::Vertical);
+            }
+        }
+
+        let rect = self.rect(node)?;
+
+        if self.is_square(rect) {
+            Some(Split::Toggle)
+        } else if self.is_vertical(rect) {
+            Some(Split::Vertical)
+        } else {
+            Some(Split::Horizontal)
+        }
+    }
+
+    /// Whether the node is vertical (`None` if it has no sane rect).
+    pub fn is_node_vertical(&self, node: &I3Node) -> Option<bool> {
+        self.rect(node).map(|rect| self.is_vertical(rect))
+    }
+
+    /// The rectangle of the node considered for its orientation.
+    ///
+    /// If it is not sane (see `sane_rect`) the other rectangle of the node is considered.
+    fn rect<'a>(&self, node: &'a I3Node) -> Option<&'a Rect> {
+        let (preferred, fallback) = match self.rect_source {
+            RectSource::Window => (&node.window_rect, &node.rect),
+            RectSource::Container => (&node.rect, &node.window_rect),
+        };
+
+        sane_rect(preferred).or_else(|| sane_rect(fallback))
+    }
+
+    /// Whether the rectangle is vertical.
+    fn is_vertical(&self, rect: &Rect) -> bool {
+        rect.height as f64 > rect.width as f64 * self.threshold
+    }
+
+    /// Whether the aspect ratio of the rectangle is within the square band (bounds included).
+    fn is_square(&self, rect: &Rect) -> bool {
+        // The tolerance keeps the bounds in despite rounding (e.g., 550 / 500 - 1.0 > 0.1).
+        self.square_band > 0.0
+            && (rect.height as f64 / rect.width as f64 - 1.0).abs()
+                <= self.square_band + f64::EPSILON
+    }
+}
+
+/// The rectangle, if its size can be reasoned about (i.e., it is not empty).
+///
+/// i3 reports empty rects for new workspaces and while outputs are reconfigured.
+/// Coordinates are not checked: they are negative for outputs left of (or above) the origin.
+pub fn sane_rect(rect: &Rect) -> Option<&Rect> {
+    (rect.width > 0 && rect.height > 0).then_some(rect)
+}
+
+/// Decide the split of a node from the cells it would make.
+///
+/// Splitting in the direction of the parent adds a cell to the parent (its children share
+/// it); the other direction halves the node. The direction whose cells are closest to
+/// `target_aspect` (width / height) wins, so that a row of two windows gets the third
+/// below the focused one instead of a third narrow column.
+///
+/// It is `None` when the node (or its parent) has no sane rect to decide on.
+pub fn cells_split(node: &I3Node, parent_node: &I3Node, target_aspect: f64) -> Option<Split> {
+    let rect = sane_rect(&node.rect)?;
+    let parent_rect = sane_rect(&parent_node.rect)?;
+    let cells = parent_node.nodes.len() as f64 + 1.0;
+
+    let (horizontal, vertical) = match parent_node.layout {
+        NodeLayout::SplitH => (
+            (parent_rect.width as f64 / cells, parent_rect.height as f64),
+            (rect.width as f64, rect.height as f64 / 2.0),
+        ),
+        _ => (
+            (rect.width as f64 / 2.0, rect.height as f64),
+            (parent_rect.width as f64, parent_rect.height as f64 / cells),
+        ),
+    };
+
+    Some(closest_cells(horizontal, vertical, target_aspect))
+}
+
+/// The split whose cell (width, height) has the aspect ratio closest to `target_aspect`.
+///
+/// Ratios are compared on a logarithmic scale, so that a cell twice as wide as the target
+/// is as far as one twice as tall. Ties split horizontally.
+fn closest_cells(horizontal: (f64, f64), vertical: (f64, f64), target_aspect: f64) -> Split {
+    let distance = |(width, height): (f64, f64)| (width / height / target_aspect).ln().abs();
+
+    if distance(horizontal) <= distance(vertical) {
+        Split::Horizontal
+    } else {
+        Split::Vertical
     }
 }
 
@@ -85,6 +254,87 @@ pub fn find_node_by_id(node_id: usize, root_node: &RootNode) -> Option<&I3Node>
     None
 }
 
+/// Find a node by id.
+///
+/// Differently from `find_node_by_id`, floating nodes (and so the scratchpad) are inspected too.
+pub fn find_node_by_id_including_floating(node_id: usize, root_node: &RootNode) -> Option<&I3Node> {
+    let mut dfs = vec![root_node.node()];
+
+    while let Some(current) = dfs.pop() {
+        if current.id == node_id {
+            return Some(current);
+        }
+
+        dfs.extend(current.nodes.as_slice());
+        dfs.extend(current.floating_nodes.as_slice());
+    }
+
+    None
+}
+
+/// Find a node by its X11 window id, floating nodes (and so the scratchpad) included.
+///
+/// Unlike the con id, the X11 window id survives i3 restarts.
+pub fn find_node_by_window_including_floating(
+    window: usize,
+    root_node: &RootNode,
+) -> Option<&I3Node> {
+    let mut dfs = vec![root_node.node()];
+
+    while let Some(current) = dfs.pop() {
+        if current.window == Some(window) {
+            return Some(current);
+        }
+
+        dfs.extend(current.nodes.as_slice());
+        dfs.extend(current.floating_nodes.as_slice());
+    }
+
+    None
+}
+
+/// Find the node carrying a mark (floating nodes included).
+pub fn find_node_by_mark<'a>(mark: &str, root_node: &'a RootNode) -> Option<&'a I3Node> {
+    let mut dfs = vec![root_node.node()];
+
+    while let Some(current) = dfs.pop() {
+        if node_marks(current)
+            .iter()
+            .any(|node_mark| node_mark == mark)
+        {
+            return Some(current);
+        }
+
+        dfs.extend(current.nodes.as_slice());
+        dfs.extend(current.floating_nodes.as_slice());
+    }
+
+    None
+}
+
+/// Find the focused node.
+///
+/// Differently from other lookups, floating nodes are inspected too.
+pub fn find_focused_node(root_node: &RootNode) -> Option<&I3Node> {
+    let mut dfs = vec![root_node.node()];
+
+    while let Some(current) = dfs.pop() {
+        if current.focused {
+            return Some(current);
+        }
+
+        dfs.extend(current.nodes.as_slice());
+        dfs.extend(current.floating_nodes.as_slice());
+    }
+
+    None
+}
+
+/// Whether the workspace is the (hidden) scratchpad workspace.
+pub fn is_scratchpad_workspace(workspace: &I3Node) -> bool {
+    workspace.name.as_deref() == Some("__i3_scratch")
+}
+
 /// Find a node's parent.
 pub fn find_node_parent(node_id: usize, root_node: &RootNode) -> Option<&I3Node> {
     // It's not a real problem, but a waste of CPU cycles
@@ -108,27 +358,106 @@ pub fn find_node_parent(node_id: usize, root_node: &RootNode) -> Option<&I3Node>
     None
 }
 
-/// Find the workspace which contains the node.
+/// Find a node's parent.
 ///
-/// Note: a window might not be always associated with a workspace.
-/// For instance, floating windows or windows on scratchpad.
-pub fn find_workspace_of_node(node_id: usize, root_node: &RootNode) -> Option<&I3Node> {
-    let mut dfs = find_workspaces(root_node)
-        .into_iter()
-        .map(|workspace| (workspace, workspace))
-        .collect::<Vec<_>>();
+/// Differently from `find_node_parent`, floating nodes are inspected too: the parent of a
+/// floating window is its floating container, whose parent is the workspace.
+pub fn find_node_parent_including_floating(
+    node_id: usize,
+    root_node: &RootNode,
+) -> Option<&I3Node> {
+    debug_assert!(node_id != root_node.node().id);
+
+    let root = root_node.node();
+    let mut dfs: Vec<(&I3Node, &I3Node)> = root
+        .nodes
+        .iter()
+        .chain(root.floating_nodes.iter())
+        .map(|n| (n, root))
+        .collect();
 
-    while let Some((current, workspace)) = dfs.pop() {
+    while let Some((current, parent)) = dfs.pop() {
         if current.id == node_id {
-            return Some(workspace);
+            return Some(parent);
         }
 
-        dfs.extend(current.nodes.iter().map(|node| (node, workspace)));
+        dfs.extend(
+            current
+                .nodes
+                .iter()
+                .chain(current.floating_nodes.iter())
+                .map(|n| (n, current)),
+        );
     }
 
     None
 }
 
+/// Find the tiled ancestors of the node, from its parent up to its workspace.
+pub fn find_node_ancestors(node_id: usize, root_node: &RootNode) -> Vec<&I3Node> {
+    let mut ancestors = vec![];
+    let mut current_id = node_id;
+
+    while let Some(parent) = find_node_parent(current_id, root_node) {
+        ancestors.push(parent);
+
+        if parent.node_type == NodeType::Workspace {
+            break;
+        }
+
+        current_id = parent.id;
+    }
+
+    ancestors
+}
+
+/// Find the path from the root down to the node (both included), floating nodes included.
+///
+/// It is empty if the node is not in the tree.
+pub fn find_node_path(node_id: usize, root_node: &RootNode) -> Vec<&I3Node> {
+    let Some(node) = find_node_by_id_including_floating(node_id, root_node) else {
+        return vec![];
+    };
+
+    let mut path = vec![node];
+    let mut current_id = node_id;
+
+    while current_id != root_node.node().id {
+        let Some(parent) = find_node_parent_including_floating(current_id, root_node) else {
+            break;
+        };
+
+        path.push(parent);
+        current_id = parent.id;
+    }
+
+    path.reverse();
+    path
+}
+
+/// Find the workspace which contains the node.
+///
+/// The subtree (tiled and floating nodes) of each workspace is walked down separately,
+/// so the node is always attributed to the workspace actually containing it.
+///
+/// Note: windows on scratchpad belong to the scratchpad workspace (`__i3_scratch`).
+pub fn find_workspace_of_node(node_id: usize, root_node: &RootNode) -> Option<&I3Node> {
+    find_workspaces(root_node).into_iter().find(|workspace| {
+        let mut dfs = vec![*workspace];
+
+        while let Some(current) = dfs.pop() {
+            if current.id == node_id {
+                return true;
+            }
+
+            dfs.extend(current.nodes.as_slice());
+            dfs.extend(current.floating_nodes.as_slice());
+        }
+
+        false
+    })
+}
+
 /// Find all I3 nodes in the tree that are workspaces type.
 pub fn find_workspaces(root_node: &RootNode) -> Vec<&I3Node> {
     let mut workspaces = vec![];
@@ -158,9 +487,16 @@ pub fn find_workspace_by_num(root_node: &RootNode, workspace_num: i32) -> Option
     })
 }
 
+/// Find the workspace with the given name in the nodes tree.
+pub fn find_workspace_by_name<'a>(root_node: &'a RootNode, name: &str) -> Option<&'a I3Node> {
+    find_workspaces(root_node)
+        .into_iter()
+        .find(|workspace| workspace.name.as_deref() == Some(name))
+}
+
 /// Query and retrieve the currently focused workspace.
 ///
-/// It queries via `command_executor` the currently focused workspace number;
+/// It queries via `command_executor` the currently focused workspace name;
 /// afterwards, it performs a research across the give nodes tree for that workspace-node.
 ///
 /// *Note*: the `root_node` might be inconsistent (older state-snapshot).
@@ -168,28 +504,65 @@ pub fn query_workspace_focused<'a>(
     root_node: &'a RootNode,
     command_executor: &mut CommandExecutor,
 ) -> Result<&'a I3Node> {
-    let workspace_num = command_executor
+    let workspace_name = command_executor
         .query_workspaces()?
         .into_iter()
         .find(|workspace| workspace.focused)
         .ok_or_else(|| anyhow!("Cannot detect the current focused workspace"))?
-        .num;
+        .name;
 
-    find_workspace_by_num(root_node, workspace_num)
-        .ok_or_else(|| anyhow!("Cannot find the workspace number '{}'", workspace_num))
+    find_workspace_by_name(root_node, &workspace_name)
+        .ok_or_else(|| anyhow!("Cannot find the workspace '{}'", workspace_name))
 }
 
-/// Query workspaces and check if a given workspace is currently focused.
-pub fn query_workspace_is_focused(
-    workspace_num: i32,
+/// Query the workspace currently visible on an output (by name).
+///
+/// Errors for unknown (or inactive) outputs list the active ones.
+pub fn query_workspace_visible_on(
+    output: &str,
     command_executor: &mut CommandExecutor,
-) -> Result<bool> {
-    Ok(command_executor
-        .query_workspaces()?
+) -> Result<I3Workspace> {
+    let outputs = command_executor.query_outputs()?;
+    let workspaces = command_executor.query_workspaces()?;
+
+    workspace_visible_on(output, &outputs, workspaces)
+}
+
+/// The workspace visible on an output among `workspaces` (see `query_workspace_visible_on`).
+fn workspace_visible_on(
+    output: &str,
+    outputs: &[I3Output],
+    workspaces: Vec<I3Workspace>,
+) -> Result<I3Workspace> {
+    let active_outputs = outputs
+        .iter()
+        .filter(|candidate| candidate.active)
+        .map(|candidate| candidate.name.as_str())
+        .collect::<Vec<_>>();
+
+    if !active_outputs.contains(&output) {
+        return Err(anyhow!(
+            "Cannot find the output '{}' (available outputs: {})",
+            output,
+            active_outputs.join(", ")
+        ));
+    }
+
+    workspaces
         .into_iter()
-        .find(|workspace| workspace.focused)
-        .map(|workspace| workspace.num == workspace_num)
-        .unwrap_or_default())
+        .find(|workspace| workspace.visible && workspace.output == output)
+        .ok_or_else(|| anyhow!("No workspace is visible on output '{}'", output))
+}
+
+/// The I3 command which sets a layout.
+pub fn layout_command(layout: Layout) -> &'static str {
+    match layout {
+        Layout::Default => "layout default",
+        Layout::Tabbed => "layout tabbed",
+        Layout::SplitH => "layout splith",
+        Layout::SplitV => "layout splitv",
+        Layout::Stacked => "layout stacked",
+    }
 }
 
 /// Set the layout for a particular node.
@@ -198,13 +571,7 @@ pub fn set_node_layout(
     layout: Layout,
     command_executor: &mut CommandExecutor,
 ) -> Result<()> {
-    let layout_cmd = match layout {
-        Layout::Default => "layout default",
-        Layout::Tabbed => "layout tabbed",
-        Layout::SplitH => "layout splith",
-        Layout::SplitV => "layout splitv",
-        Layout::Stacked => "layout stacked",
-    };
+    let layout_cmd = layout_command(layout);
 
     command_executor
         .run_on_node_id(node_id, layout_cmd)
@@ -220,6 +587,7 @@ pub fn set_node_split(
     let split_cmd = match split {
         Split::Horizontal => "split horizontal",
         Split::Vertical => "split vertical",
+        Split::Toggle => "split toggle",
     };
 
     command_executor
@@ -227,6 +595,132 @@ pub fn set_node_split(
         .with_context(|| format!("Cannot split a node ('{}')", split_cmd))
 }
 
+/// Quote a string as argument of an i3 command.
+///
+/// Within double quotes i3 unescapes `\"` and `\\` only, while `;` and `,` lose their
+/// meaning of command separators: any string can be passed safely.
+pub fn quote(s: &str) -> String {
+    format!("\"{}\"", s.replace('\\', "\\\\").replace('"', "\\\""))
+}
+
+/// Quote a workspace name as argument of an i3 command.
+///
+/// Names which cannot designate a workspace (empty or containing control characters,
+/// e.g., a newline which would end the command) are rejected.
+pub fn quote_ws_name(name: &str) -> Result<String> {
+    if name.trim().is_empty() || name.chars().any(char::is_control) {
+        return Err(anyhow!("Invalid workspace name {:?}", name));
+    }
+
+    Ok(quote(name))
+}
+
+/// The border style of a window, as accepted by the i3 `border` command.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum BorderStyle {
+    /// Border with title bar (and optional width in pixels).
+    Normal(Option<u32>),
+
+    /// Border without title bar (and optional width in pixels).
+    Pixel(Option<u32>),
+
+    /// No border.
+    None,
+}
+
+impl BorderStyle {
+    /// The current border style of a window.
+    pub fn of(node: &I3Node) -> Self {
+        let width = u32::try_from(node.current_border_width).ok();
+
+        match node.border {
+            NodeBorder::Normal => Self::Normal(width),
+            NodeBorder::Pixel => Self::Pixel(width),
+            NodeBorder::None => Self::None,
+        }
+    }
+}
+
+impl FromStr for BorderStyle {
+    type Err = anyhow::Error;
+
+    /// Parse a style like `normal`, `pixel 2` or `none`.
+    fn from_str(s: &str) -> Result<Self> {
+        let invalid = || {
+            anyhow!(
+                "Invalid border style '{}' (expected 'normal [PX]', 'pixel [PX]' or 'none')",
+                s
+            )
+        };
+
+        let mut tokens = s.split_whitespace();
+        let style = tokens.next().ok_or_else(invalid)?;
+        let width = tokens
+            .next()
+            .map(|width| width.parse::<u32>().map_err(|_| invalid()))
+            .transpose()?;
+
+        if tokens.next().is_some() {
+            return Err(invalid());
+        }
+
+        match (style, width) {
+            ("normal", width) => Ok(Self::Normal(width)),
+            ("pixel", width) => Ok(Self::Pixel(width)),
+            ("none", None) => Ok(Self::None),
+            _ => Err(invalid()),
+        }
+    }
+}
+
+impl Display for BorderStyle {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Self::Normal(None) => write!(f, "normal"),
+            Self::Normal(Some(width)) => write!(f, "normal {}", width),
+            Self::Pixel(None) => write!(f, "pixel"),
+            Self::Pixel(Some(width)) => write!(f, "pixel {}", width),
+            Self::None => write!(f, "none"),
+        }
+    }
+}
+
+impl<'de> serde::Deserialize<'de> for BorderStyle {
+    fn deserialize<D>(deserializer: D) -> std::result::Result<Self, D::Error>
+    where
+        D: serde::Deserializer<'de>,
+    {
+        let style = String::deserialize(deserializer)?;
+        style.parse().map_err(serde::de::Error::custom)
+    }
+}
+
+impl serde::Serialize for BorderStyle {
+    fn serialize<S>(&self, serializer: S) -> std::result::Result<S::Ok, S::Error>
+    where
+        S: serde::Serializer,
+    {
+        serializer.serialize_str(&self.to_string())
+    }
+}
+
+/// Compile a glob pattern (`*` and `?` wildcards) matching a whole string.
+pub fn glob_regex(pattern: &str) -> Regex {
+    let mut regex = String::from("^");
+
+    for c in pattern.chars() {
+        match c {
+            '*' => regex.push_str(".*"),
+            '?' => regex.push('.'),
+            c => regex.push_str(&regex::escape(&c.to_string())),
+        }
+    }
+
+    regex.push('$');
+
+    Regex::new(&regex).expect("Expected glob to be a valid regex")
+}
+
 /// Check whether the node is a floating container or not.
 pub fn is_floating_container(node: &I3Node) -> bool {
     match node.floating {
@@ -235,11 +729,1449 @@ pub fn is_floating_container(node: &I3Node) -> bool {
     }
 }
 
-/// Check the ratio of a node.
-pub fn ratio_of_node(node: &I3Node) -> RectRatio {
-    if node.window_rect.height > node.window_rect.width {
-        RectRatio::Vertical
-    } else {
-        RectRatio::Horizontal
+/// The marks associated with a node.
+pub fn node_marks(node: &I3Node) -> Vec<String> {
+    node.marks
+        .as_ref()
+        .and_then(|marks| serde_json::to_value(marks).ok())
+        .and_then(|marks| serde_json::from_value(marks).ok())
+        .unwrap_or_default()
+}
+
+/// Apply marks to containers, keeping the marks they already have.
+///
+/// `marks` pairs each container id with its marks. i3 allows a mark on a single container:
+/// a mark requested for more containers, or held by another container already, is reported
+/// and skipped.
+pub fn apply_marks(
+    marks: &[(usize, Vec<String>)],
+    root_node: &RootNode,
+    command_executor: &mut CommandExecutor,
+) -> Result<()> {
+    let mut holders = HashMap::new();
+    let mut dfs = vec![root_node.node()];
+
+    while let Some(current) = dfs.pop() {
+        for mark in node_marks(current) {
+            holders.insert(mark, current.id);
+        }
+
+        dfs.extend(current.nodes.as_slice());
+        dfs.extend(current.floating_nodes.as_slice());
+    }
+
+    let mut requested = HashMap::new();
+
+    for (node_id, node_marks) in marks {
+        for mark in node_marks {
+            if let Some(other_id) = requested.insert(mark.as_str(), *node_id) {
+                eprintln!(
+                    "[WARN]: Duplicated mark '{}' (containers '{}' and '{}'); keeping it on '{}'",
+                    mark, other_id, node_id, other_id
+                );
+                requested.insert(mark.as_str(), other_id);
+                continue;
+            }
+
+            match holders.get(mark) {
+                Some(holder_id) if holder_id == node_id => {}
+
+                Some(holder_id) => eprintln!(
+                    "[WARN]: Cannot mark container '{}' with '{}' (already on container '{}')",
+                    node_id, mark, holder_id
+                ),
+
+                None => command_executor
+                    .run_on_node_id(*node_id, format!("mark --add {}", quote(mark)))
+                    .with_context(|| format!("Cannot mark container '{}'", node_id))?,
+            }
+        }
+    }
+
+    Ok(())
+}
+
+/// Whether the node is a leaf holding an application window.
+pub fn is_window(node: &I3Node) -> bool {
+    node.window.is_some() && node.nodes.is_empty()
+}
+
+/// A description of a window for the user: its class and title (or its con id).
+pub fn describe_window(node: &I3Node) -> String {
+    let properties = node.window_properties.as_ref();
+    let class = properties.and_then(|properties| properties.class.as_deref());
+    let title = properties
+        .and_then(|properties| properties.title.as_deref())
+        .or(node.name.as_deref());
+
+    match (class, title) {
+        (Some(class), Some(title)) => format!("{} ({:?})", class, title),
+        (Some(class), None) => class.to_string(),
+        (None, Some(title)) => format!("{:?}", title),
+        (None, None) => format!("con {}", node.id),
+    }
+}
+
+/// The number of tiled windows of a subtree (e.g., a workspace).
+pub fn count_windows(subtree: &I3Node) -> usize {
+    let mut windows = 0;
+    let mut dfs = vec![subtree];
+
+    while let Some(current) = dfs.pop() {
+        if is_window(current) {
+            windows += 1;
+        }
+
+        dfs.extend(current.nodes.as_slice());
+    }
+
+    windows
+}
+
+/// The default tolerance (percentage points) of the verification of shares.
+pub const DEFAULT_SHARE_TOLERANCE: f64 = 1.0;
+
+/// The maximum number of correction rounds of the verification of shares.
+const SHARE_CORRECTION_ROUNDS: usize = 2;
+
+/// Equally distribute the space among the children of each split container in the subtree.
+///
+/// The resizes are planned by `plan_balance`. The result is verified with `verify_shares`.
+pub fn balance_subtree(
+    subtree: &I3Node,
+    ratio: &ShareRatio,
+    tolerance: f64,
+    command_executor: &mut CommandExecutor,
+) -> Result<()> {
+    let plan = plan_balance(subtree, ratio);
+
+    for (node_id, command) in plan.resizes {
+        command_executor
+            .run_on_node_id(node_id, command)
+            .context("Cannot resize a node while balancing")?;
+    }
+
+    for (parent_id, targets) in plan.targets {
+        verify_shares(parent_id, &targets, tolerance, command_executor)?;
+    }
+
+    Ok(())
+}
+
+/// The resizes balancing a subtree (see `plan_balance`).
+pub struct BalancePlan {
+    /// The resizes (node id and command).
+    pub resizes: Vec<(usize, String)>,
+
+    /// The target shares of the children of each balanced container (see `balanced_shares`),
+    /// by container id.
+    pub targets: Vec<(usize, Vec<(usize, f64)>)>,
+}
+
+/// The resizes balancing the subtree.
+///
+/// Tabbed and stacked containers are left untouched (but their descendants are balanced).
+/// Children holding a size lock keep their share. The last unlocked child of each container
+/// is not resized: it takes the remaining space.
+pub fn plan_balance(subtree: &I3Node, ratio: &ShareRatio) -> BalancePlan {
+    let mut resizes = vec![];
+    let mut balanced = vec![];
+    let mut dfs = vec![subtree];
+
+    while let Some(current) = dfs.pop() {
+        if let Some(dimension) = split_dimension(current) {
+            if current.nodes.len() > 1 {
+                let targets = balanced_shares(current, ratio);
+
+                let unlocked = current
+                    .nodes
+                    .iter()
+                    .filter(|child| !holds_size_lock(child))
+                    .collect::<Vec<_>>();
+
+                for child in unlocked.iter().take(unlocked.len().saturating_sub(1)) {
+                    let Some(&(_, share)) =
+                        targets.iter().find(|(node_id, _)| *node_id == child.id)
+                    else {
+                        continue;
+                    };
+
+                    resizes.push((
+                        child.id,
+                        format!("resize set {} {} ppt", dimension, share.floor()),
+                    ));
+                }
+
+                balanced.push((current.id, targets));
+            }
+        }
+
+        dfs.extend(current.nodes.as_slice());
+    }
+
+    BalancePlan {
+        resizes,
+        targets: balanced,
+    }
+}
+
+/// The balanced shares (percentage points) of the children of a split container.
+///
+/// Children holding a size lock (see `holds_size_lock`) keep their current share: the
+/// rest of the container is divided among the others according to `ratio`.
+pub fn balanced_shares(parent: &I3Node, ratio: &ShareRatio) -> Vec<(usize, f64)> {
+    let Some(dimension) = split_dimension(parent) else {
+        return vec![];
+    };
+
+    let size_of = |node: &I3Node| match dimension {
+        "width" => node.rect.width.max(0) as f64,
+        _ => node.rect.height.max(0) as f64,
+    };
+
+    let total = parent.nodes.iter().map(size_of).sum::<f64>();
+    let locked = parent
+        .nodes
+        .iter()
+        .filter(|child| holds_size_lock(child))
+        .map(|child| (child.id, size_of(child) * 100.0 / total.max(1.0)))
+        .collect::<Vec<_>>();
+
+    let num_unlocked = parent.nodes.len() - locked.len();
+    let locked_share = locked.iter().map(|(_, share)| share).sum::<f64>();
+    let scale = (100.0 - locked_share).max(0.0) / 100.0;
+    let mut shares = ratio_shares(num_unlocked, ratio).into_iter();
+
+    parent
+        .nodes
+        .iter()
+        .map(|child| {
+            locked
+                .iter()
+                .find(|(node_id, _)| *node_id == child.id)
+                .copied()
+                .unwrap_or_else(|| {
+                    let share = shares.next().unwrap_or_default();
+                    (child.id, share as f64 * scale)
+                })
+        })
+        .collect()
+}
+
+/// How the space of a container is divided among its children.
+#[derive(Clone, Debug, Default, PartialEq)]
+pub enum ShareRatio {
+    /// Equal shares.
+    #[default]
+    Equal,
+
+    /// The first child gets the golden share (~62%) and the next ones divide the
+    /// remainder by the same rule.
+    Golden,
+
+    /// Shares proportional to these weights when there are as many children; otherwise
+    /// the first child gets the share of the first weight and the next ones divide the
+    /// remainder by the same rule (e.g., `60,40` gives `60, 24, 16` to three children).
+    Weights(Vec<u32>),
+}
+
+impl ShareRatio {
+    /// The share of the first child in the golden ratio cascade.
+    const GOLDEN_SHARE: f64 = 0.618_033_988_749_895;
+}
+
+impl FromStr for ShareRatio {
+    type Err = anyhow::Error;
+
+    /// Parse `equal`, `golden` or a comma-separated list of weights (e.g., `60,40`).
+    fn from_str(s: &str) -> Result<Self> {
+        match s {
+            "equal" => return Ok(Self::Equal),
+            "golden" => return Ok(Self::Golden),
+            _ => {}
+        }
+
+        let weights = s
+            .split(',')
+            .map(|token| {
+                token
+                    .trim()
+                    .parse::<u32>()
+                    .ok()
+                    .filter(|weight| *weight > 0)
+                    .ok_or_else(|| {
+                        anyhow!(
+                            "Invalid ratio token '{}' in '{}' (expected 'equal', 'golden' or positive weights like '60,40')",
+                            token,
+                            s
+                        )
+                    })
+            })
+            .collect::<Result<Vec<_>>>()?;
+
+        if weights.len() < 2 {
+            return Err(anyhow!(
+                "Invalid ratio '{}' (a list needs at least two weights, e.g., '60,40')",
+                s
+            ));
+        }
+
+        Ok(Self::Weights(weights))
+    }
+}
+
+impl Display for ShareRatio {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Self::Equal => write!(f, "equal"),
+            Self::Golden => write!(f, "golden"),
+            Self::Weights(weights) => {
+                let weights = weights.iter().map(u32::to_string).collect::<Vec<_>>();
+                write!(f, "{}", weights.join(","))
+            }
+        }
+    }
+}
+
+impl<'de> serde::Deserialize<'de> for ShareRatio {
+    fn deserialize<D>(deserializer: D) -> std::result::Result<Self, D::Error>
+    where
+        D: serde::Deserializer<'de>,
+    {
+        let ratio = String::deserialize(deserializer)?;
+        ratio.parse().map_err(serde::de::Error::custom)
+    }
+}
+
+impl serde::Serialize for ShareRatio {
+    fn serialize<S>(&self, serializer: S) -> std::result::Result<S::Ok, S::Error>
+    where
+        S: serde::Serializer,
+    {
+        serializer.serialize_str(&self.to_string())
+    }
+}
+
+/// The shares (percentage points) of `count` children divided by `ratio`.
+///
+/// Shares are whole and sum to 100: the points lost by rounding down go to the largest
+/// remainders (the first children on ties).
+pub fn ratio_shares(count: usize, ratio: &ShareRatio) -> Vec<u32> {
+    if count == 0 {
+        return vec![];
+    }
+
+    let cascade = |first_share: f64| {
+        let mut remaining = 100.0;
+        let mut shares = vec![];
+
+        for _ in 1..count {
+            let share = remaining * first_share;
+            shares.push(share);
+            remaining -= share;
+        }
+
+        shares.push(remaining);
+        shares
+    };
+
+    let exact = match ratio {
+        ShareRatio::Equal => vec![100.0 / count as f64; count],
+        ShareRatio::Golden => cascade(ShareRatio::GOLDEN_SHARE),
+        ShareRatio::Weights(weights) => {
+            let total = weights.iter().sum::<u32>() as f64;
+
+            if weights.len() == count {
+                weights
+                    .iter()
+                    .map(|weight| *weight as f64 * 100.0 / total)
+                    .collect()
+            } else {
+                cascade(weights[0] as f64 / total)
+            }
+        }
+    };
+
+    let mut shares = exact
+        .iter()
+        .map(|share| share.floor() as u32)
+        .collect::<Vec<_>>();
+
+    let mut by_remainder = (0..count).collect::<Vec<_>>();
+    by_remainder.sort_by(|&a, &b| {
+        let remainder = |index: usize| exact[index] - exact[index].floor();
+        remainder(b).total_cmp(&remainder(a)).then(a.cmp(&b))
+    });
+
+    let missing = 100 - shares.iter().sum::<u32>();
+    for &index in by_remainder.iter().cycle().take(missing as usize) {
+        shares[index] += 1;
+    }
+
+    shares
+}
+
+/// Whether resizing a node would resize a container with a locked size, i.e., the node
+/// or one of its descendants carries a size lock mark (see `lock-size`).
+pub fn holds_size_lock(node: &I3Node) -> bool {
+    let mut dfs = vec![node];
+
+    while let Some(current) = dfs.pop() {
+        if has_size_lock_mark(current) {
+            return true;
+        }
+
+        dfs.extend(current.nodes.as_slice());
+    }
+
+    false
+}
+
+/// Whether the size of a node is locked, by itself, one of its descendants or one of its
+/// ancestors (whose size changes along with it).
+pub fn is_size_locked(node_id: usize, root_node: &RootNode) -> bool {
+    find_node_by_id(node_id, root_node).is_some_and(holds_size_lock)
+        || find_node_ancestors(node_id, root_node)
+            .into_iter()
+            .any(has_size_lock_mark)
+}
+
+/// Whether a node carries a size lock mark.
+fn has_size_lock_mark(node: &I3Node) -> bool {
+    node_marks(node)
+        .iter()
+        .any(|mark| mark.starts_with(SIZE_LOCK_MARK_PREFIX))
+}
+
+/// The dimension (`width` or `height`) along which a container splits its children.
+///
+/// It is `None` for tabbed and stacked containers.
+pub fn split_dimension(node: &I3Node) -> Option<&'static str> {
+    match node.layout {
+        NodeLayout::SplitH => Some("width"),
+        NodeLayout::SplitV => Some("height"),
+        _ => None,
+    }
+}
+
+/// A resize moving the share of a container towards its target.
+#[derive(Debug, PartialEq, Eq)]
+pub struct ShareCorrection {
+    /// The container.
+    pub node_id: usize,
+
+    /// Whether the container grows (or shrinks).
+    pub grow: bool,
+
+    /// The resized dimension (`width` or `height`).
+    pub dimension: &'static str,
+
+    /// The amount of the resize.
+    pub px: usize,
+}
+
+impl Display for ShareCorrection {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(
+            f,
+            "resize {} {} {} px",
+            if self.grow { "grow" } else { "shrink" },
+            self.dimension,
+            self.px
+        )
+    }
+}
+
+/// Compute the resizes bringing the children of `parent` to their target shares.
+///
+/// `targets` maps children to their share (percentage points) of the parent; the actual
+/// shares are computed from the rects of the children. Children deviating by at most
+/// `tolerance` are left alone, as are children without target.
+///
+/// i3 takes the space of a resize evenly from the siblings: the effect of each correction
+/// on the following ones is accounted for, but a further round may still be needed.
+pub fn share_corrections(
+    parent: &I3Node,
+    targets: &[(usize, f64)],
+    tolerance: f64,
+) -> Vec<ShareCorrection> {
+    let Some(dimension) = split_dimension(parent) else {
+        return vec![];
+    };
+
+    let num_children = parent.nodes.len();
+    if num_children < 2 {
+        return vec![];
+    }
+
+    if sane_rect(&parent.rect).is_none() {
+        return vec![];
+    }
+
+    let size_of = |rect: &Rect| match dimension {
+        "width" => rect.width.max(0),
+        _ => rect.height.max(0),
+    };
+
+    let mut sizes = parent
+        .nodes
+        .iter()
+        .map(|child| size_of(&child.rect) as f64)
+        .collect::<Vec<_>>();
+    let total = sizes.iter().sum::<f64>();
+
+    if total <= 0.0 {
+        return vec![];
+    }
+
+    let mut corrections = vec![];
+
+    for (index, child) in parent.nodes.iter().enumerate() {
+        let Some(&(_, target)) = targets.iter().find(|(node_id, _)| *node_id == child.id) else {
+            continue;
+        };
+
+        let deviation = target - sizes[index] * 100.0 / total;
+        if deviation.abs() <= tolerance {
+            continue;
+        }
+
+        let px = (deviation.abs() * total / 100.0).round();
+        if px < 1.0 {
+            continue;
+        }
+
+        let delta = px.copysign(deviation);
+        let sibling_delta = delta / (num_children - 1) as f64;
+
+        for (sibling_index, size) in sizes.iter_mut().enumerate() {
+            if sibling_index == index {
+                *size += delta;
+            } else {
+                *size -= sibling_delta;
+            }
+        }
+
+        corrections.push(ShareCorrection {
+            node_id: child.id,
+            grow: deviation > 0.0,
+            dimension,
+            px: px as usize,
+        });
+    }
+
+    corrections
+}
+
+/// Verify that the children of a container have their target shares, correcting the drift.
+///
+/// i3 rounds sizes to pixels, so actual shares may end up off their targets (see
+/// `share_corrections`). At most `SHARE_CORRECTION_ROUNDS` rounds of corrections are
+/// issued; the remaining drift is reported as a warning.
+pub fn verify_shares(
+    parent_id: usize,
+    targets: &[(usize, f64)],
+    tolerance: f64,
+    command_executor: &mut CommandExecutor,
+) -> Result<()> {
+    for round in 0..=SHARE_CORRECTION_ROUNDS {
+        let root_node = command_executor.query_root_node()?;
+
+        let Some(parent) = find_node_by_id(parent_id, &root_node) else {
+            return Ok(());
+        };
+
+        let corrections = share_corrections(parent, targets, tolerance);
+        if corrections.is_empty() {
+            return Ok(());
+        }
+
+        if round == SHARE_CORRECTION_ROUNDS {
+            eprintln!(
+                "[WARN]: The shares of container '{}' drift beyond {} ppt after {} corrections",
+                parent_id, tolerance, SHARE_CORRECTION_ROUNDS
+            );
+            return Ok(());
+        }
+
+        for correction in corrections {
+            command_executor
+                .run_on_node_id(correction.node_id, correction.to_string())
+                .with_context(|| {
+                    format!("Cannot correct the size of node '{}'", correction.node_id)
+                })?;
+        }
+    }
+
+    Ok(())
+}
+
+/// The directory where i3-autolayout keeps its configuration.
+///
+/// It follows the XDG base directory specification (`$XDG_CONFIG_HOME/i3-autolayout`).
+/// The directory is created if it does not exist.
+pub fn config_dir() -> Result<PathBuf> {
+    xdg_dir("XDG_CONFIG_HOME", ".config")
+}
+
+/// The directory where i3-autolayout keeps its persistent state.
+///
+/// It follows the XDG base directory specification (`$XDG_STATE_HOME/i3-autolayout`).
+/// The directory is created if it does not exist.
+pub fn state_dir() -> Result<PathBuf> {
+    xdg_dir("XDG_STATE_HOME", ".local/state")
+}
+
+/// The directory where i3-autolayout keeps runtime (non persistent) state.
+///
+/// It is `$XDG_RUNTIME_DIR/i3-autolayout`, with a fallback in the temporary directory
+/// (see `private_dir`). The directory is created if it does not exist.
+pub fn runtime_dir() -> Result<PathBuf> {
+    match std::env::var_os("XDG_RUNTIME_DIR") {
+        Some(dir) if !dir.is_empty() => {
+            let dir = PathBuf::from(dir).join("i3-autolayout");
+
+            std::fs::create_dir_all(&dir)
+                .with_context(|| format!("Cannot create the directory '{}'", dir.display()))?;
+
+            Ok(dir)
+        }
+
+        _ => {
+            let dir = std::env::temp_dir().join(format!(
+                "i3-autolayout-{}",
+                std::env::var("USER").unwrap_or_default()
+            ));
+
+            private_dir(&dir)?;
+            Ok(dir)
+        }
+    }
+}
+
+/// Create a directory usable by the current user only, in a directory shared with other
+/// users (e.g., `/tmp`).
+///
+/// The directory is created with mode 0700. An existing one must be a real directory (not
+/// a symlink) owned by the current user, otherwise another user could have prepared it to
+/// read or forge the state; its permissions are restricted if needed.
+fn private_dir(dir: &Path) -> Result<()> {
+    match DirBuilder::new().mode(0o700).create(dir) {
+        Ok(()) => return Ok(()),
+        Err(error) if error.kind() == ErrorKind::AlreadyExists => {}
+        Err(error) => {
+            return Err(error)
+                .with_context(|| format!("Cannot create the directory '{}'", dir.display()))
+        }
+    }
+
+    let metadata = std::fs::symlink_metadata(dir)
+        .with_context(|| format!("Cannot inspect the directory '{}'", dir.display()))?;
+
+    if !metadata.file_type().is_dir() {
+        return Err(anyhow!(
+            "Cannot use '{}': it is not a directory (or it is a symlink)",
+            dir.display()
+        ));
+    }
+
+    if metadata.uid() != current_uid()? {
+        return Err(anyhow!(
+            "Cannot use the directory '{}': it is owned by another user",
+            dir.display()
+        ));
+    }
+
+    if metadata.mode() & 0o077 != 0 {
+        std::fs::set_permissions(dir, Permissions::from_mode(0o700))
+            .with_context(|| format!("Cannot restrict the permissions of '{}'", dir.display()))?;
+    }
+
+    Ok(())
+}
+
+/// The user id of the process: the owner of `/proc/self`, or of a file it creates.
+fn current_uid() -> Result<u32> {
+    if let Ok(metadata) = std::fs::metadata("/proc/self") {
+        return Ok(metadata.uid());
+    }
+
+    let probe = std::env::temp_dir().join(format!("i3-autolayout-uid-{}", std::process::id()));
+    let file = OpenOptions::new()
+        .write(true)
+        .create_new(true)
+        .mode(0o600)
+        .open(&probe)
+        .context("Cannot detect the user id")?;
+    let uid = file.metadata().map(|metadata| metadata.uid());
+    let _ = std::fs::remove_file(&probe);
+
+    uid.context("Cannot detect the user id")
+}
+
+/// Expand a leading `~/` of a path to the home directory (left as is if `$HOME` is not set).
+pub fn expand_home(path: &str) -> PathBuf {
+    match (path.strip_prefix("~/"), std::env::var_os("HOME")) {
+        (Some(rest), Some(home)) => PathBuf::from(home).join(rest),
+        _ => PathBuf::from(path),
+    }
+}
+
+/// A short human-readable duration (e.g., `3h`), rounded down to its largest unit.
+pub fn format_age(age: Duration) -> String {
+    let age = age.as_secs();
+
+    match age {
+        0..=59 => format!("{}s", age),
+        60..=3599 => format!("{}m", age / 60),
+        3600..=86399 => format!("{}h", age / 3600),
+        _ => format!("{}d", age / 86400),
+    }
+}
+
+/// The i3-autolayout directory inside an XDG base directory.
+///
+/// `env_var` is the XDG variable, `home_fallback` the default path relative to `$HOME`.
+fn xdg_dir(env_var: &str, home_fallback: &str) -> Result<PathBuf> {
+    let base = match std::env::var_os(env_var) {
+        Some(dir) if !dir.is_empty() => PathBuf::from(dir),
+        _ => std::env::var_os("HOME")
+            .map(|home| PathBuf::from(home).join(home_fallback))
+            .ok_or_else(|| anyhow!("Cannot detect ${} directory ($HOME is not set)", env_var))?,
+    };
+
+    let dir = base.join("i3-autolayout");
+
+    std::fs::create_dir_all(&dir)
+        .with_context(|| format!("Cannot create the directory '{}'", dir.display()))?;
+
+    Ok(dir)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::fixtures;
+
+    fn workspace_names<'a>(workspaces: impl IntoIterator<Item = &'a I3Node>) -> Vec<String> {
+        let mut names = workspaces
+            .into_iter()
+            .map(|workspace| workspace.name.clone().unwrap_or_default())
+            .collect::<Vec<_>>();
+        names.sort();
+        names
+    }
+
+    #[test]
+    fn find_node_parent_nested() {
+        let root_node = fixtures::load("workspaces.json");
+
+        assert_eq!(find_node_parent(201, &root_node).unwrap().id, 200);
+        assert_eq!(find_node_parent(211, &root_node).unwrap().id, 210);
+        assert_eq!(find_node_parent(222, &root_node).unwrap().id, 220);
+        assert_eq!(find_node_parent(220, &root_node).unwrap().id, 210);
+        assert!(find_node_parent(9999, &root_node).is_none());
+    }
+
+    #[test]
+    fn find_node_parent_of_workspace_is_content() {
+        let root_node = fixtures::load("multi_output.json");
+
+        let content = find_node_parent(200, &root_node).unwrap();
+        assert_eq!(content.name.as_deref(), Some("content"));
+        assert_eq!(content.output.as_deref(), Some("HDMI-1"));
+
+        let output = find_node_parent(content.id, &root_node).unwrap();
+        assert_eq!(output.node_type, NodeType::Output);
+        assert_eq!(find_node_parent(output.id, &root_node).unwrap().id, 1);
+    }
+
+    #[test]
+    fn find_node_parent_skips_floating() {
+        let root_node = fixtures::load("floating.json");
+
+        assert!(find_node_parent(111, &root_node).is_none());
+        assert!(find_node_parent(110, &root_node).is_none());
+
+        assert_eq!(
+            find_node_parent_including_floating(111, &root_node)
+                .unwrap()
+                .id,
+            110
+        );
+        assert_eq!(
+            find_node_parent_including_floating(110, &root_node)
+                .unwrap()
+                .id,
+            100
+        );
+    }
+
+    #[test]
+    fn find_workspace_of_node_multi_output() {
+        let root_node = fixtures::load("multi_output.json");
+        let workspace_of =
+            |node_id| find_workspace_of_node(node_id, &root_node).and_then(|ws| ws.name.as_deref());
+
+        assert_eq!(workspace_of(101), Some("1"));
+        assert_eq!(workspace_of(301), Some("3"));
+        assert_eq!(workspace_of(212), Some("2"));
+        assert_eq!(workspace_of(210), Some("2"));
+        assert_eq!(workspace_of(200), Some("2"));
+
+        // The bar is in a dockarea, outside of any workspace.
+        assert_eq!(workspace_of(15), None);
+        assert_eq!(workspace_of(9999), None);
+    }
+
+    #[test]
+    fn find_workspace_of_node_floating_and_scratchpad() {
+        let root_node = fixtures::load("floating.json");
+
+        assert_eq!(find_workspace_of_node(121, &root_node).unwrap().id, 100);
+        assert_eq!(find_workspace_of_node(221, &root_node).unwrap().id, 200);
+
+        let root_node = fixtures::load("scratchpad.json");
+
+        // Shown on a workspace.
+        assert_eq!(find_workspace_of_node(111, &root_node).unwrap().id, 100);
+
+        // Hidden.
+        let workspace = find_workspace_of_node(41, &root_node).unwrap();
+        assert!(is_scratchpad_workspace(workspace));
+    }
+
+    fn outputs_and_workspaces() -> (Vec<I3Output>, Vec<I3Workspace>) {
+        let rect = serde_json::json!({ "x": 0, "y": 0, "width": 1920, "height": 1080 });
+        let output = |name: &str, active: bool, current: Option<&str>| {
+            serde_json::json!({
+                "name": name,
+                "active": active,
+                "primary": false,
+                "rect": rect,
+                "current_workspace": current,
+            })
+        };
+        let workspace = |num: i32, name: &str, visible: bool, output: &str| {
+            serde_json::json!({
+                "id": num,
+                "num": num,
+                "name": name,
+                "visible": visible,
+                "focused": false,
+                "urgent": false,
+                "rect": rect,
+                "output": output,
+            })
+        };
+
+        let outputs = serde_json::json!([
+            output("eDP-1", true, Some("3")),
+            output("HDMI-1", true, Some("9: chat")),
+            output("DP-2", true, None),
+            output("DP-3", false, None),
+        ]);
+        let workspaces = serde_json::json!([
+            workspace(1, "1", false, "eDP-1"),
+            workspace(3, "3", true, "eDP-1"),
+            workspace(2, "2", false, "HDMI-1"),
+            workspace(9, "9: chat", true, "HDMI-1"),
+        ]);
+
+        (
+            serde_json::from_value(outputs).unwrap(),
+            serde_json::from_value(workspaces).unwrap(),
+        )
+    }
+
+    #[test]
+    fn workspace_visible_on_outputs() {
+        let (outputs, workspaces) = outputs_and_workspaces();
+
+        for (output, name) in [("eDP-1", "3"), ("HDMI-1", "9: chat")] {
+            let workspace = workspace_visible_on(output, &outputs, workspaces.clone()).unwrap();
+            assert_eq!(workspace.name, name);
+        }
+
+        assert_eq!(
+            workspace_visible_on("DP-2", &outputs, workspaces.clone())
+                .err()
+                .unwrap()
+                .to_string(),
+            "No workspace is visible on output 'DP-2'"
+        );
+
+        // Inactive and unknown outputs.
+        for output in ["DP-3", "VGA-1"] {
+            assert_eq!(
+                workspace_visible_on(output, &outputs, workspaces.clone())
+                    .err()
+                    .unwrap()
+                    .to_string(),
+                format!(
+                    "Cannot find the output '{}' (available outputs: eDP-1, HDMI-1, DP-2)",
+                    output
+                )
+            );
+        }
+    }
+
+    #[test]
+    fn find_workspaces_all_outputs() {
+        let root_node = fixtures::load("multi_output.json");
+
+        assert_eq!(
+            workspace_names(find_workspaces(&root_node)),
+            ["1", "2", "3", "9: chat", "__i3_scratch"]
+        );
+
+        let root_node = fixtures::load("workspaces.json");
+
+        assert_eq!(
+            workspace_names(find_workspaces(&root_node)),
+            ["1", "2", "3 []", "5", "6", "__i3_scratch", "web"]
+        );
+    }
+
+    #[test]
+    fn find_workspace_by_num_and_name() {
+        let root_node = fixtures::load("workspaces.json");
+
+        assert_eq!(find_workspace_by_num(&root_node, 2).unwrap().id, 200);
+        assert_eq!(find_workspace_by_name(&root_node, "web").unwrap().id, 400);
+        assert!(find_workspace_by_num(&root_node, 4).is_none());
+    }
+
+    const TRICKY_NAMES: [&str; 9] = [
+        "ws\"1",
+        "a\\b",
+        "1; kill",
+        "\"] kill, [class=\"",
+        "2: ✉ mail",
+        "日本語",
+        "trailing\\",
+        "trailing\\\\",
+        "\\\"",
+    ];
+
+    #[test]
+    fn quote_exact_text() {
+        assert_eq!(quote("1"), r#""1""#);
+        assert_eq!(quote("ws\"1"), r#""ws\"1""#);
+        assert_eq!(quote("a\\b"), r#""a\\b""#);
+        assert_eq!(quote("1; kill"), r#""1; kill""#);
+        assert_eq!(quote("\"] kill, [class=\""), r#""\"] kill, [class=\"""#);
+        assert_eq!(quote("2: ✉ mail"), "\"2: ✉ mail\"");
+        assert_eq!(quote("trailing\\"), r#""trailing\\""#);
+    }
+
+    #[test]
+    fn quote_does_not_escape_the_quotes() {
+        for name in TRICKY_NAMES {
+            let command = format!("workspace {}; nop", quote(name));
+            let argument = command.strip_prefix("workspace ").unwrap();
+
+            let (unquoted, rest) = fixtures::unquote(argument);
+            assert_eq!(unquoted, name);
+            assert_eq!(rest, "; nop", "{:?} escaped the quotes", name);
+        }
+    }
+
+    #[test]
+    fn quote_ws_name_accepts_tricky_names() {
+        for name in TRICKY_NAMES {
+            assert_eq!(quote_ws_name(name).unwrap(), quote(name));
+        }
+
+        assert_eq!(
+            quote_ws_name("\"] kill, [class=\"").unwrap(),
+            r#""\"] kill, [class=\"""#
+        );
+    }
+
+    #[test]
+    fn quote_ws_name_rejects_invalid_names() {
+        for name in ["", "  ", "1\nkill", "1\r", "\t", "a\u{7f}"] {
+            assert!(quote_ws_name(name).is_err(), "{:?} accepted", name);
+        }
+    }
+
+    fn rect(x: isize, y: isize, width: isize, height: isize) -> Rect {
+        Rect {
+            x,
+            y,
+            width,
+            height,
+        }
+    }
+
+    /// A window of the fixtures with the given container and window rects.
+    fn window_with_rects(container: Rect, window: Rect) -> I3Node {
+        let root_node = fixtures::load("workspaces.json");
+        let mut node = fixtures::node(&root_node, 101).clone();
+
+        node.rect = container;
+        node.window_rect = window;
+        node
+    }
+
+    #[test]
+    fn sane_rect_rejects_empty_rects() {
+        assert!(sane_rect(&rect(0, 0, 0, 0)).is_none());
+        assert!(sane_rect(&rect(0, 0, 100, 0)).is_none());
+        assert!(sane_rect(&rect(0, 0, 0, 100)).is_none());
+        assert!(sane_rect(&rect(0, 0, -100, 100)).is_none());
+        assert!(sane_rect(&rect(0, 0, 1, 1)).is_some());
+    }
+
+    #[test]
+    fn sane_rect_accepts_negative_coordinates() {
+        assert!(sane_rect(&rect(-1920, 0, 1920, 1080)).is_some());
+        assert!(sane_rect(&rect(0, -1080, 1920, 1080)).is_some());
+        assert!(sane_rect(&rect(-1920, -1080, 1920, 1080)).is_some());
+    }
+
+    #[test]
+    fn decide_table() {
+        let landscape = window_with_rects(rect(0, 0, 1920, 1080), rect(0, 0, 0, 0));
+        let portrait = window_with_rects(rect(0, 0, 1080, 1920), rect(0, 0, 0, 0));
+
+        // A wide container holding a tall window (e.g., a terminal with size hints).
+        let mixed = window_with_rects(rect(0, 0, 800, 600), rect(0, 0, 300, 600));
+
+        for (rect_source, threshold, workspace, expected) in [
+            (RectSource::Window, 1.0, None, Split::Vertical),
+            (RectSource::Container, 1.0, None, Split::Horizontal),
+            // The threshold is exclusive.
+            (RectSource::Window, 1.9, None, Split::Vertical),
+            (RectSource::Window, 2.0, None, Split::Horizontal),
+            (RectSource::Container, 0.5, None, Split::Vertical),
+            (RectSource::Window, 1.0, Some(&landscape), Split::Vertical),
+            (
+                RectSource::Container,
+                1.0,
+                Some(&landscape),
+                Split::Horizontal,
+            ),
+            (RectSource::Container, 1.0, Some(&portrait), Split::Vertical),
+            (RectSource::Window, 2.0, Some(&portrait), Split::Horizontal),
+        ] {
+            let policy = OrientationPolicy {
+                threshold,
+                rect_source,
+                square_band: 0.0,
+            };
+
+            assert_eq!(
+                policy.decide(&mixed, workspace),
+                Some(expected),
+                "threshold {} on workspace {:?}",
+                threshold,
+                workspace.map(|workspace| &workspace.rect)
+            );
+        }
+    }
+
+    #[test]
+    fn decide_without_sane_rects() {
+        let policy = OrientationPolicy::default();
+        let zero = window_with_rects(rect(0, 0, 0, 0), rect(0, 0, 0, 0));
+
+        assert_eq!(policy.decide(&zero, None), None);
+        assert_eq!(policy.is_node_vertical(&zero), None);
+
+        // An empty workspace (e.g., an output being reconfigured) decides nothing.
+        let tall = window_with_rects(rect(0, 0, 400, 800), rect(0, 0, 400, 800));
+        assert_eq!(policy.decide(&tall, Some(&zero)), None);
+    }
+
+    #[test]
+    fn decide_falls_back_to_the_other_rect() {
+        let node = window_with_rects(rect(0, 0, 400, 800), rect(0, 0, 0, 0));
+
+        let policy = OrientationPolicy::default();
+        assert_eq!(policy.decide(&node, None), Some(Split::Vertical));
+
+        let node = window_with_rects(rect(0, 0, 0, 0), rect(0, 0, 800, 400));
+
+        let policy = OrientationPolicy {
+            rect_source: RectSource::Container,
+            ..OrientationPolicy::default()
+        };
+        assert_eq!(policy.decide(&node, None), Some(Split::Horizontal));
+    }
+
+    #[test]
+    fn decide_with_negative_coordinates() {
+        let policy = OrientationPolicy::default();
+
+        let wide = window_with_rects(rect(-1920, -200, 800, 400), rect(-1918, -178, 796, 376));
+        assert_eq!(policy.decide(&wide, None), Some(Split::Horizontal));
+
+        let tall = window_with_rects(rect(-1080, 0, 400, 800), rect(-1078, 22, 396, 776));
+        assert_eq!(policy.decide(&tall, None), Some(Split::Vertical));
+
+        // A portrait output left of the origin splits vertically.
+        let workspace = window_with_rects(rect(-1080, 0, 1080, 1920), rect(0, 0, 0, 0));
+        assert_eq!(
+            policy.decide(&wide, Some(&workspace)),
+            Some(Split::Vertical)
+        );
+    }
+
+    #[test]
+    fn decide_square_band_and_threshold() {
+        let square = window_with_rects(rect(0, 0, 500, 510), rect(0, 0, 500, 510));
+
+        let policy = OrientationPolicy::default();
+        assert_eq!(policy.decide(&square, None), Some(Split::Vertical));
+
+        let policy = OrientationPolicy {
+            square_band: 0.05,
+            ..OrientationPolicy::default()
+        };
+        assert_eq!(policy.decide(&square, None), Some(Split::Toggle));
+
+        let policy = OrientationPolicy {
+            threshold: 1.5,
+            ..OrientationPolicy::default()
+        };
+        assert_eq!(policy.decide(&square, None), Some(Split::Horizontal));
+    }
+
+    #[test]
+    fn decide_square_band_boundaries() {
+        let policy = OrientationPolicy {
+            square_band: 0.1,
+            ..OrientationPolicy::default()
+        };
+
+        for (width, height, expected) in [
+            (500, 500, Split::Toggle),
+            (500, 550, Split::Toggle),
+            (500, 450, Split::Toggle),
+            (500, 551, Split::Vertical),
+            (500, 449, Split::Horizontal),
+            (1000, 1100, Split::Toggle),
+            (1000, 1101, Split::Vertical),
+        ] {
+            let node = window_with_rects(rect(0, 0, width, height), rect(0, 0, width, height));
+            assert_eq!(
+                policy.decide(&node, None),
+                Some(expected),
+                "{}x{}",
+                width,
+                height
+            );
+        }
+
+        // A zero band disables it, even for exact squares.
+        let square = window_with_rects(rect(0, 0, 500, 500), rect(0, 0, 500, 500));
+        assert_eq!(
+            OrientationPolicy::default().decide(&square, None),
+            Some(Split::Horizontal)
+        );
+    }
+
+    #[test]
+    fn closest_cells_by_log_distance() {
+        // 2:1 is as far from 1:1 as 1:2 is: ties split horizontally.
+        assert_eq!(
+            closest_cells((2.0, 1.0), (1.0, 2.0), 1.0),
+            Split::Horizontal
+        );
+
+        assert_eq!(
+            closest_cells((2.0, 1.0), (1.0, 3.0), 1.0),
+            Split::Horizontal
+        );
+        assert_eq!(closest_cells((3.0, 1.0), (1.0, 2.0), 1.0), Split::Vertical);
+
+        // Not a linear distance: 0.5 is closer to 1.0 than 1.8 on a linear scale only.
+        assert_eq!(closest_cells((1.0, 2.0), (1.8, 1.0), 1.0), Split::Vertical);
+
+        assert_eq!(
+            closest_cells((480.0, 1060.0), (640.0, 530.0), 1.2),
+            Split::Vertical
+        );
+        assert_eq!(
+            closest_cells((480.0, 1060.0), (640.0, 530.0), 0.4),
+            Split::Horizontal
+        );
+    }
+
+    #[test]
+    fn cells_split_on_fixtures() {
+        let root_node = fixtures::load("workspaces.json");
+
+        // Three columns (640x1060): a fourth column would be too narrow.
+        let node = fixtures::node(&root_node, 101);
+        let parent = fixtures::node(&root_node, 100);
+        assert_eq!(cells_split(node, parent, 1.2), Some(Split::Vertical));
+        assert_eq!(cells_split(node, parent, 0.3), Some(Split::Horizontal));
+
+        // Two rows (960x530) in a vertical container: a third row would be too flat.
+        let node = fixtures::node(&root_node, 211);
+        let parent = fixtures::node(&root_node, 210);
+        assert_eq!(cells_split(node, parent, 1.2), Some(Split::Horizontal));
+        assert_eq!(cells_split(node, parent, 3.0), Some(Split::Vertical));
+
+        let root_node = fixtures::load("multi_output.json");
+
+        // Two columns (960x1060): the third window goes below.
+        let node = fixtures::node(&root_node, 101);
+        let parent = fixtures::node(&root_node, 100);
+        assert_eq!(cells_split(node, parent, 1.2), Some(Split::Vertical));
+    }
+
+    #[test]
+    fn cells_split_without_sane_rects() {
+        let root_node = fixtures::load("workspaces.json");
+        let parent = fixtures::node(&root_node, 100);
+
+        let zero = window_with_rects(rect(0, 0, 0, 0), rect(0, 0, 0, 0));
+        assert_eq!(cells_split(&zero, parent, 1.2), None);
+
+        let mut empty_parent = parent.clone();
+        empty_parent.rect = rect(0, 0, 0, 0);
+        let node = fixtures::node(&root_node, 101);
+        assert_eq!(cells_split(node, &empty_parent, 1.2), None);
+    }
+
+    #[test]
+    fn private_dir_is_created_private() {
+        let temp_dir = fixtures::TempDir::new("private-dir");
+        let dir = temp_dir.path().join("runtime");
+
+        private_dir(&dir).unwrap();
+        assert_eq!(std::fs::metadata(&dir).unwrap().mode() & 0o777, 0o700);
+
+        // Reusing it is fine.
+        private_dir(&dir).unwrap();
+    }
+
+    #[test]
+    fn private_dir_restricts_permissions() {
+        let temp_dir = fixtures::TempDir::new("private-dir");
+        let dir = temp_dir.path().join("runtime");
+
+        std::fs::create_dir(&dir).unwrap();
+        std::fs::set_permissions(&dir, Permissions::from_mode(0o777)).unwrap();
+
+        private_dir(&dir).unwrap();
+        assert_eq!(std::fs::metadata(&dir).unwrap().mode() & 0o777, 0o700);
+    }
+
+    #[test]
+    fn private_dir_refuses_symlinks_and_files() {
+        let temp_dir = fixtures::TempDir::new("private-dir");
+
+        let target = temp_dir.path().join("target");
+        std::fs::create_dir(&target).unwrap();
+        let link = temp_dir.path().join("link");
+        std::os::unix::fs::symlink(&target, &link).unwrap();
+        assert!(private_dir(&link).is_err());
+
+        let file = temp_dir.path().join("file");
+        std::fs::write(&file, "").unwrap();
+        assert!(private_dir(&file).is_err());
+    }
+
+    #[test]
+    fn private_dir_refuses_other_owners() {
+        let temp_dir = fixtures::TempDir::new("private-dir");
+        let dir = temp_dir.path().join("runtime");
+        std::fs::create_dir(&dir).unwrap();
+
+        // Only root can give the directory away.
+        let other_uid = current_uid().unwrap() + 1;
+        if std::os::unix::fs::chown(&dir, Some(other_uid), None).is_err() {
+            return;
+        }
+
+        assert!(private_dir(&dir).is_err());
+    }
+
+    #[test]
+    fn current_uid_owns_new_files() {
+        let temp_dir = fixtures::TempDir::new("uid");
+        let file = temp_dir.path().join("file");
+        std::fs::write(&file, "").unwrap();
+
+        assert_eq!(
+            current_uid().unwrap(),
+            std::fs::metadata(&file).unwrap().uid()
+        );
+    }
+
+    #[test]
+    fn ratio_shares_sum_to_100() {
+        assert_eq!(ratio_shares(0, &ShareRatio::Equal), Vec::<u32>::new());
+        assert_eq!(ratio_shares(1, &ShareRatio::Golden), [100]);
+        assert_eq!(ratio_shares(3, &ShareRatio::Equal), [34, 33, 33]);
+        assert_eq!(ratio_shares(2, &ShareRatio::Golden), [62, 38]);
+        assert_eq!(ratio_shares(3, &ShareRatio::Golden), [62, 24, 14]);
+
+        for count in 1..=12 {
+            for ratio in ["equal", "golden", "60,40", "1,2,3"] {
+                let ratio = ratio.parse::<ShareRatio>().unwrap();
+                let shares = ratio_shares(count, &ratio);
+
+                assert_eq!(shares.len(), count);
+                assert_eq!(shares.iter().sum::<u32>(), 100, "{} of {}", ratio, count);
+            }
+        }
+    }
+
+    #[test]
+    fn ratio_shares_of_weights() {
+        let ratio = "60,40".parse::<ShareRatio>().unwrap();
+        assert_eq!(ratio_shares(2, &ratio), [60, 40]);
+
+        // More children than weights: the first weight cascades.
+        assert_eq!(ratio_shares(3, &ratio), [60, 24, 16]);
+
+        let ratio = "1,1,1".parse::<ShareRatio>().unwrap();
+        assert_eq!(ratio_shares(3, &ratio), [34, 33, 33]);
+    }
+
+    #[test]
+    fn parse_share_ratios() {
+        for (text, ratio) in [
+            ("equal", ShareRatio::Equal),
+            ("golden", ShareRatio::Golden),
+            ("60,40", ShareRatio::Weights(vec![60, 40])),
+            ("3,2,1", ShareRatio::Weights(vec![3, 2, 1])),
+        ] {
+            assert_eq!(text.parse::<ShareRatio>().unwrap(), ratio);
+            assert_eq!(ratio.to_string(), text);
+        }
+
+        assert_eq!(
+            " 60 , 40".parse::<ShareRatio>().unwrap(),
+            ShareRatio::Weights(vec![60, 40])
+        );
+
+        for (text, token) in [("60,x", "x"), ("60,0", "0"), ("60,-40", "-40"), ("60,", "")] {
+            let message = text.parse::<ShareRatio>().err().unwrap().to_string();
+            assert!(
+                message.starts_with(&format!("Invalid ratio token '{}' in '{}'", token, text)),
+                "{}",
+                message
+            );
+        }
+
+        assert_eq!(
+            "60".parse::<ShareRatio>().err().unwrap().to_string(),
+            "Invalid ratio '60' (a list needs at least two weights, e.g., '60,40')"
+        );
+    }
+
+    #[test]
+    fn balanced_shares_keep_locked_children() {
+        let root_node = fixtures::load("workspaces.json");
+        let workspace = fixtures::node(&root_node, 100);
+
+        assert_eq!(
+            balanced_shares(workspace, &ShareRatio::Golden),
+            [(101, 62.0), (102, 24.0), (103, 14.0)]
+        );
+
+        // The locked window keeps its third; the others divide the rest.
+        let mut value = serde_json::to_value(workspace).unwrap();
+        value["nodes"][0]["marks"] = serde_json::json!([format!("{}101", SIZE_LOCK_MARK_PREFIX)]);
+        let workspace: I3Node = serde_json::from_value(value).unwrap();
+
+        let shares = balanced_shares(&workspace, &ShareRatio::Golden);
+        let expected = [
+            (101, 100.0 / 3.0),
+            (102, 62.0 * 2.0 / 3.0),
+            (103, 38.0 * 2.0 / 3.0),
+        ];
+        for ((node_id, share), (expected_id, expected_share)) in shares.iter().zip(expected) {
+            assert_eq!(*node_id, expected_id);
+            assert!(
+                (share - expected_share).abs() < 1e-9,
+                "{} {}",
+                node_id,
+                share
+            );
+        }
+
+        // Tabbed containers have no shares.
+        assert!(balanced_shares(fixtures::node(&root_node, 410), &ShareRatio::Equal).is_empty());
+    }
+
+    #[test]
+    fn share_corrections_resize_towards_targets() {
+        let root_node = fixtures::load("workspaces.json");
+
+        // Three windows of 640 px out of 1920.
+        let workspace = fixtures::node(&root_node, 100);
+
+        let corrections =
+            share_corrections(workspace, &[(101, 50.0), (102, 25.0), (103, 25.0)], 1.0);
+        assert_eq!(
+            corrections,
+            [ShareCorrection {
+                node_id: 101,
+                grow: true,
+                dimension: "width",
+                px: 320,
+            }]
+        );
+        assert_eq!(corrections[0].to_string(), "resize grow width 320 px");
+
+        let corrections = share_corrections(workspace, &[(101, 20.0)], 1.0);
+        assert_eq!(corrections[0].to_string(), "resize shrink width 256 px");
+
+        // Vertical splits resize the height.
+        let splitv = fixtures::node(&root_node, 210);
+        let corrections = share_corrections(splitv, &[(211, 70.0)], 1.0);
+        assert_eq!(corrections[0].to_string(), "resize grow height 212 px");
+    }
+
+    #[test]
+    fn share_corrections_tolerance() {
+        let root_node = fixtures::load("workspaces.json");
+        let workspace = fixtures::node(&root_node, 100);
+
+        // 33.33% is within 1 ppt of 34%, not of 0.5 ppt (rounded to 13 px).
+        assert!(share_corrections(workspace, &[(101, 34.0)], 1.0).is_empty());
+        assert_eq!(
+            share_corrections(workspace, &[(101, 34.0)], 0.5)[0].to_string(),
+            "resize grow width 13 px"
+        );
+
+        // Less than a pixel of drift is left alone.
+        assert!(share_corrections(workspace, &[(101, 33.35)], 0.0).is_empty());
+
+        // Children without target are left alone.
+        assert!(share_corrections(workspace, &[(999, 50.0)], 1.0).is_empty());
+    }
+
+    #[test]
+    fn share_corrections_edge_cases() {
+        let root_node = fixtures::load("workspaces.json");
+
+        // Stacked and tabbed containers have no shares.
+        let stacked = fixtures::node(&root_node, 220);
+        assert!(share_corrections(stacked, &[(221, 80.0)], 1.0).is_empty());
+
+        // A single child.
+        let mut workspace = fixtures::node(&root_node, 100).clone();
+        workspace.nodes.truncate(1);
+        assert!(share_corrections(&workspace, &[(101, 50.0)], 1.0).is_empty());
+
+        // Not laid out yet.
+        let mut workspace = fixtures::node(&root_node, 100).clone();
+        workspace.rect = rect(0, 0, 0, 0);
+        assert!(share_corrections(&workspace, &[(101, 50.0)], 1.0).is_empty());
+
+        let mut workspace = fixtures::node(&root_node, 100).clone();
+        for child in &mut workspace.nodes {
+            child.rect = rect(0, 0, 0, 0);
+        }
+        assert!(share_corrections(&workspace, &[(101, 50.0)], 1.0).is_empty());
     }
 }
@@ -0,0 +1,501 @@
+/*
+    Copyright (C) 2022  Biagio Festa
+
+    This program is free software: you can redistribute it and/or modify
+    it under the terms of the GNU General Public License as published by
+    the Free Software Foundation, either version 3 of the License, or
+    (at your option) any later version.
+
+    This program is distributed in the hope that it will be useful,
+    but WITHOUT ANY WARRANTY; without even the implied warranty of
+    MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+    GNU General Public License for more details.
+
+    You should have received a copy of the GNU General Public License
+    along with this program.  If not, see <https://www.gnu.org/licenses/>.
+*/
+
+use crate::command_executor::CommandExecutor;
+use crate::command_executor::I3Node;
+use crate::command_executor::RootNode;
+use crate::errors::Failure;
+use crate::utilities::find_workspace_by_name;
+use crate::utilities::find_workspace_by_num;
+use crate::utilities::find_workspaces;
+use crate::utilities::glob_regex;
+use crate::utilities::is_scratchpad_workspace;
+use crate::utilities::query_workspace_focused;
+use crate::utilities::quote_ws_name;
+use anyhow::anyhow;
+use anyhow::Result;
+use regex::Regex;
+use std::fmt::Display;
+use std::str::FromStr;
+
+/// A reference to a workspace.
+///
+/// On the command line: a number, a name, or `focused`.
+#[derive(Clone, Debug, Default, PartialEq, Eq, serde::Deserialize, serde::Serialize)]
+pub enum WorkspaceRef {
+    /// The workspace with this number.
+    Num(i32),
+
+    /// The workspace with this name.
+    Name(String),
+
+    /// The currently focused workspace.
+    #[default]
+    Focused,
+}
+
+impl WorkspaceRef {
+    /// The reference to an existing workspace node.
+    ///
+    /// Numbered workspaces are referenced by number (their name may change, e.g., `1: web`).
+    /// i3 assigns number `-1` to workspaces without a numeric name.
+    pub fn of(workspace: &I3Node) -> Self {
+        match workspace.num {
+            Some(num) if num >= 0 => Self::Num(num),
+            _ => Self::Name(workspace.name.clone().unwrap_or_default()),
+        }
+    }
+
+    /// Find the referenced workspace in the tree.
+    ///
+    /// If it does not exist, the error lists the existing workspaces and suggests the
+    /// closest one (see `suggest_workspace`).
+    pub fn resolve<'a>(
+        &self,
+        root_node: &'a RootNode,
+        command_executor: &mut CommandExecutor,
+    ) -> Result<&'a I3Node> {
+        match self {
+            Self::Focused => query_workspace_focused(root_node, command_executor),
+            _ => self.find(root_node),
+        }
+    }
+
+    /// Find the workspace referenced by number or name in the tree (see `resolve`).
+    fn find<'a>(&self, root_node: &'a RootNode) -> Result<&'a I3Node> {
+        let workspace = match self {
+            Self::Num(num) => find_workspace_by_num(root_node, *num),
+            Self::Name(name) => find_workspace_by_name(root_node, name),
+            Self::Focused => return Err(anyhow!("The focused workspace has to be queried")),
+        };
+
+        workspace.ok_or_else(|| {
+            self.not_found(root_node)
+                .context(Failure::WorkspaceNotFound)
+        })
+    }
+
+    /// The error of a reference not found in the tree.
+    fn not_found(&self, root_node: &RootNode) -> anyhow::Error {
+        // Listed as i3bar does: numbered workspaces first, by number.
+        let mut workspaces = find_workspaces(root_node)
+            .into_iter()
+            .filter(|workspace| !is_scratchpad_workspace(workspace))
+            .collect::<Vec<_>>();
+        workspaces.sort_by_key(|workspace| {
+            let num = workspace.num.filter(|num| *num >= 0);
+            (num.is_none(), num, workspace.name.clone())
+        });
+
+        let names = workspaces
+            .into_iter()
+            .filter_map(|workspace| workspace.name.clone())
+            .collect::<Vec<_>>();
+
+        let mut message = match self {
+            // i3 drops the workspaces without windows (unless visible).
+            Self::Num(num) => format!(
+                "Cannot find the workspace number '{}' (it does not exist, or it has no \
+                 windows and is not visible)",
+                num
+            ),
+            _ => format!("Cannot find the workspace '{}'", self),
+        };
+
+        if !names.is_empty() {
+            message += &format!("; existing workspaces: {}", names.join(", "));
+        }
+
+        if let Some(suggestion) = suggest_workspace(self, &names) {
+            message += &format!("; did you mean '{}'?", suggestion);
+        }
+
+        anyhow!(message)
+    }
+
+    /// Whether the reference designates the workspace node (`focused` never does: it has
+    /// to be resolved first).
+    pub fn designates(&self, workspace: &I3Node) -> bool {
+        match self {
+            Self::Num(num) => workspace.num == Some(*num),
+            Self::Name(name) => workspace.name.as_deref() == Some(name),
+            Self::Focused => false,
+        }
+    }
+
+    /// A representation of the reference usable as part of a file name.
+    pub fn file_key(&self) -> String {
+        self.to_string().replace('/', "_")
+    }
+
+    /// The i3 command text selecting the workspace (e.g., `workspace number 1`).
+    ///
+    /// It can follow `move container to` too. The focused workspace has to be resolved first.
+    pub fn to_i3_selector(&self) -> Result<String> {
+        match self {
+            Self::Num(num) => Ok(format!("workspace number {}", num)),
+            Self::Name(name) => Ok(format!("workspace {}", quote_ws_name(name)?)),
+            Self::Focused => Err(anyhow!("Cannot select the focused workspace by reference")),
+        }
+    }
+}
+
+/// The workspace name closest to a reference not found, if close enough.
+///
+/// Names are compared with the edit distance (at most 2), also without their number
+/// prefix (e.g., `mial` suggests `3: mail`). Numbers are compared with the numbers of the
+/// workspaces (at most 1 digit apart, e.g., `11` suggests `1`).
+pub fn suggest_workspace<'a>(reference: &WorkspaceRef, names: &'a [String]) -> Option<&'a str> {
+    let (wanted, max_distance) = match reference {
+        WorkspaceRef::Num(num) => (num.to_string(), 1),
+        WorkspaceRef::Name(name) => (name.clone(), 2),
+        WorkspaceRef::Focused => return None,
+    };
+
+    names
+        .iter()
+        .filter_map(|name| {
+            let number = name.split(':').next().unwrap_or_default().trim();
+            let label = name.split_once(':').map(|(_, label)| label.trim());
+
+            let distance = match reference {
+                WorkspaceRef::Num(_) => number
+                    .parse::<i32>()
+                    .ok()
+                    .map(|_| edit_distance(&wanted, number))?,
+                _ => std::iter::once(name.as_str())
+                    .chain(label)
+                    .map(|candidate| edit_distance(&wanted, candidate))
+                    .min()?,
+            };
+
+            (distance <= max_distance).then_some((distance, name.as_str()))
+        })
+        .min_by_key(|(distance, _)| *distance)
+        .map(|(_, name)| name)
+}
+
+/// A selection of workspaces by patterns: names, numbers or globs (`*` and `?`).
+///
+/// A pattern matches the number of a workspace, its name, or its name without the number
+/// prefix (e.g., `mail` matches `3: mail`).
+#[derive(Default)]
+pub struct WorkspaceFilter {
+    /// When not empty, only the workspaces matching one of these are selected.
+    only: Vec<Regex>,
+
+    /// The workspaces matching one of these are never selected.
+    exclude: Vec<Regex>,
+}
+
+impl WorkspaceFilter {
+    /// A filter from the `only` and `exclude` patterns.
+    pub fn new(only: &[String], exclude: &[String]) -> Self {
+        let compile = |patterns: &[String]| {
+            patterns
+                .iter()
+                .map(|pattern| glob_regex(pattern.trim()))
+                .collect()
+        };
+
+        Self {
+            only: compile(only),
+            exclude: compile(exclude),
+        }
+    }
+
+    /// Whether the workspace with number `num` and name `name` is selected.
+    pub fn matches(&self, num: Option<i32>, name: Option<&str>) -> bool {
+        let label = name
+            .and_then(|name| name.split_once(':'))
+            .map(|(_, label)| label.trim());
+
+        let candidates = num
+            .map(|num| num.to_string())
+            .into_iter()
+            .chain(name.map(str::to_string))
+            .chain(label.map(str::to_string))
+            .collect::<Vec<_>>();
+
+        let any_match = |patterns: &[Regex]| {
+            patterns.iter().any(|pattern| {
+                candidates
+                    .iter()
+                    .any(|candidate| pattern.is_match(candidate))
+            })
+        };
+
+        (self.only.is_empty() || any_match(&self.only)) && !any_match(&self.exclude)
+    }
+}
+
+/// The Levenshtein distance between two strings (in characters).
+pub fn edit_distance(a: &str, b: &str) -> usize {
+    let b = b.chars().collect::<Vec<_>>();
+    let mut previous = (0..=b.len()).collect::<Vec<_>>();
+
+    for (i, a_char) in a.chars().enumerate() {
+        let mut current = vec![i + 1];
+
+        for (j, b_char) in b.iter().enumerate() {
+            let substitution = previous[j] + usize::from(a_char != *b_char);
+            current.push(substitution.min(previous[j + 1] + 1).min(current[j] + 1));
+        }
+
+        previous = current;
+    }
+
+    previous[b.len()]
+}
+
+/// Resolve the workspace argument of a subcommand (the focused workspace if `None`).
+///
+/// Every subcommand taking a workspace goes through `WorkspaceRef`, so that numbers,
+/// names (spaces included, e.g., `3: mail`) and `focused` behave the same everywhere.
+pub fn resolve_workspace_arg<'a>(
+    arg: Option<&WorkspaceRef>,
+    root_node: &'a RootNode,
+    command_executor: &mut CommandExecutor,
+) -> Result<&'a I3Node> {
+    arg.unwrap_or(&WorkspaceRef::Focused)
+        .resolve(root_node, command_executor)
+}
+
+impl FromStr for WorkspaceRef {
+    type Err = anyhow::Error;
+
+    /// Parse a workspace number, `focused`, or a workspace name.
+    fn from_str(s: &str) -> Result<Self> {
+        if s.is_empty() {
+            return Err(anyhow!("Empty workspace reference"));
+        }
+
+        match s.parse::<i32>() {
+            Ok(num) => Ok(Self::Num(num)),
+            Err(_) if s == "focused" => Ok(Self::Focused),
+            Err(_) => Ok(Self::Name(s.to_string())),
+        }
+    }
+}
+
+impl Display for WorkspaceRef {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Self::Num(num) => write!(f, "{}", num),
+            Self::Name(name) => write!(f, "{}", name),
+            Self::Focused => write!(f, "focused"),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::fixtures;
+
+    #[test]
+    fn edit_distances() {
+        for (a, b, distance) in [
+            ("", "", 0),
+            ("", "mail", 4),
+            ("mail", "mail", 0),
+            ("mial", "mail", 2),
+            ("mai", "mail", 1),
+            ("mails", "mail", 1),
+            ("web", "wbe", 2),
+            ("kitten", "sitting", 3),
+            ("café", "cafe", 1),
+            ("🦀", "🦀🦀", 1),
+        ] {
+            assert_eq!(edit_distance(a, b), distance, "'{}' to '{}'", a, b);
+            assert_eq!(edit_distance(b, a), distance, "'{}' to '{}'", b, a);
+        }
+    }
+
+    #[test]
+    fn suggestions() {
+        let names = ["1", "2", "3: mail", "9: chat", "web"].map(String::from);
+
+        for (reference, suggestion) in [
+            (WorkspaceRef::Num(11), Some("1")),
+            (WorkspaceRef::Num(99), Some("9: chat")),
+            (WorkspaceRef::Num(123), None),
+            // Ties go to the first workspace.
+            (WorkspaceRef::Num(4), Some("1")),
+            (WorkspaceRef::Name("mial".to_string()), Some("3: mail")),
+            (WorkspaceRef::Name("3: mial".to_string()), Some("3: mail")),
+            (WorkspaceRef::Name("chta".to_string()), Some("9: chat")),
+            (WorkspaceRef::Name("wbe".to_string()), Some("web")),
+            (WorkspaceRef::Name("webs".to_string()), Some("web")),
+            (WorkspaceRef::Name("music".to_string()), None),
+            (WorkspaceRef::Name("".to_string()), Some("1")),
+            (WorkspaceRef::Focused, None),
+        ] {
+            assert_eq!(
+                suggest_workspace(&reference, &names),
+                suggestion,
+                "{:?}",
+                reference
+            );
+        }
+
+        assert_eq!(suggest_workspace(&WorkspaceRef::Num(1), &[]), None);
+    }
+
+    #[test]
+    fn not_found_lists_and_suggests() {
+        let root_node = fixtures::load("workspaces.json");
+
+        assert_eq!(
+            WorkspaceRef::Name("wbe".to_string())
+                .not_found(&root_node)
+                .to_string(),
+            "Cannot find the workspace 'wbe'; existing workspaces: 1, 2, 3 [], 5, 6, web; \
+             did you mean 'web'?"
+        );
+
+        assert_eq!(
+            WorkspaceRef::Num(12).not_found(&root_node).to_string(),
+            "Cannot find the workspace number '12' (it does not exist, or it has no windows \
+             and is not visible); existing workspaces: 1, 2, 3 [], 5, 6, web; did you mean '1'?"
+        );
+
+        assert_eq!(
+            WorkspaceRef::Name("music".to_string())
+                .not_found(&root_node)
+                .to_string(),
+            "Cannot find the workspace 'music'; existing workspaces: 1, 2, 3 [], 5, 6, web"
+        );
+    }
+
+    #[test]
+    fn parse_references() {
+        for (text, reference) in [
+            ("1", WorkspaceRef::Num(1)),
+            ("-1", WorkspaceRef::Num(-1)),
+            ("focused", WorkspaceRef::Focused),
+            ("web", WorkspaceRef::Name("web".to_string())),
+            ("9: chat", WorkspaceRef::Name("9: chat".to_string())),
+            ("my space", WorkspaceRef::Name("my space".to_string())),
+        ] {
+            assert_eq!(text.parse::<WorkspaceRef>().unwrap(), reference);
+            assert_eq!(reference.to_string(), text);
+        }
+
+        assert!("".parse::<WorkspaceRef>().is_err());
+    }
+
+    #[test]
+    fn find_on_all_outputs() {
+        let root_node = fixtures::load("multi_output.json");
+
+        for (reference, workspace_id) in [
+            (WorkspaceRef::Num(1), 100),
+            (WorkspaceRef::Num(2), 200),
+            (WorkspaceRef::Num(3), 300),
+            (WorkspaceRef::Num(9), 900),
+            (WorkspaceRef::Name("1".to_string()), 100),
+            (WorkspaceRef::Name("9: chat".to_string()), 900),
+        ] {
+            let workspace = reference.find(&root_node).unwrap();
+            assert_eq!(workspace.id, workspace_id, "{:?}", reference);
+            assert!(reference.designates(workspace));
+        }
+    }
+
+    #[test]
+    fn find_missing_workspaces() {
+        let root_node = fixtures::load("multi_output.json");
+
+        for (reference, message) in [
+            (
+                WorkspaceRef::Num(4),
+                "Cannot find the workspace number '4' (it does not exist, or it has no windows \
+                 and is not visible); existing workspaces: 1, 2, 3, 9: chat; did you mean '1'?",
+            ),
+            (
+                WorkspaceRef::Name("chat".to_string()),
+                "Cannot find the workspace 'chat'; existing workspaces: 1, 2, 3, 9: chat; \
+                 did you mean '9: chat'?",
+            ),
+        ] {
+            let error = reference.find(&root_node).err().unwrap();
+            assert_eq!(Failure::of(&error), Some(Failure::WorkspaceNotFound));
+            assert_eq!(format!("{:#}", error.root_cause()), message);
+        }
+
+        assert!(WorkspaceRef::Focused.find(&root_node).is_err());
+    }
+
+    fn filter(only: &[&str], exclude: &[&str]) -> WorkspaceFilter {
+        let strings = |patterns: &[&str]| {
+            patterns
+                .iter()
+                .map(|pattern| pattern.to_string())
+                .collect::<Vec<_>>()
+        };
+        WorkspaceFilter::new(&strings(only), &strings(exclude))
+    }
+
+    #[test]
+    fn filter_by_number_name_and_label() {
+        let workspaces = [
+            (Some(1), Some("1")),
+            (Some(3), Some("3: mail")),
+            (Some(10), Some("10")),
+            (None, Some("web")),
+            (Some(-1), Some("web.dev")),
+        ];
+        let selected = |filter: &WorkspaceFilter| {
+            workspaces
+                .iter()
+                .filter(|(num, name)| filter.matches(*num, *name))
+                .map(|(_, name)| name.unwrap())
+                .collect::<Vec<_>>()
+        };
+
+        assert_eq!(
+            selected(&WorkspaceFilter::default()),
+            ["1", "3: mail", "10", "web", "web.dev"]
+        );
+        assert_eq!(selected(&filter(&["1"], &[])), ["1"]);
+        assert_eq!(selected(&filter(&["3"], &[])), ["3: mail"]);
+        assert_eq!(selected(&filter(&["mail"], &[])), ["3: mail"]);
+        assert_eq!(selected(&filter(&["3: mail"], &[])), ["3: mail"]);
+        assert_eq!(selected(&filter(&["1*"], &[])), ["1", "10"]);
+        assert_eq!(selected(&filter(&["?"], &[])), ["1", "3: mail"]);
+        // A dot is not a wildcard.
+        assert_eq!(selected(&filter(&["web.*"], &[])), ["web.dev"]);
+        assert_eq!(selected(&filter(&[" web "], &[])), ["web"]);
+    }
+
+    #[test]
+    fn filter_exclusions_win() {
+        let selection = filter(&["*"], &["web*", "3"]);
+
+        assert!(selection.matches(Some(1), Some("1")));
+        assert!(!selection.matches(Some(3), Some("3: mail")));
+        assert!(!selection.matches(None, Some("web")));
+        assert!(!selection.matches(Some(-1), Some("web.dev")));
+
+        // Without `only`, everything not excluded is selected.
+        let selection = filter(&[], &["mail"]);
+        assert!(selection.matches(Some(1), Some("1")));
+        assert!(!selection.matches(Some(3), Some("3: mail")));
+        assert!(selection.matches(None, None));
+    }
+}
@@ -0,0 +1,348 @@
+/*
+    Copyright (C) 2022  Biagio Festa
+
+    This program is free software: you can redistribute it and/or modify
+    it under the terms of the GNU General Public License as published by
+    the Free Software Foundation, either version 3 of the License, or
+    (at your option) any later version.
+
+    This program is distributed in the hope that it will be useful,
+    but WITHOUT ANY WARRANTY; without even the implied warranty of
+    MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+    GNU General Public License for more details.
+
+    You should have received a copy of the GNU General Public License
+    along with this program.  If not, see <https://www.gnu.org/licenses/>.
+*/
+
+//! End-to-end tests against a real i3, running on a virtual X server.
+//!
+//! They need `Xvfb`, `i3`, `i3-msg` and `xterm` and are ignored by default:
+//!
+//! ```text
+//! cargo test --test e2e -- --ignored --test-threads=1
+//! ```
+//!
+//! Each test gets its own X display, i3 instance (with a private `I3SOCK`) and XDG directories,
+//! so the i3 session of the user is never touched.
+
+#[path = "../src/fixtures/shape.rs"]
+#[allow(dead_code)]
+mod shape;
+
+use shape::Shape;
+use std::path::Path;
+use std::path::PathBuf;
+use std::process::Child;
+use std::process::Command;
+use std::process::Output;
+use std::process::Stdio;
+use std::sync::atomic::AtomicUsize;
+use std::sync::atomic::Ordering;
+use std::thread::sleep;
+use std::time::Duration;
+use std::time::Instant;
+
+/// How long to wait for the X server, i3 and windows.
+const TIMEOUT: Duration = Duration::from_secs(10);
+
+/// The i3 configuration of the sessions: no bar, and `ipc-socket` appended per session.
+const I3_CONFIG: &str = "\
+font pango:monospace 8
+default_border pixel 1
+focus_follows_mouse no
+workspace_layout default
+";
+
+static SESSIONS: AtomicUsize = AtomicUsize::new(0);
+
+/// An X server and an i3 instance, both killed on drop.
+struct Session {
+    dir: PathBuf,
+    display: String,
+    socket: PathBuf,
+    xvfb: Child,
+    i3: Option<Child>,
+    children: Vec<Child>,
+}
+
+impl Session {
+    fn start() -> Self {
+        let index = SESSIONS.fetch_add(1, Ordering::SeqCst);
+        let dir = std::env::temp_dir().join(format!(
+            "i3-autolayout-e2e-{}-{}",
+            std::process::id(),
+            index
+        ));
+        std::fs::create_dir_all(&dir).expect("Cannot create the session directory");
+
+        let display_num = free_display(100 + (std::process::id() as usize % 100) * 10 + index);
+        let display = format!(":{}", display_num);
+
+        let xvfb = Command::new("Xvfb")
+            .args([
+                display.as_str(),
+                "-screen",
+                "0",
+                "1280x800x24",
+                "-nolisten",
+                "tcp",
+            ])
+            .stdout(Stdio::null())
+            .stderr(Stdio::null())
+            .spawn()
+            .expect("Cannot start Xvfb (is it installed?)");
+
+        let mut session = Self {
+            socket: dir.join("i3.sock"),
+            dir,
+            display,
+            xvfb,
+            i3: None,
+            children: vec![],
+        };
+
+        let x_socket = PathBuf::from(format!("/tmp/.X11-unix/X{}", display_num));
+        wait_until("the X server", || x_socket.exists());
+
+        let config = session.dir.join("i3.config");
+        std::fs::write(
+            &config,
+            format!("{}ipc-socket {}\n", I3_CONFIG, session.socket.display()),
+        )
+        .expect("Cannot write the i3 config");
+
+        let i3 = Command::new("i3")
+            .arg("-c")
+            .arg(&config)
+            .env("DISPLAY", &session.display)
+            .stdout(Stdio::null())
+            .stderr(Stdio::null())
+            .spawn()
+            .expect("Cannot start i3 (is it installed?)");
+        session.i3 = Some(i3);
+
+        wait_until("i3", || {
+            session.socket.exists() && session.i3_msg(&["-t", "get_version"]).status.success()
+        });
+
+        session
+    }
+
+    /// Run `i3-msg` on the session socket.
+    fn i3_msg(&self, args: &[&str]) -> Output {
+        Command::new("i3-msg")
+            .arg("-s")
+            .arg(&self.socket)
+            .args(args)
+            .output()
+            .expect("Cannot run i3-msg (is it installed?)")
+    }
+
+    /// Run an i3 command, failing the test if i3 rejects it.
+    fn i3_command(&self, command: &str) {
+        let output = self.i3_msg(&[command]);
+        assert!(
+            output.status.success(),
+            "i3 rejected '{}': {}",
+            command,
+            String::from_utf8_lossy(&output.stdout)
+        );
+    }
+
+    /// The i3-autolayout command, talking to the session i3 only.
+    fn autolayout(&self) -> Command {
+        let mut command = Command::new(env!("CARGO_BIN_EXE_i3-autolayout"));
+
+        command
+            .env("I3SOCK", &self.socket)
+            .env("DISPLAY", &self.display)
+            .env("HOME", &self.dir)
+            .env("XDG_CONFIG_HOME", self.dir.join("config"))
+            .env("XDG_STATE_HOME", self.dir.join("state"))
+            .env("XDG_RUNTIME_DIR", self.dir.join("runtime"));
+
+        command
+    }
+
+    /// Run i3-autolayout to completion, failing the test if it fails.
+    fn run(&self, args: &[&str]) -> String {
+        let output = self
+            .autolayout()
+            .args(args)
+            .output()
+            .expect("Cannot run i3-autolayout");
+
+        assert!(
+            output.status.success(),
+            "i3-autolayout {:?} failed: {}",
+            args,
+            String::from_utf8_lossy(&output.stderr)
+        );
+
+        String::from_utf8(output.stdout).expect("Non UTF-8 output")
+    }
+
+    /// Start the autolayout service, stopped with the session.
+    fn start_service(&mut self, args: &[&str]) {
+        std::fs::create_dir_all(self.dir.join("runtime")).unwrap();
+
+        let service = self
+            .autolayout()
+            .arg("autolayout")
+            .args(args)
+            .stdout(Stdio::null())
+            .stderr(Stdio::null())
+            .spawn()
+            .expect("Cannot start the autolayout service");
+        self.children.push(service);
+
+        // The service subscribes to the events before the first window is opened.
+        sleep(Duration::from_millis(500));
+    }
+
+    /// Open a window (an xterm with a unique instance name) and wait for it in GET_TREE.
+    fn spawn_window(&mut self, instance: &str) {
+        let window = Command::new("xterm")
+            .args(["-name", instance])
+            .env("DISPLAY", &self.display)
+            .stdout(Stdio::null())
+            .stderr(Stdio::null())
+            .spawn()
+            .expect("Cannot start xterm (is it installed?)");
+        self.children.push(window);
+
+        let pattern = format!("\"instance\":\"{}\"", instance);
+        wait_until(&format!("the window '{}'", instance), || {
+            let tree = self.i3_msg(&["-t", "get_tree"]).stdout;
+            String::from_utf8_lossy(&tree).contains(&pattern)
+        });
+
+        // Let the service (if any) handle the new window.
+        sleep(Duration::from_millis(300));
+    }
+
+    /// The id of the focused con, through GET_TREE.
+    fn focused_con(&self) -> u64 {
+        let output = self.i3_msg(&["-t", "get_tree"]);
+        let tree: serde_json::Value =
+            serde_json::from_slice(&output.stdout).expect("Invalid GET_TREE reply");
+
+        let mut dfs = vec![&tree];
+        while let Some(node) = dfs.pop() {
+            if node["focused"] == true {
+                return node["id"].as_u64().expect("Invalid con id");
+            }
+
+            for key in ["nodes", "floating_nodes"] {
+                dfs.extend(node[key].as_array().into_iter().flatten());
+            }
+        }
+
+        panic!("No focused con in the tree");
+    }
+
+    /// The shape of a workspace, through `print-tree --shape`.
+    fn shape(&self, workspace: &str) -> Shape {
+        let output = self.run(&["print-tree", "--shape", workspace]);
+
+        Shape::parse(output.trim())
+            .unwrap_or_else(|error| panic!("Invalid shape '{}': {:#}", output.trim(), error))
+    }
+}
+
+impl Drop for Session {
+    fn drop(&mut self) {
+        for child in self
+            .children
+            .iter_mut()
+            .chain(self.i3.iter_mut())
+            .chain(std::iter::once(&mut self.xvfb))
+        {
+            let _ = child.kill();
+            let _ = child.wait();
+        }
+
+        let _ = std::fs::remove_dir_all(&self.dir);
+    }
+}
+
+/// The first display number from `start` without an X server.
+fn free_display(start: usize) -> usize {
+    (start..)
+        .find(|num| !Path::new(&format!("/tmp/.X{}-lock", num)).exists())
+        .unwrap()
+}
+
+fn wait_until(what: &str, mut condition: impl FnMut() -> bool) {
+    let start = Instant::now();
+
+    while !condition() {
+        assert!(start.elapsed() < TIMEOUT, "Timeout waiting for {}", what);
+        sleep(Duration::from_millis(50));
+    }
+}
+
+fn assert_shape(actual: Shape, expected: &str) {
+    let expected = Shape::parse(expected).expect("Invalid expected shape");
+    assert_eq!(actual.to_string(), expected.to_string());
+}
+
+#[test]
+#[ignore]
+fn service_alternates_splits() {
+    let mut session = Session::start();
+    session.start_service(&[]);
+
+    session.spawn_window("e2e-1");
+    session.spawn_window("e2e-2");
+    session.spawn_window("e2e-3");
+
+    assert_shape(session.shape("1"), "splith(win, splitv(win, win))");
+}
+
+#[test]
+#[ignore]
+fn restore_layout_round_trip() {
+    let mut session = Session::start();
+
+    session.spawn_window("e2e-1");
+    session.spawn_window("e2e-2");
+    session.i3_command("split v");
+    session.spawn_window("e2e-3");
+
+    let saved_shape = session.shape("1");
+    assert_shape(saved_shape.clone(), "splith(win, splitv(win, win))");
+
+    let file = session.dir.join("1.layout");
+    let file = file.to_str().unwrap();
+    session.run(&["save-layout", "--workspace", "1", "--output", file]);
+
+    session.i3_command("[instance=\"e2e-3\"] move left");
+    session.i3_command("[instance=\"e2e-3\"] move left");
+    assert_ne!(session.shape("1"), saved_shape);
+
+    session.run(&["restore-layout", "--input", file]);
+    assert_eq!(session.shape("1"), saved_shape);
+}
+
+#[test]
+#[ignore]
+fn tabmode_round_trip() {
+    let mut session = Session::start();
+
+    session.spawn_window("e2e-1");
+    session.spawn_window("e2e-2");
+    session.i3_command("[instance=\"e2e-1\"] focus");
+    let initial_shape = session.shape("1");
+    let initial_focus = session.focused_con();
+
+    session.run(&["tabmode"]);
+    assert_shape(session.shape("1"), "splith(tabbed(win, win))");
+
+    session.run(&["tabmode"]);
+    assert_eq!(session.shape("1"), initial_shape);
+
+    // Leaving tab mode gives the focus back to the window focused before.
+    assert_eq!(session.focused_con(), initial_focus);
+}
@@ -0,0 +1,92 @@
+/*
+    Copyright (C) 2022  Biagio Festa
+
+    This program is free software: you can redistribute it and/or modify
+    it under the terms of the GNU General Public License as published by
+    the Free Software Foundation, either version 3 of the License, or
+    (at your option) any later version.
+
+    This program is distributed in the hope that it will be useful,
+    but WITHOUT ANY WARRANTY; without even the implied warranty of
+    MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+    GNU General Public License for more details.
+
+    You should have received a copy of the GNU General Public License
+    along with this program.  If not, see <https://www.gnu.org/licenses/>.
+*/
+
+//! Exit codes of real failures, against a fake i3 (see `fake_i3`) serving
+//! `fixtures/workspaces.json` and failing the commands matching a pattern, so that failures
+//! reach the process exit code the way they do with a real i3.
+
+#[allow(dead_code)]
+mod fake_i3;
+
+use fake_i3::exit_code;
+use fake_i3::run_with_socket;
+use fake_i3::FakeI3;
+
+#[test]
+fn connection_failure_exits_with_4() {
+    let dir = std::env::temp_dir().join(format!(
+        "i3-autolayout-exit-codes-{}-missing",
+        std::process::id()
+    ));
+
+    let output = run_with_socket(
+        &dir.join("missing.sock"),
+        &dir,
+        &["--porcelain", "normalize", "--workspace", "2"],
+    );
+
+    assert_eq!(exit_code(&output), 4, "{:?}", output);
+    assert!(
+        String::from_utf8_lossy(&output.stdout).contains("connection"),
+        "{:?}",
+        output
+    );
+
+    let _ = std::fs::remove_dir_all(&dir);
+}
+
+#[test]
+fn partial_normalize_exits_with_6() {
+    let fake_i3 = FakeI3::start("workspaces.json", Some("move window to mark"));
+
+    let output = fake_i3.run(&["normalize", "--workspace", "2"]);
+
+    assert_eq!(exit_code(&output), 6, "{:?}", output);
+    assert!(
+        String::from_utf8_lossy(&output.stderr).contains("3 windows could not be normalized"),
+        "{:?}",
+        output
+    );
+}
+
+#[test]
+fn successful_normalize_exits_with_0() {
+    let fake_i3 = FakeI3::start("workspaces.json", None);
+
+    let output = fake_i3.run(&["normalize", "--workspace", "2"]);
+
+    assert_eq!(exit_code(&output), 0, "{:?}", output);
+}
+
+#[test]
+fn partial_restore_exits_with_5() {
+    let fake_i3 = FakeI3::start("workspaces.json", Some("move"));
+    let layout = fake_i3.dir.join("2.layout");
+    let layout = layout.to_str().unwrap();
+
+    let output = fake_i3.run(&["save-layout", "--workspace", "2", "--output", layout]);
+    assert_eq!(exit_code(&output), 0, "{:?}", output);
+
+    let output = fake_i3.run(&["restore-layout", "--input", layout]);
+
+    assert_eq!(exit_code(&output), 5, "{:?}", output);
+    assert!(
+        String::from_utf8_lossy(&output.stderr).contains("refused by the fake i3"),
+        "{:?}",
+        output
+    );
+}
@@ -0,0 +1,284 @@
+/*
+    Copyright (C) 2022  Biagio Festa
+
+    This program is free software: you can redistribute it and/or modify
+    it under the terms of the GNU General Public License as published by
+    the Free Software Foundation, either version 3 of the License, or
+    (at your option) any later version.
+
+    This program is distributed in the hope that it will be useful,
+    but WITHOUT ANY WARRANTY; without even the implied warranty of
+    MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+    GNU General Public License for more details.
+
+    You should have received a copy of the GNU General Public License
+    along with this program.  If not, see <https://www.gnu.org/licenses/>.
+*/
+
+//! A fake i3 answering on a private `I3SOCK`, for the tests running the binary without X.
+//!
+//! It serves the tree of a fixture (which never changes): the workspaces, outputs and focus
+//! replied are derived from it. It records the commands it receives, and fails the ones
+//! matching a pattern.
+
+use serde_json::json;
+use serde_json::Value;
+use std::io::Read;
+use std::io::Write;
+use std::os::unix::net::UnixListener;
+use std::os::unix::net::UnixStream;
+use std::path::Path;
+use std::path::PathBuf;
+use std::process::Command;
+use std::process::Output;
+use std::sync::atomic::AtomicUsize;
+use std::sync::atomic::Ordering;
+use std::sync::Arc;
+use std::sync::Mutex;
+
+/// The `i3-ipc` magic string starting every message.
+const MAGIC: &[u8] = b"i3-ipc";
+
+static SESSIONS: AtomicUsize = AtomicUsize::new(0);
+
+/// A fake i3 listening on a socket in its own directory, removed on drop.
+pub struct FakeI3 {
+    /// The directory of the socket, also used as home and XDG directories.
+    pub dir: PathBuf,
+
+    socket: PathBuf,
+
+    /// The commands received, one per entry (a batch is split).
+    commands: Arc<Mutex<Vec<String>>>,
+}
+
+impl FakeI3 {
+    /// Start a fake i3 serving `fixture`, failing the commands containing `failing` (if any).
+    pub fn start(fixture: &str, failing: Option<&'static str>) -> Self {
+        let index = SESSIONS.fetch_add(1, Ordering::SeqCst);
+        let dir = std::env::temp_dir().join(format!(
+            "i3-autolayout-fake-i3-{}-{}",
+            std::process::id(),
+            index
+        ));
+        std::fs::create_dir_all(&dir).expect("Cannot create the session directory");
+
+        let socket = dir.join("i3.sock");
+        let listener = UnixListener::bind(&socket).expect("Cannot bind the fake i3 socket");
+
+        let path = Path::new(env!("CARGO_MANIFEST_DIR"))
+            .join("fixtures")
+            .join(fixture);
+        let tree: Value = serde_json::from_str(
+            &std::fs::read_to_string(&path)
+                .unwrap_or_else(|error| panic!("Cannot read '{}': {}", path.display(), error)),
+        )
+        .unwrap_or_else(|error| panic!("Cannot parse '{}': {}", path.display(), error));
+
+        let commands = Arc::new(Mutex::new(vec![]));
+        let received = Arc::clone(&commands);
+
+        std::thread::spawn(move || {
+            for stream in listener.incoming().flatten() {
+                let tree = tree.clone();
+                let received = Arc::clone(&received);
+                std::thread::spawn(move || serve(stream, &tree, failing, &received));
+            }
+        });
+
+        Self {
+            dir,
+            socket,
+            commands,
+        }
+    }
+
+    /// Run i3-autolayout against the fake i3.
+    pub fn run(&self, args: &[&str]) -> Output {
+        run_with_socket(&self.socket, &self.dir, args)
+    }
+
+    /// Take the commands received so far.
+    pub fn take_commands(&self) -> Vec<String> {
+        std::mem::take(&mut *self.commands.lock().unwrap())
+    }
+}
+
+impl Drop for FakeI3 {
+    fn drop(&mut self) {
+        let _ = std::fs::remove_dir_all(&self.dir);
+    }
+}
+
+/// Run i3-autolayout with `socket` as `I3SOCK`, and the home and XDG directories in `dir`.
+pub fn run_with_socket(socket: &Path, dir: &Path, args: &[&str]) -> Output {
+    Command::new(env!("CARGO_BIN_EXE_i3-autolayout"))
+        .args(args)
+        .env("I3SOCK", socket)
+        .env("HOME", dir)
+        .env("XDG_CONFIG_HOME", dir.join("config"))
+        .env("XDG_STATE_HOME", dir.join("state"))
+        .env("XDG_DATA_HOME", dir.join("data"))
+        .env("XDG_RUNTIME_DIR", dir.join("runtime"))
+        .env_remove("DISPLAY")
+        .output()
+        .expect("Cannot run i3-autolayout")
+}
+
+/// The exit code, failing the test if the process has been killed.
+pub fn exit_code(output: &Output) -> i32 {
+    output.status.code().unwrap_or_else(|| {
+        panic!(
+            "i3-autolayout has been killed: {}",
+            String::from_utf8_lossy(&output.stderr)
+        )
+    })
+}
+
+/// Answer the messages of a client until it disconnects.
+fn serve(
+    mut stream: UnixStream,
+    tree: &Value,
+    failing: Option<&str>,
+    received: &Mutex<Vec<String>>,
+) {
+    loop {
+        let mut header = [0; 14];
+        if stream.read_exact(&mut header).is_err() || &header[..6] != MAGIC {
+            return;
+        }
+
+        let length = u32::from_ne_bytes(header[6..10].try_into().unwrap()) as usize;
+        let message_type = u32::from_ne_bytes(header[10..14].try_into().unwrap());
+
+        let mut payload = vec![0; length];
+        if stream.read_exact(&mut payload).is_err() {
+            return;
+        }
+        let payload = String::from_utf8_lossy(&payload);
+
+        let reply = match message_type {
+            // RUN_COMMAND: one result per command (`run_batch` joins them with ';').
+            0 => Value::Array(
+                payload
+                    .split(';')
+                    .map(|command| {
+                        received.lock().unwrap().push(command.trim().to_string());
+
+                        match failing {
+                            Some(failing) if command.contains(failing) => {
+                                json!({"success": false, "error": "refused by the fake i3"})
+                            }
+                            _ => json!({"success": true}),
+                        }
+                    })
+                    .collect(),
+            ),
+            1 => workspaces(tree),
+            // SUBSCRIBE and SEND_TICK.
+            2 | 10 => json!({"success": true}),
+            3 => outputs(tree),
+            4 => tree.clone(),
+            5 => json!([]),
+            7 => json!({
+                "major": 4,
+                "minor": 22,
+                "patch": 0,
+                "human_readable": "4.22 (fake)",
+                "loaded_config_file_name": "/dev/null",
+            }),
+            _ => return,
+        };
+
+        let reply = reply.to_string();
+        let mut message = MAGIC.to_vec();
+        message.extend((reply.len() as u32).to_ne_bytes());
+        message.extend(message_type.to_ne_bytes());
+        message.extend(reply.as_bytes());
+
+        if stream.write_all(&message).is_err() {
+            return;
+        }
+    }
+}
+
+/// The outputs of a tree (the `__i3` one excluded), with their content container.
+fn output_nodes(tree: &Value) -> impl Iterator<Item = (&Value, &Value)> {
+    children(tree)
+        .filter(|output| {
+            !output["name"]
+                .as_str()
+                .unwrap_or_default()
+                .starts_with("__")
+        })
+        .filter_map(|output| {
+            let content = children(output).find(|node| node["name"] == "content")?;
+            Some((output, content))
+        })
+}
+
+/// The workspace visible in a content container: the first one of its focus list.
+fn visible_workspace(content: &Value) -> Option<&Value> {
+    let id = content["focus"].as_array()?.first()?;
+    children(content).find(|workspace| workspace["id"] == *id)
+}
+
+/// The GET_WORKSPACES reply of a tree.
+///
+/// The focused workspace is the one holding the focused node.
+fn workspaces(tree: &Value) -> Value {
+    let mut workspaces = vec![];
+
+    for (output, content) in output_nodes(tree) {
+        let visible = visible_workspace(content).map(|workspace| &workspace["id"]);
+
+        for workspace in children(content) {
+            let name = workspace["name"].as_str().unwrap_or_default();
+
+            workspaces.push(json!({
+                "id": workspace["id"],
+                "num": workspace["num"],
+                "name": name,
+                "visible": Some(&workspace["id"]) == visible,
+                "focused": holds_focus(workspace),
+                "urgent": false,
+                "rect": workspace["rect"],
+                "output": output["name"],
+            }));
+        }
+    }
+
+    Value::Array(workspaces)
+}
+
+/// The GET_OUTPUTS reply of a tree: all active.
+fn outputs(tree: &Value) -> Value {
+    Value::Array(
+        output_nodes(tree)
+            .map(|(output, content)| {
+                json!({
+                    "name": output["name"],
+                    "active": true,
+                    "primary": false,
+                    "current_workspace": visible_workspace(content)
+                        .map(|workspace| workspace["name"].clone()),
+                    "rect": output["rect"],
+                })
+            })
+            .collect(),
+    )
+}
+
+/// Whether the node or one of its descendants is focused.
+fn holds_focus(node: &Value) -> bool {
+    node["focused"] == true || children(node).any(holds_focus)
+}
+
+/// The children of a node (tiling and floating).
+fn children(node: &Value) -> impl Iterator<Item = &Value> {
+    node["nodes"]
+        .as_array()
+        .into_iter()
+        .chain(node["floating_nodes"].as_array())
+        .flatten()
+}
@@ -0,0 +1,193 @@
+/*
+    Copyright (C) 2022  Biagio Festa
+
+    This program is free software: you can redistribute it and/or modify
+    it under the terms of the GNU General Public License as published by
+    the Free Software Foundation, either version 3 of the License, or
+    (at your option) any later version.
+
+    This program is distributed in the hope that it will be useful,
+    but WITHOUT ANY WARRANTY; without even the implied warranty of
+    MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+    GNU General Public License for more details.
+
+    You should have received a copy of the GNU General Public License
+    along with this program.  If not, see <https://www.gnu.org/licenses/>.
+*/
+
+//! The workspace selectors of the workspace-targeting subcommands, against a fake i3 (see
+//! `fake_i3`) serving `fixtures/multi_output.json`: workspaces '1' and '3' on eDP-1 ('1'
+//! visible), '2' (focused) and '9: chat' (empty) on HDMI-1 ('2' visible).
+//!
+//! Every subcommand has to act on the same workspace for the same selector.
+
+#[allow(dead_code)]
+mod fake_i3;
+
+use fake_i3::exit_code;
+use fake_i3::FakeI3;
+use serde_json::Value;
+use std::collections::BTreeSet;
+use std::path::Path;
+
+/// How a subcommand is told the workspace.
+struct Subcommand {
+    args: &'static [&'static str],
+
+    /// The flag taking the workspace (`None` if positional).
+    workspace_flag: Option<&'static str>,
+
+    /// The flag taking the output (`None` if not supported).
+    output_flag: Option<&'static str>,
+
+    /// Whether the focused workspace is the default (instead of all the workspaces).
+    defaults_to_focused: bool,
+
+    /// Where the ids of the nodes acted on are found: after this prefix in the commands
+    /// sent to i3, or in the standard output.
+    ids_prefix: &'static str,
+
+    /// Whether the ids are in the standard output (instead of in the commands).
+    ids_in_stdout: bool,
+}
+
+const SUBCOMMANDS: [Subcommand; 4] = [
+    Subcommand {
+        args: &["normalize"],
+        workspace_flag: Some("--workspace"),
+        output_flag: None,
+        defaults_to_focused: true,
+        ids_prefix: "[con_id=",
+        ids_in_stdout: false,
+    },
+    Subcommand {
+        args: &["balance"],
+        workspace_flag: Some("--workspace"),
+        output_flag: Some("--output"),
+        defaults_to_focused: true,
+        ids_prefix: "[con_id=",
+        ids_in_stdout: false,
+    },
+    Subcommand {
+        args: &["save-layout", "--json"],
+        workspace_flag: Some("--workspace"),
+        output_flag: Some("--on-output"),
+        defaults_to_focused: true,
+        ids_prefix: "\"id\": ",
+        ids_in_stdout: true,
+    },
+    Subcommand {
+        args: &["print-tree"],
+        workspace_flag: None,
+        output_flag: Some("--output"),
+        defaults_to_focused: false,
+        ids_prefix: "[ID: ",
+        ids_in_stdout: true,
+    },
+];
+
+/// A workspace selector.
+enum Selector {
+    Workspace(&'static str),
+    Output(&'static str),
+    Focused,
+}
+
+/// The selectors, with the id of the workspace they designate.
+const SELECTORS: [(Selector, usize); 5] = [
+    (Selector::Workspace("1"), 100),
+    (Selector::Workspace("9: chat"), 900),
+    (Selector::Output("eDP-1"), 100),
+    (Selector::Output("HDMI-1"), 200),
+    (Selector::Focused, 200),
+];
+
+/// The workspace of each node of the fixture.
+fn workspaces_of_nodes() -> Vec<(usize, usize)> {
+    let path = Path::new(env!("CARGO_MANIFEST_DIR")).join("fixtures/multi_output.json");
+    let tree: Value = serde_json::from_str(&std::fs::read_to_string(path).unwrap()).unwrap();
+
+    let mut workspaces = vec![];
+    let mut dfs = vec![(&tree, None)];
+
+    while let Some((node, workspace)) = dfs.pop() {
+        let id = node["id"].as_u64().unwrap() as usize;
+        let workspace = match node["type"].as_str() {
+            Some("workspace") => Some(id),
+            _ => workspace,
+        };
+
+        if let Some(workspace) = workspace {
+            workspaces.push((id, workspace));
+        }
+
+        for child in node["nodes"].as_array().into_iter().flatten() {
+            dfs.push((child, workspace));
+        }
+    }
+
+    workspaces
+}
+
+/// The numbers following each occurrence of `prefix` in `text`.
+fn ids_after(text: &str, prefix: &str) -> Vec<usize> {
+    text.match_indices(prefix)
+        .filter_map(|(index, _)| {
+            let digits = text[index + prefix.len()..]
+                .chars()
+                .take_while(char::is_ascii_digit)
+                .collect::<String>();
+            digits.parse().ok()
+        })
+        .collect()
+}
+
+#[test]
+fn subcommands_resolve_selectors_alike() {
+    let fake_i3 = FakeI3::start("multi_output.json", None);
+    let workspaces_of_nodes = workspaces_of_nodes();
+
+    for subcommand in &SUBCOMMANDS {
+        for (selector, expected) in &SELECTORS {
+            let mut args = subcommand.args.to_vec();
+
+            match (selector, subcommand.workspace_flag, subcommand.output_flag) {
+                (Selector::Workspace(workspace), Some(flag), _) => args.extend([flag, workspace]),
+                (Selector::Workspace(workspace), None, _) => args.push(workspace),
+                (Selector::Output(output), _, Some(flag)) => args.extend([flag, output]),
+                (Selector::Output(_), _, None) => continue,
+                (Selector::Focused, ..) if !subcommand.defaults_to_focused => continue,
+                (Selector::Focused, ..) => {}
+            }
+
+            let output = fake_i3.run(&args);
+            assert_eq!(exit_code(&output), 0, "{:?}: {:?}", args, output);
+
+            let text = if subcommand.ids_in_stdout {
+                String::from_utf8_lossy(&output.stdout).into_owned()
+            } else {
+                fake_i3.take_commands().join("\n")
+            };
+
+            let acted_on = ids_after(&text, subcommand.ids_prefix)
+                .into_iter()
+                .map(|id| {
+                    workspaces_of_nodes
+                        .iter()
+                        .find(|(node, _)| *node == id)
+                        .unwrap_or_else(|| panic!("{:?}: unknown node {}", args, id))
+                        .1
+                })
+                .collect::<BTreeSet<_>>();
+
+            // Balancing the empty workspace has nothing to resize.
+            let expected = if args == ["balance", "--workspace", "9: chat"] {
+                BTreeSet::new()
+            } else {
+                BTreeSet::from([*expected])
+            };
+
+            assert_eq!(acted_on, expected, "{:?}: {}", args, text);
+        }
+    }
+}